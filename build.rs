@@ -11,6 +11,10 @@ fn main() -> Result<(), Box<dyn Error>> {
     env::set_var("PROTOC", protoc);
     env::set_var("PROTOC_INCLUDE", protoc_include);
 
+    // Sorted, so that the metadata encodes the same on every master, see `metadata_digest`
+    let mut config = prost_build::Config::new();
+    config.btree_map([".master.Metadata"]);
+
     // Configure tonic-build and compile the .proto files
     tonic_build::configure()
         .build_server(true)
@@ -19,7 +23,31 @@ fn main() -> Result<(), Box<dyn Error>> {
             "ChunkInfo",
             "#[derive(serde::Serialize, serde::Deserialize)]",
         )
-        .compile(&["proto/master.proto", "proto/chunk.proto"], &["proto"])?;
+        .type_attribute(
+            "TrashedFile",
+            "#[derive(serde::Serialize, serde::Deserialize)]",
+        )
+        .type_attribute("ClusterStatusResponse", "#[derive(serde::Serialize)]")
+        .type_attribute("ListEntry", "#[derive(serde::Serialize)]")
+        .type_attribute("FileInfoResponse", "#[derive(serde::Serialize)]")
+        .type_attribute("UsageResponse", "#[derive(serde::Serialize)]")
+        .type_attribute("DecommissionResponse", "#[derive(serde::Serialize)]")
+        .type_attribute("RebalanceStatusResponse", "#[derive(serde::Serialize)]")
+        .type_attribute("CopyStatusResponse", "#[derive(serde::Serialize)]")
+        .type_attribute("ShadowMasterStatus", "#[derive(serde::Serialize)]")
+        .type_attribute("ChunkServerStatus", "#[derive(serde::Serialize)]")
+        .type_attribute("FileStatus", "#[derive(serde::Serialize)]")
+        .type_attribute("ReloadConfigResponse", "#[derive(serde::Serialize)]")
+        .type_attribute("QueryAuditResponse", "#[derive(serde::Serialize)]")
+        .type_attribute(
+            "AuditEntry",
+            "#[derive(serde::Serialize, serde::Deserialize)]",
+        )
+        .compile_with_config(
+            config,
+            &["proto/master.proto", "proto/chunk.proto"],
+            &["proto"],
+        )?;
 
     Ok(())
 }
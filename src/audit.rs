@@ -0,0 +1,263 @@
+// Append-only log of who uploaded, appended and deleted which files, kept apart from the
+// operational logs of the master
+use prometheus::IntCounter;
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs::{self, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{mpsc, Mutex};
+use tonic::{Request, Status};
+use tracing::{error, info, warn};
+
+use crate::config::MasterConfig;
+use crate::error::Result;
+use crate::namespace::is_under;
+use crate::proto::master::{AuditEntry, QueryAuditResponse};
+use crate::request_id::request_id;
+use crate::shutdown::BackgroundTasks;
+
+/// Entries returned by a query that sets no limit
+pub const DEFAULT_QUERY_LIMIT: usize = 1000;
+
+/// Entries written to the file at once
+const WRITE_BATCH_SIZE: usize = 64;
+
+/// Where a request came from, recorded together with its outcome
+#[derive(Clone, Debug, Default)]
+pub struct AuditSource {
+    pub address: String,    // IP address of the client
+    pub request_id: String, // ID of the client operation
+}
+
+impl AuditSource {
+    pub fn of<T>(request: &Request<T>) -> Self {
+        Self {
+            address: request
+                .remote_addr()
+                .map_or_else(String::new, |addr| addr.ip().to_string()),
+            request_id: request_id(request),
+        }
+    }
+}
+
+/// Result of a request as recorded in the audit log, "ok" or the status it failed with
+pub fn outcome<T>(result: &std::result::Result<T, Status>) -> String {
+    match result {
+        Ok(_) => "ok".to_string(),
+        Err(status) => format!("{:?}: {}", status.code(), status.message()),
+    }
+}
+
+/// Audit log of a master, stored as JSON lines and rotated once it reaches `max_size`.
+///
+/// Requests only queue their entries, a writer task appends them to the file. Entries that
+/// don't fit into the queue are dropped and counted instead of holding up the request.
+#[derive(Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+    max_size: u64,
+    max_files: usize,
+    sender: Option<mpsc::Sender<AuditEntry>>, // None if the audit log is disabled
+    receiver: Mutex<Option<mpsc::Receiver<AuditEntry>>>, // Taken by the writer task
+    dropped: IntCounter,                      // Entries that were never written
+}
+
+impl AuditLog {
+    pub fn new(path: &str, config: &MasterConfig, dropped: IntCounter) -> Self {
+        let (sender, receiver) = if config.audit_log {
+            let (sender, receiver) = mpsc::channel(config.audit_queue_size);
+            (Some(sender), Some(receiver))
+        } else {
+            (None, None)
+        };
+        Self {
+            path: PathBuf::from(path),
+            max_size: config.audit_log_max_size,
+            max_files: config.audit_log_max_files,
+            sender,
+            receiver: Mutex::new(receiver),
+            dropped,
+        }
+    }
+
+    /// Queue an entry for `operation` on `file_name` by `user`
+    pub fn record(
+        &self,
+        operation: &str,
+        file_name: &str,
+        user: &str,
+        source: &AuditSource,
+        result: String,
+    ) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+        let entry = AuditEntry {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            user: user.to_string(),
+            operation: operation.to_string(),
+            file_name: file_name.to_string(),
+            result,
+            source: source.address.clone(),
+            request_id: source.request_id.clone(),
+        };
+        if let Err(e) = sender.try_send(entry) {
+            self.dropped.inc();
+            warn!(
+                operation = operation,
+                file_name = %file_name,
+                user = %user,
+                "[Audit] Dropped an audit entry: {}",
+                e
+            );
+        }
+    }
+
+    /// Starts the task appending queued entries to the file. On shutdown it writes the entries
+    /// still queued before it returns.
+    pub async fn start_writer(&self, tasks: &BackgroundTasks) {
+        let Some(mut receiver) = self.receiver.lock().await.take() else {
+            return;
+        };
+        let path = self.path.clone();
+        let (max_size, max_files) = (self.max_size, self.max_files);
+        let dropped = self.dropped.clone();
+        let stopped = tasks.stopped();
+        info!("[Audit] Writing the audit log to {}", path.display());
+        tasks.spawn_graceful("audit log writer", async move {
+            tokio::pin!(stopped);
+            let mut closed = false;
+            let mut batch = Vec::with_capacity(WRITE_BATCH_SIZE);
+            loop {
+                tokio::select! {
+                    received = receiver.recv_many(&mut batch, WRITE_BATCH_SIZE) => {
+                        if received == 0 {
+                            break; // Closed and written out
+                        }
+                    }
+                    () = &mut stopped, if !closed => {
+                        receiver.close();
+                        closed = true;
+                        continue;
+                    }
+                }
+                if let Err(e) = append_entries(&path, max_size, max_files, &batch).await {
+                    error!(
+                        "[Audit] Failed to write {} entries to {}: {}",
+                        batch.len(),
+                        path.display(),
+                        e
+                    );
+                    dropped.inc_by(batch.len() as u64);
+                }
+                batch.clear();
+            }
+        });
+    }
+
+    /// The most recent `limit` entries from `since` on, of `dir` or files below it, oldest first
+    pub async fn query(&self, dir: &str, since: u64, limit: usize) -> Result<QueryAuditResponse> {
+        let limit = if limit == 0 {
+            DEFAULT_QUERY_LIMIT
+        } else {
+            limit
+        };
+        let mut entries = VecDeque::new();
+        let mut truncated = false;
+        let files = (1..=self.max_files)
+            .rev()
+            .map(|n| rotated_path(&self.path, n))
+            .chain([self.path.clone()]);
+        for file in files {
+            let content = match fs::read_to_string(&file).await {
+                Ok(content) => content,
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            // Skips lines cut off by a crash while being written
+            for entry in content
+                .lines()
+                .filter_map(|line| serde_json::from_str::<AuditEntry>(line).ok())
+            {
+                if entry.timestamp < since
+                    || (entry.file_name != dir && !is_under(&entry.file_name, dir))
+                {
+                    continue;
+                }
+                if entries.len() == limit {
+                    entries.pop_front();
+                    truncated = true;
+                }
+                entries.push_back(entry);
+            }
+        }
+        Ok(QueryAuditResponse {
+            entries: entries.into(),
+            truncated,
+            dropped: self.dropped.get(),
+        })
+    }
+}
+
+/// Path of the `n`th most recently rotated file, e.g. audit.log.1
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    PathBuf::from(format!("{}.{}", path.display(), n))
+}
+
+/// Append entries as JSON lines, rotating the file first if they would grow it beyond `max_size`
+async fn append_entries(
+    path: &Path,
+    max_size: u64,
+    max_files: usize,
+    entries: &[AuditEntry],
+) -> Result<()> {
+    let mut lines = String::new();
+    for entry in entries {
+        lines.push_str(&serde_json::to_string(entry)?);
+        lines.push('\n');
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    let size = match fs::metadata(path).await {
+        Ok(metadata) => metadata.len(),
+        Err(e) if e.kind() == ErrorKind::NotFound => 0,
+        Err(e) => return Err(e.into()),
+    };
+    if size > 0 && size + lines.len() as u64 > max_size {
+        rotate(path, max_files).await?;
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(lines.as_bytes()).await?;
+    file.flush().await?;
+    Ok(())
+}
+
+/// Shift audit.log to audit.log.1, audit.log.1 to audit.log.2 and so on, replacing the oldest
+/// of `max_files` rotated files
+async fn rotate(path: &Path, max_files: usize) -> Result<()> {
+    if max_files == 0 {
+        fs::remove_file(path).await?;
+        return Ok(());
+    }
+    for n in (1..max_files).rev() {
+        let from = rotated_path(path, n);
+        if fs::try_exists(&from).await? {
+            fs::rename(&from, rotated_path(path, n + 1)).await?;
+        }
+    }
+    fs::rename(path, rotated_path(path, 1)).await?;
+    info!("[Audit] Rotated {}", path.display());
+    Ok(())
+}
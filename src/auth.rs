@@ -0,0 +1,244 @@
+// Password hashing and verification for the master's authentication file
+use argon2::password_hash::{rand_core::OsRng, PasswordHash, SaltString};
+use argon2::{Argon2, PasswordHasher, PasswordVerifier};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use crate::config::MasterConfig;
+use crate::error::{Error, Result};
+
+/// Hash a password with a random salt, returning the PHC string stored in the authentication file
+pub fn hash_password(password: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| Error::Auth(format!("Failed to hash password: {}", e)))?;
+    Ok(hash.to_string())
+}
+
+/// Verify a password against a stored PHC hash string.
+///
+/// Returns `Ok(false)` for a wrong password and an error when the stored hash is malformed.
+/// The comparison of the derived hash is constant-time.
+pub fn verify_password(password: &str, password_hash: &str) -> Result<bool> {
+    let parsed_hash = PasswordHash::new(password_hash)
+        .map_err(|e| Error::Auth(format!("Malformed password hash: {}", e)))?;
+    Ok(Argon2::default()
+        .verify_password(password.as_bytes(), &parsed_hash)
+        .is_ok())
+}
+
+/// Hash that no password is checked against successfully, verified for unknown users
+fn dummy_password_hash() -> &'static str {
+    static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+    DUMMY_HASH.get_or_init(|| {
+        hash_password(SaltString::generate(&mut OsRng).as_str())
+            .expect("Hashing a random password succeeds")
+    })
+}
+
+/// Look up a user in the authentication data and verify the password.
+///
+/// Legacy entries storing a plaintext `password` field are rejected; they must be migrated
+/// with `master add-user`.
+pub fn verify_user(auth_data: &Value, username: &str, password: &str) -> Result<bool> {
+    let Some(user_entry) = auth_data.get(username) else {
+        // Take as long as for a known user, so that the response time doesn't reveal which
+        // usernames exist
+        verify_password(password, dummy_password_hash())?;
+        return Ok(false);
+    };
+
+    match user_entry.get("password_hash").and_then(Value::as_str) {
+        Some(password_hash) => verify_password(password, password_hash),
+        None if user_entry.get("password").is_some() => Err(Error::Auth(format!(
+            "User '{}' has a plaintext password in the authentication file, re-add the user with `master add-user` to store a hash",
+            username
+        ))),
+        None => Err(Error::Auth(format!(
+            "User '{}' has no password_hash entry",
+            username
+        ))),
+    }
+}
+
+/// Role of a user, stored as `role` in its entry of the authentication file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    Admin,     // May delete and append to the files of any user
+    ReadWrite, // May only delete and append to its own files
+    ReadOnly,  // May only read and list files
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::ReadWrite => "readwrite",
+            Role::ReadOnly => "readonly",
+        }
+    }
+
+    /// Whether the role allows uploading, appending and deleting
+    pub fn may_write(&self) -> bool {
+        *self != Role::ReadOnly
+    }
+}
+
+impl std::str::FromStr for Role {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "admin" => Ok(Role::Admin),
+            "readwrite" => Ok(Role::ReadWrite),
+            "readonly" => Ok(Role::ReadOnly),
+            _ => Err(Error::Auth(format!(
+                "Unknown role '{}', expected admin, readwrite or readonly",
+                value
+            ))),
+        }
+    }
+}
+
+/// Look up the role of a user in the authentication data. Entries without a `role` are
+/// `readwrite`.
+pub fn user_role(auth_data: &Value, username: &str) -> Result<Role> {
+    let Some(user_entry) = auth_data.get(username) else {
+        return Err(Error::Auth(format!("Unknown user '{}'", username)));
+    };
+    match user_entry.get("role").and_then(Value::as_str) {
+        Some(role) => role.parse(),
+        None => Ok(Role::ReadWrite),
+    }
+}
+
+/// Add or replace a user in the authentication file, storing only the password hash
+pub fn add_user(path: &str, username: &str, password: &str, role: Role) -> Result<()> {
+    let mut auth_data: Map<String, Value> = if Path::new(path).exists() {
+        serde_json::from_str(&fs::read_to_string(path)?)?
+    } else {
+        Map::new()
+    };
+
+    let mut user_entry = Map::new();
+    user_entry.insert(
+        "password_hash".to_string(),
+        Value::String(hash_password(password)?),
+    );
+    user_entry.insert("role".to_string(), Value::String(role.as_str().to_string()));
+    auth_data.insert(username.to_string(), Value::Object(user_entry));
+
+    fs::write(path, serde_json::to_string_pretty(&auth_data)?)?;
+    Ok(())
+}
+
+/// Failed authentications of one username or source address
+#[derive(Debug, Default)]
+struct FailureRecord {
+    failures: VecDeque<Instant>, // Failures within the window, oldest first
+    lockouts: u32,               // Bursts of failures so far, each doubling the next lockout
+    locked_until: Option<Instant>,
+    last_failure: Option<Instant>,
+}
+
+/// Throttles failed authentications per username and per source address.
+///
+/// Failures are counted in a sliding window of `auth_failure_window`; `auth_max_failures` of
+/// them lock the username or source out for `auth_lockout`, doubled with every further burst
+/// up to `auth_max_lockout`. Records of keys without failures for `auth_max_lockout` are
+/// dropped, and a successful authentication clears the records of its username and source.
+#[derive(Debug)]
+pub struct AuthThrottle {
+    max_failures: usize,
+    window: Duration,
+    lockout: Duration,
+    max_lockout: Duration,
+    records: HashMap<String, FailureRecord>, // "user:<name>" or "source:<address>" -> Failures
+}
+
+impl AuthThrottle {
+    pub fn new(config: &MasterConfig) -> Self {
+        Self {
+            max_failures: config.auth_max_failures,
+            window: config.auth_failure_window,
+            lockout: config.auth_lockout,
+            max_lockout: config.auth_max_lockout,
+            records: HashMap::new(),
+        }
+    }
+
+    fn keys(username: &str, source: &str) -> [String; 2] {
+        [format!("user:{}", username), format!("source:{}", source)]
+    }
+
+    /// Time left until the username and the source may try again, `None` if neither is locked out
+    pub fn locked_for(&mut self, username: &str, source: &str, now: Instant) -> Option<Duration> {
+        self.expire(now);
+        Self::keys(username, source)
+            .iter()
+            .filter_map(|key| self.records.get(key)?.locked_until)
+            .filter(|locked_until| *locked_until > now)
+            .map(|locked_until| locked_until - now)
+            .max()
+    }
+
+    /// Count a failed authentication, returning the lockout it started, if any
+    pub fn record_failure(
+        &mut self,
+        username: &str,
+        source: &str,
+        now: Instant,
+    ) -> Option<Duration> {
+        if self.max_failures == 0 {
+            return None;
+        }
+        let mut started = None;
+        for key in Self::keys(username, source) {
+            let record = self.records.entry(key).or_default();
+            while record
+                .failures
+                .front()
+                .is_some_and(|failure| now.duration_since(*failure) >= self.window)
+            {
+                record.failures.pop_front();
+            }
+            record.failures.push_back(now);
+            record.last_failure = Some(now);
+            if record.failures.len() >= self.max_failures {
+                let lockout = self
+                    .lockout
+                    .saturating_mul(2u32.saturating_pow(record.lockouts))
+                    .min(self.max_lockout);
+                record.lockouts += 1;
+                record.locked_until = Some(now + lockout);
+                record.failures.clear();
+                started = started.max(Some(lockout));
+            }
+        }
+        started
+    }
+
+    /// Forget the failures of a username and source after a successful authentication
+    pub fn record_success(&mut self, username: &str, source: &str) {
+        for key in Self::keys(username, source) {
+            self.records.remove(&key);
+        }
+    }
+
+    /// Drop records whose lockout is over and whose last failure is older than `max_lockout`
+    /// and the window
+    fn expire(&mut self, now: Instant) {
+        let retention = self.max_lockout.max(self.window);
+        self.records.retain(|_, record| {
+            record.locked_until.is_some_and(|until| until > now)
+                || record
+                    .last_failure
+                    .is_some_and(|last| now.duration_since(last) < retention)
+        });
+    }
+}
@@ -0,0 +1,432 @@
+// Synthetic workload measuring the throughput and chunk latencies of a cluster, run by
+// `client bench`
+use futures::future::try_join_all;
+use rand::distributions::Alphanumeric;
+use rand::{Rng, RngCore};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, ReadBuf};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::client::{ChunkCacheStats, ChunkUpload, Client};
+use crate::config::Config;
+use crate::credentials::Credentials;
+use crate::error::{Error, Result};
+
+/// Bytes of each append of a benchmark
+pub const APPEND_SIZE: usize = 1024;
+
+/// Workload of a benchmark
+#[derive(Clone, Debug)]
+pub struct BenchOptions {
+    pub file_size: u64, // Bytes of each file
+    pub files: usize,
+    pub concurrency: usize, // Files uploaded or read at once, each by a client of its own
+    pub read: bool,         // Whether to read the files back before deleting them
+    pub appends: usize,     // Appends of `APPEND_SIZE` bytes, each client to a file of its own
+}
+
+/// Benchmark uploading random files below a directory of its own, so that `cleanup` can delete
+/// all of them at once
+#[derive(Debug)]
+pub struct Bench {
+    options: BenchOptions,
+    directory: String,
+}
+
+/// Results of a benchmark
+#[derive(Debug, Serialize)]
+pub struct BenchReport {
+    pub directory: String,
+    pub files: usize,
+    pub file_size: u64,
+    pub concurrency: usize,
+    pub upload: PhaseReport,
+    pub read: Option<PhaseReport>,    // Without --read
+    pub append: Option<AppendReport>, // Without --appends
+    pub delete_secs: f64,
+    pub chunk_cache: ChunkCacheStats, // Of all clients, see `chunk_cache_ttl`
+    pub sync_writes: String, // As set in the local configuration, which the chunkservers may not share
+}
+
+/// Throughput and chunk latencies of uploading or reading all files
+#[derive(Debug, Serialize)]
+pub struct PhaseReport {
+    pub bytes: u64,
+    pub chunks: usize,
+    pub secs: f64,
+    pub mb_per_sec: f64, // Megabytes (10^6 bytes) per second
+    pub chunk_latency_ms: Percentiles,
+    pub replica_failures: BTreeMap<String, u64>, // Server -> Chunks it failed to store or serve
+}
+
+/// Rate and latencies of the appends, each including the lookup of the chunk to append to
+#[derive(Debug, Serialize)]
+pub struct AppendReport {
+    pub appends: usize,
+    pub secs: f64,
+    pub appends_per_sec: f64,
+    pub latency_ms: Percentiles,
+}
+
+/// Percentiles of the chunk latencies, in milliseconds
+#[derive(Debug, Default, Serialize)]
+pub struct Percentiles {
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+/// A file uploaded by the benchmark
+struct BenchFile {
+    name: String,
+    crc32: u32,
+    chunks: Vec<ChunkUpload>,
+}
+
+/// What one read of a file measured
+struct FileRead {
+    latencies: Vec<Duration>,
+    failed_replicas: Vec<String>,
+}
+
+impl Bench {
+    pub fn new(options: BenchOptions) -> Self {
+        let id = Uuid::new_v4().to_string();
+        Bench {
+            options,
+            directory: format!("/bench-{}", &id[..8]),
+        }
+    }
+
+    /// Directory holding the files of the benchmark
+    pub fn directory(&self) -> &str {
+        &self.directory
+    }
+
+    /// Upload the files, read them back if asked to, and delete them, each with
+    /// `concurrency` clients connected like one from `config`.
+    ///
+    /// Files read back must match the uploaded data, else the benchmark fails with
+    /// `VerificationFailed`. The files are deleted as well if the benchmark fails.
+    pub async fn run(
+        &self,
+        config: &Config,
+        credentials: Option<&Credentials>,
+    ) -> Result<BenchReport> {
+        // A single client may end up making all appends, to the last chunk of its file
+        let append_bytes = (self.options.appends * APPEND_SIZE) as u64;
+        if append_bytes >= config.common.chunk_size {
+            return Err(Error::Usage(format!(
+                "{} appends of {} bytes don't fit into a chunk of {} bytes",
+                self.options.appends, APPEND_SIZE, config.common.chunk_size
+            )));
+        }
+        let mut clients = Vec::new();
+        for _ in 0..self.options.concurrency.clamp(1, self.options.files.max(1)) {
+            let mut client = Client::from_config(config.clone()).await?;
+            if client.use_authentication() {
+                let Some(credentials) = credentials else {
+                    return Err(Error::Usage(
+                        "Authentication requires a username and password".to_string(),
+                    ));
+                };
+                client
+                    .authenticate(&credentials.username, &credentials.password)
+                    .await?;
+            }
+            clients.push(client);
+        }
+
+        let measured = self.measure(&mut clients, config).await;
+        if measured.is_err() {
+            if let Err(e) = self.cleanup(&mut clients[0]).await {
+                warn!(
+                    "Failed to delete the benchmark files in {}: {}",
+                    self.directory, e
+                );
+            }
+        }
+        measured
+    }
+
+    /// Delete the files of the benchmark and return how many there were, e.g. after it was
+    /// interrupted
+    pub async fn cleanup(&self, client: &mut Client) -> Result<usize> {
+        match client.delete_prefix(&self.directory).await {
+            Ok(deleted) => Ok(deleted.len()),
+            Err(Error::NotFound(_)) => Ok(0), // Interrupted before the first upload
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn measure(&self, clients: &mut [Client], config: &Config) -> Result<BenchReport> {
+        info!(
+            "Uploading {} file(s) of {} bytes to {}",
+            self.options.files, self.options.file_size, self.directory
+        );
+        let started = Instant::now();
+        let next = AtomicUsize::new(0);
+        let uploaded: Vec<BenchFile> = try_join_all(
+            clients
+                .iter_mut()
+                .map(|client| self.upload_files(client, &next)),
+        )
+        .await?
+        .into_iter()
+        .flatten()
+        .collect();
+        let chunks = uploaded.iter().flat_map(|file| &file.chunks);
+        let upload = phase_report(
+            started.elapsed(),
+            self.options.file_size * uploaded.len() as u64,
+            chunks.clone().map(|chunk| chunk.elapsed).collect(),
+            chunks.flat_map(|chunk| &chunk.failed_replicas),
+        );
+
+        let read = if self.options.read {
+            info!("Reading {} file(s) back", uploaded.len());
+            let started = Instant::now();
+            let next = AtomicUsize::new(0);
+            let reads: Vec<FileRead> = try_join_all(
+                clients
+                    .iter_mut()
+                    .map(|client| self.read_files(client, &uploaded, &next)),
+            )
+            .await?
+            .into_iter()
+            .flatten()
+            .collect();
+            Some(phase_report(
+                started.elapsed(),
+                self.options.file_size * reads.len() as u64,
+                reads
+                    .iter()
+                    .flat_map(|read| &read.latencies)
+                    .copied()
+                    .collect(),
+                reads.iter().flat_map(|read| &read.failed_replicas),
+            ))
+        } else {
+            None
+        };
+
+        let append = if self.options.appends > 0 {
+            info!("Appending {} time(s)", self.options.appends);
+            let started = Instant::now();
+            let next = AtomicUsize::new(0);
+            let mut latencies: Vec<Duration> = try_join_all(
+                clients
+                    .iter_mut()
+                    .enumerate()
+                    .map(|(index, client)| self.append_to_file(client, index, &next)),
+            )
+            .await?
+            .into_iter()
+            .flatten()
+            .collect();
+            let secs = started.elapsed().as_secs_f64();
+            Some(AppendReport {
+                appends: latencies.len(),
+                secs,
+                appends_per_sec: if secs > 0.0 {
+                    latencies.len() as f64 / secs
+                } else {
+                    0.0
+                },
+                latency_ms: percentiles(&mut latencies),
+            })
+        } else {
+            None
+        };
+
+        let started = Instant::now();
+        clients[0].delete_prefix(&self.directory).await?;
+        let delete_secs = started.elapsed().as_secs_f64();
+        let mut chunk_cache = ChunkCacheStats::default();
+        for client in clients.iter() {
+            chunk_cache.hits += client.chunk_cache_stats().hits;
+            chunk_cache.misses += client.chunk_cache_stats().misses;
+        }
+        Ok(BenchReport {
+            directory: self.directory.clone(),
+            files: self.options.files,
+            file_size: self.options.file_size,
+            concurrency: clients.len(),
+            upload,
+            read,
+            append,
+            delete_secs,
+            chunk_cache,
+            sync_writes: config.chunkserver.sync_writes.clone(),
+        })
+    }
+
+    /// Upload random files until `next` runs past the number of files
+    async fn upload_files(
+        &self,
+        client: &mut Client,
+        next: &AtomicUsize,
+    ) -> Result<Vec<BenchFile>> {
+        let mut uploaded = Vec::new();
+        loop {
+            let index = next.fetch_add(1, Ordering::SeqCst);
+            if index >= self.options.files {
+                return Ok(uploaded);
+            }
+            let mut data = RandomData::new(self.options.file_size);
+            let name = format!("{}/file-{}", self.directory, index);
+            let summary = client.upload_stream(&mut data, &name).await?;
+            uploaded.push(BenchFile {
+                name: summary.file_name,
+                crc32: data.hasher.finalize(),
+                chunks: summary.chunks,
+            });
+        }
+    }
+
+    /// Append to a file of the client's own, created on the first append, until `next` runs
+    /// past the number of appends, and return how long each append took
+    async fn append_to_file(
+        &self,
+        client: &mut Client,
+        index: usize,
+        next: &AtomicUsize,
+    ) -> Result<Vec<Duration>> {
+        let mut latencies = Vec::new();
+        let mut file_name = None;
+        while next.fetch_add(1, Ordering::SeqCst) < self.options.appends {
+            let file_name = match &mut file_name {
+                Some(file_name) => file_name,
+                None => {
+                    let name = format!("{}/append-{}", self.directory, index);
+                    let summary = client.upload_stream(&mut RandomData::new(1), &name).await?;
+                    file_name.insert(summary.file_name)
+                }
+            };
+            let data: String = rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(APPEND_SIZE)
+                .map(char::from)
+                .collect();
+            let started = Instant::now();
+            let all_server_addresses = client.get_append_server_addresses(file_name).await?;
+            client
+                .append_file(all_server_addresses, file_name, data)
+                .await?;
+            latencies.push(started.elapsed());
+        }
+        Ok(latencies)
+    }
+
+    /// Read `files` until `next` runs past their number, checking them against the uploaded data
+    async fn read_files(
+        &self,
+        client: &mut Client,
+        files: &[BenchFile],
+        next: &AtomicUsize,
+    ) -> Result<Vec<FileRead>> {
+        let mut reads = Vec::new();
+        while let Some(file) = files.get(next.fetch_add(1, Ordering::SeqCst)) {
+            let summary = client
+                .read_range_to(&file.name, 0, u64::MAX, &mut io::sink())
+                .await?;
+            if summary.bytes != self.options.file_size || summary.crc32 != file.crc32 {
+                return Err(Error::VerificationFailed(format!(
+                    "'{}' was read back as {} bytes with CRC32 {:08x}, but {} bytes with CRC32 {:08x} were uploaded",
+                    file.name, summary.bytes, summary.crc32, self.options.file_size, file.crc32
+                )));
+            }
+            reads.push(FileRead {
+                latencies: summary.chunks.iter().map(|chunk| chunk.elapsed).collect(),
+                failed_replicas: summary
+                    .chunks
+                    .into_iter()
+                    .flat_map(|chunk| chunk.failed_replicas)
+                    .collect(),
+            });
+        }
+        Ok(reads)
+    }
+}
+
+/// Summarize a phase that transferred `bytes` in `elapsed`
+fn phase_report<'a>(
+    elapsed: Duration,
+    bytes: u64,
+    mut latencies: Vec<Duration>,
+    failed_replicas: impl Iterator<Item = &'a String>,
+) -> PhaseReport {
+    let secs = elapsed.as_secs_f64();
+    let mut replica_failures = BTreeMap::new();
+    for server in failed_replicas {
+        *replica_failures.entry(server.clone()).or_default() += 1;
+    }
+    PhaseReport {
+        bytes,
+        chunks: latencies.len(),
+        secs,
+        mb_per_sec: if secs > 0.0 {
+            bytes as f64 / secs / 1e6
+        } else {
+            0.0
+        },
+        chunk_latency_ms: percentiles(&mut latencies),
+        replica_failures,
+    }
+}
+
+/// Percentiles of `latencies`, which are sorted on the way
+fn percentiles(latencies: &mut [Duration]) -> Percentiles {
+    latencies.sort();
+    let percentile = |p: f64| {
+        let rank = (p / 100.0 * latencies.len() as f64).ceil() as usize;
+        latencies
+            .get(rank.saturating_sub(1))
+            .map_or(0.0, |latency| latency.as_secs_f64() * 1000.0)
+    };
+    Percentiles {
+        p50: percentile(50.0),
+        p90: percentile(90.0),
+        p99: percentile(99.0),
+        max: percentile(100.0),
+    }
+}
+
+/// `len` random bytes, generated as they are read, together with their CRC32
+struct RandomData {
+    remaining: u64,
+    hasher: crc32fast::Hasher,
+}
+
+impl RandomData {
+    fn new(len: u64) -> Self {
+        RandomData {
+            remaining: len,
+            hasher: crc32fast::Hasher::new(),
+        }
+    }
+}
+
+impl AsyncRead for RandomData {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let len = self.remaining.min(buf.remaining() as u64) as usize;
+        let data = buf.initialize_unfilled_to(len);
+        rand::thread_rng().fill_bytes(data);
+        self.hasher.update(data);
+        buf.advance(len);
+        self.remaining -= len as u64;
+        Poll::Ready(Ok(()))
+    }
+}
@@ -0,0 +1,277 @@
+use std::env;
+use std::process::ExitCode;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::error;
+use tracing_subscriber::{fmt, layer::SubscriberExt, EnvFilter, Registry};
+
+use rustfs::client::Client;
+use rustfs::config::{config_path, load_config};
+use rustfs::credentials::{resolve_credentials, PartialCredentials};
+use rustfs::error::{Error, Result};
+use rustfs::proto::master::{
+    ChunkServerStatus, ClusterStatusResponse, FileStatus, QueryAuditResponse, ReloadConfigResponse,
+};
+use rustfs::units::parse_duration;
+
+/// Options followed by a value, which is not a positional argument
+const VALUE_OPTIONS: [&str; 5] = ["--config", "-u", "-p", "--since", "--limit"];
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            e.exit_code()
+        }
+    }
+}
+
+async fn run() -> Result<()> {
+    let args: Vec<String> = env::args().collect();
+    let config_flag = option(&args, "--config")?;
+    let config = load_config(&config_path(config_flag))?;
+
+    // Log to stderr only, so the output can be piped into other tools
+    let stderr_layer = fmt::layer().with_writer(std::io::stderr).with_ansi(true);
+    let env_filter =
+        EnvFilter::from_default_env().add_directive(config.common.log_level.parse().unwrap());
+    let subscriber = Registry::default().with(env_filter).with(stderr_layer);
+    tracing::subscriber::set_global_default(subscriber)
+        .expect("Failed to set global default subscriber");
+
+    let json = args.iter().any(|arg| arg == "--json");
+    let positional: Vec<&String> = args
+        .iter()
+        .enumerate()
+        .skip(1)
+        .filter(|(i, arg)| !arg.starts_with('-') && !VALUE_OPTIONS.contains(&args[i - 1].as_str()))
+        .map(|(_, arg)| arg)
+        .collect();
+    let Some(command) = positional.first() else {
+        error!("Usage: rustfs-admin <command> [--json] [--config <path>] [-u <username>] [-p <password>]");
+        error!("Commands: status, servers, files, reload [master address], audit [path] [--since <duration>] [--limit <n>]");
+        return Ok(());
+    };
+
+    let mut client = Client::from_config(config).await?;
+    // Only the audit log requires an admin login, the other commands are open to everyone
+    if client.use_authentication() {
+        let flags = PartialCredentials {
+            username: option(&args, "-u")?.map(str::to_string),
+            password: option(&args, "-p")?.map(str::to_string),
+        };
+        if let Some(credentials) = resolve_credentials(flags)? {
+            client
+                .authenticate(&credentials.username, &credentials.password)
+                .await?;
+        }
+    }
+    match command.as_str() {
+        "status" => {
+            let status = client.cluster_status(false).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&status)?);
+            } else {
+                print_status(&status);
+            }
+        }
+        "servers" => {
+            let status = client.cluster_status(false).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&status.chunk_servers)?);
+            } else {
+                print_servers(&status.chunk_servers);
+                print_shadow_note(&status);
+            }
+        }
+        "files" => {
+            let status = client.cluster_status(true).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&status.files)?);
+            } else {
+                print_files(&status.files);
+                print_shadow_note(&status);
+            }
+        }
+        "reload" => {
+            let master_addr = positional.get(1).map(|addr| addr.as_str());
+            let response = client.reload_config(master_addr).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&response)?);
+            } else {
+                print_reload(&response);
+            }
+        }
+        "audit" => {
+            let path = positional.get(1).map_or("/", |path| path.as_str());
+            let since = match option(&args, "--since")? {
+                Some(value) => {
+                    let ago = parse_duration(value).map_err(Error::Config)?;
+                    SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap()
+                        .saturating_sub(ago)
+                        .as_secs()
+                }
+                None => 0,
+            };
+            let limit = match option(&args, "--limit")? {
+                Some(value) => value
+                    .parse()
+                    .map_err(|_| Error::Config(format!("Invalid limit '{}'", value)))?,
+                None => 0,
+            };
+            let response = client.query_audit(path, since, limit).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&response)?);
+            } else {
+                print_audit(&response);
+            }
+        }
+        _ => {
+            error!("Invalid command. Available commands: status, servers, files, reload, audit");
+        }
+    }
+
+    Ok(())
+}
+
+/// Value of the option `name`, an error if it is the last argument
+fn option<'a>(args: &'a [String], name: &str) -> Result<Option<&'a str>> {
+    match args.iter().position(|arg| arg == name) {
+        Some(position) => match args.get(position + 1) {
+            Some(value) => Ok(Some(value.as_str())),
+            None => Err(Error::Config(format!("Missing value after {}", name))),
+        },
+        None => Ok(None),
+    }
+}
+
+fn print_status(status: &ClusterStatusResponse) {
+    println!(
+        "Leader:            {} (epoch {}, metadata version {})",
+        status.leader_address, status.epoch, status.metadata_version
+    );
+    if status.shadow_masters.is_empty() {
+        println!("Shadow masters:    none");
+    }
+    for shadow_master in &status.shadow_masters {
+        println!(
+            "Shadow master:     {} ({} version(s) behind)",
+            shadow_master.address, shadow_master.replication_lag
+        );
+    }
+    let healthy = status.chunk_servers.iter().filter(|s| s.healthy).count();
+    let draining = status.chunk_servers.iter().filter(|s| s.draining).count();
+    println!(
+        "Chunkservers:      {} ({} healthy, {} draining)",
+        status.chunk_servers.len(),
+        healthy,
+        draining
+    );
+    println!("Files:             {}", status.total_files);
+    println!(
+        "Chunks:            {} ({} under-replicated)",
+        status.total_chunks, status.under_replicated_chunks
+    );
+    println!("Pending deletions: {}", status.pending_deletions);
+    print_shadow_note(status);
+}
+
+fn print_servers(servers: &[ChunkServerStatus]) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    println!(
+        "{:<24} {:>7} {:>10}  {:<9} {:>14} {:>14} {:>10} {:>8}",
+        "ADDRESS", "CHUNKS", "HEARTBEAT", "STATUS", "AVAILABLE", "CAPACITY", "SCRUBBED", "CORRUPT"
+    );
+    for server in servers {
+        let status = match (server.healthy, server.draining) {
+            (false, _) => "FAILED",
+            (true, true) => "DRAINING",
+            (true, false) => "OK",
+        };
+        let scrubbed = if server.last_scrub_time == 0 {
+            "never".to_string()
+        } else {
+            format!("{}s ago", now.saturating_sub(server.last_scrub_time))
+        };
+        println!(
+            "{:<24} {:>7} {:>10}  {:<9} {:>14} {:>14} {:>10} {:>8}",
+            server.address,
+            server.chunks,
+            format!("{}s ago", server.last_heartbeat_age),
+            status,
+            server.available_bytes,
+            server.total_bytes,
+            scrubbed,
+            server.corrupt_chunks
+        );
+    }
+}
+
+fn print_files(files: &[FileStatus]) {
+    println!(
+        "{:>12} {:>7} {:>7}  {:<17} NAME",
+        "SIZE", "CHUNKS", "UNDER", "STATUS"
+    );
+    for file in files {
+        let status = if file.under_replicated_chunks == 0 {
+            "OK"
+        } else {
+            "UNDER-REPLICATED"
+        };
+        println!(
+            "{:>12} {:>7} {:>7}  {:<17} {}",
+            file.size, file.chunks, file.under_replicated_chunks, status, file.file_name
+        );
+    }
+}
+
+fn print_reload(response: &ReloadConfigResponse) {
+    if response.applied.is_empty() && response.ignored.is_empty() {
+        println!("No settings changed");
+    }
+    for change in &response.applied {
+        println!("Applied  {}", change);
+    }
+    for change in &response.ignored {
+        println!("Ignored  {} (needs a restart)", change);
+    }
+}
+
+fn print_audit(response: &QueryAuditResponse) {
+    if response.truncated {
+        println!("Note: older entries were left out, raise --limit to see them");
+    }
+    if response.dropped > 0 {
+        println!(
+            "Note: {} entries were dropped since the master started",
+            response.dropped
+        );
+    }
+    println!(
+        "{:>10}  {:<12} {:<14} {:<16} {:<30} RESULT",
+        "TIME", "USER", "OPERATION", "SOURCE", "FILE"
+    );
+    for entry in &response.entries {
+        let user = if entry.user.is_empty() {
+            "-"
+        } else {
+            &entry.user
+        };
+        println!(
+            "{:>10}  {:<12} {:<14} {:<16} {:<30} {}",
+            entry.timestamp, user, entry.operation, entry.source, entry.file_name, entry.result
+        );
+    }
+}
+
+fn print_shadow_note(status: &ClusterStatusResponse) {
+    if status.from_shadow {
+        println!("Note: served by a shadow master, which receives no heartbeats");
+    }
+}
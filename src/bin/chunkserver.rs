@@ -1,38 +1,21 @@
-use clap::{Arg, Command};
-use std::net::SocketAddr;
+use clap::{Arg, ArgAction, Command};
+use tokio::sync::oneshot;
 use tonic::transport::Server;
 
-use rustfs::config::{load_config, ChunkServerConfig, CommonConfig};
+use rustfs::config::{config_path, load_config, ChunkServerConfig, CommonConfig, Config};
+use rustfs::logging;
+use rustfs::metrics::start_metrics_server;
 use rustfs::proto::chunk::chunk_server::ChunkServer;
-use rustfs::proto::master::RegisterRequest;
-use rustfs::util::connect_to_master;
-use tracing::{debug, error, info};
-use tracing_appender::rolling;
-use tracing_subscriber::{fmt, layer::SubscriberExt, EnvFilter, Registry};
+use rustfs::request_id::RequestIdLayer;
+use rustfs::shutdown;
+use rustfs::util::{node_addresses, server_tls_config};
+use tracing::{info, warn};
 
 // Using modules chunkserver_impl and chunkserver_service from `src/`
 use rustfs::chunkserver_service::ChunkService;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // load logging config
-    let config = load_config("config.toml")?;
-    let log_path = config.chunkserver.log_path.clone();
-    let log_level = config.common.log_level.clone();
-
-    // Set up logger
-    let file_appender = rolling::daily(log_path, "chunkserver.log");
-    let stdout_layer = fmt::layer().with_writer(std::io::stdout).with_ansi(true);
-    let file_layer = fmt::layer().with_writer(file_appender).with_ansi(false); // Disable ANSI escape codes for file logs
-    let env_filter = EnvFilter::from_default_env().add_directive(log_level.parse().unwrap());
-    let subscriber = Registry::default()
-        .with(env_filter)
-        .with(stdout_layer)
-        .with(file_layer);
-
-    tracing::subscriber::set_global_default(subscriber)
-        .expect("Failed to set global default subscriber");
-
     // Parse command line arguments
     let matches = Command::new("ChunkServer")
         .version("1.0")
@@ -41,70 +24,123 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             Arg::new("address")
                 .short('a')
                 .value_name("ADDR")
-                .help("Sets the address for the ChunkServer (e.g., 127.0.0.1:50010)")
-                .required(true),
+                .help("Sets the address the ChunkServer binds and advertises (e.g., 127.0.0.1:50010)"),
+        )
+        .arg(
+            Arg::new("bind_addr")
+                .long("bind-addr")
+                .value_name("ADDR")
+                .help("Address the gRPC server listens on (e.g., 0.0.0.0:50010), overrides -a"),
+        )
+        .arg(
+            Arg::new("advertise_addr")
+                .long("advertise-addr")
+                .value_name("ADDR")
+                .help("Address the master, chunkservers and clients connect to, defaults to the bind address"),
+        )
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .value_name("PATH")
+                .help("Path of the config file, defaults to $RUSTFS_CONFIG or ./config.toml"),
+        )
+        .arg(
+            Arg::new("log_level")
+                .long("log-level")
+                .value_name("LEVEL")
+                .help("Overrides log_level from the config file (e.g., debug, or rustfs=trace)"),
+        )
+        .arg(
+            Arg::new("print_default_config")
+                .long("print-default-config")
+                .action(ArgAction::SetTrue)
+                .exclusive(true)
+                .help("Prints a config file with every setting at its default value and exits"),
         )
         .get_matches();
 
-    let address = matches
-        .get_one::<String>("address")
-        .expect("Address is required");
-    let addr: SocketAddr = address.parse().expect("Invalid address format");
+    if matches.get_flag("print_default_config") {
+        print!("{}", Config::example());
+        return Ok(());
+    }
+
+    // Set up logger as configured by log_level and log_output
+    let config_path = config_path(matches.get_one::<String>("config").map(String::as_str));
+    let mut config = load_config(&config_path)?;
+    if let Some(log_level) = matches.get_one::<String>("log_level") {
+        config.common.log_level = log_level.clone();
+    }
+    logging::init(
+        &config.common,
+        &config.chunkserver.log_path,
+        "chunkserver.log",
+    )?;
+
+    let arg = |name: &str| matches.get_one::<String>(name).map(String::as_str);
+    let addresses = node_addresses(
+        arg("address"),
+        arg("bind_addr"),
+        arg("advertise_addr"),
+        &config.chunkserver.bind_addr,
+        &config.chunkserver.advertise_addr,
+    )?;
+    let addr = &addresses.advertise;
 
     // Load configuration
     let chunkserver_config: ChunkServerConfig = config.chunkserver;
     let common_config: CommonConfig = config.common;
 
-    // Create path to data files of chunkserver
-    let sanitized_address = addr.to_string().replace(':', "_"); // Convert to a valid directory name
-    let full_data_path = format!("{}/{}", sanitized_address, chunkserver_config.data_path);
-    if !std::path::Path::new(&full_data_path).exists() {
-        std::fs::create_dir_all(&full_data_path).map_err(|e| {
-            error!(
-                "Failed to create data directory '{}': {}",
-                full_data_path, e
-            );
-            e
-        })?;
-    }
-    debug!("Data directory verified: {}", full_data_path);
-
-    // Connect to the master, if no master is available, exit the program
-    let mut master_client = connect_to_master(&common_config.master_addrs).await?;
-
-    // Send register request to master
-    let response = master_client
-        .register_chunk_server(RegisterRequest {
-            address: addr.to_string(),
-        })
-        .await?;
-    info!("Registered with Master: {}", response.into_inner().message);
+    // Load TLS settings before the common config is moved into the service
+    let tls_config = server_tls_config(&common_config.tls)?;
 
     // Start chunkserver service
-    info!("ChunkServer running at {}", addr);
-    let service = ChunkService::new(
-        &addr.to_string(),
-        &sanitized_address,
-        chunkserver_config,
-        common_config,
+    info!(
+        "ChunkServer listening on {}, advertised as {}",
+        addresses.bind, addr
     );
+    let sanitized_address = addr.replace(':', "_"); // Convert to a valid directory name
+    let service = ChunkService::new(addr, &sanitized_address, chunkserver_config, common_config);
+    service.prepare_data_dir()?;
+
+    // Exit if no leader is reachable within master_retry_deadline
+    service.join_cluster().await?;
+
+    if !service.config.metrics_addr.is_empty() {
+        start_metrics_server(
+            &service.config.metrics_addr,
+            service.metrics.registry.clone(),
+            Some(service.metrics.master_connected.clone()),
+        );
+    }
 
-    // Periodically cleanup outdated OTP
-    service.start_otp_cleanup();
-
-    // Clone the master_client and spawn the heartbeat task at background
-    let heartbeat_client = master_client.clone();
-    let heartbeat_service = service.clone();
-    tokio::spawn(async move {
-        if let Err(e) = heartbeat_service.send_heartbeat(heartbeat_client).await {
-            error!("Heartbeat task failed: {}", e);
+    let mut server = Server::builder();
+    if let Some(tls_config) = tls_config {
+        info!("TLS enabled for ChunkServer");
+        server = server.tls_config(tls_config)?;
+    }
+    // Stop accepting requests on SIGTERM or Ctrl-C and let running uploads and appends finish,
+    // so that no partially written chunks are left behind
+    let (stop_serving, serving_stopped) = oneshot::channel::<()>();
+    let serve = server
+        .layer(RequestIdLayer)
+        .add_service(ChunkServer::new(service.clone()))
+        .serve_with_shutdown(addresses.bind, async {
+            let _ = serving_stopped.await;
+        });
+    tokio::pin!(serve);
+    tokio::select! {
+        served = &mut serve => served?,
+        () = shutdown::signal() => {
+            let _ = stop_serving.send(());
+            let timeout = service.common_config.shutdown_timeout;
+            match tokio::time::timeout(timeout, &mut serve).await {
+                Ok(served) => served?,
+                Err(_) => warn!("[Shutdown] Requests still running after {:?} are aborted", timeout),
+            }
         }
-    });
-
-    Server::builder()
-        .add_service(ChunkServer::new(service))
-        .serve(addr)
-        .await?;
+    }
 
+    service.shutdown().await;
+    info!("ChunkServer stopped");
     Ok(())
 }
@@ -1,463 +1,219 @@
-use chunk::chunk_client::ChunkClient;
-use chunk::{AppendRequest, DeleteRequest, FileChunk, FileInfo, ReadRequest, UploadRequest};
-use rand::seq::SliceRandom;
+use serde::Serialize;
+use serde_json::{json, Value};
 use std::env;
-use tokio::fs::File;
-use tokio::io::AsyncReadExt;
-use tokio_stream::wrappers::ReceiverStream;
-use tonic::Request;
-use tracing::{debug, error, info};
-use tracing_appender::rolling;
-use tracing_subscriber::{fmt, layer::SubscriberExt, EnvFilter, Registry};
-
-use rustfs::config::{load_config, CommonConfig};
-use rustfs::proto::master::{
-    master_client::MasterClient, AssignRequest, ChunkInfo, DeleteFileRequest,
-    FileChunkMappingRequest,
-};
-use rustfs::util::connect_to_master;
-
-pub mod chunk {
-    tonic::include_proto!("chunk");
-}
-
-pub struct Client {
-    common_config: CommonConfig,
-    master_client: MasterClient<tonic::transport::Channel>,
-    otp: Option<String>,
-}
-
-impl Client {
-    pub async fn new(config_path: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        let config = load_config(config_path)?;
-        let common_config: CommonConfig = config.common;
-
-        let master_client = connect_to_master(&common_config.master_addrs).await?;
-
-        Ok(Client {
-            common_config,
-            master_client,
-            otp: None,
-        })
-    }
-
-    pub async fn authenticate(
-        &mut self,
-        username: &str,
-        password: &str,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        if !self.common_config.use_authentication {
-            info!("Authentication is disabled. Skipping OTP request.");
-            return Ok(());
-        }
-        info!("Authenticating user: {}", username);
-
-        // Create and send the authentication request to the master server
-        let response = self
-            .master_client
-            .authenticate(Request::new(rustfs::proto::master::AuthenticateRequest {
-                username: username.to_string(),
-                password: password.to_string(),
-            }))
-            .await;
-
-        match response {
-            Ok(response) => {
-                let auth_response = response.into_inner();
-                self.otp = Some(auth_response.otp);
-
-                info!(
-                    "User '{}' authenticated successfully. OTP received and stored.",
-                    username
-                );
-                Ok(())
-            }
-            Err(err) => {
-                // Log the specific error and re-throw it
-                error!(
-                    "Authentication failed for user '{}': {}",
-                    username,
-                    err.message()
-                );
-                Err(Box::new(err))
-            }
-        }
-    }
-
-    /// Randomly select a server address for each chunk for read operations
-    pub async fn get_randomized_server_addresses(
-        &mut self,
-        file_name: &str,
-    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
-        let response = self
-            .master_client
-            .get_file_chunks(Request::new(FileChunkMappingRequest {
-                file_name: file_name.to_string(),
-            }))
-            .await?;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::process::ExitCode;
+use tracing::{error, info, info_span, Instrument};
+
+use rustfs::bench::{Bench, BenchOptions, BenchReport, APPEND_SIZE};
+use rustfs::client::{ChunkVerification, Client, ReadSummary, ReplicaChecksum};
+use rustfs::config::{config_path, load_config, Config};
+use rustfs::credentials::{resolve_credentials, PartialCredentials};
+use rustfs::error::{Error, Result};
+use rustfs::logging::{self, Console};
+use rustfs::proto::master::FileInfoResponse;
+use rustfs::shutdown;
+use rustfs::units::{format_duration, format_size, parse_size};
+
+/// Bytes `head` and `tail` print without `-n`
+const DEFAULT_HEAD_TAIL_BYTES: u64 = 1024;
+
+/// Workload of `bench` without `--size`, `--files` and `--concurrency`
+const DEFAULT_BENCH_FILE_SIZE: u64 = 64 * 1024 * 1024;
+const DEFAULT_BENCH_FILES: usize = 4;
+const DEFAULT_BENCH_CONCURRENCY: usize = 2;
 
-        let chunk_info_list = response.into_inner().chunks;
-
-        let mut rng = rand::thread_rng();
-        let mut randomized_addresses = Vec::new();
-
-        // Randomly choose a server for server_addresses of each chunk
-        for chunk_info in chunk_info_list.iter() {
-            if let Some(random_server) = chunk_info.server_addresses.choose(&mut rng) {
-                randomized_addresses.push(random_server.clone());
-            } else {
-                return Err(Box::new(std::io::Error::new(
-                    std::io::ErrorKind::NotFound,
-                    "No available servers for one or more chunks",
-                )));
+#[tokio::main]
+async fn main() -> ExitCode {
+    let json = env::args().any(|arg| arg == "--json");
+    match run(json).await {
+        Ok(()) => ExitCode::SUCCESS,
+        // Printed together with the result of the command
+        Err(e @ (Error::VerificationFailed(_) | Error::Degraded(_))) if json => e.exit_code(),
+        Err(e) if json => {
+            let error = json!({ "error": { "code": e.code(), "message": e.to_string() } });
+            if let Err(e) = print_json(&error) {
+                eprintln!("Error: {}", e);
             }
+            e.exit_code()
         }
-
-        if randomized_addresses.is_empty() {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                "No available chunk servers for the file",
-            )));
+        Err(e @ Error::Usage(_)) => {
+            eprintln!("{}", e);
+            e.exit_code()
         }
-
-        Ok(randomized_addresses)
-    }
-
-    /// Select a server address for each chunk for write operations
-    pub async fn get_primary_server_addresses(
-        &mut self,
-        file_name: &str,
-    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
-        let response = self
-            .master_client
-            .get_file_chunks(Request::new(FileChunkMappingRequest {
-                file_name: file_name.to_string(),
-            }))
-            .await?;
-
-        let chunk_info_list = response.into_inner().chunks;
-        let server_addresses: Vec<String> = chunk_info_list
-            .iter()
-            .filter_map(|chunk| chunk.server_addresses.get(0).cloned())
-            .collect();
-
-        if server_addresses.is_empty() {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                "No available chunk servers for the file",
-            )));
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            e.exit_code()
         }
-
-        Ok(server_addresses)
     }
+}
 
-    /// Retrieves all server addresses for each chunk of the specified file.
-    ///
-    /// Returns a 2D vector where each inner vector contains the server addresses
-    /// for a single chunk.
-    pub async fn get_all_server_addresses(
-        &mut self,
-        file_name: &str,
-    ) -> Result<Vec<Vec<String>>, Box<dyn std::error::Error>> {
-        let response = self
-            .master_client
-            .get_file_chunks(Request::new(FileChunkMappingRequest {
-                file_name: file_name.to_string(),
-            }))
-            .await?;
-
-        let chunk_info_list = response.into_inner().chunks;
-        let all_server_addresses: Vec<Vec<String>> = chunk_info_list
-            .iter()
-            .map(|chunk| chunk.server_addresses.clone()) // Clone server addresses for each chunk
-            .collect();
-
-        if all_server_addresses.is_empty() {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                "No available chunk servers for the file",
-            )));
-        }
-
-        Ok(all_server_addresses)
+async fn run(json: bool) -> Result<()> {
+    let args: Vec<String> = env::args().collect();
+    if args.iter().any(|arg| arg == "--print-default-config") {
+        print!("{}", Config::example());
+        return Ok(());
     }
-
-    pub async fn upload_file(
-        &self,
-        chunk_info_list: Vec<ChunkInfo>,
-        file_name: String,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        debug!("Attempting to open file: {}", file_name);
-        let mut file = File::open(&file_name).await.map_err(|e| {
-            error!("Failed to open file '{}': {}", file_name, e);
-            e
-        })?;
-
-        // Separate the file into chunks
-        let chunk_size = self.common_config.chunk_size as usize;
-        let mut chunks = Vec::new();
-        let mut buf = vec![0; chunk_size];
-        loop {
-            let n = file.read(&mut buf).await?;
-            if n == 0 {
-                break; // EOF
-            }
-            chunks.push(buf[..n].to_vec());
-        }
-
-        // Check the length of chunk is the same as chunk_info_list (from AssignResponse)
-        if chunks.len() != chunk_info_list.len() {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Mismatch between number of file chunks and chunk_info_list length",
-            )));
-        }
-
-        // Iterate through each chunk and upload to all chunkservers // TODO: upload to the primary chunkserver only
-        for (chunk_index, chunk) in chunks.into_iter().enumerate() {
-            let chunk_info = &chunk_info_list[chunk_index];
-            for server_address in &chunk_info.server_addresses {
-                let mut chunk_client =
-                    ChunkClient::connect(format!("http://{}", server_address)).await?;
-
-                let (tx, rx) = tokio::sync::mpsc::channel(4);
-
-                let file_name_clone = file_name.clone();
-                let chunk_id = chunk_info.chunk_id.clone();
-                let chunk_data = chunk.clone();
-                let otp_clone = self.otp.clone();
-                let otp_value = otp_clone.unwrap_or_else(|| "".to_string());
-
-                tokio::spawn(async move {
-                    let file_info = FileInfo {
-                        file_name: file_name_clone,
-                        chunk_id: chunk_id.parse::<u64>().unwrap_or(0),
-                    };
-
-                    if let Err(e) = tx
-                        .send(UploadRequest {
-                            request: Some(chunk::upload_request::Request::Info(file_info)),
-                            otp: otp_value.clone(),
-                            is_internal: false,
-                        })
-                        .await
-                    {
-                        error!("Failed to send file info: {}", e);
-                        return;
-                    }
-
-                    let file_chunk = FileChunk { data: chunk_data };
-
-                    if let Err(e) = tx
-                        .send(UploadRequest {
-                            request: Some(chunk::upload_request::Request::Chunk(file_chunk)),
-                            otp: otp_value.clone(),
-                            is_internal: false,
-                        })
-                        .await
-                    {
-                        error!("Failed to send file chunk: {}", e);
-                        return;
-                    }
-                });
-
-                let response = chunk_client
-                    .upload(Request::new(ReceiverStream::new(rx)))
-                    .await?;
-
-                debug!(
-                    "Upload Response from server {} for chunk {}: {}",
-                    server_address,
-                    chunk_index,
-                    response.into_inner().message
-                );
-            }
-        }
-
-        info!("File upload completed successfully.");
-        Ok(())
+    // Mistyped commands are reported without reading the config or connecting to the cluster
+    let command_line = parse_command_line(&args)?;
+
+    // Set up logger as configured by log_level and log_output, with --json stdout only carries
+    // the result of the command
+    let mut config = load_config(&config_path(option_value(&args, "--config")?))?;
+    if let Some(log_level) = option_value(&args, "--log-level")? {
+        config.common.log_level = log_level.to_string();
     }
-
-    /// Read each chunk and then concatenate
-    pub async fn read_file(
-        &self,
-        randomized_server_addresses: Vec<String>,
-        file_name: &str,
-    ) -> Result<String, Box<dyn std::error::Error>> {
-        let mut file_content = String::new();
-        let otp_clone = self.otp.clone();
-        let otp_value = otp_clone.unwrap_or_else(|| "".to_string());
-
-        for (chunk_id, server_address) in randomized_server_addresses.iter().enumerate() {
-            // Connect to the chunk server
-            let mut chunk_client =
-                ChunkClient::connect(format!("http://{}", server_address)).await?;
-
-            // Read the chunk
-            let response = chunk_client
-                .read(Request::new(ReadRequest {
-                    file_name: file_name.to_string(),
-                    chunk_id: chunk_id as u64,
-                    otp: otp_value.clone(),
-                }))
-                .await?;
-
-            // Append the chunk content to the file content
-            file_content.push_str(&response.into_inner().content.trim_end());
-        }
-
-        println!("{}", file_content);
-        Ok(file_content)
+    let console = if json {
+        Console::Stderr
+    } else {
+        Console::Stdout
+    };
+    logging::init_with_console(
+        &config.common,
+        &config.client.log_path,
+        "client.log",
+        console,
+    )?;
+
+    let operation_timeout = config.client.operation_timeout;
+    let operation = async {
+        // Create client instance
+        let mut client = Client::from_config(config.clone()).await?;
+
+        // Name the request ID in every log line of the operation, the master and chunkservers
+        // log it as well
+        let span = info_span!("operation", request_id = %client.request_id());
+        let result = execute(&mut client, &config, command_line, json)
+            .instrument(span)
+            .await;
+        // Unreadable replicas found by reads are reported in the background
+        client.finish_reports().await;
+        result
+    };
+    if operation_timeout.is_zero() {
+        return operation.await;
     }
+    tokio::time::timeout(operation_timeout, operation)
+        .await
+        .map_err(|_| {
+            Error::Timeout(format!(
+                "the operation did not finish within {}",
+                format_duration(operation_timeout)
+            ))
+        })?
+}
 
-    pub async fn delete_file(
-        &self,
-        all_server_addresses: Vec<Vec<String>>, // 2D vector of server addresses for each chunk
-        file_name: &str,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let otp_clone = self.otp.clone();
-        let otp_value = otp_clone.unwrap_or_else(|| "".to_string());
-
-        for (chunk_id, server_addresses) in all_server_addresses.iter().enumerate() {
-            for server_address in server_addresses {
-                // Connect to the chunk server
-                match ChunkClient::connect(format!("http://{}", server_address)).await {
-                    Ok(mut chunk_client) => {
-                        // Send the delete request
-                        match chunk_client
-                            .delete(Request::new(DeleteRequest {
-                                file_name: file_name.to_string(),
-                                chunk_id: chunk_id as u64,
-                                otp: otp_value.clone(),
-                            }))
-                            .await
-                        {
-                            Ok(response) => {
-                                info!(
-                                    "Delete Response from {} for chunk {}: {}",
-                                    server_address,
-                                    chunk_id,
-                                    response.into_inner().message
-                                );
-                            }
-                            Err(e) => {
-                                error!(
-                                    "Failed to delete chunk {} from server {}: {}",
-                                    chunk_id, server_address, e
-                                );
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        error!(
-                            "Failed to connect to chunk server {} for chunk {}: {}",
-                            server_address, chunk_id, e
-                        );
-                    }
-                }
-            }
-        }
-        Ok(())
+/// Value following `option` on the command line, if the option is given
+fn option_value<'a>(args: &'a [String], option: &str) -> Result<Option<&'a str>> {
+    match args.iter().position(|arg| arg == option) {
+        Some(position) => match args.get(position + 1) {
+            Some(value) => Ok(Some(value)),
+            None => Err(Error::Usage(format!("Missing value after {}", option))),
+        },
+        None => Ok(None),
     }
+}
 
-    // Append data to the end of the file
-    pub async fn append_file(
-        &self,
-        all_server_addresses: Vec<Vec<String>>, // 2D vector of server addresses
-        file_name: &str,
+/// Operation given on the command line, with its arguments
+enum Command {
+    Upload {
+        local_path: String, // "-" for stdin
+        remote_name: String,
+    },
+    Read {
+        file_name: String,
+        offset: Option<u64>,
+        length: Option<u64>,
+    },
+    Print {
+        file_name: String,
+        part: Part,
+    },
+    Copy {
+        source: String,
+        destination: String,
+    },
+    Snapshot {
+        source: String,
+        snapshot_name: String,
+    },
+    Delete {
+        file_name: String,
+    },
+    Ls {
+        directory: String,
+    },
+    Rm {
+        path: String,
+        recursive: bool,
+    },
+    Undelete {
+        file_name: String,
+    },
+    Append {
+        file_name: String,
         data: String,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let otp_clone = self.otp.clone();
-        let otp_value = otp_clone.unwrap_or_else(|| "".to_string());
-
-        for (chunk_id, server_addresses) in all_server_addresses.iter().enumerate() {
-            let mut append_tasks = vec![];
-
-            for server_address in server_addresses {
-                let chunk_id = chunk_id as u64; // Convert to u64 for compatibility
-                let server_address = server_address.clone();
-                let file_name = file_name.to_string();
-                let data = data.clone();
-                let otp_value = otp_value.clone();
-
-                // Spawn a task for each replica
-                let task = tokio::spawn(async move {
-                    match ChunkClient::connect(format!("http://{}", server_address)).await {
-                        Ok(mut chunk_client) => {
-                            match chunk_client
-                                .append(Request::new(AppendRequest {
-                                    file_name,
-                                    chunk_id,
-                                    data,
-                                    otp: otp_value,
-                                }))
-                                .await
-                            {
-                                Ok(response) => {
-                                    info!(
-                                        "Append Response from server {} for chunk {}: {}",
-                                        server_address,
-                                        chunk_id,
-                                        response.into_inner().message
-                                    );
-                                }
-                                Err(e) => {
-                                    error!(
-                                        "Failed to append to chunk {} on server {}: {}",
-                                        chunk_id, server_address, e
-                                    );
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            error!("Failed to connect to server {}: {}", server_address, e);
-                        }
-                    }
-                });
+    },
+    Write {
+        file_name: String,
+        offset: u64,
+        data: String,
+    },
+    Truncate {
+        file_name: String,
+        length: u64,
+    },
+    Verify {
+        file_name: String,
+    },
+    Stat {
+        file_name: String,
+    },
+    Usage,
+    Decommission {
+        address: String,
+        status_only: bool, // decommission-status
+    },
+    Rebalance {
+        status_only: bool, // rebalance-status
+    },
+    Bench(BenchOptions),
+}
 
-                append_tasks.push(task);
-            }
+/// Part of a file `cat`, `head` and `tail` print
+enum Part {
+    All,
+    Head(u64),
+    Tail(u64),
+}
 
-            // Wait for all tasks to complete for the current chunk
-            futures::future::join_all(append_tasks).await;
-        }
-        Ok(())
-    }
+/// The command line with its options, checked before connecting to the cluster
+struct CommandLine {
+    command: Command,
+    username: Option<String>,
+    password: Option<String>,
+    output: Option<String>,
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // load logging config
-    let config = load_config("config.toml")?;
-    let log_path = config.client.log_path;
-    let log_level = config.common.log_level;
-
-    // Set up logger
-    let file_appender = rolling::daily(log_path, "client.log");
-    let stdout_layer = fmt::layer().with_writer(std::io::stdout).with_ansi(true);
-    let file_layer = fmt::layer().with_writer(file_appender).with_ansi(false); // Disable ANSI escape codes for file logs
-    let env_filter = EnvFilter::from_default_env().add_directive(log_level.parse().unwrap());
-    let subscriber = Registry::default()
-        .with(env_filter)
-        .with(stdout_layer)
-        .with(file_layer);
-
-    tracing::subscriber::set_global_default(subscriber)
-        .expect("Failed to set global default subscriber");
-
-    // Create client instance and load config
-    let mut client = Client::new("config.toml").await?;
-
-    // Parse command-line arguments
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        error!("Usage: client <command> [arguments] [-u <username>] [-p <password>]");
-        error!("Commands: upload <file_name>, read <file_name>, delete <file_name>, append <file_name> <data>");
-        return Ok(());
-    }
-    let operation = args[1].as_str();
+/// Parse the command line of the client, failing with `Error::Usage` if the command is missing,
+/// unknown or lacks arguments
+fn parse_command_line(args: &[String]) -> Result<CommandLine> {
+    let Some(operation) = args.get(1).filter(|arg| !arg.starts_with('-')) else {
+        return Err(Error::Usage(
+            "Usage: client <command> [arguments] [-u <username>] [-p <password>] [--json] [--output <path>] [--config <path>] [--log-level <level>]\n\
+             Commands: upload <local_path> [remote_name], upload - <remote_name>, read <file_name> [--offset <n>] [--length <n>], cat <file_name>, head <file_name> [-n <bytes>], tail <file_name> [-n <bytes>], copy <source> <destination>, snapshot <source> <snapshot_name>, delete <file_name>, undelete <file_name>, ls [directory], rm [-r] <path>, append <file_name> <data>, write <file_name> <offset> <data>, truncate <file_name> <bytes>, stat <file_name>, verify <file_name>, usage, decommission <address>, decommission-status <address>, rebalance, rebalance-status, bench [--size <size>] [--files <n>] [--concurrency <n>] [--read] [--appends <n>]"
+                .to_string(),
+        ));
+    };
+    let operation = operation.as_str();
     let mut username: Option<String> = None;
     let mut password: Option<String> = None;
+    let mut offset: Option<u64> = None;
+    let mut length: Option<u64> = None;
+    let mut bytes: Option<u64> = None;
+    let output = option_value(args, "--output")?.map(str::to_string);
 
     // Extract the authentication parameters
     let mut i = 2; // Start after the command name
@@ -468,8 +224,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     username = Some(args[i + 1].clone());
                     i += 1; // Skip the next argument as it's the username
                 } else {
-                    error!("Missing username after -u");
-                    return Ok(());
+                    return Err(Error::Usage("Missing username after -u".to_string()));
                 }
             }
             "-p" => {
@@ -477,88 +232,299 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     password = Some(args[i + 1].clone());
                     i += 1; // Skip the next argument as it's the password
                 } else {
-                    error!("Missing password after -p");
-                    return Ok(());
+                    return Err(Error::Usage("Missing password after -p".to_string()));
+                }
+            }
+            "--config" | "--log-level" => i += 1, // Applied when setting up the client
+            "--output" | "--size" | "--files" | "--concurrency" | "--appends" => i += 1, // Parsed where used
+            "--offset" | "--length" | "-n" => {
+                match args.get(i + 1).and_then(|value| value.parse::<u64>().ok()) {
+                    Some(value) if args[i] == "--offset" => offset = Some(value),
+                    Some(value) if args[i] == "-n" => bytes = Some(value),
+                    Some(value) => length = Some(value),
+                    None => {
+                        return Err(Error::Usage(format!(
+                            "Missing or invalid number after {}",
+                            args[i]
+                        )));
+                    }
                 }
+                i += 1; // Skip the next argument as it's the number
             }
             _ => {} // Ignore other arguments
         }
         i += 1;
     }
 
-    // Authenticate the user
-    if client.common_config.use_authentication {
-        if let (Some(ref username), Some(ref password)) = (username.as_deref(), password.as_deref())
-        {
-            client.authenticate(username, password).await?;
-            info!("after auth");
-        } else {
-            error!("Authentication requires both username and password.");
-            return Ok(());
-        }
-    }
-
-    match operation {
+    // Positional arguments follow the command, `usage` is the message if they are missing
+    let argument = |index: usize, usage: &str| {
+        args.get(index)
+            .cloned()
+            .ok_or_else(|| Error::Usage(usage.to_string()))
+    };
+    let number = |index: usize, usage: &str| {
+        args.get(index)
+            .and_then(|value| value.parse::<u64>().ok())
+            .ok_or_else(|| Error::Usage(usage.to_string()))
+    };
+    let command = match operation {
         "upload" => {
-            if args.len() < 3 {
-                error!("Usage: upload <file_name>");
-                return Ok(());
+            let usage = "Usage: upload <local_path> [remote_name], or upload - <remote_name> to upload stdin";
+            let local_path = argument(2, usage)?;
+            // The remote name defaults to the file name of the local path
+            let remote_name = args
+                .get(3)
+                .filter(|arg| !arg.starts_with('-'))
+                .map(String::as_str)
+                .or_else(|| {
+                    Path::new(&local_path)
+                        .file_name()
+                        .and_then(|name| name.to_str())
+                        .filter(|_| local_path != "-")
+                })
+                .ok_or_else(|| Error::Usage(usage.to_string()))?
+                .to_string();
+            Command::Upload {
+                local_path,
+                remote_name,
             }
-            let file_name = args[2].clone();
-            let file_metadata = tokio::fs::metadata(&file_name).await.map_err(|e| {
-                error!("Failed to get metadata for file '{}': {}", file_name, e);
-                e
-            })?;
-            let file_size = file_metadata.len();
-            debug!("File size: {} bytes", file_size);
-
-            info!("Requesting chunk assignment for file: {}", file_name);
-            let assign_response = client
-                .master_client
-                .assign_chunks(Request::new(AssignRequest {
-                    file_name: file_name.clone(),
-                    file_size,
-                }))
-                .await?
-                .into_inner();
-            debug!("Got chunk assignment for file: {}", file_name);
-
-            if let Err(e) = client
-                .upload_file(assign_response.chunk_info_list, file_name)
-                .await
-            {
-                error!("Error during upload: {}", e);
+        }
+        "read" => Command::Read {
+            file_name: argument(2, "Usage: read <file_name> [--offset <n>] [--length <n>]")?,
+            offset,
+            length,
+        },
+        "cat" => Command::Print {
+            file_name: argument(2, "Usage: cat <file_name>")?,
+            part: Part::All,
+        },
+        "head" | "tail" => {
+            let file_name = argument(2, &format!("Usage: {} <file_name> [-n <bytes>]", operation))?;
+            let bytes = bytes.unwrap_or(DEFAULT_HEAD_TAIL_BYTES);
+            Command::Print {
+                file_name,
+                part: if operation == "head" {
+                    Part::Head(bytes)
+                } else {
+                    Part::Tail(bytes)
+                },
             }
         }
-        "read" => {
-            if args.len() < 3 {
-                error!("Usage: read <file_name>");
-                return Ok(());
+        "copy" => {
+            let usage = "Usage: copy <source> <destination>";
+            Command::Copy {
+                source: argument(2, usage)?,
+                destination: argument(3, usage)?,
             }
-            let file_name = args[2].as_str();
-            let randomized_server_addresses = client
-                .get_randomized_server_addresses(file_name)
-                .await
-                .map_err(|e| {
-                    error!("Error retrieving random server addresses: {}", e);
-                    e
-                })?;
+        }
+        "snapshot" => {
+            let usage = "Usage: snapshot <source> <snapshot_name>";
+            Command::Snapshot {
+                source: argument(2, usage)?,
+                snapshot_name: argument(3, usage)?,
+            }
+        }
+        "delete" => Command::Delete {
+            file_name: argument(2, "Usage: delete <file_name>")?,
+        },
+        // Lists the root if no directory is given
+        "ls" => Command::Ls {
+            directory: args
+                .get(2)
+                .filter(|arg| !arg.starts_with('-'))
+                .map_or("/", |arg| arg.as_str())
+                .to_string(),
+        },
+        "rm" => {
+            let recursive = args.get(2).is_some_and(|arg| arg == "-r");
+            let path_index = if recursive { 3 } else { 2 };
+            let Some(path) = args.get(path_index).filter(|arg| !arg.starts_with('-')) else {
+                return Err(Error::Usage("Usage: rm [-r] <path>".to_string()));
+            };
+            Command::Rm {
+                path: path.clone(),
+                recursive,
+            }
+        }
+        "undelete" => Command::Undelete {
+            file_name: argument(2, "Usage: undelete <file_name>")?,
+        },
+        "append" => {
+            let usage = "Usage: append <file_name> <data>";
+            Command::Append {
+                file_name: argument(2, usage)?,
+                data: argument(3, usage)?,
+            }
+        }
+        "write" => {
+            let usage = "Usage: write <file_name> <offset> <data>";
+            Command::Write {
+                file_name: argument(2, usage)?,
+                offset: number(3, usage)?,
+                data: argument(4, usage)?,
+            }
+        }
+        "truncate" => {
+            let usage = "Usage: truncate <file_name> <bytes>";
+            Command::Truncate {
+                file_name: argument(2, usage)?,
+                length: number(3, usage)?,
+            }
+        }
+        "verify" => Command::Verify {
+            file_name: argument(2, "Usage: verify <file_name>")?,
+        },
+        "stat" => Command::Stat {
+            file_name: argument(2, "Usage: stat <file_name>")?,
+        },
+        "usage" => Command::Usage,
+        "decommission" | "decommission-status" => Command::Decommission {
+            address: argument(2, &format!("Usage: {} <address>", operation))?,
+            status_only: operation == "decommission-status",
+        },
+        "rebalance" | "rebalance-status" => Command::Rebalance {
+            status_only: operation == "rebalance-status",
+        },
+        "bench" => Command::Bench(parse_bench_options(args)?),
+        _ => {
+            return Err(Error::Usage(format!(
+                "Invalid command '{}'. Available commands: upload, read, cat, head, tail, copy, snapshot, delete, undelete, ls, rm, append, write, truncate, stat, verify, usage, decommission, decommission-status, rebalance, rebalance-status, bench",
+                operation
+            )));
+        }
+    };
+
+    Ok(CommandLine {
+        command,
+        username,
+        password,
+        output,
+    })
+}
 
-            if let Err(e) = client
-                .read_file(randomized_server_addresses, file_name)
-                .await
-            {
-                error!("Error during read: {}", e);
+/// Workload of `bench` given by `--size`, `--files`, `--concurrency`, `--read` and `--appends`
+fn parse_bench_options(args: &[String]) -> Result<BenchOptions> {
+    let usage = || {
+        Error::Usage(
+            "Usage: bench [--size <size>] [--files <n>] [--concurrency <n>] [--read] [--appends <n>]"
+                .to_string(),
+        )
+    };
+    let file_size = match option_value(args, "--size")? {
+        Some(value) => parse_size(value).map_err(|_| usage())?,
+        None => DEFAULT_BENCH_FILE_SIZE,
+    };
+    let count = |option, default| match option_value(args, option)? {
+        Some(value) => value.parse::<usize>().map_err(|_| usage()),
+        None => Ok(default),
+    };
+    let options = BenchOptions {
+        file_size,
+        files: count("--files", DEFAULT_BENCH_FILES)?,
+        concurrency: count("--concurrency", DEFAULT_BENCH_CONCURRENCY)?,
+        read: args.iter().any(|arg| arg == "--read"),
+        appends: count("--appends", 0)?,
+    };
+    if options.file_size == 0 || options.files == 0 || options.concurrency == 0 {
+        return Err(usage());
+    }
+    Ok(options)
+}
+
+/// Perform the operation given on the command line, printing its result as JSON with `json`.
+/// `config` is the configuration `client` was connected with.
+async fn execute(
+    client: &mut Client,
+    config: &Config,
+    command_line: CommandLine,
+    json: bool,
+) -> Result<()> {
+    let CommandLine {
+        command,
+        username,
+        password,
+        output,
+    } = command_line;
+    let output = output.as_deref();
+
+    // Authenticate the user
+    let mut credentials = None;
+    if client.use_authentication() {
+        let flags = PartialCredentials { username, password };
+        let Some(resolved) = resolve_credentials(flags)? else {
+            return Err(Error::Usage(
+                "Authentication requires a username: use -u, set RUSTFS_USERNAME or add it to ~/.rustfs/credentials".to_string(),
+            ));
+        };
+        client
+            .authenticate(&resolved.username, &resolved.password)
+            .await?;
+        credentials = Some(resolved);
+    }
+
+    match command {
+        Command::Upload {
+            local_path,
+            remote_name,
+        } => {
+            let uploaded = if local_path == "-" {
+                client.upload_stream(tokio::io::stdin(), &remote_name).await
+            } else {
+                client.upload(&local_path, &remote_name).await
+            };
+            let summary = uploaded.map_err(|e| {
+                error!("Error during upload: {}", e);
+                e
+            })?;
+            // Stored with fewer replicas than replication_factor, see `allow_degraded_writes`
+            let degraded_chunks = summary.degraded_chunks();
+            let failure = (degraded_chunks > 0).then(|| {
+                Error::Degraded(format!(
+                    "{} of {} chunk(s) of '{}' are stored with fewer than {} replicas",
+                    degraded_chunks,
+                    summary.chunks.len(),
+                    summary.file_name,
+                    summary.replication_factor
+                ))
+            });
+            if json {
+                print_result(&summary, failure.as_ref())?;
+            } else {
+                info!("File '{}' uploaded as '{}'.", local_path, summary.file_name);
+            }
+            if let Some(failure) = failure {
+                return Err(failure);
             }
         }
-        "delete" => {
-            if args.len() < 3 {
-                error!("Usage: delete <file_name>");
+        Command::Read {
+            file_name,
+            offset,
+            length,
+        } => {
+            let file_name = file_name.as_str();
+
+            // Only read the chunks covering the requested byte range
+            if offset.is_some() || length.is_some() || output.is_some() || json {
+                let mut out = read_output(output, json)?;
+                let summary = client
+                    .read_range_to(
+                        file_name,
+                        offset.unwrap_or(0),
+                        length.unwrap_or(u64::MAX),
+                        &mut out,
+                    )
+                    .await
+                    .map_err(|e| {
+                        error!("Error during read: {}", e);
+                        e
+                    })?;
+                out.flush()?;
+                if json {
+                    print_read(file_name, summary)?;
+                }
                 return Ok(());
             }
-            let file_name = args[2].as_str();
 
-            // Obtain a complete list of addresses
             let all_server_addresses =
                 client
                     .get_all_server_addresses(file_name)
@@ -568,67 +534,558 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         e
                     })?;
 
-            if all_server_addresses.is_empty() {
-                error!("No chunk servers found for file '{}'.", file_name);
-                return Ok(());
+            let file_content = client
+                .read_file(all_server_addresses, file_name)
+                .await
+                .map_err(|e| {
+                    error!("Error during read: {}", e);
+                    e
+                })?;
+            print!("{}", file_content);
+            if !file_content.ends_with('\n') {
+                println!();
             }
-
-            // Create DeleteFileRequest for metadata on the Master node
-            let request = DeleteFileRequest {
-                file_name: file_name.to_string(),
+        }
+        Command::Print { file_name, part } => {
+            let file_name = file_name.as_str();
+
+            // Written chunk by chunk, so that large files are not held in memory. Stdout is not
+            // locked meanwhile, as the logger writes to it from other threads.
+            let mut out = read_output(output, json)?;
+            let read = match part {
+                Part::All => client.read_range_to(file_name, 0, u64::MAX, &mut out).await,
+                Part::Head(bytes) => client.read_range_to(file_name, 0, bytes, &mut out).await,
+                Part::Tail(bytes) => client.read_tail_to(file_name, bytes, &mut out).await,
             };
-
-            // Call delete_file RPC
-            match client.master_client.delete_file(request).await {
-                Ok(response) => {
-                    if response.get_ref().success {
-                        info!("File '{}' deleted successfully.", file_name);
-                    } else {
-                        error!(
-                            "Failed to delete file '{}': {}",
-                            file_name,
-                            response.get_ref().message
-                        );
-                        return Ok(()); // Stop further execution if the deletion failed
-                    }
+            let summary = read.map_err(|e| {
+                error!("Error during read: {}", e);
+                e
+            })?;
+            out.flush()?;
+            if json {
+                print_read(file_name, summary)?;
+            }
+        }
+        Command::Copy {
+            source,
+            destination,
+        } => {
+            let status = client.copy(&source, &destination).await.map_err(|e| {
+                error!("Error during copy: {}", e);
+                e
+            })?;
+            if json {
+                print_json(&status)?;
+            } else {
+                info!("File '{}' copied to '{}'.", source, destination);
+            }
+        }
+        Command::Snapshot {
+            source,
+            snapshot_name,
+        } => {
+            let shared_chunks = client
+                .snapshot_file(&source, &snapshot_name)
+                .await
+                .map_err(|e| {
+                    error!("Error during snapshot: {}", e);
+                    e
+                })?;
+            if json {
+                print_json(&json!({
+                    "source": source,
+                    "snapshot_name": snapshot_name,
+                    "shared_chunks": shared_chunks
+                }))?;
+            } else {
+                info!("Snapshot '{}' of '{}' created.", snapshot_name, source);
+            }
+        }
+        Command::Delete { file_name } => {
+            client.delete_file(&file_name).await.map_err(|e| {
+                error!("Error during delete: {}", e);
+                e
+            })?;
+            if json {
+                print_json(&json!({ "deleted_files": [file_name] }))?;
+            } else {
+                info!("File '{}' deleted successfully.", file_name);
+            }
+        }
+        Command::Ls { directory } => {
+            let entries = client.list_files(&directory).await.map_err(|e| {
+                error!("Error listing '{}': {}", directory, e);
+                e
+            })?;
+            if json {
+                return print_json(&entries);
+            }
+            for entry in entries {
+                if entry.is_directory {
+                    println!("{:>12}  {}/", "-", entry.path);
+                } else {
+                    println!("{:>12}  {}", entry.size, entry.path);
                 }
-                Err(e) => {
-                    error!("Error during delete: {}", e);
-                    return Ok(()); // Stop further execution if the RPC call failed
+            }
+        }
+        Command::Rm { path, recursive } => {
+            let deleted_files = if recursive {
+                client.delete_prefix(&path).await
+            } else {
+                client.delete_file(&path).await.map(|()| vec![path.clone()])
+            }
+            .map_err(|e| {
+                error!("Error during delete: {}", e);
+                e
+            })?;
+            if json {
+                print_json(&json!({ "deleted_files": deleted_files }))?;
+            } else {
+                for file_name in &deleted_files {
+                    info!("File '{}' deleted successfully.", file_name);
                 }
             }
+        }
+        Command::Undelete { file_name } => {
+            let restored_name = client.undelete_file(&file_name).await.map_err(|e| {
+                error!("Error during undelete: {}", e);
+                e
+            })?;
+            if json {
+                print_json(&json!({ "file_name": file_name, "restored_as": restored_name }))?;
+            } else {
+                info!("File '{}' restored as '{}'.", file_name, restored_name);
+            }
+        }
+        Command::Append { file_name, data } => {
+            let appended_bytes = data.len();
+            let all_server_addresses = client
+                .get_append_server_addresses(&file_name)
+                .await
+                .map_err(|e| {
+                    error!("Error retrieving server addresses: {}", e);
+                    e
+                })?;
 
-            if let Err(e) = client.delete_file(all_server_addresses, file_name).await {
-                error!("Error during delete: {}", e);
+            client
+                .append_file(all_server_addresses, &file_name, data)
+                .await
+                .map_err(|e| {
+                    error!("Error during append: {}", e);
+                    e
+                })?;
+            if json {
+                print_json(&json!({ "file_name": file_name, "bytes": appended_bytes }))?;
             }
         }
-        "append" => {
-            if args.len() < 4 {
-                error!("Usage: append <file_name> <data>");
-                return Ok(());
+        Command::Write {
+            file_name,
+            offset,
+            data,
+        } => {
+            client
+                .write_at(&file_name, offset, data.as_bytes())
+                .await
+                .map_err(|e| {
+                    error!("Error during write: {}", e);
+                    e
+                })?;
+            if json {
+                print_json(
+                    &json!({ "file_name": file_name, "offset": offset, "bytes": data.len() }),
+                )?;
             }
-            let file_name = args[2].as_str();
-            let data = args[3].to_string();
-            let all_server_addresses =
+        }
+        Command::Truncate {
+            file_name,
+            length: new_length,
+        } => {
+            let dropped_chunks =
                 client
-                    .get_all_server_addresses(file_name)
+                    .truncate_file(&file_name, new_length)
                     .await
                     .map_err(|e| {
-                        error!("Error retrieving server addresses: {}", e);
+                        error!("Error during truncate: {}", e);
                         e
                     })?;
-
-            if let Err(e) = client
-                .append_file(all_server_addresses, file_name, data)
-                .await
-            {
-                error!("Error during append: {}", e);
+            if json {
+                print_json(&json!({
+                    "file_name": file_name,
+                    "size": new_length,
+                    "dropped_chunks": dropped_chunks
+                }))?;
+            } else {
+                info!("File '{}' truncated to {} byte(s).", file_name, new_length);
             }
         }
-        _ => {
-            error!("Invalid command. Available commands: upload, read, delete, append");
+        Command::Verify { file_name } => {
+            let file_name = file_name.as_str();
+            let verifications = client.verify_file(file_name).await.map_err(|e| {
+                error!("Error verifying file: {}", e);
+                e
+            })?;
+            let replication_factor = client.replication_factor();
+            let without_quorum = verifications
+                .iter()
+                .filter(|chunk| !chunk.has_quorum(replication_factor))
+                .count();
+            let failure = (without_quorum > 0).then(|| {
+                Error::VerificationFailed(format!(
+                    "{} of {} chunk(s) of '{}' have no agreeing majority of replicas",
+                    without_quorum,
+                    verifications.len(),
+                    file_name
+                ))
+            });
+            if json {
+                print_result(
+                    &verification_json(file_name, &verifications, replication_factor),
+                    failure.as_ref(),
+                )?;
+            } else {
+                print_verification(file_name, &verifications, replication_factor);
+            }
+            if let Some(failure) = failure {
+                return Err(failure);
+            }
+        }
+        Command::Stat { file_name } => {
+            let file_info = client.get_file_info(&file_name).await.map_err(|e| {
+                error!("Error retrieving file info: {}", e);
+                e
+            })?;
+            if json {
+                return print_json(&file_info);
+            }
+            print_file_info(&file_info);
+        }
+        Command::Usage => {
+            let usage = client.get_usage().await.map_err(|e| {
+                error!("Error retrieving usage: {}", e);
+                e
+            })?;
+            if json {
+                return print_json(&usage);
+            }
+            let limit = |max: u64| {
+                if max == 0 {
+                    "unlimited".to_string()
+                } else {
+                    max.to_string()
+                }
+            };
+            println!(
+                "Bytes: {} of {}",
+                usage.total_bytes,
+                limit(usage.max_total_bytes)
+            );
+            println!("Files: {} of {}", usage.files, limit(usage.max_files));
+        }
+        Command::Decommission {
+            address,
+            status_only,
+        } => {
+            let progress = if status_only {
+                client.decommission_status(&address).await
+            } else {
+                client.decommission_server(&address).await
+            }
+            .map_err(|e| {
+                error!("Error during decommission of {}: {}", address, e);
+                e
+            })?;
+            if json {
+                return print_json(&progress);
+            }
+            println!("{}", progress.message);
+            println!(
+                "Chunks remaining: {} ({} unplaceable)",
+                progress.chunks_remaining, progress.unplaceable_chunks
+            );
+        }
+        Command::Rebalance { status_only } => {
+            let status = if status_only {
+                client.rebalance_status().await
+            } else {
+                client.trigger_rebalance().await
+            }
+            .map_err(|e| {
+                error!("Error during rebalance: {}", e);
+                e
+            })?;
+            if json {
+                return print_json(&status);
+            }
+            println!("{}", status.message);
+            println!(
+                "Moves: {} done, {} failed, {} planned",
+                status.moves_done, status.moves_failed, status.moves_planned
+            );
+            println!("Chunk count spread: {}", status.spread);
+        }
+        Command::Bench(options) => {
+            // The files are deleted when the benchmark ends, fails or is interrupted
+            let bench = Bench::new(options);
+            let report = tokio::select! {
+                report = bench.run(config, credentials.as_ref()) => report.map_err(|e| {
+                    error!("Error during benchmark: {}", e);
+                    e
+                })?,
+                () = shutdown::signal() => {
+                    let deleted = bench.cleanup(client).await?;
+                    return Err(Error::Interrupted(format!(
+                        "deleted the {} file(s) of the benchmark in {}",
+                        deleted,
+                        bench.directory()
+                    )));
+                }
+            };
+            if json {
+                print_json(&report)?;
+            } else {
+                print_bench(&report);
+            }
         }
     }
 
     Ok(())
 }
+
+/// Print the result of a command as JSON on stdout
+fn print_json(value: &impl Serialize) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+/// Print the result of a command as JSON on stdout, with the error it nevertheless failed with
+fn print_result(value: &impl Serialize, failure: Option<&Error>) -> Result<()> {
+    let mut result = serde_json::to_value(value)?;
+    if let Some(e) = failure {
+        result["error"] = json!({ "code": e.code(), "message": e.to_string() });
+    }
+    print_json(&result)
+}
+
+/// Where `read`, `cat`, `head` and `tail` write the file data: the `--output` file if given,
+/// else stdout, which carries the JSON result instead with `json`
+fn read_output(output: Option<&str>, json: bool) -> Result<Box<dyn Write>> {
+    Ok(match output {
+        Some(path) => Box::new(File::create(path)?),
+        None if json => Box::new(std::io::sink()),
+        None => Box::new(std::io::stdout()),
+    })
+}
+
+/// Print the results of a benchmark
+fn print_bench(report: &BenchReport) {
+    println!(
+        "{} file(s) of {}, {} at once, in {}, sync_writes = \"{}\" in the local configuration",
+        report.files,
+        format_size(report.file_size),
+        report.concurrency,
+        report.directory,
+        report.sync_writes
+    );
+    let phases = [
+        ("Upload", Some(&report.upload)),
+        ("Read", report.read.as_ref()),
+    ];
+    for (name, phase) in phases {
+        let Some(phase) = phase else { continue };
+        let latency = &phase.chunk_latency_ms;
+        println!(
+            "{}: {} bytes in {:.2}s, {:.1} MB/s, {} chunk(s), latency p50 {:.1}ms, p90 {:.1}ms, p99 {:.1}ms, max {:.1}ms",
+            name,
+            phase.bytes,
+            phase.secs,
+            phase.mb_per_sec,
+            phase.chunks,
+            latency.p50,
+            latency.p90,
+            latency.p99,
+            latency.max
+        );
+        for (server, failures) in &phase.replica_failures {
+            println!("  {} failed {} chunk(s)", server, failures);
+        }
+    }
+    if let Some(append) = &report.append {
+        let latency = &append.latency_ms;
+        println!(
+            "Append: {} append(s) of {} bytes in {:.2}s, {:.1}/s, latency p50 {:.1}ms, p90 {:.1}ms, p99 {:.1}ms, max {:.1}ms",
+            append.appends,
+            APPEND_SIZE,
+            append.secs,
+            append.appends_per_sec,
+            latency.p50,
+            latency.p90,
+            latency.p99,
+            latency.max
+        );
+    }
+    println!("Delete: {:.2}s", report.delete_secs);
+    let cache = &report.chunk_cache;
+    if cache.hits + cache.misses > 0 {
+        println!(
+            "Chunk cache: {} hit(s), {} miss(es)",
+            cache.hits, cache.misses
+        );
+    }
+}
+
+/// Print the JSON result of a read
+fn print_read(file_name: &str, summary: ReadSummary) -> Result<()> {
+    print_json(&json!({
+        "file_name": file_name,
+        "bytes": summary.bytes,
+        "crc32": summary.crc32,
+    }))
+}
+
+/// Status of a verified chunk and the number of replicas agreeing on its contents
+fn chunk_status(chunk: &ChunkVerification, replication_factor: usize) -> (&'static str, usize) {
+    let agreeing = chunk.agreement().map_or(0, |(_, count)| count);
+    let status = if !chunk.has_quorum(replication_factor) {
+        "NO QUORUM"
+    } else if agreeing < chunk.replicas.len() {
+        "DIVERGENT"
+    } else if agreeing < replication_factor {
+        "UNDER-REPLICATED"
+    } else {
+        "OK"
+    };
+    (status, agreeing)
+}
+
+/// Whether a checked replica agrees with the majority of the replicas of its chunk
+fn replica_verdict(replica: &ReplicaChecksum, chunk: &ChunkVerification) -> &'static str {
+    match (chunk.agreement(), &replica.checksum) {
+        _ if replica.corrupt => "CORRUPT",
+        (None, _) => "NO MAJORITY",
+        (Some((agreed, _)), Ok(checksum)) if agreed == *checksum => "agrees",
+        _ => "DIFFERS",
+    }
+}
+
+/// The verification of a file as JSON
+fn verification_json(
+    file_name: &str,
+    verifications: &[ChunkVerification],
+    replication_factor: usize,
+) -> Value {
+    let chunks: Vec<Value> = verifications
+        .iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            let (status, agreeing) = chunk_status(chunk, replication_factor);
+            let replicas: Vec<Value> = chunk
+                .replicas
+                .iter()
+                .map(|replica| match &replica.checksum {
+                    Ok((checksum, length)) => json!({
+                        "server_address": replica.server_address,
+                        "crc32": checksum,
+                        "bytes": length,
+                        "verdict": replica_verdict(replica, chunk),
+                    }),
+                    Err(e) => json!({
+                        "server_address": replica.server_address,
+                        "error": e.to_string(),
+                    }),
+                })
+                .collect();
+            json!({
+                "index": index,
+                "chunk_id": chunk.chunk_id,
+                "agreeing": agreeing,
+                "status": status,
+                "replicas": replicas,
+            })
+        })
+        .collect();
+    json!({
+        "file_name": file_name,
+        "replication_factor": replication_factor,
+        "chunks": chunks,
+    })
+}
+
+/// Print the chunk layout of a file as a table, flagging under-replicated chunks
+fn print_verification(
+    file_name: &str,
+    verifications: &[ChunkVerification],
+    replication_factor: usize,
+) {
+    println!("File: {}", file_name);
+    println!(
+        "{:<6} {:<40} {:>8}  {:<17}",
+        "INDEX", "CHUNK ID", "AGREEING", "STATUS"
+    );
+    for (index, chunk) in verifications.iter().enumerate() {
+        let (status, agreeing) = chunk_status(chunk, replication_factor);
+        println!(
+            "{:<6} {:<40} {:>8}  {:<17}",
+            index,
+            chunk.chunk_id,
+            format!("{}/{}", agreeing, replication_factor),
+            status
+        );
+        for replica in &chunk.replicas {
+            let detail = match &replica.checksum {
+                Ok((checksum, length)) => format!(
+                    "{:>10} bytes  crc {:08x}  {}",
+                    length,
+                    checksum,
+                    replica_verdict(replica, chunk)
+                ),
+                Err(e) => format!("UNAVAILABLE: {}", e),
+            };
+            println!("       {:<24} {}", replica.server_address, detail);
+        }
+    }
+}
+
+fn print_file_info(file_info: &FileInfoResponse) {
+    println!("File: {}", file_info.file_name);
+    if !file_info.owner.is_empty() {
+        println!("Owner: {}", file_info.owner);
+    }
+    println!(
+        "Size: {} bytes in {} chunk(s), replication factor {}",
+        file_info.total_size,
+        file_info.chunks.len(),
+        file_info.replication_factor
+    );
+    println!(
+        "{:<6} {:<40} {:>8} {:>10}  {:<17} REPLICAS",
+        "INDEX", "CHUNK ID", "VERSION", "SIZE", "STATUS"
+    );
+    for (index, chunk) in file_info.chunks.iter().enumerate() {
+        let status = if (chunk.server_addresses.len() as u64) < file_info.replication_factor {
+            "UNDER-REPLICATED"
+        } else {
+            "OK"
+        };
+        println!(
+            "{:<6} {:<40} {:>8} {:>10}  {:<17} {}",
+            index,
+            chunk.chunk_id,
+            chunk.version,
+            chunk.size,
+            status,
+            chunk.server_addresses.join(", ")
+        );
+    }
+    if file_info.from_shadow {
+        println!("Note: served by a shadow master, the layout may be outdated");
+    }
+    if file_info.under_replicated_chunks == 0 {
+        println!("Replica health: fully replicated");
+    } else {
+        println!(
+            "Replica health: {} of {} chunk(s) under-replicated",
+            file_info.under_replicated_chunks,
+            file_info.chunks.len()
+        );
+    }
+}
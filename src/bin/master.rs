@@ -1,58 +1,148 @@
-use clap::{Arg, Command};
-use std::net::SocketAddr;
+use clap::{Arg, ArgAction, Command};
 use std::sync::Arc;
+use tokio::sync::oneshot;
 use tonic::transport::Server;
 
 use crate::master::PingMasterRequest;
-use rustfs::config::load_config;
-use rustfs::master_service::MasterService;
+use rustfs::auth::{add_user, Role};
+use rustfs::cluster_auth::sign_request;
+use rustfs::config::{config_path, load_config, Config};
+use rustfs::logging;
+use rustfs::master_service::{ping_master_fields, MasterService};
+use rustfs::metrics::start_metrics_server;
 use rustfs::proto::master;
+use rustfs::reload::ConfigReloader;
+use rustfs::request_id::RequestIdLayer;
+use rustfs::shutdown;
+use rustfs::util::{connect_channel, node_addresses, server_tls_config};
 use tracing::{error, info, warn};
-use tracing_appender::rolling;
-use tracing_subscriber::{fmt, layer::SubscriberExt, EnvFilter, Registry};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // load logging config
-    let config = load_config("config.toml")?;
-    let log_path = config.master.log_path.clone();
-    let log_level = config.common.log_level.clone();
-
-    // Set up logger
-    let file_appender = rolling::daily(log_path, "master.log");
-    let stdout_layer = fmt::layer().with_writer(std::io::stdout).with_ansi(true);
-    let file_layer = fmt::layer().with_writer(file_appender).with_ansi(false); // Disable ANSI escape codes for file logs
-    let env_filter = EnvFilter::from_default_env().add_directive(log_level.parse().unwrap());
-    let subscriber = Registry::default()
-        .with(env_filter)
-        .with(stdout_layer)
-        .with(file_layer);
-
-    tracing::subscriber::set_global_default(subscriber)
-        .expect("Failed to set global default subscriber");
-
     // Parse command line arguments
     let matches = Command::new("MasterServer")
         .version("1.0")
         .about("Starts a MasterServer")
         .arg(
-            Arg::new("address")
-                .short('a')
+            Arg::new("address").short('a').value_name("ADDR").help(
+                "Sets the address the MasterServer binds and advertises (e.g., 127.0.0.1:50051)",
+            ),
+        )
+        .arg(
+            Arg::new("bind_addr")
+                .long("bind-addr")
+                .value_name("ADDR")
+                .help("Address the gRPC server listens on (e.g., 0.0.0.0:50051), overrides -a"),
+        )
+        .arg(
+            Arg::new("advertise_addr")
+                .long("advertise-addr")
                 .value_name("ADDR")
-                .help("Sets the address for the MasterServer (e.g., 127.0.0.1:50051)")
-                .required(true),
+                .help("Address other nodes and clients connect to, defaults to the bind address"),
+        )
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .value_name("PATH")
+                .help("Path of the config file, defaults to $RUSTFS_CONFIG or ./config.toml")
+                .global(true),
+        )
+        .arg(
+            Arg::new("log_level")
+                .long("log-level")
+                .value_name("LEVEL")
+                .help("Overrides log_level from the config file (e.g., debug, or rustfs=trace)"),
+        )
+        .arg(
+            Arg::new("print_default_config")
+                .long("print-default-config")
+                .action(ArgAction::SetTrue)
+                .exclusive(true)
+                .help("Prints a config file with every setting at its default value and exits"),
+        )
+        .subcommand_negates_reqs(true)
+        .subcommand(
+            Command::new("add-user")
+                .about("Adds a user to the authentication file, storing a password hash")
+                .arg(
+                    Arg::new("username")
+                        .short('u')
+                        .value_name("USERNAME")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("password")
+                        .short('p')
+                        .value_name("PASSWORD")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("role")
+                        .long("role")
+                        .value_name("ROLE")
+                        .value_parser(["admin", "readwrite", "readonly"])
+                        .default_value("readwrite")
+                        .help("admin users may change the files of every user, readonly users only read"),
+                ),
         )
         .get_matches();
 
-    let addr = matches
-        .get_one::<String>("address")
-        .expect("Address is required");
+    if matches.get_flag("print_default_config") {
+        print!("{}", Config::example());
+        return Ok(());
+    }
+
+    // Set up logger as configured by log_level and log_output
+    let config_path = config_path(matches.get_one::<String>("config").map(String::as_str));
+    let mut config = load_config(&config_path)?;
+    if let Some(log_level) = matches.get_one::<String>("log_level") {
+        config.common.log_level = log_level.clone();
+    }
+    let log_handle = logging::init(&config.common, &config.master.log_path, "master.log")?;
+
+    if let Some(add_user_matches) = matches.subcommand_matches("add-user") {
+        let username = add_user_matches
+            .get_one::<String>("username")
+            .expect("Username is required");
+        let password = add_user_matches
+            .get_one::<String>("password")
+            .expect("Password is required");
+        let role: Role = add_user_matches
+            .get_one::<String>("role")
+            .expect("Role has a default")
+            .parse()?;
+        add_user(
+            &config.master.authentication_file_path,
+            username,
+            password,
+            role,
+        )?;
+        info!(
+            "User '{}' added to {} as {}",
+            username,
+            config.master.authentication_file_path,
+            role.as_str()
+        );
+        return Ok(());
+    }
+
+    let arg = |name: &str| matches.get_one::<String>(name).map(String::as_str);
+    let addresses = node_addresses(
+        arg("address"),
+        arg("bind_addr"),
+        arg("advertise_addr"),
+        &config.master.bind_addr,
+        &config.master.advertise_addr,
+    )?;
+    let addr = &addresses.advertise;
 
-    // Load configuration
-    let config = load_config("config.toml")?;
+    let running_config = config.clone();
     let common_config = config.common;
 
-    info!("MasterServer running at {}", addr);
+    info!(
+        "MasterServer listening on {}, advertised as {}",
+        addresses.bind, addr
+    );
 
     // Determine the leader
     let mut leader_found = false;
@@ -62,11 +152,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             continue; // Skip pinging itself
         }
 
-        let target_url = format!("http://{}", master_addr);
-        match rustfs::proto::master::master_client::MasterClient::connect(target_url).await {
-            Ok(mut client) => {
+        match connect_channel(master_addr, &common_config.tls).await {
+            Ok(channel) => {
+                let mut client = rustfs::proto::master::master_client::MasterClient::new(channel);
                 let request = tonic::Request::new(PingMasterRequest {
                     sender_address: addr.clone(),
+                    internal_proof: sign_request(
+                        &common_config.cluster_secret,
+                        &ping_master_fields(addr),
+                    ),
                 });
 
                 match client.ping_master(request).await {
@@ -91,26 +185,82 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let is_leader = !leader_found;
 
     let master_service = Arc::new(MasterService::new(
-        &addr,
+        addr,
         config.master,
         common_config.clone(),
         is_leader,
         actural_master_addr,
     ));
+    let metrics_server = if master_service.config.metrics_addr.is_empty() {
+        None
+    } else {
+        start_metrics_server(
+            &master_service.config.metrics_addr,
+            master_service.metrics.registry.clone(),
+            None,
+        )
+    };
+    *master_service.reloader.lock().await = Some(ConfigReloader::new(
+        &config_path,
+        log_handle,
+        running_config,
+        metrics_server,
+        master_service.metrics.registry.clone(),
+    ));
+    #[cfg(unix)]
+    reload_on_hangup(Arc::clone(&master_service))?;
     if is_leader {
         info!("No leader found. This node will act as the leader.");
-        Arc::clone(&master_service).start_heartbeat_checker().await;
     } else {
         info!("This node is not the leader.");
-        Arc::clone(&master_service)
-            .start_shadow_master_ping_task()
-            .await;
     }
+    master_service.start_tasks().await;
+
+    let mut server = Server::builder();
+    if let Some(tls_config) = server_tls_config(&common_config.tls)? {
+        info!("TLS enabled for MasterServer");
+        server = server.tls_config(tls_config)?;
+    }
+    // Stop accepting requests on SIGTERM or Ctrl-C and let the running ones finish
+    let (stop_serving, serving_stopped) = oneshot::channel::<()>();
+    let serve = server
+        .layer(RequestIdLayer)
+        .add_service(master::master_server::MasterServer::new(Arc::clone(
+            &master_service,
+        )))
+        .serve_with_shutdown(addresses.bind, async {
+            let _ = serving_stopped.await;
+        });
+    tokio::pin!(serve);
+    tokio::select! {
+        served = &mut serve => served?,
+        () = shutdown::signal() => {
+            let _ = stop_serving.send(());
+            let timeout = common_config.shutdown_timeout;
+            match tokio::time::timeout(timeout, &mut serve).await {
+                Ok(served) => served?,
+                Err(_) => warn!("[Shutdown] Requests still running after {:?} are aborted", timeout),
+            }
+        }
+    }
+
+    master_service.shutdown().await;
+    info!("MasterServer stopped");
+    Ok(())
+}
 
-    Server::builder()
-        .add_service(master::master_server::MasterServer::new(master_service))
-        .serve(addr.parse::<SocketAddr>()?)
-        .await?;
+/// Reload the config file whenever the master receives SIGHUP, e.g. from `kill -HUP <pid>`
+#[cfg(unix)]
+fn reload_on_hangup(master_service: Arc<MasterService>) -> std::io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
 
+    let mut hangups = signal(SignalKind::hangup())?;
+    tokio::spawn(async move {
+        while hangups.recv().await.is_some() {
+            if let Err(e) = master_service.reload_config().await {
+                error!("[Reload] Keeping the current settings: {}", e);
+            }
+        }
+    });
     Ok(())
 }
@@ -0,0 +1,87 @@
+// Pool of reusable buffers bounding the memory used by chunk reads
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::chunkserver_service::TRANSFER_FRAME_SIZE;
+use crate::config::ChunkServerConfig;
+
+/// A fixed number of equally sized buffers, each used by one task at a time.
+///
+/// Taking a buffer waits while all of them are in use, so the buffers never take more than
+/// `capacity` times `buffer_size` bytes, however many tasks ask for one at once. Buffers are
+/// allocated when first needed and reused once they are returned.
+#[derive(Debug)]
+pub struct BufferPool {
+    buffer_size: usize,
+    permits: Arc<Semaphore>,   // One per buffer that may be taken
+    free: Mutex<Vec<Vec<u8>>>, // Returned buffers
+}
+
+impl BufferPool {
+    pub fn new(capacity: usize, buffer_size: usize) -> Self {
+        BufferPool {
+            buffer_size,
+            permits: Arc::new(Semaphore::new(capacity)),
+            free: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Wait for a free buffer, which is returned to the pool when dropped
+    pub async fn take(self: &Arc<Self>) -> PooledBuffer {
+        let permit = Arc::clone(&self.permits)
+            .acquire_owned()
+            .await
+            .expect("the semaphore is never closed");
+        let buffer = self
+            .free
+            .lock()
+            .unwrap()
+            .pop()
+            .unwrap_or_else(|| vec![0; self.buffer_size]);
+        PooledBuffer {
+            buffer,
+            pool: Arc::clone(self),
+            _permit: permit,
+        }
+    }
+}
+
+impl Default for BufferPool {
+    /// A pool of `read_buffers` frames, as configured by default
+    fn default() -> Self {
+        BufferPool::new(
+            ChunkServerConfig::default().read_buffers,
+            TRANSFER_FRAME_SIZE,
+        )
+    }
+}
+
+/// Buffer taken from a `BufferPool`, see `BufferPool::take`
+#[derive(Debug)]
+pub struct PooledBuffer {
+    buffer: Vec<u8>,
+    pool: Arc<BufferPool>,
+    _permit: OwnedSemaphorePermit, // Released after the buffer is back in the pool
+}
+
+impl Deref for PooledBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buffer
+    }
+}
+
+impl DerefMut for PooledBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.buffer
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        let buffer = std::mem::take(&mut self.buffer);
+        self.pool.free.lock().unwrap().push(buffer);
+    }
+}
@@ -1,44 +1,68 @@
-use std::fs;
-use tokio::fs::{File, OpenOptions};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use std::io::SeekFrom;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_stream::wrappers::ReceiverStream;
 use tonic::{Request, Response, Status};
 use tracing::{debug, error, info, warn};
 
 use crate::proto::chunk;
 use crate::proto::chunk::chunk_client::ChunkClient;
 
-use crate::chunkserver_service::ChunkService;
+use crate::chunkserver_service::{
+    apply_append_fields, apply_write_at_fields, checksum_file, clone_chunk_fields,
+    grant_lease_fields, leader_changed_fields, transfer_chunk_fields, truncate_chunk_fields,
+    upload_fields, write_error_status, ChunkForward, ChunkService, Lease, TRANSFER_FRAME_SIZE,
+};
+use crate::compression::{self, ChunkReader, ChunkWriter};
 use crate::proto::chunk::chunk_server::Chunk;
 use crate::proto::chunk::{
-    AppendRequest, AppendResponse, DeleteRequest, DeleteResponse, OtpRequest, OtpResponse,
-    ReadRequest, ReadResponse, SendChunkRequest, SendChunkResponse, UploadRequest, UploadResponse,
+    AppendRequest, AppendResponse, ApplyAppendRequest, ApplyAppendResponse, ApplyWriteAtRequest,
+    ApplyWriteAtResponse, ChecksumChunkRequest, ChecksumChunkResponse, CloneChunkRequest,
+    CloneChunkResponse, GrantLeaseRequest, GrantLeaseResponse, LeaderChangedRequest,
+    LeaderChangedResponse, ReadRequest, ReadResponse, SendChunkRequest, SendChunkResponse,
+    TruncateChunkRequest, TruncateChunkResponse, UploadRequest, UploadResponse, WriteAtRequest,
+    WriteAtResponse,
 };
+use crate::request_id::{request_id, request_with_id};
+use crate::util::connect_channel;
 
 #[tonic::async_trait]
 impl Chunk for ChunkService {
-    /// Store OTP for user
-    async fn send_otp(
+    /// Send the next heartbeats to a master that took over as leader
+    async fn leader_changed(
         &self,
-        request: Request<OtpRequest>,
-    ) -> Result<Response<OtpResponse>, Status> {
-        let req = request.into_inner();
-        let username = req.username;
-        let otp = req.otp;
-        let expiration_time = req.expiration_time;
-
+        request: Request<LeaderChangedRequest>,
+    ) -> Result<Response<LeaderChangedResponse>, Status> {
+        let LeaderChangedRequest {
+            leader_address,
+            epoch,
+            internal_proof,
+        } = request.into_inner();
+        self.validate_internal_proof(
+            &internal_proof,
+            &leader_changed_fields(&leader_address, &epoch.to_be_bytes()),
+        )
+        .await?;
+        // Heartbeats only ever go to a configured master
+        if !self.common_config.master_addrs.contains(&leader_address) {
+            warn!(
+                "[leader_changed] Ignoring announcement of '{}', which is not a configured master",
+                leader_address
+            );
+            return Err(Status::permission_denied(format!(
+                "'{}' is not a configured master",
+                leader_address
+            )));
+        }
+        self.advance_epoch(epoch).await?;
         info!(
-            "[send_otp] Received OTP for user '{}' with expiration time '{}'",
-            username, expiration_time
+            "[leader_changed] Master {} is the new leader",
+            leader_address
         );
-
-        // Store the OTP in the `otp_store` map
-        let mut otp_store_guard = self.otp_store.lock().await;
-        otp_store_guard.insert(otp.clone(), expiration_time);
-
-        // Respond to the master server
-        Ok(Response::new(OtpResponse {
-            message: format!("OTP for user '{}' received successfully.", username),
-        }))
+        *self.new_leader.lock().await = Some(leader_address);
+        Ok(Response::new(LeaderChangedResponse {}))
     }
 
     /// Transfers a chunk from this chunkserver to another chunkserver
@@ -47,282 +71,1005 @@ impl Chunk for ChunkService {
         &self,
         request: Request<SendChunkRequest>,
     ) -> Result<Response<SendChunkResponse>, Status> {
-        let req = request.into_inner();
-        let chunk_name = req.chunk_name;
-        let target_address = req.target_address;
-
-        info!(
-            "[transfer_chunk] Received request to transfer chunk '{}' to '{}'",
-            chunk_name, target_address
-        );
-
-        // Step 1: Read the chunk's data from local storage
-        // Split the chunk_name into file_name and chunk_id
-        let mut file_name_part = String::new();
-        let mut chunk_id_part: u64 = 0;
-        if let Some((file_part, id_part)) = chunk_name.split_once("_chunk_") {
-            // Assign values to the variables
-            file_name_part = file_part.to_string();
-            if let Ok(id) = id_part.parse::<u64>() {
-                chunk_id_part = id;
-            } else {
-                error!(
-                    "[transfer_chunk] Failed to parse chunk_id from '{}'",
-                    id_part
-                );
+        let timer = self.metrics.requests.start("transfer_chunk");
+        let result: Result<Response<SendChunkResponse>, Status> = async move {
+            let request_id = request_id(&request);
+            let req = request.into_inner();
+            self.validate_internal_proof(
+                &req.internal_proof,
+                &transfer_chunk_fields(
+                    &req.chunk_name,
+                    &req.target_address,
+                    &req.epoch.to_be_bytes(),
+                    &req.target_chunk_name,
+                ),
+            )
+            .await?;
+            self.validate_chunk_id(&req.chunk_name).await?;
+            if !req.target_chunk_name.is_empty() {
+                self.validate_chunk_id(&req.target_chunk_name).await?;
             }
-        } else {
-            error!(
-                "[transfer_chunk] Invalid chunk_name format: '{}'",
-                chunk_name
+            self.advance_epoch(req.epoch).await?;
+            let chunk_name = req.chunk_name;
+            let target_address = req.target_address;
+            let epoch = req.epoch;
+            // A copy under another name, e.g. of a copied file, may go to this server itself
+            let target_chunk_name = if req.target_chunk_name.is_empty() {
+                chunk_name.clone()
+            } else {
+                req.target_chunk_name
+            };
+
+            info!(
+                chunk_id = %chunk_name,
+                target = %target_address,
+                target_chunk_id = %target_chunk_name,
+                "[transfer_chunk] Received request to transfer chunk"
             );
-        }
-        debug!("[transfer_chunk] File name: {}", file_name_part);
-        debug!("[transfer_chunk] Chunk ID: {}", chunk_id_part);
 
-        let file_path = format!(
-            "{}/{}/{}",
-            self.addr_sanitized, self.config.data_path, chunk_name
-        );
-        info!(
-            "[transfer_chunk] Reading chunk '{}' from file: {}",
-            chunk_name, file_path
-        );
+            // Step 1: Open the chunk's file in local storage
+            let file_path = self.chunk_path(&chunk_name);
+            info!(
+                "[transfer_chunk] Reading chunk '{}' from file: {}",
+                chunk_name, file_path
+            );
 
-        let mut file = tokio::fs::File::open(&file_path).await.map_err(|e| {
-            tonic::Status::internal(format!(
-                "[transfer_chunk] Failed to open file '{}': {}",
-                file_path, e
-            ))
-        })?;
-
-        let mut buffer = Vec::new();
-        file.read_to_end(&mut buffer).await.map_err(|e| {
-            tonic::Status::internal(format!(
-                "[transfer_chunk] Failed to read file '{}': {}",
-                file_path, e
-            ))
-        })?;
-
-        // Step 2: Connect to the target chunkserver
-        let mut client = ChunkClient::connect(format!("http://{}", target_address))
-            .await
-            .map_err(|e| {
+            let mut file = tokio::fs::File::open(&file_path).await.map_err(|e| {
                 tonic::Status::internal(format!(
-                    "[transfer_chunk] Failed to connect to target chunkserver '{}': {}",
-                    target_address, e
+                    "[transfer_chunk] Failed to open file '{}': {}",
+                    file_path, e
                 ))
             })?;
-
-        // Step 3: Send the chunk data as an UploadRequest
-        let mut stream = client
-            .upload(tonic::Request::new(tokio_stream::iter(vec![
-                UploadRequest {
-                    request: Some(chunk::upload_request::Request::Info(chunk::FileInfo {
-                        file_name: file_name_part.to_string(),
-                        chunk_id: chunk_id_part,
-                    })),
-                    otp: String::new(),
-                    is_internal: true,
-                },
-                UploadRequest {
-                    request: Some(chunk::upload_request::Request::Chunk(chunk::FileChunk {
-                        data: buffer.clone(),
-                    })),
-                    otp: String::new(),
-                    is_internal: true,
-                },
-            ])))
+            // The file is sent as stored, a compressed chunk is not decompressed on the way
+            let framed = async {
+                let framed = compression::is_framed(&mut file).await?;
+                file.seek(SeekFrom::Start(0)).await?;
+                Ok::<bool, std::io::Error>(framed)
+            }
             .await
             .map_err(|e| {
                 tonic::Status::internal(format!(
-                    "[transfer_chunk] Failed to send chunk to target chunkserver '{}': {}",
-                    target_address, e
+                    "[transfer_chunk] Failed to read file '{}': {}",
+                    file_path, e
                 ))
             })?;
 
-        // Handle response from target
-        let response = stream.get_mut();
-        info!(
-            "[transfer_chunk] File '{}' chunk '{}' successfully transferred from '{}' to '{}'. Response: {}",
-            file_name_part, chunk_id_part, self.addr, target_address, response.message
-        );
+            // Step 2: Connect to the target chunkserver
+            let channel = connect_channel(&target_address, &self.common_config.tls)
+                .await
+                .map_err(|e| {
+                    tonic::Status::internal(format!(
+                        "[transfer_chunk] Failed to connect to target chunkserver '{}': {}",
+                        target_address, e
+                    ))
+                })?;
+            let mut client = ChunkClient::new(channel);
 
-        Ok(tonic::Response::new(SendChunkResponse {
-            message: format!(
-                "Chunk '{}' successfully transferred to target '{}'",
-                file_name_part, target_address
-            ),
-        }))
+            // Step 3: Stream the chunk data in frames, so only a few frames are held in memory at once
+            let (tx, rx) = tokio::sync::mpsc::channel(4);
+            let chunk_id = target_chunk_name;
+            let proof = self.upload_proof(&chunk_id, epoch, true);
+            // The target verifies the copy against the checksum stored when the chunk was written
+            let stored_checksum = self.stored_checksum(&chunk_name).await;
+            let limiter = Arc::clone(&self.replication_limiter);
+            // The sender returns the length and checksum of the data it sent, None if reading failed
+            let sender = tokio::spawn(async move {
+                let upload_request = |request| UploadRequest {
+                    request,
+                    token: String::new(),
+                    is_internal: true,
+                    internal_proof: proof.clone(),
+                };
+                let info = chunk::upload_request::Request::Info(chunk::FileInfo {
+                    chunk_id,
+                    secondary_addresses: Vec::new(),
+                    epoch,
+                    checksum: stored_checksum,
+                    stored_form: true,
+                    replicas: Vec::new(),
+                    placement_proof: String::new(),
+                    primary: String::new(),
+                });
+                if tx.send(upload_request(Some(info))).await.is_err() {
+                    return None;
+                }
+
+                let mut frame = vec![0; TRANSFER_FRAME_SIZE];
+                let mut sent_bytes = 0;
+                let mut hasher = crc32fast::Hasher::new();
+                loop {
+                    let request = match file.read(&mut frame).await {
+                        Ok(0) => break, // EOF
+                        Ok(n) => {
+                            limiter.acquire(n as u64).await;
+                            sent_bytes += n as u64;
+                            hasher.update(&frame[..n]);
+                            Some(chunk::upload_request::Request::Chunk(chunk::FileChunk {
+                                data: frame[..n].to_vec(),
+                            }))
+                        }
+                        Err(e) => {
+                            // An empty request makes the target discard the partial chunk
+                            error!(
+                                "[transfer_chunk] Failed to read file '{}': {}",
+                                file_path, e
+                            );
+                            None
+                        }
+                    };
+                    let failed = request.is_none();
+                    if tx.send(upload_request(request)).await.is_err() || failed {
+                        return None;
+                    }
+                }
+                Some((sent_bytes, hasher.finalize()))
+            });
+
+            let response = client
+                .upload(request_with_id(ReceiverStream::new(rx), &request_id))
+                .await
+                .map_err(|e| {
+                    tonic::Status::internal(format!(
+                        "[transfer_chunk] Failed to send chunk to target chunkserver '{}': {}",
+                        target_address, e
+                    ))
+                })?
+                .into_inner();
+
+            // Only report success if the target wrote exactly the data that was sent
+            let sent = sender.await.ok().flatten().ok_or_else(|| {
+                Status::internal(format!(
+                    "[transfer_chunk] Failed to read chunk '{}' while sending it",
+                    chunk_name
+                ))
+            })?;
+            self.metrics
+                .bytes_read
+                .with_label_values(&["transfer_chunk"])
+                .inc_by(sent.0);
+            // The target reports the size and checksum of the data, not of a compressed file
+            let sent = if framed {
+                let data: std::io::Result<(u64, u32)> = async {
+                    let checksum = match stored_checksum {
+                        Some(checksum) => checksum,
+                        None => self.compute_checksum(&chunk_name, 0).await?,
+                    };
+                    Ok((self.data_size(&chunk_name).await?, checksum))
+                }
+                .await;
+                data.map_err(|e| {
+                    Status::internal(format!(
+                        "[transfer_chunk] Failed to read chunk '{}' after sending it: {}",
+                        chunk_name, e
+                    ))
+                })?
+            } else {
+                sent
+            };
+            if sent != (response.bytes_written, response.checksum) {
+                error!(
+                    "[transfer_chunk] Target '{}' wrote {} byte(s) with checksum {:08x} for chunk '{}', but {} byte(s) with checksum {:08x} were sent",
+                    target_address, response.bytes_written, response.checksum, chunk_name, sent.0, sent.1
+                );
+                return Err(Status::data_loss(format!(
+                    "[transfer_chunk] Copy of chunk '{}' on '{}' does not match the source",
+                    chunk_name, target_address
+                )));
+            }
+            info!(
+                "[transfer_chunk] Chunk '{}' successfully transferred from '{}' to '{}'. Response: {}",
+                chunk_name, self.addr, target_address, response.message
+            );
+
+            Ok(tonic::Response::new(SendChunkResponse {
+                message: format!(
+                    "Chunk '{}' successfully transferred to target '{}'",
+                    chunk_name, target_address
+                ),
+                bytes_written: response.bytes_written,
+                checksum: response.checksum,
+            }))
+        }
+        .await;
+        timer.finish(&result);
+        result
     }
 
     async fn upload(
         &self,
         request: Request<tonic::Streaming<UploadRequest>>,
     ) -> Result<Response<UploadResponse>, Status> {
-        info!("Upload request received.");
-
-        let mut stream = request.into_inner();
-        let mut file_name = String::new();
-        let mut file: Option<File> = None;
-
-        while let Some(req) = stream.message().await? {
-            match req.request {
-                Some(chunk::upload_request::Request::Info(info)) => {
-                    if !req.is_internal {
-                        // Validate OTP only once
-                        if let Err(e) = self.validate_otp(&req.otp).await {
-                            error!("OTP validation failed: {}", e);
-                            return Err(e);
+        let timer = self.metrics.requests.start("upload");
+        let result: Result<Response<UploadResponse>, Status> = async move {
+            // Held until the chunk is stored, also while it is forwarded to the secondaries
+            let _slot = self.acquire_write_slot().await?;
+            info!("Upload request received.");
+
+            let request_id = request_id(&request);
+            let mut stream = request.into_inner();
+            let mut chunk_id = String::new();
+            let mut temp_path: Option<String> = None;
+            let mut secondary_addresses = Vec::new();
+            let mut epoch = 0;
+            let mut expected_checksum = None;
+            // Forwarded to the secondaries as the frames arrive
+            let mut forwards = Vec::new();
+            let mut received_bytes = 0;
+            let mut hasher = crc32fast::Hasher::new();
+            let mut stored_form = false;
+            let mut stored_data = None; // CRC32 and size of the data in a chunk file sent as stored
+
+            // Write the incoming data to a temporary file so that an interrupted upload never leaves
+            // a partial chunk behind
+            let streamed: Result<(), Status> = async {
+                let mut file: Option<ChunkWriter> = None;
+                let chunk_size = self.common_config.chunk_size;
+                while let Some(req) = stream.message().await? {
+                    match req.request {
+                        Some(chunk::upload_request::Request::Info(info)) => {
+                            if file.is_some() {
+                                return Err(Status::invalid_argument("File info received twice"));
+                            }
+                            // Validate the session token, or the proof of internal transfers, only once
+                            let validated = if req.is_internal {
+                                let epoch = info.epoch.to_be_bytes();
+                                self.validate_internal_proof(
+                                    &req.internal_proof,
+                                    &upload_fields(&info.chunk_id, &epoch, info.stored_form),
+                                )
+                                .await
+                            } else {
+                                self.validate_write_token(&req.token).await
+                            };
+                            if let Err(e) = validated {
+                                error!("Upload authentication failed: {}", e);
+                                return Err(e);
+                            }
+                            self.validate_chunk_id(&info.chunk_id).await?;
+                            self.check_epoch(info.epoch).await?;
+                            epoch = info.epoch;
+                            expected_checksum = info.checksum;
+
+                            // Only client uploads are forwarded, so the pipeline stops at the secondaries
+                            if !req.is_internal {
+                                self.validate_placement(&info).await?;
+                                secondary_addresses = info.secondary_addresses;
+                            }
+                            chunk_id = info.chunk_id;
+                            stored_form = info.stored_form && req.is_internal;
+                            info!(chunk_id = %chunk_id, "Starting upload");
+                            // The temporary file is written to the chunk's data directory, so it
+                            // is moved into place without copying
+                            self.place_chunk(&chunk_id);
+                            self.check_free_space(&chunk_id, self.common_config.chunk_size)
+                                .await?;
+                            let path = self.temp_chunk_path(&chunk_id);
+                            debug!("Receiving chunk into: {}", path);
+
+                            let created = File::create(&path).await.map_err(|e| {
+                                Status::internal(format!(
+                                    "Failed to create file at '{}': {}",
+                                    path, e
+                                ))
+                            })?;
+                            // A chunk file copied as stored is not compressed again
+                            file = Some(if stored_form {
+                                ChunkWriter::stored(created)
+                            } else {
+                                ChunkWriter::new(created, self.compression())
+                            });
+                            temp_path = Some(path);
+                            for secondary_address in &secondary_addresses {
+                                let forward = self
+                                    .forward_chunk(
+                                        secondary_address,
+                                        &chunk_id,
+                                        expected_checksum,
+                                        epoch,
+                                        &request_id,
+                                    )
+                                    .await;
+                                forwards.push(forward);
+                            }
                         }
+                        Some(chunk::upload_request::Request::Chunk(chunk)) => {
+                            if let Some(f) = &mut file {
+                                received_bytes += chunk.data.len() as u64;
+                                // A chunk file sent as stored is checked by its data below
+                                if received_bytes > chunk_size && !stored_form {
+                                    return Err(Status::invalid_argument(format!(
+                                        "Chunk '{}' exceeds the chunk size of {} bytes",
+                                        chunk_id, chunk_size
+                                    )));
+                                }
+                                f.write(&chunk.data).await.map_err(|e| {
+                                    write_error_status(temp_path.as_deref().unwrap_or_default(), e)
+                                })?;
+                                hasher.update(&chunk.data);
+                                futures::future::join_all(
+                                    forwards.iter_mut().map(|forward| forward.send(&chunk.data)),
+                                )
+                                .await;
+                            } else {
+                                return Err(Status::invalid_argument("File info not received yet"));
+                            }
+                        }
+                        None => return Err(Status::invalid_argument("Empty request")),
+                    }
+                }
+
+                let path = temp_path.as_deref().unwrap_or_default();
+                let file = file
+                    .ok_or_else(|| Status::invalid_argument("File info not received"))?
+                    .finish()
+                    .await
+                    .map_err(|e| write_error_status(path, e))?;
+                self.sync_chunk_file(&file)
+                    .await
+                    .map_err(|e| write_error_status(path, e))?;
+
+                // The checksum covers the data, which differs from the bytes sent if the chunk
+                // file is compressed
+                if stored_form {
+                    let (checksum, size) = checksum_file(path, 0).await.map_err(|e| {
+                        Status::data_loss(format!("Invalid chunk file '{}' received: {}", path, e))
+                    })?;
+                    if size > chunk_size {
+                        return Err(Status::invalid_argument(format!(
+                            "Chunk '{}' exceeds the chunk size of {} bytes",
+                            chunk_id, chunk_size
+                        )));
                     }
+                    stored_data = Some((checksum, size));
+                }
+                Ok(())
+            }
+            .await;
 
-                    file_name = info.file_name.clone();
-                    info!("Starting upload for file: {}", file_name);
-                    let chunk_id = info.chunk_id;
-                    let file_path = format!(
-                        "{}/{}/{}_chunk_{}",
-                        self.addr_sanitized, self.config.data_path, file_name, chunk_id
-                    );
-                    info!("Saving file to: {}", file_path);
-
-                    file = Some(tokio::fs::File::create(&file_path).await.map_err(|e| {
-                        Status::internal(format!("Failed to create file at '{}': {}", file_path, e))
-                    })?);
-
-                    // update metadata of chunkserver
-                    let chunk_name = format!("{}_chunk_{}", file_name, chunk_id);
-                    let mut server_chunks_guard = self.server_chunks.lock().await;
-                    server_chunks_guard.insert(chunk_name);
+            // Reject data corrupted on the way from the sender
+            let (checksum, received_bytes) =
+                stored_data.unwrap_or((hasher.finalize(), received_bytes));
+            let streamed = match (streamed, expected_checksum) {
+                (Ok(()), Some(expected)) if expected != checksum => {
+                    Err(Status::data_loss(format!(
+                        "Chunk '{}' has checksum {:08x}, but {:08x} was sent",
+                        chunk_id, checksum, expected
+                    )))
                 }
-                Some(chunk::upload_request::Request::Chunk(chunk)) => {
-                    if let Some(f) = &mut file {
-                        f.write_all(&chunk.data).await.map_err(|e| {
-                            Status::internal(format!("Failed to write to file: {}", e))
+                (streamed, _) => streamed,
+            };
+
+            // Move the complete chunk into place, only then is it served and reported to the master
+            let file_path = self.chunk_path(&chunk_id);
+            let committed = match (&streamed, &temp_path) {
+                (Ok(()), Some(path)) => {
+                    async {
+                        self.store_checksum(&chunk_id, checksum).await?;
+                        tokio::fs::rename(path, &file_path).await.map_err(|e| {
+                            Status::internal(format!(
+                                "Failed to move '{}' to '{}': {}",
+                                path, file_path, e
+                            ))
                         })?;
-                    } else {
-                        return Err(Status::invalid_argument("File info not received yet"));
+                        self.framed_sizes.lock().unwrap().remove(&chunk_id);
+                        let data_dir = self.chunk_dir(&chunk_id);
+                        self.sync_data_dir(&data_dir)
+                            .await
+                            .map_err(|e| write_error_status(&data_dir, e))
+                    }
+                    .await
+                }
+                _ => streamed,
+            };
+            if let Err(e) = committed {
+                if let Some(path) = &temp_path {
+                    if let Err(e) = tokio::fs::remove_file(path).await {
+                        warn!("Failed to remove temporary chunk '{}': {}", path, e);
                     }
                 }
-                None => return Err(Status::invalid_argument("Empty request")),
+                if !chunk_id.is_empty() {
+                    self.unplace_chunk(&chunk_id);
+                }
+                error!(chunk_id = %chunk_id, error = %e, "Upload failed");
+                return Err(e);
             }
+            info!(chunk_id = %chunk_id, path = %file_path, "Saved chunk");
+            self.metrics
+                .bytes_written
+                .with_label_values(&["upload"])
+                .inc_by(received_bytes);
+
+            // update metadata of chunkserver
+            self.server_chunks.lock().await.insert(chunk_id.clone());
+            debug!("Chunk '{}' uploaded successfully.", chunk_id);
+
+            // Let the secondaries store the chunk and wait for their acknowledgements, which must
+            // match the data stored here
+            let forwarded = futures::future::join_all(forwards.into_iter().map(ChunkForward::finish));
+            let mut failed_secondaries = Vec::new();
+            for (secondary_address, result) in secondary_addresses.iter().zip(forwarded.await) {
+                match result {
+                    Ok(response) if (response.bytes_written, response.checksum) == (received_bytes, checksum) => debug!(
+                        "Chunk '{}' forwarded to secondary '{}'",
+                        chunk_id, secondary_address
+                    ),
+                    Ok(response) => {
+                        error!(
+                            "Secondary '{}' stored {} byte(s) with checksum {:08x} for chunk '{}', but {} byte(s) with checksum {:08x} were received",
+                            secondary_address, response.bytes_written, response.checksum, chunk_id, received_bytes, checksum
+                        );
+                        failed_secondaries.push(secondary_address.clone());
+                    }
+                    Err(e) => {
+                        error!(
+                            "Failed to forward chunk '{}' to secondary '{}': {}",
+                            chunk_id, secondary_address, e
+                        );
+                        failed_secondaries.push(secondary_address.clone());
+                    }
+                }
+            }
+
+            Ok(Response::new(UploadResponse {
+                message: format!("Chunk '{}' uploaded successfully.", chunk_id),
+                failed_secondaries,
+                bytes_written: received_bytes,
+                checksum,
+            }))
         }
-        debug!("File '{}' uploaded successfully.", file_name);
-        Ok(Response::new(UploadResponse {
-            message: format!("File '{}' uploaded successfully.", file_name),
-        }))
+        .await;
+        timer.finish(&result);
+        result
     }
 
     /// Read the file chunk
-    async fn read(&self, request: Request<ReadRequest>) -> Result<Response<ReadResponse>, Status> {
-        let req = request.into_inner();
-        self.validate_otp(&req.otp).await?;
+    type ReadStream = ReceiverStream<Result<ReadResponse, Status>>;
 
-        let file_name = req.file_name;
-        let chunk_id = req.chunk_id;
-        let file_path = format!(
-            "{}/{}/{}_chunk_{}",
-            self.addr_sanitized, self.config.data_path, file_name, chunk_id
-        );
-        info!("Fetching file: {}", file_path);
+    /// Stream a chunk, or the given range of it, in frames of at most `TRANSFER_FRAME_SIZE`.
+    ///
+    /// The frames are read through a buffer taken from `read_buffers` for the whole read, so
+    /// reads beyond the configured number wait for a running one to finish instead of holding
+    /// further chunks in memory.
+    async fn read(
+        &self,
+        request: Request<ReadRequest>,
+    ) -> Result<Response<Self::ReadStream>, Status> {
+        let timer = self.metrics.requests.start("read");
+        let result: Result<Response<Self::ReadStream>, Status> = async move {
+            let req = request.into_inner();
+            self.validate_token(&req.token).await?;
+            self.validate_chunk_id(&req.chunk_id).await?;
 
-        // Read up to the chunk size from the file
-        let mut buffer = vec![0; self.common_config.chunk_size as usize];
-        let mut file = tokio::fs::File::open(&file_path)
-            .await
-            .map_err(|e| Status::internal(format!("Failed to open file '{}': {}", file_path, e)))?;
-        let bytes_read = file.read(&mut buffer).await.map_err(|e| {
-            Status::internal(format!("Failed to read chunk file '{}': {}", file_path, e))
-        })?;
-        buffer.truncate(bytes_read);
-
-        let content = String::from_utf8(buffer).map_err(|e| {
-            Status::internal(format!(
-                "Invalid UTF-8 content in file '{}': {}",
-                file_path, e
-            ))
-        })?;
-        debug!("Content is: {}", content);
-        Ok(Response::new(ReadResponse { content }))
+            let file_path = self.chunk_path(&req.chunk_id);
+            info!(chunk_id = %req.chunk_id, path = %file_path, "Fetching chunk");
+
+            // Read the requested range, clamped at the chunk size and the end of the file
+            let file = tokio::fs::File::open(&file_path).await.map_err(|e| {
+                if e.kind() == std::io::ErrorKind::NotFound {
+                    Status::not_found(format!("Chunk '{}' not found", req.chunk_id))
+                } else {
+                    Status::internal(format!("Failed to open file '{}': {}", file_path, e))
+                }
+            })?;
+            // Compressed chunks are decompressed a frame at a time
+            let mut reader = ChunkReader::new(file).await.map_err(|e| {
+                Status::internal(format!("Failed to read file '{}': {}", file_path, e))
+            })?;
+            let offset = req.offset.unwrap_or(0);
+            if offset > 0 {
+                reader.seek(offset).await.map_err(|e| {
+                    Status::internal(format!(
+                        "Failed to seek to {} in chunk file '{}': {}",
+                        offset, file_path, e
+                    ))
+                })?;
+            }
+            let mut remaining = req
+                .length
+                .unwrap_or(u64::MAX)
+                .min(self.common_config.chunk_size);
+
+            let mut buffer = self.read_buffers.take().await;
+            let streaming_reads = self.metrics.streaming_reads.clone();
+            let bytes_read = self.metrics.bytes_read.with_label_values(&["read"]);
+            streaming_reads.inc();
+            // A single frame waits to be sent, the next one is read once it is taken
+            let (tx, rx) = tokio::sync::mpsc::channel(1);
+            tokio::spawn(async move {
+                let mut sent = 0;
+                loop {
+                    let limit = remaining.min(buffer.len() as u64) as usize;
+                    let frame = match reader.read(&mut buffer[..limit]).await {
+                        Ok(0) => break, // End of the range
+                        Ok(n) => {
+                            sent += n;
+                            remaining -= n as u64;
+                            bytes_read.inc_by(n as u64);
+                            Ok(ReadResponse {
+                                content: buffer[..n].to_vec(),
+                                bytes_read: n as u64,
+                            })
+                        }
+                        Err(e) => Err(Status::internal(format!(
+                            "Failed to read chunk file '{}': {}",
+                            file_path, e
+                        ))),
+                    };
+                    let failed = frame.is_err();
+                    // Stop once the client is gone
+                    if tx.send(frame).await.is_err() || failed {
+                        break;
+                    }
+                }
+                debug!("Read {} byte(s) at offset {}", sent, offset);
+                drop(buffer);
+                streaming_reads.dec();
+            });
+            Ok(Response::new(ReceiverStream::new(rx)))
+        }
+        .await;
+        timer.finish(&result);
+        result
     }
 
-    async fn delete(
+    /// Compute the checksum and length of a chunk, so that replicas can be compared without
+    /// reading them
+    async fn checksum_chunk(
         &self,
-        request: Request<DeleteRequest>,
-    ) -> Result<Response<DeleteResponse>, Status> {
-        let req = request.into_inner();
-        self.validate_otp(&req.otp).await?;
+        request: Request<ChecksumChunkRequest>,
+    ) -> Result<Response<ChecksumChunkResponse>, Status> {
+        let timer = self.metrics.requests.start("checksum_chunk");
+        let result: Result<Response<ChecksumChunkResponse>, Status> = async move {
+            let req = request.into_inner();
+            self.validate_token(&req.token).await?;
+            self.validate_chunk_id(&req.chunk_id).await?;
 
-        let file_name = req.file_name;
-        let chunk_id = req.chunk_id;
+            let file_path = self.chunk_path(&req.chunk_id);
+            // Appends update the data and the stored checksum, so hold them off meanwhile
+            let lock = self.chunk_lock(&req.chunk_id).await;
+            let computed: std::io::Result<(u32, u64, Option<u32>)> = {
+                let _guard = lock.lock().await;
+                async {
+                    let checksum = self.compute_checksum(&req.chunk_id, 0).await?;
+                    let length = self.data_size(&req.chunk_id).await?;
+                    Ok((checksum, length, self.stored_checksum(&req.chunk_id).await))
+                }
+                .await
+            };
+            self.release_chunk_lock(&req.chunk_id, lock).await;
+            let (checksum, length, stored_checksum) = computed.map_err(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => Status::not_found(format!(
+                    "Chunk '{}' is not stored on {}",
+                    req.chunk_id, self.addr
+                )),
+                _ => Status::internal(format!("Failed to read chunk file '{}': {}", file_path, e)),
+            })?;
 
-        // Include chunk_id in the file path (if chunks are stored separately by ID)
-        let file_path = format!(
-            "{}/{}/{}_chunk_{}",
-            self.addr_sanitized, self.config.data_path, file_name, chunk_id
-        );
-        info!("Deleting chunk file: {}", file_path);
-
-        fs::remove_file(&file_path).map_err(|e| {
-            Status::internal(format!("Failed to delete file '{}': {}", file_path, e))
-        })?;
-
-        // Remove file chunk from metadata
-        let chunk_to_remove = format!("{}_chunk_{}", file_name, chunk_id);
-        let mut server_chunks_guard = self.server_chunks.lock().await;
-        if server_chunks_guard.remove(&chunk_to_remove) {
-            info!("Removed chunk: {}", chunk_to_remove);
-        } else {
-            warn!("chunk not found: {}", chunk_to_remove);
+            Ok(Response::new(ChecksumChunkResponse {
+                checksum,
+                length,
+                stored_checksum,
+            }))
         }
-
-        Ok(Response::new(DeleteResponse {
-            message: format!(
-                "Chunk '{}' of file '{}' deleted successfully.",
-                chunk_id, file_name
-            ),
-        }))
+        .await;
+        timer.finish(&result);
+        result
     }
 
-    /// TODO: currently assuming the last chunk fits the appended content
+    /// Sent to the primary of the chunk, which picks the offset and applies the append to the
+    /// secondaries at the same offset. Rejected unless this server holds the lease on the chunk,
+    /// and with `InvalidArgument` if the data would grow the chunk past chunk_size, leaving it
+    /// unchanged.
     async fn append(
         &self,
         request: Request<AppendRequest>,
     ) -> Result<Response<AppendResponse>, Status> {
-        let req = request.into_inner();
-        self.validate_otp(&req.otp).await?;
+        let timer = self.metrics.requests.start("append");
+        let result: Result<Response<AppendResponse>, Status> = async move {
+            let request_id = request_id(&request);
+            let req = request.into_inner();
+            self.validate_write_token(&req.token).await?;
+            self.validate_chunk_id(&req.chunk_id).await?;
+            self.check_epoch(req.epoch).await?;
+
+            // Appends forwarded to the secondaries don't take a slot there, as a rejected one
+            // would leave the replica behind the primary
+            let slot = self.acquire_write_slot().await?;
+
+            let chunk_id = req.chunk_id;
+            let length = req.data.len() as u64;
 
-        let file_name = req.file_name;
-        let chunk_id = req.chunk_id;
-        let data = req.data;
+            info!(chunk_id = %chunk_id, bytes = length, "Appending data");
+            self.check_free_space(&chunk_id, length).await?;
 
-        let file_path = format!(
-            "{}/{}/{}_chunk_{}",
-            self.addr_sanitized, self.config.data_path, file_name, chunk_id
+            // Appends to the same chunk are applied one at a time, on the secondaries as well.
+            // A task of its own applies the append, so that a client giving up on the request,
+            // e.g. after a timeout, cannot interrupt it before it reached the secondaries and the
+            // journal, which a retry of it is answered from.
+            let service = self.clone();
+            let append_chunk_id = chunk_id.clone();
+            let appended = tokio::spawn(async move {
+                let _slot = slot;
+                let chunk_id = append_chunk_id;
+                let lock = service.chunk_lock(&chunk_id).await;
+                let appended = {
+                    let _guard = lock.lock().await;
+                    service
+                        .append_as_primary(
+                            &chunk_id,
+                            req.data.as_bytes(),
+                            &req.append_id,
+                            &req.secondary_addresses,
+                            req.epoch,
+                            &request_id,
+                        )
+                        .await
+                };
+                service.release_chunk_lock(&chunk_id, lock).await;
+                appended
+            })
+            .await
+            .map_err(|e| {
+                Status::internal(format!("Append to chunk '{}' failed: {}", chunk_id, e))
+            })?;
+            let (offset, failed_secondaries, applied) = appended?;
+            if applied {
+                self.metrics
+                    .bytes_written
+                    .with_label_values(&["append"])
+                    .inc_by(length);
+            } else {
+                self.metrics.duplicate_appends.inc();
+            }
+
+            Ok(Response::new(AppendResponse {
+                message: format!("Data appended to chunk '{}' at offset {}", chunk_id, offset),
+                offset,
+                failed_secondaries,
+            }))
+        }
+        .await;
+        timer.finish(&result);
+        result
+    }
+
+    /// Make this server the primary of a chunk until the lease runs out. A lease of 0 seconds
+    /// revokes it, once the append or write to the chunk in progress, if any, is done.
+    async fn grant_lease(
+        &self,
+        request: Request<GrantLeaseRequest>,
+    ) -> Result<Response<GrantLeaseResponse>, Status> {
+        let req = request.into_inner();
+        self.validate_internal_proof(
+            &req.internal_proof,
+            &grant_lease_fields(
+                &req.chunk_id,
+                &req.duration.to_be_bytes(),
+                &req.epoch.to_be_bytes(),
+                &req.replicas,
+            ),
+        )
+        .await?;
+        self.validate_chunk_id(&req.chunk_id).await?;
+        self.advance_epoch(req.epoch).await?;
+        if req.duration == 0 {
+            let lock = self.chunk_lock(&req.chunk_id).await;
+            {
+                let _guard = lock.lock().await;
+                self.leases.lock().await.remove(&req.chunk_id);
+            }
+            self.release_chunk_lock(&req.chunk_id, lock).await;
+            info!("Revoked the lease on chunk '{}'", req.chunk_id);
+            return Ok(Response::new(GrantLeaseResponse {}));
+        }
+        if !self.server_chunks.lock().await.contains(&req.chunk_id) {
+            return Err(Status::not_found(format!(
+                "Chunk '{}' is not stored on {}",
+                req.chunk_id, self.addr
+            )));
+        }
+
+        let expiry = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + req.duration;
+        let lease = Lease {
+            expiry,
+            epoch: req.epoch,
+            replicas: req.replicas,
+        };
+        self.leases.lock().await.insert(req.chunk_id.clone(), lease);
+        info!(
+            "Holding lease on chunk '{}' for {} second(s)",
+            req.chunk_id, req.duration
         );
-        info!("Appending data '{}' to file: {}", data, file_path);
+        Ok(Response::new(GrantLeaseResponse {}))
+    }
+
+    /// Apply an append forwarded by the primary at the offset it picked
+    async fn apply_append(
+        &self,
+        request: Request<ApplyAppendRequest>,
+    ) -> Result<Response<ApplyAppendResponse>, Status> {
+        let timer = self.metrics.requests.start("apply_append");
+        let result: Result<Response<ApplyAppendResponse>, Status> = async move {
+            let req = request.into_inner();
+            self.validate_internal_proof(
+                &req.internal_proof,
+                &apply_append_fields(
+                    &req.chunk_id,
+                    &req.data,
+                    &req.offset.to_be_bytes(),
+                    &req.epoch.to_be_bytes(),
+                    &req.append_id,
+                ),
+            )
+            .await?;
+            self.validate_chunk_id(&req.chunk_id).await?;
+            self.check_epoch(req.epoch).await?;
+            self.check_free_space(&req.chunk_id, req.data.len() as u64)
+                .await?;
 
-        let mut file = OpenOptions::new()
-            .write(true)
-            .append(true)
-            .open(&file_path)
+            debug!(
+                "Applying append of {} byte(s) at offset {} to chunk '{}'",
+                req.data.len(),
+                req.offset,
+                req.chunk_id
+            );
+            // Applied by a task of its own like in `append`, so that the primary giving up on
+            // the request cannot interrupt it before it reached the journal
+            let service = self.clone();
+            let chunk_id = req.chunk_id.clone();
+            let length = req.data.len() as u64;
+            let applied = tokio::spawn(async move {
+                let lock = service.chunk_lock(&req.chunk_id).await;
+                let applied = {
+                    let _guard = lock.lock().await;
+                    service
+                        .append_as_secondary(&req.chunk_id, &req.data, req.offset, &req.append_id)
+                        .await
+                };
+                service.release_chunk_lock(&req.chunk_id, lock).await;
+                applied
+            })
             .await
-            .map_err(|e| Status::internal(format!("Failed to open file '{}': {}", file_path, e)))?;
+            .map_err(|e| {
+                Status::internal(format!("Append to chunk '{}' failed: {}", chunk_id, e))
+            })?;
+            if applied? {
+                self.metrics
+                    .bytes_written
+                    .with_label_values(&["apply_append"])
+                    .inc_by(length);
+            } else {
+                self.metrics.duplicate_appends.inc();
+            }
+
+            Ok(Response::new(ApplyAppendResponse {}))
+        }
+        .await;
+        timer.finish(&result);
+        result
+    }
 
-        // Write data to the file
-        file.write_all(data.as_bytes()).await.map_err(|e| {
-            Status::internal(format!("Failed to write to file '{}': {}", file_path, e))
-        })?;
+    /// Overwrite part of a chunk this server holds the lease on and apply the write to the
+    /// secondaries, like `append`
+    async fn write_at(
+        &self,
+        request: Request<WriteAtRequest>,
+    ) -> Result<Response<WriteAtResponse>, Status> {
+        let timer = self.metrics.requests.start("write_at");
+        let result: Result<Response<WriteAtResponse>, Status> = async move {
+            let request_id = request_id(&request);
+            let req = request.into_inner();
+            self.validate_write_token(&req.token).await?;
+            self.validate_chunk_id(&req.chunk_id).await?;
+            self.check_epoch(req.epoch).await?;
+            let slot = self.acquire_write_slot().await?;
+
+            let chunk_id = req.chunk_id;
+            info!(
+                chunk_id = %chunk_id,
+                file_name = %req.file_name,
+                offset = req.offset,
+                bytes = req.data.len(),
+                "Writing data"
+            );
+            let length = req.data.len() as u64;
+            let offset = req.offset;
+            self.check_free_space(&chunk_id, length).await?;
 
-        file.sync_all()
+            // Writes are applied one at a time along with the appends to the chunk, by a task
+            // of its own like in `append`, so that a client giving up on the request cannot
+            // interrupt it between the replicas
+            let service = self.clone();
+            let write_chunk_id = chunk_id.clone();
+            let written = tokio::spawn(async move {
+                let _slot = slot;
+                let chunk_id = write_chunk_id;
+                let lock = service.chunk_lock(&chunk_id).await;
+                let written = {
+                    let _guard = lock.lock().await;
+                    service
+                        .write_as_primary(
+                            &chunk_id,
+                            req.offset,
+                            &req.data,
+                            req.allow_extend,
+                            &req.secondary_addresses,
+                            req.epoch,
+                            &request_id,
+                        )
+                        .await
+                };
+                service.release_chunk_lock(&chunk_id, lock).await;
+                written
+            })
             .await
-            .map_err(|e| Status::internal(format!("Failed to sync file '{}': {}", file_path, e)))?;
+            .map_err(|e| {
+                Status::internal(format!("Write to chunk '{}' failed: {}", chunk_id, e))
+            })?;
+            let (size, failed_secondaries) = written?;
+            self.metrics
+                .bytes_written
+                .with_label_values(&["write_at"])
+                .inc_by(length);
 
-        Ok(Response::new(AppendResponse {
-            message: format!(
-                "Data appended to chunk {} of file '{}'",
-                chunk_id, file_name
-            ),
-        }))
+            Ok(Response::new(WriteAtResponse {
+                message: format!(
+                    "Wrote {} byte(s) to chunk '{}' at offset {}",
+                    length, chunk_id, offset
+                ),
+                size,
+                failed_secondaries,
+            }))
+        }
+        .await;
+        timer.finish(&result);
+        result
+    }
+
+    /// Apply a write forwarded by the primary to this replica
+    async fn apply_write_at(
+        &self,
+        request: Request<ApplyWriteAtRequest>,
+    ) -> Result<Response<ApplyWriteAtResponse>, Status> {
+        let timer = self.metrics.requests.start("apply_write_at");
+        let result: Result<Response<ApplyWriteAtResponse>, Status> = async move {
+            let req = request.into_inner();
+            self.validate_internal_proof(
+                &req.internal_proof,
+                &apply_write_at_fields(
+                    &req.chunk_id,
+                    &req.data,
+                    &req.offset.to_be_bytes(),
+                    &req.size.to_be_bytes(),
+                    &req.epoch.to_be_bytes(),
+                ),
+            )
+            .await?;
+            self.validate_chunk_id(&req.chunk_id).await?;
+            self.check_epoch(req.epoch).await?;
+            self.check_free_space(&req.chunk_id, req.data.len() as u64)
+                .await?;
+
+            debug!(
+                "Applying write of {} byte(s) at offset {} to chunk '{}'",
+                req.data.len(),
+                req.offset,
+                req.chunk_id
+            );
+            // Applied by a task of its own like in `apply_append`
+            let service = self.clone();
+            let chunk_id = req.chunk_id.clone();
+            let length = req.data.len() as u64;
+            let applied = tokio::spawn(async move {
+                let lock = service.chunk_lock(&req.chunk_id).await;
+                let applied = {
+                    let _guard = lock.lock().await;
+                    service
+                        .write_to_chunk(&req.chunk_id, req.offset, &req.data, true, Some(req.size))
+                        .await
+                };
+                service.release_chunk_lock(&req.chunk_id, lock).await;
+                applied
+            })
+            .await
+            .map_err(|e| {
+                Status::internal(format!("Write to chunk '{}' failed: {}", chunk_id, e))
+            })?;
+            applied?;
+            self.metrics
+                .bytes_written
+                .with_label_values(&["apply_write_at"])
+                .inc_by(length);
+
+            Ok(Response::new(ApplyWriteAtResponse {}))
+        }
+        .await;
+        timer.finish(&result);
+        result
+    }
+
+    /// Cut a chunk to the length its file is truncated to, on request of the master
+    async fn truncate_chunk(
+        &self,
+        request: Request<TruncateChunkRequest>,
+    ) -> Result<Response<TruncateChunkResponse>, Status> {
+        let timer = self.metrics.requests.start("truncate_chunk");
+        let result: Result<Response<TruncateChunkResponse>, Status> = async move {
+            let req = request.into_inner();
+            self.validate_internal_proof(
+                &req.internal_proof,
+                &truncate_chunk_fields(
+                    &req.chunk_id,
+                    &req.length.to_be_bytes(),
+                    &req.epoch.to_be_bytes(),
+                ),
+            )
+            .await?;
+            self.validate_chunk_id(&req.chunk_id).await?;
+            self.advance_epoch(req.epoch).await?;
+
+            let lock = self.chunk_lock(&req.chunk_id).await;
+            let truncated = {
+                let _guard = lock.lock().await;
+                self.truncate_chunk(&req.chunk_id, req.length).await
+            };
+            self.release_chunk_lock(&req.chunk_id, lock).await;
+            truncated?;
+            info!(
+                "Truncated chunk '{}' to {} byte(s)",
+                req.chunk_id, req.length
+            );
+
+            Ok(Response::new(TruncateChunkResponse {}))
+        }
+        .await;
+        timer.finish(&result);
+        result
+    }
+
+    /// Copy a chunk to a new chunk ID on this server, on request of the master before a chunk
+    /// shared by several files is changed
+    async fn clone_chunk(
+        &self,
+        request: Request<CloneChunkRequest>,
+    ) -> Result<Response<CloneChunkResponse>, Status> {
+        let timer = self.metrics.requests.start("clone_chunk");
+        let result: Result<Response<CloneChunkResponse>, Status> = async move {
+            let req = request.into_inner();
+            self.validate_internal_proof(
+                &req.internal_proof,
+                &clone_chunk_fields(&req.chunk_id, &req.new_chunk_id, &req.epoch.to_be_bytes()),
+            )
+            .await?;
+            self.validate_chunk_id(&req.chunk_id).await?;
+            self.validate_chunk_id(&req.new_chunk_id).await?;
+            self.advance_epoch(req.epoch).await?;
+
+            // Appends and writes to the chunk are done before it is copied
+            let lock = self.chunk_lock(&req.chunk_id).await;
+            let cloned = {
+                let _guard = lock.lock().await;
+                self.clone_chunk(&req.chunk_id, &req.new_chunk_id).await
+            };
+            self.release_chunk_lock(&req.chunk_id, lock).await;
+            let size = cloned?;
+            self.metrics
+                .bytes_written
+                .with_label_values(&["clone_chunk"])
+                .inc_by(size);
+            info!(
+                "Copied chunk '{}' to '{}' ({} byte(s))",
+                req.chunk_id, req.new_chunk_id, size
+            );
+
+            Ok(Response::new(CloneChunkResponse {}))
+        }
+        .await;
+        timer.finish(&result);
+        result
     }
 }
@@ -1,23 +1,194 @@
+use prometheus::IntGauge;
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
+use std::io::SeekFrom;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
-use tokio::sync::Mutex;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::{Mutex, OwnedSemaphorePermit, Semaphore};
 use tokio::time::Duration;
+use tonic::metadata::MetadataValue;
 use tonic::Status;
-use tracing::{error, info};
+use tracing::{debug, error, info, warn};
+use uuid::Uuid;
 
+use crate::buffer_pool::BufferPool;
+use crate::cluster_auth::{sign_request, verify_placement, verify_request};
+use crate::compression::{self, ChunkReader, ChunkWriter, Compression, FRAMED_MAGIC};
 use crate::config::{ChunkServerConfig, CommonConfig};
-use crate::proto::master::{master_client::MasterClient, HeartbeatRequest};
-use crate::util::connect_to_master;
+use crate::error::{Error, Result};
+use crate::master_service::{
+    heartbeat_digest, heartbeat_fields, register_fields, report_corrupt_chunk_fields,
+    unregister_fields,
+};
+use crate::metrics::ChunkServerMetrics;
+use crate::proto::chunk;
+use crate::proto::chunk::chunk_client::ChunkClient;
+use crate::proto::chunk::{
+    ApplyAppendRequest, ApplyWriteAtRequest, FileInfo, UploadRequest, UploadResponse,
+};
+use crate::proto::master::{
+    master_client::MasterClient, HeartbeatRequest, RegisterRequest, ReportCorruptChunkRequest,
+    UnregisterRequest,
+};
+use crate::request_id::request_with_id;
+use crate::retry::RetryPolicy;
+use crate::shutdown::BackgroundTasks;
+use crate::throttle::RateLimiter;
+use crate::token::{verify_token, TokenClaims};
+use crate::util::{connect_channel, connect_to_master, OVERLOADED_KEY};
+use append_journal::{AppendJournal, JournaledAppend};
+
+pub mod append_journal;
+
+/// Size of the frames a chunk is split into when it is transferred between chunkservers
+pub const TRANSFER_FRAME_SIZE: usize = 256 * 1024;
+
+/// Marks chunk files that are still being received, see `ChunkService::temp_chunk_path`
+pub const TEMP_CHUNK_MARKER: &str = ".tmp.";
+
+/// Suffix of the file next to a chunk storing its CRC32, see `ChunkService::checksum_path`
+pub const CHECKSUM_SUFFIX: &str = ".crc";
+
+/// Suffix of the file next to a chunk journaling its recent appends, see
+/// `ChunkService::append_journal_path`
+pub const APPEND_JOURNAL_SUFFIX: &str = ".appends";
+
+/// Longest append ID accepted, so that the journal of a chunk stays small
+pub const MAX_APPEND_ID_LEN: usize = 128;
+
+/// Longest wait between attempts to reach a leader master after heartbeats failed
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(10);
+
+/// When written chunks are flushed to disk, see `sync_writes` in config.toml
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncWrites {
+    Never,   // Left to the operating system
+    OnClose, // The data of each uploaded chunk before it is moved into place, and of each append
+    Always,  // Like OnClose, but all of the chunk's file and its checksum, then the data directory
+}
+
+impl FromStr for SyncWrites {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "never" => Ok(SyncWrites::Never),
+            "on_close" => Ok(SyncWrites::OnClose),
+            "always" => Ok(SyncWrites::Always),
+            _ => Err(Error::Config(format!(
+                "Invalid sync_writes '{}', expected \"never\", \"on_close\" or \"always\"",
+                value
+            ))),
+        }
+    }
+}
+
+/// Capacity of the disk holding a data directory, see `ChunkService::disk_usage`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DiskUsage {
+    pub device: Option<u64>, // Same for data directories on the same disk, None where unknown
+    pub total_bytes: u64,
+    pub available_bytes: u64,
+}
+
+/// Progress of the background scrubbing, see `ChunkService::start_scrubber`
+#[derive(Clone, Debug, Default)]
+pub struct ScrubStats {
+    pub last_scrub_time: u64, // When the last pass finished (seconds since UNIX epoch)
+    pub corrupt_chunks: u64,  // Corrupt chunks found since startup
+    pub unreported: Vec<String>, // Deleted corrupt chunks the master has not been told about yet
+}
+
+/// Lease making this server the primary of a chunk, see `ChunkService::check_lease`
+#[derive(Clone, Debug)]
+pub struct Lease {
+    pub expiry: u64,           // Seconds since UNIX epoch
+    pub epoch: u64,            // Leader epoch of the granting master, the lease ends with it
+    pub replicas: Vec<String>, // Replicas of the chunk when the lease was granted
+}
 
 #[derive(Clone, Debug, Default)]
 pub struct ChunkService {
-    pub addr: String,                               // Chunkserver address
-    pub addr_sanitized: String,                     // Sanitized address, used for file directories
+    pub addr: String,                                              // Chunkserver address
+    pub addr_sanitized: String, // Sanitized address, used for file directories
     pub server_chunks: Arc<Mutex<HashSet<String>>>, // Track metadata of all chunks stored
+    pub chunk_dirs: Arc<std::sync::Mutex<HashMap<String, usize>>>, // Chunk ID -> Index in `data_dirs` of the directory holding it
+    pub framed_sizes: Arc<std::sync::Mutex<HashMap<String, u64>>>, // Chunk ID -> Size of the data of a framed chunk appended to, see `framed_size`
     pub config: ChunkServerConfig,
     pub common_config: CommonConfig,
-    pub otp_store: Arc<Mutex<HashMap<String, u64>>>, // Store OTPs with expiration
+    pub new_leader: Arc<Mutex<Option<String>>>, // Leader announced by a master, not yet heartbeated
+    pub epoch: Arc<AtomicU64>,                  // Highest leader epoch seen, see `advance_epoch`
+    pub chunk_locks: Arc<Mutex<HashMap<String, Arc<Mutex<()>>>>>, // Chunk ID -> Lock held while appending to it
+    pub leases: Arc<Mutex<HashMap<String, Lease>>>, // Chunk ID -> Lease making this server its primary
+    pub scrub_stats: Arc<Mutex<ScrubStats>>,
+    pub metrics: ChunkServerMetrics,
+    pub tasks: Arc<BackgroundTasks>, // Heartbeats and scrubbing, stopped on shutdown
+    pub replication_limiter: Arc<RateLimiter>, // Shared by all chunks sent by `transfer_chunk` at once
+    pub read_buffers: Arc<BufferPool>, // Frames chunks are read into by `read`, one per read
+    pub write_slots: Option<Arc<Semaphore>>, // One permit per write handled at once, None without max_concurrent_writes
+}
+
+/// Slot of an upload or append being handled, see `ChunkService::acquire_write_slot`
+#[derive(Debug)]
+pub struct WriteSlot {
+    _permit: Option<OwnedSemaphorePermit>,
+    writes_in_progress: IntGauge,
+}
+
+impl Drop for WriteSlot {
+    fn drop(&mut self) {
+        self.writes_in_progress.dec();
+    }
+}
+
+/// Frames of a forwarded chunk buffered for each secondary, the primary waits for a slow
+/// secondary once they are taken
+const FORWARDED_FRAMES: usize = 4;
+
+/// Upload of a chunk a primary forwards to a secondary while receiving it from the client, so
+/// that only a few frames of it are held in memory, see `ChunkService::forward_chunk`.
+///
+/// Dropping it before `finish` makes the secondary discard the chunk.
+pub struct ChunkForward {
+    sender: Option<tokio::sync::mpsc::Sender<UploadRequest>>, // None once the secondary failed
+    proof: String,
+    completed: Arc<AtomicBool>,
+    response: tokio::task::JoinHandle<Result<UploadResponse>>,
+}
+
+impl ChunkForward {
+    /// Forward the next frame of the chunk, unless the secondary failed already
+    pub async fn send(&mut self, data: &[u8]) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+        let frame = UploadRequest {
+            request: Some(chunk::upload_request::Request::Chunk(chunk::FileChunk {
+                data: data.to_vec(),
+            })),
+            token: String::new(),
+            is_internal: true,
+            internal_proof: self.proof.clone(),
+        };
+        // The secondary stopped receiving, `finish` reports why
+        if sender.send(frame).await.is_err() {
+            self.sender = None;
+        }
+    }
+
+    /// End the upload once the primary stored the chunk, and wait for the secondary to store it
+    pub async fn finish(mut self) -> Result<UploadResponse> {
+        self.completed.store(true, Ordering::SeqCst);
+        self.sender = None;
+        (&mut self.response)
+            .await
+            .map_err(|e| Status::internal(format!("Forwarding the chunk failed: {}", e)))?
+    }
 }
 
 impl ChunkService {
@@ -28,28 +199,1493 @@ impl ChunkService {
         common_config: CommonConfig,
     ) -> Self {
         Self {
+            replication_limiter: Arc::new(RateLimiter::new(config.replication_bytes_per_sec)),
+            read_buffers: Arc::new(BufferPool::new(config.read_buffers, TRANSFER_FRAME_SIZE)),
+            write_slots: (config.max_concurrent_writes > 0)
+                .then(|| Arc::new(Semaphore::new(config.max_concurrent_writes))),
             server_chunks: Arc::new(Mutex::new(HashSet::new())),
+            chunk_dirs: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            framed_sizes: Arc::new(std::sync::Mutex::new(HashMap::new())),
             addr: addr.to_string(),
             addr_sanitized: addr_sanitized.to_string(),
             config,
             common_config,
-            otp_store: Arc::new(Mutex::new(HashMap::new())),
+            new_leader: Arc::new(Mutex::new(None)),
+            epoch: Arc::new(AtomicU64::new(0)),
+            chunk_locks: Arc::new(Mutex::new(HashMap::new())),
+            leases: Arc::new(Mutex::new(HashMap::new())),
+            scrub_stats: Arc::new(Mutex::new(ScrubStats::default())),
+            metrics: ChunkServerMetrics::new(),
+            tasks: Arc::new(BackgroundTasks::default()),
+        }
+    }
+
+    /// When written chunks are flushed to disk, checked by `Config::validate`
+    pub fn sync_writes(&self) -> SyncWrites {
+        self.config
+            .sync_writes
+            .parse()
+            .unwrap_or(SyncWrites::OnClose)
+    }
+
+    /// How new chunks are compressed on disk, checked by `Config::validate`
+    pub fn compression(&self) -> Compression {
+        self.config.compression.parse().unwrap_or(Compression::None)
+    }
+
+    /// Flush a chunk file written by an upload or append to disk, as far as `sync_writes` asks
+    pub async fn sync_chunk_file(&self, file: &File) -> std::io::Result<()> {
+        match self.sync_writes() {
+            SyncWrites::Never => return Ok(()),
+            SyncWrites::OnClose => file.sync_data().await?,
+            SyncWrites::Always => file.sync_all().await?,
+        }
+        self.metrics.syncs.with_label_values(&["chunk"]).inc();
+        Ok(())
+    }
+
+    /// With `sync_writes = "always"`, flush a data directory, so that chunk files created or
+    /// moved into place survive a power loss
+    pub async fn sync_data_dir(&self, dir: &str) -> std::io::Result<()> {
+        if self.sync_writes() == SyncWrites::Always {
+            File::open(dir).await?.sync_all().await?;
+            self.metrics.syncs.with_label_values(&["directory"]).inc();
+        }
+        Ok(())
+    }
+
+    /// Lock serializing the appends, writes and truncations of a chunk, so that each is applied
+    /// as a whole. Pass it to `release_chunk_lock` when done.
+    pub async fn chunk_lock(&self, chunk_id: &str) -> Arc<Mutex<()>> {
+        Arc::clone(
+            self.chunk_locks
+                .lock()
+                .await
+                .entry(chunk_id.to_string())
+                .or_default(),
+        )
+    }
+
+    /// Forget the lock of a chunk once no other request holds or waits for it
+    pub async fn release_chunk_lock(&self, chunk_id: &str, lock: Arc<Mutex<()>>) {
+        let mut chunk_locks = self.chunk_locks.lock().await;
+        drop(lock);
+        if chunk_locks
+            .get(chunk_id)
+            .is_some_and(|lock| Arc::strong_count(lock) == 1)
+        {
+            chunk_locks.remove(chunk_id);
+        }
+    }
+
+    /// Reject a write carrying an older leader epoch than the current one with
+    /// `FailedPrecondition`, as it comes from the chunk assignments of a demoted leader.
+    ///
+    /// Only compares the epoch: epochs sent by clients or forwarded from them are not trusted
+    /// to raise it, see `advance_epoch`.
+    pub async fn check_epoch(&self, epoch: u64) -> std::result::Result<(), Status> {
+        let current = self.epoch.load(Ordering::SeqCst);
+        if epoch < current {
+            warn!(
+                "Rejecting request of epoch {}, epoch {} is current",
+                epoch, current
+            );
+            return Err(Status::failed_precondition(format!(
+                "Leader epoch {} is outdated, the current epoch is {}",
+                epoch, current
+            )));
+        }
+        Ok(())
+    }
+
+    /// Raise the current leader epoch to that of a command of the master, rejecting a command of
+    /// an older epoch like `check_epoch`, as it comes from a demoted leader.
+    ///
+    /// Only called for commands authenticated as coming from a master, and for the responses
+    /// to heartbeats, as a raised epoch fences off all writes of older ones.
+    pub async fn advance_epoch(&self, epoch: u64) -> std::result::Result<(), Status> {
+        let highest = self.epoch.fetch_max(epoch, Ordering::SeqCst);
+        if epoch < highest {
+            warn!(
+                "Rejecting command of epoch {}, epoch {} is current",
+                epoch, highest
+            );
+            return Err(Status::failed_precondition(format!(
+                "Leader epoch {} is outdated, the current epoch is {}",
+                epoch, highest
+            )));
+        }
+        Ok(())
+    }
+
+    /// Reject acting as primary of a chunk with `FailedPrecondition` unless this server holds an
+    /// unexpired lease on it, granted by a leader of the current epoch. Secondaries the client
+    /// asks to apply the change to that are not replicas of the chunk according to the lease are
+    /// rejected with `PermissionDenied`, so a client cannot make this server send data elsewhere.
+    pub async fn check_lease(
+        &self,
+        chunk_id: &str,
+        secondary_addresses: &[String],
+    ) -> std::result::Result<(), Status> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let mut leases = self.leases.lock().await;
+        let epoch = self.epoch.load(Ordering::SeqCst);
+        leases.retain(|_, lease| lease.expiry > now && lease.epoch >= epoch);
+        let Some(lease) = leases.get(chunk_id) else {
+            return Err(Status::failed_precondition(format!(
+                "Chunkserver {} holds no lease on chunk '{}'",
+                self.addr, chunk_id
+            )));
+        };
+        if let Some(secondary) = secondary_addresses
+            .iter()
+            .find(|secondary| !lease.replicas.contains(secondary))
+        {
+            warn!(
+                "Refused to forward a change of chunk '{}' to '{}', which is not a replica",
+                chunk_id, secondary
+            );
+            return Err(Status::permission_denied(format!(
+                "'{}' is not a replica of chunk '{}'",
+                secondary, chunk_id
+            )));
+        }
+        Ok(())
+    }
+
+    /// Append `data` to a chunk this server holds the lease on, then apply it to the
+    /// secondaries, see `forward_append`.
+    ///
+    /// Returns the offset, the secondaries that did not acknowledge the append and whether it
+    /// was applied now. An append whose `append_id` is in the chunk's journal was applied
+    /// before, e.g. by a request whose response the client did not get, and is answered with
+    /// the result recorded then. The caller holds the lock of the chunk, see `chunk_lock`.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn append_as_primary(
+        &self,
+        chunk_id: &str,
+        data: &[u8],
+        append_id: &str,
+        secondary_addresses: &[String],
+        epoch: u64,
+        request_id: &str,
+    ) -> std::result::Result<(u64, Vec<String>, bool), Status> {
+        self.check_lease(chunk_id, secondary_addresses).await?;
+        if append_id.len() > MAX_APPEND_ID_LEN || append_id.contains(char::is_whitespace) {
+            return Err(Status::invalid_argument(format!(
+                "Invalid append ID '{}': at most {} characters without whitespace",
+                append_id, MAX_APPEND_ID_LEN
+            )));
+        }
+        if let Some(append) = self.journaled_append(chunk_id, append_id).await {
+            if append.length != data.len() as u64 {
+                return Err(Status::invalid_argument(format!(
+                    "Append ID '{}' was already used for an append of {} byte(s) to chunk '{}'",
+                    append_id, append.length, chunk_id
+                )));
+            }
+            info!(
+                "Append '{}' was already applied to chunk '{}' at offset {}, not applying it again",
+                append_id, chunk_id, append.offset
+            );
+            return Ok((append.offset, append.failed_secondaries, false));
+        }
+
+        let offset = self.append_to_chunk(chunk_id, data, None).await?;
+        let failed_secondaries = self
+            .forward_append(
+                secondary_addresses,
+                chunk_id,
+                data,
+                offset,
+                append_id,
+                epoch,
+                request_id,
+            )
+            .await;
+        self.journal_append(
+            chunk_id,
+            append_id,
+            offset,
+            data.len() as u64,
+            failed_secondaries.clone(),
+        )
+        .await;
+        Ok((offset, failed_secondaries, true))
+    }
+
+    /// Apply an append the primary wrote at `offset`, see `append_to_chunk`, unless the
+    /// chunk's journal shows that `append_id` was applied before, e.g. as the primary retried
+    /// forwarding it. Returns whether it was applied now. The caller holds the lock of the
+    /// chunk, see `chunk_lock`.
+    pub async fn append_as_secondary(
+        &self,
+        chunk_id: &str,
+        data: &[u8],
+        offset: u64,
+        append_id: &str,
+    ) -> std::result::Result<bool, Status> {
+        if let Some(append) = self.journaled_append(chunk_id, append_id).await {
+            debug!(
+                "Append '{}' was already applied to chunk '{}' at offset {}",
+                append_id, chunk_id, append.offset
+            );
+            return Ok(false);
+        }
+        self.append_to_chunk(chunk_id, data, Some(offset)).await?;
+        self.journal_append(chunk_id, append_id, offset, data.len() as u64, Vec::new())
+            .await;
+        Ok(true)
+    }
+
+    /// Write `data` at `offset` of a chunk this server holds the lease on, then apply the write
+    /// to the secondaries, see `forward_write`.
+    ///
+    /// Returns the size of the chunk after the write and the secondaries that did not
+    /// acknowledge it. The caller holds the lock of the chunk, see `chunk_lock`.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn write_as_primary(
+        &self,
+        chunk_id: &str,
+        offset: u64,
+        data: &[u8],
+        allow_extend: bool,
+        secondary_addresses: &[String],
+        epoch: u64,
+        request_id: &str,
+    ) -> std::result::Result<(u64, Vec<String>), Status> {
+        self.check_lease(chunk_id, secondary_addresses).await?;
+        let size = self
+            .write_to_chunk(chunk_id, offset, data, allow_extend, None)
+            .await?;
+        let failed_secondaries = self
+            .forward_write(
+                secondary_addresses,
+                chunk_id,
+                offset,
+                data,
+                size,
+                epoch,
+                request_id,
+            )
+            .await;
+        Ok((size.max(offset + data.len() as u64), failed_secondaries))
+    }
+
+    /// Path of the file storing the given chunk
+    pub fn chunk_path(&self, chunk_id: &str) -> String {
+        format!("{}/{}", self.chunk_dir(chunk_id), chunk_id)
+    }
+
+    /// Data directory holding the given chunk, the first one for chunks not stored here
+    pub fn chunk_dir(&self, chunk_id: &str) -> String {
+        let index = self
+            .chunk_dirs
+            .lock()
+            .unwrap()
+            .get(chunk_id)
+            .copied()
+            .unwrap_or(0);
+        self.data_dirs().swap_remove(index)
+    }
+
+    /// Pick the data directory of a chunk about to be written and return it. A chunk that is
+    /// already stored stays where it is, see `select_data_dir` for new ones.
+    pub fn place_chunk(&self, chunk_id: &str) -> String {
+        if !self.chunk_dirs.lock().unwrap().contains_key(chunk_id) {
+            let index = self.select_data_dir();
+            self.chunk_dirs
+                .lock()
+                .unwrap()
+                .entry(chunk_id.to_string())
+                .or_insert(index);
+        }
+        self.chunk_dir(chunk_id)
+    }
+
+    /// Forget the directory `place_chunk` picked for a chunk whose write failed, unless the chunk
+    /// was stored before
+    pub fn unplace_chunk(&self, chunk_id: &str) {
+        if !Path::new(&self.chunk_path(chunk_id)).exists() {
+            self.chunk_dirs.lock().unwrap().remove(chunk_id);
+        }
+    }
+
+    /// Index in `data_dirs` of the directory for a new chunk: the one with the most free space,
+    /// of equally free ones the one holding the fewest chunks. Directories on the same disk are
+    /// thus filled in turn.
+    fn select_data_dir(&self) -> usize {
+        let mut chunks = vec![0; self.config.data_path.len()];
+        for &index in self.chunk_dirs.lock().unwrap().values() {
+            if let Some(count) = chunks.get_mut(index) {
+                *count += 1;
+            }
+        }
+        self.disk_usage()
+            .into_iter()
+            .enumerate()
+            .max_by_key(|(index, (_, usage))| {
+                let available = usage.as_ref().map_or(0, |usage| usage.available_bytes);
+                (available, std::cmp::Reverse(chunks[*index]))
+            })
+            .map_or(0, |(index, _)| index)
+    }
+
+    /// Capacity of the disk holding each data directory. Directories on the same disk share
+    /// one reading, so they compare as equally free.
+    pub fn disk_usage(&self) -> Vec<(String, std::io::Result<DiskUsage>)> {
+        let mut disks: HashMap<u64, DiskUsage> = HashMap::new();
+        self.data_dirs()
+            .into_iter()
+            .map(|dir| {
+                let usage = device_of(&dir).and_then(|device| {
+                    if let Some(usage) = device.and_then(|device| disks.get(&device)) {
+                        return Ok(*usage);
+                    }
+                    let usage = DiskUsage {
+                        device,
+                        total_bytes: fs2::total_space(&dir)?,
+                        available_bytes: fs2::available_space(&dir)?,
+                    };
+                    if let Some(device) = device {
+                        disks.insert(device, usage);
+                    }
+                    Ok(usage)
+                });
+                (dir, usage)
+            })
+            .collect()
+    }
+
+    /// Path of the file storing the CRC32 of the given chunk
+    pub fn checksum_path(&self, chunk_id: &str) -> String {
+        format!("{}{}", self.chunk_path(chunk_id), CHECKSUM_SUFFIX)
+    }
+
+    /// CRC32 of a chunk as stored when it was written, `None` for chunks stored without one
+    pub async fn stored_checksum(&self, chunk_id: &str) -> Option<u32> {
+        let path = self.checksum_path(chunk_id);
+        let content = tokio::fs::read_to_string(&path).await.ok()?;
+        u32::from_str_radix(content.trim(), 16)
+            .map_err(|e| warn!("Ignoring invalid checksum in '{}': {}", path, e))
+            .ok()
+    }
+
+    /// Persist the CRC32 of a chunk, so that it can be verified when the chunk is read later
+    pub async fn store_checksum(
+        &self,
+        chunk_id: &str,
+        checksum: u32,
+    ) -> std::result::Result<(), Status> {
+        let path = self.checksum_path(chunk_id);
+        async {
+            let mut file = File::create(&path).await?;
+            file.write_all(format!("{:08x}", checksum).as_bytes())
+                .await?;
+            if self.sync_writes() == SyncWrites::Always {
+                file.sync_all().await?;
+                self.metrics.syncs.with_label_values(&["checksum"]).inc();
+            }
+            Ok(())
+        }
+        .await
+        .map_err(|e| write_error_status(&path, e))
+    }
+
+    /// Delete the stored checksum of a chunk, if any
+    async fn remove_checksum(&self, chunk_id: &str) {
+        let path = self.checksum_path(chunk_id);
+        match tokio::fs::remove_file(&path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                warn!("Failed to remove checksum file '{}': {}", path, e)
+            }
+            _ => {}
+        }
+    }
+
+    /// Path of the file journaling the recent appends to the given chunk
+    pub fn append_journal_path(&self, chunk_id: &str) -> String {
+        format!("{}{}", self.chunk_path(chunk_id), APPEND_JOURNAL_SUFFIX)
+    }
+
+    /// Whether append IDs are kept, see `append_id_retention` in config.toml
+    fn journals_appends(&self) -> bool {
+        !self.config.append_id_retention.is_zero() && self.config.max_remembered_appends > 0
+    }
+
+    /// Recent appends to a chunk, without the ones older than `append_id_retention`. Empty for
+    /// chunks appended to without append IDs or if the journal cannot be read.
+    pub async fn append_journal(&self, chunk_id: &str) -> AppendJournal {
+        let path = self.append_journal_path(chunk_id);
+        let mut journal = match tokio::fs::read_to_string(&path).await {
+            Ok(content) => AppendJournal::parse(&content),
+            Err(e) => {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    warn!("Ignoring unreadable append journal '{}': {}", path, e);
+                }
+                AppendJournal::default()
+            }
+        };
+        journal.expire(now_millis(), self.config.append_id_retention);
+        journal
+    }
+
+    /// The append to a chunk with this ID, if it was applied within `append_id_retention`.
+    /// `None` for an empty ID, which clients that don't deduplicate their appends send.
+    pub async fn journaled_append(
+        &self,
+        chunk_id: &str,
+        append_id: &str,
+    ) -> Option<JournaledAppend> {
+        if append_id.is_empty() || !self.journals_appends() {
+            return None;
+        }
+        self.append_journal(chunk_id).await.find(append_id).cloned()
+    }
+
+    /// Record an applied append in the journal of its chunk, keeping at most
+    /// `max_remembered_appends` of them. A failure is only logged, as the append was applied and
+    /// failing it would make the client retry it.
+    pub async fn journal_append(
+        &self,
+        chunk_id: &str,
+        append_id: &str,
+        offset: u64,
+        length: u64,
+        failed_secondaries: Vec<String>,
+    ) {
+        if append_id.is_empty() || !self.journals_appends() {
+            return;
+        }
+        let mut journal = self.append_journal(chunk_id).await;
+        journal.record(
+            JournaledAppend {
+                append_id: append_id.to_string(),
+                appended_at: now_millis(),
+                offset,
+                length,
+                failed_secondaries,
+            },
+            self.config.max_remembered_appends,
+        );
+        if let Err(e) = self.store_append_journal(chunk_id, &journal).await {
+            warn!(
+                "Failed to journal append '{}' to chunk '{}', a retry of it would be applied again: {}",
+                append_id, chunk_id, e
+            );
+        }
+    }
+
+    /// Persist the journal of a chunk, removing the file once it is empty
+    async fn store_append_journal(
+        &self,
+        chunk_id: &str,
+        journal: &AppendJournal,
+    ) -> std::io::Result<()> {
+        if journal.is_empty() {
+            self.remove_append_journal(chunk_id).await;
+            return Ok(());
+        }
+        let mut file = File::create(self.append_journal_path(chunk_id)).await?;
+        file.write_all(journal.serialize().as_bytes()).await?;
+        if self.sync_writes() == SyncWrites::Always {
+            file.sync_all().await?;
+            self.metrics
+                .syncs
+                .with_label_values(&["append_journal"])
+                .inc();
+        }
+        Ok(())
+    }
+
+    /// Delete the append journal of a chunk, if any
+    async fn remove_append_journal(&self, chunk_id: &str) {
+        let path = self.append_journal_path(chunk_id);
+        match tokio::fs::remove_file(&path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                warn!("Failed to remove append journal '{}': {}", path, e)
+            }
+            _ => {}
+        }
+    }
+
+    /// Start forwarding a chunk received from a client to a secondary replica, frame by frame as
+    /// the frames arrive, see `ChunkForward`. The secondary verifies the chunk against
+    /// `checksum`, if the client sent one.
+    pub async fn forward_chunk(
+        &self,
+        secondary_address: &str,
+        chunk_id: &str,
+        checksum: Option<u32>,
+        epoch: u64,
+        request_id: &str,
+    ) -> ChunkForward {
+        let (sender, receiver) = tokio::sync::mpsc::channel(FORWARDED_FRAMES);
+        let completed = Arc::new(AtomicBool::new(false));
+        let proof = self.upload_proof(chunk_id, epoch, false);
+        let info = UploadRequest {
+            request: Some(chunk::upload_request::Request::Info(chunk::FileInfo {
+                chunk_id: chunk_id.to_string(),
+                secondary_addresses: Vec::new(),
+                epoch,
+                checksum,
+                stored_form: false,
+                replicas: Vec::new(),
+                placement_proof: String::new(),
+                primary: String::new(),
+            })),
+            token: String::new(),
+            is_internal: true,
+            internal_proof: proof.clone(),
+        };
+        // Sent ahead of the frames, the channel has room for it
+        let _ = sender.send(info).await;
+
+        // Once the frames end, a chunk the primary did not complete is discarded by an empty
+        // request, rather than stored partially
+        let requests = futures::stream::unfold(
+            (receiver, Arc::clone(&completed), proof.clone(), false),
+            |(mut receiver, completed, proof, ended)| async move {
+                match receiver.recv().await {
+                    Some(request) => Some((request, (receiver, completed, proof, ended))),
+                    None if ended || completed.load(Ordering::SeqCst) => None,
+                    None => {
+                        let abort = UploadRequest {
+                            request: None,
+                            token: String::new(),
+                            is_internal: true,
+                            internal_proof: proof.clone(),
+                        };
+                        Some((abort, (receiver, completed, proof, true)))
+                    }
+                }
+            },
+        );
+        let tls = self.common_config.tls.clone();
+        let address = secondary_address.to_string();
+        let request_id = request_id.to_string();
+        let response = tokio::spawn(async move {
+            let mut client = ChunkClient::new(connect_channel(&address, &tls).await?);
+            let response = client
+                .upload(request_with_id(requests, &request_id))
+                .await?;
+            Ok(response.into_inner())
+        });
+        ChunkForward {
+            sender: Some(sender),
+            proof,
+            completed,
+            response,
+        }
+    }
+
+    /// Apply an append written by this primary at `offset` to the secondaries while the chunk
+    /// is still locked, so that every replica applies the appends in the same order.
+    ///
+    /// Returns the secondaries that did not acknowledge the append.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn forward_append(
+        &self,
+        secondary_addresses: &[String],
+        chunk_id: &str,
+        data: &[u8],
+        offset: u64,
+        append_id: &str,
+        epoch: u64,
+        request_id: &str,
+    ) -> Vec<String> {
+        let forwards = secondary_addresses
+            .iter()
+            .map(|secondary_address| async move {
+                let mut client = ChunkClient::new(
+                    connect_channel(secondary_address, &self.common_config.tls).await?,
+                );
+                let internal_proof = sign_request(
+                    &self.common_config.cluster_secret,
+                    &apply_append_fields(
+                        chunk_id,
+                        data,
+                        &offset.to_be_bytes(),
+                        &epoch.to_be_bytes(),
+                        append_id,
+                    ),
+                );
+                client
+                    .apply_append(request_with_id(
+                        ApplyAppendRequest {
+                            chunk_id: chunk_id.to_string(),
+                            data: data.to_vec(),
+                            offset,
+                            epoch,
+                            internal_proof,
+                            append_id: append_id.to_string(),
+                        },
+                        request_id,
+                    ))
+                    .await?;
+                Ok::<(), Error>(())
+            });
+        let mut failed_secondaries = Vec::new();
+        for (secondary_address, result) in secondary_addresses
+            .iter()
+            .zip(futures::future::join_all(forwards).await)
+        {
+            if let Err(e) = result {
+                error!(
+                    "Failed to apply append to chunk '{}' on secondary '{}': {}",
+                    chunk_id, secondary_address, e
+                );
+                failed_secondaries.push(secondary_address.clone());
+            }
+        }
+        failed_secondaries
+    }
+
+    /// Apply a write by this primary at `offset` to the secondaries while the chunk is still
+    /// locked, like `forward_append`. `size` is the size of the chunk before the write, which
+    /// every secondary must have.
+    ///
+    /// Returns the secondaries that did not acknowledge the write.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn forward_write(
+        &self,
+        secondary_addresses: &[String],
+        chunk_id: &str,
+        offset: u64,
+        data: &[u8],
+        size: u64,
+        epoch: u64,
+        request_id: &str,
+    ) -> Vec<String> {
+        let forwards = secondary_addresses
+            .iter()
+            .map(|secondary_address| async move {
+                let mut client = ChunkClient::new(
+                    connect_channel(secondary_address, &self.common_config.tls).await?,
+                );
+                let internal_proof = sign_request(
+                    &self.common_config.cluster_secret,
+                    &apply_write_at_fields(
+                        chunk_id,
+                        data,
+                        &offset.to_be_bytes(),
+                        &size.to_be_bytes(),
+                        &epoch.to_be_bytes(),
+                    ),
+                );
+                client
+                    .apply_write_at(request_with_id(
+                        ApplyWriteAtRequest {
+                            chunk_id: chunk_id.to_string(),
+                            data: data.to_vec(),
+                            offset,
+                            size,
+                            epoch,
+                            internal_proof,
+                        },
+                        request_id,
+                    ))
+                    .await?;
+                Ok::<(), Error>(())
+            });
+        let mut failed_secondaries = Vec::new();
+        for (secondary_address, result) in secondary_addresses
+            .iter()
+            .zip(futures::future::join_all(forwards).await)
+        {
+            if let Err(e) = result {
+                error!(
+                    "Failed to apply write to chunk '{}' on secondary '{}': {}",
+                    chunk_id, secondary_address, e
+                );
+                failed_secondaries.push(secondary_address.clone());
+            }
+        }
+        failed_secondaries
+    }
+
+    /// Path of a temporary file an incoming chunk is written to before being moved into place
+    pub fn temp_chunk_path(&self, chunk_id: &str) -> String {
+        format!(
+            "{}{}{}",
+            self.chunk_path(chunk_id),
+            TEMP_CHUNK_MARKER,
+            Uuid::new_v4()
+        )
+    }
+
+    /// Directories holding the chunk files of this chunkserver, one per `data_path`: below a
+    /// directory named after the chunkserver's address, or the path itself if it is absolute
+    pub fn data_dirs(&self) -> Vec<String> {
+        self.config
+            .data_path
+            .iter()
+            .map(|path| {
+                Path::new(&self.addr_sanitized)
+                    .join(path)
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    /// Wait up to `write_queue_timeout` for one of the `max_concurrent_writes` slots, held until
+    /// the returned slot is dropped. A write finding none free is rejected with
+    /// `ResourceExhausted`, marked with `OVERLOADED_KEY`, so the client retries it later or on
+    /// another replica.
+    pub async fn acquire_write_slot(&self) -> std::result::Result<WriteSlot, Status> {
+        let permit = match &self.write_slots {
+            Some(slots) => {
+                let acquired = tokio::time::timeout(
+                    self.config.write_queue_timeout,
+                    Arc::clone(slots).acquire_owned(),
+                )
+                .await;
+                match acquired {
+                    Ok(permit) => Some(permit.expect("the semaphore is never closed")),
+                    Err(_) => {
+                        warn!(
+                            "Rejecting write: {} write(s) already running",
+                            self.config.max_concurrent_writes
+                        );
+                        self.metrics.rejected_writes.inc();
+                        let mut status = Status::resource_exhausted(format!(
+                            "Chunkserver {} is busy with other writes",
+                            self.addr
+                        ));
+                        status
+                            .metadata_mut()
+                            .insert(OVERLOADED_KEY, MetadataValue::from_static("true"));
+                        return Err(status);
+                    }
+                }
+            }
+            None => None,
+        };
+        self.metrics.writes_in_progress.inc();
+        Ok(WriteSlot {
+            _permit: permit,
+            writes_in_progress: self.metrics.writes_in_progress.clone(),
+        })
+    }
+
+    /// Reject a write of `incoming_bytes` to a chunk with `ResourceExhausted` if it would leave
+    /// less than `min_free_bytes` of free space on the disk holding the chunk
+    pub async fn check_free_space(
+        &self,
+        chunk_id: &str,
+        incoming_bytes: u64,
+    ) -> std::result::Result<(), Status> {
+        let data_dir = self.chunk_dir(chunk_id);
+        let available = fs2::available_space(&data_dir).map_err(|e| {
+            Status::internal(format!(
+                "Failed to read free space of '{}': {}",
+                data_dir, e
+            ))
+        })?;
+        if available < incoming_bytes.saturating_add(self.config.min_free_bytes) {
+            warn!(
+                "Rejecting write of {} byte(s): only {} byte(s) free in '{}'",
+                incoming_bytes, available, data_dir
+            );
+            return Err(Status::resource_exhausted(format!(
+                "Chunkserver {} is out of disk space",
+                self.addr
+            )));
+        }
+        Ok(())
+    }
+
+    /// Delete a chunk file and forget the chunk.
+    ///
+    /// Returns `false` if the file was already absent, which still counts as deleted.
+    pub async fn delete_chunk(&self, chunk_id: &str) -> std::io::Result<bool> {
+        let removed = match tokio::fs::remove_file(self.chunk_path(chunk_id)).await {
+            Ok(()) => true,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => false,
+            Err(e) => return Err(e),
+        };
+        self.remove_checksum(chunk_id).await;
+        self.remove_append_journal(chunk_id).await;
+        self.server_chunks.lock().await.remove(chunk_id);
+        self.chunk_dirs.lock().unwrap().remove(chunk_id);
+        self.framed_sizes.lock().unwrap().remove(chunk_id);
+        Ok(removed)
+    }
+
+    /// Append `data` to the end of a chunk and return the offset it was written at.
+    ///
+    /// A secondary passes the offset the primary wrote at as `expected_offset`, the append is
+    /// rejected with `FailedPrecondition` if the chunk has another size, as then the replica
+    /// missed or reordered earlier appends. The caller holds the lock of the chunk, see
+    /// `chunk_lock`.
+    pub async fn append_to_chunk(
+        &self,
+        chunk_id: &str,
+        data: &[u8],
+        expected_offset: Option<u64>,
+    ) -> std::result::Result<u64, Status> {
+        let file_path = self.chunk_path(chunk_id);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(&file_path)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to open file '{}': {}", file_path, e)))?;
+
+        // A chunk never grows past chunk_size, the read path relies on it
+        let sized: std::io::Result<(bool, u64)> = async {
+            if compression::is_framed(&mut file).await? {
+                Ok((true, self.framed_size(chunk_id, &mut file).await?))
+            } else {
+                Ok((false, file.metadata().await?.len()))
+            }
+        }
+        .await;
+        let (framed, current_size) = sized
+            .map_err(|e| Status::internal(format!("Failed to stat file '{}': {}", file_path, e)))?;
+        if let Some(expected_offset) = expected_offset {
+            if current_size != expected_offset {
+                return Err(Status::failed_precondition(format!(
+                    "Replica of chunk '{}' has {} byte(s), the primary appended at offset {}",
+                    chunk_id, current_size, expected_offset
+                )));
+            }
+        }
+        if current_size + data.len() as u64 > self.common_config.chunk_size {
+            return Err(Status::invalid_argument(format!(
+                "Appending {} byte(s) to chunk '{}' of {} byte(s) exceeds the chunk size of {} bytes",
+                data.len(),
+                chunk_id,
+                current_size,
+                self.common_config.chunk_size
+            )));
+        }
+
+        // A framed chunk gets further frames. An unframed one too short to tell is rewritten
+        // framed if chunks are compressed or its data would start with the magic.
+        let (encoded, framed) = if framed {
+            (
+                Cow::Owned(compression::encode_frames(self.compression(), data)),
+                true,
+            )
+        } else if current_size < FRAMED_MAGIC.len() as u64 {
+            let mut content = Vec::new();
+            file.read_to_end(&mut content).await.map_err(|e| {
+                Status::internal(format!("Failed to read file '{}': {}", file_path, e))
+            })?;
+            content.extend_from_slice(data);
+            if self.compression() != Compression::None || content.starts_with(FRAMED_MAGIC) {
+                file.set_len(0)
+                    .await
+                    .map_err(|e| write_error_status(&file_path, e))?;
+                let mut encoded = FRAMED_MAGIC.to_vec();
+                encoded.extend(compression::encode_frames(self.compression(), &content));
+                (Cow::Owned(encoded), true)
+            } else {
+                (Cow::Borrowed(data), false)
+            }
+        } else {
+            (Cow::Borrowed(data), false)
+        };
+
+        // Write data to the file, discarding the chunk if it ends up partially written
+        if let Err(e) = file.write_all(&encoded).await {
+            self.discard_chunk(chunk_id).await;
+            return Err(write_error_status(&file_path, e));
+        }
+
+        if let Err(e) = self.sync_chunk_file(&file).await {
+            self.discard_chunk(chunk_id).await;
+            return Err(write_error_status(&file_path, e));
+        }
+
+        // Extend the stored checksum by the appended data
+        if let Some(checksum) = self.stored_checksum(chunk_id).await {
+            let mut hasher = crc32fast::Hasher::new_with_initial(checksum);
+            hasher.update(data);
+            if let Err(e) = self.store_checksum(chunk_id, hasher.finalize()).await {
+                self.discard_chunk(chunk_id).await;
+                return Err(e);
+            }
+        }
+        if framed {
+            self.framed_sizes
+                .lock()
+                .unwrap()
+                .insert(chunk_id.to_string(), current_size + data.len() as u64);
+        }
+        Ok(current_size)
+    }
+
+    /// Replace the bytes of a chunk from `offset` on with `data` and return the size the chunk
+    /// had before.
+    ///
+    /// The write must start within the chunk and, unless `allow_extend` is set, end within it;
+    /// it never extends the chunk past chunk_size. A secondary passes the size the chunk had on
+    /// the primary as `expected_size`, the write is rejected with `FailedPrecondition` if the
+    /// chunk has another size, like in `append_to_chunk`.
+    ///
+    /// A framed chunk is rewritten to a temporary file moved into place, as its frames cannot be
+    /// patched; other chunks are written in place. Either way the chunk is streamed through a
+    /// buffer of `read_buffers` rather than read into memory, to check it against its stored
+    /// checksum and to compute the new one. A failed write leaves the rest of the chunk as it
+    /// is: a chunk written in place in part no longer matches its checksum, so it is repaired
+    /// like a corrupt one. The caller holds the lock of the chunk, see `chunk_lock`.
+    pub async fn write_to_chunk(
+        &self,
+        chunk_id: &str,
+        offset: u64,
+        data: &[u8],
+        allow_extend: bool,
+        expected_size: Option<u64>,
+    ) -> std::result::Result<u64, Status> {
+        let file_path = self.chunk_path(chunk_id);
+        let sized: std::io::Result<(bool, u64)> = async {
+            let mut file = File::open(&file_path).await?;
+            if compression::is_framed(&mut file).await? {
+                Ok((true, self.framed_size(chunk_id, &mut file).await?))
+            } else {
+                Ok((false, file.metadata().await?.len()))
+            }
+        }
+        .await;
+        let (framed, size) = sized.map_err(|e| self.read_error_status(chunk_id, e))?;
+        let end = offset.checked_add(data.len() as u64).ok_or_else(|| {
+            Status::out_of_range(format!(
+                "Writing {} byte(s) at offset {} of chunk '{}' overflows the offset",
+                data.len(),
+                offset,
+                chunk_id
+            ))
+        })?;
+        if let Some(expected_size) = expected_size {
+            if size != expected_size {
+                return Err(Status::failed_precondition(format!(
+                    "Replica of chunk '{}' has {} byte(s), the primary wrote to it at {} byte(s)",
+                    chunk_id, size, expected_size
+                )));
+            }
+        }
+        if offset > size || (end > size && !allow_extend) {
+            return Err(Status::out_of_range(format!(
+                "Writing {} byte(s) at offset {} of chunk '{}' goes past its end at {} byte(s)",
+                data.len(),
+                offset,
+                chunk_id,
+                size
+            )));
+        }
+        if end > self.common_config.chunk_size {
+            return Err(Status::invalid_argument(format!(
+                "Writing {} byte(s) at offset {} of chunk '{}' exceeds the chunk size of {} bytes",
+                data.len(),
+                offset,
+                chunk_id,
+                self.common_config.chunk_size
+            )));
+        }
+        let stored_checksum = self.stored_checksum(chunk_id).await;
+
+        // An unframed chunk whose data would start with the magic is framed, see `ChunkWriter`
+        let framed = framed || self.writes_magic(chunk_id, offset, data).await?;
+        let new_checksum = if framed {
+            let temp_path = self.temp_chunk_path(chunk_id);
+            let rewritten: std::result::Result<u32, Status> = async {
+                let written: std::io::Result<(u32, u32)> = async {
+                    let mut writer =
+                        ChunkWriter::new(File::create(&temp_path).await?, self.compression());
+                    let checksums = self
+                        .written_checksums(chunk_id, offset, data, Some(&mut writer))
+                        .await?;
+                    self.sync_chunk_file(&writer.finish().await?).await?;
+                    Ok(checksums)
+                }
+                .await;
+                let (checksum, new_checksum) =
+                    written.map_err(|e| write_error_status(&file_path, e))?;
+                if stored_checksum.is_some_and(|stored| stored != checksum) {
+                    return Err(checksum_mismatch_status(chunk_id));
+                }
+                let moved: std::io::Result<()> = async {
+                    tokio::fs::rename(&temp_path, &file_path).await?;
+                    self.sync_data_dir(&self.chunk_dir(chunk_id)).await
+                }
+                .await;
+                moved.map_err(|e| write_error_status(&file_path, e))?;
+                Ok(new_checksum)
+            }
+            .await;
+            self.framed_sizes.lock().unwrap().remove(chunk_id);
+            if rewritten.is_err() {
+                let _ = tokio::fs::remove_file(&temp_path).await;
+            }
+            rewritten?
+        } else {
+            let new_checksum = match stored_checksum {
+                Some(_) => {
+                    let (checksum, new_checksum) = self
+                        .written_checksums(chunk_id, offset, data, None)
+                        .await
+                        .map_err(|e| self.read_error_status(chunk_id, e))?;
+                    if stored_checksum.is_some_and(|stored| stored != checksum) {
+                        return Err(checksum_mismatch_status(chunk_id));
+                    }
+                    new_checksum
+                }
+                None => 0,
+            };
+            let written: std::io::Result<()> = async {
+                let mut file = OpenOptions::new().write(true).open(&file_path).await?;
+                file.seek(SeekFrom::Start(offset)).await?;
+                file.write_all(data).await?;
+                self.sync_chunk_file(&file).await
+            }
+            .await;
+            written.map_err(|e| write_error_status(&file_path, e))?;
+            new_checksum
+        };
+
+        if stored_checksum.is_some() {
+            self.store_checksum(chunk_id, new_checksum).await?;
         }
+        Ok(size)
+    }
+
+    /// Cut a chunk's data down to `length` bytes, which must not exceed its size.
+    ///
+    /// The chunk is checked against its stored checksum first, like in `write_to_chunk`. An
+    /// unframed chunk file is cut with `set_len`, a framed one rewritten. The caller holds the
+    /// lock of the chunk, see `chunk_lock`.
+    pub async fn truncate_chunk(
+        &self,
+        chunk_id: &str,
+        length: u64,
+    ) -> std::result::Result<(), Status> {
+        let file_path = self.chunk_path(chunk_id);
+        let (framed, mut content) = self.read_chunk_data(chunk_id).await?;
+        if length > content.len() as u64 {
+            return Err(Status::out_of_range(format!(
+                "Cannot truncate chunk '{}' of {} byte(s) to {} byte(s)",
+                chunk_id,
+                content.len(),
+                length
+            )));
+        }
+        let stored_checksum = self.verify_chunk_data(chunk_id, &content).await?;
+
+        content.truncate(length as usize);
+        if framed {
+            self.rewrite_chunk(chunk_id, &content).await?;
+        } else {
+            let truncated: std::io::Result<()> = async {
+                let file = OpenOptions::new().write(true).open(&file_path).await?;
+                file.set_len(length).await?;
+                self.sync_chunk_file(&file).await
+            }
+            .await;
+            if let Err(e) = truncated {
+                self.discard_chunk(chunk_id).await;
+                return Err(write_error_status(&file_path, e));
+            }
+        }
+
+        if stored_checksum.is_some() {
+            if let Err(e) = self
+                .store_checksum(chunk_id, crc32fast::hash(&content))
+                .await
+            {
+                self.discard_chunk(chunk_id).await;
+                return Err(e);
+            }
+        }
+
+        // A retried append whose data was cut off is applied again
+        let mut journal = self.append_journal(chunk_id).await;
+        if !journal.is_empty() {
+            journal.truncate(length);
+            if let Err(e) = self.store_append_journal(chunk_id, &journal).await {
+                warn!(
+                    "Failed to update the append journal of chunk '{}', removing it: {}",
+                    chunk_id, e
+                );
+                self.remove_append_journal(chunk_id).await;
+            }
+        }
+        Ok(())
+    }
+
+    /// Copy a chunk file as stored, with its checksum, to `new_chunk_id` in the same data
+    /// directory and return the size of the copied file. The copy is written to a temporary
+    /// file first, like an upload. The caller holds the lock of the chunk, see `chunk_lock`.
+    pub async fn clone_chunk(
+        &self,
+        chunk_id: &str,
+        new_chunk_id: &str,
+    ) -> std::result::Result<u64, Status> {
+        if !self.server_chunks.lock().await.contains(chunk_id) {
+            return Err(Status::not_found(format!(
+                "Chunk '{}' is not stored on {}",
+                chunk_id, self.addr
+            )));
+        }
+        let file_path = self.chunk_path(chunk_id);
+        let size = tokio::fs::metadata(&file_path)
+            .await
+            .map_err(|e| Status::internal(format!("Failed to stat file '{}': {}", file_path, e)))?
+            .len();
+        let index = self
+            .chunk_dirs
+            .lock()
+            .unwrap()
+            .get(chunk_id)
+            .copied()
+            .unwrap_or(0);
+        self.chunk_dirs
+            .lock()
+            .unwrap()
+            .insert(new_chunk_id.to_string(), index);
+        let temp_path = self.temp_chunk_path(new_chunk_id);
+        let new_path = self.chunk_path(new_chunk_id);
+        let cloned = async {
+            self.check_free_space(new_chunk_id, size).await?;
+            let copied: std::io::Result<()> = async {
+                tokio::fs::copy(&file_path, &temp_path).await?;
+                self.sync_chunk_file(&File::open(&temp_path).await?).await
+            }
+            .await;
+            copied.map_err(|e| write_error_status(&temp_path, e))?;
+            if let Some(checksum) = self.stored_checksum(chunk_id).await {
+                self.store_checksum(new_chunk_id, checksum).await?;
+            }
+            tokio::fs::rename(&temp_path, &new_path)
+                .await
+                .map_err(|e| write_error_status(&new_path, e))?;
+            let data_dir = self.chunk_dir(new_chunk_id);
+            self.sync_data_dir(&data_dir)
+                .await
+                .map_err(|e| write_error_status(&data_dir, e))
+        }
+        .await;
+        if let Err(e) = cloned {
+            let _ = tokio::fs::remove_file(&temp_path).await;
+            self.remove_checksum(new_chunk_id).await;
+            self.unplace_chunk(new_chunk_id);
+            return Err(e);
+        }
+        self.server_chunks
+            .lock()
+            .await
+            .insert(new_chunk_id.to_string());
+        Ok(size)
+    }
+
+    /// Whether a chunk file is framed, and the chunk's data
+    async fn read_chunk_data(
+        &self,
+        chunk_id: &str,
+    ) -> std::result::Result<(bool, Vec<u8>), Status> {
+        let file_path = self.chunk_path(chunk_id);
+        let read: std::io::Result<(bool, Vec<u8>)> = async {
+            let mut reader = ChunkReader::new(File::open(&file_path).await?).await?;
+            let mut content = Vec::new();
+            let mut buffer = vec![0; TRANSFER_FRAME_SIZE];
+            loop {
+                let n = reader.read(&mut buffer).await?;
+                if n == 0 {
+                    break;
+                }
+                content.extend_from_slice(&buffer[..n]);
+            }
+            Ok((reader.framed(), content))
+        }
+        .await;
+        read.map_err(|e| self.read_error_status(chunk_id, e))
+    }
+
+    /// Check a chunk's data against its stored checksum before it is changed, so that a
+    /// corrupt chunk never gets a matching checksum again. Returns the stored checksum, if any.
+    async fn verify_chunk_data(
+        &self,
+        chunk_id: &str,
+        content: &[u8],
+    ) -> std::result::Result<Option<u32>, Status> {
+        let stored_checksum = self.stored_checksum(chunk_id).await;
+        if stored_checksum.is_some_and(|checksum| checksum != crc32fast::hash(content)) {
+            return Err(checksum_mismatch_status(chunk_id));
+        }
+        Ok(stored_checksum)
+    }
+
+    /// CRC32 of a chunk's data as it is, and as it is once `data` is written at `offset`. The
+    /// chunk is read frame by frame through a buffer of `read_buffers`; the data after the
+    /// write is passed to `writer` as well, if given.
+    async fn written_checksums(
+        &self,
+        chunk_id: &str,
+        offset: u64,
+        data: &[u8],
+        mut writer: Option<&mut ChunkWriter>,
+    ) -> std::io::Result<(u32, u32)> {
+        let mut reader = ChunkReader::new(File::open(self.chunk_path(chunk_id)).await?).await?;
+        let mut buffer = self.read_buffers.take().await;
+        let end = offset + data.len() as u64;
+        let mut checksum = crc32fast::Hasher::new();
+        let mut new_checksum = crc32fast::Hasher::new();
+        let mut position = 0;
+        let mut data_written = false;
+        loop {
+            let n = reader.read(&mut buffer).await?;
+            let read = &buffer[..n];
+            checksum.update(read);
+            // The bytes read before `offset` and after `end` are kept, those between replaced
+            let start = position;
+            position += n as u64;
+            let kept = |at: u64| (at.clamp(start, position) - start) as usize;
+            let mut parts = [&read[..kept(offset)], &[][..], &read[kept(end)..]];
+            if !data_written && (offset < position || n == 0) {
+                parts[1] = data;
+                data_written = true;
+            }
+            for part in parts {
+                new_checksum.update(part);
+                if let Some(writer) = writer.as_deref_mut() {
+                    writer.write(part).await?;
+                }
+            }
+            if n == 0 {
+                return Ok((checksum.finalize(), new_checksum.finalize()));
+            }
+        }
+    }
+
+    /// Whether writing `data` at `offset` makes an unframed chunk start with `FRAMED_MAGIC`
+    async fn writes_magic(
+        &self,
+        chunk_id: &str,
+        offset: u64,
+        data: &[u8],
+    ) -> std::result::Result<bool, Status> {
+        let magic_len = FRAMED_MAGIC.len() as u64;
+        if offset >= magic_len {
+            return Ok(false);
+        }
+        let read: std::io::Result<Vec<u8>> = async {
+            let mut head = Vec::new();
+            File::open(self.chunk_path(chunk_id))
+                .await?
+                .take(magic_len)
+                .read_to_end(&mut head)
+                .await?;
+            Ok(head)
+        }
+        .await;
+        let mut head = read.map_err(|e| self.read_error_status(chunk_id, e))?;
+        let offset = offset as usize;
+        let end = (offset + data.len()).min(FRAMED_MAGIC.len());
+        head.resize(head.len().max(end), 0);
+        head[offset..end].copy_from_slice(&data[..end - offset]);
+        Ok(head.starts_with(FRAMED_MAGIC))
+    }
+
+    /// Status of a failure to read a chunk file, `NotFound` if the chunk is not stored here
+    fn read_error_status(&self, chunk_id: &str, e: std::io::Error) -> Status {
+        match e.kind() {
+            std::io::ErrorKind::NotFound => Status::not_found(format!(
+                "Chunk '{}' is not stored on {}",
+                chunk_id, self.addr
+            )),
+            _ => Status::internal(format!(
+                "Failed to read file '{}': {}",
+                self.chunk_path(chunk_id),
+                e
+            )),
+        }
+    }
+
+    /// Replace a chunk file by one holding `content`, written to a temporary file that is then
+    /// moved into place, so the chunk is never left partially written
+    async fn rewrite_chunk(
+        &self,
+        chunk_id: &str,
+        content: &[u8],
+    ) -> std::result::Result<(), Status> {
+        let file_path = self.chunk_path(chunk_id);
+        let temp_path = self.temp_chunk_path(chunk_id);
+        let rewritten: std::io::Result<()> = async {
+            let mut writer = ChunkWriter::new(File::create(&temp_path).await?, self.compression());
+            writer.write(content).await?;
+            self.sync_chunk_file(&writer.finish().await?).await?;
+            tokio::fs::rename(&temp_path, &file_path).await?;
+            self.sync_data_dir(&self.chunk_dir(chunk_id)).await
+        }
+        .await;
+        if let Err(e) = rewritten {
+            let _ = tokio::fs::remove_file(&temp_path).await;
+            return Err(write_error_status(&file_path, e));
+        }
+        self.framed_sizes.lock().unwrap().remove(chunk_id);
+        Ok(())
+    }
+
+    /// Size of the data of a framed chunk, see `compression::framed_size`. It is kept for the
+    /// next append, so only the first one reads all frame headers. The caller holds the lock of
+    /// the chunk.
+    async fn framed_size(&self, chunk_id: &str, file: &mut File) -> std::io::Result<u64> {
+        if let Some(size) = self.framed_sizes.lock().unwrap().get(chunk_id) {
+            return Ok(*size);
+        }
+        let size = compression::framed_size(file).await?;
+        self.framed_sizes
+            .lock()
+            .unwrap()
+            .insert(chunk_id.to_string(), size);
+        Ok(size)
+    }
+
+    /// Size of a chunk's data, which is not the size of its file if the chunk is framed
+    pub async fn data_size(&self, chunk_id: &str) -> std::io::Result<u64> {
+        let mut file = File::open(self.chunk_path(chunk_id)).await?;
+        if !compression::is_framed(&mut file).await? {
+            return Ok(file.metadata().await?.len());
+        }
+        // Only appends, which hold the chunk's lock, record the size
+        let cached = self.framed_sizes.lock().unwrap().get(chunk_id).copied();
+        match cached {
+            Some(size) => Ok(size),
+            None => compression::framed_size(&mut file).await,
+        }
+    }
+
+    /// Delete a partially written chunk so that it is neither served nor reported to the master
+    pub async fn discard_chunk(&self, chunk_id: &str) {
+        let file_path = self.chunk_path(chunk_id);
+        if let Err(e) = tokio::fs::remove_file(&file_path).await {
+            warn!("Failed to remove partial chunk '{}': {}", file_path, e);
+        }
+        self.remove_checksum(chunk_id).await;
+        self.remove_append_journal(chunk_id).await;
+        self.server_chunks.lock().await.remove(chunk_id);
+        self.chunk_dirs.lock().unwrap().remove(chunk_id);
+        self.framed_sizes.lock().unwrap().remove(chunk_id);
+    }
+
+    /// Create the data directories, remove the temporary chunks an earlier run left behind and
+    /// record which directory holds each stored chunk
+    pub fn prepare_data_dir(&self) -> Result<()> {
+        let mut chunk_dirs = HashMap::new();
+        for (index, data_dir) in self.data_dirs().into_iter().enumerate() {
+            if !Path::new(&data_dir).exists() {
+                std::fs::create_dir_all(&data_dir).map_err(|e| {
+                    error!("Failed to create data directory '{}': {}", data_dir, e);
+                    e
+                })?;
+            }
+            debug!("Data directory verified: {}", data_dir);
+
+            // Chunks written before opaque chunk IDs were named "<file>_chunk_<index>" and can no longer be referenced
+            let legacy_chunks: Vec<String> = std::fs::read_dir(&data_dir)?
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.file_name().to_string_lossy().to_string())
+                .filter(|name| name.contains("_chunk_"))
+                .collect();
+            if !legacy_chunks.is_empty() {
+                warn!(
+                    "Found {} legacy chunk file(s) named '<file>_chunk_<index>' in '{}', they are ignored; re-upload the files and remove them",
+                    legacy_chunks.len(),
+                    data_dir
+                );
+            }
+
+            // Temporary files are left behind by uploads interrupted by a crash and never hold a complete chunk
+            let mut chunks = 0;
+            for entry in std::fs::read_dir(&data_dir)?.filter_map(|entry| entry.ok()) {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if name.contains(TEMP_CHUNK_MARKER) {
+                    info!("Removing leftover temporary chunk: {:?}", entry.path());
+                    std::fs::remove_file(entry.path())?;
+                } else if !name.ends_with(CHECKSUM_SUFFIX)
+                    && !name.ends_with(APPEND_JOURNAL_SUFFIX)
+                    && !name.contains("_chunk_")
+                    && entry.file_type().is_ok_and(|file_type| file_type.is_file())
+                {
+                    if let Some(first) = chunk_dirs.insert(name.clone(), index) {
+                        // Only one copy can be resolved, keep the one found first
+                        chunk_dirs.insert(name.clone(), first);
+                        warn!(
+                            "Chunk '{}' is stored in both '{}' and '{}', ignoring the latter",
+                            name,
+                            self.data_dirs()[first],
+                            data_dir
+                        );
+                    } else {
+                        chunks += 1;
+                    }
+                }
+            }
+            info!("Found {} chunk(s) in '{}'", chunks, data_dir);
+        }
+        *self.chunk_dirs.lock().unwrap() = chunk_dirs;
+        Ok(())
+    }
+
+    /// Register with the leader master, trying for up to `master_retry_deadline`, then start
+    /// sending heartbeats and scrubbing the stored chunks
+    pub async fn join_cluster(&self) -> Result<()> {
+        let mut master_client = connect_to_master(
+            &self.common_config.master_addrs,
+            &self.common_config.tls,
+            &RetryPolicy::until(self.config.master_retry_deadline),
+            None,
+        )
+        .await?;
+        let response = master_client
+            .register_chunk_server(RegisterRequest {
+                address: self.addr.clone(),
+                failure_domain: self.config.failure_domain.clone(),
+                internal_proof: sign_request(
+                    &self.common_config.cluster_secret,
+                    &register_fields(&self.addr, &self.config.failure_domain),
+                ),
+            })
+            .await?
+            .into_inner();
+        info!("Registered with Master: {}", response.message);
+
+        // Report the chunks `prepare_data_dir` found, e.g. those stored before a restart
+        let stored: Vec<String> = self.chunk_dirs.lock().unwrap().keys().cloned().collect();
+        self.server_chunks.lock().await.extend(stored);
+
+        // Periodically verify the stored chunks against their checksums
+        self.start_scrubber();
+        self.send_heartbeat(master_client).await
     }
 
     pub async fn send_heartbeat(
         &self,
         master_client: MasterClient<tonic::transport::Channel>, // Take ownership
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let interval_duration = Duration::from_secs(self.common_config.heartbeat_interval);
+    ) -> Result<()> {
+        let interval_duration = self.common_config.heartbeat_interval;
         let addr = self.addr.clone();
         let server_chunks = self.server_chunks.clone(); // Clone the Arc<Mutex<HashSet>> pointer
+        let service = self.clone();
         let master_addrs = self.common_config.master_addrs.clone(); // Clone master_addrs to ensure 'static lifetime
+        let tls = self.common_config.tls.clone();
+        let retry_policy = RetryPolicy::until(self.config.master_retry_deadline);
         let mut first_time_reconnected = false;
 
-        tokio::spawn(async move {
+        self.tasks.spawn("heartbeat task", async move {
             let mut interval = tokio::time::interval(interval_duration);
             let mut client = master_client; // Move the owned client into the task
+            let mut deleted_chunks = Vec::new(); // Deletions to confirm in the next heartbeat
+            let mut generation = 0; // Of the last chunk report
+            // Generation and chunks of the last report the master applied, None to report all chunks
+            let mut acked: Option<(u64, HashSet<String>)> = None;
+            let mut failed_reconnects = 0; // In a row, to back off while no leader is reachable
+            service.metrics.master_connected.set(1);
 
             loop {
                 // Wait for interval seconds
@@ -59,40 +1695,143 @@ impl ChunkService {
                     first_time_reconnected = false;
                 }
 
-                // Collect chunk information
-                let chunks: Vec<String> = server_chunks
-                    .lock()
-                    .await
-                    .iter()
-                    .cloned() // Clone each String from the HashSet
-                    .collect();
+                // Switch to a master that announced it took over as leader
+                let new_leader = service.new_leader.lock().await.take();
+                if let Some(leader) = new_leader {
+                    match connect_channel(&leader, &tls).await {
+                        Ok(channel) => {
+                            info!("Sending heartbeats to new leader {}", leader);
+                            acked = None; // It has no chunk report from this server yet
+                            client = MasterClient::new(channel);
+                        }
+                        Err(e) => error!("Failed to connect to new leader {}: {}", leader, e),
+                    }
+                }
+
+                // Report all chunks, or only the changes since the report the master applied
+                let current: HashSet<String> = server_chunks.lock().await.clone();
+                generation += 1;
+                let (chunks, base_generation, added_chunks, removed_chunks) = match &acked {
+                    Some((base_generation, reported)) => (
+                        Vec::new(),
+                        *base_generation,
+                        current.difference(reported).cloned().collect(),
+                        reported.difference(&current).cloned().collect(),
+                    ),
+                    None => (current.iter().cloned().collect(), 0, Vec::new(), Vec::new()),
+                };
+                let delta = acked.is_some();
+
+                // Report disk capacity so the master can place chunks by free space, counting
+                // each disk once however many data directories it holds
+                let disks = service.disk_usage();
+                let (mut total_bytes, mut available_bytes) = (0, 0);
+                let mut devices = HashSet::new();
+                for (data_dir, usage) in &disks {
+                    match usage {
+                        Ok(usage) => {
+                            if usage.device.is_none_or(|device| devices.insert(device)) {
+                                total_bytes += usage.total_bytes;
+                                available_bytes += usage.available_bytes;
+                            }
+                        }
+                        Err(e) => warn!("Failed to read disk capacity of '{}': {}", data_dir, e),
+                    }
+                }
+                service
+                    .update_storage_metrics(current.len(), total_bytes, available_bytes, &disks)
+                    .await;
 
                 // Create and send the heartbeat request
-                let request = HeartbeatRequest {
+                let (last_scrub_time, corrupt_chunks) = {
+                    let scrub_stats = service.scrub_stats.lock().await;
+                    (scrub_stats.last_scrub_time, scrub_stats.corrupt_chunks)
+                };
+                let mut request = HeartbeatRequest {
                     chunkserver_address: addr.clone(),
                     chunks,
+                    total_bytes,
+                    available_bytes,
+                    deleted_chunks: std::mem::take(&mut deleted_chunks),
+                    last_scrub_time,
+                    corrupt_chunks,
+                    failure_domain: service.config.failure_domain.clone(),
+                    generation,
+                    delta,
+                    base_generation,
+                    added_chunks,
+                    removed_chunks,
+                    internal_proof: String::new(),
                 };
+                request.internal_proof = sign_request(
+                    &service.common_config.cluster_secret,
+                    &heartbeat_fields(&addr, &heartbeat_digest(&request)),
+                );
 
-                match client.heartbeat(tonic::Request::new(request)).await {
+                let sent = client.heartbeat(tonic::Request::new(request)).await;
+                service
+                    .metrics
+                    .heartbeats
+                    .with_label_values(&[if sent.is_ok() { "ok" } else { "error" }])
+                    .inc();
+                match sent {
                     Ok(response) => {
-                        info!(
-                            "Heartbeat acknowledged by Master: {}",
-                            response.into_inner().message
-                        );
+                        let response = response.into_inner();
+                        info!("Heartbeat acknowledged by Master: {}", response.message);
+                        if service.metrics.master_connected.get() == 0 {
+                            info!("Heartbeats reach the leader master again");
+                            service.metrics.master_connected.set(1);
+                        }
+                        failed_reconnects = 0;
+                        // Masters that don't know about deltas acknowledge no report
+                        acked = (response.acked_generation == generation)
+                            .then_some((generation, current));
+                        if delta && acked.is_none() {
+                            info!("Master asked for a full chunk report");
+                            first_time_reconnected = true; // Send it right away
+                        }
+                        if service.advance_epoch(response.epoch).await.is_err() {
+                            continue; // A demoted leader must not delete chunks
+                        }
+
+                        // Delete the chunks of deleted files, unconfirmed ones are requested again
+                        for chunk_id in response.chunks_to_delete {
+                            match service.delete_chunk(&chunk_id).await {
+                                Ok(_) => {
+                                    info!("Deleted chunk '{}' as requested by Master", chunk_id);
+                                    deleted_chunks.push(chunk_id);
+                                }
+                                Err(e) => error!("Failed to delete chunk '{}': {}", chunk_id, e),
+                            }
+                        }
                     }
                     Err(e) => {
                         error!("Failed to send heartbeat: {}", e);
+                        // The master may not have applied the report, and a restarted master
+                        // knows no chunks of this server, so the next report lists them all
+                        acked = None;
+                        if service.metrics.master_connected.get() == 1 {
+                            error!("Lost contact with the leader master, reporting NOT_SERVING until heartbeats reach it again");
+                            service.metrics.master_connected.set(0);
+                        }
 
-                        // Attempt to reconnect to the master
-                        match connect_to_master(&master_addrs).await {
+                        // Attempt to reconnect to the leader, e.g. after it failed over
+                        match connect_to_master(&master_addrs, &tls, &retry_policy, None).await {
                             Ok(new_client) => {
                                 info!("Reconnected to Master");
                                 client = new_client;
                                 first_time_reconnected = true; // Avoid waiting for heartbeat_interval before retrying
                             }
                             Err(e) => {
-                                error!("Failed to reconnect to Master: {}", e);
-                                break; // Exit the loop on repeated failures
+                                // Keeps trying for good, so it rejoins once a leader is back
+                                failed_reconnects += 1;
+                                let backoff = reconnect_backoff(interval_duration, failed_reconnects);
+                                error!(
+                                    "Failed to reconnect to Master ({} time(s) in a row), retrying in {:?}: {}",
+                                    failed_reconnects, backoff, e
+                                );
+                                tokio::time::sleep(backoff).await;
+                                first_time_reconnected = true; // The backoff replaces heartbeat_interval
                             }
                         }
                     }
@@ -103,43 +1842,535 @@ impl ChunkService {
         Ok(())
     }
 
-    /// Periodic cleanup for outdated OTP
-    pub fn start_otp_cleanup(&self) {
-        let otp_store = self.otp_store.clone();
-        tokio::spawn(async move {
-            loop {
-                tokio::time::sleep(Duration::from_secs(300)).await;
-                let now = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs();
+    /// Stop sending heartbeats and scrubbing, then tell the master that this server is gone so
+    /// it copies the chunks to other servers right away
+    pub async fn shutdown(&self) {
+        let timeout = self.common_config.shutdown_timeout;
+        self.tasks.shutdown(timeout).await;
+        match tokio::time::timeout(timeout, self.unregister()).await {
+            Ok(Ok(message)) => info!("[Shutdown] {}", message),
+            Ok(Err(e)) => warn!("[Shutdown] Failed to unregister from the master: {}", e),
+            Err(_) => warn!(
+                "[Shutdown] The master did not answer the unregistration within {:?}",
+                timeout
+            ),
+        }
+    }
+
+    /// Remove this server from the leader master, see `shutdown`
+    async fn unregister(&self) -> Result<String> {
+        let mut master_client = connect_to_master(
+            &self.common_config.master_addrs,
+            &self.common_config.tls,
+            &RetryPolicy::never(),
+            None,
+        )
+        .await?;
+        let response = master_client
+            .unregister_chunk_server(UnregisterRequest {
+                address: self.addr.clone(),
+                internal_proof: sign_request(
+                    &self.common_config.cluster_secret,
+                    &unregister_fields(&self.addr),
+                ),
+            })
+            .await?
+            .into_inner();
+        Ok(response.message)
+    }
 
-                let mut otp_store_guard = otp_store.lock().await;
-                otp_store_guard.retain(|_, expiration| *expiration > now);
+    /// Refresh the storage gauges, once per heartbeat rather than on every scrape
+    async fn update_storage_metrics(
+        &self,
+        chunks: usize,
+        total_bytes: u64,
+        available_bytes: u64,
+        disks: &[(String, std::io::Result<DiskUsage>)],
+    ) {
+        self.metrics.chunks.set(chunks as i64);
+        self.metrics.disk_total_bytes.set(total_bytes as i64);
+        self.metrics
+            .disk_available_bytes
+            .set(available_bytes as i64);
 
-                info!("[start_otp_cleanup] Cleaned up expired OTPs.");
+        let mut dir_chunks = vec![0; disks.len()];
+        for &index in self.chunk_dirs.lock().unwrap().values() {
+            if let Some(count) = dir_chunks.get_mut(index) {
+                *count += 1;
+            }
+        }
+        let mut data_bytes = 0;
+        for ((data_dir, usage), chunks) in disks.iter().zip(dir_chunks) {
+            let dir = [data_dir.as_str()];
+            self.metrics.dir_chunks.with_label_values(&dir).set(chunks);
+            if let Ok(usage) = usage {
+                self.metrics
+                    .dir_total_bytes
+                    .with_label_values(&dir)
+                    .set(usage.total_bytes as i64);
+                self.metrics
+                    .dir_available_bytes
+                    .with_label_values(&dir)
+                    .set(usage.available_bytes as i64);
+            }
+            match data_dir_size(data_dir).await {
+                Ok(size) => {
+                    self.metrics
+                        .dir_data_bytes
+                        .with_label_values(&dir)
+                        .set(size as i64);
+                    data_bytes += size;
+                }
+                Err(e) => warn!("Failed to measure data directory '{}': {}", data_dir, e),
+            }
+        }
+        self.metrics.data_bytes.set(data_bytes as i64);
+    }
+
+    /// CRC32 of a chunk's data, read at no more than `bytes_per_sec` (0 for no limit)
+    pub async fn compute_checksum(
+        &self,
+        chunk_id: &str,
+        bytes_per_sec: u64,
+    ) -> std::io::Result<u32> {
+        let (checksum, _) = checksum_file(&self.chunk_path(chunk_id), bytes_per_sec).await?;
+        Ok(checksum)
+    }
+
+    /// Periodically verify every chunk against its stored checksum, every `scrub_interval`. Corrupt replicas are deleted and reported to the master, which copies the chunk
+    /// from another replica.
+    pub fn start_scrubber(&self) {
+        let interval = self.config.scrub_interval;
+        if interval.is_zero() {
+            info!("[Scrubber] Scrubbing is disabled");
+            return;
+        }
+        let service = self.clone();
+        self.tasks.spawn("scrubber", async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                service.scrub().await;
             }
         });
     }
 
-    pub async fn validate_otp(&self, otp: &str) -> Result<(), Status> {
-        if !self.common_config.use_authentication {
-            // Skip OTP validation if authentication is disabled
-            return Ok(());
+    /// Verify all chunks once, see `start_scrubber`
+    pub async fn scrub(&self) {
+        let chunk_ids: Vec<String> = self.server_chunks.lock().await.iter().cloned().collect();
+        debug!("[Scrubber] Verifying {} chunk(s)", chunk_ids.len());
+        let mut corrupt_chunks = Vec::new();
+        for chunk_id in chunk_ids {
+            match self.is_corrupt(&chunk_id).await {
+                Ok(false) => {}
+                Ok(true) => corrupt_chunks.push(chunk_id),
+                // E.g. deleted since the pass started
+                Err(e) => warn!("[Scrubber] Failed to verify chunk '{}': {}", chunk_id, e),
+            }
+        }
+
+        for chunk_id in &corrupt_chunks {
+            if let Err(e) = self.delete_chunk(chunk_id).await {
+                error!(
+                    "[Scrubber] Failed to delete corrupt chunk '{}': {}",
+                    chunk_id, e
+                );
+            }
         }
 
-        let otp_store_guard = self.otp_store.lock().await;
-        if let Some(&expiration_time) = otp_store_guard.get(otp) {
-            let now = SystemTime::now()
+        let unreported = {
+            let mut scrub_stats = self.scrub_stats.lock().await;
+            scrub_stats.last_scrub_time = SystemTime::now()
                 .duration_since(UNIX_EPOCH)
                 .unwrap()
                 .as_secs();
+            scrub_stats.corrupt_chunks += corrupt_chunks.len() as u64;
+            scrub_stats.unreported.extend(corrupt_chunks);
+            std::mem::take(&mut scrub_stats.unreported)
+        };
+        // Reports that fail are sent again after the next pass
+        let unreported = self.report_corrupt_chunks(unreported).await;
+        self.scrub_stats.lock().await.unreported.extend(unreported);
+    }
+
+    /// Whether a chunk does not match its stored checksum. Chunks without one are not verified.
+    async fn is_corrupt(&self, chunk_id: &str) -> std::io::Result<bool> {
+        let Some(expected) = self.stored_checksum(chunk_id).await else {
+            return Ok(false);
+        };
+        if self
+            .compute_checksum(chunk_id, self.config.scrub_bytes_per_sec)
+            .await?
+            == expected
+        {
+            return Ok(false);
+        }
+
+        // The chunk may have been appended to while it was read, so check again with
+        // appends held off
+        let lock = self.chunk_lock(chunk_id).await;
+        let corrupt = {
+            let _guard = lock.lock().await;
+            match self.stored_checksum(chunk_id).await {
+                Some(expected) => self
+                    .compute_checksum(chunk_id, 0)
+                    .await
+                    .map(|checksum| checksum != expected),
+                None => Ok(false),
+            }
+        };
+        self.release_chunk_lock(chunk_id, lock).await;
+        if matches!(corrupt, Ok(true)) {
+            error!(
+                "[Scrubber] Chunk '{}' does not match its checksum",
+                chunk_id
+            );
+        }
+        corrupt
+    }
+
+    /// Tell the master about deleted corrupt chunks and return those it could not be told about
+    async fn report_corrupt_chunks(&self, chunk_ids: Vec<String>) -> Vec<String> {
+        if chunk_ids.is_empty() {
+            return chunk_ids;
+        }
+        let mut master_client = match connect_to_master(
+            &self.common_config.master_addrs,
+            &self.common_config.tls,
+            // Chunks not reported now are reported after the next scrub
+            &RetryPolicy::never(),
+            None,
+        )
+        .await
+        {
+            Ok(client) => client,
+            Err(e) => {
+                warn!("[Scrubber] Failed to reach the master: {}", e);
+                return chunk_ids;
+            }
+        };
+        let mut unreported = Vec::new();
+        for chunk_id in chunk_ids {
+            let request = ReportCorruptChunkRequest {
+                chunkserver_address: self.addr.clone(),
+                chunk_id: chunk_id.clone(),
+                internal_proof: sign_request(
+                    &self.common_config.cluster_secret,
+                    &report_corrupt_chunk_fields(&self.addr, &chunk_id),
+                ),
+            };
+            if let Err(e) = master_client
+                .report_corrupt_chunk(tonic::Request::new(request))
+                .await
+            {
+                warn!(
+                    "[Scrubber] Failed to report corrupt chunk '{}': {}",
+                    chunk_id, e
+                );
+                unreported.push(chunk_id);
+            }
+        }
+        unreported
+    }
 
-            if expiration_time > now {
-                return Ok(()); // OTP is valid
+    /// Check the session token of a client request, signed by the master with the cluster secret,
+    /// and return its claims, `None` without authentication
+    pub async fn validate_token(
+        &self,
+        token: &str,
+    ) -> std::result::Result<Option<TokenClaims>, Status> {
+        if !self.common_config.use_authentication {
+            // Skip token validation if authentication is disabled
+            return Ok(None);
+        }
+
+        let claims = verify_token(&self.common_config.cluster_secret, token)
+            .map_err(|e| Status::unauthenticated(e.to_string()))?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        if claims.expires_at <= now {
+            return Err(Status::unauthenticated(format!(
+                "Session token of user '{}' expired",
+                claims.username
+            )));
+        }
+        Ok(Some(claims))
+    }
+
+    /// Check the session token of a client request that changes a chunk, rejecting readonly
+    /// users with `PermissionDenied`
+    pub async fn validate_write_token(&self, token: &str) -> std::result::Result<(), Status> {
+        match self.validate_token(token).await? {
+            Some(claims) if !claims.role.may_write() => {
+                warn!(user = %claims.username, "Rejected a change by a readonly user");
+                Err(Status::permission_denied(format!(
+                    "User '{}' is readonly and may not change chunks",
+                    claims.username
+                )))
             }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check that a chunk ID received in a request has the form of the IDs the master issues, a
+    /// hyphenated UUID, as it names the chunk's files. Anything else, e.g. `../x`, could name a
+    /// file outside the data directories and is rejected with `InvalidArgument`.
+    pub async fn validate_chunk_id(&self, chunk_id: &str) -> std::result::Result<(), Status> {
+        let issued =
+            Uuid::try_parse(chunk_id).is_ok_and(|uuid| uuid.hyphenated().to_string() == chunk_id);
+        if !issued {
+            return Err(Status::invalid_argument(format!(
+                "Invalid chunk ID '{}'",
+                chunk_id
+            )));
         }
+        Ok(())
+    }
 
-        Err(Status::unauthenticated("Invalid or expired OTP"))
+    /// Check that a client uploads a chunk to a replica the master placed it on, and that the
+    /// secondaries it asks this server to forward the chunk to are among those replicas. When
+    /// authentication is enabled the placement must carry the master's signature, so that a
+    /// client can't store data under the ID of another user's chunk.
+    pub async fn validate_placement(&self, info: &FileInfo) -> std::result::Result<(), Status> {
+        if let Some(secondary) = info
+            .secondary_addresses
+            .iter()
+            .find(|secondary| !info.replicas.contains(secondary))
+        {
+            return Err(Status::permission_denied(format!(
+                "'{}' is not a replica of chunk '{}'",
+                secondary, info.chunk_id
+            )));
+        }
+        if !self.common_config.use_authentication {
+            // Trusted like the rest of the client request when authentication is disabled
+            return Ok(());
+        }
+        // Any replica takes over as primary if the placed one is unreachable
+        if !info.replicas.contains(&self.addr) {
+            return Err(Status::permission_denied(format!(
+                "'{}' is not a replica of chunk '{}'",
+                self.addr, info.chunk_id
+            )));
+        }
+        verify_placement(
+            &self.common_config.cluster_secret,
+            &info.placement_proof,
+            &info.chunk_id,
+            &info.primary,
+            &info.replicas,
+        )
+        .map_err(|e| Status::permission_denied(e.to_string()))
+    }
+
+    /// Proof of an internal upload of a chunk to another chunkserver, carried by every message
+    /// of the upload, see `validate_internal_proof`
+    pub fn upload_proof(&self, chunk_id: &str, epoch: u64, stored_form: bool) -> String {
+        sign_request(
+            &self.common_config.cluster_secret,
+            &upload_fields(chunk_id, &epoch.to_be_bytes(), stored_form),
+        )
+    }
+
+    /// Check the proof carried by an internal request, signed by another chunkserver or by a
+    /// master over `fields`, the name of the RPC followed by the fields it acts on
+    pub async fn validate_internal_proof(
+        &self,
+        proof: &str,
+        fields: &[&[u8]],
+    ) -> std::result::Result<(), Status> {
+        if !self.common_config.use_authentication {
+            // Internal requests are trusted like client requests when authentication is disabled
+            return Ok(());
+        }
+        if self.common_config.cluster_secret.is_empty() {
+            return Err(Status::unauthenticated(
+                "Internal requests are rejected: no cluster_secret is configured",
+            ));
+        }
+        verify_request(&self.common_config.cluster_secret, proof, fields)
+            .map_err(|e| Status::unauthenticated(e.to_string()))
+    }
+}
+
+/// Fields an internal upload is signed over, see `ChunkService::upload_proof`
+pub fn upload_fields<'a>(
+    chunk_id: &'a str,
+    epoch: &'a [u8; 8],
+    stored_form: bool,
+) -> [&'a [u8]; 4] {
+    let stored_form: &[u8] = if stored_form { b"stored" } else { b"data" };
+    [b"upload", chunk_id.as_bytes(), epoch, stored_form]
+}
+
+/// Fields the announcement of a new leader by a master is signed over
+pub fn leader_changed_fields<'a>(leader_address: &'a str, epoch: &'a [u8; 8]) -> [&'a [u8]; 3] {
+    [b"chunk_leader_changed", leader_address.as_bytes(), epoch]
+}
+
+/// Fields a lease granted by the master is signed over
+pub fn grant_lease_fields<'a>(
+    chunk_id: &'a str,
+    duration: &'a [u8; 8],
+    epoch: &'a [u8; 8],
+    replicas: &'a [String],
+) -> Vec<&'a [u8]> {
+    [b"grant_lease", chunk_id.as_bytes(), duration, epoch]
+        .into_iter()
+        .chain(replicas.iter().map(|replica| replica.as_bytes()))
+        .collect()
+}
+
+/// Fields a transfer of a chunk started by the master is signed over
+pub fn transfer_chunk_fields<'a>(
+    chunk_name: &'a str,
+    target_address: &'a str,
+    epoch: &'a [u8; 8],
+    target_chunk_name: &'a str,
+) -> [&'a [u8]; 5] {
+    [
+        b"transfer_chunk",
+        chunk_name.as_bytes(),
+        target_address.as_bytes(),
+        epoch,
+        target_chunk_name.as_bytes(),
+    ]
+}
+
+/// Fields a truncation of a chunk by the master is signed over
+pub fn truncate_chunk_fields<'a>(
+    chunk_id: &'a str,
+    length: &'a [u8; 8],
+    epoch: &'a [u8; 8],
+) -> [&'a [u8]; 4] {
+    [b"truncate_chunk", chunk_id.as_bytes(), length, epoch]
+}
+
+/// Fields a copy of a shared chunk made by the master is signed over
+pub fn clone_chunk_fields<'a>(
+    chunk_id: &'a str,
+    new_chunk_id: &'a str,
+    epoch: &'a [u8; 8],
+) -> [&'a [u8]; 4] {
+    [
+        b"clone_chunk",
+        chunk_id.as_bytes(),
+        new_chunk_id.as_bytes(),
+        epoch,
+    ]
+}
+
+/// Fields an append applied to a secondary is signed over, see `ChunkService::forward_append`
+pub fn apply_append_fields<'a>(
+    chunk_id: &'a str,
+    data: &'a [u8],
+    offset: &'a [u8; 8],
+    epoch: &'a [u8; 8],
+    append_id: &'a str,
+) -> [&'a [u8]; 6] {
+    [
+        b"apply_append",
+        chunk_id.as_bytes(),
+        data,
+        offset,
+        epoch,
+        append_id.as_bytes(),
+    ]
+}
+
+/// Fields a write applied to a secondary is signed over, see `ChunkService::forward_write`
+pub fn apply_write_at_fields<'a>(
+    chunk_id: &'a str,
+    data: &'a [u8],
+    offset: &'a [u8; 8],
+    size: &'a [u8; 8],
+    epoch: &'a [u8; 8],
+) -> [&'a [u8]; 6] {
+    [
+        b"apply_write_at",
+        chunk_id.as_bytes(),
+        data,
+        offset,
+        size,
+        epoch,
+    ]
+}
+
+/// Wait before the next attempt to reach a leader after `failed_reconnects` failed ones in a row,
+/// doubling from `heartbeat_interval` up to `MAX_RECONNECT_BACKOFF`
+fn reconnect_backoff(heartbeat_interval: Duration, failed_reconnects: u32) -> Duration {
+    heartbeat_interval
+        .saturating_mul(2u32.saturating_pow(failed_reconnects.saturating_sub(1)))
+        .min(MAX_RECONNECT_BACKOFF)
+}
+
+/// Current time in milliseconds since UNIX epoch, as recorded in append journals
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+/// Convert a failed chunk write into a status, reporting a full disk as `ResourceExhausted`
+pub fn write_error_status(file_path: &str, e: std::io::Error) -> Status {
+    if e.kind() == std::io::ErrorKind::StorageFull {
+        Status::resource_exhausted(format!("Disk full while writing '{}': {}", file_path, e))
+    } else {
+        Status::internal(format!("Failed to write to file '{}': {}", file_path, e))
+    }
+}
+
+/// Status of a chunk whose data does not match its stored checksum before it is changed
+fn checksum_mismatch_status(chunk_id: &str) -> Status {
+    Status::data_loss(format!(
+        "Chunk '{}' does not match its stored checksum",
+        chunk_id
+    ))
+}
+
+/// CRC32 and size of the data in a chunk file, framed or not, read at no more than
+/// `bytes_per_sec` (0 for no limit)
+pub async fn checksum_file(path: &str, bytes_per_sec: u64) -> std::io::Result<(u32, u64)> {
+    let mut reader = ChunkReader::new(File::open(path).await?).await?;
+    let mut frame = vec![0; TRANSFER_FRAME_SIZE];
+    let mut hasher = crc32fast::Hasher::new();
+    let mut size = 0;
+    loop {
+        let n = reader.read(&mut frame).await?;
+        if n == 0 {
+            return Ok((hasher.finalize(), size));
+        }
+        hasher.update(&frame[..n]);
+        size += n as u64;
+        if bytes_per_sec > 0 {
+            tokio::time::sleep(Duration::from_secs_f64(n as f64 / bytes_per_sec as f64)).await;
+        }
+    }
+}
+
+/// Device of the filesystem holding `path`, telling which data directories share a disk
+#[cfg(unix)]
+fn device_of(path: &str) -> std::io::Result<Option<u64>> {
+    use std::os::unix::fs::MetadataExt;
+    Ok(Some(std::fs::metadata(path)?.dev()))
+}
+
+#[cfg(not(unix))]
+fn device_of(_path: &str) -> std::io::Result<Option<u64>> {
+    Ok(None)
+}
+
+/// Total size of the files directly in `dir`
+async fn data_dir_size(dir: &str) -> std::io::Result<u64> {
+    let mut size = 0;
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let metadata = entry.metadata().await?;
+        if metadata.is_file() {
+            size += metadata.len();
+        }
     }
+    Ok(size)
 }
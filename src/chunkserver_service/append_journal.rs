@@ -0,0 +1,104 @@
+// Recent appends to a chunk by the append ID the client chose, so that an append retried after a
+// lost response is answered with the offset of the first attempt instead of being applied twice
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Append applied to a chunk, together with its result
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JournaledAppend {
+    pub append_id: String,
+    pub appended_at: u64, // When the append was applied (milliseconds since UNIX epoch)
+    pub offset: u64,      // Offset in the chunk the data was written at
+    pub length: u64,      // Bytes appended
+    pub failed_secondaries: Vec<String>, // Secondaries that did not acknowledge it, only known on the primary
+}
+
+/// Appends applied to a chunk, oldest first.
+///
+/// Stored next to the chunk with one line per append:
+/// `<append ID> <time> <offset> <length> <failed secondaries separated by commas>`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AppendJournal {
+    appends: VecDeque<JournaledAppend>,
+}
+
+impl AppendJournal {
+    /// Parse a stored journal. Invalid lines are skipped, as losing an entry only means that a
+    /// retry of that append is applied again.
+    pub fn parse(content: &str) -> Self {
+        let appends = content
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split(' ');
+                let append_id = fields.next().filter(|id| !id.is_empty())?;
+                let appended_at = fields.next()?.parse().ok()?;
+                let offset = fields.next()?.parse().ok()?;
+                let length = fields.next()?.parse().ok()?;
+                let failed_secondaries = fields
+                    .next()
+                    .unwrap_or_default()
+                    .split(',')
+                    .filter(|address| !address.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                Some(JournaledAppend {
+                    append_id: append_id.to_string(),
+                    appended_at,
+                    offset,
+                    length,
+                    failed_secondaries,
+                })
+            })
+            .collect();
+        AppendJournal { appends }
+    }
+
+    /// The journal in the stored form, see `parse`
+    pub fn serialize(&self) -> String {
+        self.appends
+            .iter()
+            .map(|append| {
+                format!(
+                    "{} {} {} {} {}\n",
+                    append.append_id,
+                    append.appended_at,
+                    append.offset,
+                    append.length,
+                    append.failed_secondaries.join(",")
+                )
+            })
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.appends.is_empty()
+    }
+
+    /// The append with this ID, if it is still in the journal
+    pub fn find(&self, append_id: &str) -> Option<&JournaledAppend> {
+        self.appends
+            .iter()
+            .find(|append| append.append_id == append_id)
+    }
+
+    /// Forget the appends applied `retention` or longer before `now` (milliseconds since UNIX
+    /// epoch)
+    pub fn expire(&mut self, now: u64, retention: Duration) {
+        let cutoff = now.saturating_sub(retention.as_millis() as u64);
+        self.appends.retain(|append| append.appended_at > cutoff);
+    }
+
+    /// Add an append, then forget the oldest ones beyond `capacity`
+    pub fn record(&mut self, append: JournaledAppend, capacity: usize) {
+        self.appends.push_back(append);
+        while self.appends.len() > capacity {
+            self.appends.pop_front();
+        }
+    }
+
+    /// Forget the appends ending past `length`, whose data a truncation cut off
+    pub fn truncate(&mut self, length: u64) {
+        self.appends
+            .retain(|append| append.offset + append.length <= length);
+    }
+}
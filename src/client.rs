@@ -0,0 +1,2515 @@
+// Library client for uploading, reading, appending and deleting RustFS files
+use rand::seq::SliceRandom;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::io::Write;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::Mutex;
+use tokio::task::JoinSet;
+use tokio::time::{Duration, Instant};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::Channel;
+use tonic::{Request, Response, Status};
+use tracing::{debug, error, info, warn};
+use uuid::Uuid;
+
+use crate::chunkserver_service::TRANSFER_FRAME_SIZE;
+use crate::config::{load_config, ClientConfig, CommonConfig, Config};
+use crate::encryption::{self, Encryption, FileKey};
+use crate::error::{Error, Result};
+use crate::namespace::normalize_path;
+use crate::proto::chunk;
+use crate::proto::chunk::chunk_client::ChunkClient;
+use crate::proto::chunk::{
+    AppendRequest, AppendResponse, ChecksumChunkRequest, FileChunk, FileInfo, ReadRequest,
+    UploadRequest, UploadResponse, WriteAtRequest, WriteAtResponse,
+};
+use crate::proto::master::{
+    master_client::MasterClient, AssignNextChunkRequest, AssignRequest, AssignResponse,
+    AuthenticateRequest, AuthenticateResponse, ChunkInfo, ClusterStatusRequest,
+    ClusterStatusResponse, CopyFileRequest, CopyStatusRequest, CopyStatusResponse,
+    DecommissionRequest, DecommissionResponse, DeleteFileRequest, DeletePrefixRequest,
+    FailedReplica, FileChunkMappingRequest, FileInfoRequest, FileInfoResponse, ListEntry,
+    ListFilesRequest, QueryAuditRequest, QueryAuditResponse, RebalanceRequest,
+    RebalanceStatusResponse, RecordAppendRequest, ReloadConfigRequest, ReloadConfigResponse,
+    RenewTokenRequest, ReportBadReplicaRequest, ReportFailedReplicasRequest, SnapshotFileRequest,
+    TruncateFileRequest, UndeleteRequest, UsageRequest, UsageResponse,
+};
+use crate::request_id::{new_request_id, request_with_id};
+use crate::retry::{retry, RetryPolicy, Transient};
+use crate::throttle::RateLimiter;
+use crate::util::{connect_channel_within, connect_to_master, LEADER_ADDRESS_KEY};
+
+/// Time to wait for a chunk read when the master knows no live replica of some chunk
+const STALE_READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Times a chunk is sent again to a primary that received it corrupted
+const CORRUPT_UPLOAD_RETRIES: usize = 2;
+
+/// Time between polls of the master while waiting for a copy to finish
+const COPY_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Session tokens are renewed once less than this fraction of their lifetime is left
+const TOKEN_RENEWAL_DIVISOR: u64 = 5;
+
+/// Period in which a client reports at most `MAX_BAD_REPLICA_REPORTS` unreadable replicas to
+/// the master, see `report_bad_replicas`
+const BAD_REPLICA_REPORT_PERIOD: Duration = Duration::from_secs(60);
+const MAX_BAD_REPLICA_REPORTS: usize = 10;
+
+/// Session token issued by the master, sent with every chunkserver request
+#[derive(Clone, Debug)]
+struct SessionToken {
+    token: String,
+    expires_at: u64, // Seconds since UNIX epoch
+    lifetime: u64,   // Seconds the token was valid for when it was received
+}
+
+impl SessionToken {
+    fn new(response: AuthenticateResponse) -> Self {
+        let expires_at = response.expiration_time.max(0) as u64;
+        SessionToken {
+            token: response.token,
+            expires_at,
+            lifetime: expires_at.saturating_sub(unix_now()),
+        }
+    }
+
+    /// Whether less than 1/`TOKEN_RENEWAL_DIVISOR` of the lifetime is left
+    fn needs_renewal(&self, now: u64) -> bool {
+        self.expires_at.saturating_sub(now) * TOKEN_RENEWAL_DIVISOR <= self.lifetime
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Chunk list of a file kept by the client for `chunk_cache_ttl`
+#[derive(Clone, Debug)]
+struct CachedChunks {
+    chunks: Vec<ChunkInfo>,
+    fetched_at: Instant,
+    ordered_by_load: bool, // See `Client::replicas_ordered`
+}
+
+/// Chunk list of a file being read, see `Client::read_listed`
+struct ListedChunks {
+    file_name: String,
+    chunks: Vec<ChunkInfo>,
+    cached: bool, // Taken from the cache instead of fetched from the master
+}
+
+impl ListedChunks {
+    fn fetched(file_name: &str, chunks: Vec<ChunkInfo>) -> Self {
+        ListedChunks {
+            file_name: file_name.to_string(),
+            chunks,
+            cached: false,
+        }
+    }
+
+    fn chunk_ids(&self) -> Vec<String> {
+        self.chunks
+            .iter()
+            .map(|chunk| chunk.chunk_id.clone())
+            .collect()
+    }
+
+    /// The chunk `chunk_id`, missing if the file was replaced since the read started
+    fn find(&self, chunk_id: &str) -> Result<ChunkInfo> {
+        self.chunks
+            .iter()
+            .find(|chunk| chunk.chunk_id == chunk_id)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(self.file_name.clone()))
+    }
+}
+
+/// Lookups in the client's cache of chunk lists, see `chunk_cache_ttl`
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct ChunkCacheStats {
+    pub hits: u64,
+    pub misses: u64, // Including lists fetched again after their replicas failed
+}
+
+/// Unreadable replicas reported to the master in the current period, see `report_bad_replicas`
+#[derive(Debug, Default)]
+struct BadReplicaReports {
+    period_start: Option<Instant>,
+    reported: HashSet<(String, String)>, // (chunk ID, server address)
+}
+
+impl BadReplicaReports {
+    /// Whether a replica may be reported now, recording it if so. Each replica is reported once
+    /// per period, and no more than `MAX_BAD_REPLICA_REPORTS` replicas.
+    fn admit(&mut self, chunk_id: &str, server_address: &str) -> bool {
+        let now = Instant::now();
+        if self
+            .period_start
+            .is_none_or(|start| now.duration_since(start) >= BAD_REPLICA_REPORT_PERIOD)
+        {
+            self.period_start = Some(now);
+            self.reported.clear();
+        }
+        self.reported.len() < MAX_BAD_REPLICA_REPORTS
+            && self
+                .reported
+                .insert((chunk_id.to_string(), server_address.to_string()))
+    }
+}
+
+/// Key of a file in the chunk list cache, so that `a.txt` and `/a.txt` share an entry
+fn cache_key(file_name: &str) -> String {
+    normalize_path(file_name).unwrap_or_else(|_| file_name.to_string())
+}
+
+/// Outcome of an upload, see `Client::upload`
+#[derive(Clone, Debug, Serialize)]
+pub struct UploadSummary {
+    pub file_name: String, // Name the file was stored as, may differ from the requested one
+    pub bytes: u64,
+    pub chunks: Vec<ChunkUpload>,
+    pub replication_factor: u64,
+}
+
+impl UploadSummary {
+    /// Number of chunks acknowledged by fewer than `replication_factor` replicas
+    pub fn degraded_chunks(&self) -> usize {
+        self.chunks
+            .iter()
+            .filter(|chunk| (chunk.replicas.len() as u64) < self.replication_factor)
+            .count()
+    }
+}
+
+/// Replicas that stored one chunk of an upload
+#[derive(Clone, Debug, Serialize)]
+pub struct ChunkUpload {
+    pub index: u64,
+    pub chunk_id: String,
+    pub bytes: u64,
+    pub replicas: Vec<String>, // Servers that acknowledged the chunk, the primary first
+    #[serde(skip)]
+    pub failed_replicas: Vec<String>, // Assigned servers that did not store the chunk
+    #[serde(skip)]
+    pub elapsed: Duration, // Time to store the chunk, including retries and other primaries
+}
+
+/// Bytes written by a read and their CRC32, see `Client::read_range_to`
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ReadSummary {
+    pub bytes: u64,
+    pub crc32: u32,
+    #[serde(skip)]
+    pub chunks: Vec<ChunkRead>, // In the order they were read
+}
+
+/// One chunk of a read
+#[derive(Clone, Debug)]
+pub struct ChunkRead {
+    pub index: u64,
+    pub chunk_id: String,
+    pub bytes: u64,
+    pub failed_replicas: Vec<String>, // Servers that failed to serve the chunk before one did
+    pub elapsed: Duration,            // Time to read the chunk, including retries
+}
+
+/// Writer passing data on to another one, counting it and computing its CRC32 on the way
+struct SummingWriter<'a, W: Write> {
+    out: &'a mut W,
+    hasher: crc32fast::Hasher,
+    bytes: u64,
+}
+
+impl<'a, W: Write> SummingWriter<'a, W> {
+    fn new(out: &'a mut W) -> Self {
+        SummingWriter {
+            out,
+            hasher: crc32fast::Hasher::new(),
+            bytes: 0,
+        }
+    }
+
+    fn summary(self, chunks: Vec<ChunkRead>) -> ReadSummary {
+        ReadSummary {
+            chunks,
+            bytes: self.bytes,
+            crc32: self.hasher.finalize(),
+        }
+    }
+}
+
+impl<W: Write> Write for SummingWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.out.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.bytes += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Checksum and length of one replica of a chunk, as computed by its chunkserver
+#[derive(Debug)]
+pub struct ReplicaChecksum {
+    pub server_address: String,
+    pub checksum: Result<(u32, u64)>, // CRC32 and length, or why the replica could not be checked
+    pub corrupt: bool, // The replica no longer matches the checksum stored when it was written
+}
+
+/// Checksums of all replicas of a chunk, see `Client::verify_file`
+#[derive(Debug)]
+pub struct ChunkVerification {
+    pub chunk_id: String,
+    pub replicas: Vec<ReplicaChecksum>,
+}
+
+impl ChunkVerification {
+    /// CRC32 and length shared by the most intact replicas, together with their number.
+    /// `None` if no replica could be checked or the largest groups are tied.
+    pub fn agreement(&self) -> Option<((u32, u64), usize)> {
+        let mut counts: HashMap<(u32, u64), usize> = HashMap::new();
+        for replica in &self.replicas {
+            if let (Ok(checksum), false) = (&replica.checksum, replica.corrupt) {
+                *counts.entry(*checksum).or_default() += 1;
+            }
+        }
+        let mut counts: Vec<_> = counts.into_iter().collect();
+        counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        match counts.as_slice() {
+            [first, second, ..] if first.1 == second.1 => None,
+            [first, ..] => Some(*first),
+            [] => None,
+        }
+    }
+
+    /// Whether a majority of `replication_factor` replicas agree on the chunk's contents
+    pub fn has_quorum(&self, replication_factor: usize) -> bool {
+        self.agreement()
+            .is_some_and(|(_, count)| count > replication_factor / 2)
+    }
+}
+
+/// Client connected to the RustFS master, used to perform file operations
+pub struct Client {
+    common_config: CommonConfig,
+    master_client: MasterClient<Channel>,
+    session: Mutex<Option<SessionToken>>, // Set by `authenticate`, renewed by `session_token`
+    epoch: u64, // Highest leader epoch received from a master, sent along with writes
+    possibly_stale: bool, // The last chunk list contained chunks without a replica known to be alive
+    replicas_ordered: bool, // The master ordered the replicas of the last chunk list least loaded first
+    request_id: String,     // Sent with every request, so the logs of all nodes can be correlated
+    retry_policy: RetryPolicy, // Retries of RPCs failing for a transient reason
+    client_config: ClientConfig, // Timeouts of connections and RPCs
+    upload_limiter: Arc<RateLimiter>, // Shared by all chunks uploaded at once, see `max_upload_bytes_per_sec`
+    download_limiter: Arc<RateLimiter>, // Shared by all chunks read at once, see `max_download_bytes_per_sec`
+    chunk_cache: HashMap<String, CachedChunks>, // Normalized file name -> Its chunk list, see `chunk_cache_ttl`
+    chunk_cache_stats: ChunkCacheStats,
+    encryption: Option<Encryption>, // Set by `encryption_key_file`, see `seal_chunks`
+    bad_replica_reports: std::sync::Mutex<BadReplicaReports>, // See `report_bad_replicas`
+    pending_reports: std::sync::Mutex<JoinSet<()>>, // Reports not answered yet, see `finish_reports`
+}
+
+impl Client {
+    /// Load the configuration file and connect to the first reachable master
+    pub async fn new(config_path: &str) -> Result<Self> {
+        Self::from_config(load_config(config_path)?).await
+    }
+
+    /// Connect to the first reachable master of an already loaded configuration
+    pub async fn from_config(config: Config) -> Result<Self> {
+        let common_config: CommonConfig = config.common;
+        let retry_policy = RetryPolicy::new(&config.client);
+        let encryption = match config.client.encryption_key_file.as_str() {
+            "" => None,
+            key_file => Some(Encryption::load(key_file)?),
+        };
+
+        let master_client = connect_to_master(
+            &common_config.master_addrs,
+            &common_config.tls,
+            &retry_policy,
+            Some(config.client.connect_timeout),
+        )
+        .await?;
+
+        Ok(Client {
+            common_config,
+            master_client,
+            session: Mutex::new(None),
+            epoch: 0,
+            possibly_stale: false,
+            replicas_ordered: false,
+            request_id: new_request_id(),
+            retry_policy,
+            upload_limiter: Arc::new(RateLimiter::new(config.client.max_upload_bytes_per_sec)),
+            download_limiter: Arc::new(RateLimiter::new(config.client.max_download_bytes_per_sec)),
+            client_config: config.client,
+            chunk_cache: HashMap::new(),
+            chunk_cache_stats: ChunkCacheStats::default(),
+            encryption,
+            bad_replica_reports: std::sync::Mutex::new(BadReplicaReports::default()),
+            pending_reports: std::sync::Mutex::new(JoinSet::new()),
+        })
+    }
+
+    /// ID sent with the requests of the current operation
+    pub fn request_id(&self) -> &str {
+        &self.request_id
+    }
+
+    /// Start a new logical operation, whose requests are sent with a new ID
+    pub fn start_operation(&mut self) -> &str {
+        self.request_id = new_request_id();
+        &self.request_id
+    }
+
+    /// Wrap `message` in a master request carrying the ID of the current operation
+    fn request<M>(&self, message: M) -> Request<M> {
+        let mut request = request_with_id(message, &self.request_id);
+        request.set_timeout(self.client_config.master_rpc_timeout);
+        request
+    }
+
+    /// Wrap `message` in a chunkserver request carrying the ID of the current operation
+    fn chunk_request<M>(&self, message: M) -> Request<M> {
+        let mut request = request_with_id(message, &self.request_id);
+        request.set_timeout(self.client_config.chunk_rpc_timeout);
+        request
+    }
+
+    /// Open a channel to a master or chunkserver, giving up after the connect timeout
+    async fn connect(&self, addr: &str) -> Result<Channel> {
+        connect_channel_within(
+            addr,
+            &self.common_config.tls,
+            Some(self.client_config.connect_timeout),
+        )
+        .await
+    }
+
+    /// Whether the cluster requires authentication before file operations
+    pub fn use_authentication(&self) -> bool {
+        self.common_config.use_authentication
+    }
+
+    /// Authenticate with the master and store the session token used for subsequent chunkserver
+    /// requests
+    pub async fn authenticate(&mut self, username: &str, password: &str) -> Result<()> {
+        if !self.common_config.use_authentication {
+            info!("Authentication is disabled. Skipping token request.");
+            return Ok(());
+        }
+        info!("Authenticating user: {}", username);
+
+        // Create and send the authentication request to the master server
+        let response = self
+            .master_client
+            .authenticate(self.request(AuthenticateRequest {
+                username: username.to_string(),
+                password: password.to_string(),
+            }))
+            .await;
+
+        match response {
+            Ok(response) => {
+                *self.session.lock().await = Some(SessionToken::new(response.into_inner()));
+
+                info!(
+                    "User '{}' authenticated successfully. Session token received and stored.",
+                    username
+                );
+                Ok(())
+            }
+            Err(err) => {
+                // Log the specific error and re-throw it
+                error!(
+                    "Authentication failed for user '{}': {}",
+                    username,
+                    err.message()
+                );
+                Err(Error::Auth(err.message().to_string()))
+            }
+        }
+    }
+
+    /// Session token to send with the next chunkserver request, renewed first if it is about to
+    /// expire. Empty without authentication.
+    async fn session_token(&self) -> Result<String> {
+        let mut session = self.session.lock().await;
+        match session.as_ref() {
+            None => Ok(String::new()),
+            Some(current) if !current.needs_renewal(unix_now()) => Ok(current.token.clone()),
+            Some(current) => {
+                let renewed = self.renew_token(&current.token).await?;
+                let token = renewed.token.clone();
+                *session = Some(renewed);
+                Ok(token)
+            }
+        }
+    }
+
+    /// Replace a session token a chunkserver rejected, unless it was already renewed meanwhile
+    async fn replace_session_token(&self, rejected: &str) -> Result<String> {
+        let mut session = self.session.lock().await;
+        match session.as_ref() {
+            Some(current) if current.token != rejected => Ok(current.token.clone()),
+            _ => {
+                let renewed = self.renew_token(rejected).await?;
+                let token = renewed.token.clone();
+                *session = Some(renewed);
+                Ok(token)
+            }
+        }
+    }
+
+    async fn renew_token(&self, token: &str) -> Result<SessionToken> {
+        debug!("Renewing the session token");
+        let response = self
+            .master_client
+            .clone()
+            .renew_token(self.request(RenewTokenRequest {
+                token: token.to_string(),
+            }))
+            .await
+            .map_err(|status| {
+                Error::Auth(format!(
+                    "Failed to renew the session token: {}",
+                    status.message()
+                ))
+            })?;
+        Ok(SessionToken::new(response.into_inner()))
+    }
+
+    /// Send a chunkserver request with the session token. If the chunkserver rejects the token,
+    /// e.g. as it expired during a long upload, renew it and send the request once more.
+    async fn with_session_token<T, F, Fut>(&self, call: F) -> Result<T>
+    where
+        F: Fn(String) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let token = self.session_token().await?;
+        match call(token.clone()).await {
+            Err(Error::Rpc(status))
+                if status.code() == tonic::Code::Unauthenticated && !token.is_empty() =>
+            {
+                warn!(
+                    "Chunkserver rejected the session token ({}), renewing it",
+                    status.message()
+                );
+                call(self.replace_session_token(&token).await?).await
+            }
+            result => result,
+        }
+    }
+
+    /// Send a mutating request to the master, retrying once at the leader if the master
+    /// rejected it as a shadow
+    async fn on_leader<M, T, F, Fut>(
+        &mut self,
+        message: M,
+        call: F,
+    ) -> std::result::Result<Response<T>, Status>
+    where
+        M: Clone,
+        F: Fn(MasterClient<Channel>, Request<M>) -> Fut,
+        Fut: Future<Output = std::result::Result<Response<T>, Status>>,
+    {
+        let request = self.request(message.clone());
+        match call(self.master_client.clone(), request).await {
+            Err(status) if self.follow_leader(&status).await => {
+                call(self.master_client.clone(), self.request(message)).await
+            }
+            result => result,
+        }
+    }
+
+    /// Send a request to the master like `on_leader`. If it fails for a transient reason, e.g. as
+    /// the leader just went down, connect to the masters again and retry as the retry policy
+    /// allows. Only for requests that can safely be applied twice.
+    async fn on_leader_retrying<M, T, F, Fut>(
+        &mut self,
+        operation: &str,
+        message: M,
+        call: F,
+    ) -> std::result::Result<Response<T>, Status>
+    where
+        M: Clone,
+        F: Fn(MasterClient<Channel>, Request<M>) -> Fut,
+        Fut: Future<Output = std::result::Result<Response<T>, Status>>,
+    {
+        let retry_policy = self.retry_policy.clone();
+        let mut backoff = retry_policy.backoff();
+        loop {
+            let status = match self.on_leader(message.clone(), &call).await {
+                Err(status) if status.is_transient() => status,
+                result => return result,
+            };
+            if !backoff.wait(operation, &status).await {
+                return Err(status);
+            }
+            match connect_to_master(
+                &self.common_config.master_addrs,
+                &self.common_config.tls,
+                &RetryPolicy::never(),
+                Some(self.client_config.connect_timeout),
+            )
+            .await
+            {
+                Ok(master_client) => self.master_client = master_client,
+                Err(e) => debug!("Failed to reconnect to the masters: {}", e),
+            }
+        }
+    }
+
+    /// Reconnect to the leader named in a rejection from a shadow master.
+    ///
+    /// Returns false if `status` is not such a rejection or the leader is unreachable.
+    async fn follow_leader(&mut self, status: &Status) -> bool {
+        if status.code() != tonic::Code::FailedPrecondition {
+            return false;
+        }
+        let Some(leader) = status
+            .metadata()
+            .get(LEADER_ADDRESS_KEY)
+            .and_then(|value| value.to_str().ok())
+        else {
+            return false;
+        };
+
+        match self.connect(leader).await {
+            Ok(channel) => {
+                info!("Master is not the leader, retrying at {}", leader);
+                self.master_client = MasterClient::new(channel);
+                true
+            }
+            Err(e) => {
+                warn!("Failed to connect to leader at {}: {}", leader, e);
+                false
+            }
+        }
+    }
+
+    /// Fetch the chunk list of a file from the master. With `for_append`, the master grants a
+    /// lease on the last chunk and on those at the indexes in `lease_chunks`.
+    async fn get_file_chunks(
+        &mut self,
+        file_name: &str,
+        for_append: bool,
+        lease_chunks: &[u64],
+    ) -> Result<Vec<ChunkInfo>> {
+        // Only appends and writes are restricted to the owner of the file
+        let token = if for_append {
+            self.session_token().await?
+        } else {
+            String::new()
+        };
+        let response = self
+            .on_leader_retrying(
+                "Fetching the chunks of a file",
+                FileChunkMappingRequest {
+                    file_name: file_name.to_string(),
+                    for_append,
+                    token,
+                    lease_chunks: lease_chunks.to_vec(),
+                },
+                |mut master, request| async move { master.get_file_chunks(request).await },
+            )
+            .await
+            .map_err(|status| match status.code() {
+                tonic::Code::NotFound => Error::NotFound(file_name.to_string()),
+                _ => status.into(),
+            })?
+            .into_inner();
+        if response.from_shadow {
+            warn!(
+                "Chunks of '{}' were listed by a shadow master and may be outdated",
+                file_name
+            );
+        }
+        self.epoch = self.epoch.max(response.epoch);
+        self.possibly_stale = response.possibly_stale;
+        self.replicas_ordered = response.ordered_by_load;
+        if response.possibly_stale {
+            warn!(
+                "Some chunks of '{}' have no replica known to be alive, reads may fail",
+                file_name
+            );
+        }
+
+        Ok(response.chunks)
+    }
+
+    /// Chunk list of a file to read, from the cache if it was fetched less than
+    /// `chunk_cache_ttl` ago, else from the master
+    async fn list_chunks(&mut self, file_name: &str) -> Result<ListedChunks> {
+        let ttl = self.client_config.chunk_cache_ttl;
+        if ttl.is_zero() {
+            let chunks = self.get_file_chunks(file_name, false, &[]).await?;
+            return Ok(ListedChunks::fetched(file_name, chunks));
+        }
+        let key = cache_key(file_name);
+        if let Some(entry) = self.chunk_cache.get(&key) {
+            if entry.fetched_at.elapsed() < ttl {
+                self.chunk_cache_stats.hits += 1;
+                // Lists with possibly dead replicas are never cached
+                self.possibly_stale = false;
+                self.replicas_ordered = entry.ordered_by_load;
+                return Ok(ListedChunks {
+                    file_name: file_name.to_string(),
+                    chunks: entry.chunks.clone(),
+                    cached: true,
+                });
+            }
+        }
+        self.chunk_cache_stats.misses += 1;
+        let chunks = self.get_file_chunks(file_name, false, &[]).await?;
+        self.chunk_cache
+            .retain(|_, entry| entry.fetched_at.elapsed() < ttl);
+        if self.possibly_stale {
+            self.chunk_cache.remove(&key);
+        } else {
+            self.chunk_cache.insert(
+                key,
+                CachedChunks {
+                    chunks: chunks.clone(),
+                    fetched_at: Instant::now(),
+                    ordered_by_load: self.replicas_ordered,
+                },
+            );
+        }
+        Ok(ListedChunks::fetched(file_name, chunks))
+    }
+
+    /// Drop the cached chunk list of a file, as it changed or its replicas failed
+    fn invalidate_chunks(&mut self, file_name: &str) {
+        self.chunk_cache.remove(&cache_key(file_name));
+    }
+
+    /// Hits and misses of the chunk list cache since the client was created
+    pub fn chunk_cache_stats(&self) -> ChunkCacheStats {
+        self.chunk_cache_stats
+    }
+
+    /// Fetch the chunk layout, total size and replica health of a file
+    pub async fn get_file_info(&mut self, file_name: &str) -> Result<FileInfoResponse> {
+        let response = self
+            .on_leader_retrying(
+                "Fetching file info",
+                FileInfoRequest {
+                    file_name: file_name.to_string(),
+                },
+                |mut master, request| async move { master.get_file_info(request).await },
+            )
+            .await
+            .map_err(|status| match status.code() {
+                tonic::Code::NotFound => Error::NotFound(file_name.to_string()),
+                _ => status.into(),
+            })?;
+
+        Ok(response.into_inner())
+    }
+
+    /// Select the primary of each chunk for write operations.
+    ///
+    /// Returns `(chunk_id, server_address)` pairs in chunk order.
+    pub async fn get_primary_server_addresses(
+        &mut self,
+        file_name: &str,
+    ) -> Result<Vec<(String, String)>> {
+        let chunk_info_list = self.get_file_chunks(file_name, false, &[]).await?;
+        let server_addresses: Vec<(String, String)> = chunk_info_list
+            .iter()
+            .filter_map(|chunk| {
+                primary_first(chunk)
+                    .into_iter()
+                    .next()
+                    .map(|server| (chunk.chunk_id.clone(), server))
+            })
+            .collect();
+
+        if server_addresses.is_empty() {
+            return Err(Error::NoAvailableServers(
+                "No available chunk servers for the file".to_string(),
+            ));
+        }
+
+        Ok(server_addresses)
+    }
+
+    /// Retrieves all server addresses for each chunk of the specified file.
+    ///
+    /// Returns `(chunk_id, server_addresses)` pairs in chunk order, where `server_addresses`
+    /// contains every replica of that chunk.
+    pub async fn get_all_server_addresses(
+        &mut self,
+        file_name: &str,
+    ) -> Result<Vec<(String, Vec<String>)>> {
+        let chunk_info_list = self.get_file_chunks(file_name, false, &[]).await?;
+        let all_server_addresses: Vec<(String, Vec<String>)> = chunk_info_list
+            .into_iter()
+            .map(|chunk| (chunk.chunk_id, chunk.server_addresses))
+            .collect();
+
+        if all_server_addresses.is_empty() {
+            return Err(Error::NoAvailableServers(
+                "No available chunk servers for the file".to_string(),
+            ));
+        }
+
+        Ok(all_server_addresses)
+    }
+
+    /// Retrieves the server addresses for each chunk of the specified file like
+    /// `get_all_server_addresses`, with the master granting a lease on the last chunk. The
+    /// primary of each chunk is listed first.
+    pub async fn get_append_server_addresses(
+        &mut self,
+        file_name: &str,
+    ) -> Result<Vec<(String, Vec<String>)>> {
+        self.invalidate_chunks(file_name);
+        let chunk_info_list = self.get_file_chunks(file_name, true, &[]).await?;
+        match chunk_info_list.last() {
+            Some(chunk) => {
+                debug!(
+                    "Primary of chunk {} is {} until {}",
+                    chunk.chunk_id, chunk.primary, chunk.lease_expiry
+                );
+            }
+            None => {
+                return Err(Error::NoAvailableServers(
+                    "No available chunk servers for the file".to_string(),
+                ))
+            }
+        }
+
+        Ok(chunk_info_list
+            .iter()
+            .map(|chunk| (chunk.chunk_id.clone(), primary_first(chunk)))
+            .collect())
+    }
+
+    /// Ask the master to assign chunks for a new file.
+    ///
+    /// The returned file name may differ from `file_name` if the name was already taken.
+    pub async fn assign_chunks(
+        &mut self,
+        file_name: &str,
+        file_size: u64,
+    ) -> Result<AssignResponse> {
+        let token = self.session_token().await?;
+        let response = self
+            .on_leader_retrying(
+                "Assigning chunks",
+                AssignRequest {
+                    file_name: file_name.to_string(),
+                    file_size,
+                    token,
+                    // Lets the master recognize a retry of an assignment whose response was lost
+                    operation_id: Uuid::new_v4().to_string(),
+                },
+                |mut master, request| async move { master.assign_chunks(request).await },
+            )
+            .await
+            .map_err(|status| match status.code() {
+                tonic::Code::ResourceExhausted => {
+                    Error::QuotaExceeded(status.message().to_string())
+                }
+                _ => status.into(),
+            })?
+            .into_inner();
+        self.epoch = self.epoch.max(response.epoch);
+        self.invalidate_chunks(&response.file_name);
+        Ok(response)
+    }
+
+    /// Add a chunk of `size` bytes at the end of `file_name`, see `upload_stream`
+    pub async fn assign_next_chunk(
+        &mut self,
+        file_name: &str,
+        size: u64,
+    ) -> Result<AssignResponse> {
+        let token = self.session_token().await?;
+        let response = self
+            .on_leader(
+                AssignNextChunkRequest {
+                    file_name: file_name.to_string(),
+                    size,
+                    token,
+                },
+                |mut master, request| async move { master.assign_next_chunk(request).await },
+            )
+            .await
+            .map_err(|status| match status.code() {
+                tonic::Code::ResourceExhausted => {
+                    Error::QuotaExceeded(status.message().to_string())
+                }
+                _ => status.into(),
+            })?
+            .into_inner();
+        self.epoch = self.epoch.max(response.epoch);
+        self.invalidate_chunks(&response.file_name);
+        Ok(response)
+    }
+
+    /// Fetch the storage used by all files and the configured quotas
+    pub async fn get_usage(&mut self) -> Result<UsageResponse> {
+        let response = self
+            .master_client
+            .get_usage(self.request(UsageRequest {}))
+            .await?;
+        Ok(response.into_inner())
+    }
+
+    /// Start draining a chunkserver so it can be removed, and return the drain progress
+    pub async fn decommission_server(&mut self, address: &str) -> Result<DecommissionResponse> {
+        let token = self.session_token().await?;
+        let response = self
+            .on_leader(
+                DecommissionRequest {
+                    address: address.to_string(),
+                    token,
+                },
+                |mut master, request| async move { master.decommission_server(request).await },
+            )
+            .await?;
+        Ok(response.into_inner())
+    }
+
+    /// Fetch the progress of draining a decommissioned chunkserver
+    pub async fn decommission_status(&mut self, address: &str) -> Result<DecommissionResponse> {
+        let token = self.session_token().await?;
+        let response = self
+            .master_client
+            .decommission_status(self.request(DecommissionRequest {
+                address: address.to_string(),
+                token,
+            }))
+            .await?;
+        Ok(response.into_inner())
+    }
+
+    /// Start moving chunks from the most to the least loaded chunkservers
+    pub async fn trigger_rebalance(&mut self) -> Result<RebalanceStatusResponse> {
+        let token = self.session_token().await?;
+        let response = self
+            .on_leader(
+                RebalanceRequest { token },
+                |mut master, request| async move { master.trigger_rebalance(request).await },
+            )
+            .await?;
+        Ok(response.into_inner())
+    }
+
+    /// Fetch the progress of the current or last rebalance
+    pub async fn rebalance_status(&mut self) -> Result<RebalanceStatusResponse> {
+        let token = self.session_token().await?;
+        let response = self
+            .master_client
+            .rebalance_status(self.request(RebalanceRequest { token }))
+            .await?;
+        Ok(response.into_inner())
+    }
+
+    /// Fetch an overview of the masters, chunkservers and, with `include_files`, the files of the
+    /// cluster
+    pub async fn cluster_status(&mut self, include_files: bool) -> Result<ClusterStatusResponse> {
+        let token = self.session_token().await?;
+        let response = self
+            .master_client
+            .cluster_status(self.request(ClusterStatusRequest {
+                include_files,
+                token,
+            }))
+            .await?;
+        Ok(response.into_inner())
+    }
+
+    /// Have a master re-read its config file and apply the settings that can change without a
+    /// restart, the connected master unless `master_addr` names another one
+    pub async fn reload_config(
+        &mut self,
+        master_addr: Option<&str>,
+    ) -> Result<ReloadConfigResponse> {
+        let token = self.session_token().await?;
+        let request = self.request(ReloadConfigRequest { token });
+        let response = match master_addr {
+            Some(addr) => {
+                MasterClient::new(self.connect(addr).await?)
+                    .reload_config(request)
+                    .await?
+            }
+            None => self.master_client.reload_config(request).await?,
+        };
+        Ok(response.into_inner())
+    }
+
+    /// Fetch the most recent `limit` audit log entries about `file_name` or the files below it,
+    /// recorded at or after the Unix time `since`
+    pub async fn query_audit(
+        &mut self,
+        file_name: &str,
+        since: u64,
+        limit: u32,
+    ) -> Result<QueryAuditResponse> {
+        let token = self.session_token().await?;
+        let response = self
+            .on_leader(
+                QueryAuditRequest {
+                    file_name: file_name.to_string(),
+                    since,
+                    limit,
+                    token,
+                },
+                |mut master, request| async move { master.query_audit(request).await },
+            )
+            .await?;
+        Ok(response.into_inner())
+    }
+
+    /// Delete a file.
+    ///
+    /// The master removes its metadata and has the chunkservers delete the chunks on their next
+    /// heartbeat, so replicas that are currently unreachable are cleaned up once they return.
+    pub async fn delete_file(&mut self, file_name: &str) -> Result<()> {
+        self.invalidate_chunks(file_name);
+        let token = self.session_token().await?;
+        let response = self
+            .on_leader_retrying(
+                "Deleting a file",
+                DeleteFileRequest {
+                    file_name: file_name.to_string(),
+                    token,
+                    operation_id: Uuid::new_v4().to_string(),
+                },
+                |mut master, request| async move { master.delete_file(request).await },
+            )
+            .await?
+            .into_inner();
+        if !response.success {
+            return Err(Error::NotFound(file_name.to_string()));
+        }
+        info!("{}", response.message);
+        Ok(())
+    }
+
+    /// Shorten a file to `new_length` bytes and return the number of chunks deleted from it.
+    ///
+    /// Files cannot be extended this way. Refused with encryption, like appends, as the last
+    /// remaining chunk would no longer decrypt.
+    pub async fn truncate_file(&mut self, file_name: &str, new_length: u64) -> Result<u64> {
+        if self.encryption.is_some() {
+            return Err(Error::Usage(format!(
+                "Cannot truncate '{}': truncating is not supported with client.encryption_key_file set, as encrypted chunks can only be written whole",
+                file_name
+            )));
+        }
+        self.invalidate_chunks(file_name);
+        let token = self.session_token().await?;
+        let response = self
+            .on_leader_retrying(
+                "Truncating a file",
+                TruncateFileRequest {
+                    file_name: file_name.to_string(),
+                    new_length,
+                    token,
+                },
+                |mut master, request| async move { master.truncate_file(request).await },
+            )
+            .await
+            .map_err(|status| match status.code() {
+                tonic::Code::NotFound => Error::NotFound(file_name.to_string()),
+                _ => status.into(),
+            })?
+            .into_inner();
+        info!("{}", response.message);
+        Ok(response.dropped_chunks)
+    }
+
+    /// Restore a file from the trash and return the name it was restored as
+    pub async fn undelete_file(&mut self, file_name: &str) -> Result<String> {
+        let token = self.session_token().await?;
+        let response = self
+            .on_leader(
+                UndeleteRequest {
+                    file_name: file_name.to_string(),
+                    token,
+                },
+                |mut master, request| async move { master.undelete(request).await },
+            )
+            .await
+            .map_err(|status| match status.code() {
+                tonic::Code::NotFound => Error::NotFound(file_name.to_string()),
+                _ => status.into(),
+            })?
+            .into_inner();
+        info!("{}", response.message);
+        self.invalidate_chunks(&response.file_name);
+        Ok(response.file_name)
+    }
+
+    /// Copy `source` to the new file `destination` and wait until the copy is complete.
+    ///
+    /// The chunkservers copy the chunks locally, so no data passes through the client.
+    pub async fn copy(&mut self, source: &str, destination: &str) -> Result<CopyStatusResponse> {
+        let mut status = self.copy_file(source, destination).await?;
+        while status.running {
+            debug!("{}", status.message);
+            tokio::time::sleep(COPY_POLL_INTERVAL).await;
+            status = self.copy_status(destination).await?;
+        }
+        if !status.error.is_empty() {
+            return Err(Error::CopyFailed(status.error));
+        }
+        info!("{}", status.message);
+        Ok(status)
+    }
+
+    /// Start copying `source` to the new file `destination` on the chunkservers, see `copy`
+    pub async fn copy_file(
+        &mut self,
+        source: &str,
+        destination: &str,
+    ) -> Result<CopyStatusResponse> {
+        self.invalidate_chunks(destination);
+        let token = self.session_token().await?;
+        let response = self
+            .on_leader(
+                CopyFileRequest {
+                    source: source.to_string(),
+                    destination: destination.to_string(),
+                    token,
+                },
+                |mut master, request| async move { master.copy_file(request).await },
+            )
+            .await
+            .map_err(|status| match status.code() {
+                tonic::Code::NotFound => Error::NotFound(source.to_string()),
+                tonic::Code::ResourceExhausted => {
+                    Error::QuotaExceeded(status.message().to_string())
+                }
+                _ => status.into(),
+            })?;
+        Ok(response.into_inner())
+    }
+
+    /// Create `snapshot_name` as a point-in-time copy of `source` and return the number of chunks
+    /// they share.
+    ///
+    /// No data is copied until either file changes a chunk, only that chunk is copied then.
+    pub async fn snapshot_file(&mut self, source: &str, snapshot_name: &str) -> Result<u64> {
+        self.invalidate_chunks(source);
+        self.invalidate_chunks(snapshot_name);
+        let token = self.session_token().await?;
+        let response = self
+            .on_leader(
+                SnapshotFileRequest {
+                    source: source.to_string(),
+                    snapshot_name: snapshot_name.to_string(),
+                    token,
+                },
+                |mut master, request| async move { master.snapshot_file(request).await },
+            )
+            .await
+            .map_err(|status| match status.code() {
+                tonic::Code::NotFound => Error::NotFound(source.to_string()),
+                tonic::Code::ResourceExhausted => {
+                    Error::QuotaExceeded(status.message().to_string())
+                }
+                _ => status.into(),
+            })?
+            .into_inner();
+        info!("{}", response.message);
+        Ok(response.shared_chunks)
+    }
+
+    /// Fetch the progress of the current or last copy to `destination`
+    pub async fn copy_status(&mut self, destination: &str) -> Result<CopyStatusResponse> {
+        let response = self
+            .on_leader(
+                CopyStatusRequest {
+                    destination: destination.to_string(),
+                },
+                |mut master, request| async move { master.copy_status(request).await },
+            )
+            .await?;
+        Ok(response.into_inner())
+    }
+
+    /// List the files and subdirectories of a directory
+    pub async fn list_files(&mut self, directory: &str) -> Result<Vec<ListEntry>> {
+        let response = self
+            .on_leader_retrying(
+                "Listing files",
+                ListFilesRequest {
+                    directory: directory.to_string(),
+                },
+                |mut master, request| async move { master.list_files(request).await },
+            )
+            .await
+            .map_err(|status| match status.code() {
+                tonic::Code::NotFound => Error::NotFound(directory.to_string()),
+                _ => status.into(),
+            })?;
+        Ok(response.into_inner().entries)
+    }
+
+    /// Delete all files below a directory and return their names
+    pub async fn delete_prefix(&mut self, directory: &str) -> Result<Vec<String>> {
+        self.chunk_cache.clear();
+        let token = self.session_token().await?;
+        let response = self
+            .on_leader_retrying(
+                "Deleting a directory",
+                DeletePrefixRequest {
+                    directory: directory.to_string(),
+                    token,
+                },
+                |mut master, request| async move { master.delete_prefix(request).await },
+            )
+            .await
+            .map_err(|status| match status.code() {
+                tonic::Code::NotFound => Error::NotFound(directory.to_string()),
+                _ => status.into(),
+            })?
+            .into_inner();
+        info!("{}", response.message);
+        Ok(response.deleted_files)
+    }
+
+    /// Bytes of data stored in chunk `index` of a new file, less than `chunk_size` if encrypted
+    fn chunk_capacity(&self, index: u64) -> u64 {
+        let chunk_size = self.common_config.chunk_size;
+        match self.encryption {
+            Some(_) => encryption::chunk_capacity(chunk_size, index),
+            None => chunk_size,
+        }
+    }
+
+    /// Bytes stored for a new file holding `size` bytes
+    fn stored_size(&self, size: u64) -> u64 {
+        match self.encryption {
+            Some(_) => encryption::encrypted_size(self.common_config.chunk_size, size),
+            None => size,
+        }
+    }
+
+    /// Key to encrypt a new file with, `None` unless `encryption_key_file` is set
+    fn new_file_key(&self) -> Result<Option<FileKey>> {
+        self.encryption
+            .as_ref()
+            .map(Encryption::new_file)
+            .transpose()
+    }
+
+    /// Key to decrypt `file_name` with, from the start of its first chunk
+    fn file_key(&self, file_name: &str, first_chunk: &[u8]) -> Result<FileKey> {
+        self.encryption
+            .as_ref()
+            .expect("only encrypted files are opened")
+            .open_file(file_name, first_chunk)
+    }
+
+    /// Upload the local file at `local_path` as `remote_name` and return the name it was stored
+    /// as, together with the replicas of each chunk.
+    ///
+    /// The master appends a suffix (`-1`, `-2`, ...) if `remote_name` is already taken.
+    pub async fn upload(&mut self, local_path: &str, remote_name: &str) -> Result<UploadSummary> {
+        let file_size = tokio::fs::metadata(local_path)
+            .await
+            .map_err(|e| {
+                error!("Failed to get metadata for file '{}': {}", local_path, e);
+                e
+            })?
+            .len();
+        debug!("File size: {} bytes", file_size);
+
+        info!("Requesting chunk assignment for file: {}", remote_name);
+        let assign_response = self
+            .assign_chunks(remote_name, self.stored_size(file_size))
+            .await?;
+        debug!(
+            "Got chunk assignment for file: {}",
+            assign_response.file_name
+        );
+
+        let chunks = self
+            .upload_file(assign_response.chunk_info_list, local_path.to_string())
+            .await?;
+        warn_if_degraded(
+            &assign_response.file_name,
+            assign_response.replicas,
+            assign_response.replication_factor,
+        );
+        Ok(UploadSummary {
+            file_name: assign_response.file_name,
+            bytes: file_size,
+            chunks,
+            replication_factor: assign_response.replication_factor,
+        })
+    }
+
+    /// Upload everything `reader` yields as `remote_name`, e.g. stdin, without knowing the size
+    /// upfront. Returns the name the file was stored as, like `upload`.
+    ///
+    /// Each chunk is assigned once `chunk_size` bytes were read, so a failed upload leaves the
+    /// file with the chunks uploaded before.
+    pub async fn upload_stream(
+        &mut self,
+        mut reader: impl AsyncRead + Unpin,
+        remote_name: &str,
+    ) -> Result<UploadSummary> {
+        let file_key = self.new_file_key()?;
+        let mut chunk = Vec::new();
+        (&mut reader)
+            .take(self.chunk_capacity(0))
+            .read_to_end(&mut chunk)
+            .await?;
+        // Every chunk is read ahead of its predecessor's upload, which is sealed knowing whether
+        // it is the last one
+        let mut next = Vec::new();
+        (&mut reader)
+            .take(self.chunk_capacity(1))
+            .read_to_end(&mut next)
+            .await?;
+        let mut bytes = chunk.len() as u64;
+        let mut stored = seal_chunk(file_key.as_ref(), 0, next.is_empty(), chunk)?;
+
+        info!("Requesting chunk assignment for file: {}", remote_name);
+        let assign_response = self.assign_chunks(remote_name, stored.len() as u64).await?;
+        let file_name = assign_response.file_name;
+        let mut replicas = assign_response.replicas;
+        let mut chunk_info = assign_response.chunk_info_list.into_iter().next();
+        let mut chunks = Vec::new();
+        let mut index = 0;
+        while let Some(info) = chunk_info.take() {
+            chunks.extend(self.upload_chunks(vec![(info, stored)]).await?);
+
+            if next.is_empty() {
+                break; // EOF
+            }
+            let chunk = std::mem::take(&mut next);
+            index += 1;
+            (&mut reader)
+                .take(self.chunk_capacity(index + 1))
+                .read_to_end(&mut next)
+                .await?;
+            bytes += chunk.len() as u64;
+            stored = seal_chunk(file_key.as_ref(), index, next.is_empty(), chunk)?;
+            let next = self
+                .assign_next_chunk(&file_name, stored.len() as u64)
+                .await?;
+            replicas = replicas.min(next.replicas);
+            chunk_info = next.chunk_info_list.into_iter().next();
+        }
+        info!("File upload completed successfully.");
+        warn_if_degraded(&file_name, replicas, assign_response.replication_factor);
+        Ok(UploadSummary {
+            file_name,
+            bytes,
+            chunks,
+            replication_factor: assign_response.replication_factor,
+        })
+    }
+
+    /// Split the local file into chunks and upload each chunk to the servers in `chunk_info_list`
+    pub async fn upload_file(
+        &mut self,
+        chunk_info_list: Vec<ChunkInfo>,
+        local_path: String,
+    ) -> Result<Vec<ChunkUpload>> {
+        debug!("Attempting to open file: {}", local_path);
+        let mut file = File::open(&local_path).await.map_err(|e| {
+            error!("Failed to open file '{}': {}", local_path, e);
+            e
+        })?;
+
+        // Separate the file into chunks
+        // (a single read may return less than a chunk, so read until each chunk is full)
+        let file_key = self.new_file_key()?;
+        let mut chunks = Vec::new();
+        loop {
+            let mut buf = Vec::new();
+            let capacity = self.chunk_capacity(chunks.len() as u64);
+            (&mut file).take(capacity).read_to_end(&mut buf).await?;
+            // An encrypted file keeps its header in the first chunk, even when empty
+            if buf.is_empty() && (file_key.is_none() || !chunks.is_empty()) {
+                break; // EOF
+            }
+            chunks.push(buf);
+        }
+        let count = chunks.len();
+        let chunks = (0..)
+            .zip(chunks)
+            .map(|(index, buf)| {
+                seal_chunk(file_key.as_ref(), index, index + 1 == count as u64, buf)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // Check the length of chunk is the same as chunk_info_list (from AssignResponse)
+        if chunks.len() != chunk_info_list.len() {
+            return Err(Error::InvalidData(
+                "Mismatch between number of file chunks and chunk_info_list length".to_string(),
+            ));
+        }
+        let uploads = self
+            .upload_chunks(chunk_info_list.into_iter().zip(chunks).collect())
+            .await?;
+        info!("File upload completed successfully.");
+        Ok(uploads)
+    }
+
+    /// Upload each chunk to the servers of its `ChunkInfo` and return the replicas that stored it
+    async fn upload_chunks(
+        &mut self,
+        chunks: Vec<(ChunkInfo, Vec<u8>)>,
+    ) -> Result<Vec<ChunkUpload>> {
+        // Upload each chunk once to its primary, which forwards it to the secondaries.
+        // If the primary is unreachable, the next replica takes over as primary.
+        let write_quorum = self.common_config.write_quorum;
+        let mut failed_replicas = Vec::new();
+        let mut unacknowledged_chunks = Vec::new();
+        let mut out_of_space = false;
+        // Servers that stayed unreachable are tried only once for the following chunks
+        let mut unreachable = HashSet::new();
+        let mut uploads = Vec::new();
+        for (chunk_info, chunk) in chunks {
+            let chunk_index = chunk_info.index;
+            let started = Instant::now();
+            let mut acknowledged = 0;
+            let mut stored_on = Vec::new();
+            let mut replicas = primary_first(&chunk_info);
+            while !replicas.is_empty() {
+                let primary_address = replicas.remove(0);
+                let retry_policy = if unreachable.contains(&primary_address) {
+                    RetryPolicy::never()
+                } else {
+                    self.retry_policy.clone()
+                };
+                let mut uploaded = self
+                    .upload_chunk_to_primary(
+                        &primary_address,
+                        &chunk_info,
+                        &replicas,
+                        chunk.clone(),
+                        &retry_policy,
+                    )
+                    .await;
+                // Data corrupted on the way to the primary is sent again
+                for _ in 0..CORRUPT_UPLOAD_RETRIES {
+                    if !uploaded.as_ref().is_err_and(Error::is_data_loss) {
+                        break;
+                    }
+                    warn!(
+                        "Chunk {} arrived corrupted at primary {}, sending it again",
+                        chunk_index, primary_address
+                    );
+                    uploaded = self
+                        .upload_chunk_to_primary(
+                            &primary_address,
+                            &chunk_info,
+                            &replicas,
+                            chunk.clone(),
+                            &retry_policy,
+                        )
+                        .await;
+                }
+                match uploaded {
+                    Ok(response) => {
+                        debug!(
+                            "Upload Response from primary {} for chunk {}: {}",
+                            primary_address, chunk_index, response.message
+                        );
+                        acknowledged =
+                            (1 + replicas.len()).saturating_sub(response.failed_secondaries.len());
+                        stored_on = std::iter::once(&primary_address)
+                            .chain(&replicas)
+                            .filter(|server| !response.failed_secondaries.contains(server))
+                            .cloned()
+                            .collect();
+                        for server_address in response.failed_secondaries {
+                            error!(
+                                "Secondary {} failed to store chunk {}",
+                                server_address, chunk_index
+                            );
+                            failed_replicas.push(FailedReplica {
+                                chunk_id: chunk_info.chunk_id.clone(),
+                                server_address,
+                            });
+                        }
+                        break;
+                    }
+                    Err(e @ Error::PermissionDenied(_)) => {
+                        // Every replica would refuse the user as well
+                        error!(
+                            "Primary {} refused chunk {}: {}",
+                            primary_address, chunk_index, e
+                        );
+                        self.report_failed_replicas(failed_replicas).await;
+                        return Err(e);
+                    }
+                    Err(e) => {
+                        // A full chunkserver is skipped like an unreachable one
+                        out_of_space |= e.is_out_of_space();
+                        if e.is_transient() {
+                            unreachable.insert(primary_address.clone());
+                        }
+                        error!(
+                            "Failed to upload chunk {} to primary {}: {}",
+                            chunk_index, primary_address, e
+                        );
+                        failed_replicas.push(FailedReplica {
+                            chunk_id: chunk_info.chunk_id.clone(),
+                            server_address: primary_address,
+                        });
+                    }
+                }
+            }
+
+            if acknowledged < write_quorum {
+                unacknowledged_chunks.push(format!(
+                    "chunk {} acknowledged by {} of {} required replicas",
+                    chunk_index, acknowledged, write_quorum
+                ));
+            }
+            uploads.push(ChunkUpload {
+                index: chunk_index,
+                chunk_id: chunk_info.chunk_id,
+                bytes: chunk.len() as u64,
+                failed_replicas: chunk_info
+                    .server_addresses
+                    .into_iter()
+                    .filter(|server| !stored_on.contains(server))
+                    .collect(),
+                replicas: stored_on,
+                elapsed: started.elapsed(),
+            });
+        }
+
+        // Let the master drop the replicas that were never written
+        self.report_failed_replicas(failed_replicas).await;
+
+        if !unacknowledged_chunks.is_empty() {
+            if out_of_space {
+                return Err(Error::ClusterFull(format!(
+                    "chunkservers are out of disk space ({})",
+                    unacknowledged_chunks.join(", ")
+                )));
+            }
+            return Err(Error::QuorumNotReached(unacknowledged_chunks.join(", ")));
+        }
+        Ok(uploads)
+    }
+
+    /// Report replicas that missed a write, so that the master stops serving them and copies
+    /// the chunk from an up-to-date replica instead
+    async fn report_failed_replicas(&mut self, failed_replicas: Vec<FailedReplica>) {
+        if failed_replicas.is_empty() {
+            return;
+        }
+        warn!(
+            "{} replica(s) failed, reporting them to the master",
+            failed_replicas.len()
+        );
+        let token = match self.session_token().await {
+            Ok(token) => token,
+            Err(e) => {
+                warn!("Failed to report failed replicas to the master: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = self
+            .on_leader_retrying(
+                "Reporting failed replicas",
+                ReportFailedReplicasRequest {
+                    failed_replicas: failed_replicas.clone(),
+                    token,
+                },
+                |mut master, request| async move { master.report_failed_replicas(request).await },
+            )
+            .await
+        {
+            warn!("Failed to report failed replicas to the master: {}", e);
+        }
+    }
+
+    /// Stream a single chunk to its primary, which forwards it to `secondary_addresses`.
+    /// Storing a chunk again overwrites it, so the upload is retried as `retry_policy` allows.
+    async fn upload_chunk_to_primary(
+        &self,
+        primary_address: &str,
+        chunk_info: &ChunkInfo,
+        secondary_addresses: &[String],
+        chunk_data: Vec<u8>,
+        retry_policy: &RetryPolicy,
+    ) -> Result<UploadResponse> {
+        retry(retry_policy, "Uploading a chunk", || {
+            self.with_session_token(|token| {
+                self.send_chunk_to_primary(
+                    primary_address,
+                    chunk_info,
+                    secondary_addresses,
+                    chunk_data.clone(),
+                    token,
+                )
+            })
+        })
+        .await
+    }
+
+    async fn send_chunk_to_primary(
+        &self,
+        primary_address: &str,
+        chunk_info: &ChunkInfo,
+        secondary_addresses: &[String],
+        chunk_data: Vec<u8>,
+        token: String,
+    ) -> Result<UploadResponse> {
+        let mut chunk_client = ChunkClient::new(self.connect(primary_address).await?);
+
+        let (tx, rx) = tokio::sync::mpsc::channel(4);
+
+        let file_info = FileInfo {
+            chunk_id: chunk_info.chunk_id.clone(),
+            secondary_addresses: secondary_addresses.to_vec(),
+            epoch: self.epoch,
+            checksum: Some(crc32fast::hash(&chunk_data)),
+            stored_form: false,
+            replicas: chunk_info.server_addresses.clone(),
+            placement_proof: chunk_info.placement_proof.clone(),
+            primary: chunk_info.primary.clone(),
+        };
+        let limiter = Arc::clone(&self.upload_limiter);
+        tokio::spawn(async move {
+            if let Err(e) = tx
+                .send(UploadRequest {
+                    request: Some(chunk::upload_request::Request::Info(file_info)),
+                    token: token.clone(),
+                    is_internal: false,
+                    internal_proof: String::new(),
+                })
+                .await
+            {
+                error!("Failed to send file info: {}", e);
+                return;
+            }
+
+            // Send the data in frames, so that the upload limit applies evenly
+            for frame in chunk_data.chunks(TRANSFER_FRAME_SIZE) {
+                limiter.acquire(frame.len() as u64).await;
+                let file_chunk = FileChunk {
+                    data: frame.to_vec(),
+                };
+                if let Err(e) = tx
+                    .send(UploadRequest {
+                        request: Some(chunk::upload_request::Request::Chunk(file_chunk)),
+                        token: token.clone(),
+                        is_internal: false,
+                        internal_proof: String::new(),
+                    })
+                    .await
+                {
+                    error!("Failed to send file chunk: {}", e);
+                    return;
+                }
+            }
+        });
+
+        let response = chunk_client
+            .upload(self.chunk_request(ReceiverStream::new(rx)))
+            .await?;
+        Ok(response.into_inner())
+    }
+
+    /// Read each chunk from one of the replicas in the given `(chunk_id, server_addresses)`
+    /// pairs and then concatenate
+    pub async fn read_file(
+        &mut self,
+        all_server_addresses: Vec<(String, Vec<String>)>,
+        file_name: &str,
+    ) -> Result<String> {
+        let mut file_content = Vec::new();
+        let mut file_key = None;
+
+        for (index, (chunk_id, server_addresses)) in all_server_addresses.iter().enumerate() {
+            debug!("Reading chunk {} of file '{}'", chunk_id, file_name);
+
+            // Append the chunk content to the file content
+            let mut content = self
+                .read_chunk(server_addresses, chunk_id, None, None, &mut Vec::new())
+                .await?;
+            if self.encryption.is_some() {
+                if index == 0 {
+                    file_key = Some(self.file_key(file_name, &content)?);
+                }
+                let file_key = file_key.as_ref().expect("the first chunk is read first");
+                let last = index + 1 == all_server_addresses.len();
+                content = file_key.open(file_name, index as u64, last, &content)?;
+            }
+            file_content.extend_from_slice(&content);
+        }
+
+        String::from_utf8(file_content).map_err(|e| {
+            Error::InvalidData(format!("File '{}' is not valid UTF-8: {}", file_name, e))
+        })
+    }
+
+    /// Read `length` bytes of a file starting at `offset`.
+    ///
+    /// Only the chunks overlapping the range are read, each from one of its replicas.
+    /// The result is shorter than `length` if the range extends past the end of the file.
+    pub async fn read_range(
+        &mut self,
+        file_name: &str,
+        offset: u64,
+        length: u64,
+    ) -> Result<Vec<u8>> {
+        let mut content = Vec::new();
+        self.read_range_to(file_name, offset, length, &mut content)
+            .await?;
+        Ok(content)
+    }
+
+    /// Write `length` bytes of a file starting at `offset` to `out`, one chunk at a time, and
+    /// return the number of bytes written with their CRC32.
+    ///
+    /// Only the chunks overlapping the range are read, unless the master does not know the
+    /// size of some chunk; then the chunks are read from the start until the range is covered.
+    pub async fn read_range_to(
+        &mut self,
+        file_name: &str,
+        offset: u64,
+        length: u64,
+        out: &mut impl Write,
+    ) -> Result<ReadSummary> {
+        let mut listed = self.list_chunks(file_name).await?;
+        if self.encryption.is_some() {
+            return self
+                .read_encrypted_to(file_name, listed, offset, length, out)
+                .await;
+        }
+        let mut out = SummingWriter::new(out);
+        let mut reads = Vec::new();
+        if let Some(ranges) = chunk_ranges(&listed.chunks, offset, length) {
+            let ranges: Vec<(String, u64, u64)> = ranges
+                .into_iter()
+                .map(|range| (range.chunk.chunk_id.clone(), range.offset, range.length))
+                .collect();
+            for (chunk_id, offset, length) in ranges {
+                debug!(
+                    "Reading {} byte(s) at offset {} of chunk {}",
+                    length, offset, chunk_id
+                );
+                let data = self
+                    .read_listed(
+                        file_name,
+                        &mut listed,
+                        &chunk_id,
+                        Some(offset),
+                        Some(length),
+                        &mut reads,
+                    )
+                    .await?;
+                out.write_all(&data)?;
+            }
+            return Ok(out.summary(reads));
+        }
+
+        let range_end = offset.saturating_add(length);
+        let mut chunk_start = 0;
+        for chunk_id in listed.chunk_ids() {
+            if chunk_start >= range_end {
+                break;
+            }
+            let data = self
+                .read_listed(file_name, &mut listed, &chunk_id, None, None, &mut reads)
+                .await?;
+            let chunk_end = chunk_start + data.len() as u64;
+            if chunk_end > offset {
+                let from = offset.saturating_sub(chunk_start) as usize;
+                let to = (range_end.min(chunk_end) - chunk_start) as usize;
+                out.write_all(&data[from..to])?;
+            }
+            chunk_start = chunk_end;
+        }
+        Ok(out.summary(reads))
+    }
+
+    /// Write the last `length` bytes of a file, or all of it if it is shorter, to `out` and
+    /// return the number of bytes written with their CRC32.
+    ///
+    /// Only the last chunks are read, unless the master does not know the size of some chunk;
+    /// then the whole file is read, keeping its last `length` bytes.
+    pub async fn read_tail_to(
+        &mut self,
+        file_name: &str,
+        length: u64,
+        out: &mut impl Write,
+    ) -> Result<ReadSummary> {
+        let mut listed = self.list_chunks(file_name).await?;
+        if listed.chunks.iter().all(|chunk| chunk.size > 0) {
+            let file_size: u64 = match self.encryption {
+                Some(_) => (0..)
+                    .zip(&listed.chunks)
+                    .map(|(index, chunk)| encryption::plaintext_size(index, chunk.size))
+                    .sum(),
+                None => listed.chunks.iter().map(|chunk| chunk.size).sum(),
+            };
+            return self
+                .read_range_to(file_name, file_size.saturating_sub(length), length, out)
+                .await;
+        }
+
+        if self.encryption.is_some() {
+            let mut data = Vec::new();
+            let read = self
+                .read_encrypted_to(file_name, listed, 0, u64::MAX, &mut data)
+                .await?;
+            let mut out = SummingWriter::new(out);
+            out.write_all(&data[data.len().saturating_sub(length as usize)..])?;
+            return Ok(out.summary(read.chunks));
+        }
+
+        let mut tail = Vec::new();
+        let mut reads = Vec::new();
+        for chunk_id in listed.chunk_ids() {
+            let data = self
+                .read_listed(file_name, &mut listed, &chunk_id, None, None, &mut reads)
+                .await?;
+            tail.extend_from_slice(&data);
+            let excess = tail.len().saturating_sub(length as usize);
+            tail.drain(..excess);
+        }
+        let mut out = SummingWriter::new(out);
+        out.write_all(&tail)?;
+        Ok(out.summary(reads))
+    }
+
+    /// Write `length` bytes of an encrypted file starting at `offset` to `out`, like
+    /// `read_range_to`.
+    ///
+    /// Each chunk is decrypted as a whole, so the chunks overlapping the range are read in full.
+    /// Chunks before the range are skipped if the master knows the size of every chunk, apart
+    /// from the header of the first one holding the file's salt.
+    async fn read_encrypted_to(
+        &mut self,
+        file_name: &str,
+        mut listed: ListedChunks,
+        offset: u64,
+        length: u64,
+        out: &mut impl Write,
+    ) -> Result<ReadSummary> {
+        let mut out = SummingWriter::new(out);
+        let mut reads = Vec::new();
+        let sizes_known = listed.chunks.iter().all(|chunk| chunk.size > 0);
+        let range_end = offset.saturating_add(length);
+        let mut file_key = None;
+        let mut chunk_start = 0;
+        let chunk_ids = listed.chunk_ids();
+        let count = chunk_ids.len() as u64;
+        for (index, chunk_id) in (0..).zip(chunk_ids) {
+            if chunk_start >= range_end {
+                break;
+            }
+            if sizes_known {
+                let size = encryption::plaintext_size(index, listed.find(&chunk_id)?.size);
+                if chunk_start + size <= offset {
+                    if index == 0 {
+                        let header = self
+                            .read_listed(
+                                file_name,
+                                &mut listed,
+                                &chunk_id,
+                                Some(0),
+                                Some(encryption::HEADER_LEN),
+                                &mut reads,
+                            )
+                            .await?;
+                        file_key = Some(self.file_key(file_name, &header)?);
+                    }
+                    chunk_start += size;
+                    continue;
+                }
+            }
+
+            let chunk = self
+                .read_listed(file_name, &mut listed, &chunk_id, None, None, &mut reads)
+                .await?;
+            if index == 0 {
+                file_key = Some(self.file_key(file_name, &chunk)?);
+            }
+            let file_key = file_key.as_ref().expect("the first chunk is read first");
+            let data = file_key.open(file_name, index, index + 1 == count, &chunk)?;
+            let chunk_end = chunk_start + data.len() as u64;
+            if chunk_end > offset {
+                let from = offset.saturating_sub(chunk_start) as usize;
+                let to = (range_end.min(chunk_end) - chunk_start) as usize;
+                out.write_all(&data[from..to])?;
+            }
+            chunk_start = chunk_end;
+        }
+        Ok(out.summary(reads))
+    }
+
+    /// Read the chunk `chunk_id` of a file from the replicas in `listed`, recorded in `reads`.
+    ///
+    /// If `listed` came from the cache and no replica holds the chunk anymore, e.g. as it was
+    /// moved meanwhile, the chunk list is fetched from the master again, once per read, and the
+    /// chunk is read from the replicas it lists now.
+    async fn read_listed(
+        &mut self,
+        file_name: &str,
+        listed: &mut ListedChunks,
+        chunk_id: &str,
+        offset: Option<u64>,
+        length: Option<u64>,
+        reads: &mut Vec<ChunkRead>,
+    ) -> Result<Vec<u8>> {
+        let chunk = listed.find(chunk_id)?;
+        match self.read_recorded(&chunk, offset, length, reads).await {
+            Err(e) if listed.cached && e.is_missing_replica() => {
+                warn!(
+                    "Cached replicas of chunk {} of '{}' failed ({}), fetching them again",
+                    chunk_id, file_name, e
+                );
+                self.invalidate_chunks(file_name);
+                *listed = self.list_chunks(file_name).await?;
+                let chunk = listed.find(chunk_id)?;
+                self.read_recorded(&chunk, offset, length, reads).await
+            }
+            result => result,
+        }
+    }
+
+    /// Read a chunk like `read_chunk` and add how long it took and which replicas failed to
+    /// `reads`
+    async fn read_recorded(
+        &self,
+        chunk_info: &ChunkInfo,
+        offset: Option<u64>,
+        length: Option<u64>,
+        reads: &mut Vec<ChunkRead>,
+    ) -> Result<Vec<u8>> {
+        let started = Instant::now();
+        let mut failed_replicas = Vec::new();
+        let data = self
+            .read_chunk(
+                &chunk_info.server_addresses,
+                &chunk_info.chunk_id,
+                offset,
+                length,
+                &mut failed_replicas,
+            )
+            .await?;
+        reads.push(ChunkRead {
+            index: chunk_info.index,
+            chunk_id: chunk_info.chunk_id.clone(),
+            bytes: data.len() as u64,
+            failed_replicas,
+            elapsed: started.elapsed(),
+        });
+        Ok(data)
+    }
+
+    /// Read a chunk, or the given range of it, from its replicas until one of them answers,
+    /// e.g. if a chunkserver is down or doesn't answer within the RPC timeout. The replicas are
+    /// tried least loaded first if the master ordered them so, else in random order. If all of
+    /// them failed for a transient reason, they are tried again as the retry policy allows. The servers that failed are added to `failed_replicas`, and reported to the master once another replica served the chunk, see `report_bad_replicas`.
+    async fn read_chunk(
+        &self,
+        server_addresses: &[String],
+        chunk_id: &str,
+        offset: Option<u64>,
+        length: Option<u64>,
+        failed_replicas: &mut Vec<String>,
+    ) -> Result<Vec<u8>> {
+        let mut server_addresses = server_addresses.to_vec();
+        let mut backoff = self.retry_policy.backoff();
+        let mut failures = Vec::new(); // (server, error), reported once another replica answered
+        loop {
+            // Least loaded first if the master ordered them, else spread over them at random
+            if !self.replicas_ordered {
+                server_addresses.shuffle(&mut rand::thread_rng());
+            }
+            let mut last_error =
+                Error::NoAvailableServers(format!("No available servers for chunk {}", chunk_id));
+            for server_address in &server_addresses {
+                debug!("Reading chunk {} from {}", chunk_id, server_address);
+                match self
+                    .read_replica(server_address, chunk_id, offset, length)
+                    .await
+                {
+                    Ok(content) => {
+                        self.report_bad_replicas(chunk_id, &failures);
+                        return Ok(content);
+                    }
+                    // Every replica would refuse the user as well
+                    Err(e @ Error::PermissionDenied(_)) => return Err(e),
+                    Err(e) => {
+                        warn!(
+                            "Failed to read chunk {} from {}: {}",
+                            chunk_id, server_address, e
+                        );
+                        failed_replicas.push(server_address.clone());
+                        failures.push((server_address.clone(), e.to_string()));
+                        last_error = e;
+                    }
+                }
+            }
+            if !last_error.is_transient() || !backoff.wait("Reading a chunk", &last_error).await {
+                return Err(last_error);
+            }
+        }
+    }
+
+    /// Report replicas that failed to serve a chunk that another replica served to the master,
+    /// which checks them and copies the chunk again if they lost it. The reports are sent in
+    /// the background, a failed report is only logged. Each replica is reported once, and at
+    /// most `MAX_BAD_REPLICA_REPORTS` per `BAD_REPLICA_REPORT_PERIOD`, so that a flapping
+    /// network doesn't flood the master.
+    fn report_bad_replicas(&self, chunk_id: &str, failures: &[(String, String)]) {
+        if failures.is_empty() {
+            return;
+        }
+        let mut reports = self.bad_replica_reports.lock().unwrap();
+        let mut pending_reports = self.pending_reports.lock().unwrap();
+        for (server_address, reason) in failures {
+            if !reports.admit(chunk_id, server_address) {
+                debug!(
+                    "Not reporting replica of chunk {} on {} again",
+                    chunk_id, server_address
+                );
+                continue;
+            }
+            let mut master = self.master_client.clone();
+            let request = self.request(ReportBadReplicaRequest {
+                chunk_id: chunk_id.to_string(),
+                server_address: server_address.clone(),
+                reason: reason.clone(),
+            });
+            let replica = format!("chunk {} on {}", chunk_id, server_address);
+            pending_reports.spawn(async move {
+                if let Err(e) = master.report_bad_replica(request).await {
+                    warn!("Failed to report the replica of {}: {}", replica, e);
+                }
+            });
+        }
+    }
+
+    /// Wait for the reports of unreadable replicas that were not answered yet, e.g. before the
+    /// process exits. Each report gives up after `master_rpc_timeout`.
+    pub async fn finish_reports(&self) {
+        let mut pending_reports = std::mem::take(&mut *self.pending_reports.lock().unwrap());
+        while pending_reports.join_next().await.is_some() {}
+    }
+
+    /// Read a chunk, or the given range of it, from one chunkserver.
+    ///
+    /// If the chunk list was possibly stale, the server may be down, so the read gives up
+    /// after `STALE_READ_TIMEOUT` instead of waiting for the connection to time out.
+    async fn read_replica(
+        &self,
+        server_address: &str,
+        chunk_id: &str,
+        offset: Option<u64>,
+        length: Option<u64>,
+    ) -> Result<Vec<u8>> {
+        let read = self.with_session_token(|token| async move {
+            let mut chunk_client = ChunkClient::new(self.connect(server_address).await?);
+
+            let mut frames = chunk_client
+                .read(self.chunk_request(ReadRequest {
+                    chunk_id: chunk_id.to_string(),
+                    token,
+                    offset,
+                    length,
+                }))
+                .await?
+                .into_inner();
+            // The chunk arrives in frames, so that none exceeds the gRPC message size limit
+            let mut content = Vec::new();
+            while let Some(frame) = frames.message().await? {
+                content.extend_from_slice(&frame.content);
+            }
+            Ok(content)
+        });
+        let content = if self.possibly_stale {
+            tokio::time::timeout(STALE_READ_TIMEOUT, read)
+                .await
+                .map_err(|_| {
+                    Error::NoAvailableServers(format!(
+                        "Reading chunk {} from {} timed out, the server may be down",
+                        chunk_id, server_address
+                    ))
+                })??
+        } else {
+            read.await?
+        };
+        self.download_limiter.acquire(content.len() as u64).await;
+        Ok(content)
+    }
+
+    /// Append data to the end of the file, i.e. to every replica of its last chunk.
+    ///
+    /// The data is sent to the primary of the chunk, the first of its replicas as listed by
+    /// `get_append_server_addresses`. It picks the offset and applies the append to the
+    /// secondaries, so that all replicas hold the appends in the same order. Fails unless
+    /// `write_quorum` replicas acknowledged the append. Secondaries that missed it are reported
+    /// to the master, so reads only go to replicas holding the appended data.
+    pub async fn append_file(
+        &mut self,
+        all_server_addresses: Vec<(String, Vec<String>)>, // Server addresses for each chunk, primary first
+        file_name: &str,
+        data: String,
+    ) -> Result<()> {
+        if self.encryption.is_some() {
+            return Err(Error::Usage(format!(
+                "Cannot append to '{}': appending is not supported with client.encryption_key_file set, as encrypted chunks can only be written whole",
+                file_name
+            )));
+        }
+        self.invalidate_chunks(file_name);
+        let mut quorum_error = None;
+        if let Some((chunk_id, server_addresses)) = all_server_addresses.last() {
+            let Some((primary_address, secondary_addresses)) = server_addresses.split_first()
+            else {
+                return Err(Error::NoAvailableServers(format!(
+                    "No replica of chunk {} to append to",
+                    chunk_id
+                )));
+            };
+
+            // Only the lease holder accepts appends, so an unreachable primary fails the append
+            // until the master moved the lease to another replica
+            let response = self
+                .append_to_primary(primary_address, chunk_id, secondary_addresses, &data)
+                .await
+                .map_err(|e| {
+                    error!(
+                        "Failed to append to chunk {} on primary {}: {}",
+                        chunk_id, primary_address, e
+                    );
+                    if e.is_out_of_space() {
+                        Error::ClusterFull(format!(
+                            "primary of chunk {} has no space for the appended data",
+                            chunk_id
+                        ))
+                    } else {
+                        e
+                    }
+                })?;
+            info!(
+                "Append Response from primary {} for chunk {}: {}",
+                primary_address, chunk_id, response.message
+            );
+            let acknowledged = server_addresses
+                .len()
+                .saturating_sub(response.failed_secondaries.len());
+            let failed_replicas = response
+                .failed_secondaries
+                .into_iter()
+                .map(|server_address| {
+                    error!(
+                        "Secondary {} failed to append to chunk {}",
+                        server_address, chunk_id
+                    );
+                    FailedReplica {
+                        chunk_id: chunk_id.clone(),
+                        server_address,
+                    }
+                })
+                .collect();
+
+            self.report_failed_replicas(failed_replicas).await;
+            let write_quorum = self.common_config.write_quorum;
+            if acknowledged < write_quorum {
+                quorum_error = Some(Error::QuorumNotReached(format!(
+                    "append to chunk {} acknowledged by {} of {} required replicas",
+                    chunk_id, acknowledged, write_quorum
+                )));
+            }
+        }
+
+        // Record the appended bytes so the master keeps the chunk sizes up to date, also if the
+        // quorum was not reached, as the data is on the replicas still serving the chunk
+        self.record_append(file_name, data.len() as u64).await?;
+        if let Some(e) = quorum_error {
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Tell the master that the last chunk of `file_name` grew by `size` bytes
+    async fn record_append(&mut self, file_name: &str, size: u64) -> Result<()> {
+        let token = self.session_token().await?;
+        self.on_leader(
+            RecordAppendRequest {
+                file_name: file_name.to_string(),
+                size,
+                token,
+            },
+            |mut master, request| async move { master.record_append(request).await },
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Send an append to the primary of a chunk, which applies it to `secondary_addresses`.
+    ///
+    /// All attempts carry the same random append ID, which the replicas journal, so a retry of
+    /// an append that was applied although its response got lost, e.g. as it timed out, is
+    /// answered with the first attempt's offset instead of appending the data again.
+    async fn append_to_primary(
+        &self,
+        primary_address: &str,
+        chunk_id: &str,
+        secondary_addresses: &[String],
+        data: &str,
+    ) -> Result<AppendResponse> {
+        let append_id = Uuid::new_v4().to_string();
+        retry(&self.retry_policy, "Appending to the primary", || {
+            self.with_session_token(|token| {
+                let append_id = append_id.clone();
+                async move {
+                    let mut chunk_client = ChunkClient::new(self.connect(primary_address).await?);
+                    let response = chunk_client
+                        .append(self.chunk_request(AppendRequest {
+                            chunk_id: chunk_id.to_string(),
+                            data: data.to_string(),
+                            token,
+                            epoch: self.epoch,
+                            secondary_addresses: secondary_addresses.to_vec(),
+                            append_id,
+                        }))
+                        .await?
+                        .into_inner();
+                    Ok(response)
+                }
+            })
+        })
+        .await
+    }
+
+    /// Replace the bytes of a file starting at `offset` with `data`, e.g. to update
+    /// fixed-size records without uploading the file again.
+    ///
+    /// The bytes are written to every replica of the chunks they fall into, one chunk after the
+    /// other. Each part is sent to the primary of its chunk, which applies it to the
+    /// secondaries in the same order as the appends to the chunk. Data past the end of the
+    /// file extends it, as long as it fits into the last chunk; a write leaving a gap after the
+    /// end is rejected. Fails unless `write_quorum` replicas acknowledged each part, like
+    /// `append_file`; the parts written before a failed one stay written.
+    pub async fn write_at(&mut self, file_name: &str, offset: u64, data: &[u8]) -> Result<()> {
+        if self.encryption.is_some() {
+            return Err(Error::Usage(format!(
+                "Cannot write to '{}': writing within a file is not supported with client.encryption_key_file set, as encrypted chunks can only be written whole",
+                file_name
+            )));
+        }
+        if data.is_empty() {
+            return Ok(());
+        }
+        self.invalidate_chunks(file_name);
+
+        // Every chunk but the last is full, so the master can grant the leases upfront
+        let chunk_size = self.common_config.chunk_size;
+        let end = offset.saturating_add(data.len() as u64);
+        let lease_chunks: Vec<u64> = (offset / chunk_size..=(end - 1) / chunk_size).collect();
+        let chunks = self.get_file_chunks(file_name, true, &lease_chunks).await?;
+        let Some(ranges) = chunk_ranges(&chunks, offset, data.len() as u64) else {
+            return Err(Error::InvalidData(format!(
+                "The master does not know the size of every chunk of '{}', cannot locate offset {}",
+                file_name, offset
+            )));
+        };
+        let file_size: u64 = chunks.iter().map(|chunk| chunk.size).sum();
+        let Some(last_chunk) = chunks.last().filter(|_| offset <= file_size) else {
+            return Err(Error::Usage(format!(
+                "Cannot write at offset {} of '{}', which has {} byte(s)",
+                offset, file_name, file_size
+            )));
+        };
+        let extension = end.saturating_sub(file_size);
+        if last_chunk.size + extension > chunk_size {
+            return Err(Error::Usage(format!(
+                "Writing {} byte(s) at offset {} of '{}' extends it past its last chunk, append to it instead",
+                data.len(),
+                offset,
+                file_name
+            )));
+        }
+
+        // (chunk, offset in the chunk, bytes of `data`), a cross-chunk write split at the
+        // chunk boundaries, the part past the end of the file going to the last chunk
+        let mut parts: Vec<(&ChunkInfo, u64, std::ops::Range<usize>)> = Vec::new();
+        let mut position = 0;
+        for range in ranges {
+            let length = range.length as usize;
+            parts.push((range.chunk, range.offset, position..position + length));
+            position += length;
+        }
+        if extension > 0 {
+            match parts.last_mut() {
+                Some((chunk, _, bytes)) if chunk.chunk_id == last_chunk.chunk_id => {
+                    bytes.end = data.len()
+                }
+                _ => parts.push((last_chunk, last_chunk.size, position..data.len())),
+            }
+        }
+
+        let write_quorum = self.common_config.write_quorum;
+        let mut failed_replicas = Vec::new();
+        let mut quorum_errors = Vec::new();
+        let mut failure = None;
+        for (chunk, chunk_offset, bytes) in parts {
+            let replicas = primary_first(chunk);
+            let Some((primary_address, secondary_addresses)) = replicas.split_first() else {
+                failure = Some(Error::NoAvailableServers(format!(
+                    "No replica of chunk {} to write to",
+                    chunk.chunk_id
+                )));
+                break;
+            };
+            let allow_extend = chunk_offset + bytes.len() as u64 > chunk.size;
+            let written = self
+                .write_to_primary(
+                    primary_address,
+                    file_name,
+                    &chunk.chunk_id,
+                    chunk_offset,
+                    &data[bytes],
+                    allow_extend,
+                    secondary_addresses,
+                )
+                .await;
+            let response = match written {
+                Ok(response) => response,
+                Err(e) => {
+                    error!(
+                        "Failed to write to chunk {} on primary {}: {}",
+                        chunk.chunk_id, primary_address, e
+                    );
+                    failure = Some(if e.is_out_of_space() {
+                        Error::ClusterFull(format!(
+                            "primary of chunk {} has no space for the written data",
+                            chunk.chunk_id
+                        ))
+                    } else {
+                        e
+                    });
+                    break;
+                }
+            };
+            debug!(
+                "Write Response from primary {} for chunk {}: {}",
+                primary_address, chunk.chunk_id, response.message
+            );
+            let acknowledged = chunk
+                .server_addresses
+                .len()
+                .saturating_sub(response.failed_secondaries.len());
+            if acknowledged < write_quorum {
+                quorum_errors.push(format!(
+                    "write to chunk {} acknowledged by {} of {} required replicas",
+                    chunk.chunk_id, acknowledged, write_quorum
+                ));
+            }
+            for server_address in response.failed_secondaries {
+                error!(
+                    "Secondary {} failed to write to chunk {}",
+                    server_address, chunk.chunk_id
+                );
+                failed_replicas.push(FailedReplica {
+                    chunk_id: chunk.chunk_id.clone(),
+                    server_address,
+                });
+            }
+        }
+        self.report_failed_replicas(failed_replicas).await;
+
+        // The last chunk grew if its part was written, whether or not the quorum was reached
+        if extension > 0 && failure.is_none() {
+            self.record_append(file_name, extension).await?;
+        }
+        if let Some(e) = failure {
+            return Err(e);
+        }
+        if !quorum_errors.is_empty() {
+            return Err(Error::QuorumNotReached(quorum_errors.join(", ")));
+        }
+        Ok(())
+    }
+
+    /// Send one part of a `write_at` to the primary of its chunk
+    #[allow(clippy::too_many_arguments)]
+    async fn write_to_primary(
+        &self,
+        primary_address: &str,
+        file_name: &str,
+        chunk_id: &str,
+        offset: u64,
+        data: &[u8],
+        allow_extend: bool,
+        secondary_addresses: &[String],
+    ) -> Result<WriteAtResponse> {
+        self.with_session_token(|token| async move {
+            let channel = retry(&self.retry_policy, "Connecting to the primary", || {
+                self.connect(primary_address)
+            })
+            .await?;
+            let mut chunk_client = ChunkClient::new(channel);
+            let response = chunk_client
+                .write_at(self.chunk_request(WriteAtRequest {
+                    file_name: file_name.to_string(),
+                    chunk_id: chunk_id.to_string(),
+                    offset,
+                    data: data.to_vec(),
+                    allow_extend,
+                    token,
+                    epoch: self.epoch,
+                    secondary_addresses: secondary_addresses.to_vec(),
+                }))
+                .await?
+                .into_inner();
+            Ok(response)
+        })
+        .await
+    }
+
+    /// Ask every replica of every chunk of a file for its checksum and length, computed by the
+    /// chunkservers without transferring the data
+    pub async fn verify_file(&mut self, file_name: &str) -> Result<Vec<ChunkVerification>> {
+        let all_server_addresses = self.get_all_server_addresses(file_name).await?;
+        let mut verifications = Vec::new();
+        for (chunk_id, server_addresses) in all_server_addresses {
+            let checksums = server_addresses
+                .iter()
+                .map(|server_address| self.checksum_replica(server_address, &chunk_id));
+            let replicas = server_addresses
+                .iter()
+                .zip(futures::future::join_all(checksums).await)
+                .map(|(server_address, checksum)| {
+                    let corrupt = checksum.as_ref().is_ok_and(|(checksum, stored)| {
+                        stored.is_some_and(|stored| stored != checksum.0)
+                    });
+                    ReplicaChecksum {
+                        server_address: server_address.clone(),
+                        checksum: checksum.map(|(checksum, _)| checksum),
+                        corrupt,
+                    }
+                })
+                .collect();
+            verifications.push(ChunkVerification { chunk_id, replicas });
+        }
+        Ok(verifications)
+    }
+
+    /// CRC32 and length of a replica, with the checksum stored when it was written
+    async fn checksum_replica(
+        &self,
+        server_address: &str,
+        chunk_id: &str,
+    ) -> Result<((u32, u64), Option<u32>)> {
+        let response = retry(&self.retry_policy, "Checksumming a replica", || {
+            self.with_session_token(|token| async move {
+                let mut chunk_client = ChunkClient::new(self.connect(server_address).await?);
+                let response = chunk_client
+                    .checksum_chunk(self.chunk_request(ChecksumChunkRequest {
+                        chunk_id: chunk_id.to_string(),
+                        token,
+                    }))
+                    .await?;
+                Ok(response.into_inner())
+            })
+        })
+        .await?;
+        Ok((
+            (response.checksum, response.length),
+            response.stored_checksum,
+        ))
+    }
+
+    /// Replication factor the cluster is configured with
+    pub fn replication_factor(&self) -> usize {
+        self.common_config.replication_factor
+    }
+}
+
+/// Chunk `index` of a new file as it is stored, encrypted with `file_key` if set. `last` tells
+/// whether the file ends with it.
+fn seal_chunk(
+    file_key: Option<&FileKey>,
+    index: u64,
+    last: bool,
+    chunk: Vec<u8>,
+) -> Result<Vec<u8>> {
+    match file_key {
+        Some(file_key) => file_key.seal(index, last, &chunk),
+        None => Ok(chunk),
+    }
+}
+
+/// Warn that `file_name` got only `replicas` of `replication_factor` replicas
+fn warn_if_degraded(file_name: &str, replicas: u64, replication_factor: u64) {
+    if replicas < replication_factor {
+        warn!(
+            "'{}' is stored with only {} of {} replicas, as there are not enough chunkservers; the master adds the missing ones once more chunkservers join",
+            file_name, replicas, replication_factor
+        );
+    }
+}
+
+/// The replicas of a chunk with its primary first, which appends and writes go to. Masters
+/// that don't report the primary list it first.
+fn primary_first(chunk: &ChunkInfo) -> Vec<String> {
+    let mut replicas = chunk.server_addresses.clone();
+    if let Some(position) = replicas.iter().position(|addr| *addr == chunk.primary) {
+        replicas[..=position].rotate_right(1);
+    }
+    replicas
+}
+
+/// The part of a chunk that a byte range of its file covers, see `chunk_ranges`
+#[derive(Debug)]
+pub struct ChunkRange<'a> {
+    pub chunk: &'a ChunkInfo,
+    pub offset: u64, // Offset of the range in the chunk
+    pub length: u64, // Bytes of the range in the chunk
+}
+
+/// Map `length` bytes of a file starting at `offset` to the chunks holding them, in order.
+///
+/// A range extending past the end of the file covers the chunks up to it. Returns `None` if
+/// the size of some chunk is not known, i.e. 0.
+pub fn chunk_ranges(chunks: &[ChunkInfo], offset: u64, length: u64) -> Option<Vec<ChunkRange<'_>>> {
+    if chunks.iter().any(|chunk| chunk.size == 0) {
+        return None;
+    }
+    let range_end = offset.saturating_add(length);
+    let mut ranges = Vec::new();
+    let mut chunk_start = 0;
+    for chunk in chunks {
+        let chunk_end = chunk_start + chunk.size;
+        if chunk_end > offset && chunk_start < range_end {
+            let read_offset = offset.saturating_sub(chunk_start);
+            ranges.push(ChunkRange {
+                chunk,
+                offset: read_offset,
+                length: range_end.min(chunk_end) - chunk_start - read_offset,
+            });
+        }
+        chunk_start = chunk_end;
+    }
+    Some(ranges)
+}
@@ -0,0 +1,129 @@
+// Proofs that a request comes from a node of the cluster, signed with keys derived from the
+// cluster secret, which itself never leaves the node
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::{Error, Result};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Seconds an internal request proof is accepted for after it was signed
+pub const PROOF_VALIDITY: u64 = 300;
+
+/// Key used for a single `purpose`, derived from the cluster secret, so that a key leaked from
+/// one use, e.g. by a node an internal request was sent to, cannot be used for another one
+pub fn derive_key(secret: &str, purpose: &str) -> Vec<u8> {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(b"rustfs ");
+    mac.update(purpose.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// HMAC of `fields` with `key`, each field prefixed with its length so that no two lists of
+/// fields are signed alike
+fn mac(key: &[u8], fields: &[&[u8]]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    for field in fields {
+        mac.update(&(field.len() as u64).to_be_bytes());
+        mac.update(field);
+    }
+    mac
+}
+
+/// Lowercase hex encoding of `bytes`
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Bytes encoded by `to_hex`, `None` if `hex` is not such an encoding
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Sign an internal request, given as the name of the RPC followed by the fields it acts on.
+///
+/// The proof reads "<expires_at>.<HMAC-SHA256 in hex>" and is accepted for `PROOF_VALIDITY`
+/// seconds, so that it cannot be replayed later or for another request.
+pub fn sign_request(secret: &str, fields: &[&[u8]]) -> String {
+    let expires_at = now() + PROOF_VALIDITY;
+    let signature = mac(&derive_key(secret, "internal request"), fields)
+        .chain_update(expires_at.to_be_bytes())
+        .finalize()
+        .into_bytes();
+    format!("{}.{}", expires_at, to_hex(&signature))
+}
+
+/// Check the proof of an internal request signed by `sign_request` with the same fields
+pub fn verify_request(secret: &str, proof: &str, fields: &[&[u8]]) -> Result<()> {
+    let invalid = |reason: &str| Error::Auth(format!("Invalid internal request proof: {}", reason));
+    let (expires_at, signature) = proof.split_once('.').ok_or_else(|| invalid("malformed"))?;
+    let expires_at: u64 = expires_at.parse().map_err(|_| invalid("malformed"))?;
+    let signature = from_hex(signature).ok_or_else(|| invalid("malformed"))?;
+    // Compares in constant time
+    mac(&derive_key(secret, "internal request"), fields)
+        .chain_update(expires_at.to_be_bytes())
+        .verify_slice(&signature)
+        .map_err(|_| invalid("wrong signature"))?;
+    // Allows for clocks a validity period apart
+    let now = now();
+    if expires_at <= now || expires_at > now + 2 * PROOF_VALIDITY {
+        return Err(invalid("expired or not yet valid"));
+    }
+    Ok(())
+}
+
+/// Sign the primary and replicas the master placed a chunk on, which a client passes to the
+/// primary along with the chunk, so that only those servers store it
+pub fn sign_placement(secret: &str, chunk_id: &str, primary: &str, replicas: &[String]) -> String {
+    let signature = mac(
+        &derive_key(secret, "placement"),
+        &placement_fields(chunk_id, primary, replicas),
+    )
+    .finalize()
+    .into_bytes();
+    to_hex(&signature)
+}
+
+/// Check the primary and replicas of a chunk against their signature by `sign_placement`
+pub fn verify_placement(
+    secret: &str,
+    signature: &str,
+    chunk_id: &str,
+    primary: &str,
+    replicas: &[String],
+) -> Result<()> {
+    let invalid = || Error::Auth(format!("Invalid placement of chunk '{}'", chunk_id));
+    let signature = from_hex(signature).ok_or_else(invalid)?;
+    mac(
+        &derive_key(secret, "placement"),
+        &placement_fields(chunk_id, primary, replicas),
+    )
+    .verify_slice(&signature)
+    .map_err(|_| invalid())
+}
+
+fn placement_fields<'a>(
+    chunk_id: &'a str,
+    primary: &'a str,
+    replicas: &'a [String],
+) -> Vec<&'a [u8]> {
+    [chunk_id.as_bytes(), primary.as_bytes()]
+        .into_iter()
+        .chain(replicas.iter().map(|replica| replica.as_bytes()))
+        .collect()
+}
@@ -0,0 +1,315 @@
+// Chunk files stored compressed, in frames so that an append adds a frame instead of rewriting the chunk
+use std::io::{ErrorKind, SeekFrom};
+use std::str::FromStr;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+use crate::chunkserver_service::TRANSFER_FRAME_SIZE;
+use crate::error::{Error, Result};
+
+/// Start of a framed chunk file. Chunk files without it hold the chunk's data as it is.
+pub const FRAMED_MAGIC: &[u8; 8] = b"RUSTFS\x00\x01";
+
+/// Codec byte, stored length and data length of a frame, each length as a little-endian u32
+const FRAME_HEADER_LEN: usize = 9;
+
+/// How chunks are compressed on disk, see `compression` in config.toml
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None, // Chunks are stored as they are, unless their data starts with `FRAMED_MAGIC`
+    Lz4,  // Fast, for a moderate gain
+    Zstd, // Slower, for a larger gain
+}
+
+impl Compression {
+    /// Byte identifying the codec of a frame
+    fn codec(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Lz4 => 1,
+            Compression::Zstd => 2,
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "none" => Ok(Compression::None),
+            "lz4" => Ok(Compression::Lz4),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(Error::Config(format!(
+                "Invalid compression '{}', expected \"none\", \"lz4\" or \"zstd\"",
+                value
+            ))),
+        }
+    }
+}
+
+/// Frames holding `data`, each with at most `TRANSFER_FRAME_SIZE` bytes of it. Frames that
+/// would not shrink are stored uncompressed.
+pub fn encode_frames(compression: Compression, data: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(data.len() + FRAME_HEADER_LEN);
+    for part in data.chunks(TRANSFER_FRAME_SIZE) {
+        let compressed = match compression {
+            Compression::None => None,
+            Compression::Lz4 => Some(lz4_flex::block::compress(part)),
+            Compression::Zstd => zstd::bulk::compress(part, 0).ok(),
+        };
+        let (codec, stored) = match &compressed {
+            Some(compressed) if compressed.len() < part.len() => {
+                (compression.codec(), compressed.as_slice())
+            }
+            _ => (Compression::None.codec(), part),
+        };
+        encoded.push(codec);
+        encoded.extend_from_slice(&(stored.len() as u32).to_le_bytes());
+        encoded.extend_from_slice(&(part.len() as u32).to_le_bytes());
+        encoded.extend_from_slice(stored);
+    }
+    encoded
+}
+
+/// Data of a frame stored with `codec`
+fn decode_frame(codec: u8, stored: Vec<u8>, len: usize) -> std::io::Result<Vec<u8>> {
+    let invalid = |e: String| std::io::Error::new(ErrorKind::InvalidData, e);
+    let data = match codec {
+        0 => stored,
+        1 => lz4_flex::block::decompress(&stored, len).map_err(|e| invalid(e.to_string()))?,
+        2 => zstd::bulk::decompress(&stored, len)?,
+        _ => return Err(invalid(format!("Unknown codec {} of a chunk frame", codec))),
+    };
+    if data.len() != len {
+        return Err(invalid(format!(
+            "Chunk frame holds {} byte(s), its header says {}",
+            data.len(),
+            len
+        )));
+    }
+    Ok(data)
+}
+
+/// Read the header of the frame at the current position of `file`, `None` at the end of the file.
+///
+/// Fails with `InvalidData` if the file ends within the header, or if the header gives lengths
+/// no frame written by `encode_frames` has, so that a corrupt header is not trusted with the
+/// size of a buffer.
+async fn read_frame_header(file: &mut File) -> std::io::Result<Option<(u8, usize, usize)>> {
+    let invalid = |e: String| std::io::Error::new(ErrorKind::InvalidData, e);
+    let mut header = [0; FRAME_HEADER_LEN];
+    let mut filled = 0;
+    while filled < FRAME_HEADER_LEN {
+        match file.read(&mut header[filled..]).await? {
+            0 if filled == 0 => return Ok(None),
+            0 => {
+                return Err(invalid(format!(
+                    "Chunk file ends within a frame header, after {} of {} byte(s)",
+                    filled, FRAME_HEADER_LEN
+                )))
+            }
+            n => filled += n,
+        }
+    }
+    let stored_len = u32::from_le_bytes(header[1..5].try_into().unwrap()) as usize;
+    let len = u32::from_le_bytes(header[5..9].try_into().unwrap()) as usize;
+    // Frames hold at most TRANSFER_FRAME_SIZE bytes, and are only compressed if that shrinks them
+    if len > TRANSFER_FRAME_SIZE || stored_len > len {
+        return Err(invalid(format!(
+            "Invalid chunk frame header: {} stored byte(s) for {} byte(s) of data, a frame holds at most {}",
+            stored_len, len, TRANSFER_FRAME_SIZE
+        )));
+    }
+    Ok(Some((header[0], stored_len, len)))
+}
+
+/// Whether a chunk file is framed. Leaves `file` positioned at its first frame if so, at its
+/// start otherwise.
+pub async fn is_framed(file: &mut File) -> std::io::Result<bool> {
+    file.seek(SeekFrom::Start(0)).await?;
+    let mut magic = [0; FRAMED_MAGIC.len()];
+    let framed = match file.read_exact(&mut magic).await {
+        Ok(_) => &magic == FRAMED_MAGIC,
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => false,
+        Err(e) => return Err(e),
+    };
+    if !framed {
+        file.seek(SeekFrom::Start(0)).await?;
+    }
+    Ok(framed)
+}
+
+/// Size of the data held by the frames of a framed chunk file, see `is_framed`. Only the frame
+/// headers are read.
+pub async fn framed_size(file: &mut File) -> std::io::Result<u64> {
+    file.seek(SeekFrom::Start(FRAMED_MAGIC.len() as u64))
+        .await?;
+    let mut size = 0;
+    while let Some((_, stored_len, len)) = read_frame_header(file).await? {
+        file.seek(SeekFrom::Current(stored_len as i64)).await?;
+        size += len as u64;
+    }
+    Ok(size)
+}
+
+/// Writes an incoming chunk to its file, framed if `compression` asks for it.
+///
+/// Uncompressed chunks are written as they arrive, except those starting with `FRAMED_MAGIC`,
+/// which are framed so they are not mistaken for a framed file.
+#[derive(Debug)]
+pub struct ChunkWriter {
+    file: File,
+    compression: Compression,
+    framed: Option<bool>, // None until the first bytes are known
+    pending: Vec<u8>,     // Data not written yet, less than a frame of a framed chunk
+}
+
+impl ChunkWriter {
+    pub fn new(file: File, compression: Compression) -> Self {
+        ChunkWriter {
+            file,
+            compression,
+            framed: None,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Writer storing bytes as they are, for a chunk file received from another chunkserver
+    pub fn stored(file: File) -> Self {
+        ChunkWriter {
+            file,
+            compression: Compression::None,
+            framed: Some(false),
+            pending: Vec::new(),
+        }
+    }
+
+    pub async fn write(&mut self, data: &[u8]) -> std::io::Result<()> {
+        match self.framed {
+            Some(false) => return self.file.write_all(data).await,
+            Some(true) => self.pending.extend_from_slice(data),
+            None => {
+                self.pending.extend_from_slice(data);
+                if self.pending.len() < FRAMED_MAGIC.len() {
+                    return Ok(());
+                }
+                self.start().await?;
+            }
+        }
+        self.write_frames(false).await
+    }
+
+    /// Write the rest of the chunk and return its file
+    pub async fn finish(mut self) -> std::io::Result<File> {
+        if self.framed.is_none() {
+            self.start().await?;
+        }
+        self.write_frames(true).await?;
+        Ok(self.file)
+    }
+
+    /// Start the file once its first bytes are known, with `FRAMED_MAGIC` if it is framed
+    async fn start(&mut self) -> std::io::Result<()> {
+        let framed =
+            self.compression != Compression::None || self.pending.starts_with(FRAMED_MAGIC);
+        self.framed = Some(framed);
+        if framed {
+            self.file.write_all(FRAMED_MAGIC).await
+        } else {
+            let pending = std::mem::take(&mut self.pending);
+            self.file.write_all(&pending).await
+        }
+    }
+
+    /// Write the pending data of a framed chunk as frames, only whole ones unless `all`
+    async fn write_frames(&mut self, all: bool) -> std::io::Result<()> {
+        if self.framed != Some(true) {
+            return Ok(());
+        }
+        let len = if all {
+            self.pending.len()
+        } else {
+            self.pending.len() / TRANSFER_FRAME_SIZE * TRANSFER_FRAME_SIZE
+        };
+        if len > 0 {
+            let frames = encode_frames(self.compression, &self.pending[..len]);
+            self.file.write_all(&frames).await?;
+            self.pending.drain(..len);
+        }
+        Ok(())
+    }
+}
+
+/// Reads the data of a chunk file, framed or not
+#[derive(Debug)]
+pub struct ChunkReader {
+    file: File,
+    framed: bool,
+    frame: Vec<u8>,  // Data of the current frame of a framed chunk
+    position: usize, // Of the next byte to read in `frame`
+}
+
+impl ChunkReader {
+    pub async fn new(mut file: File) -> std::io::Result<Self> {
+        let framed = is_framed(&mut file).await?;
+        Ok(ChunkReader {
+            file,
+            framed,
+            frame: Vec::new(),
+            position: 0,
+        })
+    }
+
+    /// Whether the chunk file is framed, see `is_framed`
+    pub fn framed(&self) -> bool {
+        self.framed
+    }
+
+    /// Continue reading at `offset` in the chunk's data. Frames before it are skipped by their
+    /// headers.
+    pub async fn seek(&mut self, offset: u64) -> std::io::Result<()> {
+        if !self.framed {
+            self.file.seek(SeekFrom::Start(offset)).await?;
+            return Ok(());
+        }
+        self.file
+            .seek(SeekFrom::Start(FRAMED_MAGIC.len() as u64))
+            .await?;
+        self.frame.clear();
+        self.position = 0;
+        let mut start = 0;
+        while let Some((codec, stored_len, len)) = read_frame_header(&mut self.file).await? {
+            if start + len as u64 > offset {
+                let mut stored = vec![0; stored_len];
+                self.file.read_exact(&mut stored).await?;
+                self.frame = decode_frame(codec, stored, len)?;
+                self.position = (offset - start) as usize;
+                break;
+            }
+            self.file.seek(SeekFrom::Current(stored_len as i64)).await?;
+            start += len as u64;
+        }
+        Ok(())
+    }
+
+    /// Read data into `buffer`, returning how many bytes were read, 0 at the end of the chunk
+    pub async fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        if !self.framed {
+            return self.file.read(buffer).await;
+        }
+        while self.position == self.frame.len() {
+            let Some((codec, stored_len, len)) = read_frame_header(&mut self.file).await? else {
+                return Ok(0);
+            };
+            let mut stored = vec![0; stored_len];
+            self.file.read_exact(&mut stored).await?;
+            self.frame = decode_frame(codec, stored, len)?;
+            self.position = 0;
+        }
+        let n = buffer.len().min(self.frame.len() - self.position);
+        buffer[..n].copy_from_slice(&self.frame[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
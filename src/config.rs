@@ -1,40 +1,292 @@
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
 use std::fs;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tonic::transport::ClientTlsConfig;
+use tracing_subscriber::filter::Directive;
 
-#[derive(Clone, Debug, Deserialize, Default)]
+use crate::chunkserver_service::SyncWrites;
+use crate::compression::Compression;
+use crate::encryption;
+use crate::error::{Error, Result};
+use crate::logging::LogOutput;
+use crate::master_service::placement::PlacementPolicy;
+use crate::units::{format_duration, format_size};
+use crate::util::{client_tls_config, is_advertisable};
+
+/// Settings of the master nodes. Every setting is optional, see `Default` for the values used
+/// when it is left out.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
 pub struct MasterConfig {
     pub log_path: String,
-    pub cron_interval: u64, // Interval for load balancing cron job
+    #[serde(with = "crate::units::duration")]
+    pub cron_interval: Duration, // Interval for load balancing cron job
     pub heartbeat_failure_threshold: u64, // Determines when a chunkserver is considered unavailable
+    #[serde(with = "crate::units::duration")]
+    pub registration_grace_period: Duration, // Time a newly registered chunkserver has for its first heartbeat, on top of the failure threshold
     pub authentication_file_path: String,
+    #[serde(with = "crate::units::size")]
+    pub min_free_bytes: u64, // Chunkservers with less free disk space are not assigned new chunks
+    pub max_concurrent_repairs: usize, // Maximum number of under-replicated chunks copied at once
+    #[serde(with = "crate::units::size")]
+    pub max_total_bytes: u64, // Quota on the total size of all files, 0 for no limit
+    pub max_files: u64,                // Quota on the number of files, 0 for no limit
+    pub rebalance_threshold: usize, // Chunk count spread between servers that starts a rebalance, 0 to only rebalance on request
+    pub max_concurrent_moves: usize, // Maximum number of chunks moved at once while rebalancing
+    pub data_path: String,          // State kept across restarts, e.g. the leader epoch
+    #[serde(with = "crate::units::duration")]
+    pub lease_duration: Duration, // Seconds a chunkserver stays primary of a chunk without renewal
+    #[serde(with = "crate::units::duration")]
+    pub orphan_grace_period: Duration, // Seconds after becoming leader before chunks of no file are deleted
+    pub metrics_addr: String, // Address serving Prometheus metrics at /metrics, empty to disable
+    pub bind_addr: String, // Address the gRPC server listens on, used if no -a or --bind-addr is given
+    pub advertise_addr: String, // Address other nodes and clients connect to, defaults to bind_addr
+    pub auth_max_failures: usize, // Failed authentications per username or source within auth_failure_window before a lockout, 0 for no limit
+    #[serde(with = "crate::units::duration")]
+    pub auth_failure_window: Duration, // Sliding window counting failed authentications
+    #[serde(with = "crate::units::duration")]
+    pub auth_lockout: Duration, // Lockout after the first burst of failures, doubled with every further burst
+    #[serde(with = "crate::units::duration")]
+    pub auth_max_lockout: Duration, // Upper bound of the lockout, also how long failures are remembered
+    #[serde(with = "crate::units::duration")]
+    pub max_session_duration: Duration, // Time after authenticating beyond which session tokens are no longer renewed
+    pub audit_log: bool, // Whether to record uploads, appends and deletions in audit.log in the data directory
+    #[serde(with = "crate::units::size")]
+    pub audit_log_max_size: u64, // Size at which the audit log is rotated
+    pub audit_log_max_files: usize, // Rotated audit logs kept, the oldest is deleted
+    pub audit_queue_size: usize, // Audit entries waiting to be written before further ones are dropped
+    pub placement_policy: String, // How chunkservers are selected for replicas (e.g., "failure_domain")
+    pub allow_degraded_writes: bool, // Whether uploads get fewer replicas than replication_factor if there are not enough chunkservers, instead of failing
+    #[serde(with = "crate::units::duration")]
+    pub operation_retention: Duration, // How long responses to uploads and deletions are kept for clients retrying them, 0 to not keep them
+    pub max_remembered_operations: usize, // Responses kept at most, the oldest are forgotten first
+    pub shadow_master_failure_threshold: u64, // Missed pings after which the leader stops sending metadata to a shadow master
+    #[serde(with = "crate::units::duration")]
+    pub metadata_send_timeout: Duration, // Time a shadow master has to apply a metadata update before it is retried
+}
+
+impl Default for MasterConfig {
+    fn default() -> Self {
+        MasterConfig {
+            log_path: "logs".to_string(),
+            cron_interval: Duration::from_secs(5),
+            heartbeat_failure_threshold: 2,
+            registration_grace_period: Duration::from_secs(30),
+            authentication_file_path: "auth_data.json".to_string(),
+            min_free_bytes: 0,
+            max_concurrent_repairs: 4,
+            max_total_bytes: 0,
+            max_files: 0,
+            rebalance_threshold: 0,
+            max_concurrent_moves: 4,
+            data_path: "data".to_string(),
+            lease_duration: Duration::from_secs(60),
+            orphan_grace_period: Duration::from_secs(300),
+            metrics_addr: String::new(),
+            bind_addr: String::new(),
+            advertise_addr: String::new(),
+            auth_max_failures: 5,
+            auth_failure_window: Duration::from_secs(60),
+            auth_lockout: Duration::from_secs(30),
+            auth_max_lockout: Duration::from_secs(3600),
+            max_session_duration: Duration::from_secs(12 * 3600),
+            audit_log: true,
+            audit_log_max_size: 64 * 1024 * 1024,
+            audit_log_max_files: 4,
+            audit_queue_size: 1024,
+            placement_policy: "failure_domain".to_string(),
+            allow_degraded_writes: true,
+            operation_retention: Duration::from_secs(600),
+            max_remembered_operations: 10000,
+            shadow_master_failure_threshold: 6,
+            metadata_send_timeout: Duration::from_secs(10),
+        }
+    }
 }
 
-#[derive(Clone, Debug, Deserialize, Default)]
+/// Settings of the chunkservers, all optional
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
 pub struct ChunkServerConfig {
-    pub data_path: String,
+    #[serde(deserialize_with = "one_or_many")]
+    pub data_path: Vec<String>, // Directories holding the chunks, e.g. one per disk; new chunks go to the one with the most free space
     pub log_path: String,
+    #[serde(with = "crate::units::size")]
+    pub min_free_bytes: u64, // Free disk space to keep when accepting uploads and appends
+    #[serde(with = "crate::units::duration")]
+    pub scrub_interval: Duration, // Seconds between passes verifying all chunks against their checksums, 0 to disable
+    #[serde(with = "crate::units::size")]
+    pub scrub_bytes_per_sec: u64, // Rate at which chunks are read while scrubbing, 0 for no limit
+    #[serde(with = "crate::units::size")]
+    pub replication_bytes_per_sec: u64, // Rate at which chunks are sent to other chunkservers, 0 for no limit
+    pub read_buffers: usize, // Chunk reads streamed at once, each through a buffer of one frame; further reads wait
+    pub max_concurrent_writes: usize, // Uploads and appends handled at once, 0 for no limit
+    #[serde(with = "crate::units::duration")]
+    pub write_queue_timeout: Duration, // How long a write waits for a running one to finish before it is rejected
+    pub sync_writes: String, // When written chunks are flushed to disk: "never", "on_close" or "always"
+    pub compression: String, // How new chunks are compressed on disk: "none", "lz4" or "zstd"
+    pub metrics_addr: String, // Address serving Prometheus metrics at /metrics, empty to disable
+    pub bind_addr: String, // Address the gRPC server listens on, used if no -a or --bind-addr is given
+    pub advertise_addr: String, // Address other nodes and clients connect to, defaults to bind_addr
+    #[serde(with = "crate::units::duration")]
+    pub master_retry_deadline: Duration, // How long to keep trying to reach the leader master, e.g. while the masters restart
+    pub failure_domain: String, // Rack, host or zone this chunkserver fails together with, empty for the host of its address
+    #[serde(with = "crate::units::duration")]
+    pub append_id_retention: Duration, // How long the IDs of appends are kept for clients retrying them, 0 to not keep them
+    pub max_remembered_appends: usize, // Append IDs kept at most per chunk, the oldest are forgotten first
 }
 
-#[derive(Clone, Debug, Deserialize, Default)]
+impl Default for ChunkServerConfig {
+    fn default() -> Self {
+        ChunkServerConfig {
+            data_path: vec!["data".to_string()],
+            log_path: "logs".to_string(),
+            min_free_bytes: 0,
+            scrub_interval: Duration::from_secs(3600),
+            scrub_bytes_per_sec: 16 * 1024 * 1024,
+            replication_bytes_per_sec: 0,
+            read_buffers: 64,
+            max_concurrent_writes: 64,
+            write_queue_timeout: Duration::from_secs(1),
+            sync_writes: "on_close".to_string(),
+            compression: "none".to_string(),
+            metrics_addr: String::new(),
+            bind_addr: String::new(),
+            advertise_addr: String::new(),
+            master_retry_deadline: Duration::from_secs(60),
+            failure_domain: String::new(),
+            append_id_retention: Duration::from_secs(600),
+            max_remembered_appends: 100,
+        }
+    }
+}
+
+/// Settings of the client, all optional
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
 pub struct ClientConfig {
     pub log_path: String,
+    pub retry_attempts: u32, // Attempts of an RPC failing for a transient reason, including the first
+    #[serde(with = "crate::units::duration")]
+    pub retry_initial_backoff: Duration, // Wait before the first retry, doubled with every further one
+    #[serde(with = "crate::units::duration")]
+    pub retry_max_backoff: Duration, // Longest wait between two attempts
+    #[serde(with = "crate::units::duration")]
+    pub retry_deadline: Duration, // No retry is started after this time since the first attempt
+    #[serde(with = "crate::units::duration")]
+    pub connect_timeout: Duration, // Time to establish a connection to a master or chunkserver
+    #[serde(with = "crate::units::duration")]
+    pub master_rpc_timeout: Duration, // Time to wait for the answer to a master request
+    #[serde(with = "crate::units::duration")]
+    pub chunk_rpc_timeout: Duration, // Time to wait for the answer to a chunkserver request
+    #[serde(with = "crate::units::duration")]
+    pub operation_timeout: Duration, // Time for a whole client command, 0 for no limit
+    #[serde(with = "crate::units::size")]
+    pub max_upload_bytes_per_sec: u64, // Rate at which chunks are uploaded, shared by all parallel transfers, 0 for no limit
+    #[serde(with = "crate::units::size")]
+    pub max_download_bytes_per_sec: u64, // Rate at which chunks are read, shared by all parallel transfers, 0 for no limit
+    #[serde(with = "crate::units::duration")]
+    pub chunk_cache_ttl: Duration, // How long the chunk list of a file read is reused for further reads, 0 to disable
+    pub encryption_key_file: String, // File holding the key or passphrase files are encrypted with, empty to store them as they are
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        ClientConfig {
+            log_path: "client/logs".to_string(),
+            retry_attempts: 4,
+            retry_initial_backoff: Duration::from_millis(100),
+            retry_max_backoff: Duration::from_secs(2),
+            retry_deadline: Duration::from_secs(10),
+            connect_timeout: Duration::from_secs(5),
+            master_rpc_timeout: Duration::from_secs(10),
+            chunk_rpc_timeout: Duration::from_secs(30),
+            operation_timeout: Duration::ZERO,
+            max_upload_bytes_per_sec: 0,
+            max_download_bytes_per_sec: 0,
+            chunk_cache_ttl: Duration::ZERO,
+            encryption_key_file: String::new(),
+        }
+    }
 }
 
-#[derive(Clone, Debug, Deserialize, Default)]
+/// Settings shared by all nodes. Only `master_addrs` is required.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
 pub struct CommonConfig {
-    pub master_addrs: Vec<String>,        // List of master addresses
-    pub heartbeat_interval: u64,          // Heartbeat interval in seconds
-    pub shadow_master_ping_interval: u64, // Shadow master ping interval in seconds
-    pub chunk_size: u64,                  // Chunk size in bytes
-    pub max_allowed_chunks: usize,        // Maximum number of chunks per chunkserver
-    pub replication_factor: usize,        // Number of chunk replicas
-    pub log_level: String,                // Log level (e.g., "debug", "info", etc.)
-    pub log_output: String,               // Log output (e.g., "stdout", "file", etc.)
-    pub otp_valid_duration: u64,          // Valid duration of OTP in seconds
-    pub use_authentication: bool,         // Whether to use user authentication feature
-}
-
-#[derive(Clone, Debug, Deserialize)]
+    pub master_addrs: Vec<String>, // List of master addresses
+    #[serde(with = "crate::units::duration")]
+    pub heartbeat_interval: Duration, // Heartbeat interval in seconds
+    #[serde(with = "crate::units::duration")]
+    pub shadow_master_ping_interval: Duration, // Shadow master ping interval in seconds
+    #[serde(with = "crate::units::size")]
+    pub chunk_size: u64, // Chunk size in bytes
+    pub max_allowed_chunks: usize, // Maximum number of chunks per chunkserver
+    pub replication_factor: usize, // Number of chunk replicas
+    pub log_level: String,         // Log level (e.g., "debug", "info", etc.)
+    pub log_output: String,        // Log output (e.g., "stdout", "file", etc.)
+    #[serde(with = "crate::units::duration")]
+    pub otp_valid_duration: Duration, // How long a session token stays valid
+    pub use_authentication: bool,  // Whether to use user authentication feature
+    pub cluster_secret: String, // Shared secret signing session tokens and authenticating chunkserver-to-chunkserver transfers
+    #[serde(with = "crate::units::duration")]
+    pub trash_retention_secs: Duration, // How long deleted files can be restored, 0 deletes immediately
+    pub write_quorum: usize, // Minimum number of replicas that must acknowledge a chunk upload or append
+    pub tls: TlsConfig,      // TLS settings shared by all gRPC endpoints
+    #[serde(with = "crate::units::duration")]
+    pub shutdown_timeout: Duration, // Time to finish running requests and background tasks on SIGTERM or Ctrl-C
+}
+
+impl Default for CommonConfig {
+    fn default() -> Self {
+        CommonConfig {
+            master_addrs: Vec::new(),
+            heartbeat_interval: Duration::from_secs(5),
+            shadow_master_ping_interval: Duration::from_secs(5),
+            chunk_size: 64 * 1024 * 1024,
+            max_allowed_chunks: 1000,
+            replication_factor: 3,
+            log_level: "info".to_string(),
+            log_output: "stdout".to_string(),
+            otp_valid_duration: Duration::from_secs(60),
+            use_authentication: false,
+            cluster_secret: String::new(),
+            trash_retention_secs: Duration::ZERO,
+            write_quorum: 1,
+            tls: TlsConfig::default(),
+            shutdown_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
+#[serde(default)]
+pub struct TlsConfig {
+    pub enabled: bool,       // Whether gRPC endpoints use TLS
+    pub cert_path: String,   // PEM certificate presented by masters and chunkservers
+    pub key_path: String,    // PEM private key matching cert_path
+    pub ca_path: String,     // PEM CA certificate used to verify servers
+    pub domain_name: String, // Name verified against the server certificate (e.g., "localhost")
+    #[serde(skip)]
+    pub client: Option<ClientTlsConfig>, // Client configuration with the CA certificate read by `load`
+}
+
+impl TlsConfig {
+    /// Read the CA certificate once, so that connections reuse it instead of reading the file
+    /// again. Fails when TLS is enabled and the file cannot be read.
+    pub fn load(&mut self) -> Result<()> {
+        if self.enabled && self.client.is_none() {
+            self.client = Some(client_tls_config(self)?);
+        }
+        Ok(())
+    }
+}
+
+/// Contents of a config file. Sections a binary does not use may be left out.
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
+#[serde(default)]
 pub struct Config {
     pub master: MasterConfig,
     pub chunkserver: ChunkServerConfig,
@@ -42,10 +294,598 @@ pub struct Config {
     pub common: CommonConfig,
 }
 
-pub fn load_config(path: &str) -> Result<Config, Box<dyn std::error::Error>> {
+/// Every setting of a configuration that would make the nodes misbehave, see `Config::validate`
+#[derive(Debug)]
+pub struct ConfigError {
+    pub violations: Vec<String>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} invalid setting(s)", self.violations.len())?;
+        for violation in &self.violations {
+            write!(f, "\n  - {}", violation)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Deserialize a setting given as one string or a list of strings, e.g. `data_path = "data"` or
+/// `data_path = ["/data1", "/data2"]`
+fn one_or_many<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
+
+/// Whether `addr` has the form host:port, e.g. "127.0.0.1:50001" or "localhost:50001"
+fn is_host_port(addr: &str) -> bool {
+    addr.rsplit_once(':').is_some_and(|(host, port)| {
+        !host.is_empty() && port.parse::<u16>().is_ok_and(|port| port != 0)
+    })
+}
+
+impl Config {
+    /// Commented config file listing every setting with its default value
+    pub fn example() -> String {
+        let Config {
+            master,
+            chunkserver,
+            client,
+            common,
+        } = Config::default();
+        format!(
+            r#"# RustFS configuration. Every setting except common.master_addrs is optional and
+# defaults to the value shown; sections a binary does not use may be left out.
+# Durations take a unit ("500ms", "5s", "2m", "1h", "7d"), sizes are given in bytes or
+# with a unit ("64KiB", "64MiB", "1GB"). Bare integers count seconds and bytes.
+
+[master]
+log_path = "{master_log_path}" # Path to master log storage
+cron_interval = "{cron_interval}" # Interval of the periodic load balancing and repair task
+heartbeat_failure_threshold = {heartbeat_failure_threshold} # Missed heartbeat intervals after which a chunkserver is considered unavailable
+registration_grace_period = "{registration_grace_period}" # Extra time a newly registered chunkserver has for its first heartbeat, e.g. while scanning its chunks
+authentication_file_path = "{authentication_file_path}"
+min_free_bytes = "{master_min_free_bytes}" # Chunkservers with less free disk space receive no new chunks
+max_concurrent_repairs = {max_concurrent_repairs} # Maximum number of under-replicated chunks copied to new servers at once
+max_total_bytes = "{max_total_bytes}" # Quota on the total size of all files, 0 for no limit
+max_files = {max_files} # Quota on the number of files, 0 for no limit
+rebalance_threshold = {rebalance_threshold} # Rebalance once chunk counts of servers differ by more than this, 0 to only rebalance on request
+max_concurrent_moves = {max_concurrent_moves} # Maximum number of chunks moved between servers at once while rebalancing
+data_path = "{master_data_path}" # Path to master state kept across restarts, e.g. the leader epoch
+lease_duration = "{lease_duration}" # How long a chunkserver stays primary of a chunk for appends without renewal
+orphan_grace_period = "{orphan_grace_period}" # Time after becoming leader before chunks belonging to no file are deleted
+metrics_addr = "{master_metrics_addr}" # Address serving Prometheus metrics at /metrics (e.g., 127.0.0.1:9100), empty to disable
+bind_addr = "" # Address the gRPC server listens on (e.g., 0.0.0.0:50001), used if no -a or --bind-addr is given
+advertise_addr = "" # Address other masters, chunkservers and clients connect to, defaults to the bind address
+auth_max_failures = {auth_max_failures} # Failed authentications per username or source address within auth_failure_window before a lockout, 0 for no limit
+auth_failure_window = "{auth_failure_window}" # Sliding window counting failed authentications
+auth_lockout = "{auth_lockout}" # Lockout after the first burst of failures, doubled with every further burst
+auth_max_lockout = "{auth_max_lockout}" # Longest lockout, also how long failures are remembered
+max_session_duration = "{max_session_duration}" # Time after authenticating up to which clients renew their session tokens; then they must authenticate again
+audit_log = {audit_log} # Record who uploaded, appended and deleted what in audit.log in data_path
+audit_log_max_size = "{audit_log_max_size}" # Size at which the audit log is rotated
+audit_log_max_files = {audit_log_max_files} # Rotated audit logs kept, the oldest is deleted
+audit_queue_size = {audit_queue_size} # Audit entries waiting to be written before further ones are dropped
+placement_policy = "{placement_policy}" # How chunkservers are selected for the replicas of a chunk: "least_loaded" (fewest chunks), "most_free_space" or "failure_domain" (most free space, distinct failure domains first)
+allow_degraded_writes = {allow_degraded_writes} # Store uploads with fewer replicas than replication_factor if there are not enough chunkservers; false rejects them
+operation_retention = "{operation_retention}" # How long the response to an upload or deletion is kept, so that a client retrying it gets the same response instead of a second upload; 0 to disable
+max_remembered_operations = {max_remembered_operations} # Responses kept at most for retries, the oldest are forgotten first
+shadow_master_failure_threshold = {shadow_master_failure_threshold} # Missed shadow_master_ping_intervals after which the leader stops sending metadata to a shadow master, until it pings again
+metadata_send_timeout = "{metadata_send_timeout}" # Time a shadow master has to apply a metadata update before the leader retries it
+
+[chunkserver]
+data_path = "{chunkserver_data_path}" # Path to chunk data storage, or a list of paths on separate disks (e.g., ["/data1", "/data2"]) that new chunks are spread over by free space
+log_path = "{chunkserver_log_path}" # Path to log storage
+min_free_bytes = "{chunkserver_min_free_bytes}" # Free disk space to keep; uploads and appends are rejected below it
+scrub_interval = "{scrub_interval}" # Time between passes verifying all chunks against their checksums, 0 to disable
+scrub_bytes_per_sec = "{scrub_bytes_per_sec}" # Data read per second while scrubbing, 0 for no limit
+replication_bytes_per_sec = "{replication_bytes_per_sec}" # Data sent per second when copying chunks to other chunkservers, as when re-replicating, 0 for no limit
+read_buffers = {read_buffers} # Chunk reads streamed at once, each through a 256KiB buffer; further reads wait for one to be free
+max_concurrent_writes = {max_concurrent_writes} # Uploads, appends and incoming chunk transfers handled at once, 0 for no limit
+write_queue_timeout = "{write_queue_timeout}" # How long a write waits for one of max_concurrent_writes to finish before it is rejected and the client tries another replica
+sync_writes = "{sync_writes}" # When written chunks are flushed to disk before being acknowledged: "never" (fastest, a power loss may lose acknowledged writes), "on_close" (each uploaded chunk and append) or "always" (also checksums and the data directory, slowest)
+compression = "{compression}" # How chunks written from now on are compressed on disk: "none", "lz4" (fast) or "zstd" (smaller); chunks stored otherwise stay readable
+metrics_addr = "{chunkserver_metrics_addr}" # Address serving Prometheus metrics at /metrics (e.g., 127.0.0.1:9200), empty to disable
+bind_addr = "" # Address the gRPC server listens on (e.g., 0.0.0.0:50010), used if no -a or --bind-addr is given
+advertise_addr = "" # Address the master, other chunkservers and clients connect to, defaults to the bind address
+master_retry_deadline = "{master_retry_deadline}" # How long to keep trying to reach the leader master at startup and after losing it, e.g. while the masters restart
+failure_domain = "" # Rack, host or zone this chunkserver fails together with; replicas of a chunk go to distinct ones where possible. Empty for the host of the advertised address
+append_id_retention = "{append_id_retention}" # How long the ID of an append is kept, so that a client retrying it gets the same offset instead of appending twice; 0 to disable
+max_remembered_appends = {max_remembered_appends} # Append IDs kept at most per chunk, the oldest are forgotten first
+
+[client]
+log_path = "{client_log_path}" # Path to client log storage
+retry_attempts = {retry_attempts} # Attempts of an RPC failing for a transient reason (e.g., an unreachable server), 1 to never retry
+retry_initial_backoff = "{retry_initial_backoff}" # Wait before the first retry, doubled with every further one and randomized
+retry_max_backoff = "{retry_max_backoff}" # Longest wait between two attempts
+retry_deadline = "{retry_deadline}" # No retry is started after this time since the first attempt
+connect_timeout = "{connect_timeout}" # Time to establish a connection to a master or chunkserver
+master_rpc_timeout = "{master_rpc_timeout}" # Time to wait for the answer to a master request
+chunk_rpc_timeout = "{chunk_rpc_timeout}" # Time to wait for the answer to a chunkserver request, e.g. uploading or reading a chunk
+operation_timeout = "{operation_timeout}" # Time for a whole client command including its retries, 0 for no limit
+max_upload_bytes_per_sec = "{max_upload_bytes_per_sec}" # Data uploaded per second by one client command, across its parallel chunk transfers, 0 for no limit
+max_download_bytes_per_sec = "{max_download_bytes_per_sec}" # Data read per second by one client command, across its parallel chunk transfers, 0 for no limit
+chunk_cache_ttl = "{chunk_cache_ttl}" # How long a client reuses the chunk list of a file it read for further reads of the file, 0 to always ask the master
+encryption_key_file = "{encryption_key_file}" # File holding the key or passphrase to encrypt uploads and decrypt reads with, empty to store files unencrypted
+
+[common]
+master_addrs = ["127.0.0.1:50001"] # Required: addresses of all masters
+heartbeat_interval = "{heartbeat_interval}" # Heartbeat interval of the chunkservers
+shadow_master_ping_interval = "{shadow_master_ping_interval}" # Ping interval of shadow masters
+chunk_size = "{chunk_size}" # Chunk size
+max_allowed_chunks = {max_allowed_chunks} # Maximum number of chunks each chunkserver can store
+replication_factor = {replication_factor} # Number of chunk replicas
+write_quorum = {write_quorum} # Minimum replicas that must acknowledge each uploaded chunk and each append
+log_level = "{log_level}" # Options are "trace", "debug", "info", "warn", "error"
+log_output = "{log_output}" # Options are "stdout", "file", "both", "json"
+otp_valid_duration = "{otp_valid_duration}" # How long a session token stays valid, clients renew it before it expires
+trash_retention_secs = "{trash_retention_secs}" # How long deleted files can be restored with undelete, 0 deletes immediately
+shutdown_timeout = "{shutdown_timeout}" # Time a master or chunkserver stopping on SIGTERM or Ctrl-C waits for running uploads, appends and background tasks
+use_authentication = {use_authentication}
+cluster_secret = "{cluster_secret}" # Shared secret signing session tokens and authenticating chunkserver-to-chunkserver transfers, required with use_authentication
+
+[common.tls]
+enabled = {tls_enabled} # Serve and connect to all gRPC endpoints over TLS
+cert_path = "{cert_path}" # PEM certificate presented by masters and chunkservers
+key_path = "{key_path}" # PEM private key of the certificate
+ca_path = "{ca_path}" # PEM CA certificate used by clients to verify servers
+domain_name = "{domain_name}" # Name the server certificate is verified against
+"#,
+            master_log_path = master.log_path,
+            cron_interval = format_duration(master.cron_interval),
+            heartbeat_failure_threshold = master.heartbeat_failure_threshold,
+            registration_grace_period = format_duration(master.registration_grace_period),
+            authentication_file_path = master.authentication_file_path,
+            master_min_free_bytes = format_size(master.min_free_bytes),
+            max_concurrent_repairs = master.max_concurrent_repairs,
+            max_total_bytes = format_size(master.max_total_bytes),
+            max_files = master.max_files,
+            rebalance_threshold = master.rebalance_threshold,
+            max_concurrent_moves = master.max_concurrent_moves,
+            master_data_path = master.data_path,
+            lease_duration = format_duration(master.lease_duration),
+            orphan_grace_period = format_duration(master.orphan_grace_period),
+            master_metrics_addr = master.metrics_addr,
+            auth_max_failures = master.auth_max_failures,
+            auth_failure_window = format_duration(master.auth_failure_window),
+            auth_lockout = format_duration(master.auth_lockout),
+            auth_max_lockout = format_duration(master.auth_max_lockout),
+            max_session_duration = format_duration(master.max_session_duration),
+            audit_log = master.audit_log,
+            audit_log_max_size = format_size(master.audit_log_max_size),
+            audit_log_max_files = master.audit_log_max_files,
+            audit_queue_size = master.audit_queue_size,
+            placement_policy = master.placement_policy,
+            allow_degraded_writes = master.allow_degraded_writes,
+            operation_retention = format_duration(master.operation_retention),
+            max_remembered_operations = master.max_remembered_operations,
+            shadow_master_failure_threshold = master.shadow_master_failure_threshold,
+            metadata_send_timeout = format_duration(master.metadata_send_timeout),
+            chunkserver_data_path = chunkserver.data_path[0],
+            chunkserver_log_path = chunkserver.log_path,
+            chunkserver_min_free_bytes = format_size(chunkserver.min_free_bytes),
+            scrub_interval = format_duration(chunkserver.scrub_interval),
+            scrub_bytes_per_sec = format_size(chunkserver.scrub_bytes_per_sec),
+            replication_bytes_per_sec = format_size(chunkserver.replication_bytes_per_sec),
+            read_buffers = chunkserver.read_buffers,
+            max_concurrent_writes = chunkserver.max_concurrent_writes,
+            write_queue_timeout = format_duration(chunkserver.write_queue_timeout),
+            sync_writes = chunkserver.sync_writes,
+            compression = chunkserver.compression,
+            chunkserver_metrics_addr = chunkserver.metrics_addr,
+            master_retry_deadline = format_duration(chunkserver.master_retry_deadline),
+            append_id_retention = format_duration(chunkserver.append_id_retention),
+            max_remembered_appends = chunkserver.max_remembered_appends,
+            client_log_path = client.log_path,
+            retry_attempts = client.retry_attempts,
+            retry_initial_backoff = format_duration(client.retry_initial_backoff),
+            retry_max_backoff = format_duration(client.retry_max_backoff),
+            retry_deadline = format_duration(client.retry_deadline),
+            connect_timeout = format_duration(client.connect_timeout),
+            master_rpc_timeout = format_duration(client.master_rpc_timeout),
+            chunk_rpc_timeout = format_duration(client.chunk_rpc_timeout),
+            operation_timeout = format_duration(client.operation_timeout),
+            max_upload_bytes_per_sec = format_size(client.max_upload_bytes_per_sec),
+            max_download_bytes_per_sec = format_size(client.max_download_bytes_per_sec),
+            chunk_cache_ttl = format_duration(client.chunk_cache_ttl),
+            encryption_key_file = client.encryption_key_file,
+            heartbeat_interval = format_duration(common.heartbeat_interval),
+            shadow_master_ping_interval = format_duration(common.shadow_master_ping_interval),
+            chunk_size = format_size(common.chunk_size),
+            max_allowed_chunks = common.max_allowed_chunks,
+            replication_factor = common.replication_factor,
+            write_quorum = common.write_quorum,
+            log_level = common.log_level,
+            log_output = common.log_output,
+            otp_valid_duration = format_duration(common.otp_valid_duration),
+            trash_retention_secs = format_duration(common.trash_retention_secs),
+            shutdown_timeout = format_duration(common.shutdown_timeout),
+            use_authentication = common.use_authentication,
+            cluster_secret = common.cluster_secret,
+            tls_enabled = common.tls.enabled,
+            cert_path = common.tls.cert_path,
+            key_path = common.tls.key_path,
+            ca_path = common.tls.ca_path,
+            domain_name = common.tls.domain_name,
+        )
+    }
+
+    /// Check the settings that would otherwise only fail later at runtime, e.g. as busy loops or
+    /// files that can never be stored, and report all of them at once
+    pub fn validate(&self) -> std::result::Result<(), ConfigError> {
+        let common = &self.common;
+        let master = &self.master;
+        let mut violations = Vec::new();
+        let mut check = |valid: bool, violation: String| {
+            if !valid {
+                violations.push(violation);
+            }
+        };
+
+        check(
+            !common.master_addrs.is_empty(),
+            "common.master_addrs is missing or empty, list the address of at least one master (e.g., master_addrs = [\"127.0.0.1:50001\"])".to_string(),
+        );
+        for addr in &common.master_addrs {
+            check(
+                is_host_port(addr),
+                format!(
+                    "common.master_addrs contains '{}', expected host:port (e.g., 127.0.0.1:50001)",
+                    addr
+                ),
+            );
+        }
+        for (name, value) in [
+            ("common.chunk_size", common.chunk_size),
+            (
+                "master.heartbeat_failure_threshold",
+                master.heartbeat_failure_threshold,
+            ),
+            (
+                "master.shadow_master_failure_threshold",
+                master.shadow_master_failure_threshold,
+            ),
+            (
+                "chunkserver.read_buffers",
+                self.chunkserver.read_buffers as u64,
+            ),
+        ] {
+            check(value > 0, format!("{} is 0, it must be at least 1", name));
+        }
+        // Most intervals are counted in whole seconds
+        for (name, value) in [
+            ("common.heartbeat_interval", common.heartbeat_interval),
+            (
+                "common.shadow_master_ping_interval",
+                common.shadow_master_ping_interval,
+            ),
+            ("master.cron_interval", master.cron_interval),
+            ("master.lease_duration", master.lease_duration),
+        ] {
+            check(
+                value >= Duration::from_secs(1),
+                format!(
+                    "{} is {}, it must be at least \"1s\"",
+                    name,
+                    format_duration(value)
+                ),
+            );
+        }
+        check(
+            !master.metadata_send_timeout.is_zero(),
+            "master.metadata_send_timeout is 0, so every metadata update would time out"
+                .to_string(),
+        );
+        check(
+            master.auth_max_failures == 0
+                || (!master.auth_failure_window.is_zero() && !master.auth_lockout.is_zero()),
+            "master.auth_failure_window and master.auth_lockout must be above 0 unless master.auth_max_failures is 0"
+                .to_string(),
+        );
+        check(
+            master.auth_max_lockout >= master.auth_lockout,
+            format!(
+                "master.auth_max_lockout ({}) is shorter than master.auth_lockout ({})",
+                format_duration(master.auth_max_lockout),
+                format_duration(master.auth_lockout)
+            ),
+        );
+        check(
+            !master.audit_log || (master.audit_log_max_size > 0 && master.audit_queue_size > 0),
+            "master.audit_log_max_size and master.audit_queue_size must be above 0 unless master.audit_log is false"
+                .to_string(),
+        );
+        let data_paths = &self.chunkserver.data_path;
+        check(
+            !data_paths.is_empty() && data_paths.iter().all(|path| !path.is_empty()),
+            "chunkserver.data_path is empty, give the directory or directories to store chunks in"
+                .to_string(),
+        );
+        if let Some(duplicate) = data_paths
+            .iter()
+            .enumerate()
+            .find_map(|(i, path)| data_paths[..i].contains(path).then_some(path))
+        {
+            check(
+                false,
+                format!("chunkserver.data_path lists '{}' twice", duplicate),
+            );
+        }
+        check(
+            self.client.retry_attempts > 0,
+            "client.retry_attempts is 0, it must be at least 1 for the first attempt".to_string(),
+        );
+        for (name, value) in [
+            ("client.connect_timeout", self.client.connect_timeout),
+            ("client.master_rpc_timeout", self.client.master_rpc_timeout),
+            ("client.chunk_rpc_timeout", self.client.chunk_rpc_timeout),
+        ] {
+            check(
+                !value.is_zero(),
+                format!("{} is 0, so every request would time out", name),
+            );
+        }
+        check(
+            self.client.retry_max_backoff >= self.client.retry_initial_backoff,
+            format!(
+                "client.retry_max_backoff ({}) is shorter than client.retry_initial_backoff ({})",
+                format_duration(self.client.retry_max_backoff),
+                format_duration(self.client.retry_initial_backoff)
+            ),
+        );
+        check(
+            self.client.encryption_key_file.is_empty()
+                || common.chunk_size > encryption::HEADER_LEN + encryption::CHUNK_OVERHEAD,
+            format!(
+                "common.chunk_size is too small to hold encrypted data, it must be above {} bytes when client.encryption_key_file is set",
+                encryption::HEADER_LEN + encryption::CHUNK_OVERHEAD
+            ),
+        );
+        check(
+            common.replication_factor > 0,
+            "common.replication_factor is 0, every chunk needs at least one replica".to_string(),
+        );
+        check(
+            common.max_allowed_chunks > 0,
+            "common.max_allowed_chunks is 0, so no chunkserver could store any chunk".to_string(),
+        );
+        check(
+            (1..=common.replication_factor.max(1)).contains(&common.write_quorum),
+            format!(
+                "common.write_quorum ({}) must be between 1 and common.replication_factor ({})",
+                common.write_quorum, common.replication_factor
+            ),
+        );
+        check(
+            !common.use_authentication || master.max_session_duration >= common.otp_valid_duration,
+            format!(
+                "master.max_session_duration ({}) is shorter than common.otp_valid_duration ({})",
+                format_duration(master.max_session_duration),
+                format_duration(common.otp_valid_duration)
+            ),
+        );
+        check(
+            !common.use_authentication || common.otp_valid_duration >= Duration::from_secs(1),
+            "common.otp_valid_duration is below \"1s\", so session tokens would expire as soon as they are issued"
+                .to_string(),
+        );
+        check(
+            !common.use_authentication || !common.cluster_secret.is_empty(),
+            "common.cluster_secret is empty, it is required with use_authentication to sign session tokens"
+                .to_string(),
+        );
+        if let Err(e) = common.log_level.parse::<Directive>() {
+            check(
+                false,
+                format!(
+                    "common.log_level '{}' is not a valid level or filter directive: {}",
+                    common.log_level, e
+                ),
+            );
+        }
+        if let Err(Error::Config(message)) = master.placement_policy.parse::<PlacementPolicy>() {
+            check(false, format!("master.placement_policy: {}", message));
+        }
+        if let Err(Error::Config(message)) = self.chunkserver.sync_writes.parse::<SyncWrites>() {
+            check(false, format!("chunkserver.sync_writes: {}", message));
+        }
+        if let Err(Error::Config(message)) = self.chunkserver.compression.parse::<Compression>() {
+            check(false, format!("chunkserver.compression: {}", message));
+        }
+        if let Err(Error::Config(message)) = common.log_output.parse::<LogOutput>() {
+            check(false, format!("common.log_output: {}", message));
+        }
+        if common.tls.enabled {
+            for (name, path) in [
+                ("cert_path", &common.tls.cert_path),
+                ("key_path", &common.tls.key_path),
+                ("ca_path", &common.tls.ca_path),
+            ] {
+                check(
+                    !path.is_empty(),
+                    format!("common.tls.{} is empty, but TLS is enabled", name),
+                );
+            }
+        }
+        for (name, addr) in [
+            ("master.metrics_addr", &master.metrics_addr),
+            ("chunkserver.metrics_addr", &self.chunkserver.metrics_addr),
+        ] {
+            check(
+                addr.is_empty() || addr.parse::<SocketAddr>().is_ok(),
+                format!(
+                    "{} '{}' is not a socket address (e.g., 127.0.0.1:9100)",
+                    name, addr
+                ),
+            );
+        }
+
+        for (name, bind, advertise) in [
+            ("master", &master.bind_addr, &master.advertise_addr),
+            (
+                "chunkserver",
+                &self.chunkserver.bind_addr,
+                &self.chunkserver.advertise_addr,
+            ),
+        ] {
+            check(
+                bind.is_empty() || bind.parse::<SocketAddr>().is_ok(),
+                format!(
+                    "{}.bind_addr '{}' is not a socket address (e.g., 0.0.0.0:50001)",
+                    name, bind
+                ),
+            );
+            check(
+                advertise.is_empty() || is_advertisable(advertise),
+                format!(
+                    "{}.advertise_addr '{}' is not a host:port other nodes can connect to",
+                    name, advertise
+                ),
+            );
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError { violations })
+        }
+    }
+}
+
+/// Environment variable naming the config file when no `--config` flag is given
+pub const CONFIG_ENV_VAR: &str = "RUSTFS_CONFIG";
+
+/// Config file used when neither `--config` nor `RUSTFS_CONFIG` is given
+pub const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+/// Path of the config file: the `--config` flag if given, otherwise `RUSTFS_CONFIG` if set and
+/// not empty, otherwise `config.toml` in the working directory
+pub fn resolve_config_path(flag: Option<&str>, env_value: Option<&str>) -> String {
+    flag.or(env_value.filter(|value| !value.is_empty()))
+        .unwrap_or(DEFAULT_CONFIG_PATH)
+        .to_string()
+}
+
+/// Path of the config file for a binary invoked with the given `--config` flag, see
+/// `resolve_config_path`
+pub fn config_path(flag: Option<&str>) -> String {
+    resolve_config_path(flag, std::env::var(CONFIG_ENV_VAR).ok().as_deref())
+}
+
+/// Prefix of the environment variables overriding settings of the config file, see
+/// `apply_env_overrides`
+pub const ENV_PREFIX: &str = "RUSTFS_";
+
+/// Load a config file, override its settings with `RUSTFS_<SECTION>__<KEY>` environment
+/// variables and validate the result
+pub fn load_config(path: &str) -> Result<Config> {
     // Read the config file
-    let config_content = fs::read_to_string(path)?;
-    // Parse the TOML content into the Config struct
-    let config: Config = toml::from_str(&config_content)?;
+    let config_content = fs::read_to_string(path)
+        .map_err(|e| Error::Config(format!("Failed to read config file '{}': {}", path, e)))?;
+    // Parse the TOML content, then layer the environment over it
+    let mut settings: toml::Value = toml::from_str(&config_content)?;
+    apply_env_overrides(&mut settings, std::env::vars())?;
+    let mut config: Config = settings.try_into()?;
+    config.validate()?;
+    config.common.tls.load()?;
     Ok(config)
 }
+
+/// Override settings of a parsed config file with environment variables named
+/// `RUSTFS_<SECTION>__<KEY>`, e.g. `RUSTFS_CHUNKSERVER__DATA_PATH=/data` or
+/// `RUSTFS_COMMON__TLS__ENABLED=true`. Values are converted to the type of the setting; lists
+/// are given comma-separated, e.g. `RUSTFS_COMMON__MASTER_ADDRS=10.0.0.1:50001,10.0.0.2:50001`.
+///
+/// Variables without a double underscore, such as `RUSTFS_CONFIG`, are not settings and are
+/// skipped. Every variable naming an unknown setting or holding an invalid value is reported.
+pub fn apply_env_overrides(
+    settings: &mut toml::Value,
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> std::result::Result<(), ConfigError> {
+    let defaults = toml::Value::try_from(Config::default()).expect("Defaults are valid TOML");
+    let mut violations = Vec::new();
+    for (name, value) in vars {
+        let Some(path) = name
+            .strip_prefix(ENV_PREFIX)
+            .filter(|path| path.contains("__"))
+        else {
+            continue;
+        };
+        let keys: Vec<String> = path.split("__").map(str::to_lowercase).collect();
+        let default = keys
+            .iter()
+            .try_fold(&defaults, |value, key| value.get(key.as_str()));
+        match default.map(|default| env_value(default, &value)) {
+            Some(Ok(value)) => set_setting(settings, &keys, value),
+            Some(Err(e)) => violations.push(format!("{}: {}", name, e)),
+            None => violations.push(format!(
+                "{} does not name a setting, expected e.g. RUSTFS_COMMON__CHUNK_SIZE",
+                name
+            )),
+        }
+    }
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(ConfigError { violations })
+    }
+}
+
+/// Convert the value of an environment variable to the type of the setting's `default`
+fn env_value(default: &toml::Value, value: &str) -> std::result::Result<toml::Value, String> {
+    match default {
+        toml::Value::String(_) => Ok(toml::Value::String(value.to_string())),
+        toml::Value::Integer(_) => value
+            .trim()
+            .parse::<u64>()
+            .ok()
+            .and_then(|number| i64::try_from(number).ok())
+            .map(toml::Value::Integer)
+            .ok_or_else(|| format!("expected a non-negative integer, got '{}'", value)),
+        toml::Value::Boolean(_) => match value.trim().to_lowercase().as_str() {
+            "true" | "1" => Ok(toml::Value::Boolean(true)),
+            "false" | "0" => Ok(toml::Value::Boolean(false)),
+            _ => Err(format!("expected true or false, got '{}'", value)),
+        },
+        toml::Value::Array(_) => Ok(toml::Value::Array(
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| toml::Value::String(item.to_string()))
+                .collect(),
+        )),
+        _ => Err("names a section, not a setting".to_string()),
+    }
+}
+
+/// Set the setting at `keys` in `settings`, adding missing sections
+fn set_setting(settings: &mut toml::Value, keys: &[String], value: toml::Value) {
+    let Some((last, sections)) = keys.split_last() else {
+        return;
+    };
+    let mut table = settings;
+    for key in sections {
+        let toml::Value::Table(current) = table else {
+            return;
+        };
+        table = current
+            .entry(key.clone())
+            .or_insert_with(|| toml::Value::Table(Default::default()));
+    }
+    if let toml::Value::Table(current) = table {
+        current.insert(last.clone(), value);
+    }
+}
@@ -0,0 +1,129 @@
+// Username and password of the CLI binaries, from flags, the environment, a credential file or a
+// prompt, so that passwords need not be passed with -p
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::io::{ErrorKind, IsTerminal};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+use crate::error::{Error, Result};
+
+/// Environment variables holding the username and password
+pub const USERNAME_VAR: &str = "RUSTFS_USERNAME";
+pub const PASSWORD_VAR: &str = "RUSTFS_PASSWORD";
+
+/// Username and password to authenticate with
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+/// Username and password given by one source, either of which may be missing
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PartialCredentials {
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl PartialCredentials {
+    /// Credentials in `RUSTFS_USERNAME` and `RUSTFS_PASSWORD`
+    pub fn from_env() -> Self {
+        let var = |name| {
+            env::var(name)
+                .ok()
+                .filter(|value: &String| !value.is_empty())
+        };
+        PartialCredentials {
+            username: var(USERNAME_VAR),
+            password: var(PASSWORD_VAR),
+        }
+    }
+
+    /// Credentials in the TOML file at `path`, none if it does not exist. Warns if other users
+    /// may read the file.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.into()),
+        };
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(path)?.permissions().mode();
+            if mode & 0o004 != 0 {
+                warn!(
+                    "The credential file {} is readable by every user, restrict it with 'chmod 600 {}'",
+                    path.display(),
+                    path.display()
+                );
+            }
+        }
+        toml::from_str(&content).map_err(|e| {
+            Error::Config(format!("Invalid credential file {}: {}", path.display(), e))
+        })
+    }
+}
+
+/// Default credential file, `~/.rustfs/credentials`
+pub fn credentials_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| Path::new(&home).join(".rustfs").join("credentials"))
+}
+
+/// Credentials to authenticate with, given the `-u` and `-p` flags.
+///
+/// Each of the username and password is taken from the flags, else the environment, else the
+/// credential file; the password in the file only if it belongs to the resolved username. A
+/// missing password is asked for if stdin is a terminal. Returns None without a username, and
+/// a usage error if the password stays unknown.
+pub fn resolve_credentials(flags: PartialCredentials) -> Result<Option<Credentials>> {
+    let file = match credentials_path() {
+        Some(path) => PartialCredentials::from_file(&path)?,
+        None => PartialCredentials::default(),
+    };
+    merge_credentials(flags, PartialCredentials::from_env(), file, prompt_password)
+}
+
+/// Combine the credentials of the sources in the order of precedence, see `resolve_credentials`
+pub fn merge_credentials(
+    flags: PartialCredentials,
+    env: PartialCredentials,
+    file: PartialCredentials,
+    prompt: impl FnOnce(&str) -> Result<Option<String>>,
+) -> Result<Option<Credentials>> {
+    let Some(username) = flags
+        .username
+        .or(env.username)
+        .or_else(|| file.username.clone())
+    else {
+        return Ok(None);
+    };
+    let file_password = file.password.filter(|_| {
+        file.username
+            .as_ref()
+            .is_none_or(|owner| *owner == username)
+    });
+    let password = match flags.password.or(env.password).or(file_password) {
+        Some(password) => password,
+        None => prompt(&username)?.ok_or_else(|| {
+            Error::Usage(format!(
+                "No password for '{}': use -p, set {}, add it to ~/.rustfs/credentials or run interactively",
+                username, PASSWORD_VAR
+            ))
+        })?,
+    };
+    Ok(Some(Credentials { username, password }))
+}
+
+/// Ask for the password of `username` on the terminal without echoing it, None if stdin is not
+/// a terminal
+fn prompt_password(username: &str) -> Result<Option<String>> {
+    if !std::io::stdin().is_terminal() {
+        return Ok(None);
+    }
+    let password = rpassword::prompt_password(format!("Password for '{}': ", username))?;
+    Ok(Some(password))
+}
@@ -0,0 +1,192 @@
+// Client-side encryption of file contents, so that chunkservers and masters only see ciphertext
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rand::RngCore;
+use std::fmt;
+
+use crate::error::{Error, Result};
+
+/// Start of the first chunk of an encrypted file, followed by the file's salt
+pub const ENCRYPTED_MAGIC: &[u8; 8] = b"RUSTFSE\x01";
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const TAG_LEN: usize = 16;
+
+/// Magic and salt at the start of the first chunk
+pub const HEADER_LEN: u64 = (ENCRYPTED_MAGIC.len() + SALT_LEN) as u64;
+
+/// Nonce and authentication tag added to the data of every chunk
+pub const CHUNK_OVERHEAD: u64 = (NONCE_LEN + TAG_LEN) as u64;
+
+/// Secret configured by `encryption_key_file`, from which a key is derived for every file
+pub struct Encryption {
+    secret: Vec<u8>,
+}
+
+impl fmt::Debug for Encryption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Encryption { .. }")
+    }
+}
+
+impl Encryption {
+    /// Read the key or passphrase in `key_file`, without its trailing newline
+    pub fn load(key_file: &str) -> Result<Self> {
+        let mut secret = std::fs::read(key_file).map_err(|e| {
+            Error::Config(format!(
+                "Failed to read encryption key file '{}': {}",
+                key_file, e
+            ))
+        })?;
+        while secret
+            .last()
+            .is_some_and(|byte| *byte == b'\n' || *byte == b'\r')
+        {
+            secret.pop();
+        }
+        if secret.is_empty() {
+            return Err(Error::Config(format!(
+                "Encryption key file '{}' is empty",
+                key_file
+            )));
+        }
+        Ok(Encryption { secret })
+    }
+
+    /// Key of a new file, with a random salt
+    pub fn new_file(&self) -> Result<FileKey> {
+        let mut salt = [0; SALT_LEN];
+        rand::thread_rng().fill_bytes(&mut salt);
+        self.file_key(salt)
+    }
+
+    /// Key of the file whose first chunk starts with `header`, see `HEADER_LEN`
+    pub fn open_file(&self, file_name: &str, header: &[u8]) -> Result<FileKey> {
+        if header.len() < HEADER_LEN as usize || !header.starts_with(ENCRYPTED_MAGIC) {
+            return Err(Error::Decryption(format!(
+                "File '{}' is not encrypted",
+                file_name
+            )));
+        }
+        let salt = header[ENCRYPTED_MAGIC.len()..HEADER_LEN as usize]
+            .try_into()
+            .unwrap();
+        self.file_key(salt)
+    }
+
+    fn file_key(&self, salt: [u8; SALT_LEN]) -> Result<FileKey> {
+        let mut key = [0; 32];
+        Argon2::default()
+            .hash_password_into(&self.secret, &salt, &mut key)
+            .map_err(|e| Error::Config(format!("Failed to derive the encryption key: {}", e)))?;
+        Ok(FileKey {
+            salt,
+            cipher: XChaCha20Poly1305::new(&key.into()),
+        })
+    }
+}
+
+/// Plaintext bytes stored in chunk `index` of an encrypted file with chunks of `chunk_size`
+pub fn chunk_capacity(chunk_size: u64, index: u64) -> u64 {
+    let header = if index == 0 { HEADER_LEN } else { 0 };
+    chunk_size.saturating_sub(header + CHUNK_OVERHEAD).max(1)
+}
+
+/// Bytes stored for an encrypted file holding `size` bytes, at least its first chunk
+pub fn encrypted_size(chunk_size: u64, size: u64) -> u64 {
+    let first = chunk_capacity(chunk_size, 0);
+    let chunks = 1 + size
+        .saturating_sub(first)
+        .div_ceil(chunk_capacity(chunk_size, 1));
+    size + HEADER_LEN + chunks * CHUNK_OVERHEAD
+}
+
+/// Plaintext bytes of chunk `index` of an encrypted file, stored in `stored_size` bytes
+pub fn plaintext_size(index: u64, stored_size: u64) -> u64 {
+    let header = if index == 0 { HEADER_LEN } else { 0 };
+    stored_size.saturating_sub(header + CHUNK_OVERHEAD)
+}
+
+/// Key of one file, derived from the secret and the file's salt.
+///
+/// Every chunk is sealed on its own with a random nonce, its index and whether it is the last
+/// chunk being authenticated along with it, so chunks cannot be swapped, moved to another file
+/// or cut off the end of the file without failing to decrypt.
+pub struct FileKey {
+    salt: [u8; SALT_LEN],
+    cipher: XChaCha20Poly1305,
+}
+
+impl fmt::Debug for FileKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("FileKey { .. }")
+    }
+}
+
+impl FileKey {
+    /// Stored form of chunk `index`, starting with the file's header for the first chunk.
+    /// `last` tells whether the file ends with this chunk.
+    pub fn seal(&self, index: u64, last: bool, data: &[u8]) -> Result<Vec<u8>> {
+        let mut nonce = [0; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let sealed = self
+            .cipher
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: data,
+                    aad: &associated_data(index, last),
+                },
+            )
+            .map_err(|_| Error::InvalidData(format!("Failed to encrypt chunk {}", index)))?;
+        let mut chunk = Vec::with_capacity(HEADER_LEN as usize + NONCE_LEN + sealed.len());
+        if index == 0 {
+            chunk.extend_from_slice(ENCRYPTED_MAGIC);
+            chunk.extend_from_slice(&self.salt);
+        }
+        chunk.extend_from_slice(&nonce);
+        chunk.extend_from_slice(&sealed);
+        Ok(chunk)
+    }
+
+    /// Data of chunk `index` of `file_name`, from its stored form. `last` tells whether the file
+    /// is listed to end with this chunk, which fails if chunks were removed from its end.
+    pub fn open(&self, file_name: &str, index: u64, last: bool, chunk: &[u8]) -> Result<Vec<u8>> {
+        let chunk = if index == 0 {
+            chunk.get(HEADER_LEN as usize..).unwrap_or_default()
+        } else {
+            chunk
+        };
+        if chunk.len() < CHUNK_OVERHEAD as usize {
+            return Err(Error::Decryption(format!(
+                "Chunk {} of '{}' is too short to be encrypted",
+                index, file_name
+            )));
+        }
+        let (nonce, sealed) = chunk.split_at(NONCE_LEN);
+        self.cipher
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: sealed,
+                    aad: &associated_data(index, last),
+                },
+            )
+            .map_err(|_| {
+                Error::Decryption(format!(
+                    "Chunk {} of '{}' does not decrypt, the encryption key is wrong or the data was modified",
+                    index, file_name
+                ))
+            })
+    }
+}
+
+/// Data authenticated along with chunk `index`: its index and whether it is the last one
+fn associated_data(index: u64, last: bool) -> [u8; 9] {
+    let mut data = [0; 9];
+    data[..8].copy_from_slice(&index.to_le_bytes());
+    data[8] = last as u8;
+    data
+}
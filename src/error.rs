@@ -0,0 +1,154 @@
+// Error type shared by the RustFS library and binaries
+use std::process::ExitCode;
+
+use crate::retry::{is_overloaded, is_timeout};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("File '{0}' not found")]
+    NotFound(String),
+
+    #[error("No available servers: {0}")]
+    NoAvailableServers(String),
+
+    #[error("No leader master: {0}")]
+    NoLeader(String),
+
+    #[error("Authentication failed: {0}")]
+    Auth(String),
+
+    #[error("Permission denied: {0}")]
+    PermissionDenied(String),
+
+    #[error("Write quorum not reached: {0}")]
+    QuorumNotReached(String),
+
+    #[error("Degraded: {0}")]
+    Degraded(String),
+
+    #[error("Cluster full: {0}")]
+    ClusterFull(String),
+
+    #[error("Quota exceeded: {0}")]
+    QuotaExceeded(String),
+
+    #[error("Invalid path: {0}")]
+    InvalidPath(String),
+
+    #[error("Invalid data: {0}")]
+    InvalidData(String),
+
+    #[error("Verification failed: {0}")]
+    VerificationFailed(String),
+
+    #[error("Decryption failed: {0}")]
+    Decryption(String),
+
+    #[error("Copy failed: {0}")]
+    CopyFailed(String),
+
+    #[error("{0}")]
+    Usage(String), // Invalid command line, the message says how to use the command
+
+    #[error("Configuration error: {0}")]
+    Config(String),
+
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(#[from] crate::config::ConfigError),
+
+    #[error("Timed out: {0}")]
+    Timeout(String),
+
+    #[error("Interrupted: {0}")]
+    Interrupted(String), // Stopped by Ctrl-C or SIGTERM
+
+    #[error("Transport error: {0}")]
+    Transport(#[from] tonic::transport::Error),
+
+    #[error("RPC error: {0}")]
+    Rpc(Box<tonic::Status>),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl From<toml::de::Error> for Error {
+    fn from(e: toml::de::Error) -> Self {
+        Error::Config(e.to_string())
+    }
+}
+
+impl From<tonic::Status> for Error {
+    fn from(status: tonic::Status) -> Self {
+        match status.code() {
+            tonic::Code::PermissionDenied => Error::PermissionDenied(status.message().to_string()),
+            _ if is_timeout(&status) => Error::Timeout(status.message().to_string()),
+            _ => Error::Rpc(Box::new(status)),
+        }
+    }
+}
+
+impl Error {
+    /// Whether a chunkserver rejected the request because it is out of disk space
+    pub fn is_out_of_space(&self) -> bool {
+        matches!(self, Error::Rpc(status)
+            if status.code() == tonic::Code::ResourceExhausted && !is_overloaded(status))
+    }
+
+    /// Whether a chunkserver rejected the data because it does not match its checksum, i.e. it
+    /// was corrupted on the way
+    pub fn is_data_loss(&self) -> bool {
+        matches!(self, Error::Rpc(status) if status.code() == tonic::Code::DataLoss)
+    }
+
+    /// Whether a chunkserver does not hold the chunk or could not be reached, e.g. as the chunk
+    /// moved since its replicas were listed
+    pub fn is_missing_replica(&self) -> bool {
+        match self {
+            Error::NoAvailableServers(_) | Error::Transport(_) => true,
+            Error::Rpc(status) => matches!(
+                status.code(),
+                tonic::Code::NotFound | tonic::Code::Unavailable
+            ),
+            _ => false,
+        }
+    }
+
+    /// Process exit code used by the CLI binaries for this error
+    pub fn exit_code(&self) -> ExitCode {
+        ExitCode::from(self.code())
+    }
+
+    /// Number of the exit code, also reported by `client --json`: 2 for a usage or
+    /// configuration error, 3 if a file was not found, 4 if authentication failed or was refused,
+    /// 5 if no server could serve the request, 6 if a write only partly succeeded, 130 if
+    /// interrupted and 1 otherwise
+    pub fn code(&self) -> u8 {
+        match self {
+            Error::Usage(_) | Error::Config(_) | Error::InvalidConfig(_) => 2,
+            Error::NotFound(_) => 3,
+            Error::Auth(_) | Error::PermissionDenied(_) => 4,
+            Error::NoAvailableServers(_)
+            | Error::NoLeader(_)
+            | Error::Timeout(_)
+            | Error::Transport(_)
+            | Error::ClusterFull(_) => 5,
+            Error::QuorumNotReached(_) | Error::Degraded(_) => 6,
+            Error::Interrupted(_) => 130,
+            Error::QuotaExceeded(_)
+            | Error::VerificationFailed(_)
+            | Error::Decryption(_)
+            | Error::CopyFailed(_)
+            | Error::Rpc(_)
+            | Error::Io(_)
+            | Error::InvalidPath(_)
+            | Error::InvalidData(_)
+            | Error::Json(_) => 1,
+        }
+    }
+}
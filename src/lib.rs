@@ -1,7 +1,28 @@
+pub mod audit;
+pub mod auth;
+pub mod bench;
+pub mod buffer_pool;
 pub mod chunkserver_impl;
 pub mod chunkserver_service;
+pub mod client;
+pub mod cluster_auth;
+pub mod compression;
 pub mod config;
+pub mod credentials;
+pub mod encryption;
+pub mod error;
+pub mod logging;
 pub mod master_impl;
 pub mod master_service;
+pub mod metrics;
+pub mod namespace;
 pub mod proto;
+pub mod reload;
+pub mod request_id;
+pub mod retry;
+pub mod shutdown;
+pub mod testing;
+pub mod throttle;
+pub mod token;
+pub mod units;
 pub mod util;
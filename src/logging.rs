@@ -0,0 +1,152 @@
+// Logger setup shared by the RustFS binaries
+use std::str::FromStr;
+use tracing::Subscriber;
+use tracing_appender::rolling;
+use tracing_subscriber::fmt::format::{Format, Json, JsonFields};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{fmt, layer::SubscriberExt, reload, EnvFilter, Registry};
+
+use crate::config::CommonConfig;
+use crate::error::{Error, Result};
+
+/// Where log events are written and in which format, see `log_output` in config.toml
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogOutput {
+    Stdout, // Text to stdout
+    File,   // Text to the daily rotated log file
+    Both,   // Text to stdout and the log file
+    Json,   // One JSON object per event to stdout and the log file
+}
+
+impl FromStr for LogOutput {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "stdout" => Ok(LogOutput::Stdout),
+            "file" => Ok(LogOutput::File),
+            "both" => Ok(LogOutput::Both),
+            "json" => Ok(LogOutput::Json),
+            _ => Err(Error::Config(format!(
+                "Invalid log_output '{}', expected \"stdout\", \"file\", \"both\" or \"json\"",
+                value
+            ))),
+        }
+    }
+}
+
+impl LogOutput {
+    pub fn to_stdout(self) -> bool {
+        self != LogOutput::File
+    }
+
+    pub fn to_file(self) -> bool {
+        self != LogOutput::Stdout
+    }
+}
+
+/// Stream that the log events `LogOutput` sends to stdout are written to instead
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Console {
+    Stdout,
+    Stderr, // Keeps stdout free for the output of a command, e.g. the client's `--json`
+}
+
+impl Console {
+    fn writer(self) -> BoxMakeWriter {
+        match self {
+            Console::Stdout => BoxMakeWriter::new(std::io::stdout),
+            Console::Stderr => BoxMakeWriter::new(std::io::stderr),
+        }
+    }
+}
+
+/// Layer writing one JSON object per event, with the current span, file and line
+fn json_layer<S>() -> fmt::Layer<S, JsonFields, Format<Json>>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fmt::layer()
+        .json()
+        .flatten_event(true)
+        .with_current_span(true)
+        .with_file(true)
+        .with_line_number(true)
+}
+
+/// Filter of a `log_level`, on top of the directives in `RUST_LOG`
+fn env_filter(log_level: &str) -> Result<EnvFilter> {
+    let directive = log_level
+        .parse()
+        .map_err(|e| Error::Config(format!("Invalid log_level '{}': {}", log_level, e)))?;
+    Ok(EnvFilter::from_default_env().add_directive(directive))
+}
+
+/// Handle changing the level of the installed logger while the binary runs
+#[derive(Clone, Debug)]
+pub struct LogHandle(reload::Handle<EnvFilter, Registry>);
+
+impl LogHandle {
+    pub fn set_level(&self, log_level: &str) -> Result<()> {
+        let filter = env_filter(log_level)?;
+        self.0
+            .reload(filter)
+            .map_err(|e| Error::Config(format!("Failed to change the log level: {}", e)))
+    }
+
+    /// Directives of the filter in effect, e.g. "debug"
+    pub fn filter(&self) -> Result<String> {
+        self.0
+            .with_current(|filter| filter.to_string())
+            .map_err(|e| Error::Config(format!("Failed to read the log level: {}", e)))
+    }
+}
+
+/// Install the global logger of a binary as configured by `log_level` and `log_output`.
+///
+/// Log files are written to `log_path`, named after `file_name` and rotated daily. The returned
+/// handle changes the level later on.
+pub fn init(config: &CommonConfig, log_path: &str, file_name: &str) -> Result<LogHandle> {
+    init_with_console(config, log_path, file_name, Console::Stdout)
+}
+
+/// Install the global logger like `init`, writing what goes to stdout to `console` instead
+pub fn init_with_console(
+    config: &CommonConfig,
+    log_path: &str,
+    file_name: &str,
+    console: Console,
+) -> Result<LogHandle> {
+    let output: LogOutput = config.log_output.parse()?;
+    let (env_filter, handle) = reload::Layer::new(env_filter(&config.log_level)?);
+
+    // Fields such as chunk IDs and file names become keys of the JSON objects
+    let json = output == LogOutput::Json;
+    let stdout_text = (output.to_stdout() && !json)
+        .then(|| fmt::layer().with_writer(console.writer()).with_ansi(true));
+    let stdout_json =
+        (output.to_stdout() && json).then(|| json_layer().with_writer(console.writer()));
+    let file_appender = output
+        .to_file()
+        .then(|| rolling::daily(log_path, file_name));
+    let (file_text, file_json) = match file_appender {
+        Some(appender) if json => (None, Some(json_layer().with_writer(appender))),
+        // Disable ANSI escape codes for file logs
+        Some(appender) => (
+            Some(fmt::layer().with_writer(appender).with_ansi(false)),
+            None,
+        ),
+        None => (None, None),
+    };
+
+    let subscriber = Registry::default()
+        .with(env_filter)
+        .with(stdout_text)
+        .with(stdout_json)
+        .with(file_text)
+        .with(file_json);
+    tracing::subscriber::set_global_default(subscriber)
+        .map_err(|e| Error::Config(format!("Failed to set up logging: {}", e)))?;
+    Ok(LogHandle(handle))
+}
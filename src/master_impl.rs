@@ -1,20 +1,48 @@
 // Implements the gRPC server behavior defined in the Master trait
-use std::cmp::Reverse;
-use std::collections::{BinaryHeap, HashMap};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use tonic::{Request, Response, Status};
 use tracing::{debug, error, info, warn};
+use uuid::Uuid;
 
 use crate::proto::master::{
-    AssignRequest, AssignResponse, AuthenticateRequest, AuthenticateResponse, ChunkInfo,
-    DeleteFileRequest, DeleteFileResponse, FileChunkMapping, FileChunkMappingRequest,
-    HeartbeatRequest, HeartbeatResponse, PingMasterRequest, PingMasterResponse, RegisterRequest,
-    RegisterResponse, UpdateMetadataRequest, UpdateMetadataResponse,
+    AssignNextChunkRequest, AssignRequest, AssignResponse, AuthenticateRequest,
+    AuthenticateResponse, ChunkInfo, ClusterStatusRequest, ClusterStatusResponse, CopyFileRequest,
+    CopyStatusRequest, CopyStatusResponse, DecommissionRequest, DecommissionResponse,
+    DeleteFileRequest, DeleteFileResponse, DeletePrefixRequest, DeletePrefixResponse,
+    FileChunkMapping, FileChunkMappingRequest, FileInfoRequest, FileInfoResponse, GetLeaderRequest,
+    GetLeaderResponse, GetMetadataRequest, GetMetadataResponse, HeartbeatRequest,
+    HeartbeatResponse, LeaderChangedRequest, LeaderChangedResponse, ListEntry, ListFilesRequest,
+    ListFilesResponse, PingMasterRequest, PingMasterResponse, QueryAuditRequest,
+    QueryAuditResponse, RebalanceRequest, RebalanceStatusResponse, RecordAppendRequest,
+    RecordAppendResponse, RegisterRequest, RegisterResponse, ReloadConfigRequest,
+    ReloadConfigResponse, RenewTokenRequest, ReportBadReplicaRequest, ReportBadReplicaResponse,
+    ReportCorruptChunkRequest, ReportCorruptChunkResponse, ReportFailedReplicasRequest,
+    ReportFailedReplicasResponse, SnapshotFileRequest, SnapshotFileResponse, TruncateFileRequest,
+    TruncateFileResponse, UndeleteRequest, UndeleteResponse, UnregisterRequest, UnregisterResponse,
+    UpdateMetadataRequest, UpdateMetadataResponse, UsageRequest, UsageResponse, VoteRequest,
+    VoteResponse,
 };
 
+use crate::audit::{outcome, AuditSource};
+use crate::auth::Role;
+use crate::cluster_auth::sign_request;
+use crate::config::TlsConfig;
+use crate::error::Error;
+use crate::namespace::{find_conflict, is_under, normalize_dir, normalize_path};
+use crate::util::{connect_channel, LEADER_ADDRESS_KEY};
+
 // Import `MasterService` from `master_service.rs`
-use crate::master_service::MasterService;
+use crate::master_service::operations::{CompletedOperation, OperationResponse};
+use crate::master_service::placement::select_servers;
+use crate::master_service::{
+    chunk_infos, elect_primary, get_metadata_fields, heartbeat_digest, heartbeat_fields,
+    leader_changed_fields, log_map, metadata_digest, ping_master_fields, register_fields,
+    report_corrupt_chunk_fields, unregister_fields, update_metadata_fields, CopyProgress, Identity,
+    MasterService, ScrubStatus, ServerCapacity, Usage,
+};
 use crate::proto::master::master_server::Master;
 
 #[tonic::async_trait]
@@ -23,21 +51,88 @@ impl Master for Arc<MasterService> {
         &self,
         request: Request<AuthenticateRequest>,
     ) -> Result<Response<AuthenticateResponse>, Status> {
+        let source = request
+            .remote_addr()
+            .map_or_else(|| "unknown".to_string(), |addr| addr.ip().to_string());
         let request_inner = request.into_inner();
         let username = request_inner.username;
         let password = request_inner.password;
         info!("[Authenticate] authenticating user {}", username);
 
+        // Locked out usernames and sources are rejected without checking the password
+        let locked_for =
+            self.auth_throttle
+                .lock()
+                .await
+                .locked_for(&username, &source, Instant::now());
+        if let Some(locked_for) = locked_for {
+            warn!(
+                username = %username,
+                source = %source,
+                retry_after_secs = locked_for.as_secs_f64().ceil() as u64,
+                "[Authenticate] Rejected attempt during lockout"
+            );
+            return Err(Status::resource_exhausted(format!(
+                "Too many failed authentication attempts, retry in {}s",
+                locked_for.as_secs_f64().ceil() as u64
+            )));
+        }
+
         match self.authenticate_user(&username, &password).await {
-            Ok((otp, expiration_time)) => Ok(Response::new(AuthenticateResponse {
-                otp,
+            Ok((token, expiration_time)) => {
+                self.auth_throttle
+                    .lock()
+                    .await
+                    .record_success(&username, &source);
+                Ok(Response::new(AuthenticateResponse {
+                    token,
+                    expiration_time: expiration_time as i64,
+                }))
+            }
+            Err(e) => {
+                // Only wrong credentials count towards a lockout, not e.g. an unreadable file
+                if matches!(e, Error::Auth(_)) {
+                    let lockout = self.auth_throttle.lock().await.record_failure(
+                        &username,
+                        &source,
+                        Instant::now(),
+                    );
+                    warn!(
+                        username = %username,
+                        source = %source,
+                        error = %e,
+                        "[Authenticate] Failed authentication"
+                    );
+                    if let Some(lockout) = lockout {
+                        warn!(
+                            username = %username,
+                            source = %source,
+                            lockout_secs = lockout.as_secs(),
+                            "[Authenticate] Too many failures, locking out"
+                        );
+                    }
+                    Err(Status::unauthenticated("Invalid username or password"))
+                } else {
+                    error!("[Authenticate] Error issuing session token: {}", e);
+                    Err(Status::internal("Failed to issue session token"))
+                }
+            }
+        }
+    }
+
+    async fn renew_token(
+        &self,
+        request: Request<RenewTokenRequest>,
+    ) -> Result<Response<AuthenticateResponse>, Status> {
+        let token = request.into_inner().token;
+        match self.renew_session_token(&token) {
+            Ok((token, expiration_time)) => Ok(Response::new(AuthenticateResponse {
+                token,
                 expiration_time: expiration_time as i64,
             })),
             Err(e) => {
-                error!("[Authenticate] Error generating OTP: {}", e);
-                Err(Status::internal(
-                    "Failed to generate OTP: invalid username or password",
-                ))
+                warn!("[RenewToken] Rejected renewal: {}", e);
+                Err(Status::unauthenticated(e.to_string()))
             }
         }
     }
@@ -46,14 +141,76 @@ impl Master for Arc<MasterService> {
         &self,
         request: Request<RegisterRequest>,
     ) -> Result<Response<RegisterResponse>, Status> {
-        let chunkserver_address = request.into_inner().address;
+        if !self.is_leader().await {
+            return Err(not_leader(&self.current_master.read().await));
+        }
+        let RegisterRequest {
+            address: chunkserver_address,
+            failure_domain,
+            internal_proof,
+        } = request.into_inner();
+        // Only the chunkserver itself may reset what the master knows about its chunks
+        if let Err(e) = self.verify_chunkserver_request(
+            &internal_proof,
+            &register_fields(&chunkserver_address, &failure_domain),
+        ) {
+            warn!(
+                "[register_chunk_server] Ignoring registration of {}: {}",
+                chunkserver_address, e
+            );
+            return Err(Status::unauthenticated(e.to_string()));
+        }
 
-        let mut chunk_servers = self.chunk_servers.write().await;
-        chunk_servers.insert(chunkserver_address.clone(), vec![]);
-        info!(
-            "Registering chunk server: {}, Current chunk servers: {:?}",
-            chunkserver_address, *chunk_servers
-        );
+        {
+            let mut chunk_servers = self.chunk_servers.write().await;
+            chunk_servers.insert(chunkserver_address.clone(), vec![]);
+            // A restarted server numbers its reports from the start again
+            self.report_generations
+                .write()
+                .await
+                .remove(&chunkserver_address);
+            // A restarted server starts out empty, so it is no longer being decommissioned
+            if self
+                .draining
+                .write()
+                .await
+                .remove(&chunkserver_address)
+                .is_some()
+            {
+                info!(
+                    "Chunk server {} registered again, cancelling its decommissioning",
+                    chunkserver_address
+                );
+            }
+            info!(
+                server = %chunkserver_address,
+                failure_domain = %failure_domain,
+                chunk_servers = chunk_servers.len(),
+                "Registering chunk server"
+            );
+            self.failure_domains
+                .write()
+                .await
+                .insert(chunkserver_address.clone(), failure_domain);
+            log_map("[register_chunk_server] chunk_servers", &chunk_servers);
+        }
+
+        // Count the grace period as if the server had sent a heartbeat at its end, so that the
+        // heartbeat checker removes a server whose first heartbeat never arrives
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| Status::internal("[Register] SystemTime before UNIX EPOCH!"))?
+            .as_secs();
+        {
+            let mut last_heartbeat_time = self.last_heartbeat_time.write().await;
+            last_heartbeat_time.insert(
+                chunkserver_address.clone(),
+                now + self.config.registration_grace_period.as_secs(),
+            );
+            self.metrics
+                .chunkservers
+                .set(last_heartbeat_time.len() as i64);
+        }
 
         Ok(Response::new(RegisterResponse {
             message: format!(
@@ -63,58 +220,158 @@ impl Master for Arc<MasterService> {
         }))
     }
 
-    async fn update_metadata(
+    /// Removes a chunk server that is shutting down right away and copies its chunks to the
+    /// remaining servers in the background
+    async fn unregister_chunk_server(
         &self,
-        request: Request<UpdateMetadataRequest>,
-    ) -> Result<Response<UpdateMetadataResponse>, Status> {
-        let metadata = request.into_inner().metadata.unwrap();
-        {
-            let mut file_chunks = self.file_chunks.write().await;
-            *file_chunks = metadata
-                .file_chunks
-                .into_iter()
-                .map(|(key, chunk_list)| (key, chunk_list.chunks)) // Extract chunks from ChunkList
-                .collect();
+        request: Request<UnregisterRequest>,
+    ) -> Result<Response<UnregisterResponse>, Status> {
+        if !self.is_leader().await {
+            return Err(not_leader(&self.current_master.read().await));
         }
+        let UnregisterRequest {
+            address,
+            internal_proof,
+        } = request.into_inner();
+        // Only the chunkserver itself may take it out of the cluster
+        if let Err(e) =
+            self.verify_chunkserver_request(&internal_proof, &unregister_fields(&address))
         {
-            let mut chunk_servers = self.chunk_servers.write().await;
-            *chunk_servers = metadata
-                .chunk_servers
-                .into_iter()
-                .map(|(key, chunk_list)| (key, chunk_list.chunks)) // Extract chunks from ChunkList
-                .collect();
+            warn!(
+                "[unregister_chunk_server] Ignoring unregistration of {}: {}",
+                address, e
+            );
+            return Err(Status::unauthenticated(e.to_string()));
         }
-        {
-            let mut chunk_map = self.chunk_map.write().await;
-            *chunk_map = metadata.chunk_map.into();
+        let is_known = self.chunk_servers.read().await.contains_key(&address)
+            || self.last_heartbeat_time.read().await.contains_key(&address);
+        if !is_known {
+            return Err(Status::not_found(format!(
+                "Chunk server '{}' not found",
+                address
+            )));
         }
 
-        // Print the entire updated metadata
-        {
-            let file_chunks = self.file_chunks.read().await;
-            let chunk_servers = self.chunk_servers.read().await;
-            let chunk_map = self.chunk_map.read().await;
+        info!(server = %address, "Chunk server is shutting down, removing it");
+        // A decommissioned server that shuts down has nothing left to drain
+        self.draining.write().await.remove(&address);
+        let service = Arc::clone(self);
+        let server = address.clone();
+        tokio::spawn(async move { service.remove_chunk_servers(&[server]).await });
+
+        Ok(Response::new(UnregisterResponse {
+            message: format!("Chunk server '{}' unregistered successfully.", address),
+        }))
+    }
 
-            debug!("[update_metadata] Updated Metadata");
-            debug!("file_chunks: {:#?}", *file_chunks);
-            debug!("chunk_servers: {:#?}", *chunk_servers);
-            debug!("chunk_map: {:#?}", *chunk_map);
+    async fn update_metadata(
+        &self,
+        request: Request<UpdateMetadataRequest>,
+    ) -> Result<Response<UpdateMetadataResponse>, Status> {
+        // A leader that has not learned about a newer one yet must not take over its metadata
+        if self.is_leader().await {
+            return Err(Status::failed_precondition(
+                "This master is the leader and does not accept metadata updates",
+            ));
+        }
+        let UpdateMetadataRequest {
+            metadata,
+            leader_address,
+            internal_proof,
+        } = request.into_inner();
+        let Some(metadata) = metadata else {
+            return Err(Status::invalid_argument("Missing metadata"));
+        };
+        let version = metadata.metadata_version;
+        // Only a configured master may replace the metadata this one takes over as leader
+        if let Err(e) = self.verify_master_request(
+            &leader_address,
+            &internal_proof,
+            &update_metadata_fields(
+                &leader_address,
+                &version.to_be_bytes(),
+                &metadata_digest(&metadata),
+            ),
+        ) {
+            warn!(
+                "[update_metadata] Ignoring metadata version {} from {}: {}",
+                version, leader_address, e
+            );
+            return Err(Status::unauthenticated(e.to_string()));
+        }
+        if !self.apply_metadata(metadata).await {
+            return Err(Status::aborted(format!(
+                "Metadata version {} is not newer than the applied one",
+                version
+            )));
         }
 
-        info!("Updated metadata from leader");
+        info!("Updated metadata from leader to version {}", version);
         Ok(Response::new(UpdateMetadataResponse {
             message: "Metadata update applied successfully".to_string(),
         }))
     }
 
+    async fn get_metadata(
+        &self,
+        request: Request<GetMetadataRequest>,
+    ) -> Result<Response<GetMetadataResponse>, Status> {
+        let GetMetadataRequest {
+            sender_address,
+            internal_proof,
+        } = request.into_inner();
+        // The metadata lists every file, owner and chunk, so only masters may fetch it
+        if let Err(e) = self.verify_master_request(
+            &sender_address,
+            &internal_proof,
+            &get_metadata_fields(&sender_address),
+        ) {
+            warn!(
+                "[get_metadata] Refusing metadata to {}: {}",
+                sender_address, e
+            );
+            return Err(Status::unauthenticated(e.to_string()));
+        }
+        Ok(Response::new(GetMetadataResponse {
+            metadata: Some(self.current_metadata().await.into()),
+        }))
+    }
+
     async fn heartbeat(
         &self,
         request: Request<HeartbeatRequest>,
     ) -> Result<Response<HeartbeatResponse>, Status> {
+        if !self.is_leader().await {
+            return Err(not_leader(&self.current_master.read().await));
+        }
+        let mut request = request.into_inner();
+        let internal_proof = std::mem::take(&mut request.internal_proof);
+        if let Err(e) = self.verify_chunkserver_request(
+            &internal_proof,
+            &heartbeat_fields(&request.chunkserver_address, &heartbeat_digest(&request)),
+        ) {
+            warn!(
+                "[Heartbeat] Ignoring heartbeat of {}: {}",
+                request.chunkserver_address, e
+            );
+            return Err(Status::unauthenticated(e.to_string()));
+        }
         let HeartbeatRequest {
             chunkserver_address,
             chunks,
-        } = request.into_inner();
+            total_bytes,
+            available_bytes,
+            deleted_chunks,
+            last_scrub_time,
+            corrupt_chunks,
+            failure_domain,
+            generation,
+            delta,
+            base_generation,
+            added_chunks,
+            removed_chunks,
+            internal_proof: _,
+        } = request;
 
         info!(
             "[Heartbeat] received HeartbeatRequest from: {}",
@@ -131,296 +388,2192 @@ impl Master for Arc<MasterService> {
         {
             let mut last_heartbeat_time = self.last_heartbeat_time.write().await;
             last_heartbeat_time.insert(chunkserver_address.clone(), now);
+            self.metrics.heartbeats.inc();
+            self.metrics
+                .chunkservers
+                .set(last_heartbeat_time.len() as i64);
         }
 
-        // Update chunk_servers with the received chunks
-        {
-            let mut chunk_servers = self.chunk_servers.write().await;
+        // Record the reported disk capacity (zero means the chunkserver could not read it)
+        if total_bytes > 0 {
+            let mut server_capacity = self.server_capacity.write().await;
+            server_capacity.insert(
+                chunkserver_address.clone(),
+                ServerCapacity {
+                    total_bytes,
+                    available_bytes,
+                },
+            );
+        }
 
-            // Parse chunks into ChunkInfo objects
-            let chunk_info_list = {
-                let mut collected_chunks = Vec::new();
-                for chunk in chunks {
-                    let chunk_id = chunk.clone();
-
-                    // Acquire a read lock on chunk_map and get the corresponding chunk by chunk id
-                    let existing_chunk_info = {
-                        let chunk_map = self.chunk_map.read().await;
-                        chunk_map.get(&chunk_id).cloned()
-                    };
-
-                    if let Some(chunk_info) = existing_chunk_info {
-                        // If it exists, use the existing ChunkInfo
-                        collected_chunks.push(chunk_info);
-                    } else {
-                        // If it doesn't exist, print an error and skip the chunk
-                        error!(
-                            "[Heartbeat] Error: Chunk ID '{}' not found in chunk_map",
-                            chunk_id
-                        );
+        self.scrub_status.write().await.insert(
+            chunkserver_address.clone(),
+            ScrubStatus {
+                last_scrub_time,
+                corrupt_chunks,
+            },
+        );
+        self.failure_domains
+            .write()
+            .await
+            .insert(chunkserver_address.clone(), failure_domain);
+
+        // Forget the deletions the chunkserver confirmed, and ask it to delete the remaining ones
+        let (chunks_to_delete, confirmed_deletions) = {
+            let mut pending_deletions = self.pending_deletions.write().await;
+            match pending_deletions.get_mut(&chunkserver_address) {
+                Some(pending) => {
+                    let before = pending.len();
+                    for chunk_id in &deleted_chunks {
+                        pending.remove(chunk_id);
+                    }
+                    let confirmed = before - pending.len();
+                    let chunks_to_delete: Vec<String> = pending.iter().cloned().collect();
+                    if pending.is_empty() {
+                        pending_deletions.remove(&chunkserver_address);
                     }
+                    (chunks_to_delete, confirmed)
                 }
-                collected_chunks
-            };
+                None => (Vec::new(), 0),
+            }
+        };
+        if confirmed_deletions > 0 {
+            info!(
+                "[Heartbeat] {} confirmed deletion of {} chunk(s)",
+                chunkserver_address, confirmed_deletions
+            );
+            self.propagate_metadata_updates().await;
+        }
 
+        // Apply the chunk report: a full list replaces the server's entry, a delta changes it if
+        // it is based on the last report applied. The chunks are looked up before locking
+        // chunk_servers, so that the lock is only held while the entry is changed.
+        let applied_generation = self
+            .report_generations
+            .read()
+            .await
+            .get(&chunkserver_address)
+            .copied();
+        let mut orphans = Vec::new();
+        let acked_generation = if !delta {
+            let (chunk_info_list, unknown) = self
+                .resolve_reported_chunks(&chunkserver_address, chunks, &chunks_to_delete, now)
+                .await;
+            orphans = unknown;
             // Insert or update the chunk list for this server
-            chunk_servers.insert(chunkserver_address.clone(), chunk_info_list);
+            self.metrics
+                .chunkserver_chunks
+                .with_label_values(&[&chunkserver_address])
+                .set(chunk_info_list.len() as i64);
+            self.chunk_servers
+                .write()
+                .await
+                .insert(chunkserver_address.clone(), chunk_info_list);
+            generation
+        } else if applied_generation == Some(base_generation) {
+            let (added, unknown) = self
+                .resolve_reported_chunks(&chunkserver_address, added_chunks, &chunks_to_delete, now)
+                .await;
+            orphans = unknown;
+            let removed_chunks: HashSet<String> = removed_chunks.into_iter().collect();
+            let mut chunk_servers = self.chunk_servers.write().await;
+            let server_chunks = chunk_servers
+                .entry(chunkserver_address.clone())
+                .or_default();
+            server_chunks.retain(|chunk| !removed_chunks.contains(&chunk.chunk_id));
+            for chunk in added {
+                // E.g. assigned to the server by the master already
+                if !server_chunks
+                    .iter()
+                    .any(|existing| existing.chunk_id == chunk.chunk_id)
+                {
+                    server_chunks.push(chunk);
+                }
+            }
+            self.metrics
+                .chunkserver_chunks
+                .with_label_values(&[&chunkserver_address])
+                .set(server_chunks.len() as i64);
+            generation
+        } else {
+            info!(
+                "[Heartbeat] {} reported changes since generation {}, but generation {:?} is applied, asking for a full report",
+                chunkserver_address, base_generation, applied_generation
+            );
+            0
+        };
+
+        // Reports are only acknowledged once chunks of no file count as orphans, so that every
+        // server lists all of its chunks once they are checked
+        let acked_generation = if self.checks_orphans(now) {
+            acked_generation
+        } else {
+            0
+        };
+        {
+            let mut report_generations = self.report_generations.write().await;
+            if acked_generation == 0 {
+                report_generations.remove(&chunkserver_address);
+            } else {
+                report_generations.insert(chunkserver_address.clone(), acked_generation);
+            }
         }
+        let mut chunks_to_delete = chunks_to_delete;
+        chunks_to_delete.extend(orphans);
 
         Ok(Response::new(HeartbeatResponse {
             message: format!(
                 "[Heartbeat] HeartbeatRequest from '{}' processed successfully.",
                 chunkserver_address,
             ),
+            chunks_to_delete,
+            epoch: self.epoch.load(Ordering::SeqCst),
+            acked_generation,
         }))
     }
 
     /// Handles the upload of a new file.
     ///
+    /// - Selects `replication_factor` chunk servers to store each chunk, from a snapshot of the
+    ///   servers taken without holding the metadata locks.
     /// - Checks if the file name already exists. If it does, appends a suffix to make it unique.
-    /// - Selects `replication_factor` chunk servers to store the file chunks.
     /// - Updates the file_chunks with the new file and chunk information.
     /// - Adds the new chunks to the chunk_map (mapping from chunk_id to ChunkInfo).
     async fn assign_chunks(
         &self,
         request: Request<AssignRequest>,
     ) -> Result<Response<AssignResponse>, Status> {
+        let timer = self.metrics.requests.start("assign_chunks");
+        let source = AuditSource::of(&request);
         let request = request.into_inner();
-        let file_name = request.file_name;
-        let file_size = request.file_size;
+        let identity = identify(self, &request.token).await;
+        let user = username(&identity);
+        let requested_name = request.file_name.clone();
+        let result: Result<Response<AssignResponse>, Status> = async move {
+            if !self.is_leader().await {
+                return Err(not_leader(&self.current_master.read().await));
+            }
+            let file_name = normalize_path(&request.file_name).map_err(invalid_path)?;
+            let file_size = request.file_size;
+            let identity = identity?;
+            if let Some(identity) = identity.as_ref().filter(|identity| !identity.role.may_write()) {
+                return Err(permission_denied(identity, &file_name, ""));
+            }
 
-        let mut file_chunks = self.file_chunks.write().await;
-        let mut chunk_servers = self.chunk_servers.write().await;
-        let mut chunk_map = self.chunk_map.write().await;
+            // A file stored under another name due to a conflict is new, so only the requested
+            // name is locked. Held until the response is remembered for retries.
+            let lock = self.file_locks.lock(&file_name).await;
+            let user = identity.as_ref().map_or("", |identity| identity.username.as_str());
+            match completed_operation(self, &request.operation_id, user, &file_name).await? {
+                Some(OperationResponse::Assign(response)) => {
+                    info!(
+                        operation_id = %request.operation_id,
+                        "Answering a retried upload with its chunk assignment"
+                    );
+                    return Ok(Response::new(response));
+                }
+                Some(_) => return Err(operation_id_reused(&request.operation_id)),
+                None => {}
+            }
 
-        let mut updated_file_name = file_name.to_string();
-        let mut suffix = 1;
-        while file_chunks.contains_key(&updated_file_name) {
-            updated_file_name = format!("{}-{}", file_name, suffix);
-            suffix += 1;
-        }
+            // Place the chunks on a snapshot of the servers, the metadata is only locked to
+            // record them
+            let mut candidates = self.candidates().await;
+            let policy = self.placement_policy();
 
-        info!(
-            "Assigning chunks for file: {} (original name: {}, size: {} bytes)",
-            updated_file_name, file_name, file_size
-        );
+            if candidates.servers.is_empty() {
+                return Err(Status::internal(
+                    "No available chunk servers: all servers are full",
+                ));
+            }
+
+            // Calculate the number of chunks of the new file (accounting partial chunks)
+            let num_chunks = file_size.div_ceil(self.common_config.chunk_size);
+
+            let mut assigned_chunks = Vec::new();
+            let mut remaining_size = file_size;
+            let mut colocated_chunks = 0;
+            let mut under_replicated_chunks = 0;
+            let mut replicas = self.common_config.replication_factor;
+
+            for chunk_index in 0..num_chunks {
+                let placement = select_servers(
+                    &candidates,
+                    self.common_config.replication_factor,
+                    &[],
+                    policy,
+                );
+                debug!("selected servers: {:?}", placement.servers);
+                if placement.servers.is_empty() {
+                    return Err(Status::internal(
+                        "No available chunk servers: all servers are full or out of disk space",
+                    ));
+                }
+                if placement.shortfall > 0 {
+                    if !self.config.allow_degraded_writes {
+                        return Err(degraded_write_rejected(self, placement.servers.len()));
+                    }
+                    under_replicated_chunks += 1;
+                }
+                replicas = replicas.min(placement.servers.len());
+                if placement.colocated {
+                    colocated_chunks += 1;
+                }
+                let selected_servers = placement.servers;
+
+                // Generate a unique, opaque chunk ID
+                let chunk_info = ChunkInfo {
+                    chunk_id: Uuid::new_v4().to_string(),
+                    server_addresses: selected_servers.clone(),
+                    version: 0,
+                    size: remaining_size.min(self.common_config.chunk_size),
+                    file_name: String::new(), // Set once the name is known below
+                    index: chunk_index,
+                    // Leased once the chunk is appended to, see `get_file_chunks`
+                    primary: selected_servers.first().cloned().unwrap_or_default(),
+                    lease_expiry: 0,
+                    shared_with: 0,
+                    placement_proof: String::new(),
+                };
+                remaining_size -= chunk_info.size;
+
+                // Account for the new chunk so the next chunks spread across servers
+                for server in &selected_servers {
+                    if let Some(chunks) = candidates.servers.get_mut(server) {
+                        *chunks += 1;
+                        if *chunks >= self.common_config.max_allowed_chunks {
+                            candidates.servers.remove(server);
+                        }
+                    }
+                    if let Some(capacity) = candidates.capacities.get_mut(server) {
+                        capacity.available_bytes =
+                            capacity.available_bytes.saturating_sub(chunk_info.size);
+                    }
+                }
 
-        // Create a filtered map of available chunk servers.
-        let mut avail_chunk_servers: HashMap<String, Vec<ChunkInfo>> = HashMap::new();
-        for (server, chunks) in chunk_servers.iter() {
-            if chunks.len() < self.common_config.max_allowed_chunks {
-                avail_chunk_servers.insert(server.clone(), chunks.clone());
+                // Track the assigned chunk
+                assigned_chunks.push(chunk_info);
             }
-        }
 
-        if avail_chunk_servers.is_empty() {
-            return Err(Status::internal(
-                "No available chunk servers: all servers are full",
-            ));
+            let mut file_chunks = self.file_chunks.write().await;
+            let mut chunk_servers = self.chunk_servers.write().await;
+            let mut chunk_map = self.chunk_map.write().await;
+
+            let mut updated_file_name = file_name.to_string();
+            let mut suffix = 1;
+            while file_chunks.contains_key(&updated_file_name) {
+                updated_file_name = format!("{}-{}", file_name, suffix);
+                suffix += 1;
+            }
+            if let Some(conflict) = find_conflict(file_chunks.keys(), &updated_file_name) {
+                return Err(Status::already_exists(format!(
+                    "'{}' conflicts with the file '{}': a path can't be both a file and a directory",
+                    updated_file_name, conflict
+                )));
+            }
+            if let Some(message) = self.exceeded_quota(Usage::of(&file_chunks), file_size) {
+                warn!(
+                    "[assign_chunks] Rejecting '{}': {}",
+                    updated_file_name, message
+                );
+                return Err(Status::resource_exhausted(message));
+            }
+
+            // Only record the file once every chunk could be placed
+            for chunk_info in &mut assigned_chunks {
+                chunk_info.file_name = updated_file_name.clone();
+                // Shared by all metadata maps
+                let chunk = Arc::new(chunk_info.clone());
+
+                // Update file_chunks metadata for this file
+                file_chunks
+                    .entry(updated_file_name.clone())
+                    .or_default()
+                    .push(Arc::clone(&chunk));
+
+                // Update chunkserver mappings
+                for server in &chunk.server_addresses {
+                    if let Some(chunks) = chunk_servers.get_mut(server) {
+                        chunks.push(Arc::clone(&chunk));
+                    }
+                }
+                chunk_map.insert(chunk.chunk_id.clone(), chunk);
+                // Only the response carries the signature, the replicas may change later on
+                chunk_info.placement_proof = self.placement_proof(chunk_info);
+            }
+            if let Some(identity) = &identity {
+                self.file_owners
+                    .write()
+                    .await
+                    .insert(updated_file_name.clone(), identity.username.clone());
+            }
+            log_map("[assign_chunks] chunk_servers", &chunk_servers);
+
+            // Release all locks, propagating the update snapshots the metadata
+            drop(file_chunks);
+            drop(chunk_servers);
+            drop(chunk_map);
+
+            if under_replicated_chunks > 0 {
+                warn!(
+                    "[assign_chunks] {} of {} chunks of '{}' get fewer than {} replicas: not enough eligible servers, they are copied once more servers join",
+                    under_replicated_chunks,
+                    num_chunks,
+                    updated_file_name,
+                    self.common_config.replication_factor
+                );
+            }
+            if colocated_chunks > 0 {
+                warn!(
+                    "[assign_chunks] {} of {} chunks of '{}' have replicas in the same failure domain: not enough distinct hosts or domains",
+                    colocated_chunks, num_chunks, updated_file_name
+                );
+            }
+            info!(
+                file_name = %updated_file_name,
+                original_name = %file_name,
+                size = file_size,
+                chunks = num_chunks,
+                "File has been divided into chunks and assigned to servers"
+            );
+
+            // Send updated metadata to registered shadow masters
+            self.propagate_metadata_updates().await;
+
+            let response = AssignResponse {
+                file_name: updated_file_name,
+                chunk_info_list: assigned_chunks,
+                epoch: self.epoch.load(Ordering::SeqCst),
+                replicas: replicas as u64,
+                replication_factor: self.common_config.replication_factor as u64,
+            };
+            remember_operation(
+                self,
+                &request.operation_id,
+                user,
+                &file_name,
+                OperationResponse::Assign(response.clone()),
+            )
+            .await;
+            drop(lock);
+            Ok(Response::new(response))
         }
+        .await;
+        timer.finish(&result);
+        // Audited under the name the file was stored as
+        let file_name = match &result {
+            Ok(response) => response.get_ref().file_name.clone(),
+            Err(_) => audit_name(&requested_name),
+        };
+        audit(self, "upload", &file_name, &user, &source, outcome(&result)).await;
+        result
+    }
 
-        // Calculate the number of chunks of the new file (accounting partial chunks)
-        let num_chunks =
-            (file_size + self.common_config.chunk_size - 1) / self.common_config.chunk_size;
+    /// Add a chunk at the end of a file whose chunks are all full, for uploads that don't know
+    /// their size upfront. Audited as part of the upload started by `assign_chunks`.
+    async fn assign_next_chunk(
+        &self,
+        request: Request<AssignNextChunkRequest>,
+    ) -> Result<Response<AssignResponse>, Status> {
+        let timer = self.metrics.requests.start("assign_next_chunk");
+        let AssignNextChunkRequest {
+            file_name,
+            size,
+            token,
+        } = request.into_inner();
+        let result: Result<Response<AssignResponse>, Status> = async {
+            if !self.is_leader().await {
+                return Err(not_leader(&self.current_master.read().await));
+            }
+            let file_name = normalize_path(&file_name).map_err(invalid_path)?;
+            let identity = identify(self, &token).await?;
+            check_owner(self, identity.as_ref(), &file_name).await?;
+            let chunk_size = self.common_config.chunk_size;
+            if size == 0 || size > chunk_size {
+                return Err(Status::invalid_argument(format!(
+                    "Chunk size {} is not between 1 and chunk_size ({})",
+                    size, chunk_size
+                )));
+            }
 
-        let mut assigned_chunks = Vec::new();
+            // Placed before taking the metadata locks, like in `assign_chunks`
+            let placement = select_servers(
+                &self.candidates().await,
+                self.common_config.replication_factor,
+                &[],
+                self.placement_policy(),
+            );
 
-        for chunk_index in 0..num_chunks {
-            // Select servers that has minimal load
-            let mut selected_servers = vec![];
+            let lock = self.file_locks.lock(&file_name).await;
+            let mut file_chunks = self.file_chunks.write().await;
+            let mut chunk_servers = self.chunk_servers.write().await;
+            let mut chunk_map = self.chunk_map.write().await;
 
-            // Create a priority queue for servers based on their load (min-heap)
-            let mut server_queue: BinaryHeap<Reverse<(usize, String)>> = avail_chunk_servers
-                .iter()
-                .map(|(addr, chunks)| Reverse((chunks.len(), addr.clone())))
-                .collect();
+            let Some(chunks) = file_chunks.get(&file_name) else {
+                return Err(Status::not_found(format!("File '{}' not found", file_name)));
+            };
+            if chunks.last().is_some_and(|chunk| chunk.size < chunk_size) {
+                return Err(Status::failed_precondition(format!(
+                    "The last chunk of '{}' is not full",
+                    file_name
+                )));
+            }
+            let index = chunks.len() as u64;
+            // The file exists already, so only its size counts towards the quotas
+            let mut usage = Usage::of(&file_chunks);
+            usage.files -= 1;
+            if let Some(message) = self.exceeded_quota(usage, size) {
+                warn!(
+                    "[assign_next_chunk] Rejecting chunk {} of '{}': {}",
+                    index, file_name, message
+                );
+                return Err(Status::resource_exhausted(message));
+            }
 
-            // Ensure chunkservers with minimal load is selected
-            while selected_servers.len() < self.common_config.replication_factor {
-                if let Some(Reverse((load, addr))) = server_queue.pop() {
-                    // Check if repeated (when avail_chunk_servers.len() < replication_factor)
-                    if selected_servers.contains(&addr.to_string()) {
-                        break;
-                    }
-                    // Add server to selected list
-                    selected_servers.push(addr.clone());
-                    // Update the load and reinsert into the priority queue
-                    server_queue.push(Reverse((load + 1, addr)));
+            if placement.servers.is_empty() {
+                return Err(Status::internal(
+                    "No available chunk servers: all servers are full or out of disk space",
+                ));
+            }
+            if placement.shortfall > 0 {
+                if !self.config.allow_degraded_writes {
+                    return Err(degraded_write_rejected(self, placement.servers.len()));
                 }
+                warn!(
+                    "[assign_next_chunk] Chunk {} of '{}' gets {} of {} replicas: not enough eligible servers, it is copied once more servers join",
+                    index,
+                    file_name,
+                    placement.servers.len(),
+                    self.common_config.replication_factor
+                );
+            }
+            if placement.colocated {
+                warn!(
+                    "[assign_next_chunk] Chunk {} of '{}' has replicas in the same failure domain: not enough distinct hosts or domains",
+                    index, file_name
+                );
             }
-            debug!("selected servers: {:?}", selected_servers);
 
-            // Generate a unique chunk ID
-            let chunk_id = format!("{}_chunk_{}", updated_file_name, chunk_index);
-            let chunk_info = ChunkInfo {
-                chunk_id: chunk_id.clone(),
-                server_addresses: selected_servers.clone(),
+            let mut chunk_info = ChunkInfo {
+                chunk_id: Uuid::new_v4().to_string(),
+                primary: placement.servers.first().cloned().unwrap_or_default(),
+                server_addresses: placement.servers,
                 version: 0,
+                size,
+                file_name: file_name.clone(),
+                index,
+                lease_expiry: 0,
+                shared_with: 0,
+                placement_proof: String::new(),
             };
-
-            // Update file_chunks metadata for this file
+            let chunk = Arc::new(chunk_info.clone());
+            chunk_info.placement_proof = self.placement_proof(&chunk_info);
             file_chunks
                 .entry(file_name.clone())
-                .and_modify(|vec| {
-                    vec.clear();
-                    vec.push(chunk_info.clone());
-                })
-                .or_insert_with(|| vec![chunk_info.clone()]);
-
-            // Update chunkserver mappings
-            for server in &selected_servers {
+                .or_default()
+                .push(Arc::clone(&chunk));
+            for server in &chunk.server_addresses {
                 if let Some(chunks) = chunk_servers.get_mut(server) {
-                    chunks.push(chunk_info.clone());
+                    chunks.push(Arc::clone(&chunk));
                 }
             }
-            chunk_map.insert(chunk_id.clone(), chunk_info.clone());
-            info!("[assign_chunks] updated chunk_servers: {:?}", chunk_servers);
-
-            // Track the assigned chunk
-            assigned_chunks.push(chunk_info);
-        }
-
-        info!(
-            "File '{}' has been divided into {} chunk(s) and assigned to servers.",
-            file_name, num_chunks
-        );
-
-        // Release write locks
-        drop(file_chunks);
-        drop(chunk_servers);
-        drop(chunk_map);
+            chunk_map.insert(chunk.chunk_id.clone(), chunk);
+            info!(
+                file_name = %file_name,
+                index,
+                size,
+                "Assigned another chunk to the file"
+            );
 
-        // Send updated metadata to registered shadow masters
-        self.propagate_metadata_updates().await;
+            // Release all locks, propagating the update snapshots the metadata
+            drop(file_chunks);
+            drop(chunk_servers);
+            drop(chunk_map);
+            drop(lock);
+            self.propagate_metadata_updates().await;
 
-        // Return the response
-        Ok(Response::new(AssignResponse {
-            file_name: updated_file_name,
-            chunk_info_list: assigned_chunks,
-        }))
+            Ok(Response::new(AssignResponse {
+                file_name,
+                replicas: chunk_info.server_addresses.len() as u64,
+                chunk_info_list: vec![chunk_info],
+                epoch: self.epoch.load(Ordering::SeqCst),
+                replication_factor: self.common_config.replication_factor as u64,
+            }))
+        }
+        .await;
+        timer.finish(&result);
+        result
     }
 
     /// Deletes all chunks and metadata associated with a file.
     ///
+    /// - With authentication, only the owner of the file or an admin may delete it.
     /// - Removes the file and its chunks from `file_chunks`.
-    /// - Removes references to the file's chunks from `chunk_servers`.
-    /// - Deletes the chunk metadata from `chunk_map`.
+    /// - If `trash_retention_secs` is set, keeps the chunks in the trash so the file can be restored.
+    /// - Otherwise removes references to the file's chunks from `chunk_servers` and deletes the
+    ///   chunk metadata from `chunk_map`.
     /// - Sends metadata updates to shadow masters.
     async fn delete_file(
         &self,
         request: Request<DeleteFileRequest>,
     ) -> Result<Response<DeleteFileResponse>, Status> {
-        let file_name = request.into_inner().file_name;
-
-        let mut file_chunks = self.file_chunks.write().await;
-        let mut chunk_servers = self.chunk_servers.write().await;
-        let mut chunk_map = self.chunk_map.write().await;
-
-        // Check if the file exists
-        if let Some(chunks) = file_chunks.remove(&file_name) {
-            info!("Deleting metadata for file: {}", file_name);
-
-            // Remove the chunks from chunk_servers
-            for chunk_info in &chunks {
-                for server in &chunk_info.server_addresses {
-                    if let Some(server_chunks) = chunk_servers.get_mut(server) {
-                        server_chunks.retain(|chunk| chunk.chunk_id != chunk_info.chunk_id);
-                        if server_chunks.is_empty() {
-                            chunk_servers.remove(server); // Optionally clean up empty entries
-                        }
-                    }
+        let timer = self.metrics.requests.start("delete_file");
+        let source = AuditSource::of(&request);
+        let DeleteFileRequest {
+            file_name,
+            token,
+            operation_id,
+        } = request.into_inner();
+        let identity = identify(self, &token).await;
+        let user = username(&identity);
+        let audited_name = audit_name(&file_name);
+        let result: Result<Response<DeleteFileResponse>, Status> = async move {
+            if !self.is_leader().await {
+                return Err(not_leader(&self.current_master.read().await));
+            }
+            let file_name = normalize_path(&file_name).map_err(invalid_path)?;
+            let identity = identity?;
+
+            // Held until the response is remembered for retries
+            let lock = self.file_locks.lock(&file_name).await;
+            let user = identity
+                .as_ref()
+                .map_or("", |identity| identity.username.as_str());
+            match completed_operation(self, &operation_id, user, &file_name).await? {
+                Some(OperationResponse::Delete(response)) => {
+                    info!(
+                        operation_id = %operation_id,
+                        "Answering a retried deletion like the first attempt"
+                    );
+                    return Ok(Response::new(response));
+                }
+                Some(_) => return Err(operation_id_reused(&operation_id)),
+                None => {}
+            }
+
+            // Check if the file exists
+            let chunks = {
+                let mut file_chunks = self.file_chunks.write().await;
+                if file_chunks.contains_key(&file_name) {
+                    check_owner(self, identity.as_ref(), &file_name).await?;
+                }
+                file_chunks.remove(&file_name)
+            };
+            let response = if let Some(chunks) = chunks {
+                info!(file_name = %file_name, "Deleting metadata for file");
+                let message = self.discard_file(&file_name, &chunks).await;
+
+                // Send updated metadata to shadow masters
+                self.propagate_metadata_updates().await;
+
+                // Return success response
+                DeleteFileResponse {
+                    success: true,
+                    message,
+                }
+            } else {
+                warn!("File '{}' not found. No metadata deleted.", file_name);
+
+                // Return error response
+                DeleteFileResponse {
+                    success: false,
+                    message: format!("File '{}' not found.", file_name),
                 }
+            };
+            remember_operation(
+                self,
+                &operation_id,
+                user,
+                &file_name,
+                OperationResponse::Delete(response.clone()),
+            )
+            .await;
+            drop(lock);
+            Ok(Response::new(response))
+        }
+        .await;
+        timer.finish(&result);
+        let result_text = match &result {
+            Ok(response) if !response.get_ref().success => {
+                format!("NotFound: {}", response.get_ref().message)
+            }
+            result => outcome(result),
+        };
+        audit(self, "delete", &audited_name, &user, &source, result_text).await;
+        result
+    }
 
-                // Remove the chunk from chunk_map
-                chunk_map.remove(&chunk_info.chunk_id);
+    /// Restores a file from the trash, under a new name if its original name is taken again
+    async fn undelete(
+        &self,
+        request: Request<UndeleteRequest>,
+    ) -> Result<Response<UndeleteResponse>, Status> {
+        let source = AuditSource::of(&request);
+        let UndeleteRequest { file_name, token } = request.into_inner();
+        let identity = identify(self, &token).await;
+        let user = username(&identity);
+        let result: Result<Response<UndeleteResponse>, Status> = async {
+            if !self.is_leader().await {
+                return Err(not_leader(&self.current_master.read().await));
             }
+            let trashed_name = normalize_path(&file_name).map_err(invalid_path)?;
+            let identity = identity?;
 
-            info!("All metadata for file '{}' has been deleted.", file_name);
+            let entry = {
+                let mut trash = self.trash.write().await;
+                let entry = trash.get(&trashed_name).ok_or_else(|| {
+                    Status::not_found(format!("File '{}' not found in the trash", trashed_name))
+                })?;
+                if let Some(identity) = &identity {
+                    if !identity.may_modify(&entry.owner) {
+                        return Err(permission_denied(identity, &trashed_name, &entry.owner));
+                    }
+                }
+                trash
+                    .remove(&trashed_name)
+                    .expect("Trashed file was just found")
+            };
 
-            // Release write locks
-            drop(file_chunks);
-            drop(chunk_servers);
-            drop(chunk_map);
+            let lock = self.file_locks.lock(&entry.file_name).await;
+            let restored = {
+                let mut file_chunks = self.file_chunks.write().await;
+                let chunk_map = self.chunk_map.read().await;
 
-            // Send updated metadata to shadow masters
-            self.propagate_metadata_updates().await;
+                let mut file_name = entry.file_name.clone();
+                let mut suffix = 1;
+                while file_chunks.contains_key(&file_name) {
+                    file_name = format!("{}-{}", entry.file_name, suffix);
+                    suffix += 1;
+                }
+                if let Some(conflict) = find_conflict(file_chunks.keys(), &file_name) {
+                    Err(Status::failed_precondition(format!(
+                        "Cannot restore '{}' as '{}': it conflicts with the file '{}'",
+                        trashed_name, file_name, conflict
+                    )))
+                } else {
+                    // Renamed below if the file is restored under another name
+                    let chunks: Vec<Arc<ChunkInfo>> = entry
+                        .chunk_ids
+                        .iter()
+                        .filter_map(|chunk_id| chunk_map.get(chunk_id).cloned())
+                        .collect();
+                    file_chunks.insert(file_name.clone(), chunks);
+                    if !entry.owner.is_empty() {
+                        self.file_owners
+                            .write()
+                            .await
+                            .insert(file_name.clone(), entry.owner.clone());
+                    }
+                    Ok(file_name)
+                }
+            };
+            let restored_name = match restored {
+                Ok(file_name) => file_name,
+                Err(status) => {
+                    // Keep the file in the trash so it can be restored once the conflict is resolved
+                    self.trash.write().await.insert(trashed_name, entry);
+                    return Err(status);
+                }
+            };
 
-            // Return success response
-            Ok(Response::new(DeleteFileResponse {
-                success: true,
-                message: format!(
-                    "File '{}' deleted successfully from master metadata.",
-                    file_name
-                ),
-            }))
-        } else {
-            warn!("File '{}' not found. No metadata deleted.", file_name);
+            // Give the chunks the name the file was restored as, in all metadata maps sharing them
+            for chunk_id in &entry.chunk_ids {
+                self.update_chunk_info(chunk_id, |chunk| chunk.file_name = restored_name.clone())
+                    .await;
+            }
+            drop(lock);
+            info!(
+                "[undelete] Restored '{}' from the trash as '{}'",
+                trashed_name, restored_name
+            );
+
+            // Send updated metadata to registered shadow masters
+            self.propagate_metadata_updates().await;
 
-            // Return error response
-            Ok(Response::new(DeleteFileResponse {
-                success: false,
-                message: format!("File '{}' not found.", file_name),
+            Ok(Response::new(UndeleteResponse {
+                message: format!("File '{}' restored as '{}'.", trashed_name, restored_name),
+                file_name: restored_name,
             }))
         }
+        .await;
+        audit(
+            self,
+            "undelete",
+            &audit_name(&file_name),
+            &user,
+            &source,
+            outcome(&result),
+        )
+        .await;
+        result
     }
 
-    async fn get_file_chunks(
+    /// Lists the files and subdirectories directly below a directory.
+    ///
+    /// Listing a file returns just that file.
+    async fn list_files(
         &self,
-        request: Request<FileChunkMappingRequest>,
-    ) -> Result<Response<FileChunkMapping>, Status> {
-        let file_name = request.into_inner().file_name;
-        debug!("Fetching chunks for file: {}", file_name);
-
+        request: Request<ListFilesRequest>,
+    ) -> Result<Response<ListFilesResponse>, Status> {
+        let directory = normalize_dir(&request.into_inner().directory).map_err(invalid_path)?;
         let file_chunks = self.file_chunks.read().await;
-        info!("[get_file_chunks] Current file_chunks: {:?}", file_chunks);
-        let chunks = file_chunks
-            .get(&file_name)
-            .cloned()
-            .ok_or_else(|| Status::not_found(format!("File '{}' not found", file_name)))?;
 
-        Ok(Response::new(FileChunkMapping { file_name, chunks }))
+        let file_size = |chunks: &Vec<Arc<ChunkInfo>>| chunks.iter().map(|chunk| chunk.size).sum();
+        if let Some(chunks) = file_chunks.get(&directory) {
+            return Ok(Response::new(ListFilesResponse {
+                entries: vec![ListEntry {
+                    path: directory,
+                    is_directory: false,
+                    size: file_size(chunks),
+                }],
+            }));
+        }
+
+        // Files below the directory are listed by name, deeper files by their subdirectory
+        let mut entries: BTreeMap<String, ListEntry> = BTreeMap::new();
+        let prefix_len = if directory == "/" {
+            1
+        } else {
+            directory.len() + 1
+        };
+        for (file_name, chunks) in file_chunks.iter() {
+            if !is_under(file_name, &directory) {
+                continue;
+            }
+            let entry = match file_name[prefix_len..].find('/') {
+                Some(end) => ListEntry {
+                    path: file_name[..prefix_len + end].to_string(),
+                    is_directory: true,
+                    size: 0,
+                },
+                None => ListEntry {
+                    path: file_name.clone(),
+                    is_directory: false,
+                    size: file_size(chunks),
+                },
+            };
+            entries.insert(entry.path.clone(), entry);
+        }
+
+        if entries.is_empty() && directory != "/" {
+            return Err(Status::not_found(format!(
+                "Directory '{}' not found",
+                directory
+            )));
+        }
+        Ok(Response::new(ListFilesResponse {
+            entries: entries.into_values().collect(),
+        }))
     }
 
-    /// Handle ping master requests
-    async fn ping_master(
+    /// Deletes every file below a directory, like `delete_file` does for a single file.
+    ///
+    /// With authentication, nothing is deleted unless the user may delete every file.
+    async fn delete_prefix(
         &self,
-        request: Request<PingMasterRequest>,
-    ) -> Result<Response<PingMasterResponse>, Status> {
-        let sender_address = request.into_inner().sender_address;
-        info!("[ping_master] Received ping from: {}", sender_address);
+        request: Request<DeletePrefixRequest>,
+    ) -> Result<Response<DeletePrefixResponse>, Status> {
+        let source = AuditSource::of(&request);
+        let DeletePrefixRequest { directory, token } = request.into_inner();
+        let identity = identify(self, &token).await;
+        let user = username(&identity);
+        let result: Result<Response<DeletePrefixResponse>, Status> = async {
+            if !self.is_leader().await {
+                return Err(not_leader(&self.current_master.read().await));
+            }
+            let directory = normalize_dir(&directory).map_err(invalid_path)?;
+            let identity = identity?;
+            if directory == "/" {
+                return Err(Status::invalid_argument(
+                    "Refusing to delete the root directory",
+                ));
+            }
 
-        if self.is_leader().await {
-            let mut shadow_masters = self.shadow_masters.write().await;
-            // Insert sender_address and check if it was newly added
-            if shadow_masters.insert(sender_address.clone()) {
-                info!(
-                    "[ping_master] Registered '{}' as a shadow master",
-                    sender_address
-                );
+            // Locked in order, so that deletions of overlapping directories can't deadlock.
+            // Files created below the directory meanwhile are kept.
+            let mut file_names: Vec<String> = self
+                .file_chunks
+                .read()
+                .await
+                .keys()
+                .filter(|file_name| is_under(file_name, &directory))
+                .cloned()
+                .collect();
+            file_names.sort();
+            let mut locks = Vec::with_capacity(file_names.len());
+            for file_name in &file_names {
+                locks.push(self.file_locks.lock(file_name).await);
             }
-        }
 
-        Ok(Response::new(PingMasterResponse {
-            is_leader: self.is_leader().await,
-        }))
+            let deleted: Vec<(String, Vec<Arc<ChunkInfo>>)> = {
+                let mut file_chunks = self.file_chunks.write().await;
+                // Deleted meanwhile
+                file_names.retain(|file_name| file_chunks.contains_key(file_name));
+                for file_name in &file_names {
+                    check_owner(self, identity.as_ref(), file_name).await?;
+                }
+                file_names
+                    .into_iter()
+                    .filter_map(|file_name| {
+                        file_chunks
+                            .remove(&file_name)
+                            .map(|chunks| (file_name, chunks))
+                    })
+                    .collect()
+            };
+            if deleted.is_empty() {
+                return Err(Status::not_found(format!(
+                    "Directory '{}' not found",
+                    directory
+                )));
+            }
+
+            for (file_name, chunks) in &deleted {
+                self.discard_file(file_name, chunks).await;
+            }
+            drop(locks);
+            info!(
+                "[delete_prefix] Deleted {} file(s) below '{}'",
+                deleted.len(),
+                directory
+            );
+
+            // Send updated metadata to registered shadow masters
+            self.propagate_metadata_updates().await;
+
+            let mut deleted_files: Vec<String> = deleted
+                .into_iter()
+                .map(|(file_name, _)| file_name)
+                .collect();
+            deleted_files.sort();
+            Ok(Response::new(DeletePrefixResponse {
+                message: format!(
+                    "Deleted {} file(s) below '{}'.",
+                    deleted_files.len(),
+                    directory
+                ),
+                deleted_files,
+            }))
+        }
+        .await;
+        let audited_dir = normalize_dir(&directory).unwrap_or_else(|_| directory.clone());
+        audit(
+            self,
+            "delete_prefix",
+            &audited_dir,
+            &user,
+            &source,
+            outcome(&result),
+        )
+        .await;
+        result
+    }
+
+    /// Returns the storage used by all files together with the configured quotas
+    async fn get_usage(
+        &self,
+        _request: Request<UsageRequest>,
+    ) -> Result<Response<UsageResponse>, Status> {
+        let usage = Usage::of(&*self.file_chunks.read().await);
+        Ok(Response::new(UsageResponse {
+            total_bytes: usage.total_bytes,
+            files: usage.files,
+            max_total_bytes: self.config.max_total_bytes,
+            max_files: self.config.max_files,
+        }))
+    }
+
+    /// Marks a chunk server as draining: it receives no new chunks and the heartbeat checker
+    /// moves its chunks to other servers. Calling it again only reports the progress.
+    async fn decommission_server(
+        &self,
+        request: Request<DecommissionRequest>,
+    ) -> Result<Response<DecommissionResponse>, Status> {
+        if !self.is_leader().await {
+            return Err(not_leader(&self.current_master.read().await));
+        }
+        let source = AuditSource::of(&request);
+        let DecommissionRequest { address, token } = request.into_inner();
+        let identity = identify(self, &token).await;
+        let user = username(&identity);
+        let result = async {
+            check_admin(identity?.as_ref(), "decommission chunk servers").await?;
+            let is_known = self.chunk_servers.read().await.contains_key(&address)
+                || self.last_heartbeat_time.read().await.contains_key(&address);
+            if !is_known {
+                return Err(Status::not_found(format!(
+                    "Chunk server '{}' not found",
+                    address
+                )));
+            }
+
+            if self
+                .draining
+                .write()
+                .await
+                .insert(address.clone(), 0)
+                .is_none()
+            {
+                info!("[decommission_server] Draining chunk server {}", address);
+            }
+            Ok(Response::new(self.decommission_progress(&address).await))
+        }
+        .await;
+        audit(
+            self,
+            "decommission",
+            &address,
+            &user,
+            &source,
+            outcome(&result),
+        )
+        .await;
+        result
+    }
+
+    async fn decommission_status(
+        &self,
+        request: Request<DecommissionRequest>,
+    ) -> Result<Response<DecommissionResponse>, Status> {
+        let DecommissionRequest { address, token } = request.into_inner();
+        identify(self, &token).await?;
+        if !self.draining.read().await.contains_key(&address) {
+            return Err(Status::not_found(format!(
+                "Chunk server '{}' is not being decommissioned",
+                address
+            )));
+        }
+        Ok(Response::new(self.decommission_progress(&address).await))
+    }
+
+    /// Plans moving chunks from the most to the least loaded chunk servers and carries out the
+    /// moves in the background
+    async fn trigger_rebalance(
+        &self,
+        request: Request<RebalanceRequest>,
+    ) -> Result<Response<RebalanceStatusResponse>, Status> {
+        if !self.is_leader().await {
+            return Err(not_leader(&self.current_master.read().await));
+        }
+        let source = AuditSource::of(&request);
+        let identity = identify(self, &request.into_inner().token).await;
+        let user = username(&identity);
+        let result = async {
+            check_admin(identity?.as_ref(), "rebalance chunks").await?;
+            let message = match self.plan_rebalance().await {
+                None => "A rebalance is already running.".to_string(),
+                Some(moves) if moves.is_empty() => "Chunk counts are already balanced.".to_string(),
+                Some(moves) => {
+                    let message = format!("Rebalance started, moving {} chunk(s).", moves.len());
+                    let service = Arc::clone(self);
+                    tokio::spawn(async move { service.run_rebalance(moves).await });
+                    message
+                }
+            };
+            Ok(Response::new(self.rebalance_progress(Some(message)).await))
+        }
+        .await;
+        audit(self, "rebalance", "", &user, &source, outcome(&result)).await;
+        result
+    }
+
+    async fn rebalance_status(
+        &self,
+        request: Request<RebalanceRequest>,
+    ) -> Result<Response<RebalanceStatusResponse>, Status> {
+        identify(self, &request.into_inner().token).await?;
+        Ok(Response::new(self.rebalance_progress(None).await))
+    }
+
+    /// Starts copying a file on the chunkservers, see `MasterService::run_copy`
+    async fn copy_file(
+        &self,
+        request: Request<CopyFileRequest>,
+    ) -> Result<Response<CopyStatusResponse>, Status> {
+        let timer = self.metrics.requests.start("copy_file");
+        let source = AuditSource::of(&request);
+        let request = request.into_inner();
+        let identity = identify(self, &request.token).await;
+        let user = username(&identity);
+        let audited_name = audit_name(&request.destination);
+        let result: Result<Response<CopyStatusResponse>, Status> = async move {
+            if !self.is_leader().await {
+                return Err(not_leader(&self.current_master.read().await));
+            }
+            let source_name = normalize_path(&request.source).map_err(invalid_path)?;
+            let destination = normalize_path(&request.destination).map_err(invalid_path)?;
+            let identity = identity?;
+            if let Some(identity) = identity.as_ref().filter(|identity| !identity.role.may_write()) {
+                return Err(permission_denied(identity, &destination, ""));
+            }
+            if source_name == destination {
+                return Err(Status::invalid_argument(format!(
+                    "Can't copy '{}' onto itself",
+                    source_name
+                )));
+            }
+
+            let chunks = {
+                let file_chunks = self.file_chunks.read().await;
+                let Some(chunks) = file_chunks.get(&source_name).cloned() else {
+                    return Err(Status::not_found(format!(
+                        "File '{}' not found",
+                        source_name
+                    )));
+                };
+                if file_chunks.contains_key(&destination) {
+                    return Err(Status::already_exists(format!(
+                        "File '{}' already exists",
+                        destination
+                    )));
+                }
+                if let Some(conflict) = find_conflict(file_chunks.keys(), &destination) {
+                    return Err(Status::already_exists(format!(
+                        "'{}' conflicts with the file '{}': a path can't be both a file and a directory",
+                        destination, conflict
+                    )));
+                }
+                let size = chunks.iter().map(|chunk| chunk.size).sum();
+                if let Some(message) = self.exceeded_quota(Usage::of(&file_chunks), size) {
+                    warn!("[copy_file] Rejecting '{}': {}", destination, message);
+                    return Err(Status::resource_exhausted(message));
+                }
+                chunks
+            };
+
+            {
+                let mut copies = self.copies.write().await;
+                if copies.get(&destination).is_some_and(|copy| copy.running) {
+                    return Err(Status::already_exists(format!(
+                        "A copy to '{}' is already running",
+                        destination
+                    )));
+                }
+                copies.insert(
+                    destination.clone(),
+                    CopyProgress {
+                        source: source_name.clone(),
+                        running: true,
+                        chunk_ids: chunks.iter().map(|_| Uuid::new_v4().to_string()).collect(),
+                        ..Default::default()
+                    },
+                );
+            }
+            info!(
+                source = %source_name,
+                destination = %destination,
+                chunks = chunks.len(),
+                "Copying file"
+            );
+            let owner = identity.map(|identity| identity.username);
+            let service = Arc::clone(self);
+            let copied_name = destination.clone();
+            tokio::spawn(async move { service.run_copy(&copied_name, chunks, owner).await });
+
+            match self.copy_progress(&destination).await {
+                Some(status) => Ok(Response::new(status)),
+                None => Err(Status::internal(format!(
+                    "The copy to '{}' was not recorded",
+                    destination
+                ))),
+            }
+        }
+        .await;
+        timer.finish(&result);
+        audit(
+            self,
+            "copy",
+            &audited_name,
+            &user,
+            &source,
+            outcome(&result),
+        )
+        .await;
+        result
+    }
+
+    async fn copy_status(
+        &self,
+        request: Request<CopyStatusRequest>,
+    ) -> Result<Response<CopyStatusResponse>, Status> {
+        // Only the leader tracks copies, it ran them
+        if !self.is_leader().await {
+            return Err(not_leader(&self.current_master.read().await));
+        }
+        let destination =
+            normalize_path(&request.into_inner().destination).map_err(invalid_path)?;
+        match self.copy_progress(&destination).await {
+            Some(status) => Ok(Response::new(status)),
+            None => Err(Status::not_found(format!(
+                "No copy to '{}' was started on this master",
+                destination
+            ))),
+        }
+    }
+
+    /// Creates a snapshot of a file that lists the same chunks, see `snapshot_chunks`
+    async fn snapshot_file(
+        &self,
+        request: Request<SnapshotFileRequest>,
+    ) -> Result<Response<SnapshotFileResponse>, Status> {
+        let timer = self.metrics.requests.start("snapshot_file");
+        let source = AuditSource::of(&request);
+        let request = request.into_inner();
+        let identity = identify(self, &request.token).await;
+        let user = username(&identity);
+        let audited_name = audit_name(&request.snapshot_name);
+        let result: Result<Response<SnapshotFileResponse>, Status> = async move {
+            if !self.is_leader().await {
+                return Err(not_leader(&self.current_master.read().await));
+            }
+            let source_name = normalize_path(&request.source).map_err(invalid_path)?;
+            let snapshot_name = normalize_path(&request.snapshot_name).map_err(invalid_path)?;
+            let identity = identity?;
+            if let Some(identity) = identity
+                .as_ref()
+                .filter(|identity| !identity.role.may_write())
+            {
+                return Err(permission_denied(identity, &snapshot_name, ""));
+            }
+            if source_name == snapshot_name {
+                return Err(Status::invalid_argument(format!(
+                    "Can't snapshot '{}' onto itself",
+                    source_name
+                )));
+            }
+
+            // Lock both files in the same order as any other request would, to not deadlock
+            let (first, second) = match source_name < snapshot_name {
+                true => (&source_name, &snapshot_name),
+                false => (&snapshot_name, &source_name),
+            };
+            let first_lock = self.file_locks.lock(first).await;
+            let second_lock = self.file_locks.lock(second).await;
+            let owner = identity.map(|identity| identity.username);
+            let shared_chunks = snapshot_chunks(self, &source_name, &snapshot_name, owner).await?;
+            drop(second_lock);
+            drop(first_lock);
+            info!(
+                source = %source_name,
+                snapshot = %snapshot_name,
+                chunks = shared_chunks,
+                "Created snapshot"
+            );
+
+            // Send updated metadata to registered shadow masters
+            self.propagate_metadata_updates().await;
+
+            Ok(Response::new(SnapshotFileResponse {
+                message: format!(
+                    "Snapshot '{}' of '{}' created, sharing {} chunk(s).",
+                    snapshot_name, source_name, shared_chunks
+                ),
+                shared_chunks,
+            }))
+        }
+        .await;
+        timer.finish(&result);
+        audit(
+            self,
+            "snapshot",
+            &audited_name,
+            &user,
+            &source,
+            outcome(&result),
+        )
+        .await;
+        result
+    }
+
+    async fn cluster_status(
+        &self,
+        request: Request<ClusterStatusRequest>,
+    ) -> Result<Response<ClusterStatusResponse>, Status> {
+        let ClusterStatusRequest {
+            include_files,
+            token,
+        } = request.into_inner();
+        // The topology and file list are open to every authenticated user
+        identify(self, &token).await?;
+        Ok(Response::new(self.cluster_overview(include_files).await))
+    }
+
+    async fn reload_config(
+        &self,
+        request: Request<ReloadConfigRequest>,
+    ) -> Result<Response<ReloadConfigResponse>, Status> {
+        let source = AuditSource::of(&request);
+        let identity = identify(self, &request.into_inner().token).await;
+        let user = username(&identity);
+        let result = async {
+            check_admin(identity?.as_ref(), "reload the configuration").await?;
+            // Leaves the running settings untouched if the config file is invalid
+            MasterService::reload_config(self)
+                .await
+                .map(Response::new)
+                .map_err(|e| Status::failed_precondition(e.to_string()))
+        }
+        .await;
+        audit(self, "reload_config", "", &user, &source, outcome(&result)).await;
+        result
+    }
+
+    async fn query_audit(
+        &self,
+        request: Request<QueryAuditRequest>,
+    ) -> Result<Response<QueryAuditResponse>, Status> {
+        // Only the leader records entries
+        if !self.is_leader().await {
+            return Err(not_leader(&self.current_master.read().await));
+        }
+        let QueryAuditRequest {
+            file_name,
+            since,
+            limit,
+            token,
+        } = request.into_inner();
+        // With authentication, the audit log is open to admins only
+        let identity = identify(self, &token).await?;
+        check_admin(identity.as_ref(), "query the audit log").await?;
+        let dir = normalize_dir(&file_name).map_err(invalid_path)?;
+        self.audit
+            .query(&dir, since, limit as usize)
+            .await
+            .map(Response::new)
+            .map_err(|e| Status::internal(format!("Failed to read the audit log: {}", e)))
+    }
+
+    async fn get_file_chunks(
+        &self,
+        request: Request<FileChunkMappingRequest>,
+    ) -> Result<Response<FileChunkMapping>, Status> {
+        let source = AuditSource::of(&request);
+        let FileChunkMappingRequest {
+            file_name,
+            for_append,
+            token,
+            lease_chunks,
+        } = request.into_inner();
+        let file_name = normalize_path(&file_name).map_err(invalid_path)?;
+        debug!(file_name = %file_name, "Fetching chunks for file");
+        // Reads are open to every user, appends only to the owner
+        // Successful appends are audited by record_append, denied ones only show up here
+        if for_append {
+            let identity = identify(self, &token).await;
+            let user = username(&identity);
+            let allowed = match identity {
+                Ok(identity) => check_owner(self, identity.as_ref(), &file_name).await,
+                Err(status) => Err(status),
+            };
+            if allowed.is_err() {
+                audit(
+                    self,
+                    "append",
+                    &file_name,
+                    &user,
+                    &source,
+                    outcome(&allowed),
+                )
+                .await;
+            }
+            allowed?;
+        }
+
+        // Leasing may copy chunks shared with snapshots, which changes the file
+        let lock = match for_append {
+            true => Some(self.file_locks.lock(&file_name).await),
+            false => None,
+        };
+        let mut chunks = {
+            let file_chunks = self.file_chunks.read().await;
+            log_map("[get_file_chunks] file_chunks", &file_chunks);
+            file_chunks
+                .get(&file_name)
+                .map(|chunks| chunk_infos(chunks))
+                .ok_or_else(|| Status::not_found(format!("File '{}' not found", file_name)))?
+        };
+
+        // Leave out replicas on servers that missed their heartbeats, unless none is left.
+        // Shadow masters receive no heartbeats, so they list all replicas.
+        let from_shadow = !self.is_leader().await;
+        let mut possibly_stale = false;
+        if !from_shadow {
+            let healthy_servers = self.healthy_servers().await;
+            for chunk in &mut chunks {
+                let live: Vec<String> = chunk
+                    .server_addresses
+                    .iter()
+                    .filter(|addr| healthy_servers.contains(*addr))
+                    .cloned()
+                    .collect();
+                if live.is_empty() {
+                    possibly_stale = true;
+                } else {
+                    chunk.server_addresses = live;
+                    // Until the heartbeat checker elects a new primary for a failed one
+                    elect_primary(chunk);
+                }
+            }
+
+            // Appends go to the primary of the last chunk, writes within the file to those of
+            // the chunks they cover, so these need a lease. Chunks shared with snapshots are
+            // copied first, so that the snapshots keep their data.
+            if for_append {
+                let last = chunks.len().saturating_sub(1) as u64;
+                let mut unshared = false;
+                for (_, chunk) in (0..)
+                    .zip(&mut chunks)
+                    .filter(|(index, _)| *index == last || lease_chunks.contains(index))
+                {
+                    if chunk.shared_with > 0 {
+                        let copy = self
+                            .unshare_chunk(&file_name, &chunk.chunk_id)
+                            .await
+                            .map_err(|e| Status::unavailable(e.to_string()))?;
+                        *chunk = (*copy).clone();
+                        unshared = true;
+                    }
+                    if self.ensure_lease(chunk).await.is_none() {
+                        return Err(Status::unavailable(format!(
+                            "No replica of chunk '{}' accepted the lease",
+                            chunk.chunk_id
+                        )));
+                    }
+                }
+                if unshared {
+                    self.propagate_metadata_updates().await;
+                }
+            }
+
+            // Report the lease holder as primary, and also list it first for clients that
+            // predate the primary field and send appends to the first replica
+            let chunk_leases = self.chunk_leases.read().await;
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            for chunk in &mut chunks {
+                let Some(lease) = chunk_leases
+                    .get(&chunk.chunk_id)
+                    .filter(|lease| lease.expiry > now)
+                else {
+                    continue;
+                };
+                if let Some(position) = chunk
+                    .server_addresses
+                    .iter()
+                    .position(|addr| *addr == lease.primary)
+                {
+                    chunk.server_addresses[..=position].rotate_right(1);
+                    chunk.primary = lease.primary.clone();
+                    chunk.lease_expiry = lease.expiry;
+                }
+            }
+        }
+
+        // Spread reads over the replicas, the stored order stays as assigned. Appends keep the
+        // primary first.
+        drop(lock);
+        let ordered_by_load = !from_shadow && !for_append;
+        if ordered_by_load {
+            self.order_by_load(&mut chunks, &source.request_id).await;
+        }
+
+        Ok(Response::new(FileChunkMapping {
+            file_name,
+            chunks,
+            from_shadow,
+            epoch: self.epoch.load(Ordering::SeqCst),
+            possibly_stale,
+            ordered_by_load,
+        }))
+    }
+
+    /// Returns the chunk layout of a file together with its total size and replica health
+    async fn get_file_info(
+        &self,
+        request: Request<FileInfoRequest>,
+    ) -> Result<Response<FileInfoResponse>, Status> {
+        let file_name = normalize_path(&request.into_inner().file_name).map_err(invalid_path)?;
+        debug!("Fetching file info for file: {}", file_name);
+
+        let chunks = self
+            .file_chunks
+            .read()
+            .await
+            .get(&file_name)
+            .map(|chunks| chunk_infos(chunks))
+            .ok_or_else(|| Status::not_found(format!("File '{}' not found", file_name)))?;
+
+        let owner = self
+            .file_owners
+            .read()
+            .await
+            .get(&file_name)
+            .cloned()
+            .unwrap_or_default();
+        let replication_factor = self.common_config.replication_factor;
+        let total_size = chunks.iter().map(|chunk| chunk.size).sum();
+        let under_replicated_chunks = chunks
+            .iter()
+            .filter(|chunk| chunk.server_addresses.len() < replication_factor)
+            .count();
+
+        Ok(Response::new(FileInfoResponse {
+            file_name,
+            chunks,
+            total_size,
+            replication_factor: replication_factor as u64,
+            under_replicated_chunks: under_replicated_chunks as u64,
+            from_shadow: !self.is_leader().await,
+            owner,
+        }))
+    }
+
+    /// Records data appended to the last chunk of a file so that chunk sizes stay up to date
+    async fn record_append(
+        &self,
+        request: Request<RecordAppendRequest>,
+    ) -> Result<Response<RecordAppendResponse>, Status> {
+        let timer = self.metrics.requests.start("record_append");
+        let source = AuditSource::of(&request);
+        let RecordAppendRequest {
+            file_name,
+            size,
+            token,
+        } = request.into_inner();
+        let identity = identify(self, &token).await;
+        let user = username(&identity);
+        let result: Result<Response<RecordAppendResponse>, Status> = async {
+            if !self.is_leader().await {
+                return Err(not_leader(&self.current_master.read().await));
+            }
+            let file_name = normalize_path(&file_name).map_err(invalid_path)?;
+            let identity = identity?;
+            check_owner(self, identity.as_ref(), &file_name).await?;
+
+            let lock = self.file_locks.lock(&file_name).await;
+            let (last_chunk_id, last_chunk_size) = self
+                .file_chunks
+                .read()
+                .await
+                .get(&file_name)
+                .and_then(|chunks| chunks.last())
+                .map(|chunk| (chunk.chunk_id.clone(), chunk.size))
+                .ok_or_else(|| Status::not_found(format!("File '{}' not found", file_name)))?;
+
+            // A chunk never grows past chunk_size, so a larger size was not appended
+            let chunk_size = self.common_config.chunk_size;
+            let new_size = last_chunk_size
+                .checked_add(size)
+                .filter(|new_size| *new_size <= chunk_size)
+                .ok_or_else(|| {
+                    Status::invalid_argument(format!(
+                        "Appending {} byte(s) to chunk '{}' of {} byte(s) exceeds chunk_size ({})",
+                        size, last_chunk_id, last_chunk_size, chunk_size
+                    ))
+                })?;
+            self.update_chunk_info(&last_chunk_id, |chunk| chunk.size = new_size)
+                .await;
+            drop(lock);
+            info!(
+                "[record_append] Recorded {} appended byte(s) to chunk '{}'",
+                size, last_chunk_id
+            );
+
+            // Send updated metadata to registered shadow masters
+            self.propagate_metadata_updates().await;
+
+            Ok(Response::new(RecordAppendResponse {
+                message: format!("Append of {} byte(s) to '{}' recorded.", size, file_name),
+            }))
+        }
+        .await;
+        timer.finish(&result);
+        audit(
+            self,
+            "append",
+            &audit_name(&file_name),
+            &user,
+            &source,
+            outcome(&result),
+        )
+        .await;
+        result
+    }
+
+    /// Shortens a file, see `truncate_chunks`. Extending a file is not supported.
+    async fn truncate_file(
+        &self,
+        request: Request<TruncateFileRequest>,
+    ) -> Result<Response<TruncateFileResponse>, Status> {
+        let timer = self.metrics.requests.start("truncate_file");
+        let source = AuditSource::of(&request);
+        let TruncateFileRequest {
+            file_name,
+            new_length,
+            token,
+        } = request.into_inner();
+        let identity = identify(self, &token).await;
+        let user = username(&identity);
+        let audited_name = audit_name(&file_name);
+        let result: Result<Response<TruncateFileResponse>, Status> = async move {
+            if !self.is_leader().await {
+                return Err(not_leader(&self.current_master.read().await));
+            }
+            let file_name = normalize_path(&file_name).map_err(invalid_path)?;
+            let identity = identity?;
+            check_owner(self, identity.as_ref(), &file_name).await?;
+
+            let lock = self.file_locks.lock(&file_name).await;
+            let dropped_chunks = truncate_chunks(self, &file_name, new_length).await?;
+            drop(lock);
+
+            // Send updated metadata to registered shadow masters
+            self.propagate_metadata_updates().await;
+
+            Ok(Response::new(TruncateFileResponse {
+                message: format!(
+                    "File '{}' truncated to {} byte(s), {} chunk(s) deleted.",
+                    file_name, new_length, dropped_chunks
+                ),
+                dropped_chunks,
+            }))
+        }
+        .await;
+        timer.finish(&result);
+        audit(
+            self,
+            "truncate",
+            &audited_name,
+            &user,
+            &source,
+            outcome(&result),
+        )
+        .await;
+        result
+    }
+
+    /// Check replicas that a client reported as failed writes and drop those that missed the
+    /// write from the chunk metadata, see `verify_failed_replica`
+    async fn report_failed_replicas(
+        &self,
+        request: Request<ReportFailedReplicasRequest>,
+    ) -> Result<Response<ReportFailedReplicasResponse>, Status> {
+        if !self.is_leader().await {
+            return Err(not_leader(&self.current_master.read().await));
+        }
+        let ReportFailedReplicasRequest {
+            failed_replicas,
+            token,
+        } = request.into_inner();
+        let identity = identify(self, &token).await?;
+
+        // Only writers of a chunk's file know which of its replicas missed their writes
+        for replica in &failed_replicas {
+            let file_name = self
+                .chunk_map
+                .read()
+                .await
+                .get(&replica.chunk_id)
+                .map(|chunk| chunk.file_name.clone());
+            if let Some(file_name) = file_name {
+                check_owner(self, identity.as_ref(), &file_name).await?;
+            }
+        }
+
+        let mut removed = 0;
+        for replica in &failed_replicas {
+            let reported: Vec<&str> = failed_replicas
+                .iter()
+                .filter(|other| other.chunk_id == replica.chunk_id)
+                .map(|other| other.server_address.as_str())
+                .collect();
+            match self
+                .verify_failed_replica(&replica.chunk_id, &replica.server_address, &reported)
+                .await
+            {
+                Ok(true) => {
+                    warn!(
+                        "[report_failed_replicas] Removed replica of chunk '{}' on server '{}'",
+                        replica.chunk_id, replica.server_address
+                    );
+                    removed += 1;
+                }
+                Ok(false) => debug!(
+                    "[report_failed_replicas] Kept replica of chunk '{}' on server '{}'",
+                    replica.chunk_id, replica.server_address
+                ),
+                Err(e) => warn!(
+                    "[report_failed_replicas] Could not check replica of chunk '{}' on '{}', leaving it to the heartbeats: {}",
+                    replica.chunk_id, replica.server_address, e
+                ),
+            }
+        }
+
+        // Send updated metadata to registered shadow masters
+        if removed > 0 {
+            self.propagate_metadata_updates().await;
+        }
+
+        Ok(Response::new(ReportFailedReplicasResponse {
+            message: format!(
+                "Removed {} of {} reported replica(s).",
+                removed,
+                failed_replicas.len()
+            ),
+        }))
+    }
+
+    /// Drops a replica that the chunkserver deleted because it was corrupt
+    async fn report_corrupt_chunk(
+        &self,
+        request: Request<ReportCorruptChunkRequest>,
+    ) -> Result<Response<ReportCorruptChunkResponse>, Status> {
+        if !self.is_leader().await {
+            return Err(not_leader(&self.current_master.read().await));
+        }
+        let ReportCorruptChunkRequest {
+            chunkserver_address,
+            chunk_id,
+            internal_proof,
+        } = request.into_inner();
+        // Only the chunkserver holding the replica may have it dropped on its word
+        if let Err(e) = self.verify_chunkserver_request(
+            &internal_proof,
+            &report_corrupt_chunk_fields(&chunkserver_address, &chunk_id),
+        ) {
+            warn!(
+                "[report_corrupt_chunk] Ignoring report of chunk '{}' on server '{}': {}",
+                chunk_id, chunkserver_address, e
+            );
+            return Err(Status::unauthenticated(e.to_string()));
+        }
+
+        error!(
+            "[report_corrupt_chunk] Replica of chunk '{}' on server '{}' is corrupt, removing it",
+            chunk_id, chunkserver_address
+        );
+        self.remove_replica(&chunk_id, &chunkserver_address).await;
+
+        // Send updated metadata to registered shadow masters
+        self.propagate_metadata_updates().await;
+
+        Ok(Response::new(ReportCorruptChunkResponse {
+            message: format!("Removed corrupt replica of chunk '{}'.", chunk_id),
+        }))
+    }
+
+    /// Check a replica that a client failed to read from, in the background, and drop it if
+    /// the chunk is missing or corrupt there, see `verify_reported_replica`
+    async fn report_bad_replica(
+        &self,
+        request: Request<ReportBadReplicaRequest>,
+    ) -> Result<Response<ReportBadReplicaResponse>, Status> {
+        if !self.is_leader().await {
+            return Err(not_leader(&self.current_master.read().await));
+        }
+        let ReportBadReplicaRequest {
+            chunk_id,
+            server_address,
+            reason,
+        } = request.into_inner();
+        self.metrics.bad_replica_reports.inc();
+
+        warn!(
+            "[report_bad_replica] A client failed to read chunk '{}' from '{}': {}",
+            chunk_id, server_address, reason
+        );
+        let service = Arc::clone(self);
+        tokio::spawn(async move {
+            match service
+                .verify_reported_replica(&chunk_id, &server_address)
+                .await
+            {
+                Ok(true) => warn!(
+                    "[report_bad_replica] Replica of chunk '{}' on '{}' is missing or corrupt, removed it",
+                    chunk_id, server_address
+                ),
+                Ok(false) => debug!(
+                    "[report_bad_replica] Kept replica of chunk '{}' on '{}'",
+                    chunk_id, server_address
+                ),
+                Err(e) => warn!(
+                    "[report_bad_replica] Could not check replica of chunk '{}' on '{}', leaving it to the heartbeats: {}",
+                    chunk_id, server_address, e
+                ),
+            }
+        });
+
+        Ok(Response::new(ReportBadReplicaResponse {
+            message: "Checking the replica.".to_string(),
+        }))
+    }
+
+    /// Grant or deny a candidate this master's vote in an election for leader, once the leader
+    /// missed its pings and only if the candidate's metadata is at least as new, see `vote`
+    async fn request_vote(
+        &self,
+        request: Request<VoteRequest>,
+    ) -> Result<Response<VoteResponse>, Status> {
+        let VoteRequest {
+            candidate_address,
+            metadata_version,
+        } = request.into_inner();
+        let (granted, leader_address) = self.vote(&candidate_address, metadata_version).await;
+        info!(
+            "[request_vote] {} vote for {} (metadata version {})",
+            if granted { "Granting" } else { "Denying" },
+            candidate_address,
+            metadata_version
+        );
+        Ok(Response::new(VoteResponse {
+            granted,
+            leader_address: leader_address.unwrap_or_default(),
+        }))
+    }
+
+    async fn leader_changed(
+        &self,
+        request: Request<LeaderChangedRequest>,
+    ) -> Result<Response<LeaderChangedResponse>, Status> {
+        let LeaderChangedRequest {
+            leader_address,
+            metadata_version,
+            internal_proof,
+        } = request.into_inner();
+        // Only a configured master may make this one follow it, and sync its metadata from it
+        let version = metadata_version.to_be_bytes();
+        if let Err(e) = self.verify_master_request(
+            &leader_address,
+            &internal_proof,
+            &leader_changed_fields(&leader_address, &version),
+        ) {
+            warn!(
+                "[leader_changed] Ignoring announcement of leader {}: {}",
+                leader_address, e
+            );
+            return Err(Status::unauthenticated(e.to_string()));
+        }
+        let accepted = self
+            .follow_announced_leader(&leader_address, metadata_version)
+            .await;
+        if !accepted {
+            warn!(
+                "[leader_changed] Refusing leader {} with older metadata version {}",
+                leader_address, metadata_version
+            );
+        }
+        Ok(Response::new(LeaderChangedResponse {
+            accepted,
+            leader_address: if accepted {
+                String::new()
+            } else {
+                self.addr.clone()
+            },
+        }))
+    }
+
+    /// Returns this master's address if it is the leader, otherwise the leader it follows
+    async fn get_leader(
+        &self,
+        _request: Request<GetLeaderRequest>,
+    ) -> Result<Response<GetLeaderResponse>, Status> {
+        let leader_address = if self.is_leader().await {
+            self.addr.clone()
+        } else {
+            self.current_master.read().await.clone()
+        };
+        Ok(Response::new(GetLeaderResponse { leader_address }))
+    }
+
+    /// Handle ping master requests
+    async fn ping_master(
+        &self,
+        request: Request<PingMasterRequest>,
+    ) -> Result<Response<PingMasterResponse>, Status> {
+        let PingMasterRequest {
+            sender_address,
+            internal_proof,
+        } = request.into_inner();
+        info!("[ping_master] Received ping from: {}", sender_address);
+        // Registered senders are sent the full metadata, so only configured masters may ping
+        if let Err(e) = self.verify_master_request(
+            &sender_address,
+            &internal_proof,
+            &ping_master_fields(&sender_address),
+        ) {
+            warn!("[ping_master] Ignoring ping from {}: {}", sender_address, e);
+            return Err(Status::unauthenticated(e.to_string()));
+        }
+
+        if self.is_leader().await {
+            let mut shadow_masters = self.shadow_masters.write().await;
+            // Record the ping and check if the sender was newly added
+            if shadow_masters
+                .insert(sender_address.clone(), Instant::now())
+                .is_none()
+            {
+                info!(
+                    "[ping_master] Registered '{}' as a shadow master",
+                    sender_address
+                );
+            }
+        }
+
+        Ok(Response::new(PingMasterResponse {
+            is_leader: self.is_leader().await,
+            metadata_version: *self.metadata_version.read().await,
+        }))
+    }
+}
+
+/// Reject a mutating request on a shadow master with `FailedPrecondition`, naming the leader
+/// under `LEADER_ADDRESS_KEY` so that the client can retry there
+fn not_leader(leader: &str) -> Status {
+    let mut status = Status::failed_precondition(format!(
+        "This master is not the leader, the leader is at {}",
+        leader
+    ));
+    if let Ok(value) = leader.parse() {
+        status.metadata_mut().insert(LEADER_ADDRESS_KEY, value);
+    }
+    status
+}
+
+/// Reject a request with an invalid file or directory path with `InvalidArgument`
+fn invalid_path(e: Error) -> Status {
+    Status::invalid_argument(e.to_string())
+}
+
+/// Identify the user of a mutating request, `None` without authentication. A missing, invalid
+/// or expired session token is rejected with `Unauthenticated`.
+async fn identify(master: &MasterService, token: &str) -> Result<Option<Identity>, Status> {
+    master.identify(token).await.map_err(|e| match e {
+        Error::Auth(message) => Status::unauthenticated(message),
+        e => Status::internal(format!("Failed to check the session token: {}", e)),
+    })
+}
+
+/// Name of the user behind an identified request, empty without authentication or a valid token
+fn username(identity: &Result<Option<Identity>, Status>) -> String {
+    match identity {
+        Ok(Some(identity)) => identity.username.clone(),
+        _ => String::new(),
+    }
+}
+
+/// File name as recorded in the audit log, the requested name if it isn't a valid path
+fn audit_name(file_name: &str) -> String {
+    normalize_path(file_name).unwrap_or_else(|_| file_name.to_string())
+}
+
+/// Record the outcome of a request in the audit log. Shadow masters reject mutating requests and
+/// the client retries them at the leader, so only the leader records them.
+async fn audit(
+    master: &MasterService,
+    operation: &str,
+    file_name: &str,
+    user: &str,
+    source: &AuditSource,
+    result: String,
+) {
+    if master.is_leader().await {
+        master
+            .audit
+            .record(operation, file_name, user, source, result);
+    }
+}
+
+/// Reject a change of a file by a user other than its owner or an admin with `PermissionDenied`
+async fn check_owner(
+    master: &MasterService,
+    identity: Option<&Identity>,
+    file_name: &str,
+) -> Result<(), Status> {
+    let Some(identity) = identity else {
+        return Ok(());
+    };
+    let file_owners = master.file_owners.read().await;
+    let owner = file_owners.get(file_name).map_or("", String::as_str);
+    if identity.may_modify(owner) {
+        Ok(())
+    } else {
+        Err(permission_denied(identity, file_name, owner))
+    }
+}
+
+/// Reject a cluster administration request by a user other than an admin with
+/// `PermissionDenied`, `action` describes the request
+async fn check_admin(identity: Option<&Identity>, action: &str) -> Result<(), Status> {
+    match identity {
+        Some(identity) if identity.role != Role::Admin => {
+            warn!(user = %identity.username, action, "Denied an administration request");
+            Err(Status::permission_denied(format!(
+                "Only admins may {}, user '{}' is {}",
+                action,
+                identity.username,
+                identity.role.as_str()
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Cut a file to `new_length` bytes, holding its lock, see `FileLocks`.
+///
+/// Chunks ending at or before the new length are kept and the chunk it falls into is cut on
+/// all its replicas, replicas that fail are dropped. The chunks after it are deleted like the
+/// chunks of a deleted file. A cut chunk shared with snapshots is copied first, so that they
+/// keep their data. A file cut to nothing keeps its first chunk, empty, as appends go to the
+/// last chunk. Returns the number of deleted chunks.
+async fn truncate_chunks(
+    master: &MasterService,
+    file_name: &str,
+    new_length: u64,
+) -> Result<u64, Status> {
+    let chunks = master
+        .file_chunks
+        .read()
+        .await
+        .get(file_name)
+        .cloned()
+        .ok_or_else(|| Status::not_found(format!("File '{}' not found", file_name)))?;
+    let size: u64 = chunks.iter().map(|chunk| chunk.size).sum();
+    if new_length > size {
+        return Err(Status::unimplemented(format!(
+            "Cannot extend '{}' of {} byte(s) to {} byte(s), files can only be shortened",
+            file_name, size, new_length
+        )));
+    }
+
+    let mut kept = 0;
+    let mut offset = 0;
+    let mut partial = None;
+    for chunk in &chunks {
+        if offset >= new_length && kept > 0 {
+            break;
+        }
+        kept += 1;
+        if offset + chunk.size > new_length {
+            partial = Some((chunk, new_length - offset));
+        }
+        offset += chunk.size;
+    }
+
+    if let Some((chunk, length)) = partial {
+        let chunk = &master
+            .unshare_chunk(file_name, &chunk.chunk_id)
+            .await
+            .map_err(|e| Status::unavailable(e.to_string()))?;
+        let failed = master.truncate_replicas(chunk, length).await;
+        if failed.len() == chunk.server_addresses.len() {
+            return Err(Status::unavailable(format!(
+                "No replica of chunk '{}' of '{}' could be truncated",
+                chunk.chunk_id, file_name
+            )));
+        }
+        for server in &failed {
+            master.remove_replica(&chunk.chunk_id, server).await;
+        }
+        master
+            .update_chunk_info(&chunk.chunk_id, |chunk| chunk.size = length)
+            .await;
+        info!(
+            "[truncate_file] Truncated chunk '{}' of '{}' to {} byte(s)",
+            chunk.chunk_id, file_name, length
+        );
+    }
+
+    let dropped = chunks[kept..].to_vec();
+    if dropped.is_empty() {
+        return Ok(0);
+    }
+    {
+        let mut file_chunks = master.file_chunks.write().await;
+        // A file deleted in the meantime took its chunks along
+        let Some(file_chunks) = file_chunks.get_mut(file_name) else {
+            return Ok(0);
+        };
+        file_chunks.retain(|chunk| {
+            !dropped
+                .iter()
+                .any(|dropped| dropped.chunk_id == chunk.chunk_id)
+        });
+    }
+    master.purge_chunks(&dropped).await;
+    let mut chunk_leases = master.chunk_leases.write().await;
+    for chunk in &dropped {
+        chunk_leases.remove(&chunk.chunk_id);
+    }
+    info!(
+        "[truncate_file] Deleted {} chunk(s) of '{}' past {} byte(s)",
+        dropped.len(),
+        file_name,
+        new_length
+    );
+    Ok(dropped.len() as u64)
+}
+
+/// Record `snapshot_name` as a file listing the chunks of `source`, holding the locks of both,
+/// see `FileLocks`.
+///
+/// The leases on the chunks are revoked first, so appends and writes to `source` ask for new
+/// ones, which copies the chunks they change, see `MasterService::unshare_chunk`. Each chunk
+/// counts the further files sharing it, and is only deleted with the last of them. Returns the
+/// number of shared chunks.
+async fn snapshot_chunks(
+    master: &MasterService,
+    source: &str,
+    snapshot_name: &str,
+    owner: Option<String>,
+) -> Result<u64, Status> {
+    let chunks = {
+        let file_chunks = master.file_chunks.read().await;
+        let Some(chunks) = file_chunks.get(source).cloned() else {
+            return Err(Status::not_found(format!("File '{}' not found", source)));
+        };
+        if file_chunks.contains_key(snapshot_name) {
+            return Err(Status::already_exists(format!(
+                "File '{}' already exists",
+                snapshot_name
+            )));
+        }
+        if let Some(conflict) = find_conflict(file_chunks.keys(), snapshot_name) {
+            return Err(Status::already_exists(format!(
+                "'{}' conflicts with the file '{}': a path can't be both a file and a directory",
+                snapshot_name, conflict
+            )));
+        }
+        // The snapshot counts fully towards the quota, as it would once the source changes
+        let size = chunks.iter().map(|chunk| chunk.size).sum();
+        if let Some(message) = master.exceeded_quota(Usage::of(&file_chunks), size) {
+            warn!("[snapshot_file] Rejecting '{}': {}", snapshot_name, message);
+            return Err(Status::resource_exhausted(message));
+        }
+        chunks
+    };
+
+    master.revoke_leases(&chunks).await.map_err(|e| {
+        Status::unavailable(format!(
+            "Failed to revoke the leases on the chunks of '{}': {}",
+            source, e
+        ))
+    })?;
+
+    master.share_chunks(source, snapshot_name).await;
+    if let Some(owner) = owner {
+        master
+            .file_owners
+            .write()
+            .await
+            .insert(snapshot_name.to_string(), owner);
+    }
+    Ok(chunks.len() as u64)
+}
+
+/// Response to an earlier attempt of a request by `user` with `operation_id`, `None` for a new
+/// or forgotten operation or an empty ID. An ID sent before with a request for another file or
+/// by another user is rejected with `InvalidArgument`.
+async fn completed_operation(
+    master: &MasterService,
+    operation_id: &str,
+    user: &str,
+    file_name: &str,
+) -> Result<Option<OperationResponse>, Status> {
+    if operation_id.is_empty() {
+        return Ok(None);
+    }
+    match master.operations.lock().await.get(operation_id) {
+        Some(operation) if operation.user == user && operation.file_name == file_name => {
+            Ok(Some(operation.response.clone()))
+        }
+        Some(_) => Err(operation_id_reused(operation_id)),
+        None => Ok(None),
+    }
+}
+
+/// Keep the response to a completed request for retries with the same `operation_id`
+async fn remember_operation(
+    master: &MasterService,
+    operation_id: &str,
+    user: &str,
+    file_name: &str,
+    response: OperationResponse,
+) {
+    master.operations.lock().await.insert(
+        operation_id,
+        CompletedOperation {
+            user: user.to_string(),
+            file_name: file_name.to_string(),
+            response,
+        },
+    );
+}
+
+fn operation_id_reused(operation_id: &str) -> Status {
+    Status::invalid_argument(format!(
+        "Operation ID '{}' was already used for another request",
+        operation_id
+    ))
+}
+
+/// Rejection of a write that would get only `replicas` replicas with `allow_degraded_writes` off
+fn degraded_write_rejected(master: &MasterService, replicas: usize) -> Status {
+    Status::failed_precondition(format!(
+        "Only {} chunkserver(s) can store chunks, but replication_factor is {} and allow_degraded_writes is false",
+        replicas, master.common_config.replication_factor
+    ))
+}
+
+fn permission_denied(identity: &Identity, file_name: &str, owner: &str) -> Status {
+    warn!(
+        user = %identity.username,
+        role = identity.role.as_str(),
+        file_name = %file_name,
+        owner = %owner,
+        "Denied a change of a file"
+    );
+    if !identity.role.may_write() {
+        Status::permission_denied(format!(
+            "User '{}' is readonly and may not change '{}'",
+            identity.username, file_name
+        ))
+    } else if owner.is_empty() {
+        Status::permission_denied(format!(
+            "'{}' has no owner, only admins may change it",
+            file_name
+        ))
+    } else {
+        Status::permission_denied(format!(
+            "'{}' belongs to '{}', user '{}' may not change it",
+            file_name, owner, identity.username
+        ))
     }
 }
 
 /// Determines the leader among all configured master nodes.
 ///
 /// - Tries to connect to all nodes listed in `master_addrs`.
+/// - Signs each ping with `cluster_secret`, see `ping_master_fields`.
 /// - Returns the leader's address if found; otherwise, `None`.
-pub async fn determine_leader(self_addr: &str, master_addrs: &[String]) -> Option<String> {
+pub async fn determine_leader(
+    self_addr: &str,
+    master_addrs: &[String],
+    tls: &TlsConfig,
+    cluster_secret: &str,
+) -> Option<String> {
     for addr in master_addrs {
         if addr == self_addr {
             continue; // Skip checking itself
         }
 
-        let target_url = format!("http://{}", addr);
-        match crate::proto::master::master_client::MasterClient::connect(target_url).await {
-            Ok(mut client) => {
+        match connect_channel(addr, tls).await {
+            Ok(channel) => {
+                let mut client = crate::proto::master::master_client::MasterClient::new(channel);
                 let request = tonic::Request::new(PingMasterRequest {
                     sender_address: self_addr.to_string(),
+                    internal_proof: sign_request(cluster_secret, &ping_master_fields(self_addr)),
                 });
 
                 match client.ping_master(request).await {
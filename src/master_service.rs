@@ -1,82 +1,523 @@
 // Implements the internal logic and utilities of the MasterService struct
-use md5;
-use serde::{Deserialize, Serialize};
+use futures::StreamExt;
+use prost::Message;
+use rand::Rng;
 use serde_json::Value;
-use std::cmp::Reverse;
-use std::collections::{BinaryHeap, HashMap, HashSet};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
 use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
-use tokio::sync::RwLock;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, Mutex, RwLock};
 use tokio::time::{self, Duration};
-use tracing::{debug, error, info, warn};
+use tonic::transport::Channel;
+use tracing::{debug, error, info, trace, warn};
+use uuid::Uuid;
 
+use crate::audit::AuditLog;
+use crate::auth::{user_role, verify_user, AuthThrottle, Role};
+use crate::chunkserver_service::{
+    clone_chunk_fields, grant_lease_fields, leader_changed_fields as chunk_leader_changed_fields,
+    transfer_chunk_fields, truncate_chunk_fields,
+};
+use crate::cluster_auth::{sign_placement, sign_request, verify_request};
 use crate::config::{CommonConfig, MasterConfig};
+use crate::error::{Error, Result};
+use crate::metrics::MasterMetrics;
+use crate::namespace::find_conflict;
 use crate::proto::master;
-use crate::proto::master::{PingMasterRequest, UpdateMetadataRequest};
+use crate::proto::master::{
+    ChunkServerStatus, ClusterStatusResponse, DecommissionResponse, FileStatus, GetMetadataRequest,
+    LeaderChangedRequest, PingMasterRequest, RebalanceStatusResponse, ShadowMasterStatus,
+    TrashedFile, UpdateMetadataRequest, VoteRequest,
+};
+use crate::reload::{ConfigReloader, ReloadableSettings};
+use crate::shutdown::BackgroundTasks;
+use crate::token::{issue_token, verify_token, TokenClaims};
+use crate::units::format_duration;
+use crate::util::connect_channel;
 
 // Import the Master service and messages
 use crate::proto::chunk::chunk_client::ChunkClient;
-use crate::proto::chunk::SendChunkRequest;
+use crate::proto::chunk::{
+    ChecksumChunkRequest, CloneChunkRequest, GrantLeaseRequest, SendChunkRequest,
+    TruncateChunkRequest,
+};
+use file_locks::FileLocks;
 use master::ChunkInfo;
+use operations::OperationCache;
+use placement::{select_servers, Candidates, PlacementPolicy};
 
-#[derive(Serialize, Deserialize, Clone)]
+pub mod file_locks;
+pub mod operations;
+pub mod placement;
+
+/// File `name` in the master's data directory, `data_path` below a directory named after the
+/// master's address, or `data_path` itself if it is absolute
+fn data_file(addr: &str, config: &MasterConfig, name: &str) -> String {
+    Path::new(&addr.replace(':', "_"))
+        .join(&config.data_path)
+        .join(name)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// File in the master's data directory storing the last leader epoch
+fn epoch_path(addr: &str, config: &MasterConfig) -> String {
+    data_file(addr, config, "epoch")
+}
+
+/// File in the master's data directory storing the audit log
+fn audit_log_path(addr: &str, config: &MasterConfig) -> String {
+    data_file(addr, config, "audit.log")
+}
+
+/// Read the last persisted leader epoch, 0 if there is none
+fn load_epoch(path: &str) -> u64 {
+    match fs::read_to_string(path) {
+        Ok(content) => content.trim().parse().unwrap_or_else(|e| {
+            warn!("Ignoring invalid epoch in '{}': {}", path, e);
+            0
+        }),
+        Err(_) => 0,
+    }
+}
+
+/// Largest metadata map whose entries are logged at trace level, formatting larger maps on every
+/// request would slow the master down
+const MAX_LOGGED_ENTRIES: usize = 100;
+
+/// Log the number of entries of a metadata map at debug level, and the entries themselves at
+/// trace level if there are at most `MAX_LOGGED_ENTRIES`
+pub fn log_map<K: Debug, V: Debug>(name: &str, map: &HashMap<K, V>) {
+    debug!(entries = map.len(), "{}", name);
+    if map.len() <= MAX_LOGGED_ENTRIES {
+        trace!("{}: {:?}", name, map);
+    }
+}
+
+/// Metadata versions queued for replication before `propagate_metadata_updates` stops queueing
+const REPLICATION_QUEUE_SIZE: usize = 64;
+
+/// Snapshot of the metadata sent to shadow masters, sharing the chunks with the maps it was taken
+/// from until it is converted to the proto message
+#[derive(Clone)]
 pub struct Metadata {
-    pub file_chunks: HashMap<String, Vec<ChunkInfo>>,
-    pub chunk_servers: HashMap<String, Vec<ChunkInfo>>,
-    pub chunk_map: HashMap<String, ChunkInfo>,
-}
-
-impl Into<UpdateMetadataRequest> for Metadata {
-    fn into(self) -> UpdateMetadataRequest {
-        UpdateMetadataRequest {
-            metadata: Some(crate::proto::master::Metadata {
-                file_chunks: self
-                    .file_chunks
-                    .into_iter()
-                    .map(|(key, value)| {
-                        (
-                            key,
-                            crate::proto::master::ChunkList {
-                                chunks: value.into_iter().map(|chunk| chunk.into()).collect(),
-                            },
-                        )
-                    })
-                    .collect(),
-                chunk_servers: self
-                    .chunk_servers
-                    .into_iter()
-                    .map(|(key, value)| {
-                        (
-                            key,
-                            crate::proto::master::ChunkList {
-                                chunks: value.into_iter().map(|chunk| chunk.into()).collect(),
-                            },
-                        )
-                    })
-                    .collect(),
-                chunk_map: self
-                    .chunk_map
-                    .into_iter()
-                    .map(|(key, value)| (key, value.into()))
-                    .collect(),
-            }),
+    pub file_chunks: HashMap<String, Vec<Arc<ChunkInfo>>>,
+    pub chunk_servers: HashMap<String, Vec<Arc<ChunkInfo>>>,
+    pub chunk_map: HashMap<String, Arc<ChunkInfo>>,
+    pub pending_deletions: HashMap<String, HashSet<String>>,
+    pub trash: HashMap<String, TrashedFile>,
+    pub metadata_version: u64,
+    pub epoch: u64,
+    pub file_owners: HashMap<String, String>,
+}
+
+impl From<Metadata> for crate::proto::master::Metadata {
+    fn from(val: Metadata) -> Self {
+        crate::proto::master::Metadata {
+            file_chunks: val
+                .file_chunks
+                .into_iter()
+                .map(|(key, value)| {
+                    (
+                        key,
+                        crate::proto::master::ChunkList {
+                            chunks: value.into_iter().map(Arc::unwrap_or_clone).collect(),
+                        },
+                    )
+                })
+                .collect(),
+            chunk_servers: val
+                .chunk_servers
+                .into_iter()
+                .map(|(key, value)| {
+                    (
+                        key,
+                        crate::proto::master::ChunkList {
+                            chunks: value.into_iter().map(Arc::unwrap_or_clone).collect(),
+                        },
+                    )
+                })
+                .collect(),
+            chunk_map: val
+                .chunk_map
+                .into_iter()
+                .map(|(key, value)| (key, Arc::unwrap_or_clone(value)))
+                .collect(),
+            pending_deletions: val
+                .pending_deletions
+                .into_iter()
+                .map(|(key, value)| {
+                    (
+                        key,
+                        crate::proto::master::ChunkIdList {
+                            chunk_ids: value.into_iter().collect(),
+                        },
+                    )
+                })
+                .collect(),
+            trash: val.trash.into_iter().collect(),
+            metadata_version: val.metadata_version,
+            epoch: val.epoch,
+            file_owners: val.file_owners.into_iter().collect(),
+        }
+    }
+}
+
+/// User a mutating request was sent by, see `MasterService::identify`
+#[derive(Clone, Debug)]
+pub struct Identity {
+    pub username: String,
+    pub role: Role,
+}
+
+impl Identity {
+    /// Whether this user may delete or append to a file created by `owner`.
+    ///
+    /// Admins may change every file, readonly users none, and other users only their own. Files
+    /// without an owner, which were created while authentication was disabled, can only be
+    /// changed by admins.
+    pub fn may_modify(&self, owner: &str) -> bool {
+        match self.role {
+            Role::Admin => true,
+            Role::ReadWrite => !owner.is_empty() && owner == self.username,
+            Role::ReadOnly => false,
+        }
+    }
+}
+
+/// Disk capacity last reported by a chunkserver in its heartbeat
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ServerCapacity {
+    pub total_bytes: u64,
+    pub available_bytes: u64,
+}
+
+/// Lease making a replica the primary of a chunk, which orders the appends to it
+#[derive(Clone, Debug)]
+pub struct ChunkLease {
+    pub primary: String,
+    pub expiry: u64,           // Seconds since UNIX epoch
+    pub replicas: Vec<String>, // Replicas the primary may apply appends and writes to
+}
+
+/// Result of the background scrubbing last reported by a chunkserver in its heartbeat
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ScrubStatus {
+    pub last_scrub_time: u64, // Seconds since UNIX epoch, 0 if no pass finished yet
+    pub corrupt_chunks: u64,  // Corrupt chunks found since the chunkserver started
+}
+
+/// Storage used by the files of the namespace, counted against the quotas
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Usage {
+    pub total_bytes: u64,
+    pub files: u64,
+}
+
+impl Usage {
+    /// Sum up the sizes of the files in `file_chunks`
+    pub fn of(file_chunks: &HashMap<String, Vec<Arc<ChunkInfo>>>) -> Self {
+        Usage {
+            total_bytes: file_chunks
+                .values()
+                .flat_map(|chunks| chunks.iter())
+                .map(|chunk| chunk.size)
+                .sum(),
+            files: file_chunks.len() as u64,
+        }
+    }
+}
+
+/// Returns the replicas of `chunk` that are not on `failed_server`, and how many new replicas
+/// are needed to get back to `replication_factor`
+pub fn healthy_replicas(
+    chunk: &ChunkInfo,
+    failed_server: &str,
+    replication_factor: usize,
+) -> (Vec<String>, usize) {
+    let healthy: Vec<String> = chunk
+        .server_addresses
+        .iter()
+        .filter(|addr| *addr != failed_server)
+        .cloned()
+        .collect();
+    let needed = replication_factor.saturating_sub(healthy.len());
+    (healthy, needed)
+}
+
+/// Keep the primary of a chunk while it holds a replica, otherwise make the first replica the
+/// primary, e.g. once the primary failed. Empty if no replica is left.
+pub fn elect_primary(chunk: &mut ChunkInfo) {
+    if !chunk.server_addresses.contains(&chunk.primary) {
+        chunk.primary = chunk.server_addresses.first().cloned().unwrap_or_default();
+    }
+}
+
+/// Fields of a LeaderChanged request to another master signed by its `internal_proof`
+pub fn leader_changed_fields<'a>(
+    leader_address: &'a str,
+    metadata_version: &'a [u8; 8],
+) -> [&'a [u8]; 3] {
+    [
+        b"leader_changed",
+        leader_address.as_bytes(),
+        metadata_version,
+    ]
+}
+
+/// Fields of an UpdateMetadata request to a shadow master signed by its `internal_proof`, which
+/// covers the metadata sent by its `metadata_digest`
+pub fn update_metadata_fields<'a>(
+    leader_address: &'a str,
+    metadata_version: &'a [u8; 8],
+    metadata_digest: &'a [u8; 32],
+) -> [&'a [u8]; 4] {
+    [
+        b"update_metadata",
+        leader_address.as_bytes(),
+        metadata_version,
+        metadata_digest,
+    ]
+}
+
+/// SHA-256 of the encoded metadata, so that a captured proof of an UpdateMetadata request can't
+/// be replayed with other metadata. The maps of the message are sorted, so every master encodes
+/// the same metadata alike.
+pub fn metadata_digest(metadata: &master::Metadata) -> [u8; 32] {
+    Sha256::digest(metadata.encode_to_vec()).into()
+}
+
+/// Fields of a PingMaster request of another master signed by its `internal_proof`
+pub fn ping_master_fields(sender_address: &str) -> [&[u8]; 2] {
+    [b"ping_master", sender_address.as_bytes()]
+}
+
+/// Fields of a GetMetadata request of a shadow master signed by its `internal_proof`
+pub fn get_metadata_fields(sender_address: &str) -> [&[u8]; 2] {
+    [b"get_metadata", sender_address.as_bytes()]
+}
+
+/// Fields of an Unregister request of a chunkserver signed by its `internal_proof`
+pub fn unregister_fields(address: &str) -> [&[u8]; 2] {
+    [b"unregister", address.as_bytes()]
+}
+
+/// Fields of a RegisterChunkServer request of a chunkserver signed by its `internal_proof`
+pub fn register_fields<'a>(address: &'a str, failure_domain: &'a str) -> [&'a [u8]; 3] {
+    [
+        b"register_chunk_server",
+        address.as_bytes(),
+        failure_domain.as_bytes(),
+    ]
+}
+
+/// Fields of a Heartbeat request of a chunkserver signed by its `internal_proof`, see
+/// `heartbeat_digest`
+pub fn heartbeat_fields<'a>(chunkserver_address: &'a str, digest: &'a [u8; 32]) -> [&'a [u8]; 3] {
+    [b"heartbeat", chunkserver_address.as_bytes(), digest]
+}
+
+/// SHA-256 of a heartbeat whose `internal_proof` is empty, e.g. taken out, so that a captured
+/// proof can't be sent along with other chunk reports
+pub fn heartbeat_digest(request: &master::HeartbeatRequest) -> [u8; 32] {
+    Sha256::digest(request.encode_to_vec()).into()
+}
+
+/// Fields of a ReportCorruptChunk request of a chunkserver signed by its `internal_proof`
+pub fn report_corrupt_chunk_fields<'a>(
+    chunkserver_address: &'a str,
+    chunk_id: &'a str,
+) -> [&'a [u8]; 3] {
+    [
+        b"report_corrupt_chunk",
+        chunkserver_address.as_bytes(),
+        chunk_id.as_bytes(),
+    ]
+}
+
+/// Copy shared chunks into the messages of a response
+pub fn chunk_infos(chunks: &[Arc<ChunkInfo>]) -> Vec<ChunkInfo> {
+    chunks.iter().map(|chunk| ChunkInfo::clone(chunk)).collect()
+}
+
+/// Apply `update` to the ChunkInfo of a chunk in chunk_map and share the result with every
+/// copy in `file_chunks` and `chunk_servers`, returning it. None if the chunk is not in
+/// chunk_map.
+///
+/// The copies are looked up through the file named in the chunk and the servers holding its
+/// replicas before and after the update. Only a chunk shared with further files, e.g.
+/// snapshots, has the lists of all files searched, as those files are not recorded. A shared
+/// chunk that the file named in it no longer lists is attributed to a file that does.
+fn replace_chunk(
+    file_chunks: &mut HashMap<String, Vec<Arc<ChunkInfo>>>,
+    chunk_servers: &mut HashMap<String, Vec<Arc<ChunkInfo>>>,
+    chunk_map: &mut HashMap<String, Arc<ChunkInfo>>,
+    chunk_id: &str,
+    update: impl FnOnce(&mut ChunkInfo),
+) -> Option<Arc<ChunkInfo>> {
+    let shared = chunk_map.get_mut(chunk_id)?;
+    let previous = Arc::clone(shared);
+    // The copies are replaced below rather than changed in place, so they stay shared
+    let mut chunk = ChunkInfo::clone(shared);
+    update(&mut chunk);
+    let is_shared = previous.shared_with > 0 || chunk.shared_with > 0;
+    let lists_chunk = |chunks: &Vec<Arc<ChunkInfo>>| {
+        chunks
+            .iter()
+            .position(|existing| existing.chunk_id == chunk_id)
+    };
+    if is_shared
+        && file_chunks
+            .get(&chunk.file_name)
+            .and_then(lists_chunk)
+            .is_none()
+    {
+        // E.g. the file was deleted or got a copy of the chunk, a file still listing it owns it
+        if let Some((file_name, index)) = file_chunks
+            .iter()
+            .find_map(|(file_name, chunks)| Some((file_name, lists_chunk(chunks)?)))
+        {
+            chunk.file_name = file_name.clone();
+            chunk.index = index as u64;
+        }
+    }
+    let chunk = Arc::new(chunk);
+    *shared = Arc::clone(&chunk);
+
+    let replace = |chunks: &mut Vec<Arc<ChunkInfo>>| {
+        for existing in chunks
+            .iter_mut()
+            .filter(|existing| existing.chunk_id == chunk_id)
+        {
+            *existing = Arc::clone(&chunk);
+        }
+    };
+    if is_shared {
+        file_chunks.values_mut().for_each(replace);
+    } else if let Some(chunks) = file_chunks.get_mut(&chunk.file_name) {
+        replace(chunks);
+    }
+    let mut servers: Vec<&String> = previous.server_addresses.iter().collect();
+    servers.extend(&chunk.server_addresses);
+    servers.sort();
+    servers.dedup();
+    for server in servers {
+        if let Some(chunks) = chunk_servers.get_mut(server) {
+            replace(chunks);
+        }
+    }
+    Some(chunk)
+}
+
+/// Set the replicas and version of a chunk in all metadata maps.
+///
+/// Servers in `server_addresses` that do not list the chunk in `chunk_servers` yet get it added.
+/// A primary no longer among them is replaced by the first of them, see `elect_primary`.
+fn set_chunk_replicas(
+    file_chunks: &mut HashMap<String, Vec<Arc<ChunkInfo>>>,
+    chunk_servers: &mut HashMap<String, Vec<Arc<ChunkInfo>>>,
+    chunk_map: &mut HashMap<String, Arc<ChunkInfo>>,
+    chunk_id: &str,
+    server_addresses: &[String],
+    version: u64,
+) {
+    let Some(chunk) = replace_chunk(file_chunks, chunk_servers, chunk_map, chunk_id, |chunk| {
+        chunk.server_addresses = server_addresses.to_vec();
+        chunk.version = version;
+        elect_primary(chunk);
+    }) else {
+        return;
+    };
+    for server in server_addresses {
+        if let Some(chunks) = chunk_servers.get_mut(server) {
+            if !chunks.iter().any(|existing| existing.chunk_id == chunk_id) {
+                chunks.push(Arc::clone(&chunk));
+            }
         }
     }
 }
 
-#[derive(Debug, Default)]
+/// Progress of the current or last rebalance
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RebalanceProgress {
+    pub running: bool,
+    pub moves_planned: u64,
+    pub moves_done: u64,
+    pub moves_failed: u64,
+    pub finished_at: u64, // Seconds since UNIX epoch, 0 if no rebalance has finished
+}
+
+/// Progress of the current or last copy of a file to a destination, see `run_copy`
+#[derive(Clone, Debug, Default)]
+pub struct CopyProgress {
+    pub source: String,
+    pub running: bool,
+    pub chunk_ids: Vec<String>, // IDs of the copies of the source's chunks, in order
+    pub chunks_copied: u64,
+    pub error: String, // Why the copy failed, empty if it is running or succeeded
+}
+
+/// A chunk replica to move from one server to another while rebalancing
+#[derive(Clone, Debug)]
+pub struct ChunkMove {
+    pub chunk_id: String,
+    pub from: String,
+    pub to: String,
+}
+
+/// Difference between the highest and the lowest chunk count in `loads`
+pub fn load_spread(loads: &HashMap<String, usize>) -> usize {
+    let max = loads.values().max().copied().unwrap_or_default();
+    let min = loads.values().min().copied().unwrap_or_default();
+    max - min
+}
+
+#[derive(Debug)]
 pub struct MasterService {
-    pub file_chunks: Arc<RwLock<HashMap<String, Vec<ChunkInfo>>>>, // File -> List of ChunkInfo
-    pub chunk_servers: Arc<RwLock<HashMap<String, Vec<ChunkInfo>>>>, // ChunkServer -> List of chunks
+    pub file_chunks: Arc<RwLock<HashMap<String, Vec<Arc<ChunkInfo>>>>>, // File -> List of ChunkInfo
+    pub chunk_servers: Arc<RwLock<HashMap<String, Vec<Arc<ChunkInfo>>>>>, // ChunkServer -> List of chunks
     pub last_heartbeat_time: Arc<RwLock<HashMap<String, u64>>>, // ChunkServer -> Last heartbeat timestamp
-    pub chunk_map: Arc<RwLock<HashMap<String, ChunkInfo>>>,     // chunkID -> ChunkInfo
+    pub report_generations: Arc<RwLock<HashMap<String, u64>>>, // ChunkServer -> Generation of its last acknowledged chunk report
+    pub server_capacity: Arc<RwLock<HashMap<String, ServerCapacity>>>, // ChunkServer -> Reported disk capacity
+    pub scrub_status: Arc<RwLock<HashMap<String, ScrubStatus>>>, // ChunkServer -> Reported scrubbing result
+    pub failure_domains: Arc<RwLock<HashMap<String, String>>>, // ChunkServer -> Failure domain it registered with
+    pub chunk_map: Arc<RwLock<HashMap<String, Arc<ChunkInfo>>>>, // chunkID -> ChunkInfo, shared with the other two maps
+    pub pending_deletions: Arc<RwLock<HashMap<String, HashSet<String>>>>, // ChunkServer -> Chunks of deleted files still on it
+    pub trash: Arc<RwLock<HashMap<String, TrashedFile>>>, // Trashed file name -> Deleted file
+    pub file_owners: Arc<RwLock<HashMap<String, String>>>, // File -> User who created it, only with authentication
     pub config: MasterConfig,
     pub common_config: CommonConfig,
     pub addr: String,
-    pub shadow_masters: Arc<RwLock<HashSet<String>>>, // Set of shadow master node addresses
-    pub current_master: Arc<RwLock<String>>,          // Stores the current master address
-    pub is_leader_flag: Arc<RwLock<bool>>,            // Indicates if this node is the leader
+    pub shadow_masters: Arc<RwLock<HashMap<String, Instant>>>, // Shadow master address -> Time of its last ping
+    pub current_master: Arc<RwLock<String>>,                   // Stores the current master address
+    pub is_leader_flag: Arc<RwLock<bool>>, // Indicates if this node is the leader
+    pub draining: Arc<RwLock<HashMap<String, u64>>>, // ChunkServer being decommissioned -> Chunks it could not move
+    pub under_replicated_chunks: Arc<AtomicUsize>, // Chunks with too few live replicas at the last repair scan
+    pub rebalance: Arc<RwLock<RebalanceProgress>>, // Progress of the current or last rebalance
+    pub copies: Arc<RwLock<HashMap<String, CopyProgress>>>, // Destination -> Progress of the current or last copy to it
+    pub metadata_version: Arc<RwLock<u64>>, // Version of the metadata last sent (leader) or applied (shadow)
+    pub last_leader_contact: Arc<AtomicU64>, // When this shadow last reached the leader (seconds since UNIX epoch)
+    pub replication_queue: mpsc::Sender<u64>, // Metadata versions to send to the shadow masters
+    replication_receiver: Mutex<Option<mpsc::Receiver<u64>>>, // Taken by the replication dispatcher
+    pub replicated_versions: Arc<RwLock<HashMap<String, u64>>>, // Shadow master -> Last metadata version it applied
+    pub epoch: Arc<AtomicU64>, // Leader epoch, increased with every change of leadership, see `begin_epoch`
+    pub chunk_leases: Arc<RwLock<HashMap<String, ChunkLease>>>, // chunkID -> Lease of its primary, see `ensure_lease`
+    pub verifying_replicas: Arc<RwLock<HashSet<(String, String)>>>, // (chunkID, ChunkServer) reported by a client and being checked
+    pub file_locks: FileLocks, // File -> Lock held while changing its metadata, see `FileLocks`
+    pub operations: Mutex<OperationCache>, // Operation ID -> Response to a completed upload or deletion
+    pub leader_since: Arc<AtomicU64>, // When the current epoch began (seconds since UNIX epoch)
+    pub metrics: MasterMetrics,
+    pub settings: ReloadableSettings, // Settings that can change on reload, see `reload_config`
+    pub reloader: Mutex<Option<ConfigReloader>>, // Set by the master binary to allow reloads
+    pub auth_throttle: Mutex<AuthThrottle>, // Failed authentications per username and source address
+    pub audit: AuditLog,                    // Uploads, appends and deletions handled as the leader
+    pub tasks: BackgroundTasks,             // Periodic tasks, stopped on shutdown
 }
 
 // Implement a constructor for MasterService
@@ -88,459 +529,2968 @@ impl MasterService {
         is_leader: bool,
         current_master: &str,
     ) -> Self {
+        let (replication_queue, replication_receiver) = mpsc::channel(REPLICATION_QUEUE_SIZE);
+        let epoch = load_epoch(&epoch_path(addr, &config));
+        let settings = ReloadableSettings::new(&config, &common_config);
+        let auth_throttle = AuthThrottle::new(&config);
+        let metrics = MasterMetrics::new();
+        let operations =
+            OperationCache::new(config.operation_retention, config.max_remembered_operations);
+        let audit = AuditLog::new(
+            &audit_log_path(addr, &config),
+            &config,
+            metrics.audit_entries_dropped.clone(),
+        );
         Self {
             file_chunks: Arc::new(RwLock::new(HashMap::new())),
             chunk_servers: Arc::new(RwLock::new(HashMap::new())),
             last_heartbeat_time: Arc::new(RwLock::new(HashMap::new())),
+            report_generations: Arc::new(RwLock::new(HashMap::new())),
+            server_capacity: Arc::new(RwLock::new(HashMap::new())),
+            scrub_status: Arc::new(RwLock::new(HashMap::new())),
+            failure_domains: Arc::new(RwLock::new(HashMap::new())),
             chunk_map: Arc::new(RwLock::new(HashMap::new())), // Initialize the new map
+            pending_deletions: Arc::new(RwLock::new(HashMap::new())),
+            trash: Arc::new(RwLock::new(HashMap::new())),
+            file_owners: Arc::new(RwLock::new(HashMap::new())),
             addr: addr.to_string(),
             config, // Store the configuration, field init shorthand
             common_config,
-            shadow_masters: Arc::new(RwLock::new(HashSet::new())),
+            shadow_masters: Arc::new(RwLock::new(HashMap::new())),
             current_master: Arc::new(RwLock::new(current_master.to_string())),
             is_leader_flag: Arc::new(RwLock::new(is_leader)),
+            draining: Arc::new(RwLock::new(HashMap::new())),
+            under_replicated_chunks: Arc::new(AtomicUsize::new(0)),
+            rebalance: Arc::new(RwLock::new(RebalanceProgress::default())),
+            copies: Arc::new(RwLock::new(HashMap::new())),
+            metadata_version: Arc::new(RwLock::new(0)),
+            last_leader_contact: Arc::new(AtomicU64::new(0)),
+            replication_queue,
+            replication_receiver: Mutex::new(Some(replication_receiver)),
+            replicated_versions: Arc::new(RwLock::new(HashMap::new())),
+            epoch: Arc::new(AtomicU64::new(epoch)),
+            chunk_leases: Arc::new(RwLock::new(HashMap::new())),
+            verifying_replicas: Arc::new(RwLock::new(HashSet::new())),
+            file_locks: FileLocks::new(metrics.file_locks.clone()),
+            operations: Mutex::new(operations),
+            leader_since: Arc::new(AtomicU64::new(0)),
+            metrics,
+            settings,
+            reloader: Mutex::new(None),
+            auth_throttle: Mutex::new(auth_throttle),
+            audit,
+            tasks: BackgroundTasks::default(),
+        }
+    }
+
+    /// Start a new leader epoch when this master becomes the leader, and persist it.
+    ///
+    /// Chunkservers reject commands and writes carrying an older epoch, which fences off a
+    /// former leader that still believes it leads, and clients holding its chunk assignments.
+    pub fn begin_epoch(&self) -> u64 {
+        let epoch = self.epoch.fetch_add(1, Ordering::SeqCst) + 1;
+        let path = epoch_path(&self.addr, &self.config);
+        let persisted = Path::new(&path)
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(&path, epoch.to_string()));
+        if let Err(e) = persisted {
+            error!("Failed to persist epoch {} to '{}': {}", epoch, path, e);
+        }
+        self.leader_since.store(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            Ordering::SeqCst,
+        );
+        info!("[Leader] Starting epoch {}", epoch);
+        epoch
+    }
+
+    /// Whether chunks that no file refers to are deleted from the chunkservers: only after
+    /// `orphan_grace_period` seconds as leader, as the metadata may not cover all chunks yet
+    /// right after the leader started or took over
+    pub fn checks_orphans(&self, now: u64) -> bool {
+        now.saturating_sub(self.leader_since.load(Ordering::SeqCst))
+            >= self.config.orphan_grace_period.as_secs()
+    }
+
+    /// Whether a chunk that no file refers to can be deleted from the chunkservers, see
+    /// `checks_orphans`. The chunks of a running copy of a file belong to no file until the copy
+    /// finishes, so they are kept as well.
+    pub async fn is_orphan(&self, chunk_id: &str, now: u64) -> bool {
+        self.checks_orphans(now)
+            && !self.chunk_map.read().await.contains_key(chunk_id)
+            && !self
+                .trash
+                .read()
+                .await
+                .values()
+                .any(|entry| entry.chunk_ids.iter().any(|id| id == chunk_id))
+            && !self
+                .copies
+                .read()
+                .await
+                .values()
+                .any(|copy| copy.running && copy.chunk_ids.iter().any(|id| id == chunk_id))
+    }
+
+    /// Look up the chunks a chunkserver reported in chunk_map.
+    ///
+    /// Returns the known chunks, and the orphans among the unknown ones for the chunkserver to
+    /// delete. Chunks in `pending_deletion` are skipped, they are deleted already.
+    pub async fn resolve_reported_chunks(
+        &self,
+        server: &str,
+        chunk_ids: Vec<String>,
+        pending_deletion: &[String],
+        now: u64,
+    ) -> (Vec<Arc<ChunkInfo>>, Vec<String>) {
+        let mut known = Vec::new();
+        let mut unknown = Vec::new();
+        {
+            let chunk_map = self.chunk_map.read().await;
+            for chunk_id in chunk_ids {
+                match chunk_map.get(&chunk_id) {
+                    Some(chunk_info) => known.push(Arc::clone(chunk_info)),
+                    None => unknown.push(chunk_id),
+                }
+            }
+        }
+        // A chunk whose last replica was dropped, e.g. as the server missed its heartbeats, is
+        // readable again from the server reporting it
+        let mut restored = false;
+        if known.iter().any(|chunk| chunk.server_addresses.is_empty()) {
+            let mut file_chunks = self.file_chunks.write().await;
+            let mut chunk_servers = self.chunk_servers.write().await;
+            let mut chunk_map = self.chunk_map.write().await;
+            for chunk in &mut known {
+                let Some(current) = chunk_map.get(&chunk.chunk_id).cloned() else {
+                    continue;
+                };
+                if !current.server_addresses.is_empty() {
+                    continue;
+                }
+                warn!(
+                    "[Heartbeat] Chunk '{}' had no replica left, listing the one on '{}' again",
+                    chunk.chunk_id, server
+                );
+                set_chunk_replicas(
+                    &mut file_chunks,
+                    &mut chunk_servers,
+                    &mut chunk_map,
+                    &chunk.chunk_id,
+                    &[server.to_string()],
+                    current.version + 1,
+                );
+                if let Some(updated) = chunk_map.get(&chunk.chunk_id) {
+                    *chunk = Arc::clone(updated);
+                }
+                restored = true;
+            }
+        }
+        if restored {
+            self.propagate_metadata_updates().await;
+        }
+        let mut orphans = Vec::new();
+        for chunk_id in unknown {
+            if pending_deletion.contains(&chunk_id) {
+                // Chunk of a deleted file, removed once the chunkserver handles the response
+                debug!("[Heartbeat] Chunk '{}' is pending deletion", chunk_id);
+            } else if self.is_orphan(&chunk_id, now).await {
+                // E.g. the file was deleted while the chunkserver was unreachable
+                warn!(
+                    "[Heartbeat] Chunk '{}' on '{}' belongs to no file, deleting it",
+                    chunk_id, server
+                );
+                orphans.push(chunk_id);
+            } else {
+                // If it doesn't exist, print an error and skip the chunk
+                error!(
+                    "[Heartbeat] Error: Chunk ID '{}' not found in chunk_map",
+                    chunk_id
+                );
+            }
+        }
+        (known, orphans)
+    }
+
+    /// Free space a chunkserver needs to be assigned new chunks
+    pub fn min_free_bytes(&self) -> u64 {
+        self.config
+            .min_free_bytes
+            .max(self.common_config.chunk_size)
+    }
+
+    /// Chunk servers that may receive new chunks, for `select_servers`: those below
+    /// `max_allowed_chunks` that are not being decommissioned.
+    ///
+    /// Each lock is only held while its map is copied, so that heartbeats and other uploads
+    /// don't wait while chunks are placed. Servers may fail or fill up in the meantime, which
+    /// the repair scan handles like a server failing right after the placement.
+    pub async fn candidates(&self) -> Candidates {
+        let draining = self.draining.read().await.clone();
+        let servers = self
+            .chunk_servers
+            .read()
+            .await
+            .iter()
+            .filter(|(server, chunks)| {
+                chunks.len() < self.common_config.max_allowed_chunks
+                    && !draining.contains_key(*server)
+            })
+            .map(|(server, chunks)| (server.clone(), chunks.len()))
+            .collect();
+        Candidates {
+            servers,
+            capacities: self.server_capacity.read().await.clone(),
+            failure_domains: self.failure_domains.read().await.clone(),
+            min_free_bytes: self.min_free_bytes(),
         }
     }
 
+    /// How replicas are placed, checked by `Config::validate`
+    pub fn placement_policy(&self) -> PlacementPolicy {
+        self.config
+            .placement_policy
+            .parse()
+            .unwrap_or(PlacementPolicy::FailureDomain)
+    }
+
     pub async fn is_leader(&self) -> bool {
         let is_leader_lock = self.is_leader_flag.read().await;
         *is_leader_lock
     }
 
-    /// Used by shadow masters to ping the master to check its availability
+    /// Start the periodic tasks of this master, those of the leader only if it is the leader
+    pub async fn start_tasks(self: &Arc<Self>) {
+        if self.is_leader().await {
+            self.begin_epoch();
+            Arc::clone(self).start_heartbeat_checker().await;
+            Arc::clone(self).start_trash_purger().await;
+            Arc::clone(self).start_leader_announcer().await;
+        }
+        self.audit.start_writer(&self.tasks).await;
+        // Idles while this node is a shadow, sends metadata updates once it becomes the leader
+        Arc::clone(self).start_metadata_replicator().await;
+        // Idles while this node is the leader, follows the new leader if it steps down
+        Arc::clone(self).start_shadow_master_ping_task().await;
+    }
+
+    /// Used by shadow masters to ping the master to check its availability. Runs on every
+    /// master and idles while it is the leader.
+    ///
+    /// After `heartbeat_failure_threshold` failed pings in a row the shadow runs for leader,
+    /// see `run_election`.
     pub async fn start_shadow_master_ping_task(self: Arc<Self>) {
-        let current_master = Arc::clone(&self.current_master);
-        let is_leader_flag = Arc::clone(&self.is_leader_flag);
-        let addr = self.addr.clone();
-        let common_config = self.common_config.clone();
-
-        tokio::spawn(async move {
-            let mut interval = time::interval(Duration::from_secs(
-                common_config.shadow_master_ping_interval,
-            ));
+        let service = Arc::clone(&self);
+        service.tasks.spawn("shadow master ping task", async move {
+            let mut interval = time::interval(self.common_config.shadow_master_ping_interval);
+            let mut highest_version_seen = 0;
+            let mut failed_pings = 0;
             loop {
                 interval.tick().await;
 
-                // Get the current master address
-                let master_address = {
-                    let master_lock = current_master.read().await;
-                    master_lock.clone()
-                };
-
                 // Skip if this node is already the leader
-                let is_leader = {
-                    let leader_lock = is_leader_flag.read().await;
-                    *leader_lock
-                };
-                if is_leader {
+                if self.is_leader().await {
                     continue;
                 }
 
-                // Attempt to ping the master
-                let master_url = format!("http://{}", master_address);
-                match master::master_client::MasterClient::connect(master_url).await {
-                    Ok(mut client) => {
-                        let request = tonic::Request::new(PingMasterRequest {
-                            sender_address: addr.clone(),
-                        });
+                // Get the current master address
+                let master_address = self.current_master.read().await.clone();
+                if self
+                    .ping_leader(&master_address, &mut highest_version_seen)
+                    .await
+                {
+                    failed_pings = 0;
+                    let now = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap()
+                        .as_secs();
+                    self.last_leader_contact.store(now, Ordering::Relaxed);
+                    continue;
+                }
 
-                        match client.ping_master(request).await {
-                            Ok(_) => {
-                                info!("[Shadow Master] Master is alive at {}", master_address)
-                            }
-                            Err(e) => error!("[Shadow Master] Ping failed: {}", e),
-                        }
-                    }
-                    Err(e) => {
-                        error!("[Shadow Master] Failed to connect to master: {}", e);
+                // Retry before suspecting a failure, a single failed ping may be a network blip
+                failed_pings += 1;
+                if failed_pings < self.settings.heartbeat_failure_threshold()
+                    || !self.run_election(&master_address).await
+                {
+                    continue;
+                }
 
-                        // Assume leadership
-                        {
-                            let mut leader_lock = is_leader_flag.write().await;
-                            *leader_lock = true;
-                        }
-                        info!("[Shadow Master] Taking over as leader");
+                // Assume leadership
+                *self.is_leader_flag.write().await = true;
+                *self.current_master.write().await = self.addr.clone();
+                // Continue above any version the old leader may have sent to other shadows, so
+                // that the old leader steps down if it returns
+                {
+                    let mut version = self.metadata_version.write().await;
+                    *version = (*version).max(highest_version_seen) + 1;
+                }
+                info!("[Shadow Master] Taking over as leader");
+                self.begin_epoch();
 
-                        // Start the heartbeat checker as the new leader
-                        self.start_heartbeat_checker().await;
+                // Start the heartbeat checker, trash purger and leader announcer as the new leader
+                Arc::clone(&self).start_heartbeat_checker().await;
+                Arc::clone(&self).start_trash_purger().await;
+                Arc::clone(&self).start_leader_announcer().await;
+                self.announce_leadership_to_chunkservers().await;
 
-                        break; // Exit the ping loop
-                    }
-                }
+                // Keep the loop running to follow the new leader after stepping down
+                failed_pings = 0;
             }
         });
     }
 
-    /// Propagate metadata updates to shadow masters
-    pub async fn propagate_metadata_updates(&self) {
-        let metadata = self.collect_metadata().await;
-        let shadow_masters = self.shadow_masters.read().await;
-        info!("Sending to shadow masters: {:?}", shadow_masters);
-
-        for shadow_master in shadow_masters.iter() {
-            if let Err(e) = self
-                .send_metadata_to_shadow_master(shadow_master, &metadata)
-                .await
-            {
-                error!(
-                    "Failed to send metadata to shadow master {}: {}",
-                    shadow_master, e
-                );
+    /// Ping the master at `master_address`, fetching the full metadata if this shadow is behind.
+    ///
+    /// Returns true if the master answered and is still the leader.
+    async fn ping_leader(&self, master_address: &str, highest_version_seen: &mut u64) -> bool {
+        let mut client = match connect_channel(master_address, &self.common_config.tls).await {
+            Ok(channel) => master::master_client::MasterClient::new(channel),
+            Err(e) => {
+                error!("[Shadow Master] Failed to connect to master: {}", e);
+                return false;
+            }
+        };
+        let request = tonic::Request::new(PingMasterRequest {
+            sender_address: self.addr.clone(),
+            internal_proof: self.command_proof(&ping_master_fields(&self.addr)),
+        });
+        let response = match client.ping_master(request).await {
+            Ok(response) => response.into_inner(),
+            Err(e) => {
+                error!("[Shadow Master] Ping failed: {}", e);
+                return false;
             }
+        };
+        if !response.is_leader {
+            warn!("[Shadow Master] {} is no longer the leader", master_address);
+            return false;
         }
-    }
+        info!("[Shadow Master] Master is alive at {}", master_address);
 
-    /// Collect metadata of the master node
-    async fn collect_metadata(&self) -> Metadata {
-        Metadata {
-            file_chunks: self.file_chunks.read().await.clone(),
-            chunk_servers: self.chunk_servers.read().await.clone(),
-            chunk_map: self.chunk_map.read().await.clone(),
+        // Updates were missed, e.g. before this shadow registered
+        *highest_version_seen = (*highest_version_seen).max(response.metadata_version);
+        if response.metadata_version > *self.metadata_version.read().await {
+            self.sync_metadata(&mut client).await;
         }
+        true
     }
 
-    /// Send the metatdata to a shadow master
-    async fn send_metadata_to_shadow_master(
-        &self,
-        shadow_master: &str,
-        metadata: &crate::master_service::Metadata, // Use the correct module path
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let mut client =
-            master::master_client::MasterClient::connect(format!("http://{}", shadow_master))
-                .await?;
-        client
-            .update_metadata(tonic::Request::new(metadata.clone().into())) // Use `Into<UpdateMetadataRequest>`
-            .await?;
-        Ok(())
-    }
-
-    /// Starts a periodic task to check for failed chunk servers and reassign their chunks.
-    pub async fn start_heartbeat_checker(&self) {
-        let interval = self.config.cron_interval; // Interval for the periodic task
-        let heartbeat_failure_threshold = self.config.heartbeat_failure_threshold; // Threashold for determining server failure, in number of heartbeat_intervals
-        let heartbeat_interval = self.common_config.heartbeat_interval; // Interval for chunkserver heartbeats
-
-        let file_chunks = Arc::clone(&self.file_chunks); // File -> List of ChunkInfo
-        let chunk_servers = Arc::clone(&self.chunk_servers); // ChunkServer -> List of chunks
-        let last_heartbeat_time = Arc::clone(&self.last_heartbeat_time); // ChunkServer -> Last heartbeat timestamp
-        let chunk_map = Arc::clone(&self.chunk_map); // chunkID -> ChunkInfo
-        let common_config = self.common_config.clone();
-        let max_allowed_chunks = self.common_config.max_allowed_chunks;
-
-        // Spawn an asynchronous task
-        tokio::spawn(async move {
-            let mut ticker = time::interval(Duration::from_secs(interval));
-            loop {
-                ticker.tick().await;
-
-                let now = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs();
-
-                // Check for failed servers
-                let mut failed_servers = Vec::new();
-                {
-                    let last_heartbeat = last_heartbeat_time.read().await;
-                    for (server, &last_time) in last_heartbeat.iter() {
-                        if now - last_time > heartbeat_failure_threshold * heartbeat_interval {
-                            failed_servers.push(server.clone());
-                        }
-                    }
-                }
+    /// Ask all other masters for their vote to replace the unreachable leader `failed_leader`.
+    ///
+    /// Returns true if every reachable master granted its vote and, counting its own, a majority
+    /// of `master_addrs` did, so that a shadow cut off from the other masters never takes over
+    /// while they may have elected another leader. A master denies it if it is the
+    /// leader, still reaches the leader, or is a better candidate itself, that is it has a higher
+    /// metadata version, or the same version and a lower address. If a master names a live
+    /// leader, this shadow follows that leader instead.
+    async fn run_election(&self, failed_leader: &str) -> bool {
+        // Randomized timeout, so shadows that noticed the failure together don't ask at once
+        let interval_ms = self.common_config.shadow_master_ping_interval.as_millis() as u64;
+        let delay_ms = rand::thread_rng().gen_range(0..=interval_ms);
+        time::sleep(Duration::from_millis(delay_ms)).await;
+        if *self.current_master.read().await != failed_leader {
+            return false;
+        }
 
-                if failed_servers.is_empty() {
+        let metadata_version = *self.metadata_version.read().await;
+        info!(
+            "[Election] Leader {} is unreachable, running for leader with metadata version {}",
+            failed_leader, metadata_version
+        );
+        let mut votes = 1; // Its own
+        for addr in &self.common_config.master_addrs {
+            if *addr == self.addr {
+                continue;
+            }
+            let mut client = match connect_channel(addr, &self.common_config.tls).await {
+                Ok(channel) => master::master_client::MasterClient::new(channel),
+                Err(_) => continue, // Unreachable masters can't vote
+            };
+            let request = tonic::Request::new(VoteRequest {
+                candidate_address: self.addr.clone(),
+                metadata_version,
+            });
+            let response = match client.request_vote(request).await {
+                Ok(response) => response.into_inner(),
+                Err(e) => {
+                    warn!("[Election] Failed to ask {} for its vote: {}", addr, e);
                     continue;
                 }
+            };
+            if response.granted {
+                info!("[Election] {} granted its vote", addr);
+                votes += 1;
+                continue;
+            }
 
-                warn!("[Cron Task] Failed servers detected: {:?}", failed_servers);
+            info!("[Election] {} denied its vote", addr);
+            if !response.leader_address.is_empty() && response.leader_address != failed_leader {
+                info!(
+                    "[Election] Following leader {} instead",
+                    response.leader_address
+                );
+                *self.current_master.write().await = response.leader_address;
+            }
+            return false;
+        }
 
-                // Handle reassigning chunks for each failed server
-                for failed_server in failed_servers.clone() {
-                    // Collect chunks from the failed server (chunks_to_reassign is a list of all removed servers)
-                    let chunks_to_reassign = {
-                        let mut chunk_servers_lock = chunk_servers.write().await;
-                        chunk_servers_lock
-                            .remove(&failed_server)
-                            .unwrap_or_default()
-                    };
+        let masters = self.common_config.master_addrs.len();
+        if votes <= masters / 2 {
+            warn!(
+                "[Election] Got {} of {} votes, too few to take over without a majority of the masters",
+                votes, masters
+            );
+            return false;
+        }
+        true
+    }
 
-                    info!(
-                        "[Cron Task] Reassigning chunks from failed server: {:?}",
-                        chunks_to_reassign
-                    );
+    /// Decide on the vote requested by a candidate for leader, see `run_election`.
+    ///
+    /// Returns whether the vote is granted, and the leader this master knows to be alive, if any.
+    pub async fn vote(&self, candidate: &str, candidate_version: u64) -> (bool, Option<String>) {
+        if self.is_leader().await {
+            return (false, Some(self.addr.clone()));
+        }
 
-                    for chunk_info in chunks_to_reassign {
-                        let file_name = chunk_info
-                            .chunk_id
-                            .split("_chunk_")
-                            .next()
-                            .unwrap_or_default();
-
-                        // Get a list of chunkservers the current chunk is running on
-                        let source_servers: Vec<String> = {
-                            let file_chunks_lock = file_chunks.read().await;
-                            file_chunks_lock
-                                .get(file_name)
-                                .map(|chunks| {
-                                    chunks
-                                        .iter()
-                                        .filter_map(|chunk| {
-                                            if chunk.chunk_id == chunk_info.chunk_id {
-                                                Some(
-                                                    chunk
-                                                        .server_addresses
-                                                        .iter()
-                                                        .filter(|addr| addr != &&failed_server)
-                                                        .cloned()
-                                                        .collect::<Vec<_>>(),
-                                                )
-                                            } else {
-                                                None
-                                            }
-                                        })
-                                        .flatten()
-                                        .collect()
-                                })
-                                .unwrap_or_default()
-                        };
-
-                        if source_servers.is_empty() {
-                            error!(
-                                "[Cron Task] No healthy source servers found for chunk '{}'. Skipping reassignment.",
-                                chunk_info.chunk_id
-                            );
-                            continue;
-                        }
-
-                        info!(
-                            "[Cron Task] Chunk {:?} source servers: {:?}",
-                            chunk_info.chunk_id, source_servers
-                        );
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let last_contact = self.last_leader_contact.load(Ordering::Relaxed);
+        let failure_timeout = self.settings.heartbeat_failure_threshold()
+            * self.common_config.shadow_master_ping_interval.as_secs();
+        if now.saturating_sub(last_contact) < failure_timeout {
+            return (false, Some(self.current_master.read().await.clone()));
+        }
 
-                        let existing_replicas = {
-                            let file_chunks_lock = file_chunks.read().await;
-                            file_chunks_lock
-                                .get(file_name)
-                                .map(|chunks| {
-                                    chunks
-                                        .iter()
-                                        .filter(|chunk| {
-                                            chunk
-                                                .server_addresses
-                                                .iter()
-                                                .any(|addr| addr != &failed_server)
-                                        })
-                                        .count()
-                                })
-                                .unwrap_or(0)
-                        };
-                        let needed_replicas = common_config.replication_factor - existing_replicas;
-                        if needed_replicas == 0 {
-                            info!(
-                                "[Cron Task] Chunk '{}' already has enough replicas on healthy servers.",
-                                chunk_info.chunk_id
-                            );
-                            continue;
-                        }
-
-                        // Collect available chunk servers for reassignment
-                        // (available means load is less than max_allowed_chunks and does not store the same chunk)
-                        let available_servers: HashMap<String, Vec<ChunkInfo>> = {
-                            let chunk_servers_lock = chunk_servers.read().await;
-                            chunk_servers_lock
-                                .iter()
-                                .filter(|(_addr, chunks)| {
-                                    chunks.len() < max_allowed_chunks
-                                        && !chunks.iter().any(|c| c.chunk_id == chunk_info.chunk_id)
-                                })
-                                .map(|(addr, chunks)| (addr.clone(), chunks.clone()))
-                                .collect()
-                        };
-                        debug!(
-                            "[Cron Task] Chunk {:?} has available servers: {:?}",
-                            chunk_info.chunk_id, available_servers
-                        );
+        // Grant the vote only to a candidate at least as good as this master
+        let own_version = *self.metadata_version.read().await;
+        let granted = candidate_version > own_version
+            || (candidate_version == own_version && candidate <= self.addr.as_str());
+        (granted, None)
+    }
 
-                        let mut server_queue: BinaryHeap<Reverse<(usize, String)>> =
-                            available_servers
-                                .iter()
-                                .map(|(addr, chunks)| Reverse((chunks.len(), addr.clone())))
-                                .collect();
-
-                        let mut selected_servers = Vec::new();
-                        while selected_servers.len() < needed_replicas {
-                            if let Some(Reverse((load, addr))) = server_queue.pop() {
-                                if !selected_servers.contains(&addr) {
-                                    selected_servers.push(addr.clone());
-                                    // Add load to the selected server
-                                    server_queue.push(Reverse((load + 1, addr)));
-                                }
-                            } else {
-                                break; // Not enough available servers
-                            }
-                        }
-
-                        if selected_servers.is_empty() {
-                            error!(
-                                "[Cron Task] No selected servers to reassign chunk '{}'",
-                                chunk_info.chunk_id
-                            );
-                            continue;
-                        }
-
-                        info!(
-                            "[Cron Task] Reassigning chunk '{}' to servers: {:?}",
-                            chunk_info.chunk_id, selected_servers
-                        );
+    /// Tell the masters that did not register as shadows that this master is the leader, every
+    /// ping interval while it is.
+    ///
+    /// A master that still believes it is the leader, e.g. after recovering from a network
+    /// partition, steps down unless its metadata is newer, in which case this master does.
+    pub async fn start_leader_announcer(self: Arc<Self>) {
+        let service = Arc::clone(&self);
+        service.tasks.spawn("leader announcer", async move {
+            let mut interval = time::interval(self.common_config.shadow_master_ping_interval);
+            loop {
+                interval.tick().await;
+                if !self.is_leader().await {
+                    break;
+                }
 
-                        // Transfer chunk data
-                        for target_server in &selected_servers {
-                            // Get the first source server
-                            let source_server = match source_servers.get(0) {
-                                Some(server) => server.clone(),
-                                None => {
-                                    error!("[Cron Task] No source servers available. Skipping target '{}'", target_server);
-                                    continue;
-                                }
-                            };
-
-                            let chunk_id = chunk_info.chunk_id.clone();
-
-                            // Attempt to connect to the source server
-                            let mut source_client =
-                                match ChunkClient::connect(format!("http://{}", source_server))
-                                    .await
-                                {
-                                    Ok(client) => client,
-                                    Err(e) => {
-                                        error!(
-                                        "[Cron Task] Failed to connect to source server '{}': {}",
-                                        source_server, e
-                                    );
-                                        continue;
-                                    }
-                                };
-
-                            // Prepare the request to send the chunk
-                            let send_request = SendChunkRequest {
-                                chunk_name: chunk_id.clone(),
-                                target_address: target_server.clone(),
-                            };
-
-                            // Attempt to transfer the chunk
-                            match source_client
-                                .transfer_chunk(tonic::Request::new(send_request))
-                                .await
-                            {
-                                Ok(_) => {
-                                    info!(
-                                        "[Cron Task] Successfully transferred chunk '{}' from source server '{}' to target server '{}'.",
-                                        chunk_id, source_server, target_server
-                                    );
-                                }
-                                Err(e) => {
-                                    error!(
-                                        "[Cron Task] Failed to transfer chunk '{}' from '{}' to '{}': {}",
-                                        chunk_id, source_server, target_server, e
-                                    );
-                                }
-                            }
-                        }
-
-                        // Update chunk server metadata and chunk map
-                        let new_chunk_info = ChunkInfo {
-                            chunk_id: chunk_info.chunk_id.clone(),
-                            server_addresses: selected_servers.clone(),
-                            version: chunk_info.version + 1,
-                        };
-
-                        for server in &selected_servers {
-                            let mut chunk_servers_lock = chunk_servers.write().await;
-                            if let Some(chunks) = chunk_servers_lock.get_mut(server) {
-                                // update chunkserver => list of chunks mapping
-                                chunks.push(new_chunk_info.clone());
-                            }
-                        }
-
-                        {
-                            // overwrite the chunk info
-                            let mut chunk_map_lock = chunk_map.write().await;
-                            chunk_map_lock.insert(new_chunk_info.chunk_id.clone(), new_chunk_info);
-                        }
-
-                        // Update file chunk selected servers in file_chunks mapping
-                        {
-                            let mut file_chunks_lock = file_chunks.write().await;
-                            for (_file_name, chunk_list) in file_chunks_lock.iter_mut() {
-                                if let Some(chunk) = chunk_list
-                                    .iter_mut()
-                                    .find(|c| c.chunk_id == chunk_info.chunk_id)
-                                {
-                                    chunk.server_addresses = selected_servers.clone();
-                                    chunk.version += 1;
-                                }
-                            }
-                        }
+                let shadow_masters = self.shadow_masters.read().await.clone();
+                for addr in &self.common_config.master_addrs {
+                    if *addr == self.addr || shadow_masters.contains_key(addr) {
+                        continue;
                     }
-                }
-                // Remove all failed servers from the last_heartbeat_time hashmap
-                {
-                    let mut last_heartbeat_lock = last_heartbeat_time.write().await;
-                    for failed_server in &failed_servers {
-                        last_heartbeat_lock.remove(failed_server);
+                    if let Some(leader) = self.announce_leadership_to(addr).await {
+                        self.demote(&leader).await;
+                        break;
                     }
                 }
             }
         });
     }
 
-    pub async fn authenticate_user(
-        &self,
-        username: &str,
-        password: &str,
-    ) -> Result<(String, u64), Box<dyn std::error::Error>> {
-        // Load and parse the JSON authentication file
-        let file_content = fs::read_to_string(&self.config.authentication_file_path)?;
-        let auth_data: Value = serde_json::from_str(&file_content)?;
+    /// Announce to the master at `addr` that this master is the leader.
+    ///
+    /// Returns the address of the receiver if it refused because it is a leader with newer metadata.
+    async fn announce_leadership_to(&self, addr: &str) -> Option<String> {
+        // Unreachable masters are told once they return
+        let channel = connect_channel(addr, &self.common_config.tls).await.ok()?;
+        let metadata_version = *self.metadata_version.read().await;
+        let internal_proof = sign_request(
+            &self.common_config.cluster_secret,
+            &leader_changed_fields(&self.addr, &metadata_version.to_be_bytes()),
+        );
+        let request = tonic::Request::new(LeaderChangedRequest {
+            leader_address: self.addr.clone(),
+            metadata_version,
+            internal_proof,
+        });
+        match master::master_client::MasterClient::new(channel)
+            .leader_changed(request)
+            .await
+        {
+            Ok(response) => {
+                let response = response.into_inner();
+                (!response.accepted && !response.leader_address.is_empty())
+                    .then_some(response.leader_address)
+            }
+            Err(e) => {
+                warn!("[Leader] Failed to announce leadership to {}: {}", addr, e);
+                None
+            }
+        }
+    }
 
-        // Validate the username and password
-        if let Some(user_entry) = auth_data.get(username) {
-            if user_entry["password"] != password {
-                return Err("Invalid username or password".into());
+    /// Tell all registered chunk servers to send their heartbeats to this master
+    async fn announce_leadership_to_chunkservers(&self) {
+        let chunk_servers: Vec<String> = self.chunk_servers.read().await.keys().cloned().collect();
+        for chunk_server in chunk_servers {
+            if let Err(e) = self.send_leader_to_chunkserver(&chunk_server).await {
+                warn!(
+                    "[Leader] Failed to announce leadership to chunk server {}: {}",
+                    chunk_server, e
+                );
             }
-        } else {
-            return Err("Invalid username or password".into());
         }
+    }
 
-        // Generate OTP (hash of username and current time)
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        let otp = format!("{:x}", md5::compute(format!("{}_{}", username, now)));
+    /// Handle a leader announcement from `leader`, see `start_leader_announcer`.
+    ///
+    /// Returns whether this master follows the announced leader. A leader refuses if it has a
+    /// higher metadata version, or the same version and a lower address, like in `vote`.
+    pub async fn follow_announced_leader(&self, leader: &str, leader_version: u64) -> bool {
+        if self.is_leader().await {
+            let own_version = *self.metadata_version.read().await;
+            if own_version > leader_version
+                || (own_version == leader_version && self.addr.as_str() < leader)
+            {
+                return false;
+            }
+            self.demote(leader).await;
+            return true;
+        }
 
-        // Calculate expiration time (e.g., valid for 5 minutes)
-        let expiration_time = now + self.common_config.otp_valid_duration;
+        *self.current_master.write().await = leader.to_string();
+        true
+    }
 
-        // Propagate OTP to all chunk servers
-        let chunk_servers = self.chunk_servers.read().await;
-        for chunk_server in chunk_servers.keys() {
-            let mut client = ChunkClient::connect(format!("http://{}", chunk_server)).await?;
-            client
-                .send_otp(tonic::Request::new(crate::proto::chunk::OtpRequest {
-                    username: username.to_string(),
-                    otp: otp.clone(),
-                    expiration_time,
-                }))
-                .await?;
+    /// Step down as leader in favor of `leader` and follow it as a shadow.
+    ///
+    /// The periodic tasks of the leader stop on their next run and the shadow ping task resumes.
+    async fn demote(&self, leader: &str) {
+        {
+            let mut is_leader = self.is_leader_flag.write().await;
+            if !*is_leader {
+                return;
+            }
+            *is_leader = false;
         }
-
-        // Store OTP locally (optional, for reference or logging)
-        debug!(
-            "[authenticate_user] OTP generated for user {} is {}, with expiration time {}",
-            username, otp, expiration_time
+        warn!(
+            "[Leader] Master {} took over as leader, stepping down",
+            leader
         );
-        Ok((otp, expiration_time))
+        *self.current_master.write().await = leader.to_string();
+        // Fetch the full metadata of the new leader with the first ping
+        *self.metadata_version.write().await = 0;
+        self.shadow_masters.write().await.clear();
+        // The chunkservers drop leases of older epochs once they hear from the new leader
+        self.chunk_leases.write().await.clear();
+    }
+
+    /// Propagate metadata updates to shadow masters.
+    ///
+    /// Only bumps the metadata version and queues it, the replication workers send the metadata
+    /// in the background, see `start_metadata_replicator`. Callers must release their metadata
+    /// locks before, as the workers snapshot the metadata.
+    pub async fn propagate_metadata_updates(&self) {
+        let version = {
+            let mut version = self.metadata_version.write().await;
+            *version += 1;
+            *version
+        };
+        // A full queue still holds versions that are not sent yet, and the workers always send
+        // the latest metadata, so this version is covered by them
+        if let Err(TrySendError::Closed(_)) = self.replication_queue.try_send(version) {
+            error!("Metadata replication stopped, version {} not sent", version);
+        }
+    }
+
+    /// Start sending the queued metadata versions to the shadow masters.
+    ///
+    /// Each shadow master gets its own worker, so a slow shadow master does not delay the others
+    /// and receives the versions in order. Idles while this master is a shadow.
+    pub async fn start_metadata_replicator(self: Arc<Self>) {
+        let Some(mut receiver) = self.replication_receiver.lock().await.take() else {
+            return; // Already started
+        };
+        let service = Arc::clone(&self);
+        service.tasks.spawn("metadata replicator", async move {
+            let mut workers: HashMap<String, mpsc::Sender<u64>> = HashMap::new();
+            while let Some(version) = receiver.recv().await {
+                self.prune_shadow_masters().await;
+                let shadow_masters = self.shadow_masters.read().await.clone();
+                // Dropping the sender stops the worker of a shadow master that is gone
+                workers.retain(|shadow_master, _| shadow_masters.contains_key(shadow_master));
+                self.replicated_versions
+                    .write()
+                    .await
+                    .retain(|shadow_master, _| shadow_masters.contains_key(shadow_master));
+
+                for shadow_master in shadow_masters.into_keys() {
+                    let worker = workers.entry(shadow_master.clone()).or_insert_with(|| {
+                        let (sender, receiver) = mpsc::channel(REPLICATION_QUEUE_SIZE);
+                        tokio::spawn(
+                            Arc::clone(&self).replicate_to_shadow(shadow_master, receiver),
+                        );
+                        sender
+                    });
+                    // Like in `propagate_metadata_updates`, a full queue already covers this version
+                    let _ = worker.try_send(version);
+                }
+            }
+        });
+    }
+
+    /// Send the latest metadata to `shadow_master` whenever a new version is queued, retrying
+    /// with a growing delay until it is applied or the shadow master is unregistered.
+    async fn replicate_to_shadow(
+        self: Arc<Self>,
+        shadow_master: String,
+        mut versions: mpsc::Receiver<u64>,
+    ) {
+        let max_delay = self
+            .common_config
+            .shadow_master_ping_interval
+            .max(Duration::from_secs(1));
+        while let Some(mut version) = versions.recv().await {
+            // Skip to the latest queued version, its metadata contains all earlier updates
+            while let Ok(next) = versions.try_recv() {
+                version = next;
+            }
+            let replicated = self
+                .replicated_versions
+                .read()
+                .await
+                .get(&shadow_master)
+                .copied()
+                .unwrap_or(0);
+            if version <= replicated {
+                continue;
+            }
+
+            let mut delay = Duration::from_millis(100);
+            loop {
+                if !self.is_leader().await
+                    || !self
+                        .shadow_masters
+                        .read()
+                        .await
+                        .contains_key(&shadow_master)
+                {
+                    return;
+                }
+                let metadata = self.current_metadata().await;
+                let sent_version = metadata.metadata_version;
+                match self
+                    .send_metadata_to_shadow_master(&shadow_master, &metadata)
+                    .await
+                {
+                    Ok(()) => {
+                        self.replicated_versions
+                            .write()
+                            .await
+                            .insert(shadow_master.clone(), sent_version);
+                        break;
+                    }
+                    // The shadow master already synced this version itself
+                    Err(Error::Rpc(status)) if status.code() == tonic::Code::Aborted => {
+                        self.replicated_versions
+                            .write()
+                            .await
+                            .insert(shadow_master.clone(), sent_version);
+                        break;
+                    }
+                    Err(e) => {
+                        self.metrics.metadata_replication_failures.inc();
+                        let lag = *self.metadata_version.read().await - replicated;
+                        error!(
+                            "Failed to send metadata to shadow master {} ({} version(s) behind), retrying in {:?}: {}",
+                            shadow_master, lag, delay, e
+                        );
+                        time::sleep(delay).await;
+                        delay = (delay * 2).min(max_delay);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Send the latest metadata to the shadow masters that have not applied it yet, once and
+    /// without waiting for the replication workers, e.g. before this leader shuts down. The
+    /// shadow masters are sent the metadata at once, so one that is down does not delay the
+    /// others.
+    pub async fn flush_metadata(&self) {
+        let metadata = self.current_metadata().await;
+        let pending: Vec<String> = {
+            let shadow_masters = self.shadow_masters.read().await;
+            let replicated_versions = self.replicated_versions.read().await;
+            shadow_masters
+                .keys()
+                .filter(|shadow_master| {
+                    replicated_versions
+                        .get(*shadow_master)
+                        .is_none_or(|&replicated| replicated < metadata.metadata_version)
+                })
+                .cloned()
+                .collect()
+        };
+        let metadata = &metadata;
+        futures::future::join_all(pending.iter().map(|shadow_master| async move {
+            match self
+                .send_metadata_to_shadow_master(shadow_master, metadata)
+                .await
+            {
+                Ok(()) => info!(
+                    "[Shutdown] Sent metadata version {} to shadow master {}",
+                    metadata.metadata_version, shadow_master
+                ),
+                // The shadow master already synced this version itself
+                Err(Error::Rpc(status)) if status.code() == tonic::Code::Aborted => {}
+                Err(e) => error!(
+                    "[Shutdown] Failed to send metadata to shadow master {}: {}",
+                    shadow_master, e
+                ),
+            }
+        }))
+        .await;
+    }
+
+    /// Stop sending metadata to the shadow masters that missed `shadow_master_failure_threshold`
+    /// pings, e.g. as they were shut down for good. A shadow master that pings again is
+    /// registered again, see `ping_master`.
+    pub async fn prune_shadow_masters(&self) {
+        let timeout = self.common_config.shadow_master_ping_interval
+            * self.config.shadow_master_failure_threshold as u32;
+        self.shadow_masters
+            .write()
+            .await
+            .retain(|shadow_master, last_ping| {
+                let silent_for = last_ping.elapsed();
+                if silent_for <= timeout {
+                    return true;
+                }
+                warn!(
+                    "[Shadow] Shadow master {} has not pinged for {:?}, no longer sending it metadata",
+                    shadow_master, silent_for
+                );
+                false
+            });
+    }
+
+    /// Stop the background tasks, writing out the audit log, and hand the latest metadata to
+    /// the shadow masters if this master is the leader, waiting up to `shutdown_timeout`
+    pub async fn shutdown(&self) {
+        let timeout = self.common_config.shutdown_timeout;
+        self.tasks.shutdown(timeout).await;
+        if self.is_leader().await && time::timeout(timeout, self.flush_metadata()).await.is_err() {
+            warn!(
+                "[Shutdown] Shadow masters did not take the metadata within {:?}",
+                timeout
+            );
+        }
+    }
+
+    /// Number of metadata versions each shadow master is behind this leader
+    pub async fn replication_lag(&self) -> HashMap<String, u64> {
+        let version = *self.metadata_version.read().await;
+        let replicated_versions = self.replicated_versions.read().await;
+        self.shadow_masters
+            .read()
+            .await
+            .keys()
+            .map(|shadow_master| {
+                let replicated = replicated_versions.get(shadow_master).copied().unwrap_or(0);
+                (shadow_master.clone(), version.saturating_sub(replicated))
+            })
+            .collect()
+    }
+
+    /// Collect metadata of the master node under the version last sent
+    pub async fn current_metadata(&self) -> Metadata {
+        let version = self.metadata_version.read().await;
+        self.snapshot_metadata(*version).await
+    }
+
+    /// Copy all metadata maps at once.
+    ///
+    /// Callers hold the `metadata_version` lock, so a snapshot with a higher version is never
+    /// older than one with a lower version.
+    async fn snapshot_metadata(&self, metadata_version: u64) -> Metadata {
+        let file_chunks = self.file_chunks.read().await;
+        let chunk_servers = self.chunk_servers.read().await;
+        let chunk_map = self.chunk_map.read().await;
+        let pending_deletions = self.pending_deletions.read().await;
+        let trash = self.trash.read().await;
+        let file_owners = self.file_owners.read().await;
+        Metadata {
+            file_chunks: file_chunks.clone(),
+            chunk_servers: chunk_servers.clone(),
+            chunk_map: chunk_map.clone(),
+            pending_deletions: pending_deletions.clone(),
+            trash: trash.clone(),
+            metadata_version,
+            epoch: self.epoch.load(Ordering::SeqCst),
+            file_owners: file_owners.clone(),
+        }
+    }
+
+    /// Replace all metadata maps with metadata received from the leader.
+    ///
+    /// Returns false without applying anything if its version is not newer than the applied one,
+    /// as delayed updates can arrive out of order.
+    pub async fn apply_metadata(&self, metadata: master::Metadata) -> bool {
+        let mut version = self.metadata_version.write().await;
+        if metadata.metadata_version <= *version {
+            warn!(
+                "Ignoring metadata version {}, version {} is already applied",
+                metadata.metadata_version, *version
+            );
+            return false;
+        }
+
+        let mut file_chunks = self.file_chunks.write().await;
+        let mut chunk_servers = self.chunk_servers.write().await;
+        let mut chunk_map = self.chunk_map.write().await;
+        let mut pending_deletions = self.pending_deletions.write().await;
+        let mut trash = self.trash.write().await;
+        let mut file_owners = self.file_owners.write().await;
+        // The chunks listed by file and by server share the ChunkInfo in chunk_map
+        *chunk_map = metadata
+            .chunk_map
+            .into_iter()
+            .map(|(chunk_id, chunk)| (chunk_id, Arc::new(chunk)))
+            .collect();
+        let share = |chunk_list: master::ChunkList| -> Vec<Arc<ChunkInfo>> {
+            chunk_list
+                .chunks
+                .into_iter()
+                .map(|chunk| match chunk_map.get(&chunk.chunk_id) {
+                    Some(shared) if **shared == chunk => Arc::clone(shared),
+                    _ => Arc::new(chunk),
+                })
+                .collect()
+        };
+        *file_chunks = metadata
+            .file_chunks
+            .into_iter()
+            .map(|(key, chunk_list)| (key, share(chunk_list)))
+            .collect();
+        *chunk_servers = metadata
+            .chunk_servers
+            .into_iter()
+            .map(|(key, chunk_list)| (key, share(chunk_list)))
+            .collect();
+        *pending_deletions = metadata
+            .pending_deletions
+            .into_iter()
+            .map(|(key, chunk_id_list)| (key, chunk_id_list.chunk_ids.into_iter().collect()))
+            .collect();
+        *trash = metadata.trash.into_iter().collect();
+        *file_owners = metadata.file_owners.into_iter().collect();
+        *version = metadata.metadata_version;
+        self.epoch.fetch_max(metadata.epoch, Ordering::SeqCst);
+
+        debug!("[apply_metadata] Updated Metadata");
+        log_map("[apply_metadata] file_chunks", &file_chunks);
+        log_map("[apply_metadata] chunk_servers", &chunk_servers);
+        log_map("[apply_metadata] chunk_map", &chunk_map);
+        true
+    }
+
+    /// Fetch the full metadata from the leader and apply it
+    async fn sync_metadata(&self, client: &mut master::master_client::MasterClient<Channel>) {
+        match client
+            .get_metadata(tonic::Request::new(GetMetadataRequest {
+                sender_address: self.addr.clone(),
+                internal_proof: self.command_proof(&get_metadata_fields(&self.addr)),
+            }))
+            .await
+        {
+            Ok(response) => {
+                if let Some(metadata) = response.into_inner().metadata {
+                    let version = metadata.metadata_version;
+                    if self.apply_metadata(metadata).await {
+                        info!("[Shadow Master] Synced metadata version {}", version);
+                    }
+                }
+            }
+            Err(e) => error!("[Shadow Master] Failed to fetch metadata: {}", e),
+        }
+    }
+
+    /// Send the metatdata to a shadow master
+    async fn send_metadata_to_shadow_master(
+        &self,
+        shadow_master: &str,
+        metadata: &crate::master_service::Metadata, // Use the correct module path
+    ) -> Result<()> {
+        let timeout = self.config.metadata_send_timeout;
+        let send = async {
+            let mut client = master::master_client::MasterClient::new(
+                connect_channel(shadow_master, &self.common_config.tls).await?,
+            );
+            let metadata: master::Metadata = metadata.clone().into();
+            let internal_proof = self.command_proof(&update_metadata_fields(
+                &self.addr,
+                &metadata.metadata_version.to_be_bytes(),
+                &metadata_digest(&metadata),
+            ));
+            client
+                .update_metadata(tonic::Request::new(UpdateMetadataRequest {
+                    metadata: Some(metadata),
+                    leader_address: self.addr.clone(),
+                    internal_proof,
+                }))
+                .await?;
+            Ok(())
+        };
+        time::timeout(timeout, send).await.map_err(|_| {
+            Error::Timeout(format!(
+                "shadow master {} did not apply the metadata within {:?}",
+                shadow_master, timeout
+            ))
+        })?
+    }
+
+    /// Starts a periodic task to check for failed chunk servers and reassign their chunks.
+    ///
+    /// Every run also repairs chunks that are under-replicated for any other reason.
+    pub async fn start_heartbeat_checker(self: Arc<Self>) {
+        // Spawn an asynchronous task
+        let service = Arc::clone(&self);
+        service.tasks.spawn("heartbeat checker", async move {
+            let mut ticker = time::interval(self.settings.cron_interval());
+            loop {
+                self.next_cron_tick(&mut ticker).await;
+                // Stop once this master stepped down as leader
+                if !self.is_leader().await {
+                    break;
+                }
+
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs();
+
+                // Check for failed servers. Servers known from the metadata but not heard from,
+                // e.g. after taking over as leader, get the grace period of a new registration.
+                let known_servers: Vec<String> =
+                    self.chunk_servers.read().await.keys().cloned().collect();
+                let failed_servers: Vec<String> = {
+                    let mut last_heartbeat = self.last_heartbeat_time.write().await;
+                    for server in known_servers {
+                        last_heartbeat.entry(server).or_insert_with(|| {
+                            now + self.config.registration_grace_period.as_secs()
+                        });
+                    }
+                    last_heartbeat
+                        .iter()
+                        .filter(|(_, &last_time)| !self.is_heartbeat_recent(last_time, now))
+                        .map(|(server, _)| server.clone())
+                        .collect()
+                };
+
+                if !failed_servers.is_empty() {
+                    warn!("[Cron Task] Failed servers detected: {:?}", failed_servers);
+                    self.remove_chunk_servers(&failed_servers).await;
+                }
+                self.prune_shadow_masters().await;
+
+                self.repair_under_replicated_chunks().await;
+                self.drain_servers().await;
+
+                let threshold = self.config.rebalance_threshold;
+                if threshold > 0 && load_spread(&self.server_loads().await) > threshold {
+                    if let Some(moves) = self.plan_rebalance().await {
+                        self.run_rebalance(moves).await;
+                    }
+                }
+            }
+        });
+    }
+
+    /// Wait for the next run of a periodic task, following changes of `cron_interval` on reload
+    async fn next_cron_tick(&self, ticker: &mut time::Interval) {
+        ticker.tick().await;
+        let interval = self.settings.cron_interval();
+        if ticker.period() != interval {
+            *ticker = time::interval_at(time::Instant::now() + interval, interval);
+        }
+    }
+
+    /// Re-read the config file and apply the settings that can change without a restart, see
+    /// `ConfigReloader::reload`
+    pub async fn reload_config(&self) -> Result<master::ReloadConfigResponse> {
+        match self.reloader.lock().await.as_mut() {
+            Some(reloader) => reloader.reload(&self.settings),
+            None => Err(Error::Config(
+                "This master was not started with a config file to reload".to_string(),
+            )),
+        }
+    }
+
+    /// Whether a heartbeat received at `last_time` is recent enough to consider the server alive
+    fn is_heartbeat_recent(&self, last_time: u64, now: u64) -> bool {
+        // Threshold for determining server failure, in number of heartbeat_intervals
+        now.saturating_sub(last_time)
+            <= self.settings.heartbeat_failure_threshold()
+                * self.common_config.heartbeat_interval.as_secs()
+    }
+
+    /// Chunk servers whose last heartbeat is within the failure threshold
+    pub async fn healthy_servers(&self) -> HashSet<String> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        self.last_heartbeat_time
+            .read()
+            .await
+            .iter()
+            .filter(|(_, &last_time)| self.is_heartbeat_recent(last_time, now))
+            .map(|(server, _)| server.clone())
+            .collect()
+    }
+
+    /// Order the replicas of each chunk by the load of their servers, least loaded first, for
+    /// clients to read from.
+    ///
+    /// The load is the number of chunks a server holds. Servers with the same load are ordered
+    /// by a hash of `seed` and their address, so that clients passing different seeds spread
+    /// over them while the order for one seed stays the same.
+    pub async fn order_by_load(&self, chunks: &mut [ChunkInfo], seed: &str) {
+        let chunk_servers = self.chunk_servers.read().await;
+        let load = |addr: &String| chunk_servers.get(addr).map_or(0, |chunks| chunks.len());
+        for chunk in chunks {
+            chunk.server_addresses.sort_by_cached_key(|addr| {
+                (
+                    load(addr),
+                    crc32fast::hash(format!("{}/{}", seed, addr).as_bytes()),
+                )
+            });
+        }
+    }
+
+    /// Forget chunk servers that failed or shut down, copy their chunks to other servers and
+    /// move their leases to other replicas
+    pub async fn remove_chunk_servers(&self, servers: &[String]) {
+        // Remove the servers from the last_heartbeat_time and server_capacity hashmaps first, so
+        // that no new chunks or leases are placed on them while their chunks are reassigned
+        {
+            let mut last_heartbeat_lock = self.last_heartbeat_time.write().await;
+            let mut server_capacity_lock = self.server_capacity.write().await;
+            let mut scrub_status_lock = self.scrub_status.write().await;
+            let mut failure_domains_lock = self.failure_domains.write().await;
+            let mut report_generations_lock = self.report_generations.write().await;
+            for server in servers {
+                last_heartbeat_lock.remove(server);
+                // A server that comes back lists all of its chunks again
+                report_generations_lock.remove(server);
+                server_capacity_lock.remove(server);
+                scrub_status_lock.remove(server);
+                failure_domains_lock.remove(server);
+                let _ = self
+                    .metrics
+                    .chunkserver_chunks
+                    .remove_label_values(&[server]);
+            }
+            self.metrics
+                .chunkservers
+                .set(last_heartbeat_lock.len() as i64);
+        }
+
+        // Handle reassigning chunks for each removed server
+        for server in servers {
+            self.reassign_chunks_of_failed_server(server).await;
+            self.regrant_leases_of_failed_server(server).await;
+        }
+
+        // Send updated metadata to registered shadow masters
+        self.propagate_metadata_updates().await;
+    }
+
+    /// Drop a failed server from the replicas of its chunks and copy them to other servers.
+    ///
+    /// The server is dropped from every chunk first, so that clients are no longer sent to it
+    /// even if no copy can be made. Chunks left with too few replicas are copied by the next
+    /// repair scan, see `repair_under_replicated_chunks`.
+    async fn reassign_chunks_of_failed_server(&self, failed_server: &str) {
+        // Collect chunks from the failed server
+        let chunks_to_reassign = self
+            .chunk_servers
+            .write()
+            .await
+            .remove(failed_server)
+            .unwrap_or_default();
+
+        info!(
+            "[Cron Task] Reassigning chunks from failed server: {:?}",
+            chunks_to_reassign
+        );
+
+        for chunk_info in chunks_to_reassign {
+            let chunk_id = chunk_info.chunk_id.clone();
+
+            // Drop the failed server from the chunk's replicas before anything else. The copy
+            // removed from chunk_servers may be outdated, chunk_map holds the current replicas.
+            let (source_servers, needed_replicas, version) = {
+                let mut file_chunks = self.file_chunks.write().await;
+                let mut chunk_servers = self.chunk_servers.write().await;
+                let mut chunk_map = self.chunk_map.write().await;
+                let Some(current_chunk) = chunk_map.get(&chunk_id).cloned() else {
+                    warn!(
+                        "[Cron Task] Chunk '{}' is no longer in chunk_map. Skipping reassignment.",
+                        chunk_id
+                    );
+                    continue;
+                };
+                let (source_servers, needed_replicas) = healthy_replicas(
+                    &current_chunk,
+                    failed_server,
+                    self.common_config.replication_factor,
+                );
+                let version = current_chunk.version + 1;
+                set_chunk_replicas(
+                    &mut file_chunks,
+                    &mut chunk_servers,
+                    &mut chunk_map,
+                    &chunk_id,
+                    &source_servers,
+                    version,
+                );
+                (source_servers, needed_replicas, version)
+            };
+
+            if source_servers.is_empty() {
+                error!(
+                    "[Cron Task] Chunk '{}' has no replica left, it is listed again once a server holding it reports it",
+                    chunk_id
+                );
+                continue;
+            }
+
+            info!(
+                "[Cron Task] Chunk {:?} source servers: {:?}",
+                chunk_id, source_servers
+            );
+
+            if needed_replicas == 0 {
+                info!(
+                    "[Cron Task] Chunk '{}' already has enough replicas on healthy servers.",
+                    chunk_id
+                );
+                continue;
+            }
+
+            let added = self
+                .replicate_chunk(&chunk_id, &source_servers, needed_replicas, version)
+                .await;
+            if added < needed_replicas {
+                warn!(
+                    "[Cron Task] Chunk '{}' is short of {} replica(s), the repair scan copies it once servers are available",
+                    chunk_id,
+                    needed_replicas - added
+                );
+            }
+        }
+    }
+
+    /// Scan chunk_map for chunks with fewer live replicas than `replication_factor` and copy them
+    /// to new servers, at most `max_concurrent_repairs` chunks at a time.
+    ///
+    /// A replica is live if its server has sent a recent heartbeat.
+    async fn repair_under_replicated_chunks(&self) {
+        let healthy_servers = self.healthy_servers().await;
+
+        let replication_factor = self.common_config.replication_factor;
+        let under_replicated: Vec<(Arc<ChunkInfo>, Vec<String>)> = self
+            .chunk_map
+            .read()
+            .await
+            .values()
+            .filter_map(|chunk| {
+                let live: Vec<String> = chunk
+                    .server_addresses
+                    .iter()
+                    .filter(|addr| healthy_servers.contains(*addr))
+                    .cloned()
+                    .collect();
+                (live.len() < replication_factor).then(|| (chunk.clone(), live))
+            })
+            .collect();
+
+        self.under_replicated_chunks
+            .store(under_replicated.len(), Ordering::Relaxed);
+        self.metrics
+            .under_replicated_chunks
+            .set(under_replicated.len() as i64);
+        if under_replicated.is_empty() {
+            return;
+        }
+        warn!(
+            "[Repair] {} chunk(s) have fewer than {} live replica(s)",
+            under_replicated.len(),
+            replication_factor
+        );
+
+        // Chunks without a live replica can only be repaired once one of their servers returns
+        let (lost, repairable): (Vec<_>, Vec<_>) = under_replicated
+            .into_iter()
+            .partition(|(_, live)| live.is_empty());
+        if !lost.is_empty() {
+            error!(
+                "[Repair] {} chunk(s) have no live replica left to copy from",
+                lost.len()
+            );
+        }
+
+        futures::stream::iter(repairable)
+            .for_each_concurrent(
+                self.config.max_concurrent_repairs.max(1),
+                |(chunk, live)| async move {
+                    self.replicate_chunk(
+                        &chunk.chunk_id,
+                        &live,
+                        replication_factor - live.len(),
+                        chunk.version,
+                    )
+                    .await;
+                },
+            )
+            .await;
+    }
+
+    /// Number of chunks stored on `server` according to chunk_map
+    pub async fn chunks_on_server(&self, server: &str) -> u64 {
+        self.chunk_map
+            .read()
+            .await
+            .values()
+            .filter(|chunk| chunk.server_addresses.iter().any(|addr| addr == server))
+            .count() as u64
+    }
+
+    /// Progress of draining the decommissioned chunk server `server`
+    pub async fn decommission_progress(&self, server: &str) -> DecommissionResponse {
+        let chunks_remaining = self.chunks_on_server(server).await;
+        let unplaceable_chunks = self
+            .draining
+            .read()
+            .await
+            .get(server)
+            .copied()
+            .unwrap_or_default();
+        let completed = chunks_remaining == 0;
+        let message = if completed {
+            format!(
+                "Chunk server '{}' holds no more chunks and can be shut down.",
+                server
+            )
+        } else if unplaceable_chunks > 0 {
+            format!(
+                "Chunk server '{}' still holds {} chunk(s), {} of which can't be moved until more servers are available.",
+                server, chunks_remaining, unplaceable_chunks
+            )
+        } else {
+            format!(
+                "Chunk server '{}' is draining, {} chunk(s) remaining.",
+                server, chunks_remaining
+            )
+        };
+        DecommissionResponse {
+            chunks_remaining,
+            unplaceable_chunks,
+            completed,
+            message,
+        }
+    }
+
+    /// Move the chunks of draining servers to other servers.
+    ///
+    /// A chunk is only dropped from a draining server once it has `replication_factor` replicas
+    /// elsewhere; chunks that can't be placed stay where they are and are reported as unplaceable.
+    async fn drain_servers(&self) {
+        let draining: Vec<String> = self.draining.read().await.keys().cloned().collect();
+        if draining.is_empty() {
+            return;
+        }
+
+        let replication_factor = self.common_config.replication_factor;
+        let mut changed = false;
+        for server in draining {
+            // Chunks the server holds according to chunk_map, reported chunks may be outdated
+            let chunks: Vec<Arc<ChunkInfo>> = self
+                .chunk_map
+                .read()
+                .await
+                .values()
+                .filter(|chunk| chunk.server_addresses.contains(&server))
+                .cloned()
+                .collect();
+
+            let mut unplaceable_chunks: u64 = 0;
+            for chunk in &chunks {
+                let (others, needed_replicas) =
+                    healthy_replicas(chunk, &server, replication_factor);
+
+                // Copy from the draining server itself, it is still alive and holds the chunk
+                let mut version = chunk.version;
+                if needed_replicas > 0 {
+                    let mut source_servers = vec![server.clone()];
+                    source_servers.extend(others.iter().cloned());
+                    let added = self
+                        .replicate_chunk(
+                            &chunk.chunk_id,
+                            &source_servers,
+                            needed_replicas,
+                            chunk.version,
+                        )
+                        .await;
+                    if added > 0 {
+                        version += 1;
+                        changed = true;
+                    }
+                    if added < needed_replicas {
+                        unplaceable_chunks += 1;
+                        continue;
+                    }
+                }
+
+                // The chunk is fully replicated elsewhere, so the draining server can drop its copy
+                let mut replicas = self
+                    .chunk_map
+                    .read()
+                    .await
+                    .get(&chunk.chunk_id)
+                    .map(|chunk| chunk.server_addresses.clone())
+                    .unwrap_or_default();
+                replicas.retain(|addr| addr != &server);
+                set_chunk_replicas(
+                    &mut *self.file_chunks.write().await,
+                    &mut *self.chunk_servers.write().await,
+                    &mut *self.chunk_map.write().await,
+                    &chunk.chunk_id,
+                    &replicas,
+                    version,
+                );
+                if let Some(server_chunks) = self.chunk_servers.write().await.get_mut(&server) {
+                    server_chunks.retain(|existing| existing.chunk_id != chunk.chunk_id);
+                }
+                self.pending_deletions
+                    .write()
+                    .await
+                    .entry(server.clone())
+                    .or_default()
+                    .insert(chunk.chunk_id.clone());
+                changed = true;
+            }
+
+            if unplaceable_chunks > 0 {
+                warn!(
+                    "[Drain] {} chunk(s) of server '{}' can't be moved: not enough other servers to hold them",
+                    unplaceable_chunks, server
+                );
+            } else if !chunks.is_empty() {
+                info!(
+                    "[Drain] Server '{}' holds no more chunks, decommissioning is complete",
+                    server
+                );
+            }
+            if let Some(unplaceable) = self.draining.write().await.get_mut(&server) {
+                *unplaceable = unplaceable_chunks;
+            }
+        }
+
+        if changed {
+            // Send updated metadata to registered shadow masters
+            self.propagate_metadata_updates().await;
+        }
+    }
+
+    /// Copy a chunk from the first of `source_servers` to up to `needed_replicas` other servers.
+    ///
+    /// A target only counts once the source confirmed that its copy matches in length and
+    /// checksum, otherwise the next best server is tried. The chunk's replicas are then set to
+    /// `source_servers` plus the servers that received it, and its version to `version + 1`.
+    /// Returns the number of replicas added.
+    async fn replicate_chunk(
+        &self,
+        chunk_id: &str,
+        source_servers: &[String],
+        needed_replicas: usize,
+        version: u64,
+    ) -> usize {
+        // Collect available chunk servers for reassignment
+        // (available means load is less than max_allowed_chunks and not draining)
+        let candidates = self.candidates().await;
+        debug!(
+            "[Cron Task] Chunk {:?} has available servers: {:?}",
+            chunk_id, candidates.servers
+        );
+
+        // Select servers that are not a replica yet; the spares are used in place of targets
+        // whose copy fails
+        let placement = select_servers(
+            &candidates,
+            needed_replicas,
+            source_servers,
+            self.placement_policy(),
+        );
+
+        if placement.servers.is_empty() {
+            error!(
+                "[Cron Task] No selected servers to reassign chunk '{}'",
+                chunk_id
+            );
+            return 0;
+        }
+        if placement.shortfall > 0 {
+            warn!(
+                "[Cron Task] Only {} of {} missing replicas of chunk '{}' can be placed: not enough eligible servers",
+                placement.servers.len(),
+                needed_replicas,
+                chunk_id
+            );
+        }
+        if placement.colocated {
+            warn!(
+                "[Cron Task] New replicas {:?} of chunk '{}' share a failure domain with other replicas: not enough distinct hosts or domains",
+                placement.servers, chunk_id
+            );
+        }
+
+        info!(
+            "[Cron Task] Reassigning chunk '{}' to servers: {:?}",
+            chunk_id, placement.servers
+        );
+
+        // Transfer chunk data from the first healthy replica
+        let source_server = &source_servers[0];
+        let mut new_replicas = Vec::new();
+        for target_server in placement.servers.iter().chain(&placement.spares) {
+            if new_replicas.len() == needed_replicas {
+                break;
+            }
+            if self
+                .copy_chunk(chunk_id, source_server, target_server)
+                .await
+            {
+                new_replicas.push(target_server.clone());
+            }
+        }
+
+        if new_replicas.is_empty() {
+            return 0;
+        }
+
+        // Record the new replicas next to the healthy ones in all metadata maps
+        let mut server_addresses = source_servers.to_vec();
+        server_addresses.extend(new_replicas.iter().cloned());
+        set_chunk_replicas(
+            &mut *self.file_chunks.write().await,
+            &mut *self.chunk_servers.write().await,
+            &mut *self.chunk_map.write().await,
+            chunk_id,
+            &server_addresses,
+            version + 1,
+        );
+        new_replicas.len()
+    }
+
+    /// Chunk count of each live chunk server that is not being decommissioned
+    pub async fn server_loads(&self) -> HashMap<String, usize> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let last_heartbeat = self.last_heartbeat_time.read().await;
+        let draining = self.draining.read().await;
+        self.chunk_servers
+            .read()
+            .await
+            .iter()
+            .filter(|(addr, _)| {
+                last_heartbeat
+                    .get(*addr)
+                    .is_some_and(|&last_time| self.is_heartbeat_recent(last_time, now))
+                    && !draining.contains_key(*addr)
+            })
+            .map(|(addr, chunks)| (addr.clone(), chunks.len()))
+            .collect()
+    }
+
+    /// Plan moving chunks from the most to the least loaded servers until their chunk counts
+    /// differ by at most one.
+    ///
+    /// Returns None if a rebalance is already running. Otherwise the returned moves must be
+    /// passed to `run_rebalance`, which marks the rebalance as finished.
+    pub async fn plan_rebalance(&self) -> Option<Vec<ChunkMove>> {
+        {
+            let mut progress = self.rebalance.write().await;
+            if progress.running {
+                return None;
+            }
+            progress.running = true;
+        }
+
+        let mut loads = self.server_loads().await;
+        let capacities = self.server_capacity.read().await.clone();
+        let min_free_bytes = self.min_free_bytes();
+        let max_allowed_chunks = self.common_config.max_allowed_chunks;
+        // Servers that are full or low on disk space only give chunks away
+        let can_receive = |addr: &String, load: usize| {
+            load < max_allowed_chunks
+                && capacities
+                    .get(addr)
+                    .is_none_or(|capacity| capacity.available_bytes >= min_free_bytes)
+        };
+
+        let chunk_map = self.chunk_map.read().await;
+        let mut moves = Vec::new();
+        let mut planned = HashSet::new();
+        while let Some((from, from_load)) = loads
+            .iter()
+            .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0)))
+            .map(|(addr, load)| (addr.clone(), *load))
+        {
+            let Some((to, to_load)) = loads
+                .iter()
+                .filter(|(addr, load)| can_receive(addr, **load))
+                .min_by(|a, b| a.1.cmp(b.1).then(a.0.cmp(b.0)))
+                .map(|(addr, load)| (addr.clone(), *load))
+            else {
+                break;
+            };
+            if from_load <= to_load + 1 {
+                break;
+            }
+
+            // Never place two replicas of a chunk on the same server
+            let Some(chunk) = chunk_map.values().find(|chunk| {
+                !planned.contains(&chunk.chunk_id)
+                    && chunk.server_addresses.contains(&from)
+                    && !chunk.server_addresses.contains(&to)
+            }) else {
+                break;
+            };
+            planned.insert(chunk.chunk_id.clone());
+            moves.push(ChunkMove {
+                chunk_id: chunk.chunk_id.clone(),
+                from: from.clone(),
+                to: to.clone(),
+            });
+            *loads.entry(from).or_default() -= 1;
+            *loads.entry(to).or_default() += 1;
+        }
+        drop(chunk_map);
+
+        let mut progress = self.rebalance.write().await;
+        *progress = RebalanceProgress {
+            running: !moves.is_empty(),
+            moves_planned: moves.len() as u64,
+            ..RebalanceProgress::default()
+        };
+        if moves.is_empty() {
+            progress.finished_at = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+        } else {
+            info!("[Rebalance] Moving {} chunk(s)", moves.len());
+        }
+        Some(moves)
+    }
+
+    /// Carry out the moves planned by `plan_rebalance`, at most `max_concurrent_moves` at once
+    pub async fn run_rebalance(&self, moves: Vec<ChunkMove>) {
+        if moves.is_empty() {
+            return;
+        }
+
+        futures::stream::iter(moves)
+            .for_each_concurrent(
+                self.config.max_concurrent_moves.max(1),
+                |chunk_move| async move {
+                    let moved = self.move_chunk(&chunk_move).await;
+                    let mut progress = self.rebalance.write().await;
+                    if moved {
+                        progress.moves_done += 1;
+                    } else {
+                        progress.moves_failed += 1;
+                    }
+                },
+            )
+            .await;
+
+        let progress = {
+            let mut progress = self.rebalance.write().await;
+            progress.running = false;
+            progress.finished_at = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            *progress
+        };
+        info!(
+            "[Rebalance] Finished: {} of {} chunk move(s) done, {} failed",
+            progress.moves_done, progress.moves_planned, progress.moves_failed
+        );
+
+        if progress.moves_done > 0 {
+            // Send updated metadata to registered shadow masters
+            self.propagate_metadata_updates().await;
+        }
+    }
+
+    /// Copy a chunk to its new server, then replace the old replica with it in one step, so the
+    /// chunk never has fewer replicas than before the move
+    async fn move_chunk(&self, chunk_move: &ChunkMove) -> bool {
+        let ChunkMove { chunk_id, from, to } = chunk_move;
+        if !self.copy_chunk(chunk_id, from, to).await {
+            return false;
+        }
+
+        let mut file_chunks = self.file_chunks.write().await;
+        let mut chunk_servers = self.chunk_servers.write().await;
+        let mut chunk_map = self.chunk_map.write().await;
+        let mut pending_deletions = self.pending_deletions.write().await;
+        let Some(chunk) = chunk_map.get(chunk_id) else {
+            // The file was deleted during the move, so the new copy is not needed either
+            pending_deletions
+                .entry(to.clone())
+                .or_default()
+                .insert(chunk_id.clone());
+            return false;
+        };
+
+        let mut replicas = chunk.server_addresses.clone();
+        replicas.retain(|addr| addr != from);
+        if !replicas.contains(to) {
+            replicas.push(to.clone());
+        }
+        let version = chunk.version + 1;
+        set_chunk_replicas(
+            &mut file_chunks,
+            &mut chunk_servers,
+            &mut chunk_map,
+            chunk_id,
+            &replicas,
+            version,
+        );
+        if let Some(server_chunks) = chunk_servers.get_mut(from) {
+            server_chunks.retain(|existing| existing.chunk_id != *chunk_id);
+        }
+        pending_deletions
+            .entry(from.clone())
+            .or_default()
+            .insert(chunk_id.clone());
+        info!(
+            "[Rebalance] Moved chunk '{}' from '{}' to '{}'",
+            chunk_id, from, to
+        );
+        true
+    }
+
+    /// Progress of the current or last rebalance, with `message` or a summary of the progress
+    pub async fn rebalance_progress(&self, message: Option<String>) -> RebalanceStatusResponse {
+        let progress = *self.rebalance.read().await;
+        let message = message.unwrap_or_else(|| {
+            if progress.running {
+                format!(
+                    "Rebalance in progress: {} of {} chunk move(s) done.",
+                    progress.moves_done + progress.moves_failed,
+                    progress.moves_planned
+                )
+            } else if progress.finished_at > 0 {
+                format!(
+                    "Last rebalance moved {} of {} planned chunk(s).",
+                    progress.moves_done, progress.moves_planned
+                )
+            } else {
+                "No rebalance has run yet.".to_string()
+            }
+        });
+        RebalanceStatusResponse {
+            running: progress.running,
+            moves_planned: progress.moves_planned,
+            moves_done: progress.moves_done,
+            moves_failed: progress.moves_failed,
+            finished_at: progress.finished_at,
+            spread: load_spread(&self.server_loads().await) as u64,
+            message,
+        }
+    }
+
+    /// Overview of the cluster for operators, see the `ClusterStatus` RPC.
+    ///
+    /// The file and chunk maps are read together, in lock order, so the file and chunk counts
+    /// agree with each other. The per-server maps are copied one at a time afterwards.
+    pub async fn cluster_overview(&self, include_files: bool) -> ClusterStatusResponse {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let from_shadow = !self.is_leader().await;
+        let last_heartbeat_time = self.last_heartbeat_time.read().await.clone();
+        let healthy_servers: HashSet<&String> = last_heartbeat_time
+            .iter()
+            .filter(|(_, &last_time)| self.is_heartbeat_recent(last_time, now))
+            .map(|(server, _)| server)
+            .collect();
+
+        // Shadow masters receive no heartbeats, so they count all listed replicas as live
+        let replication_factor = self.common_config.replication_factor;
+        let is_under_replicated = |chunk: &ChunkInfo| {
+            let live = chunk
+                .server_addresses
+                .iter()
+                .filter(|server| from_shadow || healthy_servers.contains(server))
+                .count();
+            live < replication_factor
+        };
+
+        let mut status = ClusterStatusResponse::default();
+        let mut chunk_counts: HashMap<String, u64> = HashMap::new();
+        {
+            let file_chunks = self.file_chunks.read().await;
+            let chunk_servers = self.chunk_servers.read().await;
+            let chunk_map = self.chunk_map.read().await;
+            let pending_deletions = self.pending_deletions.read().await;
+
+            status.total_files = file_chunks.len() as u64;
+            status.total_chunks = chunk_map.len() as u64;
+            status.under_replicated_chunks = chunk_map
+                .values()
+                .filter(|chunk| is_under_replicated(chunk))
+                .count() as u64;
+            status.pending_deletions = pending_deletions.values().map(|c| c.len() as u64).sum();
+            if include_files {
+                status.files = file_chunks
+                    .iter()
+                    .map(|(file_name, chunks)| FileStatus {
+                        file_name: file_name.clone(),
+                        size: chunks.iter().map(|chunk| chunk.size).sum(),
+                        chunks: chunks.len() as u64,
+                        under_replicated_chunks: chunks
+                            .iter()
+                            .map(|chunk| chunk_map.get(&chunk.chunk_id).unwrap_or(chunk))
+                            .filter(|chunk| is_under_replicated(chunk))
+                            .count() as u64,
+                    })
+                    .collect();
+                status.files.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+            }
+            for (server, chunks) in chunk_servers.iter() {
+                chunk_counts.insert(server.clone(), chunks.len() as u64);
+            }
+        }
+
+        let server_capacity = self.server_capacity.read().await.clone();
+        let scrub_status = self.scrub_status.read().await.clone();
+        let draining = self.draining.read().await.clone();
+        let mut addresses: Vec<&String> = chunk_counts
+            .keys()
+            .chain(last_heartbeat_time.keys())
+            .collect();
+        addresses.sort();
+        addresses.dedup();
+        status.chunk_servers = addresses
+            .into_iter()
+            .map(|address| {
+                let capacity = server_capacity.get(address).copied().unwrap_or_default();
+                let scrub = scrub_status.get(address).copied().unwrap_or_default();
+                ChunkServerStatus {
+                    address: address.clone(),
+                    chunks: chunk_counts.get(address).copied().unwrap_or(0),
+                    last_heartbeat_age: last_heartbeat_time
+                        .get(address)
+                        .map_or(0, |&last_time| now.saturating_sub(last_time)),
+                    healthy: healthy_servers.contains(address),
+                    total_bytes: capacity.total_bytes,
+                    available_bytes: capacity.available_bytes,
+                    draining: draining.contains_key(address),
+                    last_scrub_time: scrub.last_scrub_time,
+                    corrupt_chunks: scrub.corrupt_chunks,
+                }
+            })
+            .collect();
+
+        // Only the leader knows how far the shadow masters got
+        let replication_lag = if from_shadow {
+            HashMap::new()
+        } else {
+            self.replication_lag().await
+        };
+        let mut shadow_masters: Vec<String> =
+            self.shadow_masters.read().await.keys().cloned().collect();
+        shadow_masters.sort();
+        status.shadow_masters = shadow_masters
+            .into_iter()
+            .map(|address| ShadowMasterStatus {
+                replication_lag: replication_lag.get(&address).copied().unwrap_or(0),
+                address,
+            })
+            .collect();
+
+        status.leader_address = self.current_master.read().await.clone();
+        status.epoch = self.epoch.load(Ordering::SeqCst);
+        status.metadata_version = *self.metadata_version.read().await;
+        status.from_shadow = from_shadow;
+        status
+    }
+
+    /// Copy the chunks of `source`, which were planned as the copy to `destination` in `copies`,
+    /// and register the copy as a file owned by `owner` once every chunk has been copied.
+    ///
+    /// Each live replica copies its chunk locally, so the copy has the same placement as the
+    /// source. If no replica of some chunk could be copied, or `destination` was created in the
+    /// meantime, the copies made so far are deleted and no file is registered.
+    pub async fn run_copy(
+        &self,
+        destination: &str,
+        chunks: Vec<Arc<ChunkInfo>>,
+        owner: Option<String>,
+    ) {
+        let Some(progress) = self.copies.read().await.get(destination).cloned() else {
+            return;
+        };
+        let healthy_servers = self.healthy_servers().await;
+
+        let mut copied = Vec::new();
+        let mut failure = None;
+        for (chunk, chunk_id) in chunks.iter().zip(&progress.chunk_ids) {
+            let sources: Vec<&String> = chunk
+                .server_addresses
+                .iter()
+                .filter(|server| healthy_servers.contains(*server))
+                .collect();
+            let results = futures::future::join_all(
+                sources
+                    .iter()
+                    .map(|server| self.copy_chunk_as(&chunk.chunk_id, server, server, chunk_id)),
+            )
+            .await;
+            let server_addresses: Vec<String> = sources
+                .into_iter()
+                .zip(results)
+                .filter(|(_, copied)| *copied)
+                .map(|(server, _)| server.clone())
+                .collect();
+            if server_addresses.is_empty() {
+                failure = Some(format!(
+                    "No replica of chunk {} of '{}' could be copied",
+                    chunk.index, progress.source
+                ));
+                break;
+            }
+            if server_addresses.len() < chunk.server_addresses.len() {
+                warn!(
+                    "[Copy] Chunk {} of '{}' was copied to only {} of {} replica(s), the rest is copied by the repair scan",
+                    chunk.index,
+                    destination,
+                    server_addresses.len(),
+                    chunk.server_addresses.len()
+                );
+            }
+            copied.push(Arc::new(ChunkInfo {
+                chunk_id: chunk_id.clone(),
+                primary: server_addresses[0].clone(),
+                server_addresses,
+                version: 0,
+                size: chunk.size,
+                file_name: destination.to_string(),
+                index: chunk.index,
+                lease_expiry: 0,
+                shared_with: 0,
+                placement_proof: String::new(),
+            }));
+            if let Some(progress) = self.copies.write().await.get_mut(destination) {
+                progress.chunks_copied += 1;
+            }
+        }
+
+        let result = match failure {
+            Some(failure) => Err(failure),
+            None => self.register_copy(destination, &copied, owner).await,
+        };
+        if let Err(failure) = &result {
+            error!(
+                "[Copy] Failed to copy '{}' to '{}': {}",
+                progress.source, destination, failure
+            );
+            let mut pending_deletions = self.pending_deletions.write().await;
+            for chunk in &copied {
+                for server in &chunk.server_addresses {
+                    pending_deletions
+                        .entry(server.clone())
+                        .or_default()
+                        .insert(chunk.chunk_id.clone());
+                }
+            }
+        } else {
+            info!(
+                "[Copy] Copied '{}' to '{}' ({} chunk(s))",
+                progress.source,
+                destination,
+                copied.len()
+            );
+        }
+        if let Some(progress) = self.copies.write().await.get_mut(destination) {
+            progress.running = false;
+            progress.error = result.err().unwrap_or_default();
+        }
+        self.propagate_metadata_updates().await;
+    }
+
+    /// Record the copied chunks as the file `destination`, unless it was created meanwhile
+    async fn register_copy(
+        &self,
+        destination: &str,
+        chunks: &[Arc<ChunkInfo>],
+        owner: Option<String>,
+    ) -> std::result::Result<(), String> {
+        if !self.is_leader().await {
+            return Err("This master is no longer the leader".to_string());
+        }
+        let _lock = self.file_locks.lock(destination).await;
+        let mut file_chunks = self.file_chunks.write().await;
+        let mut chunk_servers = self.chunk_servers.write().await;
+        let mut chunk_map = self.chunk_map.write().await;
+        if file_chunks.contains_key(destination) {
+            return Err(format!("'{}' was created during the copy", destination));
+        }
+        if let Some(conflict) = find_conflict(file_chunks.keys(), destination) {
+            return Err(format!(
+                "'{}' conflicts with the file '{}' created during the copy",
+                destination, conflict
+            ));
+        }
+        file_chunks.insert(destination.to_string(), chunks.to_vec());
+        for chunk in chunks {
+            for server in &chunk.server_addresses {
+                if let Some(server_chunks) = chunk_servers.get_mut(server) {
+                    server_chunks.push(chunk.clone());
+                }
+            }
+            chunk_map.insert(chunk.chunk_id.clone(), chunk.clone());
+        }
+        if let Some(owner) = owner {
+            self.file_owners
+                .write()
+                .await
+                .insert(destination.to_string(), owner);
+        }
+        Ok(())
+    }
+
+    /// Progress of the current or last copy to `destination`, None if there was none
+    pub async fn copy_progress(&self, destination: &str) -> Option<master::CopyStatusResponse> {
+        let progress = self.copies.read().await.get(destination).cloned()?;
+        let chunks_total = progress.chunk_ids.len() as u64;
+        let message = if progress.running {
+            format!(
+                "Copying '{}' to '{}': {} of {} chunk(s) copied.",
+                progress.source, destination, progress.chunks_copied, chunks_total
+            )
+        } else if progress.error.is_empty() {
+            format!("Copied '{}' to '{}'.", progress.source, destination)
+        } else {
+            format!(
+                "Copying '{}' to '{}' failed: {}",
+                progress.source, destination, progress.error
+            )
+        };
+        Some(master::CopyStatusResponse {
+            source: progress.source,
+            destination: destination.to_string(),
+            running: progress.running,
+            chunks_total,
+            chunks_copied: progress.chunks_copied,
+            error: progress.error,
+            message,
+        })
+    }
+
+    /// Have `source_server` send a chunk to `target_server`.
+    ///
+    /// Returns true once the source confirmed that the target's copy matches in length and checksum.
+    async fn copy_chunk(&self, chunk_id: &str, source_server: &str, target_server: &str) -> bool {
+        self.copy_chunk_as(chunk_id, source_server, target_server, chunk_id)
+            .await
+    }
+
+    /// Have `source_server` send a chunk to `target_server`, which may be the same server, and
+    /// store it there as `target_chunk_id`, see `copy_chunk`
+    async fn copy_chunk_as(
+        &self,
+        chunk_id: &str,
+        source_server: &str,
+        target_server: &str,
+        target_chunk_id: &str,
+    ) -> bool {
+        // Attempt to connect to the source server
+        let mut source_client = match connect_channel(source_server, &self.common_config.tls).await
+        {
+            Ok(channel) => ChunkClient::new(channel),
+            Err(e) => {
+                error!(
+                    "[Cron Task] Failed to connect to source server '{}': {}",
+                    source_server, e
+                );
+                return false;
+            }
+        };
+
+        // Prepare the request to send the chunk
+        let epoch = self.epoch.load(Ordering::SeqCst);
+        let send_request = SendChunkRequest {
+            chunk_name: chunk_id.to_string(),
+            target_address: target_server.to_string(),
+            epoch,
+            target_chunk_name: target_chunk_id.to_string(),
+            internal_proof: self.command_proof(&transfer_chunk_fields(
+                chunk_id,
+                target_server,
+                &epoch.to_be_bytes(),
+                target_chunk_id,
+            )),
+        };
+
+        // Attempt to transfer the chunk
+        match source_client
+            .transfer_chunk(tonic::Request::new(send_request))
+            .await
+        {
+            Ok(response) => {
+                let response = response.into_inner();
+                info!(
+                    "[Cron Task] Successfully transferred chunk '{}' ({} byte(s), checksum {:08x}) from source server '{}' to target server '{}'.",
+                    chunk_id, response.bytes_written, response.checksum, source_server, target_server
+                );
+                true
+            }
+            Err(e) => {
+                error!(
+                    "[Cron Task] Failed to transfer chunk '{}' from '{}' to '{}': {}",
+                    chunk_id, source_server, target_server, e
+                );
+                false
+            }
+        }
+    }
+
+    /// Check the password of a user and issue a session token with its expiration time
+    pub async fn authenticate_user(&self, username: &str, password: &str) -> Result<(String, u64)> {
+        // Load and parse the JSON authentication file
+        let file_content = fs::read_to_string(&self.config.authentication_file_path)?;
+        let auth_data: Value = serde_json::from_str(&file_content)?;
+
+        // Validate the username and password against the stored hash
+        if !verify_user(&auth_data, username, password)? {
+            return Err(Error::Auth("Invalid username or password".to_string()));
+        }
+
+        let role = user_role(&auth_data, username)?;
+        Ok(self.issue_session_token(username, role))
+    }
+
+    /// Sign a session token for `username` with `role`, starting a new session, see
+    /// `session_token`
+    pub fn issue_session_token(&self, username: &str, role: Role) -> (String, u64) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        self.session_token(username, role, now)
+    }
+
+    /// Sign a session token for `username` with `role` in a session started at `session_start`,
+    /// valid for `otp_valid_duration` but not beyond `max_session_duration` after the session
+    /// started. Chunkservers check it with the cluster secret on their own.
+    fn session_token(&self, username: &str, role: Role, session_start: u64) -> (String, u64) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let expiration_time = (now + self.common_config.otp_valid_duration.as_secs())
+            .min(session_start.saturating_add(self.config.max_session_duration.as_secs()));
+        let token = issue_token(
+            &self.common_config.cluster_secret,
+            &TokenClaims {
+                username: username.to_string(),
+                role,
+                expires_at: expiration_time,
+                session_start,
+            },
+        );
+        debug!(
+            "[issue_session_token] Token issued for user {} ({}), expiring at {}",
+            username,
+            role.as_str(),
+            expiration_time
+        );
+        (token, expiration_time)
+    }
+
+    /// Replace a session token with a new one for the same user.
+    ///
+    /// The token must carry a valid signature and may have expired for at most
+    /// `otp_valid_duration`, so a client whose token ran out during a long request can still
+    /// renew it. The new token belongs to the same session, which can't be renewed beyond
+    /// `max_session_duration` after the user authenticated, so a stolen token is of no use for
+    /// long. The role is looked up again, so the new token carries the current one, and users
+    /// removed from the authentication file can't renew.
+    pub fn renew_session_token(&self, token: &str) -> Result<(String, u64)> {
+        let claims = verify_token(&self.common_config.cluster_secret, token)?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let grace = self.common_config.otp_valid_duration.as_secs();
+        if claims.expires_at.saturating_add(grace) <= now {
+            return Err(Error::Auth(format!(
+                "Session token of user '{}' expired too long ago, authenticate again",
+                claims.username
+            )));
+        }
+        let max_session = self.config.max_session_duration.as_secs();
+        if claims.session_start.saturating_add(max_session) <= now {
+            return Err(Error::Auth(format!(
+                "Session of user '{}' reached max_session_duration, authenticate again",
+                claims.username
+            )));
+        }
+        let file_content = fs::read_to_string(&self.config.authentication_file_path)?;
+        let auth_data: Value = serde_json::from_str(&file_content)?;
+        let role = user_role(&auth_data, &claims.username)?;
+        Ok(self.session_token(&claims.username, role, claims.session_start))
+    }
+
+    /// Identify the user of a mutating request by its session token, `None` without
+    /// authentication.
+    ///
+    /// The role is looked up in the authentication file rather than taken from the token, so
+    /// changing it takes effect right away on the master.
+    pub async fn identify(&self, token: &str) -> Result<Option<Identity>> {
+        if !self.common_config.use_authentication {
+            return Ok(None);
+        }
+        let claims = verify_token(&self.common_config.cluster_secret, token)?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        if claims.expires_at <= now {
+            return Err(Error::Auth(format!(
+                "Session token of user '{}' expired",
+                claims.username
+            )));
+        }
+        let file_content = tokio::fs::read_to_string(&self.config.authentication_file_path).await?;
+        let auth_data: Value = serde_json::from_str(&file_content)?;
+        let role = user_role(&auth_data, &claims.username)?;
+        Ok(Some(Identity {
+            username: claims.username,
+            role,
+        }))
+    }
+
+    /// Remove the metadata of chunks whose file was deleted.
+    ///
+    /// The chunkservers holding them are asked to delete the data on their next heartbeat.
+    /// Chunks shared with other files, e.g. snapshots, are kept for those, one file fewer
+    /// sharing them.
+    pub async fn purge_chunks(&self, chunks: &[Arc<ChunkInfo>]) {
+        let mut file_chunks = self.file_chunks.write().await;
+        let mut chunk_servers = self.chunk_servers.write().await;
+        let mut chunk_map = self.chunk_map.write().await;
+        let mut pending_deletions = self.pending_deletions.write().await;
+
+        for chunk_info in chunks {
+            // The given ChunkInfo may be outdated, the one in chunk_map is current
+            if chunk_map
+                .get(&chunk_info.chunk_id)
+                .is_some_and(|chunk| chunk.shared_with > 0)
+            {
+                replace_chunk(
+                    &mut file_chunks,
+                    &mut chunk_servers,
+                    &mut chunk_map,
+                    &chunk_info.chunk_id,
+                    |chunk| chunk.shared_with -= 1,
+                );
+                continue;
+            }
+
+            // Remove the chunk from chunk_servers
+            for server in &chunk_info.server_addresses {
+                pending_deletions
+                    .entry(server.clone())
+                    .or_default()
+                    .insert(chunk_info.chunk_id.clone());
+                // A server left without chunks stays listed, so new chunks can be placed on it
+                if let Some(server_chunks) = chunk_servers.get_mut(server) {
+                    server_chunks.retain(|chunk| chunk.chunk_id != chunk_info.chunk_id);
+                }
+            }
+
+            // Remove the chunk from chunk_map
+            chunk_map.remove(&chunk_info.chunk_id);
+        }
+    }
+
+    /// Describe the quota a new file of `file_size` bytes would exceed, if any
+    pub fn exceeded_quota(&self, usage: Usage, file_size: u64) -> Option<String> {
+        let max_total_bytes = self.config.max_total_bytes;
+        let max_files = self.config.max_files;
+        if max_total_bytes > 0 && usage.total_bytes.saturating_add(file_size) > max_total_bytes {
+            return Some(format!(
+                "Storage quota exceeded: {} byte(s) used, {} byte(s) requested, limit is {} byte(s)",
+                usage.total_bytes, file_size, max_total_bytes
+            ));
+        }
+        if max_files > 0 && usage.files >= max_files {
+            return Some(format!(
+                "File quota exceeded: {} file(s) stored, limit is {} file(s)",
+                usage.files, max_files
+            ));
+        }
+        None
+    }
+
+    /// Delete a file that was already removed from `file_chunks` and return a message for the client.
+    ///
+    /// If `trash_retention_secs` is set the file is moved to the trash together with its owner,
+    /// otherwise its chunks are purged and the chunkservers delete the data on their next
+    /// heartbeat.
+    pub async fn discard_file(&self, file_name: &str, chunks: &[Arc<ChunkInfo>]) -> String {
+        let owner = self
+            .file_owners
+            .write()
+            .await
+            .remove(file_name)
+            .unwrap_or_default();
+        let retention = self.settings.trash_retention();
+        if !retention.is_zero() {
+            let trashed_name = self.move_to_trash(file_name, chunks, owner).await;
+            info!(
+                "File '{}' moved to the trash as '{}'",
+                file_name, trashed_name
+            );
+            format!(
+                "File '{}' moved to the trash, restore it with 'undelete {}' within {}.",
+                file_name,
+                trashed_name,
+                format_duration(retention)
+            )
+        } else {
+            self.purge_chunks(chunks).await;
+            info!("All metadata for file '{}' has been deleted.", file_name);
+            format!(
+                "File '{}' deleted successfully from master metadata.",
+                file_name
+            )
+        }
+    }
+
+    /// Move the chunks of a deleted file to the trash and return the name it is stored under.
+    ///
+    /// The name gets a suffix if a file with the same name is already in the trash.
+    pub async fn move_to_trash(
+        &self,
+        file_name: &str,
+        chunks: &[Arc<ChunkInfo>],
+        owner: String,
+    ) -> String {
+        let deleted_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let mut trash = self.trash.write().await;
+
+        let mut trashed_name = file_name.to_string();
+        let mut suffix = 1;
+        while trash.contains_key(&trashed_name) {
+            trashed_name = format!("{}-{}", file_name, suffix);
+            suffix += 1;
+        }
+
+        trash.insert(
+            trashed_name.clone(),
+            TrashedFile {
+                file_name: file_name.to_string(),
+                chunk_ids: chunks.iter().map(|chunk| chunk.chunk_id.clone()).collect(),
+                deleted_at,
+                owner,
+            },
+        );
+        trashed_name
+    }
+
+    /// Starts a periodic task permanently deleting files whose trash retention has expired
+    pub async fn start_trash_purger(self: Arc<Self>) {
+        let service = Arc::clone(&self);
+        service.tasks.spawn("trash purger", async move {
+            let mut ticker = time::interval(self.settings.cron_interval());
+            loop {
+                self.next_cron_tick(&mut ticker).await;
+                // Stop once this master stepped down as leader
+                if !self.is_leader().await {
+                    break;
+                }
+
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs();
+                let retention = self.settings.trash_retention().as_secs();
+
+                let expired: Vec<(String, TrashedFile)> = {
+                    let mut trash = self.trash.write().await;
+                    let expired_names: Vec<String> = trash
+                        .iter()
+                        .filter(|(_, entry)| entry.deleted_at + retention <= now)
+                        .map(|(name, _)| name.clone())
+                        .collect();
+                    expired_names
+                        .into_iter()
+                        .filter_map(|name| trash.remove(&name).map(|entry| (name, entry)))
+                        .collect()
+                };
+                if expired.is_empty() {
+                    continue;
+                }
+
+                for (trashed_name, entry) in &expired {
+                    let chunks: Vec<Arc<ChunkInfo>> = {
+                        let chunk_map = self.chunk_map.read().await;
+                        entry
+                            .chunk_ids
+                            .iter()
+                            .filter_map(|chunk_id| chunk_map.get(chunk_id).cloned())
+                            .collect()
+                    };
+                    self.purge_chunks(&chunks).await;
+                    info!(
+                        "[Trash] Permanently deleted '{}' ({} chunk(s))",
+                        trashed_name,
+                        chunks.len()
+                    );
+                }
+
+                // Send updated metadata to registered shadow masters
+                self.propagate_metadata_updates().await;
+            }
+        });
+    }
+
+    /// Apply `update` to a chunk's ChunkInfo, shared by file_chunks, chunk_servers and chunk_map
+    pub async fn update_chunk_info<F>(&self, chunk_id: &str, update: F)
+    where
+        F: FnOnce(&mut ChunkInfo),
+    {
+        let mut file_chunks = self.file_chunks.write().await;
+        let mut chunk_servers = self.chunk_servers.write().await;
+        let mut chunk_map = self.chunk_map.write().await;
+        replace_chunk(
+            &mut file_chunks,
+            &mut chunk_servers,
+            &mut chunk_map,
+            chunk_id,
+            update,
+        );
+    }
+
+    /// Tell a chunk server that this master took over as leader
+    async fn send_leader_to_chunkserver(&self, chunk_server: &str) -> Result<()> {
+        let mut client =
+            ChunkClient::new(connect_channel(chunk_server, &self.common_config.tls).await?);
+        let epoch = self.epoch.load(Ordering::SeqCst);
+        client
+            .leader_changed(tonic::Request::new(
+                crate::proto::chunk::LeaderChangedRequest {
+                    leader_address: self.addr.clone(),
+                    epoch,
+                    internal_proof: self.command_proof(&chunk_leader_changed_fields(
+                        &self.addr,
+                        &epoch.to_be_bytes(),
+                    )),
+                },
+            ))
+            .await?;
+        Ok(())
+    }
+
+    /// Proof of a command to a chunkserver signed over `fields`, see `validate_internal_proof`
+    /// of `ChunkService`
+    fn command_proof(&self, fields: &[&[u8]]) -> String {
+        sign_request(&self.common_config.cluster_secret, fields)
+    }
+
+    /// Check that a request announcing `leader` comes from one of the configured masters, by
+    /// `proof` signed over `fields` with `sign_request` when authentication is enabled
+    pub fn verify_master_request(&self, leader: &str, proof: &str, fields: &[&[u8]]) -> Result<()> {
+        if !self
+            .common_config
+            .master_addrs
+            .iter()
+            .any(|addr| addr == leader)
+        {
+            return Err(Error::Auth(format!(
+                "'{}' is not a configured master",
+                leader
+            )));
+        }
+        if !self.common_config.use_authentication {
+            // Trusted like client requests when authentication is disabled
+            return Ok(());
+        }
+        verify_request(&self.common_config.cluster_secret, proof, fields)
+    }
+
+    /// Check that a request comes from a chunkserver, by `proof` signed over `fields` with
+    /// `sign_request` when authentication is enabled
+    pub fn verify_chunkserver_request(&self, proof: &str, fields: &[&[u8]]) -> Result<()> {
+        if !self.common_config.use_authentication {
+            // Trusted like client requests when authentication is disabled
+            return Ok(());
+        }
+        verify_request(&self.common_config.cluster_secret, proof, fields)
+    }
+
+    /// Signature of the primary and replicas a chunk is placed on, with which a client uploading
+    /// the chunk shows the replicas that the master placed it there
+    pub fn placement_proof(&self, chunk: &ChunkInfo) -> String {
+        sign_placement(
+            &self.common_config.cluster_secret,
+            &chunk.chunk_id,
+            &chunk.primary,
+            &chunk.server_addresses,
+        )
+    }
+
+    /// Lease on a chunk with one of its replicas as primary.
+    ///
+    /// An unexpired lease of a live primary is kept, and renewed once less than half of the
+    /// lease duration is left. Otherwise the lease is granted to the chunk's primary if it is
+    /// live, or else to the first live replica that accepts it, which becomes the primary.
+    /// Returns `None` if no replica accepted the lease.
+    pub async fn ensure_lease(&self, chunk: &ChunkInfo) -> Option<ChunkLease> {
+        let chunk_id = &chunk.chunk_id;
+        let replicas = &chunk.server_addresses;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let healthy_servers = self.healthy_servers().await;
+        let current = self.chunk_leases.read().await.get(chunk_id).cloned();
+        if let Some(lease) = current {
+            if lease.expiry > now
+                && healthy_servers.contains(&lease.primary)
+                && replicas.contains(&lease.primary)
+            {
+                // The lease is granted again once the replicas changed, e.g. by a repair
+                if lease.expiry > now + self.config.lease_duration.as_secs() / 2
+                    && lease.replicas == *replicas
+                {
+                    return Some(lease);
+                }
+                // Another replica must not become primary while this lease may still be used
+                return match self.grant_lease(&lease.primary, chunk_id, replicas).await {
+                    Ok(renewed) => Some(renewed),
+                    Err(e) => {
+                        warn!(
+                            "[Lease] Failed to renew lease of chunk {} on {}: {}",
+                            chunk_id, lease.primary, e
+                        );
+                        Some(lease)
+                    }
+                };
+            }
+        }
+
+        let (primary, secondaries): (Vec<&String>, Vec<&String>) = replicas
+            .iter()
+            .partition(|replica| **replica == chunk.primary);
+        for replica in primary
+            .into_iter()
+            .chain(secondaries)
+            .filter(|replica| healthy_servers.contains(*replica))
+        {
+            match self.grant_lease(replica, chunk_id, replicas).await {
+                Ok(lease) => return Some(lease),
+                Err(e) => warn!(
+                    "[Lease] Failed to grant lease of chunk {} to {}: {}",
+                    chunk_id, replica, e
+                ),
+            }
+        }
+        None
+    }
+
+    /// Make `chunk_server` the primary of a chunk for the next `lease_duration` seconds, applying
+    /// appends and writes to `replicas` only
+    async fn grant_lease(
+        &self,
+        chunk_server: &str,
+        chunk_id: &str,
+        replicas: &[String],
+    ) -> Result<ChunkLease> {
+        let mut client =
+            ChunkClient::new(connect_channel(chunk_server, &self.common_config.tls).await?);
+        let duration = self.config.lease_duration.as_secs();
+        let epoch = self.epoch.load(Ordering::SeqCst);
+        client
+            .grant_lease(tonic::Request::new(GrantLeaseRequest {
+                chunk_id: chunk_id.to_string(),
+                duration,
+                epoch,
+                internal_proof: self.command_proof(&grant_lease_fields(
+                    chunk_id,
+                    &duration.to_be_bytes(),
+                    &epoch.to_be_bytes(),
+                    replicas,
+                )),
+                replicas: replicas.to_vec(),
+            }))
+            .await?;
+
+        // Counted from the acknowledgement, so the chunkserver's lease never outlasts this one
+        let lease = ChunkLease {
+            primary: chunk_server.to_string(),
+            expiry: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+                + self.config.lease_duration.as_secs(),
+            replicas: replicas.to_vec(),
+        };
+        info!(
+            "[Lease] Granted lease of chunk {} to {} until {}",
+            chunk_id, chunk_server, lease.expiry
+        );
+        self.chunk_leases
+            .write()
+            .await
+            .insert(chunk_id.to_string(), lease.clone());
+
+        // Appends and writes keep going to this replica once the lease expired
+        let moved = self
+            .chunk_map
+            .read()
+            .await
+            .get(chunk_id)
+            .is_some_and(|chunk| chunk.primary != chunk_server);
+        if moved {
+            self.update_chunk_info(chunk_id, |chunk| chunk.primary = chunk_server.to_string())
+                .await;
+            self.propagate_metadata_updates().await;
+        }
+        Ok(lease)
+    }
+
+    /// Grant the leases held by a server that missed its heartbeats to another replica
+    async fn regrant_leases_of_failed_server(&self, failed_server: &str) {
+        let chunk_ids: Vec<String> = {
+            let mut chunk_leases = self.chunk_leases.write().await;
+            let chunk_ids = chunk_leases
+                .iter()
+                .filter(|(_, lease)| lease.primary == failed_server)
+                .map(|(chunk_id, _)| chunk_id.clone())
+                .collect::<Vec<_>>();
+            for chunk_id in &chunk_ids {
+                chunk_leases.remove(chunk_id);
+            }
+            chunk_ids
+        };
+
+        for chunk_id in chunk_ids {
+            let chunk = match self.chunk_map.read().await.get(&chunk_id) {
+                Some(chunk) => Arc::clone(chunk),
+                None => continue, // Deleted in the meantime
+            };
+            match self.ensure_lease(&chunk).await {
+                Some(lease) => info!(
+                    "[Lease] Moved lease of chunk {} from failed {} to {}",
+                    chunk_id, failed_server, lease.primary
+                ),
+                None => warn!(
+                    "[Lease] No replica of chunk {} took over the lease of failed {}",
+                    chunk_id, failed_server
+                ),
+            }
+        }
+    }
+
+    /// Cut every replica of a chunk to `length` bytes. Returns the replicas that failed.
+    pub async fn truncate_replicas(&self, chunk: &ChunkInfo, length: u64) -> Vec<String> {
+        let mut failed = Vec::new();
+        for server in &chunk.server_addresses {
+            if let Err(e) = self.truncate_replica(server, &chunk.chunk_id, length).await {
+                warn!(
+                    "[Truncate] Failed to truncate chunk {} on {}: {}",
+                    chunk.chunk_id, server, e
+                );
+                failed.push(server.clone());
+            }
+        }
+        failed
+    }
+
+    async fn truncate_replica(
+        &self,
+        chunk_server: &str,
+        chunk_id: &str,
+        length: u64,
+    ) -> Result<()> {
+        let mut client =
+            ChunkClient::new(connect_channel(chunk_server, &self.common_config.tls).await?);
+        let epoch = self.epoch.load(Ordering::SeqCst);
+        client
+            .truncate_chunk(tonic::Request::new(TruncateChunkRequest {
+                chunk_id: chunk_id.to_string(),
+                length,
+                epoch,
+                internal_proof: self.command_proof(&truncate_chunk_fields(
+                    chunk_id,
+                    &length.to_be_bytes(),
+                    &epoch.to_be_bytes(),
+                )),
+            }))
+            .await?;
+        Ok(())
+    }
+
+    /// Record `snapshot_name` as a file listing the chunks of `source`, counting it among the
+    /// files sharing each of them
+    pub async fn share_chunks(&self, source: &str, snapshot_name: &str) {
+        let mut file_chunks = self.file_chunks.write().await;
+        let mut chunk_servers = self.chunk_servers.write().await;
+        let mut chunk_map = self.chunk_map.write().await;
+        let Some(chunks) = file_chunks.get(source).cloned() else {
+            return;
+        };
+        for chunk in &chunks {
+            replace_chunk(
+                &mut file_chunks,
+                &mut chunk_servers,
+                &mut chunk_map,
+                &chunk.chunk_id,
+                |chunk| chunk.shared_with += 1,
+            );
+        }
+        // The source lists the updated chunks now
+        let shared = file_chunks[source].clone();
+        file_chunks.insert(snapshot_name.to_string(), shared);
+    }
+
+    /// Revoke the unexpired leases on `chunks`, so that their primaries take no further appends
+    /// or writes, e.g. before a snapshot shares the chunks. The appends and writes in progress
+    /// are finished before. Fails if a primary cannot be reached, which keeps its lease.
+    pub async fn revoke_leases(&self, chunks: &[Arc<ChunkInfo>]) -> Result<()> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        for chunk in chunks {
+            let Some(lease) = self.chunk_leases.write().await.remove(&chunk.chunk_id) else {
+                continue;
+            };
+            if lease.expiry <= now {
+                continue;
+            }
+            if let Err(e) = self.revoke_lease(&lease.primary, &chunk.chunk_id).await {
+                self.chunk_leases
+                    .write()
+                    .await
+                    .insert(chunk.chunk_id.clone(), lease);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// End the lease of `chunk_server` on a chunk with a lease of 0 seconds
+    async fn revoke_lease(&self, chunk_server: &str, chunk_id: &str) -> Result<()> {
+        let mut client =
+            ChunkClient::new(connect_channel(chunk_server, &self.common_config.tls).await?);
+        let epoch = self.epoch.load(Ordering::SeqCst);
+        client
+            .grant_lease(tonic::Request::new(GrantLeaseRequest {
+                chunk_id: chunk_id.to_string(),
+                duration: 0,
+                epoch,
+                internal_proof: self.command_proof(&grant_lease_fields(
+                    chunk_id,
+                    &0u64.to_be_bytes(),
+                    &epoch.to_be_bytes(),
+                    &[],
+                )),
+                replicas: Vec::new(),
+            }))
+            .await?;
+        info!(
+            "[Lease] Revoked lease of chunk {} from {}",
+            chunk_id, chunk_server
+        );
+        Ok(())
+    }
+
+    /// Give `file_name` a copy of its own of a chunk it shares with other files, e.g. its
+    /// snapshots, before the chunk is changed, and return the chunk the file lists then.
+    ///
+    /// Every live replica copies the chunk locally, so the copy has the same placement, and
+    /// replicas that fail are left to the repair scan. A chunk the file does not share is
+    /// returned as it is. The caller holds the lock of the file, see `FileLocks`.
+    pub async fn unshare_chunk(&self, file_name: &str, chunk_id: &str) -> Result<Arc<ChunkInfo>> {
+        let chunk = self
+            .chunk_map
+            .read()
+            .await
+            .get(chunk_id)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(file_name.to_string()))?;
+        if chunk.shared_with == 0 {
+            return Ok(chunk);
+        }
+
+        let healthy_servers = self.healthy_servers().await;
+        let new_chunk_id = Uuid::new_v4().to_string();
+        let sources: Vec<&String> = chunk
+            .server_addresses
+            .iter()
+            .filter(|server| healthy_servers.contains(*server))
+            .collect();
+        let results = futures::future::join_all(
+            sources
+                .iter()
+                .map(|server| self.clone_replica(server, chunk_id, &new_chunk_id)),
+        )
+        .await;
+        let mut server_addresses = Vec::new();
+        for (server, result) in sources.into_iter().zip(results) {
+            match result {
+                Ok(()) => server_addresses.push(server.clone()),
+                Err(e) => warn!(
+                    "[Snapshot] Failed to copy shared chunk {} on {}: {}",
+                    chunk_id, server, e
+                ),
+            }
+        }
+        if server_addresses.is_empty() {
+            return Err(Error::NoAvailableServers(format!(
+                "no replica of chunk '{}' of '{}' could be copied before changing it, the chunk is shared with a snapshot",
+                chunk_id, file_name
+            )));
+        }
+
+        let copy = Arc::new(ChunkInfo {
+            chunk_id: new_chunk_id,
+            // The primary of the shared chunk, if its copy succeeded
+            primary: server_addresses
+                .iter()
+                .find(|server| **server == chunk.primary)
+                .unwrap_or(&server_addresses[0])
+                .clone(),
+            server_addresses,
+            version: 0,
+            size: chunk.size,
+            file_name: file_name.to_string(),
+            index: chunk.index,
+            lease_expiry: 0,
+            shared_with: 0,
+            placement_proof: String::new(),
+        });
+        let mut file_chunks = self.file_chunks.write().await;
+        let mut chunk_servers = self.chunk_servers.write().await;
+        let mut chunk_map = self.chunk_map.write().await;
+        let Some(listed) = file_chunks
+            .get_mut(file_name)
+            .and_then(|chunks| chunks.iter_mut().find(|listed| listed.chunk_id == chunk_id))
+        else {
+            drop((file_chunks, chunk_servers, chunk_map));
+            self.purge_chunks(&[copy]).await;
+            return Err(Error::NotFound(file_name.to_string()));
+        };
+        *listed = Arc::clone(&copy);
+        for server in &copy.server_addresses {
+            if let Some(chunks) = chunk_servers.get_mut(server) {
+                chunks.push(Arc::clone(&copy));
+            }
+        }
+        chunk_map.insert(copy.chunk_id.clone(), Arc::clone(&copy));
+        replace_chunk(
+            &mut file_chunks,
+            &mut chunk_servers,
+            &mut chunk_map,
+            chunk_id,
+            |chunk| chunk.shared_with -= 1,
+        );
+        info!(
+            "[Snapshot] Copied chunk {} of '{}' shared with a snapshot to {} before changing it",
+            chunk_id, file_name, copy.chunk_id
+        );
+        Ok(copy)
+    }
+
+    /// Have `chunk_server` copy a chunk it stores to `new_chunk_id`
+    async fn clone_replica(
+        &self,
+        chunk_server: &str,
+        chunk_id: &str,
+        new_chunk_id: &str,
+    ) -> Result<()> {
+        let mut client =
+            ChunkClient::new(connect_channel(chunk_server, &self.common_config.tls).await?);
+        let epoch = self.epoch.load(Ordering::SeqCst);
+        client
+            .clone_chunk(tonic::Request::new(CloneChunkRequest {
+                chunk_id: chunk_id.to_string(),
+                new_chunk_id: new_chunk_id.to_string(),
+                epoch,
+                internal_proof: self.command_proof(&clone_chunk_fields(
+                    chunk_id,
+                    new_chunk_id,
+                    &epoch.to_be_bytes(),
+                )),
+            }))
+            .await?;
+        Ok(())
+    }
+
+    /// Check a replica that a client failed to read, and drop it if the chunkserver lost the
+    /// chunk or its data no longer matches the stored checksum, so that the repair scan copies
+    /// the chunk again. Returns whether the replica was dropped.
+    ///
+    /// Reports are not trusted blindly, the client's own network may have failed: a replica
+    /// that checks out is kept, and one the master can't reach either is left to the heartbeat
+    /// checker. A replica already being checked, e.g. for another client, is not checked again.
+    pub async fn verify_reported_replica(&self, chunk_id: &str, server: &str) -> Result<bool> {
+        let listed = self
+            .chunk_map
+            .read()
+            .await
+            .get(chunk_id)
+            .is_some_and(|chunk| chunk.server_addresses.iter().any(|addr| addr == server));
+        // Dropped already, or the chunk was deleted meanwhile
+        if !listed {
+            return Ok(false);
+        }
+        let key = (chunk_id.to_string(), server.to_string());
+        if !self.verifying_replicas.write().await.insert(key.clone()) {
+            return Ok(false);
+        }
+        let bad = self.check_replica(chunk_id, server).await;
+        self.verifying_replicas.write().await.remove(&key);
+
+        if !bad? {
+            return Ok(false);
+        }
+        self.remove_replica(chunk_id, server).await;
+        self.propagate_metadata_updates().await;
+        Ok(true)
+    }
+
+    /// Check a replica that a client reported as having missed a write and drop it if the chunk
+    /// is missing or corrupt there, or if its data differs from a replica that was written.
+    /// Returns whether the replica was dropped.
+    ///
+    /// `reported` lists the servers the client reported for the chunk, which are not compared
+    /// against. The primary is preferred as the written replica, as appends and writes go there
+    /// first. A replica that can't be compared, e.g. as no written one is reachable, is kept and
+    /// left to the heartbeat checker and the repair of corrupt chunks.
+    pub async fn verify_failed_replica(
+        &self,
+        chunk_id: &str,
+        server: &str,
+        reported: &[&str],
+    ) -> Result<bool> {
+        let Some(chunk) = self.chunk_map.read().await.get(chunk_id).cloned() else {
+            return Ok(false);
+        };
+        // Dropped already, e.g. by an earlier report
+        if !chunk.server_addresses.iter().any(|addr| addr == server) {
+            return Ok(false);
+        }
+        let key = (chunk_id.to_string(), server.to_string());
+        if !self.verifying_replicas.write().await.insert(key.clone()) {
+            return Ok(false);
+        }
+        let bad: Result<bool> = async {
+            let Some(reported_data) = self.replica_checksum(chunk_id, server).await? else {
+                return Ok(true);
+            };
+            let written = std::iter::once(&chunk.primary)
+                .chain(&chunk.server_addresses)
+                .find(|addr| {
+                    !addr.is_empty() && *addr != server && !reported.contains(&addr.as_str())
+                });
+            match written {
+                Some(written) => Ok(self
+                    .replica_checksum(chunk_id, written)
+                    .await?
+                    .is_some_and(|written_data| written_data != reported_data)),
+                None => Ok(false),
+            }
+        }
+        .await;
+        self.verifying_replicas.write().await.remove(&key);
+
+        if !bad? {
+            return Ok(false);
+        }
+        self.remove_replica(chunk_id, server).await;
+        Ok(true)
+    }
+
+    /// Whether a chunkserver lost its replica of a chunk or holds corrupt data for it
+    async fn check_replica(&self, chunk_id: &str, server: &str) -> Result<bool> {
+        Ok(self.replica_checksum(chunk_id, server).await?.is_none())
+    }
+
+    /// CRC32 and length of a chunkserver's replica of a chunk, `None` if the replica is lost or
+    /// does not match the checksum stored with it
+    async fn replica_checksum(&self, chunk_id: &str, server: &str) -> Result<Option<(u32, u64)>> {
+        let (token, _) = self.issue_session_token(&self.addr, Role::ReadOnly);
+        let mut client = ChunkClient::new(connect_channel(server, &self.common_config.tls).await?);
+        match client
+            .checksum_chunk(tonic::Request::new(ChecksumChunkRequest {
+                chunk_id: chunk_id.to_string(),
+                token,
+            }))
+            .await
+        {
+            Ok(response) => {
+                let response = response.into_inner();
+                if response
+                    .stored_checksum
+                    .is_some_and(|stored| stored != response.checksum)
+                {
+                    return Ok(None);
+                }
+                Ok(Some((response.checksum, response.length)))
+            }
+            Err(status) if status.code() == tonic::Code::NotFound => Ok(None),
+            Err(status) => Err(status.into()),
+        }
+    }
+
+    /// Drop a server from the replicas of a chunk, e.g. because its copy missed a write or is
+    /// corrupt. The chunk is then copied again by `repair_under_replicated_chunks`.
+    pub async fn remove_replica(&self, chunk_id: &str, server_address: &str) {
+        self.update_chunk_info(chunk_id, |chunk| {
+            chunk.server_addresses.retain(|addr| addr != server_address);
+            elect_primary(chunk);
+        })
+        .await;
+        if let Some(chunks) = self.chunk_servers.write().await.get_mut(server_address) {
+            chunks.retain(|chunk| chunk.chunk_id != chunk_id);
+        }
     }
 }
@@ -0,0 +1,74 @@
+// Locks serializing the requests that change the metadata of the same file, e.g. a deletion
+// racing an append or a truncation
+use prometheus::IntGauge;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, Weak};
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+/// Lock of every file a request is changing or waiting to change, by file name.
+///
+/// Entries are removed once no request holds or waits for the lock, so files locked once don't
+/// stay in the map. A request cancelled while waiting, e.g. as its client went away, leaves a
+/// dead entry behind, which the next call to `lock` removes.
+#[derive(Debug)]
+pub struct FileLocks {
+    locks: Arc<Mutex<HashMap<String, Weak<AsyncMutex<()>>>>>,
+    gauge: IntGauge, // Number of entries, see `MasterMetrics::file_locks`
+}
+
+impl FileLocks {
+    pub fn new(gauge: IntGauge) -> Self {
+        FileLocks {
+            locks: Arc::new(Mutex::new(HashMap::new())),
+            gauge,
+        }
+    }
+
+    /// Wait until no other request changes `file_name`, and hold its lock until the returned
+    /// guard is dropped
+    pub async fn lock(&self, file_name: &str) -> FileLockGuard {
+        let lock = {
+            let mut locks = self.locks.lock().unwrap();
+            locks.retain(|_, lock| lock.strong_count() > 0);
+            let lock = match locks.get(file_name).and_then(Weak::upgrade) {
+                Some(lock) => lock,
+                None => {
+                    let lock = Arc::new(AsyncMutex::new(()));
+                    locks.insert(file_name.to_string(), Arc::downgrade(&lock));
+                    lock
+                }
+            };
+            self.gauge.set(locks.len() as i64);
+            lock
+        };
+        FileLockGuard {
+            locks: Arc::clone(&self.locks),
+            gauge: self.gauge.clone(),
+            file_name: file_name.to_string(),
+            guard: Some(lock.lock_owned().await),
+        }
+    }
+}
+
+/// Lock of a file, released when dropped, see `FileLocks::lock`
+#[derive(Debug)]
+pub struct FileLockGuard {
+    locks: Arc<Mutex<HashMap<String, Weak<AsyncMutex<()>>>>>,
+    gauge: IntGauge,
+    file_name: String,
+    guard: Option<OwnedMutexGuard<()>>,
+}
+
+impl Drop for FileLockGuard {
+    fn drop(&mut self) {
+        let mut locks = self.locks.lock().unwrap();
+        self.guard.take();
+        if locks
+            .get(&self.file_name)
+            .is_some_and(|lock| lock.strong_count() == 0)
+        {
+            locks.remove(&self.file_name);
+        }
+        self.gauge.set(locks.len() as i64);
+    }
+}
@@ -0,0 +1,82 @@
+// Responses of recently completed requests by the operation ID the client chose, so that a
+// request retried after a lost response is answered like the first attempt instead of being
+// applied twice
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::proto::master::{AssignResponse, DeleteFileResponse};
+
+/// Response to a completed request, together with what identifies the request
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompletedOperation {
+    pub user: String,      // User who sent the request, empty without authentication
+    pub file_name: String, // File the request named
+    pub response: OperationResponse,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum OperationResponse {
+    Assign(AssignResponse),
+    Delete(DeleteFileResponse),
+}
+
+/// Completed operations, forgotten after `operation_retention` or once more than
+/// `max_remembered_operations` completed since, the oldest first
+#[derive(Debug)]
+pub struct OperationCache {
+    completed: HashMap<String, (Instant, CompletedOperation)>,
+    order: VecDeque<String>, // Operation IDs, oldest first
+    retention: Duration,
+    capacity: usize,
+}
+
+impl OperationCache {
+    pub fn new(retention: Duration, capacity: usize) -> Self {
+        OperationCache {
+            completed: HashMap::new(),
+            order: VecDeque::new(),
+            retention,
+            capacity,
+        }
+    }
+
+    /// The completed operation with this ID, if it is still remembered
+    pub fn get(&mut self, operation_id: &str) -> Option<&CompletedOperation> {
+        self.expire();
+        self.completed
+            .get(operation_id)
+            .map(|(_, operation)| operation)
+    }
+
+    /// Remember a completed operation. Does nothing for an empty ID, which clients that don't
+    /// deduplicate their requests send.
+    pub fn insert(&mut self, operation_id: &str, operation: CompletedOperation) {
+        if operation_id.is_empty() || self.capacity == 0 || self.retention.is_zero() {
+            return;
+        }
+        if self
+            .completed
+            .insert(operation_id.to_string(), (Instant::now(), operation))
+            .is_none()
+        {
+            self.order.push_back(operation_id.to_string());
+        }
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.completed.remove(&oldest);
+            }
+        }
+    }
+
+    fn expire(&mut self) {
+        while let Some(oldest) = self.order.front() {
+            match self.completed.get(oldest) {
+                Some((completed_at, _)) if completed_at.elapsed() < self.retention => break,
+                _ => {
+                    let oldest = self.order.pop_front().unwrap();
+                    self.completed.remove(&oldest);
+                }
+            }
+        }
+    }
+}
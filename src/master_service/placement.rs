@@ -0,0 +1,126 @@
+// Selection of the chunkservers that store the replicas of a chunk, shared by the assignment of
+// new chunks and the repair of under-replicated ones
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use super::ServerCapacity;
+use crate::error::{Error, Result};
+
+/// How `select_servers` ranks the candidate servers, see `placement_policy` in config.toml
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlacementPolicy {
+    LeastLoaded,   // Fewest chunks first
+    MostFreeSpace, // Most free disk space first, then fewest chunks
+    FailureDomain, // Like MostFreeSpace, but servers in failure domains without a replica first
+}
+
+impl FromStr for PlacementPolicy {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "least_loaded" => Ok(PlacementPolicy::LeastLoaded),
+            "most_free_space" => Ok(PlacementPolicy::MostFreeSpace),
+            "failure_domain" => Ok(PlacementPolicy::FailureDomain),
+            _ => Err(Error::Config(format!(
+                "Invalid placement_policy '{}', expected \"least_loaded\", \"most_free_space\" or \"failure_domain\"",
+                value
+            ))),
+        }
+    }
+}
+
+/// What the master knows about the servers that may receive a replica, copied from its
+/// metadata so that no lock is held while replicas are placed
+#[derive(Clone, Debug, Default)]
+pub struct Candidates {
+    pub servers: HashMap<String, usize>, // Server -> Number of its chunks
+    pub capacities: HashMap<String, ServerCapacity>, // Server -> Reported disk capacity
+    pub failure_domains: HashMap<String, String>, // Server -> Failure domain it registered with
+    pub min_free_bytes: u64,             // Servers that reported less free space are never selected
+}
+
+/// Servers selected for the replicas of a chunk
+#[derive(Debug, Default)]
+pub struct Placement {
+    pub servers: Vec<String>, // Up to `count` distinct servers, best first
+    pub spares: Vec<String>, // The remaining eligible servers, best first, e.g. to copy to if a copy fails
+    pub shortfall: usize,    // Replicas missing from `count` for lack of eligible servers
+    pub colocated: bool, // Whether the selected and excluded servers share a failure domain, only checked by PlacementPolicy::FailureDomain
+}
+
+/// Failure domain of the chunkserver at `addr`: the label it registered with, or else the host of its address
+pub fn failure_domain<'a>(addr: &'a str, domains: &'a HashMap<String, String>) -> &'a str {
+    match domains.get(addr) {
+        Some(domain) if !domain.is_empty() => domain,
+        _ => addr.rsplit_once(':').map_or(addr, |(host, _)| host),
+    }
+}
+
+/// Whether two of `servers` are in the same failure domain
+pub fn shares_failure_domain<'a>(
+    servers: impl IntoIterator<Item = &'a String>,
+    domains: &HashMap<String, String>,
+) -> bool {
+    let mut seen = HashSet::new();
+    !servers
+        .into_iter()
+        .all(|addr| seen.insert(failure_domain(addr, domains)))
+}
+
+/// Select `count` distinct servers out of `candidates` for the replicas of a chunk, ranked by
+/// `policy`.
+///
+/// Servers in `exclude`, e.g. those already holding the chunk, are never selected; with
+/// `PlacementPolicy::FailureDomain` their failure domains count as taken. Servers that have not
+/// reported their capacity yet are ranked last by free space. If fewer than `count` servers are
+/// eligible, all of them are selected and the rest is reported as `shortfall`.
+pub fn select_servers(
+    candidates: &Candidates,
+    count: usize,
+    exclude: &[String],
+    policy: PlacementPolicy,
+) -> Placement {
+    let mut ranked: Vec<(u64, usize, &String)> = candidates
+        .servers
+        .iter()
+        .filter(|(addr, _)| !exclude.contains(addr))
+        .filter_map(|(addr, &chunks)| match candidates.capacities.get(addr) {
+            Some(capacity) if capacity.available_bytes < candidates.min_free_bytes => None,
+            Some(capacity) => Some((capacity.available_bytes, chunks, addr)),
+            None => Some((0, chunks, addr)),
+        })
+        .collect();
+    match policy {
+        PlacementPolicy::LeastLoaded => ranked.sort_by(|a, b| a.1.cmp(&b.1).then(a.2.cmp(b.2))),
+        PlacementPolicy::MostFreeSpace | PlacementPolicy::FailureDomain => {
+            ranked.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)).then(a.2.cmp(b.2)))
+        }
+    }
+    let mut ranked: Vec<&String> = ranked.into_iter().map(|(_, _, addr)| addr).collect();
+
+    let domains = &candidates.failure_domains;
+    if policy == PlacementPolicy::FailureDomain {
+        // Move servers in domains without a replica to the front, keeping the order otherwise
+        let mut used: HashSet<&str> = exclude
+            .iter()
+            .map(|addr| failure_domain(addr, domains))
+            .collect();
+        let (mut distinct, same_domain): (Vec<&String>, Vec<&String>) = ranked
+            .into_iter()
+            .partition(|addr| used.insert(failure_domain(addr, domains)));
+        distinct.extend(same_domain);
+        ranked = distinct;
+    }
+
+    let mut servers: Vec<String> = ranked.into_iter().cloned().collect();
+    let spares = servers.split_off(count.min(servers.len()));
+    let colocated = policy == PlacementPolicy::FailureDomain
+        && shares_failure_domain(servers.iter().chain(exclude), domains);
+    Placement {
+        shortfall: count - servers.len(),
+        servers,
+        spares,
+        colocated,
+    }
+}
@@ -0,0 +1,396 @@
+// Prometheus metrics of the master and chunkservers, served over HTTP at /metrics, together
+// with a health check at /health
+use hyper::header::CONTENT_TYPE;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use prometheus::core::Collector;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::time::Instant;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+/// Register `metric` with `registry` and return it for updating
+fn register<M: Collector + Clone + 'static>(registry: &Registry, metric: M) -> M {
+    registry
+        .register(Box::new(metric.clone()))
+        .expect("Metric registered twice");
+    metric
+}
+
+/// Count and latency of the RPCs handled by a server, labeled by method
+#[derive(Clone, Debug)]
+pub struct RequestMetrics {
+    requests: IntCounterVec,
+    durations: HistogramVec,
+}
+
+impl RequestMetrics {
+    fn new(registry: &Registry, prefix: &str) -> Self {
+        RequestMetrics {
+            requests: register(
+                registry,
+                IntCounterVec::new(
+                    Opts::new(
+                        format!("{}_requests_total", prefix),
+                        "RPCs handled, by method and outcome",
+                    ),
+                    &["method", "status"],
+                )
+                .unwrap(),
+            ),
+            durations: register(
+                registry,
+                HistogramVec::new(
+                    HistogramOpts::new(
+                        format!("{}_request_duration_seconds", prefix),
+                        "Time taken to handle an RPC, by method",
+                    ),
+                    &["method"],
+                )
+                .unwrap(),
+            ),
+        }
+    }
+
+    /// Start timing an RPC, recorded once `RequestTimer::finish` is called with its result
+    pub fn start(&self, method: &'static str) -> RequestTimer {
+        RequestTimer {
+            metrics: self.clone(),
+            method,
+            start: Instant::now(),
+        }
+    }
+}
+
+/// Running RPC, see `RequestMetrics::start`
+pub struct RequestTimer {
+    metrics: RequestMetrics,
+    method: &'static str,
+    start: Instant,
+}
+
+impl RequestTimer {
+    pub fn finish<T, E>(self, result: &Result<T, E>) {
+        let status = if result.is_ok() { "ok" } else { "error" };
+        self.metrics
+            .requests
+            .with_label_values(&[self.method, status])
+            .inc();
+        self.metrics
+            .durations
+            .with_label_values(&[self.method])
+            .observe(self.start.elapsed().as_secs_f64());
+    }
+}
+
+/// Metrics of a master node
+#[derive(Clone, Debug)]
+pub struct MasterMetrics {
+    pub registry: Registry,
+    pub requests: RequestMetrics,
+    pub chunkservers: IntGauge,          // Chunkservers sending heartbeats
+    pub chunkserver_chunks: IntGaugeVec, // Chunks per chunkserver, by server address
+    pub heartbeats: IntCounter,          // Heartbeats received
+    pub under_replicated_chunks: IntGauge, // Chunks with too few live replicas at the last repair scan
+    pub metadata_replication_failures: IntCounter, // Failed attempts to send metadata to a shadow master
+    pub audit_entries_dropped: IntCounter, // Audit entries dropped as the audit log fell behind
+    pub file_locks: IntGauge, // Files whose metadata a request is changing or waiting to change
+    pub bad_replica_reports: IntCounter, // Replicas that clients failed to read from
+}
+
+impl MasterMetrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+        MasterMetrics {
+            requests: RequestMetrics::new(&registry, "rustfs_master"),
+            chunkservers: register(
+                &registry,
+                IntGauge::new(
+                    "rustfs_master_chunkservers",
+                    "Chunkservers sending heartbeats to the master",
+                )
+                .unwrap(),
+            ),
+            chunkserver_chunks: register(
+                &registry,
+                IntGaugeVec::new(
+                    Opts::new(
+                        "rustfs_master_chunkserver_chunks",
+                        "Chunks stored on each chunkserver",
+                    ),
+                    &["server"],
+                )
+                .unwrap(),
+            ),
+            heartbeats: register(
+                &registry,
+                IntCounter::new(
+                    "rustfs_master_heartbeats_total",
+                    "Heartbeats received from chunkservers",
+                )
+                .unwrap(),
+            ),
+            under_replicated_chunks: register(
+                &registry,
+                IntGauge::new(
+                    "rustfs_master_under_replicated_chunks",
+                    "Chunks with fewer live replicas than the replication factor",
+                )
+                .unwrap(),
+            ),
+            metadata_replication_failures: register(
+                &registry,
+                IntCounter::new(
+                    "rustfs_master_metadata_replication_failures_total",
+                    "Failed attempts to send metadata to a shadow master",
+                )
+                .unwrap(),
+            ),
+            audit_entries_dropped: register(
+                &registry,
+                IntCounter::new(
+                    "rustfs_master_audit_entries_dropped_total",
+                    "Audit entries dropped as the audit log could not keep up",
+                )
+                .unwrap(),
+            ),
+            file_locks: register(
+                &registry,
+                IntGauge::new(
+                    "rustfs_master_file_locks",
+                    "Files whose metadata a request is changing or waiting to change",
+                )
+                .unwrap(),
+            ),
+            bad_replica_reports: register(
+                &registry,
+                IntCounter::new(
+                    "rustfs_master_bad_replica_reports_total",
+                    "Replicas that clients reported they failed to read a chunk from",
+                )
+                .unwrap(),
+            ),
+            registry,
+        }
+    }
+}
+
+impl Default for MasterMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Metrics of a chunkserver
+#[derive(Clone, Debug)]
+pub struct ChunkServerMetrics {
+    pub registry: Registry,
+    pub requests: RequestMetrics,
+    pub bytes_written: IntCounterVec, // Chunk data written, by method
+    pub bytes_read: IntCounterVec,    // Chunk data read, by method
+    pub heartbeats: IntCounterVec,    // Heartbeats sent, by outcome
+    pub master_connected: IntGauge, // 1 while the leader acknowledges heartbeats, 0 while it can't be reached
+    pub streaming_reads: IntGauge,  // Reads streaming a chunk, each holding a read buffer
+    pub writes_in_progress: IntGauge, // Uploads and appends holding one of max_concurrent_writes
+    pub rejected_writes: IntCounter, // Writes rejected after waiting write_queue_timeout
+    pub duplicate_appends: IntCounter, // Appends not applied again as their append ID was journaled
+    pub syncs: IntCounterVec,       // Files flushed to disk, by kind, see `sync_writes`
+    pub chunks: IntGauge,           // Chunks stored
+    pub data_bytes: IntGauge,       // Size of the files in all data directories
+    pub disk_total_bytes: IntGauge, // Capacity of the disks holding the data directories
+    pub disk_available_bytes: IntGauge, // Free space on those disks
+    pub dir_chunks: IntGaugeVec,    // Chunk files, by data directory
+    pub dir_data_bytes: IntGaugeVec, // Size of the files, by data directory
+    pub dir_total_bytes: IntGaugeVec, // Capacity of the disk holding each data directory
+    pub dir_available_bytes: IntGaugeVec, // Free space on that disk, by data directory
+}
+
+impl ChunkServerMetrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+        let counter_vec = |name: &str, help: &str, label: &str| {
+            register(
+                &registry,
+                IntCounterVec::new(Opts::new(name, help), &[label]).unwrap(),
+            )
+        };
+        let gauge =
+            |name: &str, help: &str| register(&registry, IntGauge::new(name, help).unwrap());
+        let dir_gauge = |name: &str, help: &str| {
+            register(
+                &registry,
+                IntGaugeVec::new(Opts::new(name, help), &["dir"]).unwrap(),
+            )
+        };
+        ChunkServerMetrics {
+            requests: RequestMetrics::new(&registry, "rustfs_chunkserver"),
+            bytes_written: counter_vec(
+                "rustfs_chunkserver_bytes_written_total",
+                "Chunk data written, by method",
+                "method",
+            ),
+            bytes_read: counter_vec(
+                "rustfs_chunkserver_bytes_read_total",
+                "Chunk data read, by method",
+                "method",
+            ),
+            heartbeats: counter_vec(
+                "rustfs_chunkserver_heartbeats_total",
+                "Heartbeats sent to the master, by outcome",
+                "status",
+            ),
+            master_connected: gauge(
+                "rustfs_chunkserver_master_connected",
+                "1 while the leader master acknowledges the heartbeats, 0 while none can be reached",
+            ),
+            streaming_reads: gauge(
+                "rustfs_chunkserver_streaming_reads",
+                "Reads streaming a chunk, each holding one of the read_buffers",
+            ),
+            writes_in_progress: gauge(
+                "rustfs_chunkserver_writes_in_progress",
+                "Uploads and appends being handled, at most max_concurrent_writes",
+            ),
+            rejected_writes: register(
+                &registry,
+                IntCounter::new(
+                    "rustfs_chunkserver_rejected_writes_total",
+                    "Uploads and appends rejected as max_concurrent_writes were running",
+                )
+                .unwrap(),
+            ),
+            duplicate_appends: register(
+                &registry,
+                IntCounter::new(
+                    "rustfs_chunkserver_duplicate_appends_total",
+                    "Retried appends answered from the append journal instead of being applied again",
+                )
+                .unwrap(),
+            ),
+            syncs: counter_vec(
+                "rustfs_chunkserver_syncs_total",
+                "Files flushed to disk as sync_writes asks, by kind (chunk, checksum, append_journal or directory)",
+                "kind",
+            ),
+            chunks: gauge(
+                "rustfs_chunkserver_chunks",
+                "Chunks stored on the chunkserver",
+            ),
+            data_bytes: gauge(
+                "rustfs_chunkserver_data_bytes",
+                "Size of the files in all data directories",
+            ),
+            disk_total_bytes: gauge(
+                "rustfs_chunkserver_disk_total_bytes",
+                "Capacity of the disks holding the data directories, each disk counted once",
+            ),
+            disk_available_bytes: gauge(
+                "rustfs_chunkserver_disk_available_bytes",
+                "Free space on the disks holding the data directories, each disk counted once",
+            ),
+            dir_chunks: dir_gauge(
+                "rustfs_chunkserver_dir_chunks",
+                "Chunk files in each data directory",
+            ),
+            dir_data_bytes: dir_gauge(
+                "rustfs_chunkserver_dir_data_bytes",
+                "Size of the files in each data directory",
+            ),
+            dir_total_bytes: dir_gauge(
+                "rustfs_chunkserver_dir_disk_total_bytes",
+                "Capacity of the disk holding each data directory",
+            ),
+            dir_available_bytes: dir_gauge(
+                "rustfs_chunkserver_dir_disk_available_bytes",
+                "Free space on the disk holding each data directory",
+            ),
+            registry,
+        }
+    }
+}
+
+impl Default for ChunkServerMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Serve the metrics of `registry` in the Prometheus text format at `http://<addr>/metrics`.
+///
+/// `http://<addr>/health` answers `SERVING`, or `NOT_SERVING` with status 503 while `health` is
+/// 0. Failing to listen on `addr` is logged, the node keeps running without metrics. Aborting
+/// the returned task stops serving.
+pub fn start_metrics_server(
+    addr: &str,
+    registry: Registry,
+    health: Option<IntGauge>,
+) -> Option<JoinHandle<()>> {
+    let addr: SocketAddr = match addr.parse() {
+        Ok(addr) => addr,
+        Err(e) => {
+            error!("[Metrics] Invalid metrics address '{}': {}", addr, e);
+            return None;
+        }
+    };
+    Some(tokio::spawn(async move {
+        let make_service = make_service_fn(move |_| {
+            let registry = registry.clone();
+            let health = health.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let response = respond(&registry, health.as_ref(), &request);
+                    async move { Ok::<_, Infallible>(response) }
+                }))
+            }
+        });
+        match Server::try_bind(&addr) {
+            Ok(server) => {
+                info!("[Metrics] Serving metrics at http://{}/metrics", addr);
+                if let Err(e) = server.serve(make_service).await {
+                    error!("[Metrics] Metrics server failed: {}", e);
+                }
+            }
+            Err(e) => error!("[Metrics] Failed to serve metrics at {}: {}", addr, e),
+        }
+    }))
+}
+
+fn respond(
+    registry: &Registry,
+    health: Option<&IntGauge>,
+    request: &Request<Body>,
+) -> Response<Body> {
+    if request.method() == Method::GET && request.uri().path() == "/health" {
+        return match health.is_none_or(|health| health.get() > 0) {
+            true => Response::new(Body::from("SERVING\n")),
+            false => Response::builder()
+                .status(StatusCode::SERVICE_UNAVAILABLE)
+                .body(Body::from("NOT_SERVING\n"))
+                .unwrap(),
+        };
+    }
+    if request.method() != Method::GET || request.uri().path() != "/metrics" {
+        return Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .unwrap();
+    }
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    if let Err(e) = encoder.encode(&registry.gather(), &mut buffer) {
+        error!("[Metrics] Failed to encode metrics: {}", e);
+        return Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::empty())
+            .unwrap();
+    }
+    Response::builder()
+        .header(CONTENT_TYPE, encoder.format_type())
+        .body(Body::from(buffer))
+        .unwrap()
+}
@@ -0,0 +1,80 @@
+// Path semantics of the file namespace, which the master stores as a flat map of normalized paths
+use crate::error::{Error, Result};
+
+/// Normalize a file path to the form `/dir/.../name`.
+///
+/// The leading `/` is optional and `.` segments are dropped. Empty paths, empty segments
+/// (`a//b`, a trailing `/`) and `..` segments are rejected.
+pub fn normalize_path(path: &str) -> Result<String> {
+    let normalized = normalize(path, false)?;
+    if normalized == "/" {
+        return Err(Error::InvalidPath(format!("'{}' is not a file name", path)));
+    }
+    Ok(normalized)
+}
+
+/// Normalize a directory path like `normalize_path`, also accepting the root (`/` or an empty
+/// path) and a trailing `/`
+pub fn normalize_dir(path: &str) -> Result<String> {
+    normalize(path, true)
+}
+
+fn normalize(path: &str, is_dir: bool) -> Result<String> {
+    let trimmed = path.strip_prefix('/').unwrap_or(path);
+    let trimmed = match trimmed.strip_suffix('/') {
+        Some(trimmed) if is_dir => trimmed,
+        _ => trimmed,
+    };
+
+    let mut segments = Vec::new();
+    if !trimmed.is_empty() {
+        for segment in trimmed.split('/') {
+            match segment {
+                "" => {
+                    return Err(Error::InvalidPath(format!(
+                        "'{}' contains an empty path segment",
+                        path
+                    )))
+                }
+                ".." => {
+                    return Err(Error::InvalidPath(format!(
+                        "'{}' must not contain '..'",
+                        path
+                    )))
+                }
+                "." => {}
+                segment => segments.push(segment),
+            }
+        }
+    }
+    Ok(format!("/{}", segments.join("/")))
+}
+
+/// Whether the normalized `path` lies below the normalized directory `dir`
+pub fn is_under(path: &str, dir: &str) -> bool {
+    dir == "/"
+        || path
+            .strip_prefix(dir)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// The directories containing the normalized `path`, excluding the root, e.g. `/a` and `/a/b`
+/// for `/a/b/c`
+pub fn parent_dirs(path: &str) -> impl Iterator<Item = &str> {
+    path.match_indices('/')
+        .skip(1)
+        .map(move |(index, _)| &path[..index])
+}
+
+/// Find an existing file that conflicts with creating a file at the normalized `path`.
+///
+/// A path can't be both a file and a directory, so a file conflicts if it is one of the
+/// directories containing `path`, or if it lies below `path`.
+pub fn find_conflict<'a>(
+    existing: impl IntoIterator<Item = &'a String>,
+    path: &str,
+) -> Option<&'a String> {
+    existing
+        .into_iter()
+        .find(|file| parent_dirs(path).any(|dir| dir == file.as_str()) || is_under(file, path))
+}
@@ -0,0 +1,184 @@
+// Applies changes of the config file to a running master, on SIGHUP or the ReloadConfig RPC
+use prometheus::Registry;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::config::{load_config, CommonConfig, Config, MasterConfig};
+use crate::error::{Error, Result};
+use crate::logging::LogHandle;
+use crate::metrics::start_metrics_server;
+use crate::proto::master::ReloadConfigResponse;
+
+/// Settings a running master re-reads from the config file on reload
+#[derive(Debug)]
+pub struct ReloadableSettings {
+    heartbeat_failure_threshold: AtomicU64,
+    cron_interval_ms: AtomicU64,
+    trash_retention_ms: AtomicU64,
+}
+
+impl ReloadableSettings {
+    pub fn new(config: &MasterConfig, common_config: &CommonConfig) -> Self {
+        let settings = Self {
+            heartbeat_failure_threshold: AtomicU64::new(0),
+            cron_interval_ms: AtomicU64::new(0),
+            trash_retention_ms: AtomicU64::new(0),
+        };
+        settings.set_heartbeat_failure_threshold(config.heartbeat_failure_threshold);
+        settings.set_cron_interval(config.cron_interval);
+        settings.set_trash_retention(common_config.trash_retention_secs);
+        settings
+    }
+
+    /// Missed heartbeats or pings after which a chunkserver or the leader is considered failed
+    pub fn heartbeat_failure_threshold(&self) -> u64 {
+        self.heartbeat_failure_threshold.load(Ordering::Relaxed)
+    }
+
+    pub fn set_heartbeat_failure_threshold(&self, threshold: u64) {
+        self.heartbeat_failure_threshold
+            .store(threshold, Ordering::Relaxed);
+    }
+
+    /// Interval of the periodic tasks of the leader, e.g. the heartbeat checker
+    pub fn cron_interval(&self) -> Duration {
+        Duration::from_millis(self.cron_interval_ms.load(Ordering::Relaxed))
+    }
+
+    pub fn set_cron_interval(&self, interval: Duration) {
+        self.cron_interval_ms
+            .store(interval.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// How long deleted files stay in the trash, zero if they are deleted right away
+    pub fn trash_retention(&self) -> Duration {
+        Duration::from_millis(self.trash_retention_ms.load(Ordering::Relaxed))
+    }
+
+    pub fn set_trash_retention(&self, retention: Duration) {
+        self.trash_retention_ms
+            .store(retention.as_millis() as u64, Ordering::Relaxed);
+    }
+}
+
+/// Re-reads the config file of a master and applies the changed settings that allow it
+#[derive(Debug)]
+pub struct ConfigReloader {
+    config_path: String,
+    log_handle: LogHandle,
+    current: Config,                        // Settings in effect
+    metrics_server: Option<JoinHandle<()>>, // Serves the metrics at current.master.metrics_addr
+    registry: Registry,                     // Metrics served by the metrics server
+}
+
+impl ConfigReloader {
+    pub fn new(
+        config_path: &str,
+        log_handle: LogHandle,
+        current: Config,
+        metrics_server: Option<JoinHandle<()>>,
+        registry: Registry,
+    ) -> Self {
+        Self {
+            config_path: config_path.to_string(),
+            log_handle,
+            current,
+            metrics_server,
+            registry,
+        }
+    }
+
+    /// Load the config file again and apply changes of log_level, heartbeat_failure_threshold,
+    /// cron_interval, trash_retention_secs and metrics_addr.
+    ///
+    /// Changes of other settings are logged and reported as ignored, they need a restart. An
+    /// invalid config file changes nothing.
+    pub fn reload(&mut self, settings: &ReloadableSettings) -> Result<ReloadConfigResponse> {
+        info!("[Reload] Reloading config file '{}'", self.config_path);
+        let new = load_config(&self.config_path)?;
+        let mut response = ReloadConfigResponse::default();
+        for (key, old_value, new_value) in changed_settings(&self.current, &new)? {
+            let change = format!("{}: {} -> {}", key, old_value, new_value);
+            match key.as_str() {
+                "common.log_level" => {
+                    self.log_handle.set_level(&new.common.log_level)?;
+                    self.current.common.log_level = new.common.log_level.clone();
+                }
+                "master.heartbeat_failure_threshold" => {
+                    settings
+                        .set_heartbeat_failure_threshold(new.master.heartbeat_failure_threshold);
+                    self.current.master.heartbeat_failure_threshold =
+                        new.master.heartbeat_failure_threshold;
+                }
+                "master.cron_interval" => {
+                    settings.set_cron_interval(new.master.cron_interval);
+                    self.current.master.cron_interval = new.master.cron_interval;
+                }
+                "common.trash_retention_secs" => {
+                    settings.set_trash_retention(new.common.trash_retention_secs);
+                    self.current.common.trash_retention_secs = new.common.trash_retention_secs;
+                }
+                "master.metrics_addr" => {
+                    if let Some(metrics_server) = self.metrics_server.take() {
+                        metrics_server.abort();
+                        info!("[Reload] Stopped serving metrics");
+                    }
+                    if !new.master.metrics_addr.is_empty() {
+                        self.metrics_server = start_metrics_server(
+                            &new.master.metrics_addr,
+                            self.registry.clone(),
+                            None,
+                        );
+                    }
+                    self.current.master.metrics_addr = new.master.metrics_addr.clone();
+                }
+                _ => {
+                    warn!(
+                        "[Reload] Ignoring {}, restart the master to apply it",
+                        change
+                    );
+                    response.ignored.push(change);
+                    continue;
+                }
+            }
+            info!("[Reload] Applied {}", change);
+            response.applied.push(change);
+        }
+        if response.applied.is_empty() && response.ignored.is_empty() {
+            info!("[Reload] No settings changed");
+        }
+        Ok(response)
+    }
+}
+
+/// Settings of the common and master sections that differ between two configs, as
+/// (section.key, old value, new value)
+fn changed_settings(old: &Config, new: &Config) -> Result<Vec<(String, String, String)>> {
+    let to_value = |config: &Config| {
+        toml::Value::try_from(config)
+            .map_err(|e| Error::Config(format!("Failed to compare configs: {}", e)))
+    };
+    let (old, new) = (to_value(old)?, to_value(new)?);
+    let mut changes = Vec::new();
+    for section in ["common", "master"] {
+        let (Some(old_table), Some(new_table)) = (
+            old.get(section).and_then(toml::Value::as_table),
+            new.get(section).and_then(toml::Value::as_table),
+        ) else {
+            continue;
+        };
+        for (key, new_value) in new_table {
+            match old_table.get(key) {
+                Some(old_value) if old_value == new_value => {}
+                old_value => changes.push((
+                    format!("{}.{}", section, key),
+                    old_value.map_or_else(String::new, ToString::to_string),
+                    new_value.to_string(),
+                )),
+            }
+        }
+    }
+    Ok(changes)
+}
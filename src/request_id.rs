@@ -0,0 +1,97 @@
+// Request IDs correlating the log lines of one client operation across all nodes
+use futures::future::BoxFuture;
+use std::task::{Context, Poll};
+use tonic::metadata::MetadataValue;
+use tonic::Request;
+use tower::{Layer, Service};
+use tracing::{info_span, Instrument};
+use uuid::Uuid;
+
+/// Metadata key carrying the ID of the client operation a request belongs to
+pub const REQUEST_ID_KEY: &str = "request-id";
+
+/// ID for a new logical operation
+pub fn new_request_id() -> String {
+    Uuid::new_v4().to_string()
+}
+
+/// Wrap `message` in a request carrying `request_id`
+pub fn request_with_id<T>(message: T, request_id: &str) -> Request<T> {
+    let mut request = Request::new(message);
+    if let Ok(value) = MetadataValue::try_from(request_id) {
+        request.metadata_mut().insert(REQUEST_ID_KEY, value);
+    }
+    request
+}
+
+/// ID of an incoming request, assigned by `RequestIdLayer` if the sender set none
+pub fn request_id<T>(request: &Request<T>) -> String {
+    request
+        .metadata()
+        .get(REQUEST_ID_KEY)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Server middleware handling each RPC in a span named after its request ID, so that every log
+/// line of the handler carries the ID. Requests without an ID are assigned a new one.
+///
+/// The ID is echoed in the response headers, so clients also find it in the metadata of error
+/// statuses.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RequestIdLayer;
+
+impl<S> Layer<S> for RequestIdLayer {
+    type Service = RequestIdService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestIdService { inner }
+    }
+}
+
+/// Service added by `RequestIdLayer`
+#[derive(Clone, Debug)]
+pub struct RequestIdService<S> {
+    inner: S,
+}
+
+impl<S, B, ResBody> Service<http::Request<B>> for RequestIdService<S>
+where
+    S: Service<http::Request<B>, Response = http::Response<ResBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: http::Request<B>) -> Self::Future {
+        let request_id = match request.headers().get(REQUEST_ID_KEY) {
+            Some(value) => value.clone(),
+            None => {
+                let value = http::HeaderValue::from_str(&new_request_id())
+                    .expect("UUIDs are valid header values");
+                request.headers_mut().insert(REQUEST_ID_KEY, value.clone());
+                value
+            }
+        };
+        let span = info_span!(
+            "rpc",
+            request_id = request_id.to_str().unwrap_or_default(),
+            method = request.uri().path()
+        );
+        let response = self.inner.call(request);
+        Box::pin(
+            async move {
+                let mut response = response.await?;
+                response.headers_mut().insert(REQUEST_ID_KEY, request_id);
+                Ok(response)
+            }
+            .instrument(span),
+        )
+    }
+}
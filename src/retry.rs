@@ -0,0 +1,169 @@
+// Retries of client RPCs that failed for a transient reason, e.g. a chunkserver restarting or the
+// masters electing a new leader
+use rand::Rng;
+use std::fmt::Display;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tonic::{Code, Status};
+use tracing::debug;
+
+use crate::config::ClientConfig;
+use crate::error::Error;
+use crate::util::OVERLOADED_KEY;
+
+/// How often and for how long an RPC is retried
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,         // Attempts including the first one
+    pub initial_backoff: Duration, // Wait before the first retry, doubled with every further one
+    pub max_backoff: Duration,     // Longest wait between two attempts
+    pub deadline: Duration,        // No retry is started after this time since the first attempt
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(&ClientConfig::default())
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(config: &ClientConfig) -> Self {
+        Self {
+            max_attempts: config.retry_attempts,
+            initial_backoff: config.retry_initial_backoff,
+            max_backoff: config.retry_max_backoff,
+            deadline: config.retry_deadline,
+        }
+    }
+
+    /// Send every request only once
+    pub fn never() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// Retry for up to `deadline`, however many attempts that takes
+    pub fn until(deadline: Duration) -> Self {
+        Self {
+            max_attempts: u32::MAX,
+            deadline,
+            ..Self::default()
+        }
+    }
+
+    /// Start counting the attempts of an operation
+    pub fn backoff(&self) -> Backoff<'_> {
+        Backoff {
+            policy: self,
+            attempts: 1,
+            delay: self.initial_backoff,
+            started: Instant::now(),
+        }
+    }
+}
+
+/// Attempts of one operation and the wait before the next one
+pub struct Backoff<'a> {
+    policy: &'a RetryPolicy,
+    attempts: u32,
+    delay: Duration,
+    started: Instant,
+}
+
+impl Backoff<'_> {
+    /// Wait before the next attempt of `operation`, which failed with `error`.
+    ///
+    /// Returns false without waiting if the policy allows no further attempt.
+    pub async fn wait(&mut self, operation: &str, error: &(dyn Display + Sync)) -> bool {
+        if self.attempts >= self.policy.max_attempts {
+            return false;
+        }
+        let delay = jitter(self.delay);
+        if self.started.elapsed() + delay > self.policy.deadline {
+            return false;
+        }
+        self.attempts += 1;
+        debug!(
+            "{} failed ({}), retrying in {:?} (attempt {} of {})",
+            operation, error, delay, self.attempts, self.policy.max_attempts
+        );
+        tokio::time::sleep(delay).await;
+        self.delay = (self.delay * 2).min(self.policy.max_backoff);
+        true
+    }
+}
+
+/// A random delay between half and all of `delay`, so that clients failing at the same time
+/// don't retry in lockstep
+fn jitter(delay: Duration) -> Duration {
+    let half = delay / 2;
+    half + half.mul_f64(rand::thread_rng().gen::<f64>())
+}
+
+/// Failures that may go away when the request is sent again
+pub trait Transient {
+    fn is_transient(&self) -> bool;
+}
+
+impl Transient for Status {
+    /// Unreachable servers and timeouts, as opposed to rejections such as `NotFound`,
+    /// `InvalidArgument` or `Unauthenticated`
+    fn is_transient(&self) -> bool {
+        self.code() == Code::Unavailable || is_timeout(self) || is_overloaded(self)
+    }
+}
+
+impl Transient for Error {
+    fn is_transient(&self) -> bool {
+        match self {
+            Error::Transport(_) | Error::Timeout(_) => true,
+            Error::Rpc(status) => status.is_transient(),
+            _ => false,
+        }
+    }
+}
+
+/// Message of the status tonic fails a request with once its timeout expired
+const TIMEOUT_EXPIRED: &str = "Timeout expired";
+
+/// Whether a request ran out of time. Servers report this as `DeadlineExceeded`, while tonic
+/// reports a request the client gave up on after its timeout as `Cancelled`.
+pub fn is_timeout(status: &Status) -> bool {
+    match status.code() {
+        Code::DeadlineExceeded => true,
+        Code::Cancelled => status.message() == TIMEOUT_EXPIRED,
+        _ => false,
+    }
+}
+
+/// Whether a chunkserver rejected a write as it is running as many as it allows at once
+pub fn is_overloaded(status: &Status) -> bool {
+    status.code() == Code::ResourceExhausted && status.metadata().get(OVERLOADED_KEY).is_some()
+}
+
+/// Run `call` until it succeeds, fails for a permanent reason or `policy` allows no further
+/// attempt. Only for requests that can safely be applied twice.
+pub async fn retry<T, E, F, Fut>(
+    policy: &RetryPolicy,
+    operation: &str,
+    mut call: F,
+) -> std::result::Result<T, E>
+where
+    E: Transient + Display + Sync,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = std::result::Result<T, E>>,
+{
+    let mut backoff = policy.backoff();
+    loop {
+        match call().await {
+            Err(e) if e.is_transient() => {
+                if !backoff.wait(operation, &e).await {
+                    return Err(e);
+                }
+            }
+            result => return result,
+        }
+    }
+}
@@ -0,0 +1,105 @@
+// Graceful shutdown of the master and chunkserver on SIGINT or SIGTERM
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+
+/// Resolves once the process is asked to stop, by Ctrl-C (SIGINT) or, on Unix, by SIGTERM,
+/// e.g. from `kill <pid>`
+pub async fn signal() {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("[Shutdown] Failed to listen for SIGINT: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminations) => {
+                terminations.recv().await;
+            }
+            Err(e) => {
+                error!("[Shutdown] Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = interrupt => info!("[Shutdown] Received SIGINT, shutting down"),
+        () = terminate => info!("[Shutdown] Received SIGTERM, shutting down"),
+    }
+}
+
+/// Background tasks of a server, stopped and awaited when it shuts down
+#[derive(Debug)]
+pub struct BackgroundTasks {
+    stop: watch::Sender<bool>,
+    handles: Mutex<Vec<(&'static str, JoinHandle<()>)>>,
+}
+
+impl Default for BackgroundTasks {
+    fn default() -> Self {
+        Self {
+            stop: watch::channel(false).0,
+            handles: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl BackgroundTasks {
+    /// Run `task` until it returns or the server shuts down. It is dropped at the await point
+    /// it is waiting at, so it must not leave anything half written there.
+    pub fn spawn<F>(&self, name: &'static str, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let stopped = self.stopped();
+        self.spawn_graceful(name, async move {
+            tokio::select! {
+                () = task => {}
+                () = stopped => {}
+            }
+        });
+    }
+
+    /// Run `task`, which returns by itself once `stopped` resolved, e.g. after writing what
+    /// it has queued
+    pub fn spawn_graceful<F>(&self, name: &'static str, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let handle = tokio::spawn(task);
+        self.handles.lock().unwrap().push((name, handle));
+    }
+
+    /// Resolves once the server shuts down
+    pub fn stopped(&self) -> impl Future<Output = ()> + Send + 'static {
+        let mut stop = self.stop.subscribe();
+        async move {
+            // Only fails once the tasks are dropped, which also means stopping
+            let _ = stop.wait_for(|stopped| *stopped).await;
+        }
+    }
+
+    /// Stop all tasks and wait up to `timeout` for them to return
+    pub async fn shutdown(&self, timeout: Duration) {
+        self.stop.send_replace(true);
+        let handles = std::mem::take(&mut *self.handles.lock().unwrap());
+        let deadline = tokio::time::Instant::now() + timeout;
+        for (name, handle) in handles {
+            match tokio::time::timeout_at(deadline, handle).await {
+                Ok(Ok(())) => debug!("[Shutdown] Stopped the {}", name),
+                Ok(Err(e)) => error!("[Shutdown] The {} failed: {}", name, e),
+                Err(_) => warn!("[Shutdown] The {} did not stop within {:?}", name, timeout),
+            }
+        }
+    }
+}
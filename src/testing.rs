@@ -0,0 +1,486 @@
+// In-process test clusters of a master and chunkservers listening on ephemeral ports, for
+// integration tests of the client, master and chunkserver together
+use futures::Stream;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::runtime::Runtime;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tonic::transport::Server;
+use uuid::Uuid;
+
+use crate::auth::{add_user, Role};
+use crate::chunkserver_service::ChunkService;
+use crate::client::Client;
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::master_service::MasterService;
+use crate::proto::chunk::chunk_server::ChunkServer;
+use crate::proto::master::master_server::MasterServer;
+use crate::request_id::RequestIdLayer;
+use crate::util::server_tls_config;
+
+/// How long `TestCluster` waits for a new chunkserver to send its first heartbeat
+const JOIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A master and chunkservers running inside the current process, each on a runtime of its own,
+/// with their data in a temporary directory that is removed when the cluster is dropped.
+///
+/// ```no_run
+/// # async fn example() -> rustfs::error::Result<()> {
+/// use rustfs::testing::TestCluster;
+///
+/// let mut cluster = TestCluster::start_with(3, |config| {
+///     config.common.chunk_size = 1024;
+///     config.common.replication_factor = 2;
+/// })
+/// .await?;
+/// let mut client = cluster.client().await?;
+/// cluster.kill_chunkserver(0);
+/// cluster.shutdown().await
+/// # }
+/// ```
+pub struct TestCluster {
+    config: Config,
+    dir: PathBuf,
+    masters: Vec<Option<Node<Arc<MasterService>>>>, // The first one starts as the leader, None once killed
+    chunkservers: Vec<Option<Node<ChunkService>>>,  // None once stopped or killed
+    chunkserver_addrs: Vec<String>, // Address of each chunkserver, kept to restart it on
+}
+
+impl TestCluster {
+    /// Start a master and `chunkservers` chunkservers with the default settings
+    pub async fn start(chunkservers: usize) -> Result<Self> {
+        Self::start_with(chunkservers, |_| {}).await
+    }
+
+    /// Start a master and `chunkservers` chunkservers with the settings changed by `configure`,
+    /// e.g. `chunk_size` or `replication_factor`. `master_addrs` is always set to the master.
+    pub async fn start_with(
+        chunkservers: usize,
+        configure: impl FnOnce(&mut Config),
+    ) -> Result<Self> {
+        Self::start_with_shadows(0, chunkservers, configure).await
+    }
+
+    /// Start a leading master, `shadows` shadow masters following it and `chunkservers`
+    /// chunkservers, with the settings changed by `configure`. `master_addrs` is always set to
+    /// the masters, the leader first.
+    pub async fn start_with_shadows(
+        shadows: usize,
+        chunkservers: usize,
+        configure: impl FnOnce(&mut Config),
+    ) -> Result<Self> {
+        let dir = std::env::temp_dir().join(format!("rustfs-test-{}", Uuid::new_v4()));
+        let mut config = Config::default();
+        config.master.data_path = path_in(&dir, "master");
+        config.master.authentication_file_path = path_in(&dir, "auth_data.json");
+        configure(&mut config);
+        let listeners = (0..=shadows)
+            .map(|_| bind())
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let master_addrs = listeners
+            .iter()
+            .map(|listener| Ok(listener.local_addr()?.to_string()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        config.common.master_addrs = master_addrs.clone();
+        config.validate()?;
+        config.common.tls.load()?;
+
+        let masters = listeners
+            .into_iter()
+            .enumerate()
+            .map(|(index, listener)| Ok(Some(start_master(&config, &dir, index, listener)?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut cluster = Self {
+            config,
+            dir,
+            masters,
+            chunkservers: Vec::new(),
+            chunkserver_addrs: Vec::new(),
+        };
+        for _ in 0..chunkservers {
+            cluster.add_chunkserver().await?;
+        }
+        Ok(cluster)
+    }
+
+    /// Start another chunkserver and wait until the master received its first heartbeat and the
+    /// chunkserver the response. Returns its index.
+    pub async fn add_chunkserver(&mut self) -> Result<usize> {
+        let listener = bind()?;
+        self.chunkserver_addrs
+            .push(listener.local_addr()?.to_string());
+        self.chunkservers.push(None);
+        let index = self.chunkservers.len() - 1;
+        self.run_chunkserver(index, listener).await?;
+        Ok(index)
+    }
+
+    /// Start the stopped or killed `index`-th chunkserver again, on its address and with the
+    /// chunks it stored, and wait until it joined the cluster like `add_chunkserver`
+    ///
+    /// Panics if it is running.
+    pub async fn restart_chunkserver(&mut self, index: usize) -> Result<()> {
+        assert!(
+            self.chunkservers[index].is_none(),
+            "Chunkserver {} is running",
+            index
+        );
+        let listener = rebind(&self.chunkserver_addrs[index])?;
+        self.run_chunkserver(index, listener).await
+    }
+
+    /// Serve the `index`-th chunkserver on `listener` and wait until it joined the cluster
+    async fn run_chunkserver(
+        &mut self,
+        index: usize,
+        listener: std::net::TcpListener,
+    ) -> Result<()> {
+        let addr = self.chunkserver_addrs[index].clone();
+        let service = ChunkService::new(
+            &addr,
+            &path_in(&self.dir, &addr.replace(':', "_")),
+            self.config.chunkserver.clone(),
+            self.config.common.clone(),
+        );
+        service.prepare_data_dir()?;
+        let node = Node::start(
+            "chunkserver",
+            addr.clone(),
+            service,
+            |service, stopped| async move {
+                service.join_cluster().await?;
+                serve_chunkserver(listener, service, stopped).await
+            },
+        )?;
+        let epoch = Arc::clone(&node.service.epoch);
+        self.chunkservers[index] = Some(node);
+
+        // Joined once the chunkserver also learned the leader epoch from the heartbeat response
+        let joined = async {
+            while !self.master().healthy_servers().await.contains(&addr)
+                || epoch.load(Ordering::SeqCst) < self.master().epoch.load(Ordering::SeqCst)
+            {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::time::timeout(JOIN_TIMEOUT, joined)
+            .await
+            .map_err(|_| {
+                Error::Timeout(format!(
+                    "Chunkserver {} did not join the cluster within {:?}",
+                    addr, JOIN_TIMEOUT
+                ))
+            })?;
+        Ok(())
+    }
+
+    /// Settings of all nodes, with `master_addrs` listing the masters
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Write the settings to a config file in the cluster's directory and return its path, e.g.
+    /// to run the client binary against the cluster
+    pub fn write_config(&self) -> Result<String> {
+        let path = path_in(&self.dir, "rustfs.toml");
+        let settings = toml::Value::try_from(&self.config)
+            .map_err(|e| Error::Config(format!("Failed to serialize the settings: {}", e)))?;
+        std::fs::create_dir_all(&self.dir)?;
+        std::fs::write(&path, settings.to_string())?;
+        Ok(path)
+    }
+
+    /// The master started as the leader, e.g. to inspect its metadata
+    ///
+    /// Panics if it was killed.
+    pub fn master(&self) -> &Arc<MasterService> {
+        &self.running_master(0).service
+    }
+
+    /// Address of the master started as the leader
+    ///
+    /// Panics if it was killed.
+    pub fn master_addr(&self) -> &str {
+        &self.running_master(0).addr
+    }
+
+    /// The masters that are still running, the one started as the leader first if it is
+    pub fn masters(&self) -> Vec<&Arc<MasterService>> {
+        self.masters
+            .iter()
+            .flatten()
+            .map(|node| &node.service)
+            .collect()
+    }
+
+    /// Kill the `index`-th master, 0 being the one started as the leader, like a crashed
+    /// process: the shadow masters only notice once it misses their pings
+    ///
+    /// Panics if it was already killed.
+    pub fn kill_master(&mut self, index: usize) {
+        self.running_master(index);
+        self.masters[index] = None;
+    }
+
+    /// Start the killed `index`-th master again, on its address and with the state it kept in
+    /// its data directory. The one started as the leader starts as the leader again.
+    ///
+    /// Panics if it is running.
+    pub fn restart_master(&mut self, index: usize) -> Result<()> {
+        assert!(self.masters[index].is_none(), "Master {} is running", index);
+        let listener = rebind(&self.config.common.master_addrs[index])?;
+        self.masters[index] = Some(start_master(&self.config, &self.dir, index, listener)?);
+        Ok(())
+    }
+
+    /// The chunkserver started as the `index`-th one
+    ///
+    /// Panics if it was stopped or killed.
+    pub fn chunkserver(&self, index: usize) -> &ChunkService {
+        &self.running_chunkserver(index).service
+    }
+
+    /// Addresses of the chunkservers that are still running
+    pub fn chunkserver_addrs(&self) -> Vec<String> {
+        self.chunkservers
+            .iter()
+            .flatten()
+            .map(|node| node.addr.clone())
+            .collect()
+    }
+
+    /// A client connected to the master
+    pub async fn client(&self) -> Result<Client> {
+        Client::from_config(self.config.clone()).await
+    }
+
+    /// Add a user with `role` to the authentication file of the masters, e.g. for clusters with
+    /// `use_authentication`
+    pub fn add_user(&self, username: &str, password: &str, role: Role) -> Result<()> {
+        add_user(
+            &self.config.master.authentication_file_path,
+            username,
+            password,
+            role,
+        )
+    }
+
+    /// Kill the `index`-th chunkserver like a crashed process: its connections are dropped and
+    /// the master only notices once it misses its heartbeats
+    ///
+    /// Panics if it was already stopped or killed.
+    pub fn kill_chunkserver(&mut self, index: usize) {
+        self.running_chunkserver(index);
+        self.chunkservers[index] = None;
+    }
+
+    /// Shut the `index`-th chunkserver down gracefully, see `ChunkService::shutdown`
+    ///
+    /// Panics if it was already stopped or killed.
+    pub async fn stop_chunkserver(&mut self, index: usize) -> Result<()> {
+        self.running_chunkserver(index);
+        let node = self.chunkservers[index].take().expect("Checked above");
+        node.stop().await
+    }
+
+    /// Shut all chunkservers and then the masters down gracefully and remove the data
+    pub async fn shutdown(mut self) -> Result<()> {
+        let mut result = Ok(());
+        for node in std::mem::take(&mut self.chunkservers).into_iter().flatten() {
+            result = result.and(node.stop().await);
+        }
+        for node in std::mem::take(&mut self.masters).into_iter().flatten() {
+            result = result.and(node.stop().await);
+        }
+        result
+    }
+
+    fn running_master(&self, index: usize) -> &Node<Arc<MasterService>> {
+        self.masters
+            .get(index)
+            .and_then(Option::as_ref)
+            .unwrap_or_else(|| panic!("Master {} is not running", index))
+    }
+
+    fn running_chunkserver(&self, index: usize) -> &Node<ChunkService> {
+        self.chunkservers
+            .get(index)
+            .and_then(Option::as_ref)
+            .unwrap_or_else(|| panic!("Chunkserver {} is not running", index))
+    }
+}
+
+impl Drop for TestCluster {
+    fn drop(&mut self) {
+        // Kill the nodes before removing the directory they write to
+        self.chunkservers.clear();
+        self.masters.clear();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// A master or chunkserver serving on a runtime of its own, which is shut down on drop
+struct Node<S> {
+    addr: String,
+    service: S,
+    runtime: Option<Runtime>,
+    stop: Option<oneshot::Sender<()>>,
+    served: Option<JoinHandle<Result<()>>>,
+}
+
+impl<S: Clone + Send + 'static> Node<S> {
+    /// Run `serve` on a new runtime, passing it a clone of `service` and a receiver that
+    /// resolves once the node is to stop
+    fn start<F, Fut>(name: &str, addr: String, service: S, serve: F) -> Result<Self>
+    where
+        F: FnOnce(S, oneshot::Receiver<()>) -> Fut,
+        Fut: std::future::Future<Output = Result<()>> + Send + 'static,
+    {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .thread_name(format!("{}-{}", name, addr))
+            .enable_all()
+            .build()?;
+        let (stop, stopped) = oneshot::channel();
+        let served = runtime.spawn(serve(service.clone(), stopped));
+        Ok(Self {
+            addr,
+            service,
+            runtime: Some(runtime),
+            stop: Some(stop),
+            served: Some(served),
+        })
+    }
+
+    /// Stop accepting requests, wait for the running ones and shut the service down
+    async fn stop(mut self) -> Result<()> {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        match self.served.take() {
+            Some(served) => served.await.map_err(std::io::Error::from)?,
+            None => Ok(()),
+        }
+    }
+}
+
+impl<S> Drop for Node<S> {
+    fn drop(&mut self) {
+        if let Some(runtime) = self.runtime.take() {
+            // Dropping a runtime blocks, which is not allowed inside the runtime of a test
+            runtime.shutdown_background();
+        }
+    }
+}
+
+/// Start the `index`-th master of a cluster in `dir` serving on `listener`, the first one as the
+/// leader
+fn start_master(
+    config: &Config,
+    dir: &std::path::Path,
+    index: usize,
+    listener: std::net::TcpListener,
+) -> Result<Node<Arc<MasterService>>> {
+    let master_addrs = &config.common.master_addrs;
+    let addr = &master_addrs[index];
+    // Every master keeps its metadata in a directory of its own
+    let mut master_config = config.master.clone();
+    if index > 0 {
+        master_config.data_path = path_in(dir, &format!("master-{}", index));
+    }
+    let service = Arc::new(MasterService::new(
+        addr,
+        master_config,
+        config.common.clone(),
+        index == 0,
+        &master_addrs[0],
+    ));
+    Node::start(
+        "master",
+        addr.clone(),
+        service,
+        |service, stopped| async move {
+            service.start_tasks().await;
+            serve_master(listener, service, stopped).await
+        },
+    )
+}
+
+/// Serve the master until `stopped` resolves, then shut it down, like the master binary
+async fn serve_master(
+    listener: std::net::TcpListener,
+    service: Arc<MasterService>,
+    stopped: oneshot::Receiver<()>,
+) -> Result<()> {
+    let mut server = Server::builder();
+    if let Some(tls_config) = server_tls_config(&service.common_config.tls)? {
+        server = server.tls_config(tls_config)?;
+    }
+    server
+        .layer(RequestIdLayer)
+        .add_service(MasterServer::new(Arc::clone(&service)))
+        .serve_with_incoming_shutdown(incoming(listener)?, async {
+            let _ = stopped.await;
+        })
+        .await?;
+    service.shutdown().await;
+    Ok(())
+}
+
+/// Serve a chunkserver until `stopped` resolves, then shut it down, like the chunkserver binary
+async fn serve_chunkserver(
+    listener: std::net::TcpListener,
+    service: ChunkService,
+    stopped: oneshot::Receiver<()>,
+) -> Result<()> {
+    let mut server = Server::builder();
+    if let Some(tls_config) = server_tls_config(&service.common_config.tls)? {
+        server = server.tls_config(tls_config)?;
+    }
+    server
+        .layer(RequestIdLayer)
+        .add_service(ChunkServer::new(service.clone()))
+        .serve_with_incoming_shutdown(incoming(listener)?, async {
+            let _ = stopped.await;
+        })
+        .await?;
+    service.shutdown().await;
+    Ok(())
+}
+
+/// Listen on a free port of the loopback interface
+fn bind() -> std::io::Result<std::net::TcpListener> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
+/// Listen on `addr` again after a node listening on it stopped, whose closed connections may
+/// still hold the port
+fn rebind(addr: &str) -> std::io::Result<std::net::TcpListener> {
+    let socket = tokio::net::TcpSocket::new_v4()?;
+    socket.set_reuseaddr(true)?;
+    socket.bind(addr.parse().map_err(std::io::Error::other)?)?;
+    socket.listen(1024)?.into_std()
+}
+
+/// Connections accepted by `listener`, which is moved to the runtime this is called on
+fn incoming(
+    listener: std::net::TcpListener,
+) -> std::io::Result<impl Stream<Item = std::io::Result<TcpStream>>> {
+    let listener = TcpListener::from_std(listener)?;
+    Ok(futures::stream::unfold(listener, |listener| async move {
+        let connection = listener.accept().await.map(|(stream, _)| stream);
+        Some((connection, listener))
+    }))
+}
+
+fn path_in(dir: &std::path::Path, name: &str) -> String {
+    dir.join(name).to_string_lossy().into_owned()
+}
@@ -0,0 +1,67 @@
+// Token bucket limiting the bandwidth of chunk transfers
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Limits the bytes per second passed through it, shared by all tasks transferring at once.
+///
+/// The bucket starts empty and holds at most one second's worth of bytes, so a transfer of N
+/// bytes takes at least about N / `bytes_per_sec` seconds, however many tasks it is split into.
+#[derive(Debug)]
+pub struct RateLimiter {
+    bytes_per_sec: u64, // 0 for no limit
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64, // Bytes that may pass right away, negative if waiting transfers took them in advance
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        RateLimiter {
+            bytes_per_sec,
+            bucket: Mutex::new(Bucket {
+                tokens: 0.0,
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        self.bytes_per_sec == 0
+    }
+
+    /// Wait until `bytes` more bytes may be transferred.
+    ///
+    /// The bytes are taken from the bucket right away, so concurrent callers queue up behind
+    /// each other instead of all passing once the bucket has refilled.
+    pub async fn acquire(&self, bytes: u64) {
+        if let Some(wait) = self.reserve(bytes) {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Take `bytes` from the bucket and return how long to wait until they are covered
+    fn reserve(&self, bytes: u64) -> Option<Duration> {
+        if self.is_unlimited() || bytes == 0 {
+            return None;
+        }
+        let rate = self.bytes_per_sec as f64;
+        let mut bucket = self.bucket.lock().unwrap();
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(rate);
+        bucket.refilled_at = now;
+        bucket.tokens -= bytes as f64;
+        (bucket.tokens < 0.0).then(|| Duration::from_secs_f64(-bucket.tokens / rate))
+    }
+}
+
+impl Default for RateLimiter {
+    /// A limiter letting every transfer pass right away
+    fn default() -> Self {
+        RateLimiter::new(0)
+    }
+}
@@ -0,0 +1,81 @@
+// Session tokens issued by the master and validated by the chunkservers on their own
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::auth::Role;
+use crate::cluster_auth::{derive_key, from_hex, to_hex};
+use crate::error::{Error, Result};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Who a session token was issued to, with which role and until when
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TokenClaims {
+    pub username: String,
+    pub role: Role,
+    pub expires_at: u64,    // Seconds since UNIX epoch
+    pub session_start: u64, // When the user authenticated, kept by renewals (seconds since UNIX epoch)
+}
+
+/// HMAC of a token's claims, keyed with a key derived from the cluster secret for session tokens
+/// alone, so that the proofs of internal requests cannot be turned into tokens
+fn mac(secret: &str, claims: &TokenClaims) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(&derive_key(secret, "session token"))
+        .expect("HMAC accepts keys of any length");
+    mac.update(
+        format!(
+            "{}.{}.{}.{}",
+            claims.expires_at,
+            claims.session_start,
+            claims.role.as_str(),
+            claims.username
+        )
+        .as_bytes(),
+    );
+    mac
+}
+
+/// Sign a token with the given claims, with a key derived from the `cluster_secret` shared by the
+/// master and the chunkservers.
+///
+/// The token reads "<expires_at>.<session_start>.<role>.<HMAC-SHA256 in hex>.<username>", the
+/// username last as it may contain dots.
+pub fn issue_token(secret: &str, claims: &TokenClaims) -> String {
+    let signature = to_hex(&mac(secret, claims).finalize().into_bytes());
+    format!(
+        "{}.{}.{}.{}.{}",
+        claims.expires_at,
+        claims.session_start,
+        claims.role.as_str(),
+        signature,
+        claims.username
+    )
+}
+
+/// Check the signature of a token and return its claims. Whether it expired is left to the
+/// caller.
+pub fn verify_token(secret: &str, token: &str) -> Result<TokenClaims> {
+    let invalid = || Error::Auth("Invalid session token".to_string());
+    let mut parts = token.splitn(5, '.');
+    let (Some(expires_at), Some(session_start), Some(role), Some(signature), Some(username)) = (
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+    ) else {
+        return Err(invalid());
+    };
+    let claims = TokenClaims {
+        username: username.to_string(),
+        role: role.parse().map_err(|_| invalid())?,
+        expires_at: expires_at.parse().map_err(|_| invalid())?,
+        session_start: session_start.parse().map_err(|_| invalid())?,
+    };
+    let signature = from_hex(signature).ok_or_else(invalid)?;
+    // Compares in constant time
+    mac(secret, &claims)
+        .verify_slice(&signature)
+        .map_err(|_| invalid())?;
+    Ok(claims)
+}
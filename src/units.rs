@@ -0,0 +1,204 @@
+// Durations and sizes in the config file, e.g. "5s", "2m", "64MiB" or "1GB". Units are matched
+// with their case, so that "1M" is not taken for a minute or for a megabyte.
+use serde::de::{self, Visitor};
+use serde::{Deserializer, Serializer};
+use std::fmt;
+use std::time::Duration;
+
+const DURATION_UNITS: [(&str, f64); 6] = [
+    ("ms", 0.001),
+    ("s", 1.0),
+    ("m", 60.0),
+    ("min", 60.0),
+    ("h", 3600.0),
+    ("d", 86400.0),
+];
+
+const SIZE_UNITS: [(&str, u64); 9] = [
+    ("B", 1),
+    ("KB", 1000),
+    ("MB", 1000 * 1000),
+    ("GB", 1000 * 1000 * 1000),
+    ("TB", 1000 * 1000 * 1000 * 1000),
+    ("KiB", 1 << 10),
+    ("MiB", 1 << 20),
+    ("GiB", 1 << 30),
+    ("TiB", 1 << 40),
+];
+
+/// Split "1.5 GiB" into its number and its unit
+fn split_value(value: &str) -> Result<(f64, &str), String> {
+    let value = value.trim();
+    let unit_start = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-' || c == '+'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(unit_start);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("'{}' does not start with a number", value))?;
+    if number < 0.0 {
+        return Err(format!("'{}' is negative", value));
+    }
+    Ok((number, unit.trim()))
+}
+
+/// Multiplier of `unit` in `units`, or why there is none: the unit is unknown, or only known in
+/// another case
+fn find_unit<T: Copy>(units: &[(&str, T)], value: &str, unit: &str) -> Result<T, String> {
+    if let Some((_, multiplier)) = units.iter().find(|(name, _)| *name == unit) {
+        return Ok(*multiplier);
+    }
+    match units
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(unit))
+    {
+        Some((name, _)) => Err(format!(
+            "'{}' has a unit in the wrong case, did you mean \"{}\"?",
+            value.trim(),
+            name
+        )),
+        None => Err(format!("'{}' has an unknown unit", value.trim())),
+    }
+}
+
+/// Parse a duration such as "500ms", "5s", "1.5m" (or "1.5min"), "2h" or "7d", with the unit in
+/// lowercase. A bare "0" is accepted, other numbers need a unit.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let (number, unit) = split_value(value)?;
+    if unit.is_empty() && number == 0.0 {
+        return Ok(Duration::ZERO);
+    }
+    let seconds = if unit.is_empty() {
+        Err(format!("'{}' needs a unit", value.trim()))
+    } else {
+        find_unit(&DURATION_UNITS, value, unit)
+    }
+    .map_err(|e| format!("{}, expected ms, s, m, min, h or d (e.g., \"5s\")", e))?;
+    Duration::try_from_secs_f64(number * seconds)
+        .map_err(|_| format!("'{}' is too long", value.trim()))
+}
+
+/// Parse a size such as "512B", "64KiB", "1.5GiB" or "1GB" into bytes. Units are decimal (KB, MB,
+/// GB, TB) or binary (KiB, MiB, GiB, TiB), written in this case; a bare number is a number of
+/// bytes.
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let (number, unit) = split_value(value)?;
+    let multiplier = if unit.is_empty() {
+        1
+    } else {
+        find_unit(&SIZE_UNITS, value, unit)
+            .map_err(|e| format!("{}, expected B, KB, MB, GB, TB, KiB, MiB, GiB or TiB", e))?
+    };
+    let bytes = number * multiplier as f64;
+    if bytes.fract() != 0.0 {
+        return Err(format!("'{}' is not a whole number of bytes", value.trim()));
+    }
+    if bytes >= u64::MAX as f64 {
+        return Err(format!("'{}' is too large", value.trim()));
+    }
+    Ok(bytes as u64)
+}
+
+/// Shortest exact representation of a duration, e.g. "5m" for 300 seconds
+pub fn format_duration(duration: Duration) -> String {
+    if duration.subsec_nanos() != 0 {
+        return format!("{}ms", duration.as_millis());
+    }
+    let seconds = duration.as_secs();
+    match [("d", 86400), ("h", 3600), ("m", 60)]
+        .into_iter()
+        .find(|(_, unit)| seconds > 0 && seconds.is_multiple_of(*unit))
+    {
+        Some((name, unit)) => format!("{}{}", seconds / unit, name),
+        None => format!("{}s", seconds),
+    }
+}
+
+/// Shortest exact representation of a size in binary units, e.g. "64MiB"
+pub fn format_size(bytes: u64) -> String {
+    match [
+        ("TiB", 1 << 40),
+        ("GiB", 1 << 30),
+        ("MiB", 1 << 20),
+        ("KiB", 1 << 10),
+    ]
+    .into_iter()
+    .find(|(_, unit)| bytes > 0 && bytes.is_multiple_of(*unit))
+    {
+        Some((name, unit)) => format!("{}{}", bytes / unit, name),
+        None => format!("{}B", bytes),
+    }
+}
+
+/// Serde format of durations: a string such as "5s", or a bare integer counting seconds as
+/// written by earlier versions of the config file
+pub mod duration {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format_duration(*duration))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        deserializer.deserialize_any(DurationVisitor)
+    }
+
+    struct DurationVisitor;
+
+    impl Visitor<'_> for DurationVisitor {
+        type Value = Duration;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a duration such as \"5s\" or \"2m\", or a number of seconds")
+        }
+
+        fn visit_u64<E: de::Error>(self, seconds: u64) -> Result<Duration, E> {
+            Ok(Duration::from_secs(seconds))
+        }
+
+        fn visit_i64<E: de::Error>(self, seconds: i64) -> Result<Duration, E> {
+            u64::try_from(seconds)
+                .map(Duration::from_secs)
+                .map_err(|_| E::custom(format!("{} is negative", seconds)))
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<Duration, E> {
+            parse_duration(value).map_err(E::custom)
+        }
+    }
+}
+
+/// Serde format of sizes: a string such as "64MiB", or a bare integer counting bytes
+pub mod size {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(bytes: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format_size(*bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        deserializer.deserialize_any(SizeVisitor)
+    }
+
+    struct SizeVisitor;
+
+    impl Visitor<'_> for SizeVisitor {
+        type Value = u64;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a size such as \"64MiB\" or \"1GB\", or a number of bytes")
+        }
+
+        fn visit_u64<E: de::Error>(self, bytes: u64) -> Result<u64, E> {
+            Ok(bytes)
+        }
+
+        fn visit_i64<E: de::Error>(self, bytes: i64) -> Result<u64, E> {
+            u64::try_from(bytes).map_err(|_| E::custom(format!("{} is negative", bytes)))
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<u64, E> {
+            parse_size(value).map_err(E::custom)
+        }
+    }
+}
@@ -1,25 +1,251 @@
-use crate::proto::master::master_client::MasterClient;
+use std::fs;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use tonic::transport::{
+    Certificate, Channel, ClientTlsConfig, Endpoint, Identity, ServerTlsConfig,
+};
 use tracing::{info, warn};
 
-/// Connect to the master given the list of servers
+use crate::config::TlsConfig;
+use crate::error::{Error, Result};
+use crate::proto::master::master_client::MasterClient;
+use crate::proto::master::GetLeaderRequest;
+use crate::retry::RetryPolicy;
+
+/// Metadata key under which a shadow master names the leader when rejecting a mutating request
+pub const LEADER_ADDRESS_KEY: &str = "leader-address";
+
+/// Metadata key with which a chunkserver marks a write it rejected as it runs
+/// `max_concurrent_writes` already, as opposed to rejecting it for lack of disk space
+pub const OVERLOADED_KEY: &str = "overloaded";
+
+/// Addresses a master or chunkserver listens on and is reached at by other nodes and clients
+#[derive(Clone, Debug)]
+pub struct NodeAddresses {
+    pub bind: SocketAddr,
+    pub advertise: String,
+}
+
+/// Resolve the addresses of a node from its command line and config file.
+///
+/// The bind address is taken from `--bind-addr`, `-a` or the config, in this order, and the
+/// advertised address from `--advertise-addr`, `-a`, the config or else the bind address. A
+/// wildcard address such as 0.0.0.0 can be bound but not advertised.
+pub fn node_addresses(
+    address: Option<&str>,
+    bind_flag: Option<&str>,
+    advertise_flag: Option<&str>,
+    config_bind: &str,
+    config_advertise: &str,
+) -> Result<NodeAddresses> {
+    let non_empty = |value: &str| (!value.is_empty()).then(|| value.to_string());
+    let bind = bind_flag
+        .or(address)
+        .map(str::to_string)
+        .or_else(|| non_empty(config_bind))
+        .ok_or_else(|| {
+            Error::Config("No address given, pass -a <ADDR> or --bind-addr <ADDR>".to_string())
+        })?;
+    let advertise = advertise_flag
+        .or(address)
+        .map(str::to_string)
+        .or_else(|| non_empty(config_advertise))
+        .unwrap_or_else(|| bind.clone());
+
+    let bind = bind
+        .parse()
+        .map_err(|e| Error::Config(format!("Invalid bind address '{}': {}", bind, e)))?;
+    if !is_advertisable(&advertise) {
+        return Err(Error::Config(format!(
+            "Advertised address '{}' is not reachable by other nodes, pass --advertise-addr \
+             with a routable host:port",
+            advertise
+        )));
+    }
+    Ok(NodeAddresses { bind, advertise })
+}
+
+/// Whether `addr` is a host:port other nodes can connect to, i.e. not a wildcard address
+pub fn is_advertisable(addr: &str) -> bool {
+    let Some((host, port)) = addr.rsplit_once(':') else {
+        return false;
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    !host.is_empty()
+        && port.parse::<u16>().is_ok_and(|port| port != 0)
+        && host
+            .parse::<IpAddr>()
+            .map_or(true, |ip| !ip.is_unspecified())
+}
+
+/// Connect to the leader master, asking the reachable masters in the list where it is.
+///
+/// If no master is reachable, e.g. as they are just restarting, or none of them names a reachable
+/// leader, e.g. during an election, tries again as `retry` allows. Each connection attempt gives
+/// up after `connect_timeout`, if one is given. Fails with `NoAvailableServers` if no master was
+/// reachable in the last attempt and with `NoLeader` if some were, but not the leader.
 pub async fn connect_to_master(
     master_addrs: &Vec<String>,
-) -> Result<MasterClient<tonic::transport::Channel>, Box<dyn std::error::Error>> {
+    tls: &TlsConfig,
+    retry: &RetryPolicy,
+    connect_timeout: Option<Duration>,
+) -> Result<MasterClient<tonic::transport::Channel>> {
+    let mut backoff = retry.backoff();
+    loop {
+        let e = match find_leader(master_addrs, tls, connect_timeout).await {
+            Ok(client) => return Ok(client),
+            Err(e) => e,
+        };
+        if !backoff.wait("Connecting to the leader master", &e).await {
+            return Err(e);
+        }
+    }
+}
+
+/// Ask the masters in the list for the leader, one after another, until one names a leader that
+/// can be connected to
+async fn find_leader(
+    master_addrs: &Vec<String>,
+    tls: &TlsConfig,
+    connect_timeout: Option<Duration>,
+) -> Result<MasterClient<Channel>> {
+    let mut no_leader = None;
     for addr in master_addrs {
-        match MasterClient::connect(format!("http://{}", addr)).await {
-            Ok(client) => {
-                info!("Connected to master at: {}", addr);
-                return Ok(client); // Return the MasterClient directly
-            }
+        let channel = match connect_channel_within(addr, tls, connect_timeout).await {
+            Ok(channel) => channel,
             Err(e) => {
                 warn!("Failed to connect to master at {}: {}", addr, e);
+                continue;
+            }
+        };
+        info!("Connected to master at: {}", addr);
+        match connect_to_leader(MasterClient::new(channel), addr, tls, connect_timeout).await {
+            Ok(client) => return Ok(client),
+            Err(e) => {
+                warn!("{}", e);
+                no_leader = Some(e);
             }
         }
     }
 
     // If none of the addresses are connectable, return an error
-    Err(Box::new(std::io::Error::new(
-        std::io::ErrorKind::NotFound,
-        "No master server is connectable",
-    )))
+    Err(no_leader.unwrap_or_else(|| {
+        Error::NoAvailableServers("No master server is connectable".to_string())
+    }))
+}
+
+/// Ask the master at `addr` for the leader and connect to the leader instead if it is another
+/// node. Fails with `NoLeader` if the leader is unknown or unreachable.
+async fn connect_to_leader(
+    mut client: MasterClient<Channel>,
+    addr: &str,
+    tls: &TlsConfig,
+    timeout: Option<Duration>,
+) -> Result<MasterClient<Channel>> {
+    let mut request = tonic::Request::new(GetLeaderRequest {});
+    if let Some(timeout) = timeout {
+        request.set_timeout(timeout);
+    }
+    let leader = client
+        .get_leader(request)
+        .await
+        .map_err(|status| {
+            Error::NoLeader(format!(
+                "Failed to ask master at {} for the leader: {}",
+                addr,
+                status.message()
+            ))
+        })?
+        .into_inner()
+        .leader_address;
+    if leader.is_empty() {
+        return Err(Error::NoLeader(format!(
+            "Master at {} knows no leader",
+            addr
+        )));
+    }
+    if leader == addr {
+        return Ok(client);
+    }
+
+    let channel = connect_channel_within(&leader, tls, timeout)
+        .await
+        .map_err(|e| {
+            Error::NoLeader(format!(
+                "Master at {} names {} as the leader, which is unreachable: {}",
+                addr, leader, e
+            ))
+        })?;
+    info!(
+        "Master at {} is a shadow, connected to leader at: {}",
+        addr, leader
+    );
+    Ok(MasterClient::new(channel))
+}
+
+/// Open a gRPC channel to the given address, using `https://` and the configured CA when TLS is enabled
+pub async fn connect_channel(addr: &str, tls: &TlsConfig) -> Result<Channel> {
+    connect_channel_within(addr, tls, None).await
+}
+
+/// Open a gRPC channel like `connect_channel`, giving up after `timeout` if one is given
+pub async fn connect_channel_within(
+    addr: &str,
+    tls: &TlsConfig,
+    timeout: Option<Duration>,
+) -> Result<Channel> {
+    let mut endpoint = if tls.enabled {
+        Endpoint::from_shared(format!("https://{}", addr))
+            .map_err(|e| Error::Config(format!("Invalid address '{}': {}", addr, e)))?
+            .tls_config(match &tls.client {
+                Some(config) => config.clone(),
+                None => client_tls_config(tls)?,
+            })?
+    } else {
+        Endpoint::from_shared(format!("http://{}", addr))
+            .map_err(|e| Error::Config(format!("Invalid address '{}': {}", addr, e)))?
+    };
+    if let Some(timeout) = timeout {
+        endpoint = endpoint.connect_timeout(timeout);
+    }
+    Ok(endpoint.connect().await?)
+}
+
+/// Build the client TLS configuration from the configured CA certificate, read from its file.
+/// Connections use the one `TlsConfig::load` keeps.
+pub fn client_tls_config(tls: &TlsConfig) -> Result<ClientTlsConfig> {
+    let ca = read_tls_file("CA certificate", &tls.ca_path)?;
+    let mut config = ClientTlsConfig::new().ca_certificate(Certificate::from_pem(ca));
+    if !tls.domain_name.is_empty() {
+        config = config.domain_name(tls.domain_name.clone());
+    }
+    Ok(config)
+}
+
+/// Build the server TLS configuration, or `None` when TLS is disabled
+pub fn server_tls_config(tls: &TlsConfig) -> Result<Option<ServerTlsConfig>> {
+    if !tls.enabled {
+        return Ok(None);
+    }
+    let cert = read_tls_file("certificate", &tls.cert_path)?;
+    let key = read_tls_file("private key", &tls.key_path)?;
+    Ok(Some(
+        ServerTlsConfig::new().identity(Identity::from_pem(cert, key)),
+    ))
+}
+
+/// Read a PEM file referenced by the TLS configuration
+fn read_tls_file(kind: &str, path: &str) -> Result<Vec<u8>> {
+    if path.is_empty() {
+        return Err(Error::Config(format!(
+            "TLS is enabled but no {} path is configured",
+            kind
+        )));
+    }
+    fs::read(path).map_err(|e| {
+        Error::Config(format!(
+            "TLS is enabled but the {} file '{}' could not be read: {}",
+            kind, path, e
+        ))
+    })
 }
@@ -0,0 +1,66 @@
+// Servers can bind a wildcard address while registering with the address other nodes reach
+// them at
+use rustfs::testing::TestCluster;
+use rustfs::util::node_addresses;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use uuid::Uuid;
+
+#[test]
+fn advertised_address_defaults_to_the_bind_address() {
+    let addresses = node_addresses(None, Some("127.0.0.1:50010"), None, "", "").unwrap();
+    assert_eq!(addresses.bind.to_string(), "127.0.0.1:50010");
+    assert_eq!(addresses.advertise, "127.0.0.1:50010");
+
+    let addresses =
+        node_addresses(None, None, None, "0.0.0.0:50010", "chunkserver-1:50010").unwrap();
+    assert_eq!(addresses.bind.to_string(), "0.0.0.0:50010");
+    assert_eq!(addresses.advertise, "chunkserver-1:50010");
+
+    // A wildcard address is no use to the nodes connecting to it
+    assert!(node_addresses(None, Some("0.0.0.0:50010"), None, "", "").is_err());
+}
+
+#[tokio::test]
+async fn chunkserver_registers_its_advertised_address() {
+    let cluster = TestCluster::start(0).await.unwrap();
+    let config_path = cluster.write_config().unwrap();
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let dir = std::env::temp_dir().join(format!("rustfs-advertise-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut chunkserver = Command::new(env!("CARGO_BIN_EXE_chunkserver"))
+        .args(["--config", &config_path, "--log-level", "error"])
+        .args(["--bind-addr", &format!("0.0.0.0:{}", port)])
+        .args(["--advertise-addr", &format!("127.0.0.1:{}", port)])
+        .current_dir(&dir)
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let advertised = format!("127.0.0.1:{}", port);
+    let master = cluster.master();
+    let registered = tokio::time::timeout(Duration::from_secs(30), async {
+        while !master.healthy_servers().await.contains(&advertised) {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await;
+    let servers = master.healthy_servers().await;
+    chunkserver.kill().unwrap();
+    chunkserver.wait().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(registered.is_ok(), "Registered servers: {:?}", servers);
+    assert_eq!(servers.len(), 1, "{:?}", servers);
+    assert!(master
+        .chunk_servers
+        .read()
+        .await
+        .keys()
+        .all(|server| !server.starts_with("0.0.0.0")));
+
+    cluster.shutdown().await.unwrap();
+}
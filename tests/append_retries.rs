@@ -0,0 +1,94 @@
+// An append retried after its response was lost is applied once on every replica
+use rustfs::chunkserver_service::apply_append_fields;
+use rustfs::cluster_auth::sign_request;
+use rustfs::proto::chunk::chunk_client::ChunkClient;
+use rustfs::proto::chunk::{AppendRequest, ApplyAppendRequest};
+use rustfs::testing::TestCluster;
+use rustfs::util::connect_channel;
+use std::sync::atomic::Ordering;
+use tonic::transport::Channel;
+
+async fn chunk_client(cluster: &TestCluster, addr: &str) -> ChunkClient<Channel> {
+    let channel = connect_channel(addr, &cluster.config().common.tls)
+        .await
+        .unwrap();
+    ChunkClient::new(channel)
+}
+
+#[tokio::test]
+async fn retried_append_is_applied_once() {
+    let cluster = TestCluster::start_with(3, |config| {
+        config.common.replication_factor = 3;
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    client.upload_stream(&b"start;"[..], "/log").await.unwrap();
+    let servers = client.get_append_server_addresses("/log").await.unwrap();
+    let (chunk_id, replicas) = &servers[0];
+    let (primary, secondaries) = replicas.split_first().unwrap();
+    let mut primary = chunk_client(&cluster, primary).await;
+
+    let append = |data: &str, append_id: &str| AppendRequest {
+        chunk_id: chunk_id.clone(),
+        data: data.to_string(),
+        token: String::new(),
+        epoch: cluster.master().epoch.load(Ordering::SeqCst),
+        secondary_addresses: secondaries.to_vec(),
+        append_id: append_id.to_string(),
+    };
+    // The client does not learn the outcome of the first attempt and sends it again
+    let first = primary
+        .append(append("record;", "append-1"))
+        .await
+        .unwrap()
+        .into_inner();
+    let retry = primary
+        .append(append("record;", "append-1"))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(retry.offset, first.offset);
+    assert!(retry.failed_secondaries.is_empty());
+
+    // A secondary the primary applied the append to ignores it when forwarded again
+    let mut secondary = chunk_client(&cluster, &secondaries[0]).await;
+    let epoch = cluster.master().epoch.load(Ordering::SeqCst);
+    let internal_proof = sign_request(
+        &cluster.config().common.cluster_secret,
+        &apply_append_fields(
+            chunk_id,
+            b"record;",
+            &first.offset.to_be_bytes(),
+            &epoch.to_be_bytes(),
+            "append-1",
+        ),
+    );
+    secondary
+        .apply_append(ApplyAppendRequest {
+            chunk_id: chunk_id.clone(),
+            data: b"record;".to_vec(),
+            offset: first.offset,
+            epoch,
+            internal_proof,
+            append_id: "append-1".to_string(),
+        })
+        .await
+        .unwrap();
+
+    // An append with another ID is a new record
+    let next = primary
+        .append(append("next;", "append-2"))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(next.offset, first.offset + "record;".len() as u64);
+
+    for index in 0..3 {
+        let path = cluster.chunkserver(index).chunk_path(chunk_id);
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(content, "start;record;next;", "{}", path);
+    }
+
+    cluster.shutdown().await.unwrap();
+}
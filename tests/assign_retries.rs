@@ -0,0 +1,109 @@
+// Retried uploads and deletions carrying the same operation ID are applied once
+use rustfs::proto::master::master_client::MasterClient;
+use rustfs::proto::master::{AssignRequest, DeleteFileRequest};
+use rustfs::testing::TestCluster;
+use rustfs::util::connect_channel;
+use tonic::transport::Channel;
+
+async fn start_cluster() -> (TestCluster, MasterClient<Channel>) {
+    let cluster = TestCluster::start_with(2, |config| {
+        config.common.replication_factor = 2;
+        config.common.chunk_size = 1024;
+    })
+    .await
+    .unwrap();
+    let channel = connect_channel(cluster.master_addr(), &cluster.config().common.tls)
+        .await
+        .unwrap();
+    (cluster, MasterClient::new(channel))
+}
+
+fn assign_request(operation_id: &str) -> AssignRequest {
+    AssignRequest {
+        file_name: "/retried".to_string(),
+        file_size: 3000,
+        token: String::new(),
+        operation_id: operation_id.to_string(),
+    }
+}
+
+#[tokio::test]
+async fn retried_assignment_gets_the_same_chunks() {
+    let (cluster, mut master) = start_cluster().await;
+
+    let first = master
+        .assign_chunks(assign_request("upload-1"))
+        .await
+        .unwrap()
+        .into_inner();
+    // E.g. after the response to the first request was lost
+    let retry = master
+        .assign_chunks(assign_request("upload-1"))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(first, retry);
+    assert_eq!(first.file_name, "/retried");
+    assert_eq!(first.chunk_info_list.len(), 3);
+
+    // Only one set of chunks was assigned
+    let service = cluster.master();
+    let file_chunks = service.file_chunks.read().await;
+    assert_eq!(file_chunks.keys().collect::<Vec<_>>(), vec!["/retried"]);
+    assert_eq!(service.chunk_map.read().await.len(), 3);
+    let chunk_ids: Vec<&String> = file_chunks["/retried"]
+        .iter()
+        .map(|chunk| &chunk.chunk_id)
+        .collect();
+    let assigned: Vec<&String> = first
+        .chunk_info_list
+        .iter()
+        .map(|chunk| &chunk.chunk_id)
+        .collect();
+    assert_eq!(chunk_ids, assigned);
+    drop(file_chunks);
+
+    // Another upload of the same name is assigned chunks of its own
+    let other = master
+        .assign_chunks(assign_request("upload-2"))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_ne!(other.file_name, first.file_name);
+    assert_eq!(service.chunk_map.read().await.len(), 6);
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn retried_deletion_succeeds_again() {
+    let (cluster, mut master) = start_cluster().await;
+    master
+        .assign_chunks(assign_request("upload-1"))
+        .await
+        .unwrap();
+
+    let delete = || DeleteFileRequest {
+        file_name: "/retried".to_string(),
+        token: String::new(),
+        operation_id: "delete-1".to_string(),
+    };
+    let first = master.delete_file(delete()).await.unwrap().into_inner();
+    let retry = master.delete_file(delete()).await.unwrap().into_inner();
+    assert!(first.success, "{}", first.message);
+    assert_eq!(first, retry);
+    assert!(cluster.master().file_chunks.read().await.is_empty());
+
+    // Without the operation ID the file is not found
+    let unknown = master
+        .delete_file(DeleteFileRequest {
+            operation_id: String::new(),
+            ..delete()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(!unknown.success, "{}", unknown.message);
+
+    cluster.shutdown().await.unwrap();
+}
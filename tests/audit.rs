@@ -0,0 +1,140 @@
+// The master records who changed which file, and whether it worked, in an audit log that admins
+// can query
+use rustfs::auth::Role;
+use rustfs::client::Client;
+use rustfs::proto::master::AuditEntry;
+use rustfs::testing::TestCluster;
+use std::time::Duration;
+
+/// Cluster with authentication, an admin named "admin" and a user named "writer"
+async fn start_cluster(configure: impl FnOnce(&mut rustfs::config::Config)) -> TestCluster {
+    let cluster = TestCluster::start_with(1, |config| {
+        config.common.use_authentication = true;
+        config.common.cluster_secret = "test-cluster-secret".to_string();
+        config.common.replication_factor = 1;
+        config.common.trash_retention_secs = Duration::from_secs(3600);
+        configure(config);
+    })
+    .await
+    .unwrap();
+    cluster.add_user("admin", "secret", Role::Admin).unwrap();
+    cluster
+        .add_user("writer", "secret", Role::ReadWrite)
+        .unwrap();
+    cluster
+}
+
+async fn client_of(cluster: &TestCluster, user: &str) -> Client {
+    let mut client = cluster.client().await.unwrap();
+    client.authenticate(user, "secret").await.unwrap();
+    client
+}
+
+/// Wait until the audit log holds at least `count` entries of `file_name`, for at most 10 seconds
+async fn wait_for_entries(admin: &mut Client, file_name: &str, count: usize) -> Vec<AuditEntry> {
+    for _ in 0..100 {
+        let entries = admin.query_audit(file_name, 0, 0).await.unwrap().entries;
+        if entries.len() >= count {
+            return entries;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("Fewer than {} audit entries of {}", count, file_name);
+}
+
+#[tokio::test]
+async fn operations_and_failures_are_recorded() {
+    let cluster = start_cluster(|_| {}).await;
+    let mut writer = client_of(&cluster, "writer").await;
+    let mut admin = client_of(&cluster, "admin").await;
+
+    writer
+        .upload_stream(&b"audited"[..], "/dir/file")
+        .await
+        .unwrap();
+    let servers = writer
+        .get_append_server_addresses("/dir/file")
+        .await
+        .unwrap();
+    writer
+        .append_file(servers, "/dir/file", " twice".to_string())
+        .await
+        .unwrap();
+    writer.truncate_file("/dir/file", 7).await.unwrap();
+    writer.delete_file("/dir/file").await.unwrap();
+    writer.undelete_file("/dir/file").await.unwrap();
+    // Failures are recorded as well
+    writer.delete_file("/dir/missing").await.unwrap_err();
+
+    let entries = wait_for_entries(&mut admin, "/dir", 6).await;
+    let operations: Vec<(&str, &str)> = entries
+        .iter()
+        .map(|entry| (entry.operation.as_str(), entry.file_name.as_str()))
+        .collect();
+    assert_eq!(
+        operations,
+        vec![
+            ("upload", "/dir/file"),
+            ("append", "/dir/file"),
+            ("truncate", "/dir/file"),
+            ("delete", "/dir/file"),
+            ("undelete", "/dir/file"),
+            ("delete", "/dir/missing"),
+        ]
+    );
+    for entry in &entries[..5] {
+        assert_eq!(entry.user, "writer");
+        assert_eq!(entry.result, "ok", "{:?}", entry);
+        assert!(!entry.source.is_empty(), "{:?}", entry);
+        assert!(entry.timestamp > 0);
+    }
+    assert!(
+        entries[5].result.starts_with("NotFound"),
+        "{:?}",
+        entries[5]
+    );
+
+    // Entries of other files are filtered out, and only admins may query the log
+    writer
+        .upload_stream(&b"elsewhere"[..], "/other")
+        .await
+        .unwrap();
+    wait_for_entries(&mut admin, "/other", 1).await;
+    assert_eq!(
+        admin.query_audit("/dir", 0, 0).await.unwrap().entries.len(),
+        6
+    );
+    writer.query_audit("", 0, 0).await.unwrap_err();
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn rotated_logs_are_queried_and_the_oldest_dropped() {
+    let cluster = start_cluster(|config| {
+        config.master.audit_log_max_size = 100;
+        config.master.audit_log_max_files = 2;
+    })
+    .await;
+    let mut writer = client_of(&cluster, "writer").await;
+    let mut admin = client_of(&cluster, "admin").await;
+
+    // Every entry is larger than the size limit, so each ends up in a file of its own
+    for index in 0..5 {
+        writer
+            .upload_stream(&b"data"[..], &format!("/file-{}", index))
+            .await
+            .unwrap();
+        wait_for_entries(&mut admin, &format!("/file-{}", index), 1).await;
+    }
+
+    // The current file and two rotated ones are left
+    let entries = admin.query_audit("", 0, 0).await.unwrap().entries;
+    let files: Vec<&str> = entries
+        .iter()
+        .map(|entry| entry.file_name.as_str())
+        .collect();
+    assert_eq!(files, vec!["/file-2", "/file-3", "/file-4"]);
+
+    cluster.shutdown().await.unwrap();
+}
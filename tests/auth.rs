@@ -0,0 +1,74 @@
+// Password hashing and lookups in the authentication file
+use rustfs::auth::{hash_password, user_role, verify_password, verify_user, Role};
+use rustfs::error::Error;
+use serde_json::json;
+use std::time::{Duration, Instant};
+
+#[test]
+fn verifies_hashed_password() {
+    let hash = hash_password("secret").unwrap();
+    assert!(hash.starts_with("$argon2"));
+    assert!(verify_password("secret", &hash).unwrap());
+}
+
+#[test]
+fn rejects_wrong_password() {
+    let hash = hash_password("secret").unwrap();
+    assert!(!verify_password("Secret", &hash).unwrap());
+    assert!(!verify_password("", &hash).unwrap());
+}
+
+#[test]
+fn malformed_hash_is_an_error() {
+    for malformed in ["", "secret", "$argon2id$v=19$m=19456,t=2,p=1$not base64!"] {
+        assert!(
+            matches!(verify_password("secret", malformed), Err(Error::Auth(_))),
+            "{:?} was accepted",
+            malformed
+        );
+    }
+}
+
+#[test]
+fn verifies_users_of_the_authentication_file() {
+    let auth_data = json!({
+        "alice": { "password_hash": hash_password("secret").unwrap(), "role": "admin" },
+        "bob": { "password": "plaintext" },
+    });
+    assert!(verify_user(&auth_data, "alice", "secret").unwrap());
+    assert!(!verify_user(&auth_data, "alice", "wrong").unwrap());
+    assert!(!verify_user(&auth_data, "carol", "secret").unwrap());
+    assert!(matches!(
+        verify_user(&auth_data, "bob", "plaintext"),
+        Err(Error::Auth(_))
+    ));
+    assert_eq!(user_role(&auth_data, "alice").unwrap(), Role::Admin);
+    assert_eq!(user_role(&auth_data, "bob").unwrap(), Role::ReadWrite);
+}
+
+#[test]
+fn unknown_users_take_as_long_as_known_ones() {
+    let auth_data = json!({
+        "alice": { "password_hash": hash_password("secret").unwrap() },
+    });
+    // The dummy hash of unknown users is computed once, not while they are timed
+    verify_user(&auth_data, "nobody", "secret").unwrap();
+
+    let time = |username: &str| {
+        let started = Instant::now();
+        for _ in 0..3 {
+            verify_user(&auth_data, username, "wrong").unwrap();
+        }
+        started.elapsed()
+    };
+    let known = time("alice");
+    let unknown = time("nobody");
+    // Both run argon2 with the same parameters, an early return would take microseconds
+    assert!(
+        unknown * 2 >= known && known * 2 >= unknown,
+        "known user took {:?}, unknown user {:?}",
+        known,
+        unknown
+    );
+    assert!(unknown > Duration::from_millis(1));
+}
@@ -0,0 +1,122 @@
+// Repeated failed authentications lock the username and source out for a while
+use rustfs::auth::{AuthThrottle, Role};
+use rustfs::config::MasterConfig;
+use rustfs::proto::master::master_client::MasterClient;
+use rustfs::proto::master::AuthenticateRequest;
+use rustfs::testing::TestCluster;
+use rustfs::util::connect_channel;
+use std::time::{Duration, Instant};
+use tonic::Code;
+
+fn throttle() -> AuthThrottle {
+    AuthThrottle::new(&MasterConfig {
+        auth_max_failures: 3,
+        auth_failure_window: Duration::from_secs(60),
+        auth_lockout: Duration::from_secs(30),
+        auth_max_lockout: Duration::from_secs(100),
+        ..MasterConfig::default()
+    })
+}
+
+#[test]
+fn lockout_engages_after_a_burst_and_releases() {
+    let mut throttle = throttle();
+    let start = Instant::now();
+    for _ in 0..2 {
+        assert_eq!(throttle.record_failure("alice", "10.0.0.1", start), None);
+        assert_eq!(throttle.locked_for("alice", "10.0.0.1", start), None);
+    }
+    assert_eq!(
+        throttle.record_failure("alice", "10.0.0.1", start),
+        Some(Duration::from_secs(30))
+    );
+    // Both the username, from anywhere, and the source, for any username, are locked out
+    assert_eq!(
+        throttle.locked_for("alice", "10.0.0.2", start),
+        Some(Duration::from_secs(30))
+    );
+    assert_eq!(
+        throttle.locked_for("bob", "10.0.0.1", start),
+        Some(Duration::from_secs(30))
+    );
+    assert_eq!(throttle.locked_for("bob", "10.0.0.2", start), None);
+
+    let released = start + Duration::from_secs(30);
+    assert_eq!(throttle.locked_for("alice", "10.0.0.1", released), None);
+}
+
+#[test]
+fn further_bursts_double_the_lockout_up_to_the_maximum() {
+    let mut throttle = throttle();
+    let mut now = Instant::now();
+    for expected in [30, 60, 100] {
+        for _ in 0..2 {
+            assert_eq!(throttle.record_failure("alice", "10.0.0.1", now), None);
+        }
+        assert_eq!(
+            throttle.record_failure("alice", "10.0.0.1", now),
+            Some(Duration::from_secs(expected))
+        );
+        now += Duration::from_secs(expected);
+        assert_eq!(throttle.locked_for("alice", "10.0.0.1", now), None);
+    }
+}
+
+#[test]
+fn failures_outside_the_window_and_before_a_success_are_forgotten() {
+    let mut throttle = throttle();
+    let start = Instant::now();
+    throttle.record_failure("alice", "10.0.0.1", start);
+    throttle.record_failure("alice", "10.0.0.1", start);
+    let later = start + Duration::from_secs(60);
+    assert_eq!(throttle.record_failure("alice", "10.0.0.1", later), None);
+
+    throttle.record_failure("alice", "10.0.0.1", later);
+    throttle.record_success("alice", "10.0.0.1");
+    assert_eq!(throttle.record_failure("alice", "10.0.0.1", later), None);
+    assert_eq!(throttle.record_failure("alice", "10.0.0.1", later), None);
+}
+
+#[tokio::test]
+async fn repeated_failures_are_throttled_until_the_lockout_ends() {
+    let cluster = TestCluster::start_with(1, |config| {
+        config.common.use_authentication = true;
+        config.common.cluster_secret = "test-cluster-secret".to_string();
+        config.master.auth_max_failures = 3;
+        config.master.auth_lockout = Duration::from_secs(2);
+    })
+    .await
+    .unwrap();
+    cluster
+        .add_user("alice", "secret", Role::ReadWrite)
+        .unwrap();
+    let channel = connect_channel(cluster.master_addr(), &cluster.config().common.tls)
+        .await
+        .unwrap();
+    let mut master = MasterClient::new(channel);
+    let authenticate = |password: &str| AuthenticateRequest {
+        username: "alice".to_string(),
+        password: password.to_string(),
+    };
+
+    for _ in 0..3 {
+        let status = master
+            .authenticate(authenticate("wrong"))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated, "{}", status);
+    }
+    // Locked out, even with the right password
+    for password in ["wrong", "secret"] {
+        let status = master
+            .authenticate(authenticate(password))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::ResourceExhausted, "{}", status);
+    }
+
+    tokio::time::sleep(Duration::from_millis(2100)).await;
+    master.authenticate(authenticate("secret")).await.unwrap();
+
+    cluster.shutdown().await.unwrap();
+}
@@ -0,0 +1,69 @@
+// Clients report replicas they fail to read from, and the master drops a reported replica that
+// lost the chunk, so that the repair scan copies the chunk again
+use rustfs::testing::TestCluster;
+use std::time::Duration;
+
+#[tokio::test]
+async fn reported_missing_replica_is_dropped_and_repaired() {
+    let cluster = TestCluster::start_with(3, |config| {
+        config.common.replication_factor = 2;
+        config.master.cron_interval = Duration::from_secs(1);
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    client
+        .upload_stream(&b"readable"[..], "/file")
+        .await
+        .unwrap();
+    let chunk = cluster.master().file_chunks.read().await["/file"][0].clone();
+    let lost = chunk.server_addresses[0].clone();
+    let addrs = cluster.chunkserver_addrs();
+    let lost_index = addrs.iter().position(|addr| *addr == lost).unwrap();
+    std::fs::remove_file(cluster.chunkserver(lost_index).chunk_path(&chunk.chunk_id)).unwrap();
+
+    // Reads are spread over the replicas, until one tried the lost one first
+    let metrics = &cluster.master().metrics;
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while metrics.bad_replica_reports.get() == 0 {
+            let mut client = cluster.client().await.unwrap();
+            let read = client.read_range("/file", 0, u64::MAX).await.unwrap();
+            assert_eq!(read, b"readable");
+            client.finish_reports().await;
+        }
+    })
+    .await
+    .expect("No read reported the lost replica");
+
+    let master = cluster.master();
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while master.chunk_map.read().await[&chunk.chunk_id]
+            .server_addresses
+            .contains(&lost)
+        {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("The reported replica was not dropped");
+
+    // The repair scan copies the chunk to another server
+    let repaired = tokio::time::timeout(Duration::from_secs(30), async {
+        loop {
+            let chunk = master.chunk_map.read().await[&chunk.chunk_id].clone();
+            if chunk.server_addresses.len() == 2 {
+                return chunk;
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    })
+    .await
+    .expect("The chunk was not re-replicated");
+    for addr in &repaired.server_addresses {
+        let index = addrs.iter().position(|a| a == addr).unwrap();
+        let copy = std::fs::read(cluster.chunkserver(index).chunk_path(&chunk.chunk_id)).unwrap();
+        assert_eq!(copy, b"readable", "Replica on {} differs", addr);
+    }
+
+    cluster.shutdown().await.unwrap();
+}
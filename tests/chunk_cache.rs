@@ -0,0 +1,96 @@
+// Clients reuse the chunk lists of files they read for chunk_cache_ttl, drop them when the file
+// changes and fetch them again once when a cached replica is gone
+use rustfs::testing::TestCluster;
+use std::sync::Arc;
+use std::time::Duration;
+
+async fn start_cluster(chunkservers: usize) -> TestCluster {
+    TestCluster::start_with(chunkservers, |config| {
+        config.common.replication_factor = 1;
+        config.client.chunk_cache_ttl = Duration::from_secs(3600);
+    })
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn repeated_reads_use_the_cached_list() {
+    let cluster = start_cluster(1).await;
+    let mut client = cluster.client().await.unwrap();
+    client
+        .upload_stream(&b"read often"[..], "/file")
+        .await
+        .unwrap();
+
+    for _ in 0..3 {
+        let read = client.read_range("/file", 0, u64::MAX).await.unwrap();
+        assert_eq!(read, b"read often");
+    }
+    let stats = client.chunk_cache_stats();
+    assert_eq!((stats.misses, stats.hits), (1, 2));
+
+    // Appends change the file, so the next read asks the master again
+    let servers = client.get_append_server_addresses("/file").await.unwrap();
+    client
+        .append_file(servers, "/file", ", and appended".to_string())
+        .await
+        .unwrap();
+    let read = client.read_range("/file", 0, u64::MAX).await.unwrap();
+    assert_eq!(read, b"read often, and appended");
+    assert_eq!(client.chunk_cache_stats().misses, 2);
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn moved_replica_is_fetched_again_once() {
+    let cluster = start_cluster(2).await;
+    let mut client = cluster.client().await.unwrap();
+    client
+        .upload_stream(&b"on the move"[..], "/file")
+        .await
+        .unwrap();
+    client.read_range("/file", 0, u64::MAX).await.unwrap();
+    assert_eq!(client.chunk_cache_stats().misses, 1);
+
+    // Move the only replica to the other chunkserver behind the client's back
+    let addrs = cluster.chunkserver_addrs();
+    let master = cluster.master();
+    let chunk = master.file_chunks.read().await["/file"][0].clone();
+    let from = addrs
+        .iter()
+        .position(|addr| *addr == chunk.server_addresses[0])
+        .unwrap();
+    let to = 1 - from;
+    let (source, target) = (cluster.chunkserver(from), cluster.chunkserver(to));
+    for (from_path, to_path) in [
+        (
+            source.chunk_path(&chunk.chunk_id),
+            target.chunk_path(&chunk.chunk_id),
+        ),
+        (
+            source.checksum_path(&chunk.chunk_id),
+            target.checksum_path(&chunk.chunk_id),
+        ),
+    ] {
+        std::fs::copy(&from_path, to_path).unwrap();
+        std::fs::remove_file(from_path).unwrap();
+    }
+    {
+        let mut file_chunks = master.file_chunks.write().await;
+        let mut chunk_map = master.chunk_map.write().await;
+        let mut moved = (*chunk).clone();
+        moved.server_addresses = vec![addrs[to].clone()];
+        moved.primary = addrs[to].clone();
+        let moved = Arc::new(moved);
+        chunk_map.insert(moved.chunk_id.clone(), moved.clone());
+        file_chunks.get_mut("/file").unwrap()[0] = moved;
+    }
+
+    let read = client.read_range("/file", 0, u64::MAX).await.unwrap();
+    assert_eq!(read, b"on the move");
+    let stats = client.chunk_cache_stats();
+    assert_eq!((stats.misses, stats.hits), (2, 1));
+
+    cluster.shutdown().await.unwrap();
+}
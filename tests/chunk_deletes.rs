@@ -0,0 +1,63 @@
+// Deleting a chunk that is already gone succeeds, so that deletes can be retried
+use rustfs::testing::TestCluster;
+
+#[tokio::test]
+async fn chunk_deletes_are_idempotent() {
+    let cluster = TestCluster::start_with(1, |config| {
+        config.common.replication_factor = 1;
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    client
+        .upload_stream(&b"deleted twice"[..], "/file")
+        .await
+        .unwrap();
+    let chunk = client.get_file_info("/file").await.unwrap().chunks[0].clone();
+    let chunkserver = cluster.chunkserver(0);
+
+    assert!(chunkserver.delete_chunk(&chunk.chunk_id).await.unwrap());
+    let path = chunkserver.chunk_path(&chunk.chunk_id);
+    assert!(!std::path::Path::new(&path).exists());
+    assert!(!chunkserver
+        .server_chunks
+        .lock()
+        .await
+        .contains(&chunk.chunk_id));
+
+    // E.g. a deletion the master asks for again after a lost heartbeat response
+    assert!(!chunkserver.delete_chunk(&chunk.chunk_id).await.unwrap());
+    // Or a replica that never got the chunk
+    assert!(!chunkserver.delete_chunk("never-uploaded").await.unwrap());
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn deleting_a_file_whose_chunks_are_gone_succeeds() {
+    let cluster = TestCluster::start_with(2, |config| {
+        config.common.replication_factor = 2;
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    client
+        .upload_stream(&b"lost replica"[..], "/file")
+        .await
+        .unwrap();
+    let chunk = client.get_file_info("/file").await.unwrap().chunks[0].clone();
+    std::fs::remove_file(cluster.chunkserver(0).chunk_path(&chunk.chunk_id)).unwrap();
+
+    client.delete_file("/file").await.unwrap();
+    client.get_file_info("/file").await.unwrap_err();
+    let path = cluster.chunkserver(1).chunk_path(&chunk.chunk_id);
+    tokio::time::timeout(std::time::Duration::from_secs(10), async {
+        while std::path::Path::new(&path).exists() {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("The other replica was not deleted");
+
+    cluster.shutdown().await.unwrap();
+}
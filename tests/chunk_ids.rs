@@ -0,0 +1,130 @@
+// Chunk IDs are opaque, so file names may contain anything chunk IDs used to be built from, and
+// chunkservers only accept IDs of the form the master issues
+use rustfs::proto::chunk::chunk_client::ChunkClient;
+use rustfs::proto::chunk::{
+    upload_request, ChecksumChunkRequest, FileChunk, FileInfo, ReadRequest, UploadRequest,
+};
+use rustfs::testing::TestCluster;
+use rustfs::util::connect_channel;
+use std::path::Path;
+use tonic::Code;
+
+#[tokio::test]
+async fn file_names_containing_chunk_markers() {
+    let cluster = TestCluster::start_with(2, |config| {
+        config.common.chunk_size = 16;
+        config.common.replication_factor = 2;
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+
+    let files = [
+        (
+            "/weird_chunk_1.txt",
+            &b"first file, spanning two chunks"[..],
+        ),
+        ("/weird", &b"second file, a prefix of the first"[..]),
+        ("/x_chunk_0_chunk_1", &b"third"[..]),
+    ];
+    for (name, data) in files {
+        let summary = client.upload_stream(data, name).await.unwrap();
+        assert_eq!(summary.file_name, name);
+    }
+
+    for (name, data) in files {
+        let info = client.get_file_info(name).await.unwrap();
+        for (index, chunk) in info.chunks.iter().enumerate() {
+            assert!(!chunk.chunk_id.contains("_chunk_"), "{}", chunk.chunk_id);
+            assert!(!chunk.chunk_id.contains("weird"), "{}", chunk.chunk_id);
+            assert_eq!(chunk.file_name, name);
+            assert_eq!(chunk.index, index as u64);
+        }
+        let read = client.read_range(name, 0, u64::MAX).await.unwrap();
+        assert_eq!(read, data, "{}", name);
+    }
+
+    // Deleting the first file leaves the others intact
+    client.delete_file("/weird_chunk_1.txt").await.unwrap();
+    for (name, data) in &files[1..] {
+        let read = client.read_range(name, 0, u64::MAX).await.unwrap();
+        assert_eq!(read, *data, "{}", name);
+    }
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn chunk_ids_that_are_not_issued_by_the_master_are_rejected() {
+    let cluster = TestCluster::start_with(1, |config| {
+        config.common.replication_factor = 1;
+    })
+    .await
+    .unwrap();
+    let channel = connect_channel(
+        &cluster.chunkserver_addrs()[0],
+        &cluster.config().common.tls,
+    )
+    .await
+    .unwrap();
+    let mut chunkserver = ChunkClient::new(channel);
+    // A file next to the data directory, which a read must not reach
+    let data_dir = cluster.chunkserver(0).data_dirs()[0].clone();
+    let outside = Path::new(&data_dir).parent().unwrap().join("outside");
+    std::fs::write(&outside, b"not a chunk").unwrap();
+
+    for chunk_id in ["../outside", "/etc/passwd", "", "chunk"] {
+        let status = chunkserver
+            .read(ReadRequest {
+                chunk_id: chunk_id.to_string(),
+                token: String::new(),
+                offset: None,
+                length: None,
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument, "{}", status);
+
+        let status = chunkserver
+            .checksum_chunk(ChecksumChunkRequest {
+                chunk_id: chunk_id.to_string(),
+                token: String::new(),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument, "{}", status);
+
+        let info = FileInfo {
+            chunk_id: chunk_id.to_string(),
+            secondary_addresses: Vec::new(),
+            epoch: 0,
+            checksum: None,
+            stored_form: false,
+            replicas: Vec::new(),
+            placement_proof: String::new(),
+            primary: String::new(),
+        };
+        let requests = [
+            upload_request::Request::Info(info),
+            upload_request::Request::Chunk(FileChunk {
+                data: b"overwritten".to_vec(),
+            }),
+        ]
+        .into_iter()
+        .map(|request| UploadRequest {
+            request: Some(request),
+            token: String::new(),
+            is_internal: false,
+            internal_proof: String::new(),
+        })
+        .collect::<Vec<_>>();
+        let status = chunkserver
+            .upload(futures::stream::iter(requests))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument, "{}", status);
+    }
+    assert_eq!(std::fs::read(&outside).unwrap(), b"not a chunk");
+
+    cluster.shutdown().await.unwrap();
+}
@@ -0,0 +1,97 @@
+// Chunkservers report changes to their chunks to the master that acknowledged their last report,
+// and all of their chunks to a master that did not
+use rustfs::master_service::MasterService;
+use rustfs::testing::TestCluster;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Wait until `master` acknowledged a chunk report of the chunkserver at `addr`
+async fn wait_for_acknowledged_report(master: &MasterService, addr: &str) {
+    tokio::time::timeout(TIMEOUT, async {
+        while !master.report_generations.read().await.contains_key(addr) {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("No chunk report was acknowledged");
+}
+
+async fn reported_chunks(master: &MasterService, addr: &str) -> HashSet<String> {
+    master
+        .chunk_servers
+        .read()
+        .await
+        .get(addr)
+        .map(|chunks| chunks.iter().map(|chunk| chunk.chunk_id.clone()).collect())
+        .unwrap_or_default()
+}
+
+#[tokio::test]
+async fn new_leader_gets_a_full_report() {
+    let mut cluster = TestCluster::start_with_shadows(2, 1, |config| {
+        config.common.shadow_master_ping_interval = Duration::from_secs(1);
+        config.common.heartbeat_interval = Duration::from_secs(1);
+        config.common.replication_factor = 1;
+        config.common.chunk_size = 1024;
+        config.master.orphan_grace_period = Duration::ZERO;
+    })
+    .await
+    .unwrap();
+    let addr = cluster.chunkserver_addrs()[0].clone();
+    let mut client = cluster.client().await.unwrap();
+    client
+        .upload_stream(&[1u8; 3000][..], "/file")
+        .await
+        .unwrap();
+
+    // From now on the chunkserver reports changes based on the leader's acknowledgement
+    wait_for_acknowledged_report(cluster.master(), &addr).await;
+    let leader_version = *cluster.master().metadata_version.read().await;
+    tokio::time::timeout(TIMEOUT, async {
+        loop {
+            let mut synced = true;
+            for shadow in &cluster.masters()[1..] {
+                synced &= *shadow.metadata_version.read().await >= leader_version;
+            }
+            if synced {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("The shadows did not sync with the leader");
+
+    // Acknowledged reports are only known to the leader. The shadows also forget where the
+    // chunks are, as a restarted master would, so only a full report tells them again.
+    for shadow in &cluster.masters()[1..] {
+        assert!(shadow.report_generations.read().await.is_empty());
+        shadow.chunk_servers.write().await.remove(&addr);
+    }
+    cluster.kill_master(0);
+    let shadows: Vec<Arc<MasterService>> = cluster.masters().into_iter().cloned().collect();
+    let leader = tokio::time::timeout(TIMEOUT, async {
+        loop {
+            for shadow in &shadows {
+                if shadow.is_leader().await {
+                    return Arc::clone(shadow);
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("No shadow took over as leader");
+
+    // Knowing no report of the chunkserver, the new leader rejects its changes until it gets
+    // a full report, which lists every chunk it stores
+    wait_for_acknowledged_report(&leader, &addr).await;
+    let stored = cluster.chunkserver(0).server_chunks.lock().await.clone();
+    assert_eq!(stored.len(), 3);
+    assert_eq!(reported_chunks(&leader, &addr).await, stored);
+
+    cluster.shutdown().await.unwrap();
+}
@@ -0,0 +1,138 @@
+// Chunkservers reject uploads and appends that would grow a chunk past chunk_size
+use rustfs::proto::chunk::chunk_client::ChunkClient;
+use rustfs::proto::chunk::{upload_request, FileChunk, FileInfo, UploadRequest};
+use rustfs::testing::TestCluster;
+use rustfs::util::connect_channel;
+use std::sync::atomic::Ordering;
+use uuid::Uuid;
+
+const CHUNK_SIZE: u64 = 16;
+
+async fn start_cluster() -> TestCluster {
+    TestCluster::start_with(1, |config| {
+        config.common.chunk_size = CHUNK_SIZE;
+        config.common.replication_factor = 1;
+    })
+    .await
+    .unwrap()
+}
+
+/// Upload `frames` as a new chunk straight to the chunkserver
+async fn upload_chunk(
+    cluster: &TestCluster,
+    chunk_id: &str,
+    frames: &[&[u8]],
+) -> Result<u64, tonic::Status> {
+    let addr = &cluster.chunkserver_addrs()[0];
+    let channel = connect_channel(addr, &cluster.config().common.tls)
+        .await
+        .unwrap();
+    let info = FileInfo {
+        chunk_id: chunk_id.to_string(),
+        secondary_addresses: Vec::new(),
+        epoch: cluster.master().epoch.load(Ordering::SeqCst),
+        checksum: None,
+        stored_form: false,
+        replicas: Vec::new(),
+        placement_proof: String::new(),
+        primary: String::new(),
+    };
+    let requests = std::iter::once(upload_request::Request::Info(info))
+        .chain(frames.iter().map(|data| {
+            upload_request::Request::Chunk(FileChunk {
+                data: data.to_vec(),
+            })
+        }))
+        .map(|request| UploadRequest {
+            request: Some(request),
+            token: String::new(),
+            is_internal: false,
+            internal_proof: String::new(),
+        })
+        .collect::<Vec<_>>();
+    let response = ChunkClient::new(channel)
+        .upload(futures::stream::iter(requests))
+        .await?;
+    Ok(response.into_inner().bytes_written)
+}
+
+/// Files of the chunk in its data directory, including temporary ones and its checksum
+fn chunk_files(cluster: &TestCluster, chunk_id: &str) -> Vec<String> {
+    let dir = cluster.chunkserver(0).chunk_dir(chunk_id);
+    std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.starts_with(chunk_id))
+        .collect()
+}
+
+#[tokio::test]
+async fn upload_at_chunk_size_is_accepted() {
+    let cluster = start_cluster().await;
+    let chunk_id = Uuid::new_v4().to_string();
+
+    let data = [7u8; CHUNK_SIZE as usize];
+    let written = upload_chunk(&cluster, &chunk_id, &[&data[..10], &data[10..]])
+        .await
+        .unwrap();
+    assert_eq!(written, CHUNK_SIZE);
+    assert!(chunk_files(&cluster, &chunk_id).contains(&chunk_id));
+    let stored = std::fs::metadata(cluster.chunkserver(0).chunk_path(&chunk_id)).unwrap();
+    assert_eq!(stored.len(), CHUNK_SIZE);
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn upload_one_byte_over_chunk_size_is_rejected() {
+    let cluster = start_cluster().await;
+    let chunk_id = Uuid::new_v4().to_string();
+
+    let data = [7u8; CHUNK_SIZE as usize + 1];
+    let status = upload_chunk(&cluster, &chunk_id, &[&data[..10], &data[10..]])
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::InvalidArgument, "{}", status);
+    // Neither the chunk nor its temporary file is left behind
+    assert!(
+        chunk_files(&cluster, &chunk_id).is_empty(),
+        "{:?}",
+        chunk_files(&cluster, &chunk_id)
+    );
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn append_up_to_chunk_size() {
+    let cluster = start_cluster().await;
+    let mut client = cluster.client().await.unwrap();
+    client
+        .upload_stream(&b"0123456789"[..], "/appended")
+        .await
+        .unwrap();
+
+    // Fills the chunk exactly
+    let servers = client
+        .get_append_server_addresses("/appended")
+        .await
+        .unwrap();
+    client
+        .append_file(servers, "/appended", "abcdef".to_string())
+        .await
+        .unwrap();
+    // One byte more is rejected and leaves the chunk as it was
+    let servers = client
+        .get_append_server_addresses("/appended")
+        .await
+        .unwrap();
+    assert!(client
+        .append_file(servers, "/appended", "g".to_string())
+        .await
+        .is_err());
+
+    let read = client.read_range("/appended", 0, u64::MAX).await.unwrap();
+    assert_eq!(read, b"0123456789abcdef");
+
+    cluster.shutdown().await.unwrap();
+}
@@ -0,0 +1,143 @@
+// Only chunkservers can register with the master and send it heartbeats, with a proof signed
+// with a key derived from the cluster secret over the address and the reported chunks
+use rustfs::cluster_auth::sign_request;
+use rustfs::master_service::{heartbeat_digest, heartbeat_fields, register_fields};
+use rustfs::proto::master::master_client::MasterClient;
+use rustfs::proto::master::{HeartbeatRequest, RegisterRequest};
+use rustfs::testing::TestCluster;
+use rustfs::util::connect_channel;
+use std::time::Duration;
+use tonic::transport::Channel;
+use tonic::Code;
+
+const CLUSTER_SECRET: &str = "test-cluster-secret";
+const ADDRESS: &str = "127.0.0.1:1";
+
+async fn start_cluster() -> (TestCluster, MasterClient<Channel>) {
+    let cluster = TestCluster::start_with(1, |config| {
+        config.common.use_authentication = true;
+        config.common.cluster_secret = CLUSTER_SECRET.to_string();
+    })
+    .await
+    .unwrap();
+    let channel = connect_channel(cluster.master_addr(), &cluster.config().common.tls)
+        .await
+        .unwrap();
+    (cluster, MasterClient::new(channel))
+}
+
+/// Heartbeat of `ADDRESS` reporting `chunks`, signed with `secret`
+fn heartbeat(secret: &str, chunks: &[&str]) -> HeartbeatRequest {
+    let mut request = HeartbeatRequest {
+        chunkserver_address: ADDRESS.to_string(),
+        chunks: chunks.iter().map(ToString::to_string).collect(),
+        generation: 1,
+        ..Default::default()
+    };
+    request.internal_proof = sign_request(
+        secret,
+        &heartbeat_fields(ADDRESS, &heartbeat_digest(&request)),
+    );
+    request
+}
+
+#[tokio::test]
+async fn registering_needs_a_valid_proof() {
+    let (cluster, mut master) = start_cluster().await;
+    // The chunkserver of the cluster signs its own registration
+    let chunkserver = cluster.chunkserver_addrs()[0].clone();
+    assert!(cluster
+        .master()
+        .chunk_servers
+        .read()
+        .await
+        .contains_key(&chunkserver));
+
+    let register = |internal_proof: String| RegisterRequest {
+        address: ADDRESS.to_string(),
+        failure_domain: "rack-1".to_string(),
+        internal_proof,
+    };
+    for internal_proof in [
+        String::new(),
+        sign_request("wrong-secret", &register_fields(ADDRESS, "rack-1")),
+        // A proof for another failure domain
+        sign_request(CLUSTER_SECRET, &register_fields(ADDRESS, "rack-2")),
+        // A proof for another chunkserver
+        sign_request(CLUSTER_SECRET, &register_fields(&chunkserver, "rack-1")),
+    ] {
+        let status = master
+            .register_chunk_server(register(internal_proof))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated, "{}", status);
+    }
+    assert!(!cluster
+        .master()
+        .chunk_servers
+        .read()
+        .await
+        .contains_key(ADDRESS));
+
+    master
+        .register_chunk_server(register(sign_request(
+            CLUSTER_SECRET,
+            &register_fields(ADDRESS, "rack-1"),
+        )))
+        .await
+        .unwrap();
+    assert!(cluster
+        .master()
+        .chunk_servers
+        .read()
+        .await
+        .contains_key(ADDRESS));
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn heartbeats_need_a_valid_proof() {
+    let (cluster, mut master) = start_cluster().await;
+    // The chunkserver of the cluster signs its own heartbeats
+    let service = cluster.master();
+    let chunkserver = cluster.chunkserver_addrs()[0].clone();
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while !service.healthy_servers().await.contains(&chunkserver) {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("The chunkserver's heartbeats were not accepted");
+
+    let unsigned = HeartbeatRequest {
+        internal_proof: String::new(),
+        ..heartbeat(CLUSTER_SECRET, &["chunk"])
+    };
+    // A proof of another chunk report
+    let altered = HeartbeatRequest {
+        chunks: vec!["other".to_string()],
+        ..heartbeat(CLUSTER_SECRET, &["chunk"])
+    };
+    for request in [unsigned, heartbeat("wrong-secret", &["chunk"]), altered] {
+        let status = master.heartbeat(request).await.unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated, "{}", status);
+    }
+    assert!(!service
+        .last_heartbeat_time
+        .read()
+        .await
+        .contains_key(ADDRESS));
+
+    master
+        .heartbeat(heartbeat(CLUSTER_SECRET, &["chunk"]))
+        .await
+        .unwrap();
+    assert!(service
+        .last_heartbeat_time
+        .read()
+        .await
+        .contains_key(ADDRESS));
+
+    cluster.shutdown().await.unwrap();
+}
@@ -0,0 +1,111 @@
+// With --json, the client prints one JSON object per command on stdout and nothing else, also
+// for errors
+use rustfs::testing::TestCluster;
+use serde_json::Value;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+const CHUNK_SIZE: u64 = 1024;
+
+/// Run the client against the cluster with `args` and `--json`, feeding it `stdin`. Returns the
+/// exit code and the JSON object printed.
+fn client(config_path: &str, args: &[&str], stdin: &[u8]) -> (i32, Value) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_client"))
+        .args(args)
+        .args(["--json", "--config", config_path])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    let output = child.wait_with_output().unwrap();
+    let result = serde_json::from_slice(&output.stdout).unwrap_or_else(|e| {
+        panic!(
+            "{:?} printed no single JSON object ({}): {}",
+            args,
+            e,
+            String::from_utf8_lossy(&output.stdout)
+        )
+    });
+    (output.status.code().unwrap(), result)
+}
+
+#[tokio::test]
+async fn commands_print_their_results() {
+    let cluster = TestCluster::start_with(2, |config| {
+        config.common.replication_factor = 2;
+        config.common.chunk_size = CHUNK_SIZE;
+    })
+    .await
+    .unwrap();
+    let config_path = cluster.write_config().unwrap();
+    let data: Vec<u8> = (0..2500u32).map(|i| (i % 233) as u8).collect();
+
+    let (code, upload) = client(&config_path, &["upload", "-", "/dir/file"], &data);
+    assert_eq!(code, 0, "{}", upload);
+    assert_eq!(upload["file_name"], "/dir/file");
+    assert_eq!(upload["bytes"], 2500);
+    let chunks = upload["chunks"].as_array().unwrap();
+    assert_eq!(chunks.len(), 3);
+    for chunk in chunks {
+        assert_eq!(chunk["replicas"].as_array().unwrap().len(), 2, "{}", chunk);
+    }
+
+    let mut rustfs = cluster.client().await.unwrap();
+    let mut read = Vec::new();
+    let summary = rustfs
+        .read_range_to("/dir/file", 0, u64::MAX, &mut read)
+        .await
+        .unwrap();
+    let (code, result) = client(&config_path, &["read", "/dir/file"], b"");
+    assert_eq!(code, 0, "{}", result);
+    assert_eq!(result["file_name"], "/dir/file");
+    assert_eq!(result["bytes"], 2500);
+    assert_eq!(result["crc32"], summary.crc32);
+
+    let (code, stat) = client(&config_path, &["stat", "/dir/file"], b"");
+    assert_eq!(code, 0, "{}", stat);
+    assert_eq!(stat["total_size"], 2500);
+    assert_eq!(stat["chunks"].as_array().unwrap().len(), 3);
+
+    let (code, listed) = client(&config_path, &["ls", "/dir"], b"");
+    assert_eq!(code, 0, "{}", listed);
+    let paths: Vec<&str> = listed
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| entry["path"].as_str().unwrap())
+        .collect();
+    assert_eq!(paths, vec!["/dir/file"]);
+
+    let (code, deleted) = client(&config_path, &["delete", "/dir/file"], b"");
+    assert_eq!(code, 0, "{}", deleted);
+    assert_eq!(deleted["deleted_files"][0], "/dir/file");
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn errors_are_printed_with_their_exit_code() {
+    let cluster = TestCluster::start_with(1, |config| {
+        config.common.replication_factor = 1;
+    })
+    .await
+    .unwrap();
+    let config_path = cluster.write_config().unwrap();
+
+    let (code, result) = client(&config_path, &["read", "/missing"], b"");
+    assert_eq!(code, 3);
+    assert_eq!(result["error"]["code"], 3);
+    assert!(
+        result["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("/missing"),
+        "{}",
+        result
+    );
+
+    cluster.shutdown().await.unwrap();
+}
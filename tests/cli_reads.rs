@@ -0,0 +1,131 @@
+// `cat`, `head` and `tail` print a file or its first or last bytes, reading only the chunks
+// needed unless the master does not know the chunk sizes
+use rustfs::testing::TestCluster;
+use std::process::Command;
+use std::sync::Arc;
+
+const CHUNK_SIZE: u64 = 1024;
+
+/// 3000 bytes over three chunks
+fn data() -> Vec<u8> {
+    (0..3000u32).map(|i| (i % 247) as u8).collect()
+}
+
+/// Run the client against the cluster with `args` and return what it printed
+fn client(config_path: &str, args: &[&str]) -> Vec<u8> {
+    let output = Command::new(env!("CARGO_BIN_EXE_client"))
+        .args(args)
+        .args(["--config", config_path, "--log-level", "error"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{:?}: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output.stdout
+}
+
+async fn start_cluster() -> TestCluster {
+    TestCluster::start_with(1, |config| {
+        config.common.replication_factor = 1;
+        config.common.chunk_size = CHUNK_SIZE;
+    })
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn files_are_printed_whole_or_in_part() {
+    let cluster = start_cluster().await;
+    let config_path = cluster.write_config().unwrap();
+    let mut rustfs = cluster.client().await.unwrap();
+    let data = data();
+    rustfs.upload_stream(&data[..], "/file").await.unwrap();
+    // Empty uploads store no file, emptying one leaves a chunk of size 0
+    rustfs
+        .upload_stream(&b"soon gone"[..], "/empty")
+        .await
+        .unwrap();
+    rustfs.truncate_file("/empty", 0).await.unwrap();
+
+    assert!(client(&config_path, &["cat", "/file"]) == data);
+    assert_eq!(
+        client(&config_path, &["head", "/file", "-n", "100"]),
+        &data[..100]
+    );
+    assert_eq!(
+        client(&config_path, &["tail", "/file", "-n", "100"]),
+        &data[2900..]
+    );
+    // More bytes than the file has
+    for command in ["head", "tail"] {
+        let printed = client(&config_path, &[command, "/file", "-n", "10000"]);
+        assert!(printed == data, "{}", command);
+    }
+    for command in ["cat", "head", "tail"] {
+        assert!(client(&config_path, &[command, "/empty"]).is_empty());
+    }
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn only_the_chunks_needed_are_read() {
+    let cluster = start_cluster().await;
+    let mut rustfs = cluster.client().await.unwrap();
+    let data = data();
+    rustfs.upload_stream(&data[..], "/file").await.unwrap();
+
+    let mut head = Vec::new();
+    let summary = rustfs
+        .read_range_to("/file", 0, 100, &mut head)
+        .await
+        .unwrap();
+    assert_eq!(head, &data[..100]);
+    assert_eq!(summary.chunks.len(), 1);
+    assert_eq!(summary.chunks[0].index, 0);
+
+    let mut tail = Vec::new();
+    let summary = rustfs.read_tail_to("/file", 100, &mut tail).await.unwrap();
+    assert_eq!(tail, &data[2900..]);
+    assert_eq!(summary.chunks.len(), 1);
+    assert_eq!(summary.chunks[0].index, 2);
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn files_of_unknown_size_are_read_to_the_end() {
+    let cluster = start_cluster().await;
+    let config_path = cluster.write_config().unwrap();
+    let mut rustfs = cluster.client().await.unwrap();
+    let data = data();
+    rustfs.upload_stream(&data[..], "/file").await.unwrap();
+
+    // Forget the chunk sizes, as for chunks whose size was never reported
+    let master = cluster.master();
+    {
+        let mut file_chunks = master.file_chunks.write().await;
+        let mut chunk_map = master.chunk_map.write().await;
+        for chunk in file_chunks.get_mut("/file").unwrap() {
+            let mut unsized_chunk = (**chunk).clone();
+            unsized_chunk.size = 0;
+            *chunk = Arc::new(unsized_chunk);
+            chunk_map.insert(chunk.chunk_id.clone(), chunk.clone());
+        }
+    }
+
+    assert!(client(&config_path, &["cat", "/file"]) == data);
+    assert_eq!(
+        client(&config_path, &["head", "/file", "-n", "100"]),
+        &data[..100]
+    );
+    assert_eq!(
+        client(&config_path, &["tail", "/file", "-n", "100"]),
+        &data[2900..]
+    );
+
+    cluster.shutdown().await.unwrap();
+}
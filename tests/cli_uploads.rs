@@ -0,0 +1,84 @@
+// The client uploads local files under their file name or a given remote name, and stdin
+use rustfs::testing::TestCluster;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+use uuid::Uuid;
+
+const CHUNK_SIZE: u64 = 1024;
+
+/// Run the client against the cluster with `args`, feeding it `stdin`
+fn client(config_path: &str, args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_client"))
+        .args(args)
+        .args(["--config", config_path])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(
+        output.status.success(),
+        "{:?}: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+async fn start_cluster() -> TestCluster {
+    TestCluster::start_with(1, |config| {
+        config.common.replication_factor = 1;
+        config.common.chunk_size = CHUNK_SIZE;
+    })
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn local_files_are_uploaded_under_their_file_name_or_a_remote_name() {
+    let cluster = start_cluster().await;
+    let config_path = cluster.write_config().unwrap();
+    let dir = std::env::temp_dir().join(format!("rustfs-upload-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let local_path = dir.join("artifacts.tar");
+    std::fs::write(&local_path, b"build artifacts").unwrap();
+    let local_path = local_path.to_str().unwrap();
+
+    client(&config_path, &["upload", local_path], b"");
+    client(
+        &config_path,
+        &["upload", local_path, "/builds/latest.tar"],
+        b"",
+    );
+
+    let mut rustfs = cluster.client().await.unwrap();
+    for name in ["/artifacts.tar", "/builds/latest.tar"] {
+        let read = rustfs.read_range(name, 0, u64::MAX).await.unwrap();
+        assert_eq!(read, b"build artifacts", "{}", name);
+    }
+    // Nothing was stored under the local path
+    rustfs.get_file_info(local_path).await.unwrap_err();
+
+    std::fs::remove_dir_all(dir).unwrap();
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn stdin_is_uploaded_over_several_chunks() {
+    let cluster = start_cluster().await;
+    let config_path = cluster.write_config().unwrap();
+    let data: Vec<u8> = (0..5000u32).map(|i| (i % 249) as u8).collect();
+
+    client(&config_path, &["upload", "-", "/from-stdin"], &data);
+
+    let mut rustfs = cluster.client().await.unwrap();
+    let info = rustfs.get_file_info("/from-stdin").await.unwrap();
+    assert_eq!(info.total_size, 5000);
+    assert_eq!(info.chunks.len(), 5);
+    let read = rustfs.read_range("/from-stdin", 0, u64::MAX).await.unwrap();
+    assert!(read == data);
+
+    cluster.shutdown().await.unwrap();
+}
@@ -0,0 +1,38 @@
+// Command line mistakes are reported by the client before it reads the config or connects
+use std::process::{Command, Output};
+
+/// Run the client with `args` and a config file that does not exist
+fn client(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_client"))
+        .args(args)
+        .env("RUSTFS_CONFIG", "/nonexistent/rustfs.toml")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn missing_command_prints_usage() {
+    let output = client(&[]);
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Usage: client <command>"), "{}", stderr);
+}
+
+#[test]
+fn invalid_commands_are_usage_errors() {
+    for (args, message) in [
+        (&["frobnicate"][..], "Invalid command"),
+        (&["read"][..], "Usage: read"),
+        (&["truncate", "/file", "many"][..], "Usage: truncate"),
+        (&["read", "/file", "-u"][..], "Missing username after -u"),
+        (
+            &["read", "/file", "--output"][..],
+            "Missing value after --output",
+        ),
+    ] {
+        let output = client(args);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(2), "{:?}: {}", args, stderr);
+        assert!(stderr.contains(message), "{:?}: {}", args, stderr);
+    }
+}
@@ -0,0 +1,46 @@
+// Upload, read and delete files through a client against an in-process cluster
+use rustfs::error::Error;
+use rustfs::testing::TestCluster;
+
+#[tokio::test]
+async fn upload_read_delete_round_trip() {
+    let cluster = TestCluster::start_with(3, |config| {
+        config.common.chunk_size = 1024;
+        config.common.replication_factor = 2;
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+
+    // Several chunks, the last one partial
+    let data: Vec<u8> = (0..2500u32).map(|i| (i % 251) as u8).collect();
+    let summary = client
+        .upload_stream(data.as_slice(), "/round-trip.bin")
+        .await
+        .unwrap();
+    assert_eq!(summary.file_name, "/round-trip.bin");
+    assert_eq!(summary.bytes, data.len() as u64);
+    assert_eq!(summary.chunks.len(), 3);
+    assert!(summary.chunks.iter().all(|chunk| chunk.replicas.len() == 2));
+
+    let read = client
+        .read_range("/round-trip.bin", 0, u64::MAX)
+        .await
+        .unwrap();
+    assert_eq!(read, data);
+    let middle = client
+        .read_range("/round-trip.bin", 1000, 100)
+        .await
+        .unwrap();
+    assert_eq!(middle, data[1000..1100]);
+
+    client.delete_file("/round-trip.bin").await.unwrap();
+    let deleted = client.read_range("/round-trip.bin", 0, u64::MAX).await;
+    assert!(
+        matches!(deleted, Err(Error::NotFound(_))),
+        "read after delete returned {:?}",
+        deleted.map(|data| data.len())
+    );
+
+    cluster.shutdown().await.unwrap();
+}
@@ -0,0 +1,224 @@
+// Chunk files written compressed or not read back the data as it was written
+use rustfs::chunkserver_service::TRANSFER_FRAME_SIZE;
+use rustfs::compression::{
+    encode_frames, framed_size, is_framed, ChunkReader, ChunkWriter, Compression, FRAMED_MAGIC,
+};
+use rustfs::testing::TestCluster;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+
+const COMPRESSIONS: [Compression; 3] = [Compression::None, Compression::Lz4, Compression::Zstd];
+
+/// Path of a file in a directory of the test process
+fn temp_path(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rustfs-compression-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join(name)
+}
+
+/// Compressible text followed by bytes that do not compress
+fn sample(len: usize) -> Vec<u8> {
+    let mut state = 0x2545_f491_u32;
+    (0..len)
+        .map(|i| {
+            if i < len / 2 {
+                b"compressible "[i % 13]
+            } else {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            }
+        })
+        .collect()
+}
+
+/// Write `data` with a ChunkWriter, in pieces of `piece` bytes
+async fn write_chunk(path: &PathBuf, compression: Compression, data: &[u8], piece: usize) {
+    let mut writer = ChunkWriter::new(File::create(path).await.unwrap(), compression);
+    for part in data.chunks(piece) {
+        writer.write(part).await.unwrap();
+    }
+    writer.finish().await.unwrap().sync_all().await.unwrap();
+}
+
+/// All data of a chunk file, read from `offset` on
+async fn read_chunk(path: &PathBuf, offset: u64) -> std::io::Result<Vec<u8>> {
+    let mut reader = ChunkReader::new(File::open(path).await?).await?;
+    reader.seek(offset).await?;
+    let mut data = Vec::new();
+    let mut buffer = vec![0; 100_000];
+    loop {
+        let n = reader.read(&mut buffer).await?;
+        if n == 0 {
+            return Ok(data);
+        }
+        data.extend_from_slice(&buffer[..n]);
+    }
+}
+
+#[tokio::test]
+async fn round_trip() {
+    let path = temp_path("round-trip");
+    for compression in COMPRESSIONS {
+        for len in [0, 1, 7, 8, TRANSFER_FRAME_SIZE, TRANSFER_FRAME_SIZE * 5 / 2] {
+            let data = sample(len);
+            write_chunk(&path, compression, &data, 70_000).await;
+            let context = format!("{:?}, {} byte(s)", compression, len);
+            assert_eq!(read_chunk(&path, 0).await.unwrap(), data, "{}", context);
+
+            let mut file = File::open(&path).await.unwrap();
+            let framed = is_framed(&mut file).await.unwrap();
+            assert_eq!(framed, compression != Compression::None, "{}", context);
+            if framed {
+                assert_eq!(
+                    framed_size(&mut file).await.unwrap(),
+                    len as u64,
+                    "{}",
+                    context
+                );
+            }
+            for offset in [1, TRANSFER_FRAME_SIZE, TRANSFER_FRAME_SIZE + 1, len] {
+                if offset <= len {
+                    let read = read_chunk(&path, offset as u64).await.unwrap();
+                    assert_eq!(read, data[offset..], "{} from {}", context, offset);
+                }
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn data_starting_with_the_magic_is_framed() {
+    let path = temp_path("magic");
+    let mut data = FRAMED_MAGIC.to_vec();
+    data.extend_from_slice(b" is not a header");
+    write_chunk(&path, Compression::None, &data, 3).await;
+    assert!(is_framed(&mut File::open(&path).await.unwrap())
+        .await
+        .unwrap());
+    assert_eq!(read_chunk(&path, 0).await.unwrap(), data);
+}
+
+#[tokio::test]
+async fn frames_of_mixed_codecs() {
+    // As appended by chunkservers whose compression setting changed in between
+    let path = temp_path("mixed");
+    let first = sample(TRANSFER_FRAME_SIZE + 10);
+    write_chunk(&path, Compression::Lz4, &first, TRANSFER_FRAME_SIZE).await;
+    let mut expected = first;
+    let mut file = OpenOptions::new().append(true).open(&path).await.unwrap();
+    for (compression, len) in [
+        (Compression::Zstd, 1000),
+        (Compression::None, TRANSFER_FRAME_SIZE + 1),
+        (Compression::Lz4, 5),
+    ] {
+        let data = sample(len);
+        file.write_all(&encode_frames(compression, &data))
+            .await
+            .unwrap();
+        expected.extend_from_slice(&data);
+    }
+    file.sync_all().await.unwrap();
+
+    assert_eq!(read_chunk(&path, 0).await.unwrap(), expected);
+    let offset = TRANSFER_FRAME_SIZE + 500;
+    assert_eq!(
+        read_chunk(&path, offset as u64).await.unwrap(),
+        expected[offset..]
+    );
+    let mut file = File::open(&path).await.unwrap();
+    assert!(is_framed(&mut file).await.unwrap());
+    assert_eq!(framed_size(&mut file).await.unwrap(), expected.len() as u64);
+}
+
+#[tokio::test]
+async fn truncated_and_corrupt_frame_headers_are_invalid() {
+    let path = temp_path("corrupt");
+    let data = sample(1000);
+    let mut framed = FRAMED_MAGIC.to_vec();
+    framed.extend(encode_frames(Compression::Lz4, &data));
+
+    // A header cut short after 1 to 8 of its 9 bytes
+    for header_bytes in 1..9 {
+        let mut content = framed.clone();
+        content.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0][..header_bytes]);
+        std::fs::write(&path, &content).unwrap();
+        let error = read_chunk(&path, 0).await.unwrap_err();
+        assert_eq!(
+            error.kind(),
+            ErrorKind::InvalidData,
+            "{}: {}",
+            header_bytes,
+            error
+        );
+        let mut file = File::open(&path).await.unwrap();
+        assert!(is_framed(&mut file).await.unwrap());
+        assert_eq!(
+            framed_size(&mut file).await.unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+    }
+
+    // Lengths no frame can have are rejected before a buffer of that size is allocated
+    let too_long = (TRANSFER_FRAME_SIZE as u32 + 1).to_le_bytes();
+    for (stored_len, len) in [
+        (u32::MAX.to_le_bytes(), u32::MAX.to_le_bytes()),
+        (too_long, too_long),
+        (100u32.to_le_bytes(), 10u32.to_le_bytes()),
+    ] {
+        let mut content = FRAMED_MAGIC.to_vec();
+        content.push(1);
+        content.extend_from_slice(&stored_len);
+        content.extend_from_slice(&len);
+        std::fs::write(&path, &content).unwrap();
+        let error = read_chunk(&path, 0).await.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData, "{}", error);
+    }
+}
+
+#[tokio::test]
+async fn compressed_cluster_round_trip() {
+    for compression in ["lz4", "zstd"] {
+        let cluster = TestCluster::start_with(2, |config| {
+            config.common.replication_factor = 2;
+            config.chunkserver.compression = compression.to_string();
+        })
+        .await
+        .unwrap();
+        let mut client = cluster.client().await.unwrap();
+        let data = sample(TRANSFER_FRAME_SIZE * 3 / 2);
+        client
+            .upload_stream(&data[..], "/compressed")
+            .await
+            .unwrap();
+        let servers = client
+            .get_append_server_addresses("/compressed")
+            .await
+            .unwrap();
+        client
+            .append_file(servers, "/compressed", "appended".to_string())
+            .await
+            .unwrap();
+
+        let mut expected = data;
+        expected.extend_from_slice(b"appended");
+        let read = client.read_range("/compressed", 0, u64::MAX).await.unwrap();
+        assert_eq!(read, expected, "{}", compression);
+        let read = client.read_range("/compressed", 100, 1000).await.unwrap();
+        assert_eq!(read, expected[100..1100], "{}", compression);
+
+        // Each replica is stored framed
+        let info = client.get_file_info("/compressed").await.unwrap();
+        for index in 0..2 {
+            let path = cluster
+                .chunkserver(index)
+                .chunk_path(&info.chunks[0].chunk_id);
+            let mut file = File::open(&path).await.unwrap();
+            assert!(is_framed(&mut file).await.unwrap(), "{}", path);
+        }
+        cluster.shutdown().await.unwrap();
+    }
+}
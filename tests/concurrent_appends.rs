@@ -0,0 +1,60 @@
+// Concurrent appends to the same chunk are applied one at a time, in the same order on all replicas
+use rustfs::testing::TestCluster;
+
+const APPENDS: usize = 50;
+
+/// A record whose bytes all identify it, so an interleaved write shows up as a torn record
+fn record(index: usize) -> String {
+    let fill = char::from(b'a' + (index % 26) as u8);
+    format!("<{:02}{}>", index, fill.to_string().repeat(5 + index % 7))
+}
+
+#[tokio::test]
+async fn concurrent_appends_are_not_torn() {
+    let cluster = TestCluster::start_with(3, |config| {
+        config.common.replication_factor = 3;
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    client.upload_stream(&b"start"[..], "/log").await.unwrap();
+
+    let mut appends = tokio::task::JoinSet::new();
+    for index in 0..APPENDS {
+        let mut client = cluster.client().await.unwrap();
+        appends.spawn(async move {
+            let servers = client.get_append_server_addresses("/log").await?;
+            client.append_file(servers, "/log", record(index)).await
+        });
+    }
+    while let Some(appended) = appends.join_next().await {
+        appended.unwrap().unwrap();
+    }
+
+    let expected_len = "start".len() + (0..APPENDS).map(|i| record(i).len()).sum::<usize>();
+    let info = client.get_file_info("/log").await.unwrap();
+    assert_eq!(info.total_size, expected_len as u64);
+    let content = client.read_range("/log", 0, u64::MAX).await.unwrap();
+    assert_eq!(content.len(), expected_len);
+
+    // Every record appears once and whole, in some order
+    let content = String::from_utf8(content).unwrap();
+    let mut records: Vec<&str> = content
+        .strip_prefix("start")
+        .unwrap()
+        .split_inclusive('>')
+        .collect();
+    records.sort();
+    let mut expected: Vec<String> = (0..APPENDS).map(record).collect();
+    expected.sort();
+    assert_eq!(records, expected);
+
+    // All replicas hold the records in the same order
+    let chunk_id = &info.chunks[0].chunk_id;
+    for index in 0..3 {
+        let path = cluster.chunkserver(index).chunk_path(chunk_id);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), content, "{}", path);
+    }
+
+    cluster.shutdown().await.unwrap();
+}
@@ -0,0 +1,97 @@
+// Many uploads assigned at once neither deadlock the master nor hold up the heartbeats of the
+// chunkservers
+use futures::future::join_all;
+use rustfs::proto::master::master_client::MasterClient;
+use rustfs::proto::master::{AssignRequest, HeartbeatRequest};
+use rustfs::testing::TestCluster;
+use rustfs::util::connect_channel;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+const ASSIGNS: usize = 100;
+const CHUNK_SIZE: u64 = 1024;
+
+#[tokio::test]
+async fn concurrent_assigns_and_heartbeats_complete() {
+    let cluster = TestCluster::start_with(3, |config| {
+        config.common.replication_factor = 2;
+        config.common.chunk_size = CHUNK_SIZE;
+    })
+    .await
+    .unwrap();
+    let channel = connect_channel(cluster.master_addr(), &cluster.config().common.tls)
+        .await
+        .unwrap();
+    let master = MasterClient::new(channel);
+
+    let assigns = (0..ASSIGNS).map(|index| {
+        let mut master = master.clone();
+        async move {
+            let started = Instant::now();
+            master
+                .assign_chunks(AssignRequest {
+                    file_name: format!("/file-{}", index),
+                    file_size: 3 * CHUNK_SIZE,
+                    token: String::new(),
+                    operation_id: String::new(),
+                })
+                .await
+                .unwrap();
+            started.elapsed()
+        }
+    });
+    // Heartbeats of a server holding no chunks, sent one after another meanwhile
+    let heartbeats = {
+        let mut master = master.clone();
+        async move {
+            let mut latencies = Vec::new();
+            for generation in 1..=20 {
+                let started = Instant::now();
+                master
+                    .heartbeat(HeartbeatRequest {
+                        chunkserver_address: "127.0.0.1:1".to_string(),
+                        generation,
+                        ..Default::default()
+                    })
+                    .await
+                    .unwrap();
+                latencies.push(started.elapsed());
+            }
+            latencies
+        }
+    };
+
+    let (assign_latencies, heartbeat_latencies) =
+        tokio::time::timeout(Duration::from_secs(60), async {
+            tokio::join!(join_all(assigns), heartbeats)
+        })
+        .await
+        .expect("The assignments did not finish, the master may be deadlocked");
+
+    let slowest = *assign_latencies.iter().max().unwrap();
+    assert!(
+        slowest < Duration::from_secs(10),
+        "Slowest assign took {:?}",
+        slowest
+    );
+    let slowest = heartbeat_latencies.iter().max().unwrap();
+    assert!(
+        *slowest < Duration::from_secs(2),
+        "Slowest heartbeat took {:?}",
+        slowest
+    );
+
+    // Every file got its own chunks
+    let master = cluster.master();
+    let file_chunks = master.file_chunks.read().await;
+    assert_eq!(file_chunks.len(), ASSIGNS);
+    let chunk_ids: HashSet<&String> = file_chunks
+        .values()
+        .flatten()
+        .map(|chunk| &chunk.chunk_id)
+        .collect();
+    assert_eq!(chunk_ids.len(), 3 * ASSIGNS);
+    drop(file_chunks);
+
+    cluster.shutdown().await.unwrap();
+}
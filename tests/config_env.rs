@@ -0,0 +1,148 @@
+// Environment variables overriding settings of the config file
+use rustfs::config::{apply_env_overrides, Config};
+use std::time::Duration;
+
+const CONFIG: &str = r#"
+[common]
+master_addrs = ["127.0.0.1:50001"]
+chunk_size = "64MiB"
+use_authentication = false
+
+[chunkserver]
+data_path = "data"
+"#;
+
+/// Apply the variables to `CONFIG`
+fn with_env(vars: &[(&str, &str)]) -> Result<Config, Vec<String>> {
+    let mut settings: toml::Value = toml::from_str(CONFIG).unwrap();
+    apply_env_overrides(
+        &mut settings,
+        vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string())),
+    )
+    .map_err(|e| e.violations)?;
+    Ok(settings.try_into().unwrap())
+}
+
+#[test]
+fn variables_override_the_file() {
+    let config = with_env(&[
+        ("RUSTFS_COMMON__CHUNK_SIZE", "1MiB"),
+        ("RUSTFS_COMMON__USE_AUTHENTICATION", "true"),
+        ("RUSTFS_COMMON__REPLICATION_FACTOR", " 2 "),
+        ("RUSTFS_COMMON__HEARTBEAT_INTERVAL", "10s"),
+        ("RUSTFS_CHUNKSERVER__DATA_PATH", "/data"),
+    ])
+    .unwrap();
+    assert_eq!(config.common.chunk_size, 1 << 20);
+    assert!(config.common.use_authentication);
+    assert_eq!(config.common.replication_factor, 2);
+    assert_eq!(config.common.heartbeat_interval, Duration::from_secs(10));
+    assert_eq!(config.chunkserver.data_path, vec!["/data".to_string()]);
+}
+
+#[test]
+fn settings_missing_from_the_file_are_added() {
+    let config = with_env(&[
+        ("RUSTFS_MASTER__LEASE_DURATION", "2m"),
+        ("RUSTFS_COMMON__TLS__ENABLED", "1"),
+    ])
+    .unwrap();
+    assert_eq!(config.master.lease_duration, Duration::from_secs(120));
+    assert!(config.common.tls.enabled);
+}
+
+#[test]
+fn lists_are_comma_separated() {
+    let config = with_env(&[(
+        "RUSTFS_COMMON__MASTER_ADDRS",
+        "10.0.0.1:50001, 10.0.0.2:50001,,",
+    )])
+    .unwrap();
+    assert_eq!(
+        config.common.master_addrs,
+        vec!["10.0.0.1:50001".to_string(), "10.0.0.2:50001".to_string()]
+    );
+
+    let config = with_env(&[("RUSTFS_CHUNKSERVER__DATA_PATH", "/data1,/data2")]).unwrap();
+    assert_eq!(
+        config.chunkserver.data_path,
+        vec!["/data1".to_string(), "/data2".to_string()]
+    );
+}
+
+#[test]
+fn values_of_the_wrong_type_are_rejected() {
+    for (name, value, expected) in [
+        (
+            "RUSTFS_COMMON__REPLICATION_FACTOR",
+            "three",
+            "non-negative integer",
+        ),
+        (
+            "RUSTFS_COMMON__REPLICATION_FACTOR",
+            "-1",
+            "non-negative integer",
+        ),
+        (
+            "RUSTFS_COMMON__REPLICATION_FACTOR",
+            "1.5",
+            "non-negative integer",
+        ),
+        ("RUSTFS_COMMON__USE_AUTHENTICATION", "yes", "true or false"),
+        ("RUSTFS_COMMON__TLS", "on", "section"),
+    ] {
+        let violations = with_env(&[(name, value)]).unwrap_err();
+        assert_eq!(violations.len(), 1, "{:?}", violations);
+        assert!(
+            violations[0].starts_with(name) && violations[0].contains(expected),
+            "{}={}: {:?}",
+            name,
+            value,
+            violations
+        );
+    }
+}
+
+#[test]
+fn invalid_durations_and_sizes_are_rejected_when_parsed() {
+    for (name, value) in [
+        ("RUSTFS_COMMON__CHUNK_SIZE", "lots"),
+        ("RUSTFS_COMMON__HEARTBEAT_INTERVAL", "5 fortnights"),
+    ] {
+        let mut settings: toml::Value = toml::from_str(CONFIG).unwrap();
+        apply_env_overrides(&mut settings, [(name.to_string(), value.to_string())]).unwrap();
+        let error = settings.try_into::<Config>().unwrap_err().to_string();
+        assert!(error.contains(value), "{}={}: {}", name, value, error);
+    }
+}
+
+#[test]
+fn unknown_settings_are_rejected() {
+    let violations = with_env(&[("RUSTFS_COMMON__CHUNKSIZE", "1MiB")]).unwrap_err();
+    assert_eq!(violations.len(), 1);
+    assert!(violations[0].contains("does not name a setting"));
+}
+
+#[test]
+fn every_invalid_variable_is_reported() {
+    let violations = with_env(&[
+        ("RUSTFS_COMMON__REPLICATION_FACTOR", "three"),
+        ("RUSTFS_COMMON__USE_AUTHENTICATION", "yes"),
+        ("RUSTFS_NOPE__NOTHING", "1"),
+        ("RUSTFS_COMMON__CHUNK_SIZE", "1MiB"),
+    ])
+    .unwrap_err();
+    assert_eq!(violations.len(), 3, "{:?}", violations);
+}
+
+#[test]
+fn other_variables_are_ignored() {
+    let config = with_env(&[
+        ("RUSTFS_CONFIG", "other.toml"),
+        ("PATH", "/usr/bin"),
+        ("COMMON__CHUNK_SIZE", "1"),
+    ])
+    .unwrap();
+    assert_eq!(config.common.chunk_size, 64 << 20);
+}
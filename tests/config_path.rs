@@ -0,0 +1,64 @@
+// The config file is given by --config, otherwise by RUSTFS_CONFIG, otherwise it is config.toml in
+// the working directory
+use rustfs::config::{resolve_config_path, CONFIG_ENV_VAR};
+use rustfs::testing::TestCluster;
+use std::process::{Command, Output};
+use uuid::Uuid;
+
+#[test]
+fn flag_goes_before_the_variable_and_the_default() {
+    assert_eq!(
+        resolve_config_path(Some("flag.toml"), Some("env.toml")),
+        "flag.toml"
+    );
+    assert_eq!(resolve_config_path(None, Some("env.toml")), "env.toml");
+    assert_eq!(resolve_config_path(None, Some("")), "config.toml");
+    assert_eq!(resolve_config_path(None, None), "config.toml");
+}
+
+/// Run `client ls /` in an empty directory, with `args` and `RUSTFS_CONFIG` set to `env_value`
+fn list(dir: &std::path::Path, args: &[&str], env_value: Option<&str>) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_client"));
+    command
+        .args(["ls", "/", "--log-level", "error"])
+        .args(args)
+        .current_dir(dir)
+        .env_remove(CONFIG_ENV_VAR);
+    if let Some(value) = env_value {
+        command.env(CONFIG_ENV_VAR, value);
+    }
+    command.output().unwrap()
+}
+
+#[tokio::test]
+async fn client_loads_the_resolved_file() {
+    let cluster = TestCluster::start(1).await.unwrap();
+    let config_path = cluster.write_config().unwrap();
+    let dir = std::env::temp_dir().join(format!("rustfs-config-path-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let missing = dir.join("missing.toml").to_string_lossy().to_string();
+
+    let output = list(&dir, &["--config", &config_path], Some(&missing));
+    assert!(output.status.success(), "{:?}", output);
+    let output = list(&dir, &[], Some(&config_path));
+    assert!(output.status.success(), "{:?}", output);
+
+    // The error names the file that was tried
+    let output = list(&dir, &["--config", &missing], Some(&config_path));
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains(&missing),
+        "{:?}",
+        output
+    );
+    let output = list(&dir, &[], None);
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("config.toml"),
+        "{:?}",
+        output
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    cluster.shutdown().await.unwrap();
+}
@@ -0,0 +1,188 @@
+// Settings rejected by Config::validate, each reported with the setting it concerns
+use rustfs::config::Config;
+use std::time::Duration;
+
+/// Violations of the default config with a master address, changed by `change`
+fn violations(change: impl FnOnce(&mut Config)) -> Vec<String> {
+    let mut config = Config::default();
+    config.common.master_addrs = vec!["127.0.0.1:50001".to_string()];
+    change(&mut config);
+    match config.validate() {
+        Ok(()) => Vec::new(),
+        Err(e) => e.violations,
+    }
+}
+
+/// Assert that `change` makes the config invalid for exactly one reason, which names `setting`
+fn assert_rejected(setting: &str, change: impl FnOnce(&mut Config)) {
+    let violations = violations(change);
+    assert_eq!(violations.len(), 1, "{}: {:?}", setting, violations);
+    assert!(
+        violations[0].contains(setting),
+        "{} is not named in {:?}",
+        setting,
+        violations[0]
+    );
+}
+
+#[test]
+fn default_config_is_valid() {
+    assert_eq!(violations(|_| {}), Vec::<String>::new());
+    // Except that the masters must be given
+    assert_eq!(
+        Config::default().validate().unwrap_err().violations.len(),
+        1
+    );
+}
+
+#[test]
+fn master_addrs() {
+    assert_rejected("common.master_addrs", |config| {
+        config.common.master_addrs.clear()
+    });
+    for addr in [
+        "localhost",
+        ":50001",
+        "localhost:0",
+        "localhost:port",
+        "host:70000",
+    ] {
+        assert_rejected("common.master_addrs", |config| {
+            config.common.master_addrs = vec![addr.to_string()]
+        });
+    }
+    assert_eq!(
+        violations(|config| {
+            config.common.master_addrs = vec!["10.0.0.1:50001".into(), "[::1]:50001".into()]
+        }),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn counts_of_zero() {
+    assert_rejected("common.chunk_size", |config| config.common.chunk_size = 0);
+    assert_rejected("common.replication_factor", |config| {
+        config.common.replication_factor = 0
+    });
+    assert_rejected("common.max_allowed_chunks", |config| {
+        config.common.max_allowed_chunks = 0
+    });
+    assert_rejected("master.heartbeat_failure_threshold", |config| {
+        config.master.heartbeat_failure_threshold = 0
+    });
+    assert_rejected("master.shadow_master_failure_threshold", |config| {
+        config.master.shadow_master_failure_threshold = 0
+    });
+    assert_rejected("chunkserver.read_buffers", |config| {
+        config.chunkserver.read_buffers = 0
+    });
+    assert_rejected("client.retry_attempts", |config| {
+        config.client.retry_attempts = 0
+    });
+}
+
+#[test]
+fn intervals_below_a_second() {
+    assert_rejected("common.heartbeat_interval", |config| {
+        config.common.heartbeat_interval = Duration::ZERO
+    });
+    assert_rejected("common.shadow_master_ping_interval", |config| {
+        config.common.shadow_master_ping_interval = Duration::from_millis(500)
+    });
+    assert_rejected("master.cron_interval", |config| {
+        config.master.cron_interval = Duration::ZERO
+    });
+    assert_rejected("master.lease_duration", |config| {
+        config.master.lease_duration = Duration::from_millis(999)
+    });
+    assert_eq!(
+        violations(|config| config.master.cron_interval = Duration::from_secs(1)),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn timeouts_of_zero() {
+    assert_rejected("master.metadata_send_timeout", |config| {
+        config.master.metadata_send_timeout = Duration::ZERO
+    });
+    assert_rejected("client.connect_timeout", |config| {
+        config.client.connect_timeout = Duration::ZERO
+    });
+    assert_rejected("client.master_rpc_timeout", |config| {
+        config.client.master_rpc_timeout = Duration::ZERO
+    });
+    assert_rejected("client.chunk_rpc_timeout", |config| {
+        config.client.chunk_rpc_timeout = Duration::ZERO
+    });
+}
+
+#[test]
+fn write_quorum_within_replication_factor() {
+    assert_rejected("common.write_quorum", |config| {
+        config.common.write_quorum = 0
+    });
+    assert_rejected("common.write_quorum", |config| {
+        config.common.replication_factor = 2;
+        config.common.write_quorum = 3;
+    });
+}
+
+#[test]
+fn authentication_settings() {
+    assert_rejected("common.cluster_secret", |config| {
+        config.common.use_authentication = true;
+        config.common.cluster_secret.clear();
+    });
+    assert_rejected("common.otp_valid_duration", |config| {
+        config.common.use_authentication = true;
+        config.common.cluster_secret = "secret".into();
+        config.common.otp_valid_duration = Duration::ZERO;
+    });
+    // Only required with authentication
+    assert_eq!(
+        violations(|config| config.common.cluster_secret.clear()),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn names_and_addresses() {
+    assert_rejected("common.log_level", |config| {
+        config.common.log_level = "rustfs=loud".into()
+    });
+    assert_rejected("master.placement_policy", |config| {
+        config.master.placement_policy = "anywhere".into()
+    });
+    assert_rejected("chunkserver.compression", |config| {
+        config.chunkserver.compression = "zip".into()
+    });
+    assert_rejected("master.metrics_addr", |config| {
+        config.master.metrics_addr = "localhost".into()
+    });
+    assert_rejected("chunkserver.bind_addr", |config| {
+        config.chunkserver.bind_addr = "0.0.0.0".into()
+    });
+}
+
+#[test]
+fn data_paths() {
+    assert_rejected("chunkserver.data_path", |config| {
+        config.chunkserver.data_path.clear()
+    });
+    assert_rejected("chunkserver.data_path", |config| {
+        config.chunkserver.data_path = vec!["/data1".into(), "/data1".into()]
+    });
+}
+
+#[test]
+fn every_violation_is_reported() {
+    let violations = violations(|config| {
+        config.common.chunk_size = 0;
+        config.common.replication_factor = 0;
+        config.common.master_addrs.clear();
+        config.common.heartbeat_interval = Duration::ZERO;
+    });
+    assert_eq!(violations.len(), 4, "{:?}", violations);
+}
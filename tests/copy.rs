@@ -0,0 +1,110 @@
+// Files are copied by the chunkservers into new chunks, and the copy is only registered once
+// every chunk was copied
+use rustfs::error::Error;
+use rustfs::testing::TestCluster;
+
+const CHUNK_SIZE: u64 = 1024;
+
+/// 3000 bytes over three chunks
+fn data() -> Vec<u8> {
+    (0..3000u32).map(|i| (i % 239) as u8).collect()
+}
+
+#[tokio::test]
+async fn copy_reads_back_identically_and_is_independent() {
+    let cluster = TestCluster::start_with(3, |config| {
+        config.common.replication_factor = 2;
+        config.common.chunk_size = CHUNK_SIZE;
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    let data = data();
+    client.upload_stream(&data[..], "/source").await.unwrap();
+
+    let status = client.copy("/source", "/dir/copy").await.unwrap();
+    assert!(!status.running);
+    assert_eq!((status.chunks_copied, status.chunks_total), (3, 3));
+    let read = client.read_range("/dir/copy", 0, u64::MAX).await.unwrap();
+    assert!(read == data);
+
+    // The copy has chunks of its own, each with all its replicas
+    let source = client.get_file_info("/source").await.unwrap();
+    let copy = client.get_file_info("/dir/copy").await.unwrap();
+    assert_eq!(copy.total_size, source.total_size);
+    for (original, copied) in source.chunks.iter().zip(&copy.chunks) {
+        assert_ne!(original.chunk_id, copied.chunk_id);
+        assert_eq!(copied.server_addresses.len(), 2);
+    }
+
+    client.delete_file("/source").await.unwrap();
+    let read = client.read_range("/dir/copy", 0, u64::MAX).await.unwrap();
+    assert!(read == data);
+    client.delete_file("/dir/copy").await.unwrap();
+    client.get_file_info("/dir/copy").await.unwrap_err();
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn existing_destination_is_not_overwritten() {
+    let cluster = TestCluster::start_with(1, |config| {
+        config.common.replication_factor = 1;
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    client
+        .upload_stream(&b"source"[..], "/source")
+        .await
+        .unwrap();
+    client
+        .upload_stream(&b"destination"[..], "/destination")
+        .await
+        .unwrap();
+
+    client.copy("/source", "/destination").await.unwrap_err();
+    let read = client
+        .read_range("/destination", 0, u64::MAX)
+        .await
+        .unwrap();
+    assert_eq!(read, b"destination");
+    let err = client.copy("/missing", "/other").await.unwrap_err();
+    assert!(matches!(err, Error::NotFound(_)), "{}", err);
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn failed_copy_registers_no_file() {
+    let mut cluster = TestCluster::start_with(2, |config| {
+        config.common.replication_factor = 1;
+        config.common.chunk_size = CHUNK_SIZE;
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    client.upload_stream(&data()[..], "/source").await.unwrap();
+
+    // The only replica of the last chunk is gone
+    let info = client.get_file_info("/source").await.unwrap();
+    let lost = info.chunks.last().unwrap().server_addresses[0].clone();
+    let index = cluster
+        .chunkserver_addrs()
+        .iter()
+        .position(|addr| *addr == lost)
+        .unwrap();
+    cluster.kill_chunkserver(index);
+
+    client.copy("/source", "/copy").await.unwrap_err();
+    client.get_file_info("/copy").await.unwrap_err();
+    let master = cluster.master();
+    assert!(!master.file_chunks.read().await.contains_key("/copy"));
+    assert_eq!(
+        master.chunk_map.read().await.len(),
+        info.chunks.len(),
+        "Chunks of the failed copy were kept"
+    );
+
+    cluster.shutdown().await.unwrap();
+}
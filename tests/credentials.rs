@@ -0,0 +1,117 @@
+// Precedence of the credential sources: flags, environment, credential file, prompt
+use rustfs::credentials::{merge_credentials, Credentials, PartialCredentials};
+use rustfs::error::{Error, Result};
+
+fn source(username: Option<&str>, password: Option<&str>) -> PartialCredentials {
+    PartialCredentials {
+        username: username.map(str::to_string),
+        password: password.map(str::to_string),
+    }
+}
+
+fn none() -> PartialCredentials {
+    source(None, None)
+}
+
+fn credentials(username: &str, password: &str) -> Option<Credentials> {
+    Some(Credentials {
+        username: username.to_string(),
+        password: password.to_string(),
+    })
+}
+
+/// A prompt that must not be shown
+fn no_prompt(username: &str) -> Result<Option<String>> {
+    panic!("Prompted for the password of '{}'", username)
+}
+
+#[test]
+fn each_source_on_its_own() {
+    let flags = merge_credentials(source(Some("flag"), Some("fp")), none(), none(), no_prompt);
+    assert_eq!(flags.unwrap(), credentials("flag", "fp"));
+    let env = merge_credentials(none(), source(Some("env"), Some("ep")), none(), no_prompt);
+    assert_eq!(env.unwrap(), credentials("env", "ep"));
+    let file = merge_credentials(none(), none(), source(Some("file"), Some("xp")), no_prompt);
+    assert_eq!(file.unwrap(), credentials("file", "xp"));
+    let prompted = merge_credentials(source(Some("flag"), None), none(), none(), |username| {
+        assert_eq!(username, "flag");
+        Ok(Some("typed".to_string()))
+    });
+    assert_eq!(prompted.unwrap(), credentials("flag", "typed"));
+}
+
+#[test]
+fn flags_override_env_which_overrides_the_file() {
+    let all = || {
+        (
+            source(Some("flag"), Some("fp")),
+            source(Some("env"), Some("ep")),
+            source(Some("file"), Some("xp")),
+        )
+    };
+    let (flags, env, file) = all();
+    let merged = merge_credentials(flags, env, file, no_prompt).unwrap();
+    assert_eq!(merged, credentials("flag", "fp"));
+    let (_, env, file) = all();
+    let merged = merge_credentials(none(), env, file, no_prompt).unwrap();
+    assert_eq!(merged, credentials("env", "ep"));
+
+    // The username and password are resolved separately
+    let (_, env, _) = all();
+    let merged = merge_credentials(source(Some("flag"), None), env, none(), no_prompt).unwrap();
+    assert_eq!(merged, credentials("flag", "ep"));
+    let merged = merge_credentials(
+        none(),
+        source(None, Some("ep")),
+        source(Some("file"), Some("xp")),
+        no_prompt,
+    )
+    .unwrap();
+    assert_eq!(merged, credentials("file", "ep"));
+}
+
+#[test]
+fn file_password_only_for_its_own_user() {
+    let file = || source(Some("file"), Some("xp"));
+    let merged = merge_credentials(source(Some("other"), None), none(), file(), |_| Ok(None));
+    assert!(matches!(merged, Err(Error::Usage(_))), "{:?}", merged);
+    let merged = merge_credentials(source(Some("file"), None), none(), file(), no_prompt);
+    assert_eq!(merged.unwrap(), credentials("file", "xp"));
+    // A file without a username applies to any user
+    let merged = merge_credentials(
+        source(Some("other"), None),
+        none(),
+        source(None, Some("xp")),
+        no_prompt,
+    );
+    assert_eq!(merged.unwrap(), credentials("other", "xp"));
+}
+
+#[test]
+fn prompt_only_without_a_password() {
+    let merged = merge_credentials(none(), none(), none(), no_prompt).unwrap();
+    assert_eq!(merged, None);
+    // Not a terminal
+    let merged = merge_credentials(source(Some("user"), None), none(), none(), |_| Ok(None));
+    assert!(matches!(merged, Err(Error::Usage(_))), "{:?}", merged);
+}
+
+#[test]
+fn credential_file() {
+    let dir = std::env::temp_dir().join(format!("rustfs-credentials-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("credentials");
+    let missing = PartialCredentials::from_file(&path).unwrap();
+    assert_eq!((missing.username, missing.password), (None, None));
+
+    std::fs::write(&path, "username = \"file\"\npassword = \"xp\"\n").unwrap();
+    let file = PartialCredentials::from_file(&path).unwrap();
+    assert_eq!(
+        merge_credentials(none(), none(), file, no_prompt).unwrap(),
+        credentials("file", "xp")
+    );
+    std::fs::write(&path, "user = \"file\"\n").unwrap();
+    let invalid = PartialCredentials::from_file(&path);
+    assert!(matches!(invalid, Err(Error::Config(_))), "{:?}", invalid);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,75 @@
+// Chunkservers with several data directories spread new chunks over them and find each chunk in
+// the directory it was written to, also after a restart
+use rustfs::chunkserver_service::ChunkService;
+use rustfs::testing::TestCluster;
+use std::path::Path;
+
+const CHUNK_SIZE: u64 = 1024;
+
+#[tokio::test]
+async fn chunks_are_spread_and_resolved() {
+    let cluster = TestCluster::start_with(1, |config| {
+        config.common.replication_factor = 1;
+        config.common.chunk_size = CHUNK_SIZE;
+        config.chunkserver.data_path = vec!["disk-1".to_string(), "disk-2".to_string()];
+    })
+    .await
+    .unwrap();
+    let chunkserver = cluster.chunkserver(0);
+    let dirs = chunkserver.data_dirs();
+    let mut client = cluster.client().await.unwrap();
+    let data: Vec<u8> = (0..4 * CHUNK_SIZE as u32 - 512)
+        .map(|i| (i % 251) as u8)
+        .collect();
+    client.upload_stream(&data[..], "/file").await.unwrap();
+
+    // Four chunks, and both directories are on the same disk, so they take turns
+    let chunk_ids: Vec<String> = cluster.master().file_chunks.read().await["/file"]
+        .iter()
+        .map(|chunk| chunk.chunk_id.clone())
+        .collect();
+    let placed = chunkserver.chunk_dirs.lock().unwrap().clone();
+    for (index, dir) in dirs.iter().enumerate() {
+        let chunks = placed.values().filter(|dir| **dir == index).count();
+        assert_eq!(chunks, 2, "{} holds {} chunk(s)", dir, chunks);
+    }
+    for chunk_id in &chunk_ids {
+        let (dir, other) = match placed[chunk_id] {
+            0 => (&dirs[0], &dirs[1]),
+            _ => (&dirs[1], &dirs[0]),
+        };
+        assert!(Path::new(dir).join(chunk_id).exists());
+        assert!(!Path::new(other).join(chunk_id).exists());
+    }
+
+    // Reads and appends find the chunks wherever they are
+    let read = client.read_range("/file", 0, u64::MAX).await.unwrap();
+    assert_eq!(read, data);
+    let servers = client.get_append_server_addresses("/file").await.unwrap();
+    client
+        .append_file(servers, "/file", "appended".to_string())
+        .await
+        .unwrap();
+    let read = client.read_range("/file", 0, u64::MAX).await.unwrap();
+    assert_eq!(&read[..data.len()], &data[..]);
+    assert_eq!(&read[data.len()..], b"appended");
+
+    // A chunkserver started on the same directories finds every chunk where it was written
+    let restarted = ChunkService::new(
+        &chunkserver.addr,
+        &chunkserver.addr_sanitized,
+        chunkserver.config.clone(),
+        chunkserver.common_config.clone(),
+    );
+    restarted.prepare_data_dir().unwrap();
+    let scanned = restarted.chunk_dirs.lock().unwrap().clone();
+    assert_eq!(scanned, *chunkserver.chunk_dirs.lock().unwrap());
+    for chunk_id in &chunk_ids {
+        assert_eq!(
+            restarted.chunk_path(chunk_id),
+            chunkserver.chunk_path(chunk_id)
+        );
+    }
+
+    cluster.shutdown().await.unwrap();
+}
@@ -0,0 +1,77 @@
+// The master leaves replicas on chunkservers that missed their heartbeats out of the chunk lists
+// it hands out, unless that would leave a chunk without replicas
+use rustfs::master_service::MasterService;
+use rustfs::proto::master::master_client::MasterClient;
+use rustfs::proto::master::{FileChunkMapping, FileChunkMappingRequest};
+use rustfs::testing::TestCluster;
+use rustfs::util::connect_channel;
+use std::time::Duration;
+
+async fn chunk_list(master: &mut MasterClient<tonic::transport::Channel>) -> FileChunkMapping {
+    master
+        .get_file_chunks(FileChunkMappingRequest {
+            file_name: "/file".to_string(),
+            for_append: false,
+            token: String::new(),
+            lease_chunks: Vec::new(),
+        })
+        .await
+        .unwrap()
+        .into_inner()
+}
+
+/// Wait until the master no longer counts the chunkserver at `addr` as healthy
+async fn wait_until_unhealthy(master: &MasterService, addr: &str) {
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while master.healthy_servers().await.contains(addr) {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("The master still considers the killed chunkserver healthy");
+}
+
+#[tokio::test]
+async fn dead_replica_is_not_listed() {
+    let mut cluster = TestCluster::start_with(2, |config| {
+        config.common.replication_factor = 2;
+        config.common.heartbeat_interval = Duration::from_secs(1);
+        config.master.heartbeat_failure_threshold = 1;
+        // The failed servers stay in the metadata while the test runs
+        config.master.cron_interval = Duration::from_secs(3600);
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    client
+        .upload_stream(&b"still readable"[..], "/file")
+        .await
+        .unwrap();
+    let addrs = cluster.chunkserver_addrs();
+    let channel = connect_channel(cluster.master_addr(), &cluster.config().common.tls)
+        .await
+        .unwrap();
+    let mut master = MasterClient::new(channel);
+
+    cluster.kill_chunkserver(0);
+    wait_until_unhealthy(cluster.master(), &addrs[0]).await;
+
+    let listed = chunk_list(&mut master).await;
+    assert!(!listed.possibly_stale);
+    assert_eq!(listed.chunks[0].server_addresses, vec![addrs[1].clone()]);
+    let stored = cluster.master().file_chunks.read().await["/file"][0]
+        .server_addresses
+        .len();
+    assert_eq!(stored, 2);
+    let read = client.read_range("/file", 0, u64::MAX).await.unwrap();
+    assert_eq!(read, b"still readable");
+
+    // With no replica left alive, all are listed and the list is flagged
+    cluster.kill_chunkserver(1);
+    wait_until_unhealthy(cluster.master(), &addrs[1]).await;
+    let listed = chunk_list(&mut master).await;
+    assert!(listed.possibly_stale);
+    assert_eq!(listed.chunks[0].server_addresses.len(), 2);
+
+    cluster.shutdown().await.unwrap();
+}
@@ -0,0 +1,76 @@
+// With fewer chunkservers than replication_factor, uploads are either stored with fewer replicas
+// and repaired once more servers join, or rejected if allow_degraded_writes is off
+use rustfs::error::Error;
+use rustfs::testing::TestCluster;
+use std::time::Duration;
+
+#[tokio::test]
+async fn degraded_upload_is_reported_and_repaired_later() {
+    let mut cluster = TestCluster::start_with(1, |config| {
+        config.common.replication_factor = 3;
+        config.master.cron_interval = Duration::from_secs(1);
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+
+    let assigned = client.assign_chunks("/assigned", 10).await.unwrap();
+    assert_eq!(assigned.replicas, 1);
+    assert_eq!(assigned.replication_factor, 3);
+    assert_eq!(assigned.chunk_info_list[0].server_addresses.len(), 1);
+
+    let summary = client
+        .upload_stream(&b"only one replica"[..], "/file")
+        .await
+        .unwrap();
+    assert_eq!(summary.degraded_chunks(), 1);
+    let info = client.get_file_info("/file").await.unwrap();
+    assert_eq!(info.under_replicated_chunks, 1);
+    let chunk_id = info.chunks[0].chunk_id.clone();
+
+    // Copied to the new servers by the re-replication scan
+    cluster.add_chunkserver().await.unwrap();
+    cluster.add_chunkserver().await.unwrap();
+    let master = cluster.master();
+    tokio::time::timeout(Duration::from_secs(30), async {
+        while master.chunk_map.read().await[&chunk_id]
+            .server_addresses
+            .len()
+            < 3
+        {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    })
+    .await
+    .expect("The chunk was not re-replicated");
+    let info = client.get_file_info("/file").await.unwrap();
+    assert_eq!(info.under_replicated_chunks, 0);
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn degraded_upload_is_rejected_in_strict_mode() {
+    let cluster = TestCluster::start_with(1, |config| {
+        config.common.replication_factor = 3;
+        config.master.allow_degraded_writes = false;
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+
+    let err = client.assign_chunks("/assigned", 10).await.unwrap_err();
+    assert!(
+        matches!(&err, Error::Rpc(status) if status.code() == tonic::Code::FailedPrecondition),
+        "{}",
+        err
+    );
+    client
+        .upload_stream(&b"needs three replicas"[..], "/file")
+        .await
+        .unwrap_err();
+    client.get_file_info("/file").await.unwrap_err();
+    assert!(cluster.master().chunk_map.read().await.is_empty());
+
+    cluster.shutdown().await.unwrap();
+}
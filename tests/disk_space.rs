@@ -0,0 +1,46 @@
+// Chunkservers reject uploads that would leave less than min_free_bytes free on their disk
+use rustfs::error::Error;
+use rustfs::testing::TestCluster;
+
+#[tokio::test]
+async fn writes_leaving_too_little_space_are_rejected() {
+    let cluster = TestCluster::start_with(1, |config| {
+        config.common.replication_factor = 1;
+        // More than any disk has free
+        config.chunkserver.min_free_bytes = u64::MAX / 2;
+    })
+    .await
+    .unwrap();
+    let chunkserver = cluster.chunkserver(0);
+    let status = chunkserver.check_free_space("chunk", 1).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::ResourceExhausted, "{}", status);
+
+    let mut client = cluster.client().await.unwrap();
+    let err = client
+        .upload_stream(&b"no room"[..], "/file")
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::ClusterFull(_)), "{}", err);
+    assert!(chunkserver.server_chunks.lock().await.is_empty());
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn writes_fitting_on_the_disk_are_accepted() {
+    let cluster = TestCluster::start_with(1, |config| {
+        config.common.replication_factor = 1;
+    })
+    .await
+    .unwrap();
+    let chunkserver = cluster.chunkserver(0);
+    chunkserver.check_free_space("chunk", 1).await.unwrap();
+    // But not more than the disk holds
+    let status = chunkserver
+        .check_free_space("chunk", u64::MAX)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::ResourceExhausted, "{}", status);
+
+    cluster.shutdown().await.unwrap();
+}
@@ -0,0 +1,185 @@
+// Client-side encryption: files read back with the right key only, and chunks cannot be
+// modified, reordered or cut off the end unnoticed
+use rustfs::client::Client;
+use rustfs::encryption::{Encryption, HEADER_LEN};
+use rustfs::error::Error;
+use rustfs::testing::TestCluster;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+/// Directory removed when dropped, holding key files
+struct KeyDir(PathBuf);
+
+impl KeyDir {
+    fn new() -> Self {
+        let dir = std::env::temp_dir().join(format!("rustfs-keys-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        KeyDir(dir)
+    }
+
+    /// Path of a key file holding `key`
+    fn key_file(&self, key: &str) -> String {
+        let path = self.0.join(key);
+        std::fs::write(&path, format!("{}\n", key)).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+}
+
+impl Drop for KeyDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn encryption(dir: &KeyDir, key: &str) -> Encryption {
+    Encryption::load(&dir.key_file(key)).unwrap()
+}
+
+fn assert_decryption_fails<T: std::fmt::Debug>(result: rustfs::error::Result<T>) {
+    assert!(
+        matches!(result, Err(Error::Decryption(_))),
+        "{:?} decrypted",
+        result
+    );
+}
+
+#[test]
+fn sealed_chunks_open_with_the_same_key() {
+    let dir = KeyDir::new();
+    let encryption = encryption(&dir, "key");
+    let file_key = encryption.new_file().unwrap();
+
+    let first = file_key.seal(0, false, b"first").unwrap();
+    let last = file_key.seal(1, true, b"last").unwrap();
+    // The file's key is derived again from the header of the first chunk
+    let reopened = encryption
+        .open_file("/file", &first[..HEADER_LEN as usize])
+        .unwrap();
+    assert_eq!(reopened.open("/file", 0, false, &first).unwrap(), b"first");
+    assert_eq!(reopened.open("/file", 1, true, &last).unwrap(), b"last");
+    // The plaintext appears nowhere in the stored form
+    assert!(!last.windows(4).any(|window| window == b"last"));
+}
+
+#[test]
+fn chunks_do_not_open_with_another_key() {
+    let dir = KeyDir::new();
+    let file_key = encryption(&dir, "key").new_file().unwrap();
+    let chunk = file_key.seal(0, true, b"secret").unwrap();
+
+    let other = encryption(&dir, "other key")
+        .open_file("/file", &chunk)
+        .unwrap();
+    assert_decryption_fails(other.open("/file", 0, true, &chunk));
+    // Nor does another file's key with the same secret
+    let another_file = encryption(&dir, "key").new_file().unwrap();
+    assert_decryption_fails(another_file.open("/file", 0, true, &chunk));
+}
+
+#[test]
+fn chunks_open_only_where_they_were_sealed() {
+    let dir = KeyDir::new();
+    let file_key = encryption(&dir, "key").new_file().unwrap();
+    let middle = file_key.seal(1, false, b"middle").unwrap();
+
+    assert_decryption_fails(file_key.open("/file", 2, false, &middle));
+    // A chunk sealed as followed by others is not the end of the file
+    assert_decryption_fails(file_key.open("/file", 1, true, &middle));
+    let mut modified = middle.clone();
+    *modified.last_mut().unwrap() ^= 1;
+    assert_decryption_fails(file_key.open("/file", 1, false, &modified));
+    assert_eq!(
+        file_key.open("/file", 1, false, &middle).unwrap(),
+        b"middle"
+    );
+}
+
+async fn client_with_key(cluster: &TestCluster, key_file: &str) -> Client {
+    let mut config = cluster.config().clone();
+    config.client.encryption_key_file = key_file.to_string();
+    Client::from_config(config).await.unwrap()
+}
+
+fn assert_read_fails(result: rustfs::error::Result<Vec<u8>>) {
+    assert!(
+        matches!(result, Err(Error::Decryption(_))),
+        "read {:?}",
+        result.map(|data| data.len())
+    );
+}
+
+#[tokio::test]
+async fn encrypted_files_round_trip() {
+    let dir = KeyDir::new();
+    let cluster = TestCluster::start_with(1, |config| {
+        config.common.chunk_size = 1024;
+        config.common.replication_factor = 1;
+    })
+    .await
+    .unwrap();
+    let mut client = client_with_key(&cluster, &dir.key_file("key")).await;
+    let data: Vec<u8> = (0..5000).map(|i| (i % 251) as u8).collect();
+    client.upload_stream(&data[..], "/stream").await.unwrap();
+    client.upload_stream(&b""[..], "/empty").await.unwrap();
+    let local = dir.0.join("local");
+    std::fs::write(&local, &data).unwrap();
+    client
+        .upload(local.to_str().unwrap(), "/local")
+        .await
+        .unwrap();
+
+    assert_eq!(
+        client.read_range("/stream", 0, u64::MAX).await.unwrap(),
+        data
+    );
+    assert_eq!(
+        client.read_range("/local", 0, u64::MAX).await.unwrap(),
+        data
+    );
+    assert_eq!(
+        client.read_range("/stream", 1000, 2000).await.unwrap(),
+        &data[1000..3000]
+    );
+    assert!(client
+        .read_range("/empty", 0, u64::MAX)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // Another key reads nothing, no key reads the ciphertext
+    let mut other = client_with_key(&cluster, &dir.key_file("other key")).await;
+    assert_read_fails(other.read_range("/stream", 0, u64::MAX).await);
+    let mut plain = client_with_key(&cluster, "").await;
+    let stored = plain.read_range("/stream", 0, u64::MAX).await.unwrap();
+    assert!(stored.len() > data.len());
+    assert_ne!(&stored[..data.len()], &data[..]);
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn files_cut_off_at_a_chunk_fail_to_decrypt() {
+    let dir = KeyDir::new();
+    let cluster = TestCluster::start_with(1, |config| {
+        config.common.chunk_size = 1024;
+        config.common.replication_factor = 1;
+    })
+    .await
+    .unwrap();
+    let mut client = client_with_key(&cluster, &dir.key_file("key")).await;
+    let data = vec![7u8; 3000];
+    client.upload_stream(&data[..], "/file").await.unwrap();
+
+    // The master drops the last chunk from the file, as if it was never uploaded
+    let chunks = {
+        let mut file_chunks = cluster.master().file_chunks.write().await;
+        let chunks = file_chunks.get_mut("/file").unwrap();
+        chunks.pop();
+        chunks.len()
+    };
+    assert!(chunks >= 2);
+    let mut client = client_with_key(&cluster, &dir.key_file("key")).await;
+    assert_read_fails(client.read_range("/file", 0, u64::MAX).await);
+
+    cluster.shutdown().await.unwrap();
+}
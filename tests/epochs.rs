@@ -0,0 +1,69 @@
+// Chunkservers raise their leader epoch only on commands of the master, not on client writes
+use rustfs::proto::chunk::chunk_client::ChunkClient;
+use rustfs::proto::chunk::{upload_request, FileChunk, FileInfo, UploadRequest};
+use rustfs::testing::TestCluster;
+use rustfs::util::connect_channel;
+use std::sync::atomic::Ordering;
+use uuid::Uuid;
+
+/// Upload a chunk straight to the chunkserver, claiming the leader epoch `epoch`
+async fn upload_with_epoch(cluster: &TestCluster, epoch: u64) -> Result<(), tonic::Status> {
+    let addr = &cluster.chunkserver_addrs()[0];
+    let channel = connect_channel(addr, &cluster.config().common.tls)
+        .await
+        .unwrap();
+    let info = FileInfo {
+        chunk_id: Uuid::new_v4().to_string(),
+        secondary_addresses: Vec::new(),
+        epoch,
+        checksum: None,
+        stored_form: false,
+        replicas: Vec::new(),
+        placement_proof: String::new(),
+        primary: String::new(),
+    };
+    let requests = [
+        upload_request::Request::Info(info),
+        upload_request::Request::Chunk(FileChunk {
+            data: b"data".to_vec(),
+        }),
+    ]
+    .into_iter()
+    .map(|request| UploadRequest {
+        request: Some(request),
+        token: String::new(),
+        is_internal: false,
+        internal_proof: String::new(),
+    });
+    ChunkClient::new(channel)
+        .upload(futures::stream::iter(requests))
+        .await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn client_epochs_are_compared_but_not_stored() {
+    let cluster = TestCluster::start_with(1, |config| {
+        config.common.replication_factor = 1;
+    })
+    .await
+    .unwrap();
+    let epoch = cluster.master().epoch.load(Ordering::SeqCst);
+    assert_eq!(cluster.chunkserver(0).epoch.load(Ordering::SeqCst), epoch);
+
+    // A write claiming a future epoch is accepted, but does not fence off the current leader
+    upload_with_epoch(&cluster, epoch + 100).await.unwrap();
+    assert_eq!(cluster.chunkserver(0).epoch.load(Ordering::SeqCst), epoch);
+    upload_with_epoch(&cluster, epoch).await.unwrap();
+    let mut client = cluster.client().await.unwrap();
+    client
+        .upload_stream(&b"still writable"[..], "/after")
+        .await
+        .unwrap();
+
+    // A write of an older epoch is still rejected
+    let status = upload_with_epoch(&cluster, epoch - 1).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::FailedPrecondition, "{}", status);
+
+    cluster.shutdown().await.unwrap();
+}
@@ -0,0 +1,191 @@
+// A failed chunkserver is dropped from the replicas of its chunks even if none can be re-created,
+// and replicas that clients report as having missed a write once the master checked them
+use rustfs::auth::Role;
+use rustfs::proto::master::master_client::MasterClient;
+use rustfs::proto::master::{AuthenticateRequest, FailedReplica, ReportFailedReplicasRequest};
+use rustfs::testing::TestCluster;
+use rustfs::util::connect_channel;
+use std::time::Duration;
+
+#[tokio::test]
+async fn dead_address_is_removed_without_spare_servers() {
+    let mut cluster = TestCluster::start_with(2, |config| {
+        config.common.replication_factor = 2;
+        config.common.heartbeat_interval = Duration::from_secs(1);
+        config.master.heartbeat_failure_threshold = 1;
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    client
+        .upload_stream(&b"one replica left"[..], "/file")
+        .await
+        .unwrap();
+    let before = client.get_file_info("/file").await.unwrap().chunks[0].clone();
+    assert_eq!(before.server_addresses.len(), 2);
+
+    let dead = cluster.chunkserver_addrs()[1].clone();
+    cluster.kill_chunkserver(1);
+    let master = cluster.master();
+    tokio::time::timeout(Duration::from_secs(30), async {
+        while master.chunk_map.read().await[&before.chunk_id]
+            .server_addresses
+            .contains(&dead)
+        {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    })
+    .await
+    .expect("The dead address stayed among the replicas");
+
+    // Gone from every map, with a new version, and the clients are sent to the survivor only
+    let chunk = master.chunk_map.read().await[&before.chunk_id].clone();
+    assert_eq!(
+        chunk.server_addresses,
+        vec![cluster.chunkserver_addrs()[0].clone()]
+    );
+    assert!(chunk.version > before.version);
+    assert_eq!(master.file_chunks.read().await["/file"][0], chunk);
+    assert!(master
+        .chunk_servers
+        .read()
+        .await
+        .get(&dead)
+        .is_none_or(|chunks| chunks.is_empty()));
+    let info = client.get_file_info("/file").await.unwrap();
+    assert_eq!(info.chunks[0].server_addresses, chunk.server_addresses);
+    assert_eq!(info.under_replicated_chunks, 1);
+    let read = client.read_range("/file", 0, u64::MAX).await.unwrap();
+    assert_eq!(read, b"one replica left");
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn upload_with_an_unreachable_replica_is_degraded_and_reported() {
+    let mut cluster = TestCluster::start_with(3, |config| {
+        config.common.replication_factor = 3;
+    })
+    .await
+    .unwrap();
+    // The master still places chunks on the dead server until it misses its heartbeats
+    let dead = cluster.chunkserver_addrs()[2].clone();
+    cluster.kill_chunkserver(2);
+    let mut client = cluster.client().await.unwrap();
+
+    let summary = client
+        .upload_stream(&b"two of three replicas"[..], "/file")
+        .await
+        .unwrap();
+    assert_eq!(summary.degraded_chunks(), 1);
+    let chunk = &summary.chunks[0];
+    assert_eq!(chunk.replicas.len(), 2);
+    assert!(!chunk.replicas.contains(&dead), "{:?}", chunk.replicas);
+    assert_eq!(chunk.failed_replicas, vec![dead]);
+    let read = client.read_range("/file", 0, u64::MAX).await.unwrap();
+    assert_eq!(read, b"two of three replicas");
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn only_replicas_that_missed_the_write_are_dropped() {
+    let cluster = TestCluster::start_with(2, |config| {
+        config.common.replication_factor = 2;
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    client
+        .upload_stream(&b"written to both"[..], "/file")
+        .await
+        .unwrap();
+    let chunk = client.get_file_info("/file").await.unwrap().chunks[0].clone();
+    let channel = connect_channel(cluster.master_addr(), &cluster.config().common.tls)
+        .await
+        .unwrap();
+    let mut master = MasterClient::new(channel);
+    let report = |server_address: &str| ReportFailedReplicasRequest {
+        failed_replicas: vec![FailedReplica {
+            chunk_id: chunk.chunk_id.clone(),
+            server_address: server_address.to_string(),
+        }],
+        token: String::new(),
+    };
+    let replicas = || async {
+        cluster.master().chunk_map.read().await[&chunk.chunk_id]
+            .server_addresses
+            .clone()
+    };
+
+    // A replica holding the same data as the others is kept, whatever the client claims
+    let (first, second) = (&chunk.server_addresses[0], &chunk.server_addresses[1]);
+    master.report_failed_replicas(report(first)).await.unwrap();
+    assert_eq!(replicas().await, chunk.server_addresses);
+
+    // One that lost the chunk is dropped
+    let index = cluster
+        .chunkserver_addrs()
+        .iter()
+        .position(|addr| addr == second)
+        .unwrap();
+    std::fs::remove_file(cluster.chunkserver(index).chunk_path(&chunk.chunk_id)).unwrap();
+    master.report_failed_replicas(report(second)).await.unwrap();
+    assert_eq!(replicas().await, vec![first.clone()]);
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn reports_need_the_token_of_a_writer() {
+    let cluster = TestCluster::start_with(2, |config| {
+        config.common.replication_factor = 2;
+        config.common.use_authentication = true;
+        config.common.cluster_secret = "test-cluster-secret".to_string();
+    })
+    .await
+    .unwrap();
+    cluster
+        .add_user("alice", "secret", Role::ReadWrite)
+        .unwrap();
+    cluster.add_user("bob", "secret", Role::ReadWrite).unwrap();
+    let mut alice = cluster.client().await.unwrap();
+    alice.authenticate("alice", "secret").await.unwrap();
+    alice
+        .upload_stream(&b"alice's data"[..], "/file")
+        .await
+        .unwrap();
+    let chunk = alice.get_file_info("/file").await.unwrap().chunks[0].clone();
+
+    let channel = connect_channel(cluster.master_addr(), &cluster.config().common.tls)
+        .await
+        .unwrap();
+    let mut master = MasterClient::new(channel);
+    let bob_token = master
+        .authenticate(AuthenticateRequest {
+            username: "bob".to_string(),
+            password: "secret".to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .token;
+    for (token, code) in [
+        (String::new(), tonic::Code::Unauthenticated),
+        (bob_token, tonic::Code::PermissionDenied),
+    ] {
+        let status = master
+            .report_failed_replicas(ReportFailedReplicasRequest {
+                failed_replicas: vec![FailedReplica {
+                    chunk_id: chunk.chunk_id.clone(),
+                    server_address: chunk.server_addresses[0].clone(),
+                }],
+                token,
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), code, "{}", status);
+    }
+
+    cluster.shutdown().await.unwrap();
+}
@@ -0,0 +1,63 @@
+// Chunkservers keep trying to reach the master when it is down, report NOT_SERVING meanwhile,
+// and report all their chunks once it is back
+use rustfs::testing::TestCluster;
+use std::time::Duration;
+
+#[tokio::test]
+async fn heartbeats_resume_after_a_master_restart() {
+    let mut cluster = TestCluster::start_with(1, |config| {
+        config.common.replication_factor = 1;
+        config.common.heartbeat_interval = Duration::from_secs(1);
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    client.upload_stream(&b"kept"[..], "/file").await.unwrap();
+    let chunk_id = cluster.master().file_chunks.read().await["/file"][0]
+        .chunk_id
+        .clone();
+    let addr = cluster.chunkserver_addrs()[0].clone();
+
+    cluster.kill_master(0);
+    let metrics = &cluster.chunkserver(0).metrics;
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while metrics.master_connected.get() != 0 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("The chunkserver did not notice the master was gone");
+    // Down for a few failed heartbeats
+    tokio::time::sleep(Duration::from_secs(3)).await;
+
+    cluster.restart_master(0).unwrap();
+    let master = cluster.master();
+    let metrics = &cluster.chunkserver(0).metrics;
+    // The chunkserver counts the master as connected once it got the response to the heartbeat
+    tokio::time::timeout(Duration::from_secs(30), async {
+        while !master.healthy_servers().await.contains(&addr) || metrics.master_connected.get() != 1
+        {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("Heartbeats did not resume");
+
+    // The restarted master knew nothing about the server, the full report registered it
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while !master.chunk_servers.read().await.contains_key(&addr) {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("The chunkserver did not report its chunks to the restarted master");
+    // Not deleted as an orphan of the metadata the master lost
+    assert!(cluster
+        .chunkserver(0)
+        .server_chunks
+        .lock()
+        .await
+        .contains(&chunk_id));
+
+    cluster.shutdown().await.unwrap();
+}
@@ -0,0 +1,98 @@
+// Internal uploads between chunkservers carry a proof derived from the cluster secret, never the
+// secret itself, and are rejected without a valid one
+use rustfs::chunkserver_service::upload_fields;
+use rustfs::cluster_auth::sign_request;
+use rustfs::proto::chunk::chunk_client::ChunkClient;
+use rustfs::proto::chunk::{upload_request, FileChunk, FileInfo, UploadRequest};
+use rustfs::testing::TestCluster;
+use rustfs::util::connect_channel;
+use std::sync::atomic::Ordering;
+use uuid::Uuid;
+
+const CLUSTER_SECRET: &str = "test-cluster-secret";
+
+/// Upload a chunk to the chunkserver as another chunkserver would, carrying `proof`
+async fn internal_upload(
+    cluster: &TestCluster,
+    chunk_id: &str,
+    proof: &str,
+) -> Result<(), tonic::Status> {
+    let addr = &cluster.chunkserver_addrs()[0];
+    let channel = connect_channel(addr, &cluster.config().common.tls)
+        .await
+        .unwrap();
+    let info = FileInfo {
+        chunk_id: chunk_id.to_string(),
+        secondary_addresses: Vec::new(),
+        epoch: cluster.master().epoch.load(Ordering::SeqCst),
+        checksum: None,
+        stored_form: false,
+        replicas: Vec::new(),
+        placement_proof: String::new(),
+        primary: String::new(),
+    };
+    let requests = [
+        upload_request::Request::Info(info),
+        upload_request::Request::Chunk(FileChunk {
+            data: b"data".to_vec(),
+        }),
+    ]
+    .into_iter()
+    .map(|request| UploadRequest {
+        request: Some(request),
+        token: String::new(),
+        is_internal: true,
+        internal_proof: proof.to_string(),
+    })
+    .collect::<Vec<_>>();
+    ChunkClient::new(channel)
+        .upload(futures::stream::iter(requests))
+        .await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn internal_uploads_need_a_valid_proof() {
+    let cluster = TestCluster::start_with(1, |config| {
+        config.common.replication_factor = 1;
+        config.common.use_authentication = true;
+        config.common.cluster_secret = CLUSTER_SECRET.to_string();
+    })
+    .await
+    .unwrap();
+    let chunk_id = Uuid::new_v4().to_string();
+    let epoch = cluster.master().epoch.load(Ordering::SeqCst).to_be_bytes();
+
+    let other_chunk = Uuid::new_v4().to_string();
+    for proof in [
+        String::new(),
+        // The secret itself is no proof
+        CLUSTER_SECRET.to_string(),
+        // Nor is a proof signed with another secret or for another chunk
+        sign_request("wrong-secret", &upload_fields(&chunk_id, &epoch, false)),
+        sign_request(CLUSTER_SECRET, &upload_fields(&other_chunk, &epoch, false)),
+        sign_request(CLUSTER_SECRET, &upload_fields(&chunk_id, &epoch, true)),
+    ] {
+        let status = internal_upload(&cluster, &chunk_id, &proof)
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated, "{}", status);
+    }
+    assert!(!cluster
+        .chunkserver(0)
+        .server_chunks
+        .lock()
+        .await
+        .contains(&chunk_id));
+
+    let proof = sign_request(CLUSTER_SECRET, &upload_fields(&chunk_id, &epoch, false));
+    internal_upload(&cluster, &chunk_id, &proof).await.unwrap();
+    assert!(cluster
+        .chunkserver(0)
+        .server_chunks
+        .lock()
+        .await
+        .contains(&chunk_id));
+
+    cluster.shutdown().await.unwrap();
+}
@@ -0,0 +1,223 @@
+// An upload whose stream breaks off mid-chunk leaves neither a chunk file nor metadata behind,
+// on the primary or on the secondaries it forwards the frames to as they arrive
+use rustfs::proto::chunk::chunk_client::ChunkClient;
+use rustfs::proto::chunk::{upload_request, FileChunk, FileInfo, UploadRequest};
+use rustfs::testing::TestCluster;
+use rustfs::util::connect_channel;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
+use tokio_stream::wrappers::ReceiverStream;
+use uuid::Uuid;
+
+/// Files of the chunk in the data directory of a chunkserver, including temporary ones
+fn chunk_files(cluster: &TestCluster, index: usize, chunk_id: &str) -> Vec<String> {
+    let dir = cluster.chunkserver(index).chunk_dir(chunk_id);
+    std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+                .filter(|name| name.starts_with(chunk_id))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Forward one connection to `target` until the returned sender is used or dropped, which
+/// cuts the connection like a crashed client
+async fn cuttable_proxy(target: String) -> (String, oneshot::Sender<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let (cut, cut_received) = oneshot::channel::<()>();
+    tokio::spawn(async move {
+        let (mut client, _) = listener.accept().await.unwrap();
+        let mut server = TcpStream::connect(target).await.unwrap();
+        tokio::select! {
+            _ = tokio::io::copy_bidirectional(&mut client, &mut server) => {}
+            _ = cut_received => {}
+        }
+    });
+    (addr, cut)
+}
+
+fn request(request: upload_request::Request) -> UploadRequest {
+    UploadRequest {
+        request: Some(request),
+        token: String::new(),
+        is_internal: false,
+        internal_proof: String::new(),
+    }
+}
+
+fn frame(data: Vec<u8>) -> UploadRequest {
+    request(upload_request::Request::Chunk(FileChunk { data }))
+}
+
+/// Wait until `condition` holds, for at most 10 seconds
+async fn wait_for(mut condition: impl FnMut() -> bool) {
+    for _ in 0..100 {
+        if condition() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("Condition not met within 10 seconds");
+}
+
+#[tokio::test]
+async fn dropped_upload_stream_leaves_nothing_behind() {
+    let cluster = TestCluster::start_with(1, |config| {
+        config.common.replication_factor = 1;
+    })
+    .await
+    .unwrap();
+    let chunk_id = Uuid::new_v4().to_string();
+    let (proxy, cut) = cuttable_proxy(cluster.chunkserver_addrs()[0].clone()).await;
+    let channel = connect_channel(&proxy, &cluster.config().common.tls)
+        .await
+        .unwrap();
+    // Send the file info and the first frame, then cut the connection without ending the stream
+    let (tx, rx) = tokio::sync::mpsc::channel(4);
+    tx.send(request(upload_request::Request::Info(FileInfo {
+        chunk_id: chunk_id.clone(),
+        secondary_addresses: Vec::new(),
+        epoch: cluster.master().epoch.load(Ordering::SeqCst),
+        checksum: None,
+        stored_form: false,
+        replicas: Vec::new(),
+        placement_proof: String::new(),
+        primary: String::new(),
+    })))
+    .await
+    .unwrap();
+    tx.send(frame(vec![7; 1000])).await.unwrap();
+    let upload = tokio::spawn(async move {
+        ChunkClient::new(channel)
+            .upload(ReceiverStream::new(rx))
+            .await
+    });
+    // The partial chunk is being received into a temporary file
+    wait_for(|| !chunk_files(&cluster, 0, &chunk_id).is_empty()).await;
+    assert!(!chunk_files(&cluster, 0, &chunk_id).contains(&chunk_id));
+    cut.send(()).unwrap();
+    assert!(upload.await.unwrap().is_err());
+
+    // The temporary file is removed, and the chunk was never stored or reported
+    wait_for(|| chunk_files(&cluster, 0, &chunk_id).is_empty()).await;
+    assert!(!cluster
+        .chunkserver(0)
+        .server_chunks
+        .lock()
+        .await
+        .contains(&chunk_id));
+    assert!(cluster
+        .chunkserver(0)
+        .stored_checksum(&chunk_id)
+        .await
+        .is_none());
+
+    drop(tx);
+    cluster.shutdown().await.unwrap();
+}
+
+/// File info of a chunk uploaded to the first of `replicas`, which forwards it to the others
+fn replicated_info(cluster: &TestCluster, chunk_id: &str, replicas: &[String]) -> UploadRequest {
+    request(upload_request::Request::Info(FileInfo {
+        chunk_id: chunk_id.to_string(),
+        secondary_addresses: replicas[1..].to_vec(),
+        epoch: cluster.master().epoch.load(Ordering::SeqCst),
+        checksum: None,
+        stored_form: false,
+        replicas: replicas.to_vec(),
+        placement_proof: String::new(),
+        primary: String::new(),
+    }))
+}
+
+#[tokio::test]
+async fn frames_are_forwarded_as_they_arrive() {
+    let cluster = TestCluster::start_with(2, |config| {
+        config.common.replication_factor = 2;
+    })
+    .await
+    .unwrap();
+    let chunk_id = Uuid::new_v4().to_string();
+    let replicas = cluster.chunkserver_addrs();
+    let channel = connect_channel(&replicas[0], &cluster.config().common.tls)
+        .await
+        .unwrap();
+    let (tx, rx) = tokio::sync::mpsc::channel(4);
+    tx.send(replicated_info(&cluster, &chunk_id, &replicas))
+        .await
+        .unwrap();
+    tx.send(frame(vec![1; 1000])).await.unwrap();
+    let upload = tokio::spawn(async move {
+        ChunkClient::new(channel)
+            .upload(ReceiverStream::new(rx))
+            .await
+    });
+
+    // The secondary receives the first frame while the client is still sending
+    wait_for(|| !chunk_files(&cluster, 1, &chunk_id).is_empty()).await;
+    assert!(!upload.is_finished());
+    for byte in 2..10 {
+        tx.send(frame(vec![byte; 1000])).await.unwrap();
+    }
+    drop(tx);
+    let response = upload.await.unwrap().unwrap().into_inner();
+    assert_eq!(response.bytes_written, 9000);
+    assert!(response.failed_secondaries.is_empty());
+
+    // Both replicas hold the whole chunk
+    for index in 0..2 {
+        let chunkserver = cluster.chunkserver(index);
+        assert!(chunkserver.server_chunks.lock().await.contains(&chunk_id));
+        assert_eq!(
+            chunkserver.stored_checksum(&chunk_id).await,
+            Some(response.checksum)
+        );
+    }
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn dropped_upload_stream_is_discarded_by_secondaries() {
+    let cluster = TestCluster::start_with(2, |config| {
+        config.common.replication_factor = 2;
+    })
+    .await
+    .unwrap();
+    let chunk_id = Uuid::new_v4().to_string();
+    let replicas = cluster.chunkserver_addrs();
+    let (proxy, cut) = cuttable_proxy(replicas[0].clone()).await;
+    let channel = connect_channel(&proxy, &cluster.config().common.tls)
+        .await
+        .unwrap();
+    let (tx, rx) = tokio::sync::mpsc::channel(4);
+    tx.send(replicated_info(&cluster, &chunk_id, &replicas))
+        .await
+        .unwrap();
+    tx.send(frame(vec![7; 1000])).await.unwrap();
+    let upload = tokio::spawn(async move {
+        ChunkClient::new(channel)
+            .upload(ReceiverStream::new(rx))
+            .await
+    });
+
+    // The secondary is receiving the frames forwarded so far when the client goes away
+    wait_for(|| !chunk_files(&cluster, 1, &chunk_id).is_empty()).await;
+    cut.send(()).unwrap();
+    assert!(upload.await.unwrap().is_err());
+
+    // Neither replica keeps a partial chunk
+    for index in 0..2 {
+        wait_for(|| chunk_files(&cluster, index, &chunk_id).is_empty()).await;
+        let chunkserver = cluster.chunkserver(index);
+        assert!(!chunkserver.server_chunks.lock().await.contains(&chunk_id));
+        assert!(chunkserver.stored_checksum(&chunk_id).await.is_none());
+    }
+
+    drop(tx);
+    cluster.shutdown().await.unwrap();
+}
@@ -0,0 +1,72 @@
+// Chunks larger than gRPC's default 4 MB message limit are uploaded and copied between
+// chunkservers in frames
+use rustfs::testing::TestCluster;
+use std::time::Duration;
+
+const CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+
+#[tokio::test]
+async fn chunks_over_four_megabytes_are_uploaded_and_transferred() {
+    let cluster = TestCluster::start_with(3, |config| {
+        config.common.replication_factor = 2;
+        config.common.chunk_size = CHUNK_SIZE;
+        config.master.cron_interval = Duration::from_secs(1);
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    let data: Vec<u8> = (0..6 * 1024 * 1024u32).map(|i| (i % 253) as u8).collect();
+    client.upload_stream(&data[..], "/large").await.unwrap();
+    let info = client.get_file_info("/large").await.unwrap();
+    assert_eq!(info.chunks.len(), 1);
+    let chunk = info.chunks[0].clone();
+    assert_eq!(chunk.server_addresses.len(), 2);
+
+    // Lose one replica, so that the master has the other one transfer the chunk
+    let lost_addr = chunk.server_addresses[0].clone();
+    let index = cluster
+        .chunkserver_addrs()
+        .iter()
+        .position(|addr| *addr == lost_addr)
+        .unwrap();
+    let chunkserver = cluster.chunkserver(index);
+    let path = chunkserver.chunk_path(&chunk.chunk_id);
+    let mut damaged = std::fs::read(&path).unwrap();
+    damaged[0] ^= 1;
+    std::fs::write(&path, damaged).unwrap();
+    chunkserver.scrub().await;
+    let master = cluster.master();
+    let replicas = master.chunk_map.read().await[&chunk.chunk_id]
+        .server_addresses
+        .clone();
+    assert_eq!(replicas.len(), 1, "{:?}", replicas);
+
+    let replicas = tokio::time::timeout(Duration::from_secs(60), async {
+        loop {
+            let replicas = master.chunk_map.read().await[&chunk.chunk_id]
+                .server_addresses
+                .clone();
+            if replicas.len() == 2 {
+                return replicas;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("The chunk was not transferred");
+
+    // The new replica holds the whole chunk
+    for addr in replicas {
+        let index = cluster
+            .chunkserver_addrs()
+            .iter()
+            .position(|a| *a == addr)
+            .unwrap();
+        let copy = std::fs::read(cluster.chunkserver(index).chunk_path(&chunk.chunk_id)).unwrap();
+        assert!(copy == data, "Replica on {} differs", addr);
+    }
+    let read = client.read_range("/large", 0, u64::MAX).await.unwrap();
+    assert!(read == data);
+
+    cluster.shutdown().await.unwrap();
+}
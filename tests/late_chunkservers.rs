@@ -0,0 +1,39 @@
+// Chunkservers joining after a client authenticated accept its session, which they only learn
+// how to check when registering with the master
+use rustfs::auth::Role;
+use rustfs::testing::TestCluster;
+
+#[tokio::test]
+async fn chunkservers_joining_later_serve_authenticated_clients() {
+    let mut cluster = TestCluster::start_with(0, |config| {
+        config.common.use_authentication = true;
+        config.common.cluster_secret = "test-cluster-secret".to_string();
+        config.common.chunk_size = 1024;
+        config.common.replication_factor = 2;
+    })
+    .await
+    .unwrap();
+    cluster
+        .add_user("alice", "secret", Role::ReadWrite)
+        .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    client.authenticate("alice", "secret").await.unwrap();
+
+    // Every chunkserver registers after the client authenticated
+    for _ in 0..2 {
+        cluster.add_chunkserver().await.unwrap();
+    }
+    let data: Vec<u8> = (0..2500u32).map(|i| (i % 251) as u8).collect();
+    let summary = client.upload_stream(&data[..], "/late").await.unwrap();
+    assert!(summary.chunks.iter().all(|chunk| chunk.replicas.len() == 2));
+
+    assert_eq!(client.read_range("/late", 0, u64::MAX).await.unwrap(), data);
+    for index in 0..2 {
+        assert_eq!(
+            cluster.chunkserver(index).server_chunks.lock().await.len(),
+            3
+        );
+    }
+
+    cluster.shutdown().await.unwrap();
+}
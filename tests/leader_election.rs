@@ -0,0 +1,154 @@
+// Shadow masters elect exactly one new leader once the leader fails, given a majority of the masters
+use rustfs::cluster_auth::sign_request;
+use rustfs::master_service::leader_changed_fields;
+use rustfs::proto::master::master_client::MasterClient;
+use rustfs::proto::master::LeaderChangedRequest;
+use rustfs::testing::TestCluster;
+use rustfs::util::connect_channel;
+use std::time::Duration;
+
+/// How long the shadows are given to notice the failure and elect a leader
+const ELECTION_TIMEOUT: Duration = Duration::from_secs(20);
+
+#[tokio::test]
+async fn killed_leader_is_replaced_by_exactly_one_shadow() {
+    let mut cluster = TestCluster::start_with_shadows(2, 1, |config| {
+        config.common.shadow_master_ping_interval = Duration::from_secs(1);
+        config.common.replication_factor = 1;
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    client
+        .upload_stream(&b"survives the failover"[..], "/before")
+        .await
+        .unwrap();
+
+    // Wait until both shadows caught up with the leader
+    let leader_version = *cluster.master().metadata_version.read().await;
+    tokio::time::timeout(ELECTION_TIMEOUT, async {
+        loop {
+            let mut synced = true;
+            for shadow in &cluster.masters()[1..] {
+                synced &= *shadow.metadata_version.read().await >= leader_version;
+            }
+            if synced {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("The shadows did not sync with the leader");
+
+    cluster.kill_master(0);
+    let shadows = cluster.masters();
+    let leaders = || async {
+        let mut leaders = Vec::new();
+        for shadow in &shadows {
+            if shadow.is_leader().await {
+                leaders.push(shadow.addr.clone());
+            }
+        }
+        leaders
+    };
+    tokio::time::timeout(ELECTION_TIMEOUT, async {
+        while leaders().await.is_empty() {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("No shadow took over as leader");
+
+    // Give a second promotion, e.g. by the losing shadow, time to happen
+    tokio::time::sleep(Duration::from_secs(5)).await;
+    let leaders = leaders().await;
+    assert_eq!(leaders.len(), 1, "{:?} took over", leaders);
+    for shadow in &shadows {
+        assert_eq!(*shadow.current_master.read().await, leaders[0]);
+    }
+
+    // The new leader serves the metadata replicated before the failure
+    let mut client = cluster.client().await.unwrap();
+    let read = client.read_range("/before", 0, u64::MAX).await.unwrap();
+    assert_eq!(read, b"survives the failover");
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn shadow_without_a_majority_does_not_take_over() {
+    let mut cluster = TestCluster::start_with_shadows(2, 0, |config| {
+        config.common.shadow_master_ping_interval = Duration::from_secs(1);
+    })
+    .await
+    .unwrap();
+
+    // The remaining shadow reaches neither the leader nor the other shadow, which may have
+    // elected a leader of its own on the other side of a partition
+    cluster.kill_master(0);
+    cluster.kill_master(2);
+    let shadow = cluster.masters()[0];
+    let failure_timeout = Duration::from_secs(shadow.settings.heartbeat_failure_threshold());
+    tokio::time::sleep(failure_timeout + Duration::from_secs(5)).await;
+    assert!(!shadow.is_leader().await);
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn only_masters_announce_leadership() {
+    const CLUSTER_SECRET: &str = "test-cluster-secret";
+    let cluster = TestCluster::start_with_shadows(1, 0, |config| {
+        config.common.use_authentication = true;
+        config.common.cluster_secret = CLUSTER_SECRET.to_string();
+    })
+    .await
+    .unwrap();
+    let leader = cluster.masters()[0];
+    let channel = connect_channel(cluster.master_addr(), &cluster.config().common.tls)
+        .await
+        .unwrap();
+    let announce = |leader_address: &str, secret: &str| {
+        let metadata_version = u64::MAX;
+        LeaderChangedRequest {
+            leader_address: leader_address.to_string(),
+            metadata_version,
+            internal_proof: sign_request(
+                secret,
+                &leader_changed_fields(leader_address, &metadata_version.to_be_bytes()),
+            ),
+        }
+    };
+    let shadow_addr = cluster.masters()[1].addr.clone();
+
+    // Announcements by a host that is no master, or without the cluster secret, are refused
+    let forged = [
+        announce("127.0.0.1:1", CLUSTER_SECRET),
+        announce(&shadow_addr, "wrong-secret"),
+        LeaderChangedRequest {
+            internal_proof: String::new(),
+            ..announce(&shadow_addr, CLUSTER_SECRET)
+        },
+    ];
+    for request in forged {
+        let status = MasterClient::new(channel.clone())
+            .leader_changed(request)
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+        assert!(leader.is_leader().await);
+    }
+
+    // A master with newer metadata makes the leader step down
+    let accepted = MasterClient::new(channel)
+        .leader_changed(announce(&shadow_addr, CLUSTER_SECRET))
+        .await
+        .unwrap()
+        .into_inner()
+        .accepted;
+    assert!(accepted);
+    assert!(!leader.is_leader().await);
+
+    cluster.shutdown().await.unwrap();
+}
@@ -0,0 +1,106 @@
+// Clients find the leader through any master, and shadow masters turn mutations away with the
+// leader's address so that clients retry there
+use rustfs::proto::master::master_client::MasterClient;
+use rustfs::proto::master::{AssignRequest, DeleteFileRequest, GetLeaderRequest};
+use rustfs::testing::TestCluster;
+use rustfs::util::{connect_channel, LEADER_ADDRESS_KEY};
+
+#[tokio::test]
+async fn client_connecting_to_a_shadow_reaches_the_leader() {
+    let cluster = TestCluster::start_with_shadows(1, 1, |config| {
+        config.common.replication_factor = 1;
+    })
+    .await
+    .unwrap();
+    let leader_addr = cluster.master_addr().to_string();
+    let shadow_addr = cluster.masters()[1].addr.clone();
+
+    let channel = connect_channel(&shadow_addr, &cluster.config().common.tls)
+        .await
+        .unwrap();
+    let leader = MasterClient::new(channel)
+        .get_leader(GetLeaderRequest {})
+        .await
+        .unwrap()
+        .into_inner()
+        .leader_address;
+    assert_eq!(leader, leader_addr);
+
+    // Only the shadow is listed first, the upload still goes to the leader
+    let mut config = cluster.config().clone();
+    config.common.master_addrs = vec![shadow_addr, leader_addr];
+    let mut client = rustfs::client::Client::from_config(config).await.unwrap();
+    client
+        .upload_stream(&b"through the shadow"[..], "/file")
+        .await
+        .unwrap();
+    assert!(cluster
+        .master()
+        .file_chunks
+        .read()
+        .await
+        .contains_key("/file"));
+    client.delete_file("/file").await.unwrap();
+    assert!(!cluster
+        .master()
+        .file_chunks
+        .read()
+        .await
+        .contains_key("/file"));
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn shadow_rejects_mutations_with_the_leader_address() {
+    let cluster = TestCluster::start_with_shadows(1, 1, |config| {
+        config.common.replication_factor = 1;
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    client.upload_stream(&b"kept"[..], "/kept").await.unwrap();
+    let shadow = cluster.masters()[1].clone();
+    let channel = connect_channel(&shadow.addr, &cluster.config().common.tls)
+        .await
+        .unwrap();
+    let mut master = MasterClient::new(channel);
+    let assert_redirected = |status: tonic::Status| {
+        assert_eq!(status.code(), tonic::Code::FailedPrecondition, "{}", status);
+        let leader = status.metadata().get(LEADER_ADDRESS_KEY).unwrap();
+        assert_eq!(leader.to_str().unwrap(), cluster.master_addr());
+    };
+
+    let status = master
+        .assign_chunks(AssignRequest {
+            file_name: "/new".to_string(),
+            file_size: 100,
+            token: String::new(),
+            operation_id: String::new(),
+        })
+        .await
+        .unwrap_err();
+    assert_redirected(status);
+    let status = master
+        .delete_file(DeleteFileRequest {
+            file_name: "/kept".to_string(),
+            token: String::new(),
+            operation_id: String::new(),
+        })
+        .await
+        .unwrap_err();
+    assert_redirected(status);
+
+    // Neither the shadow nor the leader changed
+    assert!(!shadow.file_chunks.read().await.contains_key("/new"));
+    assert!(!cluster
+        .master()
+        .file_chunks
+        .read()
+        .await
+        .contains_key("/new"));
+    let read = client.read_range("/kept", 0, u64::MAX).await.unwrap();
+    assert_eq!(read, b"kept");
+
+    cluster.shutdown().await.unwrap();
+}
@@ -0,0 +1,288 @@
+// The primary of a chunk applies appends and writes only to the replicas named in its lease,
+// with a proof that the secondaries check
+use rustfs::auth::Role;
+use rustfs::chunkserver_service::{apply_append_fields, apply_write_at_fields};
+use rustfs::client::Client;
+use rustfs::cluster_auth::sign_request;
+use rustfs::proto::chunk::chunk_client::ChunkClient;
+use rustfs::proto::chunk::{
+    AppendRequest, ApplyAppendRequest, ApplyWriteAtRequest, WriteAtRequest,
+};
+use rustfs::testing::TestCluster;
+use rustfs::util::connect_channel;
+use std::sync::atomic::Ordering;
+use tonic::transport::Channel;
+use tonic::Code;
+
+const CLUSTER_SECRET: &str = "test-cluster-secret";
+
+/// Cluster of 3 chunkservers with authentication, and a client of user "alice" that uploaded
+/// "/log", which is placed on 2 of them
+async fn start_cluster() -> (TestCluster, Client, String) {
+    let cluster = TestCluster::start_with(3, |config| {
+        config.common.replication_factor = 2;
+        config.common.use_authentication = true;
+        config.common.cluster_secret = CLUSTER_SECRET.to_string();
+    })
+    .await
+    .unwrap();
+    cluster
+        .add_user("alice", "secret", Role::ReadWrite)
+        .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    client.authenticate("alice", "secret").await.unwrap();
+    client.upload_stream(&b"data"[..], "/log").await.unwrap();
+    let (token, _) = cluster
+        .master()
+        .issue_session_token("alice", Role::ReadWrite);
+    (cluster, client, token)
+}
+
+async fn channel(cluster: &TestCluster, addr: &str) -> Channel {
+    connect_channel(addr, &cluster.config().common.tls)
+        .await
+        .unwrap()
+}
+
+/// Size of the chunk's data on the chunkserver, 0 if it does not hold it
+fn chunk_size(cluster: &TestCluster, index: usize, chunk_id: &str) -> u64 {
+    std::fs::metadata(cluster.chunkserver(index).chunk_path(chunk_id)).map_or(0, |file| file.len())
+}
+
+#[tokio::test]
+async fn appends_are_only_applied_to_leased_replicas() {
+    let (cluster, mut client, token) = start_cluster().await;
+    // Grants the lease on the chunk to its primary
+    let (chunk_id, replicas) = client
+        .get_append_server_addresses("/log")
+        .await
+        .unwrap()
+        .remove(0);
+    let outsider = cluster
+        .chunkserver_addrs()
+        .into_iter()
+        .find(|addr| !replicas.contains(addr))
+        .unwrap();
+    let append = |secondary_addresses: Vec<String>| AppendRequest {
+        chunk_id: chunk_id.clone(),
+        data: "more".to_string(),
+        token: token.clone(),
+        epoch: cluster.master().epoch.load(Ordering::SeqCst),
+        secondary_addresses,
+        append_id: String::new(),
+    };
+    let mut primary = ChunkClient::new(channel(&cluster, &replicas[0]).await);
+
+    let status = primary
+        .append(append(vec![outsider.clone()]))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied, "{}", status);
+    let status = primary
+        .append(append(vec![replicas[1].clone(), outsider]))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied, "{}", status);
+
+    // The replica named in the lease gets the append
+    let response = primary
+        .append(append(replicas[1..].to_vec()))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(response.failed_secondaries.is_empty());
+    for index in 0..3 {
+        let chunkserver = cluster.chunkserver(index);
+        let expected = if replicas.contains(&chunkserver.addr) {
+            8
+        } else {
+            0
+        };
+        assert_eq!(
+            chunk_size(&cluster, index, &chunk_id),
+            expected,
+            "{}",
+            chunkserver.addr
+        );
+    }
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn applied_appends_need_a_valid_proof() {
+    let (cluster, mut client, _) = start_cluster().await;
+    let (chunk_id, replicas) = client
+        .get_append_server_addresses("/log")
+        .await
+        .unwrap()
+        .remove(0);
+    let epoch = cluster.master().epoch.load(Ordering::SeqCst);
+    let data = b"more".to_vec();
+    let offset = 4u64;
+    let apply = |internal_proof: String| ApplyAppendRequest {
+        chunk_id: chunk_id.clone(),
+        data: data.clone(),
+        offset,
+        epoch,
+        internal_proof,
+        append_id: String::new(),
+    };
+    let signed = |secret: &str, data: &[u8]| {
+        sign_request(
+            secret,
+            &apply_append_fields(
+                &chunk_id,
+                data,
+                &offset.to_be_bytes(),
+                &epoch.to_be_bytes(),
+                "",
+            ),
+        )
+    };
+    let mut secondary = ChunkClient::new(channel(&cluster, &replicas[1]).await);
+
+    for proof in [
+        String::new(),
+        CLUSTER_SECRET.to_string(),
+        signed("wrong-secret", &data),
+        // Signed for other data
+        signed(CLUSTER_SECRET, b"else"),
+    ] {
+        let status = secondary.apply_append(apply(proof)).await.unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated, "{}", status);
+    }
+    secondary
+        .apply_append(apply(signed(CLUSTER_SECRET, &data)))
+        .await
+        .unwrap();
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn writes_are_only_applied_to_leased_replicas() {
+    let (cluster, mut client, token) = start_cluster().await;
+    // Grants the lease on the chunk to its primary
+    let (chunk_id, replicas) = client
+        .get_append_server_addresses("/log")
+        .await
+        .unwrap()
+        .remove(0);
+    let outsider = cluster
+        .chunkserver_addrs()
+        .into_iter()
+        .find(|addr| !replicas.contains(addr))
+        .unwrap();
+    let write = |secondary_addresses: Vec<String>| WriteAtRequest {
+        file_name: "/log".to_string(),
+        chunk_id: chunk_id.clone(),
+        offset: 0,
+        data: b"DATA".to_vec(),
+        allow_extend: false,
+        token: token.clone(),
+        epoch: cluster.master().epoch.load(Ordering::SeqCst),
+        secondary_addresses,
+    };
+    let mut primary = ChunkClient::new(channel(&cluster, &replicas[0]).await);
+
+    let status = primary
+        .write_at(write(vec![outsider.clone()]))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied, "{}", status);
+    let status = primary
+        .write_at(write(vec![replicas[1].clone(), outsider]))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied, "{}", status);
+
+    // The replica named in the lease gets the write
+    let response = primary
+        .write_at(write(replicas[1..].to_vec()))
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(response.failed_secondaries.is_empty());
+    assert_eq!(
+        client.read_range("/log", 0, u64::MAX).await.unwrap(),
+        b"DATA"
+    );
+    for index in 0..3 {
+        let chunkserver = cluster.chunkserver(index);
+        let path = chunkserver.chunk_path(&chunk_id);
+        if replicas.contains(&chunkserver.addr) {
+            assert_eq!(
+                std::fs::read(path).unwrap(),
+                b"DATA",
+                "{}",
+                chunkserver.addr
+            );
+        } else {
+            assert!(
+                !std::path::Path::new(&path).exists(),
+                "{}",
+                chunkserver.addr
+            );
+        }
+    }
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn applied_writes_need_a_valid_proof() {
+    let (cluster, mut client, _) = start_cluster().await;
+    let (chunk_id, replicas) = client
+        .get_append_server_addresses("/log")
+        .await
+        .unwrap()
+        .remove(0);
+    let epoch = cluster.master().epoch.load(Ordering::SeqCst);
+    let data = b"DA".to_vec();
+    let (offset, size) = (0u64, 4u64);
+    let apply = |internal_proof: String| ApplyWriteAtRequest {
+        chunk_id: chunk_id.clone(),
+        data: data.clone(),
+        offset,
+        size,
+        epoch,
+        internal_proof,
+    };
+    let signed = |secret: &str, data: &[u8]| {
+        sign_request(
+            secret,
+            &apply_write_at_fields(
+                &chunk_id,
+                data,
+                &offset.to_be_bytes(),
+                &size.to_be_bytes(),
+                &epoch.to_be_bytes(),
+            ),
+        )
+    };
+    let mut secondary = ChunkClient::new(channel(&cluster, &replicas[1]).await);
+
+    for proof in [
+        String::new(),
+        CLUSTER_SECRET.to_string(),
+        signed("wrong-secret", &data),
+        // Signed for other data
+        signed(CLUSTER_SECRET, b"XX"),
+    ] {
+        let status = secondary.apply_write_at(apply(proof)).await.unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated, "{}", status);
+    }
+    let index = (0..3)
+        .find(|index| cluster.chunkserver(*index).addr == replicas[1])
+        .unwrap();
+    let path = cluster.chunkserver(index).chunk_path(&chunk_id);
+    assert_eq!(std::fs::read(&path).unwrap(), b"data");
+    secondary
+        .apply_write_at(apply(signed(CLUSTER_SECRET, &data)))
+        .await
+        .unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), b"DAta");
+
+    cluster.shutdown().await.unwrap();
+}
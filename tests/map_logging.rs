@@ -0,0 +1,59 @@
+// Metadata maps are logged as entry counts, and their entries only at trace level while the
+// maps are small, so that large maps are not formatted on every request
+use rustfs::master_service::log_map;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Times a `Counted` value was formatted
+static FORMATTED: AtomicUsize = AtomicUsize::new(0);
+
+struct Counted;
+
+impl fmt::Debug for Counted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        FORMATTED.fetch_add(1, Ordering::SeqCst);
+        f.write_str("Counted")
+    }
+}
+
+#[derive(Clone, Default)]
+struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+impl Write for CapturedLogs {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn map(entries: usize) -> HashMap<usize, Counted> {
+    (0..entries).map(|key| (key, Counted)).collect()
+}
+
+#[test]
+fn only_small_maps_are_formatted() {
+    let logs = CapturedLogs::default();
+    let writer = logs.clone();
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::TRACE)
+        .with_ansi(false)
+        .with_writer(move || writer.clone())
+        .init();
+
+    log_map("large", &map(10_000));
+    assert_eq!(FORMATTED.load(Ordering::SeqCst), 0);
+
+    log_map("small", &map(3));
+    assert_eq!(FORMATTED.load(Ordering::SeqCst), 3);
+
+    let output = String::from_utf8_lossy(&logs.0.lock().unwrap()).to_string();
+    assert!(output.contains("large entries=10000"), "{}", output);
+    assert!(output.contains("small entries=3"), "{}", output);
+}
@@ -0,0 +1,177 @@
+// Chunkservers only follow commands of a master that carry a proof signed with a key derived
+// from the cluster secret
+use rustfs::chunkserver_service::{
+    clone_chunk_fields, grant_lease_fields, leader_changed_fields, transfer_chunk_fields,
+    truncate_chunk_fields,
+};
+use rustfs::cluster_auth::sign_request;
+use rustfs::proto::chunk::chunk_client::ChunkClient;
+use rustfs::proto::chunk::{
+    CloneChunkRequest, GrantLeaseRequest, LeaderChangedRequest, SendChunkRequest,
+    TruncateChunkRequest,
+};
+use rustfs::testing::TestCluster;
+use rustfs::util::connect_channel;
+use std::sync::atomic::Ordering;
+use tonic::transport::Channel;
+
+const CLUSTER_SECRET: &str = "test-cluster-secret";
+
+async fn start_cluster() -> (TestCluster, ChunkClient<Channel>) {
+    let cluster = TestCluster::start_with(2, |config| {
+        config.common.use_authentication = true;
+        config.common.cluster_secret = CLUSTER_SECRET.to_string();
+    })
+    .await
+    .unwrap();
+    let addr = &cluster.chunkserver_addrs()[0];
+    let channel = connect_channel(addr, &cluster.config().common.tls)
+        .await
+        .unwrap();
+    (cluster, ChunkClient::new(channel))
+}
+
+#[tokio::test]
+async fn commands_without_a_valid_proof_are_rejected() {
+    let (cluster, mut chunkserver) = start_cluster().await;
+    let epoch = cluster.chunkserver(0).epoch.load(Ordering::SeqCst);
+    let next_epoch = (epoch + 1).to_be_bytes();
+    let leader = cluster.master_addr().to_string();
+    let target = cluster.chunkserver_addrs()[1].clone();
+
+    for secret in ["wrong-secret", CLUSTER_SECRET] {
+        // The secret itself or a proof signed with another one
+        let proof = |fields: &[&[u8]]| {
+            if secret == CLUSTER_SECRET {
+                CLUSTER_SECRET.to_string()
+            } else {
+                sign_request(secret, fields)
+            }
+        };
+        let status = chunkserver
+            .leader_changed(LeaderChangedRequest {
+                leader_address: leader.clone(),
+                epoch: epoch + 1,
+                internal_proof: proof(&leader_changed_fields(&leader, &next_epoch)),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated, "{}", status);
+
+        let status = chunkserver
+            .grant_lease(GrantLeaseRequest {
+                chunk_id: "chunk".to_string(),
+                duration: 60,
+                epoch: epoch + 1,
+                internal_proof: proof(&grant_lease_fields(
+                    "chunk",
+                    &60u64.to_be_bytes(),
+                    &next_epoch,
+                    &[],
+                )),
+                replicas: Vec::new(),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated, "{}", status);
+
+        let status = chunkserver
+            .transfer_chunk(SendChunkRequest {
+                chunk_name: "chunk".to_string(),
+                target_address: target.clone(),
+                epoch: epoch + 1,
+                target_chunk_name: String::new(),
+                internal_proof: proof(&transfer_chunk_fields("chunk", &target, &next_epoch, "")),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated, "{}", status);
+
+        let status = chunkserver
+            .truncate_chunk(TruncateChunkRequest {
+                chunk_id: "chunk".to_string(),
+                length: 0,
+                epoch: epoch + 1,
+                internal_proof: proof(&truncate_chunk_fields(
+                    "chunk",
+                    &0u64.to_be_bytes(),
+                    &next_epoch,
+                )),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated, "{}", status);
+
+        let status = chunkserver
+            .clone_chunk(CloneChunkRequest {
+                chunk_id: "chunk".to_string(),
+                new_chunk_id: "copy".to_string(),
+                epoch: epoch + 1,
+                internal_proof: proof(&clone_chunk_fields("chunk", "copy", &next_epoch)),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated, "{}", status);
+    }
+
+    // A proof for other fields, here another chunk, is no proof either
+    let status = chunkserver
+        .grant_lease(GrantLeaseRequest {
+            chunk_id: "chunk".to_string(),
+            duration: 60,
+            epoch: epoch + 1,
+            internal_proof: sign_request(
+                CLUSTER_SECRET,
+                &grant_lease_fields("other", &60u64.to_be_bytes(), &next_epoch, &[]),
+            ),
+            replicas: Vec::new(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::Unauthenticated, "{}", status);
+
+    // Nor is a signed announcement of a leader that is not a configured master
+    let status = chunkserver
+        .leader_changed(LeaderChangedRequest {
+            leader_address: "127.0.0.1:1".to_string(),
+            epoch: epoch + 1,
+            internal_proof: sign_request(
+                CLUSTER_SECRET,
+                &leader_changed_fields("127.0.0.1:1", &next_epoch),
+            ),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::PermissionDenied, "{}", status);
+
+    // None of them raised the epoch, which would fence off the leader
+    assert_eq!(cluster.chunkserver(0).epoch.load(Ordering::SeqCst), epoch);
+    assert!(cluster.chunkserver(0).leases.lock().await.is_empty());
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn commands_with_a_valid_proof_are_followed() {
+    let (cluster, mut chunkserver) = start_cluster().await;
+    let epoch = cluster.chunkserver(0).epoch.load(Ordering::SeqCst);
+    let leader = cluster.master_addr().to_string();
+
+    chunkserver
+        .leader_changed(LeaderChangedRequest {
+            leader_address: leader.clone(),
+            epoch: epoch + 1,
+            internal_proof: sign_request(
+                CLUSTER_SECRET,
+                &leader_changed_fields(&leader, &(epoch + 1).to_be_bytes()),
+            ),
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        cluster.chunkserver(0).epoch.load(Ordering::SeqCst),
+        epoch + 1
+    );
+
+    cluster.shutdown().await.unwrap();
+}
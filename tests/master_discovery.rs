@@ -0,0 +1,89 @@
+// Connecting to the masters skips dead ones, ends up at the leader and tells apart no master being
+// reachable from no leader being known
+use rustfs::error::Error;
+use rustfs::retry::RetryPolicy;
+use rustfs::testing::TestCluster;
+use rustfs::util::connect_to_master;
+use std::time::{Duration, Instant};
+
+/// Address nothing listens on any more
+fn dead_addr() -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().to_string()
+}
+
+fn retry_policy(max_attempts: u32) -> RetryPolicy {
+    RetryPolicy {
+        max_attempts,
+        initial_backoff: Duration::from_millis(200),
+        max_backoff: Duration::from_secs(1),
+        deadline: Duration::from_secs(10),
+    }
+}
+
+#[tokio::test]
+async fn dead_master_is_skipped() {
+    let cluster = TestCluster::start_with(1, |config| {
+        config.common.replication_factor = 1;
+    })
+    .await
+    .unwrap();
+    let tls = &cluster.config().common.tls;
+    let addrs = vec![dead_addr(), cluster.master_addr().to_string()];
+    connect_to_master(&addrs, tls, &RetryPolicy::never(), None)
+        .await
+        .unwrap();
+
+    let mut config = cluster.config().clone();
+    config.common.master_addrs = addrs;
+    let mut client = rustfs::client::Client::from_config(config).await.unwrap();
+    client
+        .upload_stream(&b"past the dead master"[..], "/file")
+        .await
+        .unwrap();
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn unreachable_masters_are_retried_with_backoff() {
+    let cluster = TestCluster::start(0).await.unwrap();
+    let started = Instant::now();
+    let err = connect_to_master(
+        &vec![dead_addr()],
+        &cluster.config().common.tls,
+        &retry_policy(3),
+        None,
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, Error::NoAvailableServers(_)), "{}", err);
+    // Waited about 200ms and then 400ms before the retries, at least half of that with jitter
+    assert!(started.elapsed() >= Duration::from_millis(300));
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn shadow_without_a_live_leader_is_no_leader() {
+    let mut cluster = TestCluster::start_with_shadows(1, 0, |config| {
+        // The shadow keeps following the dead leader for the test
+        config.common.shadow_master_ping_interval = Duration::from_secs(3600);
+    })
+    .await
+    .unwrap();
+    let shadow_addr = cluster.masters()[1].addr.clone();
+    cluster.kill_master(0);
+
+    let err = connect_to_master(
+        &vec![shadow_addr],
+        &cluster.config().common.tls,
+        &RetryPolicy::never(),
+        Some(Duration::from_secs(1)),
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, Error::NoLeader(_)), "{}", err);
+
+    cluster.shutdown().await.unwrap();
+}
@@ -0,0 +1,224 @@
+// Shadow masters take metadata only from a master proving it knows the cluster secret, and the
+// leader hands its metadata out only to such masters
+use rustfs::cluster_auth::sign_request;
+use rustfs::master_service::{
+    get_metadata_fields, metadata_digest, ping_master_fields, update_metadata_fields,
+};
+use rustfs::proto::master::master_client::MasterClient;
+use rustfs::proto::master::{
+    GetMetadataRequest, Metadata, PingMasterRequest, UpdateMetadataRequest,
+};
+use rustfs::testing::TestCluster;
+use rustfs::util::connect_channel;
+use std::time::Duration;
+
+const CLUSTER_SECRET: &str = "test-cluster-secret";
+
+async fn start_cluster() -> TestCluster {
+    TestCluster::start_with_shadows(1, 1, |config| {
+        config.common.shadow_master_ping_interval = Duration::from_secs(1);
+        config.common.replication_factor = 1;
+        config.common.use_authentication = true;
+        config.common.cluster_secret = CLUSTER_SECRET.to_string();
+    })
+    .await
+    .unwrap()
+}
+
+async fn connect(cluster: &TestCluster, addr: &str) -> MasterClient<tonic::transport::Channel> {
+    let channel = connect_channel(addr, &cluster.config().common.tls)
+        .await
+        .unwrap();
+    MasterClient::new(channel)
+}
+
+#[tokio::test]
+async fn shadow_rejects_unsigned_metadata() {
+    let cluster = start_cluster().await;
+    let shadow = cluster.masters()[1];
+    let leader = cluster.master_addr().to_string();
+    let before = *shadow.metadata_version.read().await;
+    let version = before + 1000;
+    let mut client = connect(&cluster, &shadow.addr).await;
+    let metadata = Metadata {
+        metadata_version: version,
+        ..Default::default()
+    };
+    let digest = metadata_digest(&metadata);
+
+    let forged = [
+        (leader.clone(), String::new()),
+        (
+            leader.clone(),
+            sign_request(
+                "wrong-secret",
+                &update_metadata_fields(&leader, &version.to_be_bytes(), &digest),
+            ),
+        ),
+        // A proof for an older version
+        (
+            leader.clone(),
+            sign_request(
+                CLUSTER_SECRET,
+                &update_metadata_fields(&leader, &before.to_be_bytes(), &digest),
+            ),
+        ),
+    ];
+    for (leader_address, internal_proof) in forged {
+        let status = client
+            .update_metadata(UpdateMetadataRequest {
+                metadata: Some(metadata.clone()),
+                leader_address,
+                internal_proof,
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated, "{}", status);
+    }
+
+    // A signed update from an address that is no master is no better
+    let status = client
+        .update_metadata(UpdateMetadataRequest {
+            metadata: Some(metadata.clone()),
+            leader_address: "127.0.0.1:1".to_string(),
+            internal_proof: sign_request(
+                CLUSTER_SECRET,
+                &update_metadata_fields("127.0.0.1:1", &version.to_be_bytes(), &digest),
+            ),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::Unauthenticated, "{}", status);
+
+    // Nor is a captured proof replayed with other metadata of the same version
+    let mut replayed = metadata.clone();
+    replayed
+        .file_owners
+        .insert("/file".to_string(), "mallory".to_string());
+    let status = client
+        .update_metadata(UpdateMetadataRequest {
+            metadata: Some(replayed),
+            leader_address: leader.clone(),
+            internal_proof: sign_request(
+                CLUSTER_SECRET,
+                &update_metadata_fields(&leader, &version.to_be_bytes(), &digest),
+            ),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::Unauthenticated, "{}", status);
+    assert!(*shadow.metadata_version.read().await < version);
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn metadata_is_only_handed_out_to_masters() {
+    let cluster = start_cluster().await;
+    let shadow_addr = cluster.masters()[1].addr.clone();
+    let mut client = connect(&cluster, cluster.master_addr()).await;
+
+    for internal_proof in [
+        String::new(),
+        sign_request("wrong-secret", &get_metadata_fields(&shadow_addr)),
+    ] {
+        let status = client
+            .get_metadata(GetMetadataRequest {
+                sender_address: shadow_addr.clone(),
+                internal_proof,
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated, "{}", status);
+    }
+
+    let response = client
+        .get_metadata(GetMetadataRequest {
+            sender_address: shadow_addr.clone(),
+            internal_proof: sign_request(CLUSTER_SECRET, &get_metadata_fields(&shadow_addr)),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(response.metadata.is_some());
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn shadow_follows_the_signed_updates_of_the_leader() {
+    let cluster = start_cluster().await;
+    cluster
+        .add_user("alice", "secret", rustfs::auth::Role::ReadWrite)
+        .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    client.authenticate("alice", "secret").await.unwrap();
+    client
+        .upload_stream(&b"replicated metadata"[..], "/file")
+        .await
+        .unwrap();
+
+    let shadow = cluster.masters()[1];
+    tokio::time::timeout(Duration::from_secs(20), async {
+        while !shadow.file_chunks.read().await.contains_key("/file") {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("The shadow did not apply the leader's metadata");
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn only_masters_register_as_shadows() {
+    let cluster = start_cluster().await;
+    let leader = cluster.master();
+    let shadow_addr = cluster.masters()[1].addr.clone();
+    let mut client = connect(&cluster, cluster.master_addr()).await;
+
+    // Unsigned, signed with another secret, or signed by an address that is no master
+    let forged = [
+        (shadow_addr.clone(), String::new()),
+        (
+            shadow_addr.clone(),
+            sign_request("wrong-secret", &ping_master_fields(&shadow_addr)),
+        ),
+        (
+            "127.0.0.1:1".to_string(),
+            sign_request(CLUSTER_SECRET, &ping_master_fields("127.0.0.1:1")),
+        ),
+    ];
+    for (sender_address, internal_proof) in forged {
+        let status = client
+            .ping_master(PingMasterRequest {
+                sender_address,
+                internal_proof,
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated, "{}", status);
+    }
+    assert!(!leader
+        .shadow_masters
+        .read()
+        .await
+        .contains_key("127.0.0.1:1"));
+
+    let response = client
+        .ping_master(PingMasterRequest {
+            sender_address: shadow_addr.clone(),
+            internal_proof: sign_request(CLUSTER_SECRET, &ping_master_fields(&shadow_addr)),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(response.is_leader);
+    assert!(leader
+        .shadow_masters
+        .read()
+        .await
+        .contains_key(&shadow_addr));
+
+    cluster.shutdown().await.unwrap();
+}
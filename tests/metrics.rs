@@ -0,0 +1,161 @@
+// The master and chunkservers serve Prometheus metrics of the requests they handled at /metrics
+use prometheus::Registry;
+use rustfs::metrics::start_metrics_server;
+use rustfs::testing::TestCluster;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Serve `registry` on a free port and return the text served at /metrics
+async fn scrape(registry: &Registry) -> String {
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .to_string();
+    let server = start_metrics_server(&addr, registry.clone(), None).unwrap();
+    let mut stream = tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            match TcpStream::connect(&addr).await {
+                Ok(stream) => return stream,
+                Err(_) => tokio::time::sleep(Duration::from_millis(50)).await,
+            }
+        }
+    })
+    .await
+    .expect("The metrics server did not start");
+    stream
+        .write_all(b"GET /metrics HTTP/1.0\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    server.abort();
+    assert!(response.starts_with("HTTP/1.0 200"), "{}", response);
+    response
+}
+
+/// The value of the series `series` in the scraped `metrics`, panicking if it is missing
+fn value(metrics: &str, series: &str) -> f64 {
+    metrics
+        .lines()
+        .find_map(|line| line.strip_prefix(series)?.strip_prefix(' '))
+        .unwrap_or_else(|| panic!("No series {} in:\n{}", series, metrics))
+        .parse()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn master_metrics_count_requests_and_chunkservers() {
+    let cluster = TestCluster::start_with(2, |config| {
+        config.common.replication_factor = 2;
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    client.upload_stream(&b"kept"[..], "/kept").await.unwrap();
+    client
+        .upload_stream(&b"deleted"[..], "/deleted")
+        .await
+        .unwrap();
+    client.delete_file("/deleted").await.unwrap();
+
+    let metrics = scrape(&cluster.master().metrics.registry).await;
+    assert_eq!(value(&metrics, "rustfs_master_chunkservers"), 2.0);
+    assert!(value(&metrics, "rustfs_master_heartbeats_total") >= 2.0);
+    assert_eq!(
+        value(
+            &metrics,
+            r#"rustfs_master_requests_total{method="assign_chunks",status="ok"}"#
+        ),
+        2.0
+    );
+    assert_eq!(
+        value(
+            &metrics,
+            r#"rustfs_master_requests_total{method="delete_file",status="ok"}"#
+        ),
+        1.0
+    );
+    assert_eq!(
+        value(
+            &metrics,
+            r#"rustfs_master_request_duration_seconds_count{method="assign_chunks"}"#
+        ),
+        2.0
+    );
+    // Set from the heartbeats, which may not have reported the latest changes yet
+    for addr in cluster.chunkserver_addrs() {
+        let series = format!(r#"rustfs_master_chunkserver_chunks{{server="{}"}}"#, addr);
+        assert!(value(&metrics, &series) >= 0.0);
+    }
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn chunkserver_metrics_count_bytes_and_chunks() {
+    let cluster = TestCluster::start_with(1, |config| {
+        config.common.replication_factor = 1;
+        config.common.heartbeat_interval = Duration::from_secs(1);
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    let data = vec![7u8; 3000];
+    client.upload_stream(&data[..], "/file").await.unwrap();
+    let read = client.read_range("/file", 0, u64::MAX).await.unwrap();
+    assert_eq!(read, data);
+
+    // The stored chunks and disk usage are refreshed with the heartbeats
+    let chunkserver = cluster.chunkserver(0);
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while chunkserver.metrics.chunks.get() < 1 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("The stored chunks were not counted");
+
+    let metrics = scrape(&chunkserver.metrics.registry).await;
+    assert_eq!(
+        value(
+            &metrics,
+            r#"rustfs_chunkserver_requests_total{method="upload",status="ok"}"#
+        ),
+        1.0
+    );
+    assert_eq!(
+        value(
+            &metrics,
+            r#"rustfs_chunkserver_requests_total{method="read",status="ok"}"#
+        ),
+        1.0
+    );
+    assert_eq!(
+        value(
+            &metrics,
+            r#"rustfs_chunkserver_request_duration_seconds_count{method="read"}"#
+        ),
+        1.0
+    );
+    assert_eq!(
+        value(
+            &metrics,
+            r#"rustfs_chunkserver_bytes_written_total{method="upload"}"#
+        ),
+        3000.0
+    );
+    assert_eq!(
+        value(
+            &metrics,
+            r#"rustfs_chunkserver_bytes_read_total{method="read"}"#
+        ),
+        3000.0
+    );
+    assert_eq!(value(&metrics, "rustfs_chunkserver_chunks"), 1.0);
+    assert!(value(&metrics, "rustfs_chunkserver_data_bytes") >= 3000.0);
+    assert!(value(&metrics, "rustfs_chunkserver_disk_total_bytes") > 0.0);
+
+    cluster.shutdown().await.unwrap();
+}
@@ -0,0 +1,50 @@
+// Config files only need the master addresses, every other setting has a default
+use rustfs::config::{load_config, Config};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Load a config file holding `content`
+fn load(content: &str) -> rustfs::error::Result<Config> {
+    let path = std::env::temp_dir().join(format!("rustfs-config-{}.toml", Uuid::new_v4()));
+    std::fs::write(&path, content).unwrap();
+    let config = load_config(&path.to_string_lossy());
+    std::fs::remove_file(&path).unwrap();
+    config
+}
+
+/// `config` as TOML, to compare whole configs
+fn settings(config: &Config) -> toml::Value {
+    toml::Value::try_from(config).unwrap()
+}
+
+#[test]
+fn master_addrs_are_enough() {
+    let config = load("[common]\nmaster_addrs = [\"127.0.0.1:50001\"]\n").unwrap();
+    assert_eq!(config.common.master_addrs, vec!["127.0.0.1:50001"]);
+    assert_eq!(config.common.chunk_size, 64 * 1024 * 1024);
+    assert_eq!(config.common.replication_factor, 3);
+    assert_eq!(config.common.heartbeat_interval, Duration::from_secs(5));
+
+    let mut expected = Config::default();
+    expected.common.master_addrs = config.common.master_addrs.clone();
+    assert_eq!(settings(&config), settings(&expected));
+}
+
+#[test]
+fn missing_master_addrs_are_named() {
+    let message = load("[common]\nchunk_size = \"1MiB\"\n")
+        .unwrap_err()
+        .to_string();
+    assert!(message.contains("master_addrs"), "{}", message);
+}
+
+#[test]
+fn example_lists_the_defaults() {
+    let example = Config::example();
+    assert!(example.lines().any(|line| line.starts_with('#')));
+    // With an example master address, the only setting without a default
+    let config = load(&example).unwrap();
+    let mut expected = Config::default();
+    expected.common.master_addrs = config.common.master_addrs.clone();
+    assert_eq!(settings(&config), settings(&expected));
+}
@@ -0,0 +1,100 @@
+// Paths of the hierarchical namespace: normalization and names that are both a file and a
+// directory
+use rustfs::namespace::{find_conflict, is_under, normalize_dir, normalize_path};
+use rustfs::testing::TestCluster;
+
+#[test]
+fn paths_are_normalized() {
+    for (path, expected) in [
+        ("/a/b", "/a/b"),
+        ("a/b", "/a/b"),
+        ("/a/./b", "/a/b"),
+        ("./a", "/a"),
+        ("/file.txt", "/file.txt"),
+    ] {
+        assert_eq!(normalize_path(path).unwrap(), expected, "{}", path);
+    }
+}
+
+#[test]
+fn invalid_paths_are_rejected() {
+    for path in [
+        "", "/", ".", "/a/../b", "..", "/a/..", "a//b", "//a", "/a/b/", "/a/./",
+    ] {
+        assert!(normalize_path(path).is_err(), "{}", path);
+    }
+}
+
+#[test]
+fn directories_are_normalized() {
+    for (path, expected) in [
+        ("", "/"),
+        ("/", "/"),
+        ("/a/", "/a"),
+        ("a/b/", "/a/b"),
+        ("/a/./b", "/a/b"),
+    ] {
+        assert_eq!(normalize_dir(path).unwrap(), expected, "{}", path);
+    }
+    for path in ["/a//b", "/a/../b", "/a//"] {
+        assert!(normalize_dir(path).is_err(), "{}", path);
+    }
+}
+
+#[test]
+fn paths_under_directories() {
+    assert!(is_under("/a/b", "/a"));
+    assert!(is_under("/a/b/c", "/a"));
+    assert!(is_under("/a", "/"));
+    assert!(!is_under("/a", "/a"));
+    assert!(!is_under("/ab/c", "/a"));
+}
+
+#[test]
+fn files_and_directories_of_the_same_name_conflict() {
+    let existing = ["/a".to_string(), "/b/c".to_string(), "/d".to_string()];
+
+    // A file below an existing file, or a file where files lie below
+    assert_eq!(find_conflict(&existing, "/a/x"), Some(&existing[0]));
+    assert_eq!(find_conflict(&existing, "/a/x/y"), Some(&existing[0]));
+    assert_eq!(find_conflict(&existing, "/b"), Some(&existing[1]));
+
+    // Siblings and names sharing a prefix are fine
+    assert_eq!(find_conflict(&existing, "/b/e"), None);
+    assert_eq!(find_conflict(&existing, "/ab"), None);
+    assert_eq!(find_conflict(&existing, "/d2/x"), None);
+}
+
+#[tokio::test]
+async fn uploads_conflicting_with_files_are_rejected() {
+    let cluster = TestCluster::start_with(1, |config| {
+        config.common.replication_factor = 1;
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    client
+        .upload_stream(&b"file"[..], "/dir/file")
+        .await
+        .unwrap();
+
+    // `/dir` is a directory and `/dir/file` a file
+    client
+        .upload_stream(&b"clash"[..], "/dir")
+        .await
+        .unwrap_err();
+    client
+        .upload_stream(&b"clash"[..], "/dir/file/below")
+        .await
+        .unwrap_err();
+
+    // Paths are normalized before they are looked up
+    let read = client.read_range("dir/./file", 0, u64::MAX).await.unwrap();
+    assert_eq!(read, b"file");
+    client
+        .read_range("/dir/../dir/file", 0, u64::MAX)
+        .await
+        .unwrap_err();
+
+    cluster.shutdown().await.unwrap();
+}
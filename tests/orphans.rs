@@ -0,0 +1,74 @@
+// Chunks a chunkserver still holds of files deleted while it was offline are deleted once it
+// rejoins, unless the master became leader too recently to know all files
+use rustfs::testing::TestCluster;
+use std::path::Path;
+use std::time::Duration;
+
+/// Delete a file while the chunkserver holding one of its replicas is offline and dropped from
+/// the replicas, then restart that chunkserver. Returns the cluster, the chunkserver's index
+/// and the ID of the chunk left on it.
+async fn rejoin_after_delete(orphan_grace_period: Duration) -> (TestCluster, usize, String) {
+    let mut cluster = TestCluster::start_with(2, |config| {
+        config.common.replication_factor = 2;
+        config.common.heartbeat_interval = Duration::from_secs(1);
+        config.master.heartbeat_failure_threshold = 1;
+        config.master.cron_interval = Duration::from_secs(1);
+        config.master.orphan_grace_period = orphan_grace_period;
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    client
+        .upload_stream(&b"deleted offline"[..], "/file")
+        .await
+        .unwrap();
+    let chunk_id = client.get_file_info("/file").await.unwrap().chunks[0]
+        .chunk_id
+        .clone();
+    let offline = cluster.chunkserver_addrs()[1].clone();
+
+    cluster.stop_chunkserver(1).await.unwrap();
+    let master = cluster.master();
+    tokio::time::timeout(Duration::from_secs(30), async {
+        while master.chunk_map.read().await[&chunk_id]
+            .server_addresses
+            .contains(&offline)
+        {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    })
+    .await
+    .expect("The offline chunkserver stayed among the replicas");
+    client.delete_file("/file").await.unwrap();
+
+    cluster.restart_chunkserver(1).await.unwrap();
+    (cluster, 1, chunk_id)
+}
+
+#[tokio::test]
+async fn orphaned_chunk_is_deleted_on_rejoin() {
+    let (cluster, index, chunk_id) = rejoin_after_delete(Duration::ZERO).await;
+    let chunkserver = cluster.chunkserver(index);
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while Path::new(&chunkserver.chunk_path(&chunk_id)).exists() {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("The orphaned chunk was not deleted");
+    assert!(!chunkserver.server_chunks.lock().await.contains(&chunk_id));
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn orphaned_chunk_is_kept_during_the_grace_period() {
+    let (cluster, index, chunk_id) = rejoin_after_delete(Duration::from_secs(3600)).await;
+    // A few heartbeats later, the chunk is still there
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    let chunkserver = cluster.chunkserver(index);
+    assert!(Path::new(&chunkserver.chunk_path(&chunk_id)).exists());
+    assert!(chunkserver.server_chunks.lock().await.contains(&chunk_id));
+
+    cluster.shutdown().await.unwrap();
+}
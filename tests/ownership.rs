@@ -0,0 +1,116 @@
+// Only the owner of a file or an admin may delete it or record appends to it
+use rustfs::auth::Role;
+use rustfs::client::Client;
+use rustfs::error::Error;
+use rustfs::proto::master::master_client::MasterClient;
+use rustfs::proto::master::RecordAppendRequest;
+use rustfs::testing::TestCluster;
+use rustfs::util::connect_channel;
+
+/// Cluster with authentication, readwrite users "alice" and "bob" and admin "admin", all with
+/// password "secret"
+async fn start_cluster() -> TestCluster {
+    let cluster = TestCluster::start_with(1, |config| {
+        config.common.use_authentication = true;
+        config.common.cluster_secret = "test-cluster-secret".to_string();
+        config.common.replication_factor = 1;
+    })
+    .await
+    .unwrap();
+    for (username, role) in [
+        ("alice", Role::ReadWrite),
+        ("bob", Role::ReadWrite),
+        ("admin", Role::Admin),
+    ] {
+        cluster.add_user(username, "secret", role).unwrap();
+    }
+    cluster
+}
+
+async fn client_of(cluster: &TestCluster, username: &str) -> Client {
+    let mut client = cluster.client().await.unwrap();
+    client.authenticate(username, "secret").await.unwrap();
+    client
+}
+
+/// Record an append of `size` bytes to `file_name` as `username`, straight to the master
+async fn record_append(
+    cluster: &TestCluster,
+    username: &str,
+    file_name: &str,
+    size: u64,
+) -> Result<(), tonic::Status> {
+    let role = if username == "admin" {
+        Role::Admin
+    } else {
+        Role::ReadWrite
+    };
+    let (token, _) = cluster.master().issue_session_token(username, role);
+    let channel = connect_channel(cluster.master_addr(), &cluster.config().common.tls)
+        .await
+        .unwrap();
+    MasterClient::new(channel)
+        .record_append(RecordAppendRequest {
+            file_name: file_name.to_string(),
+            size,
+            token,
+        })
+        .await
+        .map(drop)
+}
+
+#[tokio::test]
+async fn only_owners_and_admins_delete_files() {
+    let cluster = start_cluster().await;
+    let mut alice = client_of(&cluster, "alice").await;
+    let mut bob = client_of(&cluster, "bob").await;
+    let mut admin = client_of(&cluster, "admin").await;
+    for file in ["/alice/own", "/alice/by-admin"] {
+        alice.upload_stream(&b"data"[..], file).await.unwrap();
+    }
+
+    let result = bob.delete_file("/alice/own").await;
+    assert!(
+        matches!(result, Err(Error::PermissionDenied(_))),
+        "{:?}",
+        result
+    );
+    assert_eq!(
+        alice.get_file_info("/alice/own").await.unwrap().owner,
+        "alice"
+    );
+
+    alice.delete_file("/alice/own").await.unwrap();
+    assert!(alice.get_file_info("/alice/own").await.is_err());
+    admin.delete_file("/alice/by-admin").await.unwrap();
+    assert!(alice.get_file_info("/alice/by-admin").await.is_err());
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn only_owners_and_admins_record_appends() {
+    let cluster = start_cluster().await;
+    let mut alice = client_of(&cluster, "alice").await;
+    alice.upload_stream(&b"data"[..], "/log").await.unwrap();
+
+    let status = record_append(&cluster, "bob", "/log", 100)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::PermissionDenied, "{}", status);
+    assert_eq!(alice.get_file_info("/log").await.unwrap().total_size, 4);
+
+    record_append(&cluster, "alice", "/log", 1).await.unwrap();
+    record_append(&cluster, "admin", "/log", 2).await.unwrap();
+    assert_eq!(alice.get_file_info("/log").await.unwrap().total_size, 7);
+
+    // Appends through the client record them as the owner
+    let servers = alice.get_append_server_addresses("/log").await.unwrap();
+    alice
+        .append_file(servers, "/log", "more".to_string())
+        .await
+        .unwrap();
+    assert_eq!(alice.get_file_info("/log").await.unwrap().total_size, 11);
+
+    cluster.shutdown().await.unwrap();
+}
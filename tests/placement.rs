@@ -0,0 +1,168 @@
+// Selection of the chunkservers receiving the replicas of a chunk
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rustfs::master_service::placement::{select_servers, Candidates, PlacementPolicy};
+use rustfs::master_service::ServerCapacity;
+use std::collections::{HashMap, HashSet};
+
+const POLICIES: [PlacementPolicy; 3] = [
+    PlacementPolicy::LeastLoaded,
+    PlacementPolicy::MostFreeSpace,
+    PlacementPolicy::FailureDomain,
+];
+
+fn host(addr: &str) -> &str {
+    addr.rsplit_once(':').unwrap().0
+}
+
+#[test]
+fn replicas_spread_over_hosts() {
+    // Three servers on one host, with the fewest chunks, and one on another host
+    let candidates = Candidates {
+        servers: HashMap::from([
+            ("10.0.0.1:50010".to_string(), 0),
+            ("10.0.0.1:50011".to_string(), 1),
+            ("10.0.0.1:50012".to_string(), 2),
+            ("10.0.0.2:50010".to_string(), 10),
+        ]),
+        ..Candidates::default()
+    };
+
+    let placement = select_servers(&candidates, 2, &[], PlacementPolicy::FailureDomain);
+    let hosts: HashSet<&str> = placement.servers.iter().map(|addr| host(addr)).collect();
+    assert_eq!(hosts.len(), 2, "{:?}", placement.servers);
+    assert!(!placement.colocated);
+
+    // With a replica on the second host already, the rest share the first one
+    let placement = select_servers(
+        &candidates,
+        2,
+        &["10.0.0.2:50010".to_string()],
+        PlacementPolicy::FailureDomain,
+    );
+    assert_eq!(placement.servers.len(), 2);
+    assert!(placement
+        .servers
+        .iter()
+        .all(|addr| host(addr) == "10.0.0.1"));
+    assert!(placement.colocated);
+
+    // Other policies ignore hosts
+    let placement = select_servers(&candidates, 2, &[], PlacementPolicy::LeastLoaded);
+    assert_eq!(placement.servers, ["10.0.0.1:50010", "10.0.0.1:50011"]);
+}
+
+/// Up to 12 servers on up to 4 hosts, with random chunk counts and capacities, some of them
+/// below `min_free_bytes` or not reported yet
+fn random_candidates(rng: &mut StdRng) -> Candidates {
+    let mut candidates = Candidates {
+        min_free_bytes: 100,
+        ..Candidates::default()
+    };
+    for port in 0..rng.gen_range(0..=12) {
+        let addr = format!("10.0.0.{}:{}", rng.gen_range(1..=4), 50000 + port);
+        candidates
+            .servers
+            .insert(addr.clone(), rng.gen_range(0..20));
+        if rng.gen_bool(0.8) {
+            let capacity = ServerCapacity {
+                total_bytes: 1000,
+                available_bytes: rng.gen_range(0..1000),
+            };
+            candidates.capacities.insert(addr.clone(), capacity);
+        }
+        if rng.gen_bool(0.3) {
+            let domain = format!("rack{}", rng.gen_range(1..=3));
+            candidates.failure_domains.insert(addr, domain);
+        }
+    }
+    candidates
+}
+
+/// Servers `select_servers` may select: not excluded and not short of free space
+fn eligible(candidates: &Candidates, exclude: &[String]) -> HashSet<String> {
+    candidates
+        .servers
+        .keys()
+        .filter(|addr| !exclude.contains(addr))
+        .filter(|addr| {
+            candidates
+                .capacities
+                .get(*addr)
+                .is_none_or(|capacity| capacity.available_bytes >= candidates.min_free_bytes)
+        })
+        .cloned()
+        .collect()
+}
+
+#[test]
+fn selections_hold_for_random_candidates() {
+    let mut rng = StdRng::seed_from_u64(593);
+    for _ in 0..2000 {
+        let candidates = random_candidates(&mut rng);
+        let exclude: Vec<String> = candidates
+            .servers
+            .keys()
+            .filter(|_| rng.gen_bool(0.25))
+            .cloned()
+            .collect();
+        let count = rng.gen_range(0..=6);
+        let eligible = eligible(&candidates, &exclude);
+        for policy in POLICIES {
+            let placement = select_servers(&candidates, count, &exclude, policy);
+            let case = format!("{:?} of {} out of {:?}", policy, count, candidates);
+
+            // No server is selected twice, or both selected and a spare
+            let selected: HashSet<&String> = placement.servers.iter().collect();
+            assert_eq!(selected.len(), placement.servers.len(), "{}", case);
+            let all: HashSet<&String> = selected.iter().copied().chain(&placement.spares).collect();
+            assert_eq!(
+                all.len(),
+                placement.servers.len() + placement.spares.len(),
+                "{}",
+                case
+            );
+            // Only eligible servers, excluded ones never, and every eligible one somewhere
+            assert_eq!(all, eligible.iter().collect::<HashSet<_>>(), "{}", case);
+            // A shortfall is reported rather than padded with ineligible or repeated servers
+            assert_eq!(
+                placement.servers.len(),
+                count.min(eligible.len()),
+                "{}",
+                case
+            );
+            assert_eq!(
+                placement.shortfall,
+                count.saturating_sub(eligible.len()),
+                "{}",
+                case
+            );
+        }
+    }
+}
+
+#[test]
+fn shortfall_when_too_few_servers_are_eligible() {
+    let candidates = Candidates {
+        servers: HashMap::from([
+            ("10.0.0.1:50010".to_string(), 0),
+            ("10.0.0.2:50010".to_string(), 0),
+            ("10.0.0.3:50010".to_string(), 0),
+        ]),
+        capacities: HashMap::from([(
+            "10.0.0.3:50010".to_string(),
+            ServerCapacity {
+                total_bytes: 1000,
+                available_bytes: 10,
+            },
+        )]),
+        min_free_bytes: 100,
+        ..Candidates::default()
+    };
+    for policy in POLICIES {
+        let placement = select_servers(&candidates, 3, &["10.0.0.1:50010".to_string()], policy);
+        assert_eq!(placement.servers, ["10.0.0.2:50010"]);
+        assert_eq!(placement.shortfall, 2);
+        assert!(placement.spares.is_empty());
+    }
+}
@@ -0,0 +1,133 @@
+// The master records the primary of each chunk, and elects another replica once the primary
+// misses its heartbeats
+use rustfs::testing::TestCluster;
+use std::time::Duration;
+
+#[tokio::test]
+async fn primary_is_reelected_when_it_fails() {
+    let mut cluster = TestCluster::start_with(3, |config| {
+        config.common.replication_factor = 3;
+        config.common.heartbeat_interval = Duration::from_secs(1);
+        config.master.heartbeat_failure_threshold = 1;
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    client.upload_stream(&b"first"[..], "/log").await.unwrap();
+    // Grants the lease on the chunk to its primary
+    let servers = client.get_append_server_addresses("/log").await.unwrap();
+    client
+        .append_file(servers, "/log", ", second".to_string())
+        .await
+        .unwrap();
+
+    let chunk = cluster.master().file_chunks.read().await["/log"][0].clone();
+    assert!(chunk.server_addresses.contains(&chunk.primary));
+    let failed = cluster
+        .chunkserver_addrs()
+        .iter()
+        .position(|addr| *addr == chunk.primary)
+        .unwrap();
+    cluster.kill_chunkserver(failed);
+
+    let master = cluster.master();
+    let primary = tokio::time::timeout(Duration::from_secs(15), async {
+        loop {
+            let primary = master.chunk_map.read().await[&chunk.chunk_id]
+                .primary
+                .clone();
+            if !primary.is_empty() && primary != chunk.primary {
+                return primary;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("No new primary was elected");
+    assert!(chunk.server_addresses.contains(&primary));
+    assert!(cluster.chunkserver_addrs().contains(&primary));
+
+    // Appends go to the new primary
+    let servers = client.get_append_server_addresses("/log").await.unwrap();
+    assert_eq!(servers[0].1[0], primary);
+    client
+        .append_file(servers, "/log", ", third".to_string())
+        .await
+        .unwrap();
+    let read = client.read_range("/log", 0, u64::MAX).await.unwrap();
+    assert_eq!(read, b"first, second, third");
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn appends_move_to_the_new_lease_holder_once_the_lease_lapsed() {
+    let mut cluster = TestCluster::start_with(3, |config| {
+        config.common.replication_factor = 3;
+        config.common.heartbeat_interval = Duration::from_secs(1);
+        config.master.heartbeat_failure_threshold = 1;
+        config.master.lease_duration = Duration::from_secs(2);
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    client.upload_stream(&b"first"[..], "/log").await.unwrap();
+    let servers = client.get_append_server_addresses("/log").await.unwrap();
+    let (chunk_id, replicas) = servers[0].clone();
+    client
+        .append_file(servers, "/log", ", second".to_string())
+        .await
+        .unwrap();
+
+    // Keeps the lease state of the old primary after it is gone
+    let failed = cluster
+        .chunkserver_addrs()
+        .iter()
+        .position(|addr| *addr == replicas[0])
+        .unwrap();
+    let old_primary = cluster.chunkserver(failed).clone();
+    old_primary.check_lease(&chunk_id, &[]).await.unwrap();
+    cluster.kill_chunkserver(failed);
+
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while old_primary.check_lease(&chunk_id, &[]).await.is_ok() {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("The lease of the old primary did not lapse");
+    let status = old_primary.check_lease(&chunk_id, &[]).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::FailedPrecondition, "{}", status);
+
+    // The next append of the sequence goes through the replica the lease moved to
+    let servers = tokio::time::timeout(Duration::from_secs(15), async {
+        loop {
+            let servers = client.get_append_server_addresses("/log").await.unwrap();
+            if servers[0].1[0] != replicas[0] {
+                return servers;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("The lease was not granted to another replica");
+    let new_primary = servers[0].1[0].clone();
+    assert!(replicas.contains(&new_primary));
+    let index = (0..3)
+        .filter(|index| *index != failed)
+        .find(|index| cluster.chunkserver(*index).addr == new_primary)
+        .unwrap();
+    cluster
+        .chunkserver(index)
+        .check_lease(&chunk_id, &servers[0].1[1..])
+        .await
+        .unwrap();
+    client
+        .append_file(servers, "/log", ", third".to_string())
+        .await
+        .unwrap();
+    let read = client.read_range("/log", 0, u64::MAX).await.unwrap();
+    assert_eq!(read, b"first, second, third");
+
+    cluster.shutdown().await.unwrap();
+}
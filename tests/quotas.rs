@@ -0,0 +1,77 @@
+// Uploads are rejected once they would take the namespace past max_total_bytes or max_files
+use rustfs::error::Error;
+use rustfs::master_service::Usage;
+use rustfs::testing::TestCluster;
+
+#[tokio::test]
+async fn files_up_to_the_byte_quota_fit() {
+    let cluster = TestCluster::start_with(1, |config| {
+        config.common.replication_factor = 1;
+        config.master.max_total_bytes = 100;
+    })
+    .await
+    .unwrap();
+    let master = cluster.master();
+
+    // Right at the limit, and one byte over it
+    let used = Usage {
+        total_bytes: 60,
+        files: 1,
+    };
+    assert_eq!(master.exceeded_quota(used, 40), None);
+    assert!(master.exceeded_quota(used, 41).is_some());
+
+    let mut client = cluster.client().await.unwrap();
+    client
+        .upload_stream(&[1u8; 60][..], "/first")
+        .await
+        .unwrap();
+    client
+        .upload_stream(&[2u8; 40][..], "/second")
+        .await
+        .unwrap();
+    let err = client
+        .upload_stream(&[3u8; 1][..], "/third")
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::QuotaExceeded(_)), "{}", err);
+
+    // Deleting a file frees its bytes
+    client.delete_file("/first").await.unwrap();
+    client
+        .upload_stream(&[3u8; 60][..], "/third")
+        .await
+        .unwrap();
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn files_past_the_file_quota_are_rejected() {
+    let cluster = TestCluster::start_with(1, |config| {
+        config.common.replication_factor = 1;
+        config.master.max_files = 2;
+    })
+    .await
+    .unwrap();
+    let master = cluster.master();
+    let usage = |files| Usage {
+        total_bytes: 0,
+        files,
+    };
+    assert_eq!(master.exceeded_quota(usage(1), 1), None);
+    assert!(master.exceeded_quota(usage(2), 1).is_some());
+
+    let mut client = cluster.client().await.unwrap();
+    for name in ["/one", "/two"] {
+        client.upload_stream(&b"data"[..], name).await.unwrap();
+    }
+    let err = client
+        .upload_stream(&b"data"[..], "/three")
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::QuotaExceeded(_)), "{}", err);
+    client.get_file_info("/three").await.unwrap_err();
+
+    cluster.shutdown().await.unwrap();
+}
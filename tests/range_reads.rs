@@ -0,0 +1,109 @@
+// Reads of a byte range of a chunk, clamped at the end of the chunk
+use futures::StreamExt;
+use rustfs::proto::chunk::chunk_client::ChunkClient;
+use rustfs::proto::chunk::ReadRequest;
+use rustfs::testing::TestCluster;
+use rustfs::util::connect_channel;
+
+const DATA: &[u8] = b"0123456789abcdefghij";
+
+/// Read `length` bytes at `offset` of a chunk straight from its chunkserver
+async fn read(
+    chunkserver: &mut ChunkClient<tonic::transport::Channel>,
+    chunk_id: &str,
+    offset: Option<u64>,
+    length: Option<u64>,
+) -> Vec<u8> {
+    let mut stream = chunkserver
+        .read(ReadRequest {
+            chunk_id: chunk_id.to_string(),
+            token: String::new(),
+            offset,
+            length,
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    let mut data = Vec::new();
+    while let Some(frame) = stream.next().await {
+        data.extend(frame.unwrap().content);
+    }
+    data
+}
+
+#[tokio::test]
+async fn ranges_are_clamped_at_the_end_of_the_chunk() {
+    let cluster = TestCluster::start_with(1, |config| {
+        config.common.replication_factor = 1;
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    client.upload_stream(DATA, "/file").await.unwrap();
+    let chunk_id = client.get_file_info("/file").await.unwrap().chunks[0]
+        .chunk_id
+        .clone();
+    let channel = connect_channel(
+        &cluster.chunkserver_addrs()[0],
+        &cluster.config().common.tls,
+    )
+    .await
+    .unwrap();
+    let mut chunkserver = ChunkClient::new(channel);
+    let size = DATA.len() as u64;
+
+    assert_eq!(read(&mut chunkserver, &chunk_id, None, None).await, DATA);
+    assert_eq!(
+        read(&mut chunkserver, &chunk_id, Some(5), Some(3)).await,
+        b"567"
+    );
+    // Zero length, an offset at or past the end, and a length running past the end
+    assert!(read(&mut chunkserver, &chunk_id, Some(5), Some(0))
+        .await
+        .is_empty());
+    assert!(read(&mut chunkserver, &chunk_id, Some(size), None)
+        .await
+        .is_empty());
+    assert!(
+        read(&mut chunkserver, &chunk_id, Some(size + 100), Some(10))
+            .await
+            .is_empty()
+    );
+    assert_eq!(
+        read(&mut chunkserver, &chunk_id, Some(15), Some(100)).await,
+        b"fghij"
+    );
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn client_ranges_span_chunks() {
+    let cluster = TestCluster::start_with(1, |config| {
+        config.common.replication_factor = 1;
+        config.common.chunk_size = 8;
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    client.upload_stream(DATA, "/file").await.unwrap();
+
+    for (offset, length) in [(0, 20), (6, 4), (8, 8), (19, 5), (3, u64::MAX)] {
+        let read = client.read_range("/file", offset, length).await.unwrap();
+        let end = (offset.saturating_add(length)).min(DATA.len() as u64);
+        assert_eq!(
+            read,
+            &DATA[offset as usize..end as usize],
+            "{} {}",
+            offset,
+            length
+        );
+    }
+    // Nothing past the end of the file
+    let read = client.read_range("/file", 20, 10).await.unwrap();
+    assert!(read.is_empty());
+    let read = client.read_range("/file", 5, 0).await.unwrap();
+    assert!(read.is_empty());
+
+    cluster.shutdown().await.unwrap();
+}
@@ -0,0 +1,76 @@
+// The master lists the replicas of each chunk least loaded first for reads, without changing the
+// order it stores them in
+use rustfs::proto::master::master_client::MasterClient;
+use rustfs::proto::master::FileChunkMappingRequest;
+use rustfs::request_id::REQUEST_ID_KEY;
+use rustfs::testing::TestCluster;
+use rustfs::util::connect_channel;
+use std::time::Duration;
+
+#[tokio::test]
+async fn heavily_loaded_server_is_listed_last() {
+    let mut cluster = TestCluster::start_with(1, |config| {
+        config.common.replication_factor = 2;
+        // Neither rebalanced nor repaired while the test runs
+        config.master.cron_interval = Duration::from_secs(3600);
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    // Only the first chunkserver is there to hold these
+    for index in 0..5 {
+        client
+            .upload_stream(&b"load"[..], &format!("/load-{}", index))
+            .await
+            .unwrap();
+    }
+    cluster.add_chunkserver().await.unwrap();
+    client.upload_stream(&b"hot"[..], "/hot").await.unwrap();
+    let addrs = cluster.chunkserver_addrs();
+    let stored = cluster.master().file_chunks.read().await["/hot"][0]
+        .server_addresses
+        .clone();
+    assert_eq!(stored.len(), 2);
+
+    let channel = connect_channel(cluster.master_addr(), &cluster.config().common.tls)
+        .await
+        .unwrap();
+    let mut master = MasterClient::new(channel);
+    // Whichever seed the request ID gives, the load decides first
+    for request_id in ["first", "second", "third", "fourth"] {
+        let mut request = tonic::Request::new(FileChunkMappingRequest {
+            file_name: "/hot".to_string(),
+            for_append: false,
+            token: String::new(),
+            lease_chunks: Vec::new(),
+        });
+        request
+            .metadata_mut()
+            .insert(REQUEST_ID_KEY, request_id.parse().unwrap());
+        let listed = master.get_file_chunks(request).await.unwrap().into_inner();
+        assert!(listed.ordered_by_load);
+        assert_eq!(
+            listed.chunks[0].server_addresses,
+            vec![addrs[1].clone(), addrs[0].clone()]
+        );
+    }
+
+    // Appends go to the primary, so their lists are not reordered
+    let listed = master
+        .get_file_chunks(FileChunkMappingRequest {
+            file_name: "/hot".to_string(),
+            for_append: true,
+            token: String::new(),
+            lease_chunks: Vec::new(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(!listed.ordered_by_load);
+    let after = cluster.master().file_chunks.read().await["/hot"][0]
+        .server_addresses
+        .clone();
+    assert_eq!(after, stored);
+
+    cluster.shutdown().await.unwrap();
+}
@@ -0,0 +1,44 @@
+// The master records appended bytes only up to the chunk size, so that a client can't corrupt the
+// sizes that reads, quotas and truncation rely on
+use rustfs::proto::master::master_client::MasterClient;
+use rustfs::proto::master::RecordAppendRequest;
+use rustfs::testing::TestCluster;
+use rustfs::util::connect_channel;
+
+#[tokio::test]
+async fn appends_past_the_chunk_size_are_not_recorded() {
+    let cluster = TestCluster::start_with(1, |config| {
+        config.common.replication_factor = 1;
+        config.common.chunk_size = 1024;
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    client
+        .upload_stream(&[7u8; 1000][..], "/file")
+        .await
+        .unwrap();
+    let channel = connect_channel(cluster.master_addr(), &cluster.config().common.tls)
+        .await
+        .unwrap();
+    let mut master = MasterClient::new(channel);
+    let record = |size: u64| RecordAppendRequest {
+        file_name: "/file".to_string(),
+        size,
+        token: String::new(),
+    };
+
+    for size in [25, u64::MAX] {
+        let status = master.record_append(record(size)).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument, "{}", status);
+    }
+    let info = client.get_file_info("/file").await.unwrap();
+    assert_eq!(info.chunks[0].size, 1000);
+
+    // Filling the chunk up exactly is fine
+    master.record_append(record(24)).await.unwrap();
+    let info = client.get_file_info("/file").await.unwrap();
+    assert_eq!(info.chunks[0].size, 1024);
+
+    cluster.shutdown().await.unwrap();
+}
@@ -0,0 +1,73 @@
+// Registered chunkservers have registration_grace_period for their first heartbeat, after which
+// the master counts those that never sent one as failed
+use rustfs::proto::master::master_client::MasterClient;
+use rustfs::proto::master::{HeartbeatRequest, RegisterRequest};
+use rustfs::testing::TestCluster;
+use rustfs::util::connect_channel;
+use std::time::{Duration, Instant};
+
+const SILENT: &str = "127.0.0.1:1";
+const ALIVE: &str = "127.0.0.1:2";
+
+#[tokio::test]
+async fn silent_server_is_removed_after_the_grace_period() {
+    let cluster = TestCluster::start_with(0, |config| {
+        config.common.heartbeat_interval = Duration::from_secs(1);
+        config.master.heartbeat_failure_threshold = 1;
+        config.master.cron_interval = Duration::from_secs(1);
+        config.master.registration_grace_period = Duration::from_secs(2);
+    })
+    .await
+    .unwrap();
+    let channel = connect_channel(cluster.master_addr(), &cluster.config().common.tls)
+        .await
+        .unwrap();
+    let mut master = MasterClient::new(channel);
+    for address in [SILENT, ALIVE] {
+        master
+            .register_chunk_server(RegisterRequest {
+                address: address.to_string(),
+                failure_domain: String::new(),
+                internal_proof: String::new(),
+            })
+            .await
+            .unwrap();
+    }
+    let service = cluster.master();
+    assert!(service
+        .last_heartbeat_time
+        .read()
+        .await
+        .contains_key(SILENT));
+
+    // Only one of them sends heartbeats, starting within the grace period
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    let started = Instant::now();
+    let mut generation = 0;
+    while service
+        .last_heartbeat_time
+        .read()
+        .await
+        .contains_key(SILENT)
+    {
+        assert!(
+            started.elapsed() < Duration::from_secs(10),
+            "The silent server was not removed"
+        );
+        generation += 1;
+        master
+            .heartbeat(HeartbeatRequest {
+                chunkserver_address: ALIVE.to_string(),
+                generation,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+    assert!(!service.chunk_servers.read().await.contains_key(SILENT));
+    let healthy = service.healthy_servers().await;
+    assert!(healthy.contains(ALIVE), "{:?}", healthy);
+
+    cluster.shutdown().await.unwrap();
+}
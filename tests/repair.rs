@@ -0,0 +1,53 @@
+// Chunks that lost a replica with a failed chunkserver are copied to another server, which is only
+// listed once its copy matched the source
+use rustfs::testing::TestCluster;
+use std::time::Duration;
+
+#[tokio::test]
+async fn under_replicated_chunk_is_copied_to_a_spare_server() {
+    let mut cluster = TestCluster::start_with(3, |config| {
+        config.common.replication_factor = 2;
+        config.common.heartbeat_interval = Duration::from_secs(1);
+        config.master.heartbeat_failure_threshold = 1;
+        config.master.cron_interval = Duration::from_secs(1);
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    let data: Vec<u8> = (0..5000u32).map(|i| (i % 241) as u8).collect();
+    client.upload_stream(&data[..], "/file").await.unwrap();
+    let before = client.get_file_info("/file").await.unwrap().chunks[0].clone();
+    assert_eq!(before.server_addresses.len(), 2);
+
+    let dead = before.server_addresses[0].clone();
+    let addrs = cluster.chunkserver_addrs();
+    let dead_index = addrs.iter().position(|addr| *addr == dead).unwrap();
+    cluster.kill_chunkserver(dead_index);
+
+    let master = cluster.master();
+    let chunk = tokio::time::timeout(Duration::from_secs(30), async {
+        loop {
+            let chunk = master.chunk_map.read().await[&before.chunk_id].clone();
+            if chunk.server_addresses.len() == 2 && !chunk.server_addresses.contains(&dead) {
+                return chunk;
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    })
+    .await
+    .expect("The chunk was not re-replicated");
+    assert!(chunk.version > before.version);
+
+    // The survivor and the spare server both hold the whole chunk
+    for addr in &chunk.server_addresses {
+        let index = addrs.iter().position(|a| a == addr).unwrap();
+        let copy = std::fs::read(cluster.chunkserver(index).chunk_path(&chunk.chunk_id)).unwrap();
+        assert!(copy == data, "Replica on {} differs", addr);
+    }
+    let info = client.get_file_info("/file").await.unwrap();
+    assert_eq!(info.under_replicated_chunks, 0);
+    let read = client.read_range("/file", 0, u64::MAX).await.unwrap();
+    assert!(read == data);
+
+    cluster.shutdown().await.unwrap();
+}
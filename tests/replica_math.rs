@@ -0,0 +1,47 @@
+// Replicas counted per chunk when reassigning the chunks of a failed server
+use rustfs::master_service::healthy_replicas;
+use rustfs::proto::master::ChunkInfo;
+
+fn chunk(index: u64, servers: &[&str]) -> ChunkInfo {
+    ChunkInfo {
+        chunk_id: format!("chunk-{}", index),
+        server_addresses: servers.iter().map(|server| server.to_string()).collect(),
+        file_name: "/file".to_string(),
+        index,
+        ..Default::default()
+    }
+}
+
+fn strings(servers: &[&str]) -> Vec<String> {
+    servers.iter().map(|server| server.to_string()).collect()
+}
+
+#[test]
+fn replicas_of_a_three_chunk_file_with_one_failed_server() {
+    // Each chunk is counted on its own: the other chunks of the file having healthy replicas
+    // does not make up for the replica a chunk lost
+    let file = [
+        chunk(0, &["a", "b", "c"]),
+        chunk(1, &["a", "c", "d"]),
+        chunk(2, &["b"]),
+    ];
+    let replicas: Vec<_> = file
+        .iter()
+        .map(|chunk| healthy_replicas(chunk, "b", 3))
+        .collect();
+    assert_eq!(
+        replicas,
+        vec![
+            (strings(&["a", "c"]), 1),
+            (strings(&["a", "c", "d"]), 0),
+            (strings(&[]), 3),
+        ]
+    );
+}
+
+#[test]
+fn more_healthy_replicas_than_replication_factor() {
+    // E.g. after replication_factor was lowered, nothing is needed rather than an underflow
+    let replicas = healthy_replicas(&chunk(0, &["a", "b", "c", "d"]), "d", 2);
+    assert_eq!(replicas, (strings(&["a", "b", "c"]), 0));
+}
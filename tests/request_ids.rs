@@ -0,0 +1,107 @@
+// The ID of a client operation is sent with all of its requests, so that the log lines the
+// master and chunkservers write while handling them carry it
+use rustfs::proto::master::master_client::MasterClient;
+use rustfs::proto::master::FileChunkMappingRequest;
+use rustfs::request_id::{request_with_id, REQUEST_ID_KEY};
+use rustfs::testing::TestCluster;
+use rustfs::util::connect_channel;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+/// Log output collected by the subscriber of the test
+#[derive(Clone, Default)]
+struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+impl Write for CapturedLogs {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl CapturedLogs {
+    /// Collect the logs of all nodes, which run on threads of their own
+    fn install() -> Self {
+        let logs = Self::default();
+        let writer = logs.clone();
+        tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .init();
+        logs
+    }
+
+    fn lines_with(&self, text: &str) -> Vec<String> {
+        String::from_utf8_lossy(&self.0.lock().unwrap())
+            .lines()
+            .filter(|line| line.contains(text))
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+#[tokio::test]
+async fn request_id_appears_in_the_logs_of_all_nodes() {
+    let logs = CapturedLogs::install();
+    let cluster = TestCluster::start_with(2, |config| {
+        config.common.replication_factor = 2;
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    let request_id = client.start_operation().to_string();
+    client.upload_stream(&b"traced"[..], "/file").await.unwrap();
+
+    let lines = logs.lines_with(&request_id);
+    assert!(
+        lines
+            .iter()
+            .any(|line| line.contains("/master.Master/AssignChunks")),
+        "{:#?}",
+        lines
+    );
+    // Both replicas, the secondary one forwarded the chunk by the primary
+    for addr in cluster.chunkserver_addrs() {
+        let data_dir = addr.replace(':', "_");
+        assert!(
+            lines.iter().any(|line| line.contains("/chunk.Chunk/Upload")
+                && line.contains("Saved chunk")
+                && line.contains(&data_dir)),
+            "No upload to {} in {:#?}",
+            addr,
+            lines
+        );
+    }
+
+    // Failed requests return the ID along with the error
+    let channel = connect_channel(cluster.master_addr(), &cluster.config().common.tls)
+        .await
+        .unwrap();
+    let request = FileChunkMappingRequest {
+        file_name: "/missing".to_string(),
+        for_append: false,
+        token: String::new(),
+        lease_chunks: Vec::new(),
+    };
+    let status = MasterClient::new(channel)
+        .get_file_chunks(request_with_id(request, &request_id))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::NotFound);
+    assert_eq!(
+        status
+            .metadata()
+            .get(REQUEST_ID_KEY)
+            .unwrap()
+            .to_str()
+            .unwrap(),
+        request_id
+    );
+
+    cluster.shutdown().await.unwrap();
+}
@@ -0,0 +1,116 @@
+// Requests failing for a transient reason are retried with backoff, others are not
+use rustfs::proto::master::master_client::MasterClient;
+use rustfs::proto::master::master_server::MasterServer;
+use rustfs::proto::master::ClusterStatusRequest;
+use rustfs::retry::{retry, RetryPolicy};
+use rustfs::testing::TestCluster;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+use tonic::transport::{Channel, Server};
+use tonic::{Code, Status};
+
+/// Serve the master of `cluster` on another port, failing the first `failures` requests with
+/// `code`. Returns a client of it and the number of requests it received.
+async fn serve_failing(
+    cluster: &TestCluster,
+    failures: usize,
+    code: Code,
+) -> (MasterClient<Channel>, Arc<AtomicUsize>) {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counted = Arc::clone(&requests);
+    // Interceptors return a plain `Status`, as tonic requires
+    #[allow(clippy::result_large_err)]
+    let service = MasterServer::with_interceptor(Arc::clone(cluster.master()), move |request| {
+        if counted.fetch_add(1, Ordering::SeqCst) < failures {
+            Err(Status::new(code, "failing on purpose"))
+        } else {
+            Ok(request)
+        }
+    });
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(Server::builder().add_service(service).serve_with_incoming(
+        futures::stream::unfold(listener, |listener| async move {
+            let connection = listener.accept().await.map(|(stream, _)| stream);
+            Some((connection, listener))
+        }),
+    ));
+    let channel = Channel::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    (MasterClient::new(channel), requests)
+}
+
+fn policy(max_attempts: u32) -> RetryPolicy {
+    RetryPolicy {
+        max_attempts,
+        initial_backoff: Duration::from_millis(200),
+        max_backoff: Duration::from_secs(1),
+        deadline: Duration::from_secs(10),
+    }
+}
+
+async fn cluster_status(
+    policy: &RetryPolicy,
+    master: &MasterClient<Channel>,
+) -> Result<(), Status> {
+    retry(policy, "Fetching the cluster status", || {
+        let mut master = master.clone();
+        async move {
+            master
+                .cluster_status(ClusterStatusRequest {
+                    include_files: false,
+                    token: String::new(),
+                })
+                .await
+                .map(drop)
+        }
+    })
+    .await
+}
+
+#[tokio::test]
+async fn unavailable_service_is_retried_with_backoff() {
+    let cluster = TestCluster::start(1).await.unwrap();
+    let (master, requests) = serve_failing(&cluster, 2, Code::Unavailable).await;
+
+    let started = Instant::now();
+    cluster_status(&policy(3), &master).await.unwrap();
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+    // Waited at least half of 200ms, then half of 400ms, with jitter
+    assert!(
+        started.elapsed() >= Duration::from_millis(300),
+        "{:?}",
+        started.elapsed()
+    );
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn retries_end_after_the_last_attempt() {
+    let cluster = TestCluster::start(1).await.unwrap();
+    let (master, requests) = serve_failing(&cluster, 2, Code::Unavailable).await;
+
+    let status = cluster_status(&policy(2), &master).await.unwrap_err();
+    assert_eq!(status.code(), Code::Unavailable);
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn permanent_failures_are_not_retried() {
+    let cluster = TestCluster::start(1).await.unwrap();
+    let (master, requests) = serve_failing(&cluster, 2, Code::PermissionDenied).await;
+
+    let status = cluster_status(&policy(3), &master).await.unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied);
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+    cluster.shutdown().await.unwrap();
+}
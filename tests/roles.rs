@@ -0,0 +1,381 @@
+// Requests with authentication are allowed or refused by the role of the user sending them
+use rustfs::auth::Role;
+use rustfs::client::Client;
+use rustfs::cluster_auth::sign_placement;
+use rustfs::error::Error;
+use rustfs::logging;
+use rustfs::proto::chunk::chunk_client::ChunkClient;
+use rustfs::proto::chunk::{upload_request, FileChunk, FileInfo, UploadRequest};
+use rustfs::reload::ConfigReloader;
+use rustfs::testing::TestCluster;
+use rustfs::util::connect_channel;
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use uuid::Uuid;
+
+const ROLES: [Role; 3] = [Role::Admin, Role::ReadWrite, Role::ReadOnly];
+
+/// Cluster with authentication and a user of each role, named after it, with password "secret"
+async fn start_cluster(chunkservers: usize) -> TestCluster {
+    let cluster = TestCluster::start_with(chunkservers, |config| {
+        config.common.use_authentication = true;
+        config.common.cluster_secret = "test-cluster-secret".to_string();
+        config.common.replication_factor = 1;
+        config.common.trash_retention_secs = Duration::from_secs(3600);
+    })
+    .await
+    .unwrap();
+    for role in ROLES {
+        cluster.add_user(role.as_str(), "secret", role).unwrap();
+    }
+    cluster
+}
+
+/// Client authenticated as the user of `role`
+async fn client_of(cluster: &TestCluster, role: Role) -> Client {
+    let mut client = cluster.client().await.unwrap();
+    client.authenticate(role.as_str(), "secret").await.unwrap();
+    client
+}
+
+fn assert_permission_denied<T: std::fmt::Debug>(result: rustfs::error::Result<T>, role: Role) {
+    assert!(
+        matches!(result, Err(Error::PermissionDenied(_))),
+        "{} was not refused: {:?}",
+        role.as_str(),
+        result
+    );
+}
+
+fn assert_unauthenticated<T: std::fmt::Debug>(result: rustfs::error::Result<T>) {
+    assert!(
+        matches!(&result, Err(Error::Rpc(status)) if status.code() == tonic::Code::Unauthenticated),
+        "An unauthenticated request was not refused: {:?}",
+        result
+    );
+}
+
+/// Wait until the audit log has an entry of `operation` with `result`, for at most 10 seconds
+async fn wait_for_audit(client: &mut Client, operation: &str, user: &str, result: &str) {
+    for _ in 0..100 {
+        let entries = client.query_audit("", 0, 0).await.unwrap().entries;
+        if entries.iter().any(|entry| {
+            entry.operation == operation && entry.user == user && entry.result.starts_with(result)
+        }) {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!(
+        "No audit entry of {} by '{}' with {}",
+        operation, user, result
+    );
+}
+
+#[tokio::test]
+async fn only_admins_decommission_chunkservers() {
+    let cluster = start_cluster(2).await;
+    let address = cluster.chunkserver_addrs()[1].clone();
+
+    assert_unauthenticated(
+        cluster
+            .client()
+            .await
+            .unwrap()
+            .decommission_server(&address)
+            .await,
+    );
+    for role in [Role::ReadWrite, Role::ReadOnly] {
+        let mut client = client_of(&cluster, role).await;
+        assert_permission_denied(client.decommission_server(&address).await, role);
+    }
+    let mut admin = client_of(&cluster, Role::Admin).await;
+    admin.decommission_server(&address).await.unwrap();
+    // Every user may follow the progress
+    for role in ROLES {
+        let mut client = client_of(&cluster, role).await;
+        client.decommission_status(&address).await.unwrap();
+    }
+
+    wait_for_audit(&mut admin, "decommission", "admin", "ok").await;
+    wait_for_audit(&mut admin, "decommission", "readonly", "PermissionDenied").await;
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn only_admins_rebalance_chunks() {
+    let cluster = start_cluster(2).await;
+
+    assert_unauthenticated(cluster.client().await.unwrap().trigger_rebalance().await);
+    for role in [Role::ReadWrite, Role::ReadOnly] {
+        let mut client = client_of(&cluster, role).await;
+        assert_permission_denied(client.trigger_rebalance().await, role);
+    }
+    let mut admin = client_of(&cluster, Role::Admin).await;
+    admin.trigger_rebalance().await.unwrap();
+    // Every user may follow the progress
+    for role in ROLES {
+        let mut client = client_of(&cluster, role).await;
+        client.rebalance_status().await.unwrap();
+    }
+
+    wait_for_audit(&mut admin, "rebalance", "admin", "ok").await;
+    wait_for_audit(&mut admin, "rebalance", "readwrite", "PermissionDenied").await;
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn cluster_status_requires_authentication() {
+    let cluster = start_cluster(1).await;
+
+    assert_unauthenticated(cluster.client().await.unwrap().cluster_status(true).await);
+    for role in ROLES {
+        let mut client = client_of(&cluster, role).await;
+        let status = client.cluster_status(true).await.unwrap();
+        assert_eq!(status.chunk_servers.len(), 1);
+    }
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn only_admins_reload_the_configuration() {
+    let cluster = start_cluster(1).await;
+    // Reloads the written config file like a master started by the binary
+    let path = cluster.write_config().unwrap();
+    let mut common_config = cluster.config().common.clone();
+    common_config.log_output = "file".to_string();
+    let log_dir = Path::new(&path).parent().unwrap().display().to_string();
+    let log_handle = logging::init(&common_config, &log_dir, "master.log").unwrap();
+    let master = cluster.master();
+    *master.reloader.lock().await = Some(ConfigReloader::new(
+        &path,
+        log_handle.clone(),
+        cluster.config().clone(),
+        None,
+        master.metrics.registry.clone(),
+    ));
+
+    let mut changed = cluster.config().clone();
+    changed.common.log_level = "debug".to_string();
+    changed.master.cron_interval = Duration::from_secs(7);
+    changed.master.heartbeat_failure_threshold = 9;
+    std::fs::write(&path, toml::Value::try_from(&changed).unwrap().to_string()).unwrap();
+
+    assert_unauthenticated(cluster.client().await.unwrap().reload_config(None).await);
+    for role in [Role::ReadWrite, Role::ReadOnly] {
+        let mut client = client_of(&cluster, role).await;
+        assert_permission_denied(client.reload_config(None).await, role);
+    }
+    assert_eq!(
+        master.settings.cron_interval(),
+        cluster.config().master.cron_interval
+    );
+
+    // Applies the changed log level, cron interval and failure threshold
+    let mut admin = client_of(&cluster, Role::Admin).await;
+    let response = admin.reload_config(None).await.unwrap();
+    assert_eq!(response.applied.len(), 3, "{:?}", response);
+    assert_eq!(master.settings.cron_interval(), Duration::from_secs(7));
+    assert_eq!(master.settings.heartbeat_failure_threshold(), 9);
+    assert_eq!(log_handle.filter().unwrap(), "debug");
+
+    wait_for_audit(&mut admin, "reload_config", "admin", "ok").await;
+    wait_for_audit(&mut admin, "reload_config", "readonly", "PermissionDenied").await;
+    cluster.shutdown().await.unwrap();
+}
+
+/// Requests checked for each role by `roles_allow_their_operations`
+#[derive(Clone, Copy, Debug)]
+enum Operation {
+    Upload,
+    Append,
+    WriteAt,
+    Truncate,
+    Copy,
+    Snapshot,
+    Delete,
+    Undelete,
+    DeletePrefix,
+    Read,
+    List,
+    Stat,
+    ClusterStatus,
+    QueryAudit,
+    Rebalance,
+}
+
+impl Operation {
+    const ALL: [Operation; 15] = [
+        Operation::Upload,
+        Operation::Append,
+        Operation::WriteAt,
+        Operation::Truncate,
+        Operation::Copy,
+        Operation::Snapshot,
+        Operation::Delete,
+        Operation::Undelete,
+        Operation::DeletePrefix,
+        Operation::Read,
+        Operation::List,
+        Operation::Stat,
+        Operation::ClusterStatus,
+        Operation::QueryAudit,
+        Operation::Rebalance,
+    ];
+
+    /// Whether a user of `role` may carry out the operation on a file of their own, or of an
+    /// admin for readonly users, who own no files
+    fn is_allowed(self, role: Role) -> bool {
+        match self {
+            Operation::Read | Operation::List | Operation::Stat | Operation::ClusterStatus => true,
+            Operation::QueryAudit | Operation::Rebalance => role == Role::Admin,
+            _ => role != Role::ReadOnly,
+        }
+    }
+
+    /// Create what the operation acts on in `dir` as `owner`
+    async fn prepare(self, owner: &mut Client, dir: &str) {
+        match self {
+            Operation::Upload
+            | Operation::List
+            | Operation::ClusterStatus
+            | Operation::QueryAudit
+            | Operation::Rebalance => {}
+            Operation::DeletePrefix => upload(owner, &format!("{}/file", dir)).await,
+            Operation::Undelete => {
+                upload(owner, &format!("{}/file", dir)).await;
+                owner.delete_file(&format!("{}/file", dir)).await.unwrap();
+            }
+            _ => upload(owner, &format!("{}/file", dir)).await,
+        }
+    }
+
+    /// Carry out the operation on what `prepare` created in `dir`
+    async fn run(self, client: &mut Client, dir: &str) -> rustfs::error::Result<()> {
+        let file = format!("{}/file", dir);
+        match self {
+            Operation::Upload => client.upload_stream(&b"data"[..], &file).await.map(drop),
+            Operation::Append => {
+                let servers = client.get_append_server_addresses(&file).await?;
+                client.append_file(servers, &file, "more".to_string()).await
+            }
+            Operation::WriteAt => client.write_at(&file, 0, b"DA").await,
+            Operation::Truncate => client.truncate_file(&file, 2).await.map(drop),
+            Operation::Copy => client.copy(&file, &format!("{}/copy", dir)).await.map(drop),
+            Operation::Snapshot => client
+                .snapshot_file(&file, &format!("{}/snapshot", dir))
+                .await
+                .map(drop),
+            Operation::Delete => client.delete_file(&file).await,
+            Operation::Undelete => client.undelete_file(&file).await.map(drop),
+            Operation::DeletePrefix => client.delete_prefix(dir).await.map(drop),
+            Operation::Read => client.read_range(&file, 0, u64::MAX).await.map(drop),
+            Operation::List => client.list_files("/").await.map(drop),
+            Operation::Stat => client.get_file_info(&file).await.map(drop),
+            Operation::ClusterStatus => client.cluster_status(true).await.map(drop),
+            Operation::QueryAudit => client.query_audit("", 0, 0).await.map(drop),
+            Operation::Rebalance => client.trigger_rebalance().await.map(drop),
+        }
+    }
+}
+
+async fn upload(client: &mut Client, file: &str) {
+    client.upload_stream(&b"data"[..], file).await.unwrap();
+}
+
+#[tokio::test]
+async fn roles_allow_their_operations() {
+    let cluster = start_cluster(1).await;
+    let mut admin = client_of(&cluster, Role::Admin).await;
+    for role in ROLES {
+        let mut client = client_of(&cluster, role).await;
+        for operation in Operation::ALL {
+            let dir = format!("/{}/{:?}", role.as_str(), operation);
+            // Readonly users own no files, they get to act on files of an admin
+            let owner = if role == Role::ReadOnly {
+                &mut admin
+            } else {
+                &mut client
+            };
+            operation.prepare(owner, &dir).await;
+
+            let result = operation.run(&mut client, &dir).await;
+            if operation.is_allowed(role) {
+                assert!(
+                    result.is_ok(),
+                    "{:?} by {} failed: {:?}",
+                    operation,
+                    role.as_str(),
+                    result
+                );
+            } else {
+                assert!(
+                    matches!(result, Err(Error::PermissionDenied(_))),
+                    "{:?} by {} was not refused: {:?}",
+                    operation,
+                    role.as_str(),
+                    result
+                );
+            }
+        }
+    }
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn chunkservers_refuse_writes_of_readonly_users() {
+    let cluster = start_cluster(1).await;
+    let channel = connect_channel(
+        &cluster.chunkserver_addrs()[0],
+        &cluster.config().common.tls,
+    )
+    .await
+    .unwrap();
+
+    // Uploads straight to the chunkserver, with a placement signed as the master would
+    let addr = cluster.chunkserver_addrs()[0].clone();
+    for role in ROLES {
+        let (token, _) = cluster.master().issue_session_token(role.as_str(), role);
+        let chunk_id = Uuid::new_v4().to_string();
+        let replicas = vec![addr.clone()];
+        let info = FileInfo {
+            placement_proof: sign_placement(
+                &cluster.config().common.cluster_secret,
+                &chunk_id,
+                &addr,
+                &replicas,
+            ),
+            chunk_id,
+            secondary_addresses: Vec::new(),
+            epoch: cluster.master().epoch.load(Ordering::SeqCst),
+            checksum: None,
+            stored_form: false,
+            replicas,
+            primary: addr.clone(),
+        };
+        let requests = [
+            upload_request::Request::Info(info),
+            upload_request::Request::Chunk(FileChunk {
+                data: b"data".to_vec(),
+            }),
+        ]
+        .into_iter()
+        .map(|request| UploadRequest {
+            request: Some(request),
+            token: token.clone(),
+            is_internal: false,
+            internal_proof: String::new(),
+        })
+        .collect::<Vec<_>>();
+        let result = ChunkClient::new(channel.clone())
+            .upload(futures::stream::iter(requests))
+            .await;
+        if role == Role::ReadOnly {
+            assert_eq!(result.unwrap_err().code(), tonic::Code::PermissionDenied);
+        } else {
+            result.unwrap();
+        }
+    }
+    cluster.shutdown().await.unwrap();
+}
@@ -0,0 +1,125 @@
+// Chunkservers delete replicas that no longer match their checksum and report them, signed, to
+// the master, which copies the chunk again from a good replica
+use rustfs::auth::Role;
+use rustfs::cluster_auth::sign_request;
+use rustfs::master_service::report_corrupt_chunk_fields;
+use rustfs::proto::master::master_client::MasterClient;
+use rustfs::proto::master::ReportCorruptChunkRequest;
+use rustfs::testing::TestCluster;
+use rustfs::util::connect_channel;
+use std::time::Duration;
+
+const CLUSTER_SECRET: &str = "test-cluster-secret";
+
+#[tokio::test]
+async fn corrupt_replica_is_detected_and_repaired() {
+    let cluster = TestCluster::start_with(3, |config| {
+        config.common.replication_factor = 2;
+        config.master.cron_interval = Duration::from_secs(1);
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    client
+        .upload_stream(&b"data that rots on disk"[..], "/file")
+        .await
+        .unwrap();
+    let chunk = client.get_file_info("/file").await.unwrap().chunks[0].clone();
+    let corrupt_addr = chunk.server_addresses[0].clone();
+    let index = cluster
+        .chunkserver_addrs()
+        .iter()
+        .position(|addr| *addr == corrupt_addr)
+        .unwrap();
+
+    // Flip a bit of the stored chunk behind the chunkserver's back
+    let chunkserver = cluster.chunkserver(index);
+    let path = chunkserver.chunk_path(&chunk.chunk_id);
+    let mut data = std::fs::read(&path).unwrap();
+    *data.last_mut().unwrap() ^= 1;
+    std::fs::write(&path, data).unwrap();
+
+    chunkserver.scrub().await;
+    let stats = chunkserver.scrub_stats.lock().await;
+    assert_eq!(stats.corrupt_chunks, 1);
+    assert!(stats.unreported.is_empty(), "{:?}", stats.unreported);
+    drop(stats);
+    assert!(!std::path::Path::new(&path).exists());
+
+    // Dropped from the replicas, then copied again until there are two good ones
+    let master = cluster.master();
+    let replicas = master.chunk_map.read().await[&chunk.chunk_id]
+        .server_addresses
+        .clone();
+    assert!(!replicas.contains(&corrupt_addr), "{:?}", replicas);
+    tokio::time::timeout(Duration::from_secs(30), async {
+        while master.chunk_map.read().await[&chunk.chunk_id]
+            .server_addresses
+            .len()
+            < 2
+        {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("The corrupt replica was not replaced");
+    let read = client.read_range("/file", 0, u64::MAX).await.unwrap();
+    assert_eq!(read, b"data that rots on disk");
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn reports_need_a_valid_proof() {
+    let cluster = TestCluster::start_with(2, |config| {
+        config.common.replication_factor = 2;
+        config.common.use_authentication = true;
+        config.common.cluster_secret = CLUSTER_SECRET.to_string();
+    })
+    .await
+    .unwrap();
+    cluster
+        .add_user("alice", "password", Role::ReadWrite)
+        .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    client.authenticate("alice", "password").await.unwrap();
+    client
+        .upload_stream(&b"good data"[..], "/file")
+        .await
+        .unwrap();
+    let chunk = client.get_file_info("/file").await.unwrap().chunks[0].clone();
+    let address = chunk.server_addresses[0].clone();
+
+    let channel = connect_channel(cluster.master_addr(), &cluster.config().common.tls)
+        .await
+        .unwrap();
+    let mut master = MasterClient::new(channel);
+    for internal_proof in [
+        String::new(),
+        sign_request(
+            "wrong-secret",
+            &report_corrupt_chunk_fields(&address, &chunk.chunk_id),
+        ),
+        // A proof for another chunk
+        sign_request(
+            CLUSTER_SECRET,
+            &report_corrupt_chunk_fields(&address, "other"),
+        ),
+    ] {
+        let status = master
+            .report_corrupt_chunk(ReportCorruptChunkRequest {
+                chunkserver_address: address.clone(),
+                chunk_id: chunk.chunk_id.clone(),
+                internal_proof,
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated, "{}", status);
+    }
+    let replicas = cluster.master().chunk_map.read().await[&chunk.chunk_id]
+        .server_addresses
+        .clone();
+    assert_eq!(replicas, chunk.server_addresses);
+
+    cluster.shutdown().await.unwrap();
+}
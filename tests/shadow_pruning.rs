@@ -0,0 +1,129 @@
+// The leader stops sending metadata to shadow masters that stopped pinging, while the others
+// and the assignments of uploads go on without delay
+use rustfs::master_service::MasterService;
+use rustfs::testing::TestCluster;
+use std::net::TcpListener;
+use std::time::{Duration, Instant};
+
+/// Address of a server accepting connections without ever answering on them
+fn silent_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    std::thread::spawn(move || {
+        let mut connections = Vec::new();
+        for stream in listener.incoming() {
+            connections.push(stream);
+        }
+    });
+    addr
+}
+
+/// Wait until `master` stores `file_name`, returning how long that took
+async fn wait_for_file(master: &MasterService, file_name: &str) -> Duration {
+    let started = Instant::now();
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while !master.file_chunks.read().await.contains_key(file_name) {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("{} did not reach the shadow master", file_name));
+    started.elapsed()
+}
+
+#[tokio::test]
+async fn silent_shadow_is_pruned() {
+    let mut cluster = TestCluster::start_with_shadows(2, 1, |config| {
+        config.common.replication_factor = 1;
+        config.common.shadow_master_ping_interval = Duration::from_secs(1);
+        config.master.shadow_master_failure_threshold = 2;
+        config.master.cron_interval = Duration::from_secs(1);
+    })
+    .await
+    .unwrap();
+    let (live, silent) = (
+        cluster.masters()[1].addr.clone(),
+        cluster.masters()[2].addr.clone(),
+    );
+    let leader = cluster.master();
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while leader.shadow_masters.read().await.len() < 2 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("The shadow masters did not register");
+
+    cluster.kill_master(2);
+    let mut client = cluster.client().await.unwrap();
+    // Before and after the silent shadow master is dropped, the live one is sent updates
+    // right away
+    client
+        .upload_stream(&b"before"[..], "/before")
+        .await
+        .unwrap();
+    let latency = wait_for_file(cluster.masters()[1], "/before").await;
+    assert!(latency < Duration::from_secs(2), "Took {:?}", latency);
+
+    let leader = cluster.master();
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while leader.shadow_masters.read().await.contains_key(&silent) {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("The silent shadow master was not dropped");
+    assert!(leader.shadow_masters.read().await.contains_key(&live));
+
+    client.upload_stream(&b"after"[..], "/after").await.unwrap();
+    let latency = wait_for_file(cluster.masters()[1], "/after").await;
+    assert!(latency < Duration::from_secs(2), "Took {:?}", latency);
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn stalling_shadow_does_not_delay_assignments() {
+    let cluster = TestCluster::start_with_shadows(1, 1, |config| {
+        config.common.replication_factor = 1;
+        config.common.shadow_master_ping_interval = Duration::from_secs(30);
+        // Bounds the final hand-over of the metadata to the stalling shadow master
+        config.common.shutdown_timeout = Duration::from_secs(2);
+    })
+    .await
+    .unwrap();
+    let leader = cluster.master();
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while leader.shadow_masters.read().await.is_empty() {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("The shadow master did not register");
+    // A shadow master that takes connections but never answers the metadata updates
+    let stalling = silent_server();
+    leader
+        .shadow_masters
+        .write()
+        .await
+        .insert(stalling.clone(), Instant::now());
+
+    let mut client = cluster.client().await.unwrap();
+    for index in 0..5 {
+        let file_name = format!("/file-{}", index);
+        let started = Instant::now();
+        client.assign_chunks(&file_name, 4).await.unwrap();
+        let latency = started.elapsed();
+        assert!(latency < Duration::from_millis(500), "Took {:?}", latency);
+        let latency = wait_for_file(cluster.masters()[1], &file_name).await;
+        assert!(latency < Duration::from_secs(2), "Took {:?}", latency);
+    }
+    assert!(leader.shadow_masters.read().await.contains_key(&stalling));
+    assert!(!leader
+        .replicated_versions
+        .read()
+        .await
+        .contains_key(&stalling));
+
+    cluster.shutdown().await.unwrap();
+}
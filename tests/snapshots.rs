@@ -0,0 +1,100 @@
+// Snapshots share the chunks of their source until either file changes them
+use rustfs::testing::TestCluster;
+
+const CHUNK_SIZE: u64 = 1024;
+
+#[tokio::test]
+async fn snapshot_and_source_diverge() {
+    let cluster = TestCluster::start_with(2, |config| {
+        config.common.replication_factor = 2;
+        config.common.chunk_size = CHUNK_SIZE;
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    let original: Vec<u8> = (0..1500).map(|i| b'a' + (i % 26) as u8).collect();
+    client.upload_stream(&original[..], "/log").await.unwrap();
+
+    assert_eq!(client.snapshot_file("/log", "/snap").await.unwrap(), 2);
+    let servers = client.get_append_server_addresses("/log").await.unwrap();
+    client
+        .append_file(servers, "/log", "appended".to_string())
+        .await
+        .unwrap();
+    client.write_at("/log", 10, b"changed").await.unwrap();
+
+    let mut changed = original.clone();
+    changed[10..17].copy_from_slice(b"changed");
+    changed.extend_from_slice(b"appended");
+    assert_eq!(
+        client.read_range("/log", 0, u64::MAX).await.unwrap(),
+        changed
+    );
+    assert_eq!(
+        client.read_range("/snap", 0, u64::MAX).await.unwrap(),
+        original
+    );
+
+    // Both changed chunks were copied for the source, the snapshot keeps the originals
+    let source = client.get_file_info("/log").await.unwrap();
+    let snapshot = client.get_file_info("/snap").await.unwrap();
+    for (source_chunk, snapshot_chunk) in source.chunks.iter().zip(&snapshot.chunks) {
+        assert_ne!(source_chunk.chunk_id, snapshot_chunk.chunk_id);
+    }
+    let chunk_map = cluster.master().chunk_map.read().await;
+    for (index, chunk) in snapshot.chunks.iter().enumerate() {
+        let chunk = &chunk_map[&chunk.chunk_id];
+        assert_eq!(
+            (chunk.file_name.as_str(), chunk.index),
+            ("/snap", index as u64)
+        );
+        assert_eq!(chunk.shared_with, 0);
+    }
+    drop(chunk_map);
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn snapshot_outlives_its_source() {
+    let cluster = TestCluster::start_with(2, |config| {
+        config.common.replication_factor = 2;
+        config.common.chunk_size = CHUNK_SIZE;
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    let content = vec![b'x'; 2500];
+    client.upload_stream(&content[..], "/log").await.unwrap();
+    client.snapshot_file("/log", "/snap").await.unwrap();
+
+    // The shared chunks now belong to the snapshot alone
+    client.delete_file("/log").await.unwrap();
+    let snapshot = client.get_file_info("/snap").await.unwrap();
+    {
+        let chunk_map = cluster.master().chunk_map.read().await;
+        assert_eq!(chunk_map.len(), 3);
+        for (index, chunk) in snapshot.chunks.iter().enumerate() {
+            let chunk = &chunk_map[&chunk.chunk_id];
+            assert_eq!(
+                (chunk.file_name.as_str(), chunk.index),
+                ("/snap", index as u64)
+            );
+            assert_eq!(chunk.shared_with, 0);
+        }
+    }
+    assert_eq!(
+        client.read_range("/snap", 0, u64::MAX).await.unwrap(),
+        content
+    );
+
+    // Not shared any more, so changed in place
+    client.write_at("/snap", 0, b"changed").await.unwrap();
+    let after = client.get_file_info("/snap").await.unwrap();
+    assert_eq!(after.chunks[0].chunk_id, snapshot.chunks[0].chunk_id);
+
+    client.delete_file("/snap").await.unwrap();
+    assert!(cluster.master().chunk_map.read().await.is_empty());
+
+    cluster.shutdown().await.unwrap();
+}
@@ -0,0 +1,48 @@
+// sync_writes decides which of the files written for uploads and appends are flushed to disk
+// before the writes are acknowledged
+use rustfs::testing::TestCluster;
+
+/// Upload and append to a file with `sync_writes` set to `mode`, returning the flushes of each
+/// kind on the chunkserver
+async fn syncs_after_writes(mode: &str) -> [u64; 4] {
+    let cluster = TestCluster::start_with(1, |config| {
+        config.common.replication_factor = 1;
+        config.chunkserver.sync_writes = mode.to_string();
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    client
+        .upload_stream(&b"durable"[..], "/file")
+        .await
+        .unwrap();
+    let servers = client.get_append_server_addresses("/file").await.unwrap();
+    client
+        .append_file(servers, "/file", " data".to_string())
+        .await
+        .unwrap();
+    let read = client.read_range("/file", 0, u64::MAX).await.unwrap();
+    assert_eq!(read, b"durable data");
+
+    let syncs = &cluster.chunkserver(0).metrics.syncs;
+    let counts = ["chunk", "checksum", "append_journal", "directory"]
+        .map(|kind| syncs.with_label_values(&[kind]).get());
+    cluster.shutdown().await.unwrap();
+    counts
+}
+
+#[tokio::test]
+async fn never_flushes_nothing() {
+    assert_eq!(syncs_after_writes("never").await, [0, 0, 0, 0]);
+}
+
+#[tokio::test]
+async fn on_close_flushes_the_chunk_of_each_write() {
+    assert_eq!(syncs_after_writes("on_close").await, [2, 0, 0, 0]);
+}
+
+#[tokio::test]
+async fn always_also_flushes_checksums_and_directories() {
+    // The upload renames its chunk into the data directory, the append journals what it changed
+    assert_eq!(syncs_after_writes("always").await, [2, 2, 1, 1]);
+}
@@ -0,0 +1,85 @@
+// A transfer of N bytes limited to L bytes per second takes at least about N / L seconds
+use rustfs::testing::TestCluster;
+use rustfs::throttle::RateLimiter;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const LIMIT: u64 = 64 * 1024;
+const BYTES: u64 = 2 * LIMIT;
+
+/// Assert that `elapsed` is close to the time `BYTES` take at `LIMIT`, allowing for timer
+/// granularity below and for slow machines above
+fn assert_limited(elapsed: Duration) {
+    let expected = Duration::from_secs_f64(BYTES as f64 / LIMIT as f64);
+    assert!(
+        elapsed >= expected.mul_f64(0.9),
+        "{:?} is faster than {:?}",
+        elapsed,
+        expected
+    );
+    assert!(
+        elapsed < expected + Duration::from_secs(10),
+        "{:?} is much slower than {:?}",
+        elapsed,
+        expected
+    );
+}
+
+#[tokio::test]
+async fn limiter_is_shared_by_concurrent_tasks() {
+    let limiter = Arc::new(RateLimiter::new(LIMIT));
+    let started = Instant::now();
+    let mut tasks = tokio::task::JoinSet::new();
+    for _ in 0..8 {
+        let limiter = Arc::clone(&limiter);
+        tasks.spawn(async move {
+            for _ in 0..4 {
+                limiter.acquire(BYTES / 32).await;
+            }
+        });
+    }
+    while let Some(task) = tasks.join_next().await {
+        task.unwrap();
+    }
+    assert_limited(started.elapsed());
+}
+
+#[tokio::test]
+async fn unlimited_limiter_does_not_wait() {
+    let limiter = RateLimiter::default();
+    assert!(limiter.is_unlimited());
+    let started = Instant::now();
+    limiter.acquire(u64::MAX).await;
+    assert!(started.elapsed() < Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn uploads_and_downloads_are_limited() {
+    let cluster = TestCluster::start_with(1, |config| {
+        config.common.replication_factor = 1;
+        // Several chunks, transferred in parallel
+        config.common.chunk_size = BYTES / 4;
+        config.client.max_upload_bytes_per_sec = LIMIT;
+        config.client.max_download_bytes_per_sec = LIMIT;
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    let content = vec![b'x'; BYTES as usize];
+
+    let started = Instant::now();
+    client
+        .upload_stream(&content[..], "/limited")
+        .await
+        .unwrap();
+    assert_limited(started.elapsed());
+
+    // A new client, as the bucket of the first one refilled while uploading
+    let mut client = cluster.client().await.unwrap();
+    let started = Instant::now();
+    let read = client.read_range("/limited", 0, u64::MAX).await.unwrap();
+    assert_limited(started.elapsed());
+    assert_eq!(read, content);
+
+    cluster.shutdown().await.unwrap();
+}
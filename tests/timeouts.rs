@@ -0,0 +1,103 @@
+// Servers that accept connections but never answer make client requests time out instead of
+// hanging, and reads move on to another replica
+use rustfs::testing::TestCluster;
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Address of a server accepting connections without ever answering on them
+fn silent_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    std::thread::spawn(move || {
+        let mut connections = Vec::new();
+        for stream in listener.incoming() {
+            connections.push(stream);
+        }
+    });
+    addr
+}
+
+#[tokio::test]
+async fn silent_master_times_out() {
+    let cluster = TestCluster::start(0).await.unwrap();
+    let mut config = cluster.config().clone();
+    config.common.master_addrs = vec![silent_server()];
+    config.client.connect_timeout = Duration::from_millis(500);
+    config.client.master_rpc_timeout = Duration::from_millis(500);
+    config.client.retry_attempts = 1;
+
+    let started = Instant::now();
+    let result = rustfs::client::Client::from_config(config).await;
+    assert!(result.is_err());
+    assert!(
+        started.elapsed() < Duration::from_secs(5),
+        "Took {:?}",
+        started.elapsed()
+    );
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn reads_skip_a_silent_replica() {
+    let cluster = TestCluster::start_with(1, |config| {
+        config.common.replication_factor = 1;
+        config.client.chunk_rpc_timeout = Duration::from_millis(500);
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    client
+        .upload_stream(&b"still readable"[..], "/file")
+        .await
+        .unwrap();
+
+    // List a server that never answers, and seems alive to the master, as a replica
+    let master = cluster.master();
+    let silent = silent_server();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    master
+        .last_heartbeat_time
+        .write()
+        .await
+        .insert(silent.clone(), now);
+    {
+        let mut file_chunks = master.file_chunks.write().await;
+        let mut chunk_map = master.chunk_map.write().await;
+        let mut chunk = (*file_chunks["/file"][0]).clone();
+        chunk.server_addresses.insert(0, silent.clone());
+        chunk.primary = silent.clone();
+        let chunk = Arc::new(chunk);
+        chunk_map.insert(chunk.chunk_id.clone(), chunk.clone());
+        file_chunks.get_mut("/file").unwrap()[0] = chunk;
+    }
+
+    // Replicas are tried in random order, read until the silent one came first
+    let mut client = cluster.client().await.unwrap();
+    let mut skipped = false;
+    for _ in 0..20 {
+        let mut read = Vec::new();
+        let started = Instant::now();
+        let summary = client
+            .read_range_to("/file", 0, u64::MAX, &mut read)
+            .await
+            .unwrap();
+        assert_eq!(read, b"still readable");
+        if summary.chunks[0].failed_replicas.contains(&silent) {
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "Took {:?}",
+                started.elapsed()
+            );
+            skipped = true;
+            break;
+        }
+    }
+    assert!(skipped, "The silent replica was never tried");
+
+    cluster.shutdown().await.unwrap();
+}
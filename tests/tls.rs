@@ -0,0 +1,93 @@
+// Every node serves and connects over TLS with certificates signed by a self-signed CA
+use rcgen::{BasicConstraints, Certificate, CertificateParams, IsCa};
+use rustfs::config::TlsConfig;
+use rustfs::testing::TestCluster;
+use std::path::Path;
+use uuid::Uuid;
+
+/// Write a self-signed CA and a certificate for "localhost" signed by it to `dir`, returning the
+/// TLS settings using them
+fn write_certificates(dir: &Path) -> TlsConfig {
+    let mut ca_params = CertificateParams::new(Vec::new());
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca = Certificate::from_params(ca_params).unwrap();
+    let server =
+        Certificate::from_params(CertificateParams::new(vec!["localhost".to_string()])).unwrap();
+
+    std::fs::create_dir_all(dir).unwrap();
+    let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+    std::fs::write(path("ca.pem"), ca.serialize_pem().unwrap()).unwrap();
+    std::fs::write(
+        path("server.pem"),
+        server.serialize_pem_with_signer(&ca).unwrap(),
+    )
+    .unwrap();
+    std::fs::write(path("server.key"), server.serialize_private_key_pem()).unwrap();
+    TlsConfig {
+        enabled: true,
+        cert_path: path("server.pem"),
+        key_path: path("server.key"),
+        ca_path: path("ca.pem"),
+        domain_name: "localhost".to_string(),
+        client: None,
+    }
+}
+
+#[tokio::test]
+async fn upload_and_read_over_tls() {
+    let dir = std::env::temp_dir().join(format!("rustfs-tls-{}", Uuid::new_v4()));
+    let tls = write_certificates(&dir);
+    let cluster = TestCluster::start_with(2, |config| {
+        config.common.replication_factor = 2;
+        config.common.tls = tls;
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+
+    let data: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+    client.upload_stream(&data[..], "/secure").await.unwrap();
+    assert_eq!(
+        client.read_range("/secure", 0, u64::MAX).await.unwrap(),
+        data
+    );
+    // Both replicas were written, the secondary by the primary over TLS as well
+    let chunk_id = &client.get_file_info("/secure").await.unwrap().chunks[0].chunk_id;
+    for index in 0..2 {
+        assert!(cluster
+            .chunkserver(index)
+            .server_chunks
+            .lock()
+            .await
+            .contains(chunk_id));
+    }
+
+    // A plaintext client is not served
+    let mut config = cluster.config().clone();
+    config.common.tls = TlsConfig::default();
+    let plaintext = rustfs::client::Client::from_config(config).await;
+    assert!(plaintext.is_err());
+
+    cluster.shutdown().await.unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn missing_ca_certificate_is_reported() {
+    let mut tls = TlsConfig {
+        enabled: true,
+        ca_path: "/nonexistent/ca.pem".to_string(),
+        ..TlsConfig::default()
+    };
+    let e = tls.load().unwrap_err().to_string();
+    assert!(
+        e.contains("CA certificate file '/nonexistent/ca.pem'"),
+        "{}",
+        e
+    );
+
+    // Without TLS the CA is not needed
+    tls.enabled = false;
+    tls.load().unwrap();
+    assert!(tls.client.is_none());
+}
@@ -0,0 +1,165 @@
+// Session tokens are signed with a key of their own, derived from the cluster secret, and renewed
+// by the client while the session lasts
+use hmac::{Hmac, Mac};
+use rustfs::auth::Role;
+use rustfs::cluster_auth::{derive_key, to_hex};
+use rustfs::testing::TestCluster;
+use rustfs::token::{issue_token, verify_token, TokenClaims};
+use sha2::Sha256;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SECRET: &str = "test-cluster-secret";
+
+fn claims(username: &str, role: Role, expires_at: u64) -> TokenClaims {
+    TokenClaims {
+        username: username.to_string(),
+        role,
+        expires_at,
+        session_start: 500,
+    }
+}
+
+/// Token in the format of `issue_token`, signed with `key` instead
+fn forge_token(key: &[u8], claims: &TokenClaims) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    let signed = format!(
+        "{}.{}.{}.{}",
+        claims.expires_at,
+        claims.session_start,
+        claims.role.as_str(),
+        claims.username
+    );
+    mac.update(signed.as_bytes());
+    let signature = to_hex(&mac.finalize().into_bytes());
+    format!(
+        "{}.{}.{}.{}.{}",
+        claims.expires_at,
+        claims.session_start,
+        claims.role.as_str(),
+        signature,
+        claims.username
+    )
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[test]
+fn issued_tokens_verify() {
+    let claims = claims("alice.smith", Role::Admin, 1000);
+    let token = issue_token(SECRET, &claims);
+    assert_eq!(verify_token(SECRET, &token).unwrap(), claims);
+    assert!(verify_token("other-secret", &token).is_err());
+}
+
+#[test]
+fn altered_tokens_are_rejected() {
+    let token = issue_token(SECRET, &claims("alice", Role::ReadOnly, 1000));
+    for altered in [
+        token.replacen("1000", "9999", 1),
+        token.replacen(".500.", ".9999.", 1),
+        token.replacen("readonly", "admin", 1),
+        token.replacen("alice", "bob", 1),
+        String::new(),
+        "1000.500.admin".to_string(),
+    ] {
+        assert!(verify_token(SECRET, &altered).is_err(), "{}", altered);
+    }
+}
+
+#[test]
+fn tokens_need_the_session_token_key() {
+    // Neither the secret itself nor the key of internal request proofs sign a valid token
+    for key in [
+        SECRET.as_bytes().to_vec(),
+        derive_key(SECRET, "internal request"),
+        derive_key(SECRET, "placement"),
+    ] {
+        let token = forge_token(&key, &claims("mallory", Role::Admin, u64::MAX));
+        assert!(verify_token(SECRET, &token).is_err());
+    }
+    let token = forge_token(
+        &derive_key(SECRET, "session token"),
+        &claims("alice", Role::Admin, u64::MAX),
+    );
+    assert_eq!(verify_token(SECRET, &token).unwrap().username, "alice");
+}
+
+#[tokio::test]
+async fn token_expiring_during_an_upload_is_renewed() {
+    let cluster = TestCluster::start_with(1, |config| {
+        config.common.replication_factor = 1;
+        config.common.use_authentication = true;
+        config.common.cluster_secret = SECRET.to_string();
+        config.common.otp_valid_duration = Duration::from_secs(1);
+        config.common.chunk_size = 1024;
+        // The upload takes about 4 tokens' lifetimes
+        config.client.max_upload_bytes_per_sec = 2048;
+    })
+    .await
+    .unwrap();
+    cluster
+        .add_user("alice", "secret", Role::ReadWrite)
+        .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    client.authenticate("alice", "secret").await.unwrap();
+
+    let data: Vec<u8> = (0..8 * 1024u32).map(|i| (i % 251) as u8).collect();
+    let started = std::time::Instant::now();
+    client.upload_stream(&data[..], "/file").await.unwrap();
+    assert!(started.elapsed() >= Duration::from_secs(3));
+    let read = client.read_range("/file", 0, u64::MAX).await.unwrap();
+    assert!(read == data);
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn sessions_are_not_renewed_beyond_their_maximum_duration() {
+    let cluster = TestCluster::start_with(0, |config| {
+        config.common.use_authentication = true;
+        config.common.cluster_secret = SECRET.to_string();
+        config.common.otp_valid_duration = Duration::from_secs(60);
+        config.master.max_session_duration = Duration::from_secs(3600);
+    })
+    .await
+    .unwrap();
+    cluster
+        .add_user("alice", "secret", Role::ReadWrite)
+        .unwrap();
+    let master = cluster.master();
+
+    // Renewing keeps the session, and the token does not outlive it
+    let session_start = now() - 3590;
+    let token = issue_token(
+        SECRET,
+        &TokenClaims {
+            username: "alice".to_string(),
+            role: Role::ReadWrite,
+            expires_at: now() + 5,
+            session_start,
+        },
+    );
+    let (renewed, expires_at) = master.renew_session_token(&token).unwrap();
+    let claims = verify_token(SECRET, &renewed).unwrap();
+    assert_eq!(claims.session_start, session_start);
+    assert_eq!(expires_at, session_start + 3600);
+
+    // A token of a session that started too long ago is refused, however often it was renewed
+    let token = issue_token(
+        SECRET,
+        &TokenClaims {
+            username: "alice".to_string(),
+            role: Role::ReadWrite,
+            expires_at: now() + 5,
+            session_start: now() - 3601,
+        },
+    );
+    master.renew_session_token(&token).unwrap_err();
+
+    cluster.shutdown().await.unwrap();
+}
@@ -0,0 +1,121 @@
+// Deleted files stay in the trash for trash_retention_secs, can be restored until then and are
+// purged once it expires
+use rustfs::testing::TestCluster;
+use std::path::Path;
+use std::time::Duration;
+
+async fn start_cluster(retention: Duration) -> TestCluster {
+    TestCluster::start_with(1, |config| {
+        config.common.replication_factor = 1;
+        config.common.trash_retention_secs = retention;
+        config.master.cron_interval = Duration::from_secs(1);
+    })
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn deleted_file_is_hidden_and_can_be_restored() {
+    let cluster = start_cluster(Duration::from_secs(3600)).await;
+    let mut client = cluster.client().await.unwrap();
+    client
+        .upload_stream(&b"important data"[..], "/dir/important.dat")
+        .await
+        .unwrap();
+
+    client.delete_file("/dir/important.dat").await.unwrap();
+    client
+        .get_file_info("/dir/important.dat")
+        .await
+        .unwrap_err();
+    // The directory only held the file, so it is gone as well
+    let listed = client.list_files("/").await.unwrap();
+    assert!(listed.is_empty(), "{:?}", listed);
+    assert!(cluster
+        .master()
+        .trash
+        .read()
+        .await
+        .contains_key("/dir/important.dat"));
+
+    let restored = client.undelete_file("/dir/important.dat").await.unwrap();
+    assert_eq!(restored, "/dir/important.dat");
+    let read = client
+        .read_range("/dir/important.dat", 0, u64::MAX)
+        .await
+        .unwrap();
+    assert_eq!(read, b"important data");
+    assert!(cluster.master().trash.read().await.is_empty());
+    client
+        .undelete_file("/dir/important.dat")
+        .await
+        .unwrap_err();
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn files_deleted_under_the_same_name_are_kept_apart() {
+    let cluster = start_cluster(Duration::from_secs(3600)).await;
+    let mut client = cluster.client().await.unwrap();
+    for data in ["first", "second", "third"] {
+        client
+            .upload_stream(data.as_bytes(), "/file")
+            .await
+            .unwrap();
+        client.delete_file("/file").await.unwrap();
+    }
+
+    // The later deletions get a suffix, the first keeps the name
+    let mut trashed: Vec<String> = cluster
+        .master()
+        .trash
+        .read()
+        .await
+        .keys()
+        .cloned()
+        .collect();
+    trashed.sort();
+    assert_eq!(trashed, vec!["/file", "/file-1", "/file-2"]);
+
+    let restored = client.undelete_file("/file-1").await.unwrap();
+    assert_eq!(restored, "/file");
+    let read = client.read_range("/file", 0, u64::MAX).await.unwrap();
+    assert_eq!(read, b"second");
+
+    // Restoring another one while the name is taken restores it under a suffix
+    let restored = client.undelete_file("/file").await.unwrap();
+    assert_eq!(restored, "/file-1");
+    let read = client.read_range("/file-1", 0, u64::MAX).await.unwrap();
+    assert_eq!(read, b"first");
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn expired_files_are_purged() {
+    let cluster = start_cluster(Duration::from_secs(1)).await;
+    let mut client = cluster.client().await.unwrap();
+    client
+        .upload_stream(&b"short-lived"[..], "/file")
+        .await
+        .unwrap();
+    let chunk = client.get_file_info("/file").await.unwrap().chunks[0].clone();
+    let path = cluster.chunkserver(0).chunk_path(&chunk.chunk_id);
+    client.delete_file("/file").await.unwrap();
+    // Still in the trash, so the data is kept
+    assert!(Path::new(&path).exists());
+
+    let master = cluster.master();
+    tokio::time::timeout(Duration::from_secs(20), async {
+        while !master.trash.read().await.is_empty() || Path::new(&path).exists() {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("The expired file was not purged");
+    assert!(!master.chunk_map.read().await.contains_key(&chunk.chunk_id));
+    client.undelete_file("/file").await.unwrap_err();
+
+    cluster.shutdown().await.unwrap();
+}
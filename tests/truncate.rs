@@ -0,0 +1,87 @@
+// Truncating a file cuts the chunk the new end falls into on every replica and deletes the chunks
+// after it
+use rustfs::testing::TestCluster;
+
+const CHUNK_SIZE: u64 = 1024;
+
+/// 3000 bytes over three chunks, the last one partly filled
+fn data() -> Vec<u8> {
+    (0..3000u32).map(|i| (i % 251) as u8).collect()
+}
+
+async fn start_cluster() -> TestCluster {
+    TestCluster::start_with(2, |config| {
+        config.common.replication_factor = 2;
+        config.common.chunk_size = CHUNK_SIZE;
+    })
+    .await
+    .unwrap()
+}
+
+/// Truncate a file holding `data()` to `new_length` and check what is left of it
+async fn truncate_and_read_back(new_length: u64, dropped_chunks: u64, chunks_left: usize) {
+    let cluster = start_cluster().await;
+    let mut client = cluster.client().await.unwrap();
+    let data = data();
+    client.upload_stream(&data[..], "/file").await.unwrap();
+
+    let dropped = client.truncate_file("/file", new_length).await.unwrap();
+    assert_eq!(dropped, dropped_chunks);
+    let info = client.get_file_info("/file").await.unwrap();
+    assert_eq!(info.chunks.len(), chunks_left);
+    assert_eq!(info.total_size, new_length);
+    let read = client.read_range("/file", 0, u64::MAX).await.unwrap();
+    assert_eq!(read, &data[..new_length as usize]);
+
+    // Every replica of the last chunk was cut, not just the one that was read
+    let last = info.chunks.last().unwrap();
+    for (index, addr) in cluster.chunkserver_addrs().iter().enumerate() {
+        if last.server_addresses.contains(addr) {
+            let path = cluster.chunkserver(index).chunk_path(&last.chunk_id);
+            let length = std::fs::metadata(path).unwrap().len();
+            assert_eq!(length, new_length - (chunks_left as u64 - 1) * CHUNK_SIZE);
+        }
+    }
+
+    // The file keeps working, appends to a last chunk with room left go after the new end
+    if last.size < CHUNK_SIZE {
+        let servers = client.get_append_server_addresses("/file").await.unwrap();
+        client
+            .append_file(servers, "/file", "tail".to_string())
+            .await
+            .unwrap();
+        let read = client.read_range("/file", 0, u64::MAX).await.unwrap();
+        assert_eq!(&read[..new_length as usize], &data[..new_length as usize]);
+        assert_eq!(&read[new_length as usize..], b"tail");
+    }
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn truncate_within_a_chunk() {
+    truncate_and_read_back(1500, 1, 2).await;
+}
+
+#[tokio::test]
+async fn truncate_at_a_chunk_boundary() {
+    truncate_and_read_back(2 * CHUNK_SIZE, 1, 2).await;
+}
+
+#[tokio::test]
+async fn truncate_to_zero_length() {
+    truncate_and_read_back(0, 2, 1).await;
+}
+
+#[tokio::test]
+async fn truncate_cannot_extend_a_file() {
+    let cluster = start_cluster().await;
+    let mut client = cluster.client().await.unwrap();
+    client.upload_stream(&data()[..], "/file").await.unwrap();
+
+    client.truncate_file("/file", 4000).await.unwrap_err();
+    let info = client.get_file_info("/file").await.unwrap();
+    assert_eq!(info.total_size, 3000);
+
+    cluster.shutdown().await.unwrap();
+}
@@ -0,0 +1,192 @@
+// Durations and sizes as written in the config file
+use rustfs::config::Config;
+use rustfs::units::{format_duration, format_size, parse_duration, parse_size};
+use std::time::Duration;
+
+#[test]
+fn durations_with_units() {
+    for (value, expected) in [
+        ("500ms", Duration::from_millis(500)),
+        ("5s", Duration::from_secs(5)),
+        ("2m", Duration::from_secs(120)),
+        ("2min", Duration::from_secs(120)),
+        ("2h", Duration::from_secs(7200)),
+        ("7d", Duration::from_secs(7 * 86400)),
+        ("0", Duration::ZERO),
+        ("0s", Duration::ZERO),
+    ] {
+        assert_eq!(parse_duration(value), Ok(expected), "{}", value);
+    }
+}
+
+#[test]
+fn fractional_durations() {
+    assert_eq!(parse_duration("1.5m"), Ok(Duration::from_secs(90)));
+    assert_eq!(parse_duration("0.5s"), Ok(Duration::from_millis(500)));
+    assert_eq!(parse_duration(".25h"), Ok(Duration::from_secs(900)));
+    assert_eq!(parse_duration("1.5ms"), Ok(Duration::from_micros(1500)));
+}
+
+#[test]
+fn whitespace_around_durations() {
+    assert_eq!(parse_duration(" 5s "), Ok(Duration::from_secs(5)));
+    assert_eq!(parse_duration("5 s"), Ok(Duration::from_secs(5)));
+    assert_eq!(parse_duration("\t2m\n"), Ok(Duration::from_secs(120)));
+}
+
+#[test]
+fn invalid_durations() {
+    for (value, problem) in [
+        ("5", "needs a unit"),
+        ("5x", "unknown unit"),
+        ("5sec", "unknown unit"),
+        ("5 minutes", "unknown unit"),
+        ("5s5", "unknown unit"),
+        ("s", "does not start with a number"),
+        ("", "does not start with a number"),
+        ("1..5s", "does not start with a number"),
+        ("-5s", "negative"),
+        ("1000000000000000000000d", "too long"),
+    ] {
+        let error = parse_duration(value).unwrap_err();
+        assert!(error.contains(problem), "{:?}: {}", value, error);
+    }
+}
+
+#[test]
+fn duration_units_in_another_case() {
+    for (value, unit) in [
+        ("1M", "m"),
+        ("5S", "s"),
+        ("500MS", "ms"),
+        ("2H", "h"),
+        ("7D", "d"),
+        ("2Min", "min"),
+    ] {
+        let error = parse_duration(value).unwrap_err();
+        assert!(
+            error.contains(&format!("wrong case, did you mean \"{}\"", unit)),
+            "{:?}: {}",
+            value,
+            error
+        );
+    }
+}
+
+#[test]
+fn sizes_with_units() {
+    for (value, expected) in [
+        ("512", 512),
+        ("512B", 512),
+        ("1KB", 1000),
+        ("1MB", 1_000_000),
+        ("1GB", 1_000_000_000),
+        ("1TB", 1_000_000_000_000),
+        ("1KiB", 1 << 10),
+        ("64MiB", 64 << 20),
+        ("64 MiB", 64 << 20),
+        ("1GiB", 1 << 30),
+        ("2TiB", 2 << 40),
+        (" 1KiB ", 1024),
+        ("0", 0),
+    ] {
+        assert_eq!(parse_size(value), Ok(expected), "{}", value);
+    }
+}
+
+#[test]
+fn size_units_in_another_case() {
+    for (value, unit) in [
+        ("1b", "B"),
+        ("1kb", "KB"),
+        ("64mb", "MB"),
+        ("1Gb", "GB"),
+        ("1tb", "TB"),
+        ("1kib", "KiB"),
+        ("64 mib", "MiB"),
+        ("1GIB", "GiB"),
+        ("2TIB", "TiB"),
+    ] {
+        let error = parse_size(value).unwrap_err();
+        assert!(
+            error.contains(&format!("wrong case, did you mean \"{}\"", unit)),
+            "{:?}: {}",
+            value,
+            error
+        );
+    }
+}
+
+#[test]
+fn fractional_sizes() {
+    assert_eq!(parse_size("1.5KiB"), Ok(1536));
+    assert_eq!(parse_size("0.5MB"), Ok(500_000));
+    let error = parse_size("1.5B").unwrap_err();
+    assert!(error.contains("not a whole number of bytes"), "{}", error);
+    let error = parse_size("0.3KiB").unwrap_err();
+    assert!(error.contains("not a whole number of bytes"), "{}", error);
+}
+
+#[test]
+fn invalid_sizes() {
+    for (value, problem) in [
+        ("64M", "unknown unit"),
+        ("64 megabytes", "unknown unit"),
+        ("1KiBB", "unknown unit"),
+        ("MiB", "does not start with a number"),
+        ("", "does not start with a number"),
+        ("-1KiB", "negative"),
+        ("20000000TiB", "too large"),
+    ] {
+        let error = parse_size(value).unwrap_err();
+        assert!(error.contains(problem), "{:?}: {}", value, error);
+    }
+}
+
+#[test]
+fn formatted_values_parse_back() {
+    for seconds in [0, 1, 59, 60, 90, 3600, 86400, 90000] {
+        let duration = Duration::from_secs(seconds);
+        assert_eq!(parse_duration(&format_duration(duration)), Ok(duration));
+    }
+    assert_eq!(format_duration(Duration::from_secs(300)), "5m");
+    assert_eq!(format_duration(Duration::from_millis(1500)), "1500ms");
+    for bytes in [0, 1, 1000, 1024, 1536, 64 << 20, 3 << 40] {
+        assert_eq!(parse_size(&format_size(bytes)), Ok(bytes));
+    }
+    assert_eq!(format_size(64 << 20), "64MiB");
+    assert_eq!(format_size(1000), "1000B");
+}
+
+/// Parse a config file with the given `[common]` settings
+fn parse_common(settings: &str) -> Result<Config, String> {
+    toml::from_str(&format!("[common]\n{}\n", settings)).map_err(|e| e.to_string())
+}
+
+#[test]
+fn bare_integers_in_the_config_file() {
+    // Written by earlier versions: durations in seconds, sizes in bytes
+    let config = parse_common("heartbeat_interval = 5\nchunk_size = 67108864").unwrap();
+    assert_eq!(config.common.heartbeat_interval, Duration::from_secs(5));
+    assert_eq!(config.common.chunk_size, 64 << 20);
+
+    let config = parse_common("heartbeat_interval = \"5s\"\nchunk_size = \"64MiB\"").unwrap();
+    assert_eq!(config.common.heartbeat_interval, Duration::from_secs(5));
+    assert_eq!(config.common.chunk_size, 64 << 20);
+}
+
+#[test]
+fn invalid_values_in_the_config_file_name_the_setting() {
+    for (settings, setting) in [
+        ("heartbeat_interval = -5", "heartbeat_interval"),
+        ("heartbeat_interval = \"5\"", "heartbeat_interval"),
+        ("heartbeat_interval = 1.5", "heartbeat_interval"),
+        ("chunk_size = -1", "chunk_size"),
+        ("chunk_size = \"64M\"", "chunk_size"),
+        ("heartbeat_interval = \"1M\"", "heartbeat_interval"),
+        ("chunk_size = \"64mib\"", "chunk_size"),
+    ] {
+        let error = parse_common(settings).unwrap_err();
+        assert!(error.contains(setting), "{}: {}", settings, error);
+    }
+}
@@ -0,0 +1,104 @@
+// Only a chunkserver itself can unregister from the master, with a proof signed with a key
+// derived from the cluster secret, once the uploads it receives finished
+use rustfs::cluster_auth::sign_request;
+use rustfs::master_service::unregister_fields;
+use rustfs::proto::master::master_client::MasterClient;
+use rustfs::proto::master::UnregisterRequest;
+use rustfs::testing::TestCluster;
+use rustfs::util::connect_channel;
+use std::time::Duration;
+
+const CLUSTER_SECRET: &str = "test-cluster-secret";
+
+#[tokio::test]
+async fn unregistering_needs_a_valid_proof() {
+    let mut cluster = TestCluster::start_with(2, |config| {
+        config.common.use_authentication = true;
+        config.common.cluster_secret = CLUSTER_SECRET.to_string();
+    })
+    .await
+    .unwrap();
+    let channel = connect_channel(cluster.master_addr(), &cluster.config().common.tls)
+        .await
+        .unwrap();
+    let mut master = MasterClient::new(channel);
+    let address = cluster.chunkserver_addrs()[0].clone();
+
+    let forged = [
+        String::new(),
+        sign_request("wrong-secret", &unregister_fields(&address)),
+        // A proof for another chunkserver
+        sign_request(
+            CLUSTER_SECRET,
+            &unregister_fields(&cluster.chunkserver_addrs()[1]),
+        ),
+    ];
+    for internal_proof in forged {
+        let status = master
+            .unregister_chunk_server(UnregisterRequest {
+                address: address.clone(),
+                internal_proof,
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated, "{}", status);
+    }
+    assert!(cluster.master().healthy_servers().await.contains(&address));
+
+    // A chunkserver shutting down signs its own unregistration
+    cluster.stop_chunkserver(0).await.unwrap();
+    let master = cluster.master();
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while master.healthy_servers().await.contains(&address) {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("The stopped chunkserver was not unregistered");
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn stopping_a_chunkserver_finishes_the_upload_in_flight() {
+    let mut cluster = TestCluster::start_with(2, |config| {
+        config.common.replication_factor = 2;
+        // The chunk takes about 3 seconds to upload
+        config.client.max_upload_bytes_per_sec = 256 * 1024;
+    })
+    .await
+    .unwrap();
+    let data: Vec<u8> = (0..1024 * 1024u32).map(|i| (i % 251) as u8).collect();
+    let mut client = cluster.client().await.unwrap();
+    let upload = tokio::spawn({
+        let data = data.clone();
+        async move { client.upload_stream(&data[..], "/file").await }
+    });
+
+    // Stop a replica while it receives the chunk
+    let master = cluster.master().clone();
+    let chunk = tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            if let Some(chunks) = master.file_chunks.read().await.get("/file") {
+                return chunks[0].clone();
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("The chunk was not assigned");
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(!upload.is_finished());
+    let stopped = cluster.chunkserver(0).clone();
+    cluster.stop_chunkserver(0).await.unwrap();
+
+    upload.await.unwrap().unwrap();
+    let copy = std::fs::read(stopped.chunk_path(&chunk.chunk_id)).unwrap();
+    assert!(copy == data, "The stopped replica kept a partial chunk");
+    assert!(!master.healthy_servers().await.contains(&stopped.addr));
+    let mut client = cluster.client().await.unwrap();
+    let read = client.read_range("/file", 0, u64::MAX).await.unwrap();
+    assert!(read == data);
+
+    cluster.shutdown().await.unwrap();
+}
@@ -0,0 +1,97 @@
+// Chunkservers verify uploaded chunks against the checksum computed by the client, so data
+// corrupted on the way is rejected and sent again
+use rustfs::testing::TestCluster;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+const MARKER: &[u8] = b"corrupt me";
+
+/// Forward connections to `target`, flipping a bit of the first `MARKER` sent to it. Returns
+/// the proxy's address and whether the bit was flipped yet.
+async fn corrupting_proxy(target: String) -> (String, Arc<AtomicBool>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let flipped = Arc::new(AtomicBool::new(false));
+    let flipped_clone = Arc::clone(&flipped);
+    tokio::spawn(async move {
+        while let Ok((client, _)) = listener.accept().await {
+            let server = TcpStream::connect(&target).await.unwrap();
+            let (mut client_read, mut client_write) = client.into_split();
+            let (mut server_read, mut server_write) = server.into_split();
+            tokio::spawn(async move { tokio::io::copy(&mut server_read, &mut client_write).await });
+            let flipped = Arc::clone(&flipped_clone);
+            tokio::spawn(async move {
+                let mut buffer = vec![0; 64 * 1024];
+                loop {
+                    let read = match client_read.read(&mut buffer).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(read) => read,
+                    };
+                    let position = buffer[..read]
+                        .windows(MARKER.len())
+                        .position(|window| window == MARKER);
+                    if let Some(position) = position {
+                        if !flipped.swap(true, Ordering::SeqCst) {
+                            buffer[position] ^= 1;
+                        }
+                    }
+                    if server_write.write_all(&buffer[..read]).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    (addr, flipped)
+}
+
+#[tokio::test]
+async fn corrupted_upload_is_rejected_and_sent_again() {
+    let cluster = TestCluster::start_with(1, |config| {
+        config.common.replication_factor = 1;
+        // The chunkserver is only known by the proxy's address while the test runs
+        config.common.heartbeat_interval = Duration::from_secs(3600);
+    })
+    .await
+    .unwrap();
+    let addr = cluster.chunkserver_addrs()[0].clone();
+    let (proxy, flipped) = corrupting_proxy(addr.clone()).await;
+    let master = cluster.master();
+    {
+        let mut chunk_servers = master.chunk_servers.write().await;
+        let chunks = chunk_servers.remove(&addr).unwrap();
+        chunk_servers.insert(proxy.clone(), chunks);
+        let mut last_heartbeat_time = master.last_heartbeat_time.write().await;
+        let time = last_heartbeat_time.remove(&addr).unwrap();
+        last_heartbeat_time.insert(proxy.clone(), time);
+        let mut server_capacity = master.server_capacity.write().await;
+        if let Some(capacity) = server_capacity.remove(&addr) {
+            server_capacity.insert(proxy.clone(), capacity);
+        }
+    }
+
+    let mut client = cluster.client().await.unwrap();
+    let data = MARKER.repeat(100);
+    client.upload_stream(&data[..], "/file").await.unwrap();
+    assert!(flipped.load(Ordering::SeqCst));
+
+    // The chunk stored is the one sent again, along with its checksum
+    let chunk = master.file_chunks.read().await["/file"][0].clone();
+    assert_eq!(chunk.server_addresses, vec![proxy]);
+    let chunkserver = cluster.chunkserver(0);
+    assert_eq!(
+        std::fs::read(chunkserver.chunk_path(&chunk.chunk_id)).unwrap(),
+        data
+    );
+    assert_eq!(
+        chunkserver.stored_checksum(&chunk.chunk_id).await,
+        Some(crc32fast::hash(&data))
+    );
+    let read = client.read_range("/file", 0, u64::MAX).await.unwrap();
+    assert_eq!(read, data);
+
+    cluster.shutdown().await.unwrap();
+}
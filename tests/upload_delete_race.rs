@@ -0,0 +1,98 @@
+// Uploads and deletions of the same file, interleaved, leave the master and the chunkservers agreeing
+use rustfs::testing::TestCluster;
+use std::time::Duration;
+
+const ROUNDS: usize = 100;
+const FILE: &str = "/race";
+
+/// Content of the file uploaded in `round`, over several chunks
+fn content(round: usize) -> Vec<u8> {
+    format!("round {:03};", round).repeat(300).into_bytes()
+}
+
+#[tokio::test]
+async fn interleaved_uploads_and_deletions_stay_consistent() {
+    let cluster = TestCluster::start_with(2, |config| {
+        config.common.replication_factor = 2;
+        config.common.chunk_size = 1024;
+        config.common.heartbeat_interval = Duration::from_secs(1);
+        config.master.orphan_grace_period = Duration::ZERO;
+    })
+    .await
+    .unwrap();
+    let mut uploader = cluster.client().await.unwrap();
+    let mut deleter = cluster.client().await.unwrap();
+    let mut reader = cluster.client().await.unwrap();
+
+    for round in 0..ROUNDS {
+        let data = content(round);
+        // The deletion starts at different points of the upload from round to round
+        let delay = Duration::from_millis((round % 20) as u64 * 3);
+        let (_, _) = tokio::join!(uploader.upload_stream(&data[..], FILE), async {
+            tokio::time::sleep(delay).await;
+            deleter.delete_file(FILE).await
+        });
+
+        // The file is either gone or holds the whole upload of this round
+        if let Ok(info) = reader.get_file_info(FILE).await {
+            let read = reader.read_range(FILE, 0, u64::MAX).await;
+            assert_eq!(read.unwrap(), data, "round {}", round);
+            assert_eq!(info.total_size, data.len() as u64, "round {}", round);
+        }
+        {
+            let master = cluster.master();
+            let file_chunks = master.file_chunks.read().await;
+            let chunk_map = master.chunk_map.read().await;
+            let listed: Vec<&String> = file_chunks
+                .values()
+                .flatten()
+                .map(|chunk| &chunk.chunk_id)
+                .collect();
+            assert_eq!(listed.len(), chunk_map.len(), "round {}", round);
+            assert!(
+                listed
+                    .iter()
+                    .all(|chunk_id| chunk_map.contains_key(*chunk_id)),
+                "round {}",
+                round
+            );
+        }
+        // The next upload is not stored under another name as the file exists
+        let _ = reader.delete_file(FILE).await;
+    }
+
+    // Once the chunkservers handled their heartbeats, no chunk is left anywhere
+    let master = cluster.master();
+    let settled = tokio::time::timeout(Duration::from_secs(30), async {
+        loop {
+            let mut stored = 0;
+            for index in 0..2 {
+                stored += cluster.chunkserver(index).server_chunks.lock().await.len();
+            }
+            let metadata = master.chunk_map.read().await.len()
+                + master
+                    .chunk_servers
+                    .read()
+                    .await
+                    .values()
+                    .map(Vec::len)
+                    .sum::<usize>();
+            if stored == 0 && metadata == 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    })
+    .await;
+    assert!(
+        settled.is_ok(),
+        "chunks left: {:?} in chunk_map, {:?} on the chunkservers",
+        master.chunk_map.read().await.keys().collect::<Vec<_>>(),
+        cluster.chunkserver(0).server_chunks.lock().await
+    );
+    assert!(master.file_chunks.read().await.is_empty());
+    // Every file lock was released and removed
+    assert_eq!(master.metrics.file_locks.get(), 0);
+
+    cluster.shutdown().await.unwrap();
+}
@@ -0,0 +1,177 @@
+// The primary of an upload forwards the chunk only to replicas the master placed it on
+use rustfs::auth::Role;
+use rustfs::proto::chunk::chunk_client::ChunkClient;
+use rustfs::proto::chunk::{upload_request, FileChunk, FileInfo, UploadRequest};
+use rustfs::proto::master::ChunkInfo;
+use rustfs::testing::TestCluster;
+use rustfs::util::connect_channel;
+use std::sync::atomic::Ordering;
+use tonic::Code;
+
+/// Upload `data` to the primary of `chunk`, asking it to forward the chunk to `secondaries`
+/// and claiming the chunk is placed on `replicas`
+async fn upload(
+    cluster: &TestCluster,
+    chunk: &ChunkInfo,
+    secondaries: &[String],
+    replicas: &[String],
+    token: &str,
+) -> Result<(), tonic::Status> {
+    let channel = connect_channel(&chunk.server_addresses[0], &cluster.config().common.tls)
+        .await
+        .unwrap();
+    let info = FileInfo {
+        chunk_id: chunk.chunk_id.clone(),
+        secondary_addresses: secondaries.to_vec(),
+        epoch: cluster.master().epoch.load(Ordering::SeqCst),
+        checksum: None,
+        stored_form: false,
+        replicas: replicas.to_vec(),
+        placement_proof: chunk.placement_proof.clone(),
+        primary: chunk.primary.clone(),
+    };
+    let requests = [
+        upload_request::Request::Info(info),
+        upload_request::Request::Chunk(FileChunk {
+            data: b"data".to_vec(),
+        }),
+    ]
+    .into_iter()
+    .map(|request| UploadRequest {
+        request: Some(request),
+        token: token.to_string(),
+        is_internal: false,
+        internal_proof: String::new(),
+    })
+    .collect::<Vec<_>>();
+    let response = ChunkClient::new(channel)
+        .upload(futures::stream::iter(requests))
+        .await?
+        .into_inner();
+    assert!(response.failed_secondaries.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn uploads_are_only_forwarded_to_placed_replicas() {
+    let cluster = TestCluster::start_with(3, |config| {
+        config.common.replication_factor = 2;
+        config.common.use_authentication = true;
+        config.common.cluster_secret = "test-cluster-secret".to_string();
+    })
+    .await
+    .unwrap();
+    cluster
+        .add_user("alice", "secret", Role::ReadWrite)
+        .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    client.authenticate("alice", "secret").await.unwrap();
+    let (token, _) = cluster
+        .master()
+        .issue_session_token("alice", Role::ReadWrite);
+
+    let chunk = client
+        .assign_chunks("/file", 4)
+        .await
+        .unwrap()
+        .chunk_info_list
+        .remove(0);
+    let replicas = chunk.server_addresses.clone();
+    let outsiders: Vec<String> = cluster
+        .chunkserver_addrs()
+        .into_iter()
+        .filter(|addr| !replicas.contains(addr))
+        .collect();
+    assert_eq!(outsiders.len(), 1);
+
+    // A server the chunk is not placed on
+    let status = upload(&cluster, &chunk, &outsiders, &replicas, &token)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied, "{}", status);
+    // Nor is it accepted when claimed to be a replica, the master did not sign that
+    let mut claimed = replicas.clone();
+    claimed.extend(outsiders.clone());
+    let status = upload(&cluster, &chunk, &outsiders, &claimed, &token)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied, "{}", status);
+    for index in 0..3 {
+        assert!(!cluster
+            .chunkserver(index)
+            .server_chunks
+            .lock()
+            .await
+            .contains(&chunk.chunk_id));
+    }
+
+    // The placed secondary is accepted
+    upload(&cluster, &chunk, &replicas[1..], &replicas, &token)
+        .await
+        .unwrap();
+    for index in 0..3 {
+        let chunkserver = cluster.chunkserver(index);
+        let stored = chunkserver
+            .server_chunks
+            .lock()
+            .await
+            .contains(&chunk.chunk_id);
+        assert_eq!(
+            stored,
+            replicas.contains(&chunkserver.addr),
+            "{}",
+            chunkserver.addr
+        );
+    }
+
+    cluster.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn uploads_without_secondaries_need_the_signed_placement() {
+    let cluster = TestCluster::start_with(2, |config| {
+        config.common.replication_factor = 1;
+        config.common.use_authentication = true;
+        config.common.cluster_secret = "test-cluster-secret".to_string();
+    })
+    .await
+    .unwrap();
+    cluster
+        .add_user("alice", "secret", Role::ReadWrite)
+        .unwrap();
+    let mut alice = cluster.client().await.unwrap();
+    alice.authenticate("alice", "secret").await.unwrap();
+    alice
+        .upload_stream(&b"alice's data"[..], "/file")
+        .await
+        .unwrap();
+    let chunk = alice.get_file_info("/file").await.unwrap().chunks[0].clone();
+    let (token, _) = cluster.master().issue_session_token("bob", Role::ReadWrite);
+
+    // Another user overwriting the chunk by its ID, with a placement the master did not sign
+    let mut forged = chunk.clone();
+    forged.placement_proof = String::new();
+    let status = upload(&cluster, &forged, &[], &chunk.server_addresses, &token)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied, "{}", status);
+    assert!(status.message().contains("Invalid placement"), "{}", status);
+    // Or with the placement of another chunk
+    let other = alice
+        .assign_chunks("/other", 4)
+        .await
+        .unwrap()
+        .chunk_info_list
+        .remove(0);
+    forged.placement_proof = other.placement_proof.clone();
+    let status = upload(&cluster, &forged, &[], &other.server_addresses, &token)
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied, "{}", status);
+    assert!(status.message().contains("Invalid placement"), "{}", status);
+
+    let read = alice.read_range("/file", 0, u64::MAX).await.unwrap();
+    assert_eq!(read, b"alice's data");
+
+    cluster.shutdown().await.unwrap();
+}
@@ -0,0 +1,63 @@
+// Verifying a file compares the checksums of all replicas of its chunks, so replicas that differ
+// from the others or no longer match the checksum stored when they were written are found
+use rustfs::testing::TestCluster;
+
+#[tokio::test]
+async fn differing_and_corrupt_replicas_are_detected() {
+    let cluster = TestCluster::start_with(3, |config| {
+        config.common.replication_factor = 3;
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    let data = vec![5u8; 4096];
+    client.upload_stream(&data[..], "/file").await.unwrap();
+
+    let verification = client.verify_file("/file").await.unwrap();
+    assert_eq!(verification.len(), 1);
+    let chunk = &verification[0];
+    assert_eq!(chunk.replicas.len(), 3);
+    let ((_, length), agreeing) = chunk.agreement().unwrap();
+    assert_eq!((length, agreeing), (4096, 3));
+    assert!(chunk.has_quorum(3));
+
+    // A replica holding other data of the same length, without a stored checksum to tell it is
+    // damaged, is only outvoted
+    let chunk_id = chunk.chunk_id.clone();
+    let first = cluster.chunkserver(0);
+    std::fs::write(first.chunk_path(&chunk_id), vec![6u8; 4096]).unwrap();
+    std::fs::remove_file(first.checksum_path(&chunk_id)).unwrap();
+    let verification = client.verify_file("/file").await.unwrap();
+    let chunk = &verification[0];
+    let differing = chunk
+        .replicas
+        .iter()
+        .find(|replica| replica.server_address == cluster.chunkserver_addrs()[0])
+        .unwrap();
+    assert!(!differing.corrupt);
+    assert_ne!(
+        differing.checksum.as_ref().unwrap(),
+        &chunk.agreement().unwrap().0
+    );
+    assert_eq!(chunk.agreement().unwrap().1, 2);
+    assert!(chunk.has_quorum(3));
+
+    // A replica that no longer matches its stored checksum is corrupt and doesn't count, which
+    // leaves one replica against another
+    let second = cluster.chunkserver(1);
+    let mut damaged = std::fs::read(second.chunk_path(&chunk_id)).unwrap();
+    damaged[0] ^= 1;
+    std::fs::write(second.chunk_path(&chunk_id), damaged).unwrap();
+    let verification = client.verify_file("/file").await.unwrap();
+    let chunk = &verification[0];
+    let corrupt = chunk
+        .replicas
+        .iter()
+        .find(|replica| replica.server_address == cluster.chunkserver_addrs()[1])
+        .unwrap();
+    assert!(corrupt.corrupt);
+    assert_eq!(chunk.agreement(), None);
+    assert!(!chunk.has_quorum(3));
+
+    cluster.shutdown().await.unwrap();
+}
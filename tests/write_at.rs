@@ -0,0 +1,112 @@
+// Writes within a chunk replace its bytes on every replica, framed or not, and keep its checksum
+use rustfs::chunkserver_service::TRANSFER_FRAME_SIZE;
+use rustfs::compression::{ChunkReader, FRAMED_MAGIC};
+use rustfs::testing::TestCluster;
+use tokio::fs::File;
+use tonic::Code;
+
+/// Data of a chunk file, framed or not
+async fn chunk_data(path: &str) -> Vec<u8> {
+    let mut reader = ChunkReader::new(File::open(path).await.unwrap())
+        .await
+        .unwrap();
+    let mut data = Vec::new();
+    let mut buffer = vec![0; TRANSFER_FRAME_SIZE];
+    loop {
+        let n = reader.read(&mut buffer).await.unwrap();
+        if n == 0 {
+            return data;
+        }
+        data.extend_from_slice(&buffer[..n]);
+    }
+}
+
+#[tokio::test]
+async fn writes_replace_bytes_on_every_replica() {
+    for compression in ["none", "lz4"] {
+        let cluster = TestCluster::start_with(2, |config| {
+            config.common.replication_factor = 2;
+            config.chunkserver.compression = compression.to_string();
+        })
+        .await
+        .unwrap();
+        let mut client = cluster.client().await.unwrap();
+        let mut expected: Vec<u8> = (0..TRANSFER_FRAME_SIZE + 1000)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        client.upload_stream(&expected[..], "/file").await.unwrap();
+
+        for (offset, data) in [
+            (10, &b"in the first frame"[..]),
+            (TRANSFER_FRAME_SIZE - 3, &b"across frames"[..]),
+            (expected.len(), &b"extending the chunk"[..]),
+            // Unframed chunks starting with the magic are framed
+            (0, &FRAMED_MAGIC[..]),
+        ] {
+            client.write_at("/file", offset as u64, data).await.unwrap();
+            let end = offset + data.len();
+            expected.resize(expected.len().max(end), 0);
+            expected[offset..end].copy_from_slice(data);
+        }
+
+        let read = client.read_range("/file", 0, u64::MAX).await.unwrap();
+        assert_eq!(read, expected, "{}", compression);
+        let chunk_id = &client.get_file_info("/file").await.unwrap().chunks[0].chunk_id;
+        for index in 0..2 {
+            let chunkserver = cluster.chunkserver(index);
+            let path = chunkserver.chunk_path(chunk_id);
+            assert_eq!(
+                chunk_data(&path).await,
+                expected,
+                "{}: {}",
+                compression,
+                path
+            );
+            assert_eq!(
+                chunkserver.stored_checksum(chunk_id).await,
+                Some(crc32fast::hash(&expected)),
+                "{}: {}",
+                compression,
+                path
+            );
+        }
+        cluster.shutdown().await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn overflowing_and_outside_writes_leave_the_chunk_alone() {
+    let cluster = TestCluster::start_with(1, |config| {
+        config.common.replication_factor = 1;
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    client
+        .upload_stream(&b"unchanged"[..], "/file")
+        .await
+        .unwrap();
+    let chunk_id = &client.get_file_info("/file").await.unwrap().chunks[0].chunk_id;
+    let chunkserver = cluster.chunkserver(0);
+
+    for (offset, code) in [(u64::MAX, Code::OutOfRange), (100, Code::OutOfRange)] {
+        let status = chunkserver
+            .write_to_chunk(chunk_id, offset, b"data", true, None)
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), code, "{}: {}", offset, status);
+    }
+    let status = chunkserver
+        .write_to_chunk(chunk_id, 0, b"data", false, Some(100))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition, "{}", status);
+
+    assert_eq!(
+        chunk_data(&chunkserver.chunk_path(chunk_id)).await,
+        b"unchanged"
+    );
+    let read = client.read_range("/file", 0, u64::MAX).await.unwrap();
+    assert_eq!(read, b"unchanged");
+    cluster.shutdown().await.unwrap();
+}
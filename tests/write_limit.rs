@@ -0,0 +1,103 @@
+// Chunkservers handle at most max_concurrent_writes uploads and appends at once, writes beyond
+// that are rejected as overloaded once they waited write_queue_timeout
+use futures::channel::mpsc;
+use rustfs::proto::chunk::chunk_client::ChunkClient;
+use rustfs::proto::chunk::{upload_request, FileChunk, FileInfo, UploadRequest};
+use rustfs::testing::TestCluster;
+use rustfs::util::{connect_channel, OVERLOADED_KEY};
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// The messages of an upload of a new chunk holding `data`
+fn upload_requests(cluster: &TestCluster, data: &[u8]) -> Vec<UploadRequest> {
+    let info = FileInfo {
+        chunk_id: Uuid::new_v4().to_string(),
+        secondary_addresses: Vec::new(),
+        epoch: cluster.master().epoch.load(Ordering::SeqCst),
+        checksum: None,
+        stored_form: false,
+        replicas: Vec::new(),
+        placement_proof: String::new(),
+        primary: String::new(),
+    };
+    [
+        upload_request::Request::Info(info),
+        upload_request::Request::Chunk(FileChunk {
+            data: data.to_vec(),
+        }),
+    ]
+    .into_iter()
+    .map(|request| UploadRequest {
+        request: Some(request),
+        token: String::new(),
+        is_internal: false,
+        internal_proof: String::new(),
+    })
+    .collect()
+}
+
+#[tokio::test]
+async fn writes_beyond_the_limit_are_rejected() {
+    let cluster = TestCluster::start_with(1, |config| {
+        config.common.replication_factor = 1;
+        config.chunkserver.max_concurrent_writes = 2;
+        config.chunkserver.write_queue_timeout = Duration::from_millis(300);
+    })
+    .await
+    .unwrap();
+    let chunkserver = cluster.chunkserver(0);
+    let channel = connect_channel(
+        &cluster.chunkserver_addrs()[0],
+        &cluster.config().common.tls,
+    )
+    .await
+    .unwrap();
+
+    // Uploads that only sent their info hold both slots until the rest of their data follows
+    let mut stalled = Vec::new();
+    for _ in 0..2 {
+        let mut requests = upload_requests(&cluster, b"delayed").into_iter();
+        let (sender, receiver) = mpsc::unbounded();
+        sender.unbounded_send(requests.next().unwrap()).unwrap();
+        let mut client = ChunkClient::new(channel.clone());
+        let upload = tokio::spawn(async move { client.upload(receiver).await });
+        stalled.push((sender, requests.next().unwrap(), upload));
+    }
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while chunkserver.metrics.writes_in_progress.get() < 2 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("The uploads did not start");
+
+    let status = ChunkClient::new(channel.clone())
+        .upload(futures::stream::iter(upload_requests(&cluster, b"more")))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), tonic::Code::ResourceExhausted, "{}", status);
+    assert!(status.metadata().get(OVERLOADED_KEY).is_some());
+    assert_eq!(chunkserver.metrics.rejected_writes.get(), 1);
+    assert_eq!(chunkserver.metrics.writes_in_progress.get(), 2);
+
+    // Once the stalled uploads finish, writes are accepted again
+    for (sender, chunk, upload) in stalled {
+        sender.unbounded_send(chunk).unwrap();
+        drop(sender);
+        upload.await.unwrap().unwrap();
+    }
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while chunkserver.metrics.writes_in_progress.get() > 0 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("The slots of the stalled uploads were not released");
+    ChunkClient::new(channel)
+        .upload(futures::stream::iter(upload_requests(&cluster, b"more")))
+        .await
+        .unwrap();
+
+    cluster.shutdown().await.unwrap();
+}
@@ -0,0 +1,65 @@
+// Appends fail unless write_quorum replicas acknowledged them, and the replicas that missed an
+// append are dropped by the master either way
+use rustfs::error::{Error, Result};
+use rustfs::testing::TestCluster;
+
+/// Append to a file whose chunk one of its three replicas lost, with `write_quorum` set to
+/// `write_quorum`. Returns the result of the append and whether the replica was dropped.
+async fn append_missing_one_replica(write_quorum: usize) -> (Result<()>, bool) {
+    let cluster = TestCluster::start_with(3, |config| {
+        config.common.replication_factor = 3;
+        config.common.write_quorum = write_quorum;
+    })
+    .await
+    .unwrap();
+    let mut client = cluster.client().await.unwrap();
+    client.upload_stream(&b"quorum"[..], "/file").await.unwrap();
+    let chunk = cluster.master().file_chunks.read().await["/file"][0].clone();
+    assert_eq!(chunk.server_addresses.len(), 3);
+
+    // A secondary loses its copy, so it cannot take the append
+    let lagging = chunk
+        .server_addresses
+        .iter()
+        .find(|addr| **addr != chunk.primary)
+        .unwrap()
+        .clone();
+    let index = cluster
+        .chunkserver_addrs()
+        .iter()
+        .position(|addr| *addr == lagging)
+        .unwrap();
+    std::fs::remove_file(cluster.chunkserver(index).chunk_path(&chunk.chunk_id)).unwrap();
+
+    let servers = client.get_append_server_addresses("/file").await.unwrap();
+    let result = client
+        .append_file(servers, "/file", " reached".to_string())
+        .await;
+    let replicas = cluster.master().chunk_map.read().await[&chunk.chunk_id]
+        .server_addresses
+        .clone();
+    assert_eq!(replicas.len(), 2, "{:?}", replicas);
+    let read = client.read_range("/file", 0, u64::MAX).await.unwrap();
+    assert_eq!(read, b"quorum reached");
+
+    cluster.shutdown().await.unwrap();
+    (result, !replicas.contains(&lagging))
+}
+
+#[tokio::test]
+async fn append_acknowledged_by_the_quorum_succeeds() {
+    let (result, dropped) = append_missing_one_replica(2).await;
+    result.unwrap();
+    assert!(dropped);
+}
+
+#[tokio::test]
+async fn append_short_of_the_quorum_fails() {
+    let (result, dropped) = append_missing_one_replica(3).await;
+    assert!(
+        matches!(result, Err(Error::QuorumNotReached(_))),
+        "{:?}",
+        result
+    );
+    assert!(dropped);
+}